@@ -1,32 +1,286 @@
+mod supervisor;
+
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use supervisor::{RestartPolicy, ScalingPolicy, WorkerSupervisor};
 use tokio::signal;
+use tokio::sync::Notify;
 use tokio::time::{Duration, sleep};
-use tracing::{debug, info};
+use tracing::{info, warn};
 use anyhow::Result;
 
-use mi7::{CrossProcessPipe, logging::init_default_logging, config};
+use mi7::{
+    AffinityRing, ConfigError, CrossProcessPipe, LargeDataManager, ProcessRole,
+    SharedMemoryRegistry, SharedRateLimiter, ShutdownCoordinator, SlotJanitor, WorkerBoard,
+    affinity, cluster, config,
+    log_ring::{self, LogAggregator},
+    logging::{self, RotatingFileWriter, RotationPolicy},
+    metrics, rate_limit, worker_board,
+};
 
 #[tokio::main]
 async fn main() -> Result<()> {
     // 初始化配置系统
     config::init_config()?;
 
-    // 初始化日志系统
-    init_default_logging("daemon")?;
+    // 初始化日志系统，按 [logging] 中的策略切分与清理日志文件
+    let rotation: RotationPolicy = config::section("logging")?;
+    logging::init_with_rotation("daemon", rotation.clone())?;
 
     info!("MI7 跨进程消息队列守护进程启动");
 
+    // 按 [access] 设置共享内存的创建权限与连接握手策略
+    mi7::access::init_from_config()?;
+    // 按 [encryption] 加载负载密钥，此后创建或连接的共享内存管道加密读写
+    mi7::encryption::init_from_config()?;
+    // 按 [authentication] 加载消息认证密钥，此后创建或连接的共享内存管道校验每条消息
+    mi7::auth::init_from_config()?;
+    // 按 [wait] 设置队列空或满时的等待方式（休眠或轮询）
+    mi7::wait::init_from_config()?;
+    // 按 [memory] 锁定或预读共享内存段的页面
+    mi7::memory::init_from_config()?;
+
+    // 锁诊断：定期检查死锁、加锁顺序反转与长时间持有的锁
+    #[cfg(feature = "lock_debug")]
+    {
+        let threshold = config::int_or("lock_debug", "hold_threshold_ms", 1000) as u64;
+        let interval = config::int_or("lock_debug", "interval_ms", 5000) as u64;
+        mi7::lock_debug::spawn_watchdog(
+            std::time::Duration::from_millis(threshold),
+            std::time::Duration::from_millis(interval),
+        );
+    }
+
+    // 清理上次异常退出遗留的共享内存段（仍被其他进程映射的段会被跳过）
+    for prefix in [
+        config::string("shared_memory", "name"),
+        config::string("entry", "interface_name"),
+        config::string("worker", "interface_name"),
+        config::string_or("shutdown", "control_name", "mi7_control"),
+        config::string_or("logging", "ring_name", log_ring::DEFAULT_RING_NAME),
+        config::string_or("scheduler", "board_name", worker_board::DEFAULT_BOARD_NAME),
+        config::string_or("cluster", "name", cluster::DEFAULT_CLUSTER_NAME),
+        worker_board::RESPONSE_PIPE_PREFIX.to_string(),
+        config::string_or(
+            "scheduler",
+            "affinity_ring_name",
+            affinity::DEFAULT_RING_NAME,
+        ),
+        config::string_or("rate_limit", "name", rate_limit::DEFAULT_LIMITER_NAME),
+    ] {
+        match SharedMemoryRegistry::cleanup_stale(&prefix) {
+            Ok(removed) if !removed.is_empty() => {
+                info!("已清理遗留共享内存段: {:?}", removed)
+            }
+            Ok(_) => {}
+            Err(e) => warn!("清理遗留共享内存段失败: {}", e),
+        }
+    }
+
+    // 创建停止协调控制块，entry 与 worker 连接后登记为参与者
+    let coordinator =
+        ShutdownCoordinator::create(&config::string_or("shutdown", "control_name", "mi7_control"))?;
+    info!("停止协调控制块已创建: {}", coordinator.name());
+
+    // 创建 worker 登记表，worker 在其中登记心跳与收件管道，entry 据此派发请求
+    let board = Arc::new(WorkerBoard::create(&config::string_or(
+        "scheduler",
+        "board_name",
+        worker_board::DEFAULT_BOARD_NAME,
+    ))?);
+    info!("worker 登记表已创建: {}", board.name());
+
+    // 创建亲和哈希环，entry 以亲和路由派发时在其中维护各 worker 的归属区间
+    let _affinity_ring = AffinityRing::create(&config::string_or(
+        "scheduler",
+        "affinity_ring_name",
+        affinity::DEFAULT_RING_NAME,
+    ))?;
+
+    // 按 [rate_limit] 创建共享令牌桶，本机所有 entry 合计不超过该速率
+    let _rate_limiter = match rate_limit_config()? {
+        Some((rate, burst)) => {
+            let name = config::string_or("rate_limit", "name", rate_limit::DEFAULT_LIMITER_NAME);
+            let limiter = SharedRateLimiter::create(&name, rate, burst)?;
+            info!("限流器已创建: {} (每秒 {} 个请求，突发 {})", name, rate, burst);
+            Some(limiter)
+        }
+        None => None,
+    };
+
+    // 创建进程心跳区，守护进程、entry 与 worker 定期在其中更新心跳，据此判断存活
+    let cluster = Arc::new(cluster::from_config(true)?);
+    let heartbeat_handle = cluster::start_heartbeat(&cluster, ProcessRole::Daemon, "daemon")?;
+    metrics::register_status("cluster", {
+        let cluster = Arc::clone(&cluster);
+        move || serde_json::json!(cluster.processes())
+    });
+    info!("进程心跳区已创建: {}", cluster.name());
+
+    // worker 进程池：按排队任务数量与处理耗时启动或停止 worker，崩溃的进程按退避策略重启
+    let scaling: ScalingPolicy = config::section("supervisor")?;
+    let supervisor = if scaling.enabled {
+        let mut pool = WorkerSupervisor::new(
+            scaling,
+            config::section::<RestartPolicy>("supervisor")?,
+            Arc::clone(&board),
+            &config::string("worker", "interface_type"),
+            &config::string("worker", "interface_name"),
+        )?;
+        if config::bool_or("supervisor", "manage_entry", false) {
+            pool = pool.with_entry(&config::string_or("supervisor", "entry_binary", ""))?;
+        }
+        let status = pool.status();
+        metrics::register_status("supervisor", move || {
+            let status = status.lock().unwrap();
+            serde_json::json!({ "healthy": status.healthy(), "processes": status.processes })
+        });
+        let stop = Arc::new(Notify::new());
+        let handle = pool.spawn(Arc::clone(&stop));
+        Some((stop, handle))
+    } else {
+        None
+    };
+
+    // 日志汇聚：entry 与 worker 把日志写入共享内存日志环，由守护进程按序写入同一个文件
+    let log_aggregation = if config::bool_or("logging", "aggregate", false) {
+        let ring_name = config::string_or("logging", "ring_name", log_ring::DEFAULT_RING_NAME);
+        let ring = log_ring::create(&ring_name)?;
+        let writer = RotatingFileWriter::new(
+            config::string_or("logging", "log_path", "./logs"),
+            config::string_or("logging", "log_prefix", "mi7"),
+            rotation,
+        )?;
+        let aggregator = LogAggregator::new(&ring, writer)?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = std::thread::Builder::new()
+            .name("log-aggregator".to_string())
+            .spawn({
+                let stop = Arc::clone(&stop);
+                move || aggregator.run(stop)
+            })?;
+        info!("日志环已创建: {}", ring_name);
+        Some((ring, stop, handle))
+    } else {
+        None
+    };
+
     // 使用配置中的队列名称和容量
     let queue_name = config::string("shared_memory", "name");
     let queue_capacity = config::int("queue", "capacity");
-    let queue = Arc::new(CrossProcessPipe::<100, 4096>::create(&queue_name)?);
+    let queue = if config::bool("queue", "persistent") {
+        // 持久化队列映射普通文件，重启后恢复在途消息
+        let path = config::string_or("queue", "path", &format!("./data/{}.pipe", queue_name));
+        if let Some(dir) = std::path::Path::new(&path).parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        info!("消息队列使用持久化文件: {}", path);
+        Arc::new(CrossProcessPipe::<100, 4096>::create_persistent(&queue_name, &path)?)
+    } else {
+        Arc::new(CrossProcessPipe::<100, 4096>::create(&queue_name)?)
+    };
     info!(
         "消息队列已初始化: {} (容量: {})",
         queue_name, queue_capacity
     );
 
+    // 背压水位：entry 在占用达到高水位时直接返回 429
+    let (high_watermark, low_watermark) = queue_watermarks();
+    queue.set_watermarks(high_watermark, low_watermark)?;
+    if high_watermark > 0 {
+        info!(
+            "消息队列背压水位: 高 {} / 低 {}",
+            high_watermark, low_watermark
+        );
+    }
+
+    // 配置热加载：修改 config.toml 中的日志级别与队列水位后无需重启即可生效
+    if let Err(e) = logging::set_level(&config::string_or("logging", "level", "info")) {
+        warn!("设置日志级别失败: {}", e);
+    }
+    let config_watcher = match config::watch() {
+        Ok(watcher) => {
+            info!("正在监听配置文件: {}", watcher.path().display());
+            Some(watcher)
+        }
+        Err(e) => {
+            warn!("无法监听配置文件，配置热加载不可用: {}", e);
+            None
+        }
+    };
+    let logging_changes = config::subscribe("logging");
+    let queue_changes = config::subscribe("queue");
+    let reload_queue: Arc<CrossProcessPipe<100, 4096>> = Arc::clone(&queue);
+    let reload_handle = tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                Ok(change) = logging_changes.recv() => {
+                    if change.key != "level" {
+                        continue;
+                    }
+                    let level = config::string_or("logging", "level", "info");
+                    match logging::set_level(&level) {
+                        Ok(()) => info!("日志级别已调整为 {}", level),
+                        Err(e) => warn!("调整日志级别失败: {}", e),
+                    }
+                }
+                Ok(change) = queue_changes.recv() => {
+                    if change.key != "high_watermark" && change.key != "low_watermark" {
+                        continue;
+                    }
+                    let (high, low) = queue_watermarks();
+                    match reload_queue.set_watermarks(high, low) {
+                        Ok(()) => info!("消息队列背压水位已调整: 高 {} / 低 {}", high, low),
+                        Err(e) => warn!("调整背压水位失败: {}", e),
+                    }
+                }
+                else => break,
+            }
+        }
+    });
+
+    // 指标端点：配置了监听地址时导出队列指标供 Prometheus 抓取
+    let metrics_address = config::string_or("metrics", "bind_address", "");
+    let metrics_handle = if metrics_address.is_empty() {
+        None
+    } else {
+        metrics::register(queue.clone());
+        Some(tokio::spawn(async move {
+            if let Err(e) = metrics::serve(metrics_address.as_str()).await {
+                warn!("指标端点启动失败: {}", e);
+            }
+        }))
+    };
+
+    // 槽位回收器：回收 entry / worker 崩溃后遗留在 WRITING/READING/INPROGRESS 的槽位
+    let lease_timeout =
+        Duration::from_secs(config::int_or("janitor", "lease_timeout_seconds", 30).max(1) as u64);
+    let monitor_interval =
+        Duration::from_secs(config::int_or("janitor", "interval_seconds", 5).max(1) as u64);
+    let mut janitor = SlotJanitor::new(lease_timeout)
+        .watch_named(
+            &config::string("worker", "interface_type"),
+            &config::string("worker", "interface_name"),
+        )
+        .watch_named(
+            &config::string("entry", "interface_type"),
+            &config::string("entry", "interface_name"),
+        );
+
+    // 大数据共享内存段回收：删除消费方未释放且已过期的段
+    let large_data = Arc::new(LargeDataManager::new(&config::string_or(
+        "large_data",
+        "prefix",
+        "mi7_large",
+    )));
+    let gc_interval =
+        Duration::from_secs(config::int_or("large_data", "gc_interval_seconds", 60).max(1) as u64);
+    let gc_handle = large_data.spawn_gc(gc_interval);
+
     // 启动监控任务
     let monitor_queue: Arc<CrossProcessPipe<100, 4096>> = Arc::clone(&queue);
+    let monitor_board = Arc::clone(&board);
+    let monitor_cluster = Arc::clone(&cluster);
     let monitor_handle = tokio::spawn(async move {
         loop {
             let status = monitor_queue.status();
@@ -36,7 +290,21 @@ async fn main() -> Result<()> {
                 //     status.message_count, status.capacity
                 // );
             }
-            sleep(Duration::from_secs(5)).await;
+            monitor_queue.reclaim_stuck(lease_timeout);
+            janitor.run_once();
+            // 心跳超时的进程即使 PID 仍存在（卡死或 PID 被复用）也不再派发任务
+            for process in monitor_cluster.prune() {
+                warn!(
+                    "进程 {} (PID: {}) 已失联 {:?}，清除其记录",
+                    process.name, process.pid, process.since_beat
+                );
+                monitor_board.evict(process.pid);
+            }
+            let pruned = monitor_board.prune();
+            if pruned > 0 {
+                warn!("已清除 {} 个已退出 worker 的登记", pruned);
+            }
+            sleep(monitor_interval).await;
         }
     });
 
@@ -46,7 +314,74 @@ async fn main() -> Result<()> {
 
     info!("收到停止信号，正在关闭守护进程...");
     monitor_handle.abort();
+    gc_handle.abort();
+    heartbeat_handle.abort();
+    // 先停止伸缩，避免在停止过程中启动新的 worker
+    let supervisor = match supervisor {
+        Some((stop, handle)) => {
+            stop.notify_one();
+            handle.await.ok()
+        }
+        None => None,
+    };
+    reload_handle.abort();
+    drop(config_watcher);
+    if let Some(handle) = metrics_handle {
+        handle.abort();
+    }
+
+    // 通知 entry 与 worker 停止，等待它们处理完在途任务并确认
+    coordinator.trigger();
+    let timeout = Duration::from_secs(config::int_or("shutdown", "timeout_seconds", 10).max(1) as u64);
+    let pending = coordinator.wait_for_acks(timeout).await;
+    if pending.is_empty() {
+        info!("所有进程已确认停止");
+    } else {
+        warn!("等待停止确认超时，未确认的进程: {:?}", pending);
+    }
+    if let Some(supervisor) = supervisor {
+        supervisor.wait_exit(Duration::from_secs(1)).await;
+    }
+
+    // 各进程停止后写完日志环中剩余的日志
+    if let Some((_ring, stop, handle)) = log_aggregation {
+        stop.store(true, Ordering::Release);
+        if handle.join().is_err() {
+            warn!("日志汇聚线程异常退出");
+        }
+    }
 
     info!("守护进程已安全关闭");
     Ok(())
 }
+
+/// 配置中的背压高低水位
+fn queue_watermarks() -> (usize, usize) {
+    (
+        config::int_or("queue", "high_watermark", 0).max(0) as usize,
+        config::int_or("queue", "low_watermark", 0).max(0) as usize,
+    )
+}
+
+/// 配置中的限流速率与桶容量，`rate` 为 0 时不限流
+///
+/// 两者都必须在 1..=u32::MAX 范围内，`burst` 未配置时与 `rate` 相同。
+fn rate_limit_config() -> Result<Option<(u32, u32)>> {
+    let rate = config::int_or("rate_limit", "rate", 0);
+    if rate == 0 {
+        return Ok(None);
+    }
+    let in_range = |key: &str, value: i64| {
+        u32::try_from(value).ok().filter(|&value| value > 0).ok_or_else(|| {
+            ConfigError::Validation(format!(
+                "无效的 rate_limit.{}: {}，有效范围: 1..={}",
+                key,
+                value,
+                u32::MAX
+            ))
+        })
+    };
+    let rate = in_range("rate", rate)?;
+    let burst = in_range("burst", config::int_or("rate_limit", "burst", rate as i64))?;
+    Ok(Some((rate, burst)))
+}
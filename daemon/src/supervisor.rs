@@ -0,0 +1,684 @@
+//! worker 进程池的自动伸缩
+//!
+//! [`WorkerSupervisor`] 由守护进程启动并管理 worker 进程：按请求管道中排队的任务数量
+//! 与 worker 登记表中的平均处理耗时扩容，空闲持续一段时间后缩容，进程数量始终保持在
+//! `[min_workers, max_workers]` 之间。缩容时向 worker 发送 SIGINT，由其处理完在途任务后退出。
+//!
+//! 意外退出的 entry / worker 按指数退避重启；在 `crash_window_seconds` 内崩溃达到
+//! `max_crashes` 次的进程标记为不健康并不再重启。各进程的状态见 [`SupervisorStatus`]。
+
+use anyhow::{Context, Result};
+use mi7::WorkerBoard;
+use mi7::pipe::{DynamicPipe, PipeFactory};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::process::{Child, Command};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+
+/// 检查进程退出与到期重启的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// `[supervisor]` 配置段中的伸缩策略
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ScalingPolicy {
+    /// 是否由守护进程启动并伸缩 worker
+    pub enabled: bool,
+    /// worker 可执行文件，为空时使用守护进程所在目录下的 `worker`
+    pub worker_binary: String,
+    /// worker ID 前缀，实际 ID 为前缀加序号
+    pub worker_id_prefix: String,
+    pub min_workers: usize,
+    pub max_workers: usize,
+    /// 平均每个 worker 排队（含处理中）的任务数量达到该值时扩容
+    pub scale_up_queue_depth: f64,
+    /// 平均处理耗时达到该值（毫秒）时扩容，0 表示不按耗时扩容
+    pub scale_up_latency_ms: u64,
+    /// 没有任何排队任务持续该时长（秒）后缩容一个 worker
+    pub scale_down_idle_seconds: u64,
+    /// 两次伸缩之间的最短间隔（秒）
+    pub cooldown_seconds: u64,
+    /// 检查间隔（秒）
+    pub interval_seconds: u64,
+}
+
+impl Default for ScalingPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            worker_binary: String::new(),
+            worker_id_prefix: "worker".to_string(),
+            min_workers: 1,
+            max_workers: 4,
+            scale_up_queue_depth: 4.0,
+            scale_up_latency_ms: 0,
+            scale_down_idle_seconds: 30,
+            cooldown_seconds: 10,
+            interval_seconds: 2,
+        }
+    }
+}
+
+/// 一次检查时采集的负载
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoadSample {
+    /// 请求管道中待领取的任务与各 worker 在途任务之和
+    pub queue_depth: usize,
+    /// 上次检查以来完成任务的平均处理耗时，期间没有完成任务时为 `None`
+    pub mean_latency: Option<Duration>,
+}
+
+/// 伸缩决策
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scaling {
+    Up,
+    Down,
+    Hold,
+}
+
+/// 根据负载与策略做出伸缩决策
+struct Scaler {
+    policy: ScalingPolicy,
+    idle_since: Option<Instant>,
+    last_change: Option<Instant>,
+}
+
+impl Scaler {
+    fn new(policy: ScalingPolicy) -> Self {
+        Self {
+            policy,
+            idle_since: None,
+            last_change: None,
+        }
+    }
+
+    fn decide(&mut self, workers: usize, sample: LoadSample, now: Instant) -> Scaling {
+        let policy = &self.policy;
+        let max_workers = policy.max_workers.max(policy.min_workers);
+        self.idle_since = match sample.queue_depth {
+            0 => self.idle_since.or(Some(now)),
+            _ => None,
+        };
+
+        // 低于下限或高于上限时立即调整，不受冷却时间限制
+        let decision = if workers < policy.min_workers {
+            return self.changed(Scaling::Up, now);
+        } else if workers > max_workers {
+            return self.changed(Scaling::Down, now);
+        } else if workers < max_workers && self.overloaded(workers, sample) {
+            Scaling::Up
+        } else if workers > policy.min_workers
+            && self.idle_since.is_some_and(|since| {
+                now.duration_since(since) >= Duration::from_secs(policy.scale_down_idle_seconds)
+            })
+        {
+            Scaling::Down
+        } else {
+            return Scaling::Hold;
+        };
+
+        let cooldown = Duration::from_secs(self.policy.cooldown_seconds);
+        if self
+            .last_change
+            .is_some_and(|last| now.duration_since(last) < cooldown)
+        {
+            return Scaling::Hold;
+        }
+        self.changed(decision, now)
+    }
+
+    fn overloaded(&self, workers: usize, sample: LoadSample) -> bool {
+        let depth = sample.queue_depth as f64 / workers.max(1) as f64;
+        let slow = self.policy.scale_up_latency_ms > 0
+            && sample.mean_latency.is_some_and(|latency| {
+                latency >= Duration::from_millis(self.policy.scale_up_latency_ms)
+            });
+        depth >= self.policy.scale_up_queue_depth || slow
+    }
+
+    fn changed(&mut self, decision: Scaling, now: Instant) -> Scaling {
+        self.last_change = Some(now);
+        // 缩容后重新计算空闲时长，避免连续缩容
+        self.idle_since = None;
+        decision
+    }
+}
+
+/// `[supervisor]` 配置段中的重启策略
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RestartPolicy {
+    /// 首次重启前的等待时间（毫秒），之后每次崩溃翻倍
+    pub initial_backoff_ms: u64,
+    /// 重启等待时间的上限（秒）
+    pub max_backoff_seconds: u64,
+    /// 统计连续崩溃的时间窗口（秒）
+    pub crash_window_seconds: u64,
+    /// 时间窗口内崩溃达到该次数后标记为不健康，不再重启
+    pub max_crashes: usize,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff_ms: 500,
+            max_backoff_seconds: 30,
+            crash_window_seconds: 60,
+            max_crashes: 5,
+        }
+    }
+}
+
+/// 记录一个进程最近的崩溃，决定重启前的等待时间
+#[derive(Debug, Default)]
+struct CrashHistory {
+    crashes: VecDeque<Instant>,
+}
+
+impl CrashHistory {
+    /// 记录一次崩溃，返回重启前的等待时间；时间窗口内崩溃过多时返回 `None`
+    fn record(&mut self, now: Instant, policy: &RestartPolicy) -> Option<Duration> {
+        let window = Duration::from_secs(policy.crash_window_seconds);
+        self.crashes
+            .retain(|&crash| now.duration_since(crash) < window);
+        self.crashes.push_back(now);
+        if self.crashes.len() >= policy.max_crashes.max(1) {
+            return None;
+        }
+        let backoff = Duration::from_millis(policy.initial_backoff_ms)
+            .saturating_mul(1 << (self.crashes.len() - 1).min(16));
+        Some(backoff.min(Duration::from_secs(policy.max_backoff_seconds)))
+    }
+
+    fn recent(&self) -> usize {
+        self.crashes.len()
+    }
+}
+
+/// 受监管的进程类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Entry,
+    Worker,
+}
+
+/// 受监管进程的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessState {
+    Running,
+    /// 崩溃后等待重启
+    Backoff,
+    /// 已发送停止信号，等待其退出
+    Retiring,
+    /// 短时间内崩溃过多，不再重启
+    Unhealthy,
+}
+
+/// 一个受监管进程的状态快照
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessStatus {
+    pub id: String,
+    pub role: Role,
+    pub pid: Option<u32>,
+    pub state: ProcessState,
+    /// 累计重启次数
+    pub restarts: u32,
+    /// 时间窗口内的崩溃次数
+    pub recent_crashes: usize,
+    /// 最近一次退出的状态
+    pub last_exit: Option<String>,
+    /// 本次启动以来的时长（秒）
+    pub uptime_seconds: u64,
+}
+
+/// 监管状态，通过指标端点的 `GET /status` 输出
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SupervisorStatus {
+    pub processes: Vec<ProcessStatus>,
+}
+
+impl SupervisorStatus {
+    /// 没有不健康的进程
+    pub fn healthy(&self) -> bool {
+        self.processes
+            .iter()
+            .all(|process| process.state != ProcessState::Unhealthy)
+    }
+}
+
+/// 由守护进程启动的进程
+struct ManagedProcess {
+    id: String,
+    role: Role,
+    binary: PathBuf,
+    child: Option<Child>,
+    state: ProcessState,
+    started: Instant,
+    restart_at: Instant,
+    restarts: u32,
+    crashes: CrashHistory,
+    last_exit: Option<String>,
+}
+
+impl ManagedProcess {
+    fn start(id: String, role: Role, binary: &Path) -> Result<Self> {
+        let mut process = Self {
+            id,
+            role,
+            binary: binary.to_path_buf(),
+            child: None,
+            state: ProcessState::Running,
+            started: Instant::now(),
+            restart_at: Instant::now(),
+            restarts: 0,
+            crashes: CrashHistory::default(),
+            last_exit: None,
+        };
+        process.spawn()?;
+        Ok(process)
+    }
+
+    fn spawn(&mut self) -> Result<()> {
+        let mut command = Command::new(&self.binary);
+        if self.role == Role::Worker {
+            command.arg(&self.id);
+        }
+        let child = command
+            .spawn()
+            .with_context(|| format!("启动 {} 失败", self.binary.display()))?;
+        info!("{} 已启动 (PID: {:?})", self.id, child.id());
+        self.child = Some(child);
+        self.state = ProcessState::Running;
+        self.started = Instant::now();
+        Ok(())
+    }
+
+    fn pid(&self) -> Option<u32> {
+        self.child.as_ref().and_then(Child::id)
+    }
+
+    /// 检查进程是否退出并按重启策略处理，返回是否应从进程池中移除
+    fn poll(&mut self, policy: &RestartPolicy, now: Instant) -> bool {
+        match self.state {
+            ProcessState::Backoff if now >= self.restart_at => {
+                self.restarts += 1;
+                if let Err(e) = self.spawn() {
+                    warn!("重启 {} 失败: {:?}", self.id, e);
+                    self.crashed(policy, now);
+                }
+                return false;
+            }
+            ProcessState::Backoff | ProcessState::Unhealthy => return false,
+            _ => {}
+        }
+
+        let Some(child) = self.child.as_mut() else {
+            return self.state == ProcessState::Retiring;
+        };
+        let status = match child.try_wait() {
+            Ok(Some(status)) => status,
+            Ok(None) => return false,
+            Err(e) => {
+                warn!("查询 {} 状态失败: {}", self.id, e);
+                return false;
+            }
+        };
+        self.child = None;
+        self.last_exit = Some(status.to_string());
+        if self.state == ProcessState::Retiring {
+            info!("{} 已退出: {}", self.id, status);
+            return true;
+        }
+        warn!("{} 意外退出: {}", self.id, status);
+        self.crashed(policy, now);
+        false
+    }
+
+    fn crashed(&mut self, policy: &RestartPolicy, now: Instant) {
+        match self.crashes.record(now, policy) {
+            Some(backoff) => {
+                info!("{} 将在 {:?} 后重启", self.id, backoff);
+                self.state = ProcessState::Backoff;
+                self.restart_at = now + backoff;
+            }
+            None => {
+                error!(
+                    "{} 在 {} 秒内崩溃 {} 次，标记为不健康，不再重启",
+                    self.id,
+                    policy.crash_window_seconds,
+                    self.crashes.recent()
+                );
+                self.state = ProcessState::Unhealthy;
+            }
+        }
+    }
+
+    /// 发送 SIGINT，进程处理完在途任务后退出
+    fn retire(&mut self) {
+        self.state = ProcessState::Retiring;
+        if let Some(pid) = self.pid() {
+            unsafe { libc::kill(pid as libc::pid_t, libc::SIGINT) };
+        }
+        info!("已通知 {} 停止", self.id);
+    }
+
+    fn status(&self) -> ProcessStatus {
+        ProcessStatus {
+            id: self.id.clone(),
+            role: self.role,
+            pid: self.pid(),
+            state: self.state,
+            restarts: self.restarts,
+            recent_crashes: self.crashes.recent(),
+            last_exit: self.last_exit.clone(),
+            uptime_seconds: match self.child {
+                Some(_) => self.started.elapsed().as_secs(),
+                None => 0,
+            },
+        }
+    }
+}
+
+/// 受监管进程池：按负载伸缩 worker，崩溃的 entry / worker 按退避策略重启
+pub struct WorkerSupervisor {
+    scaler: Scaler,
+    restart: RestartPolicy,
+    binary: PathBuf,
+    board: Arc<WorkerBoard>,
+    request_type: String,
+    request_name: String,
+    request_pipe: Option<Box<dyn DynamicPipe>>,
+    processes: Vec<ManagedProcess>,
+    next_id: usize,
+    /// 上次检查时登记表中累计的（完成数量, 处理耗时）
+    totals: (u64, Duration),
+    status: Arc<Mutex<SupervisorStatus>>,
+}
+
+impl WorkerSupervisor {
+    /// 创建进程池，`request_type` / `request_name` 为 worker 共享请求管道的类型与名称
+    pub fn new(
+        policy: ScalingPolicy,
+        restart: RestartPolicy,
+        board: Arc<WorkerBoard>,
+        request_type: &str,
+        request_name: &str,
+    ) -> Result<Self> {
+        let binary = sibling_binary(&policy.worker_binary, "worker")?;
+        Ok(Self {
+            scaler: Scaler::new(policy),
+            restart,
+            binary,
+            board,
+            request_type: request_type.to_string(),
+            request_name: request_name.to_string(),
+            request_pipe: None,
+            processes: Vec::new(),
+            next_id: 1,
+            totals: (0, Duration::ZERO),
+            status: Arc::default(),
+        })
+    }
+
+    /// 同时启动并监管 entry，`binary` 为空时使用守护进程所在目录下的 `entry`
+    pub fn with_entry(mut self, binary: &str) -> Result<Self> {
+        let binary = sibling_binary(binary, "entry")?;
+        self.processes.push(ManagedProcess::start(
+            "entry".to_string(),
+            Role::Entry,
+            &binary,
+        )?);
+        Ok(self)
+    }
+
+    /// 读取监管状态的句柄，进程池移入后台任务后仍可使用
+    pub fn status(&self) -> Arc<Mutex<SupervisorStatus>> {
+        Arc::clone(&self.status)
+    }
+
+    /// 在后台定期检查并伸缩，`stop` 被通知后返回进程池以便等待进程退出
+    pub fn spawn(mut self, stop: Arc<Notify>) -> JoinHandle<Self> {
+        let interval = Duration::from_secs(self.scaler.policy.interval_seconds.max(1));
+        tokio::spawn(async move {
+            info!(
+                "worker 进程池已启动: {} (数量 {} ~ {})",
+                self.binary.display(),
+                self.scaler.policy.min_workers,
+                self.scaler.policy.max_workers
+            );
+            let mut next_scale = Instant::now();
+            loop {
+                let now = Instant::now();
+                self.poll(now);
+                if now >= next_scale {
+                    self.scale(now);
+                    next_scale = now + interval;
+                }
+                self.publish_status();
+                tokio::select! {
+                    _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                    _ = stop.notified() => break,
+                }
+            }
+            self
+        })
+    }
+
+    /// 占用名额的 worker 数量：运行中、等待重启或不健康，不含正在退出的
+    pub fn active(&self) -> usize {
+        self.workers()
+            .filter(|process| process.state != ProcessState::Retiring)
+            .count()
+    }
+
+    fn workers(&self) -> impl Iterator<Item = &ManagedProcess> {
+        self.processes
+            .iter()
+            .filter(|process| process.role == Role::Worker)
+    }
+
+    fn poll(&mut self, now: Instant) {
+        let restart = &self.restart;
+        self.processes
+            .retain_mut(|process| !process.poll(restart, now));
+    }
+
+    fn scale(&mut self, now: Instant) {
+        let sample = self.sample();
+        let workers = self.active();
+        match self.scaler.decide(workers, sample, now) {
+            Scaling::Up => {
+                info!(
+                    "扩容 worker: {} -> {} (排队 {}, 平均耗时 {:?})",
+                    workers,
+                    workers + 1,
+                    sample.queue_depth,
+                    sample.mean_latency
+                );
+                if let Err(e) = self.start_worker() {
+                    warn!("启动 worker 失败: {:?}", e);
+                }
+            }
+            Scaling::Down => {
+                info!("缩容 worker: {} -> {}", workers, workers - 1);
+                self.retire_worker();
+            }
+            Scaling::Hold => debug!("worker 数量保持 {} (排队 {})", workers, sample.queue_depth),
+        }
+    }
+
+    fn publish_status(&self) {
+        *self.status.lock().unwrap() = SupervisorStatus {
+            processes: self.processes.iter().map(ManagedProcess::status).collect(),
+        };
+    }
+
+    /// 采集排队任务数量与上次检查以来的平均处理耗时
+    fn sample(&mut self) -> LoadSample {
+        if self.request_pipe.is_none() {
+            // 请求管道由 entry 创建，尚未创建时稍后重试
+            self.request_pipe =
+                PipeFactory::connect(&self.request_type, &self.request_name, false).ok();
+        }
+        let ready = self
+            .request_pipe
+            .as_ref()
+            .map_or(0, |pipe| pipe.status().ready_count);
+
+        let workers = self.board.workers();
+        let in_flight: usize = workers.iter().map(|w| w.in_flight as usize).sum();
+        let totals = workers.iter().fold((0, Duration::ZERO), |(n, busy), w| {
+            (n + w.processed, busy + w.busy)
+        });
+        // worker 退出后其累计值离开登记表，此时只重新记录基准
+        let mean_latency = match totals.0.checked_sub(self.totals.0) {
+            Some(processed) if processed > 0 && totals.1 >= self.totals.1 => {
+                Some((totals.1 - self.totals.1) / processed as u32)
+            }
+            _ => None,
+        };
+        self.totals = totals;
+
+        LoadSample {
+            queue_depth: ready + in_flight,
+            mean_latency,
+        }
+    }
+
+    fn start_worker(&mut self) -> Result<()> {
+        let worker_id = format!("{}{}", self.scaler.policy.worker_id_prefix, self.next_id);
+        self.next_id += 1;
+        self.processes.push(ManagedProcess::start(
+            worker_id,
+            Role::Worker,
+            &self.binary,
+        )?);
+        Ok(())
+    }
+
+    /// 让最近启动的 worker 处理完在途任务后退出，优先移除没有在运行的
+    fn retire_worker(&mut self) {
+        let idle = |process: &ManagedProcess| {
+            process.role == Role::Worker
+                && matches!(
+                    process.state,
+                    ProcessState::Backoff | ProcessState::Unhealthy
+                )
+        };
+        if let Some(index) = self.processes.iter().rposition(idle) {
+            let process = self.processes.remove(index);
+            info!("已移除未在运行的 {}", process.id);
+        } else if let Some(process) =
+            self.processes.iter_mut().rev().find(|process| {
+                process.role == Role::Worker && process.state == ProcessState::Running
+            })
+        {
+            process.retire();
+        }
+    }
+
+    /// 等待所有进程退出，超时后强制结束仍在运行的进程
+    pub async fn wait_exit(mut self, timeout: Duration) {
+        let deadline = tokio::time::Instant::now() + timeout;
+        for process in &mut self.processes {
+            let Some(child) = process.child.as_mut() else {
+                continue;
+            };
+            if tokio::time::timeout_at(deadline, child.wait())
+                .await
+                .is_err()
+            {
+                warn!("{} 未在超时前退出，强制结束", process.id);
+                let _ = child.kill().await;
+            }
+        }
+    }
+}
+
+/// 配置的可执行文件路径，为空时使用守护进程所在目录下的 `name`
+fn sibling_binary(configured: &str, name: &str) -> Result<PathBuf> {
+    if configured.is_empty() {
+        Ok(std::env::current_exe()
+            .context("无法确定守护进程的路径")?
+            .with_file_name(name))
+    } else {
+        Ok(PathBuf::from(configured))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scaling_decisions() {
+        let policy = ScalingPolicy {
+            min_workers: 1,
+            max_workers: 3,
+            scale_up_latency_ms: 200,
+            scale_down_idle_seconds: 5,
+            cooldown_seconds: 2,
+            ..ScalingPolicy::default()
+        };
+        let mut scaler = Scaler::new(policy);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let load = |queue_depth, latency_ms: Option<u64>| LoadSample {
+            queue_depth,
+            mean_latency: latency_ms.map(Duration::from_millis),
+        };
+
+        // 低于下限时立即启动
+        assert_eq!(scaler.decide(0, load(0, None), at(0)), Scaling::Up);
+        // 排队过深时扩容，但受冷却时间限制
+        assert_eq!(scaler.decide(1, load(8, None), at(1)), Scaling::Hold);
+        assert_eq!(scaler.decide(1, load(8, None), at(2)), Scaling::Up);
+        // 处理耗时过长时扩容
+        assert_eq!(scaler.decide(2, load(1, Some(300)), at(4)), Scaling::Up);
+        // 达到上限后不再扩容
+        assert_eq!(scaler.decide(3, load(100, None), at(10)), Scaling::Hold);
+        // 空闲持续足够久才缩容，且每次只缩一个
+        assert_eq!(scaler.decide(3, load(0, None), at(11)), Scaling::Hold);
+        assert_eq!(scaler.decide(3, load(0, None), at(16)), Scaling::Down);
+        assert_eq!(scaler.decide(2, load(0, None), at(17)), Scaling::Hold);
+        assert_eq!(scaler.decide(2, load(0, None), at(22)), Scaling::Down);
+        // 不低于下限
+        assert_eq!(scaler.decide(1, load(0, None), at(60)), Scaling::Hold);
+    }
+
+    #[test]
+    fn test_restart_backoff() {
+        let policy = RestartPolicy {
+            initial_backoff_ms: 100,
+            max_backoff_seconds: 1,
+            crash_window_seconds: 60,
+            max_crashes: 6,
+        };
+        let mut history = CrashHistory::default();
+        let start = Instant::now();
+        let backoffs: Vec<_> = (0..5)
+            .map(|i| history.record(start + Duration::from_secs(i), &policy))
+            .collect();
+        let ms = |ms| Some(Duration::from_millis(ms));
+        assert_eq!(backoffs, [ms(100), ms(200), ms(400), ms(800), ms(1000)]);
+        // 时间窗口内崩溃过多，不再重启
+        assert_eq!(
+            history.record(start + Duration::from_secs(5), &policy),
+            None
+        );
+        // 窗口外的崩溃不再计入
+        let mut history = CrashHistory::default();
+        history.record(start, &policy);
+        assert_eq!(
+            history.record(start + Duration::from_secs(61), &policy),
+            ms(100)
+        );
+    }
+}
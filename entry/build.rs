@@ -0,0 +1,11 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // 使用随依赖发布的 protoc，构建环境无需另行安装
+    let protoc = protoc_bin_vendored::protoc_bin_path()?;
+    // SAFETY: 构建脚本是单线程的
+    unsafe { std::env::set_var("PROTOC", protoc) };
+
+    tonic_prost_build::configure()
+        .build_client(false)
+        .compile_protos(&["proto/gateway.proto"], &["proto"])?;
+    Ok(())
+}
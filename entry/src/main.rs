@@ -1,15 +1,32 @@
 mod protocols;
 mod scheduler;
 
-use mi7::{config, logging::init_default_logging};
+use mi7::{config, log_ring, logging};
 
-use protocols::{http_server, mqtt_server, tcp_server, udp_server, ws_server};
+use protocols::{grpc_server, http_server, tcp_server, udp_server, ws_server};
 use scheduler::Scheduler;
-use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
-use tracing::{debug, error, info};
+use tracing::{error, info, warn};
+use mi7::{
+    AffinityRing, ProcessRole, RpcChannel, SharedRateLimiter, ShutdownCoordinator, WorkerBoard,
+    affinity, cluster, rate_limit, worker_board,
+};
 use mi7::pipe::PipeFactory;
+use mi7::shared_box::SharedMemoryMailbox;
+
+/// 在后台运行协议服务，异常退出时记录日志
+fn spawn_server(
+    protocol: &'static str,
+    server: impl Future<Output = anyhow::Result<()>> + Send + 'static,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            error!("{} 服务异常退出: {:?}", protocol, e);
+        }
+    })
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -17,10 +34,28 @@ async fn main() -> anyhow::Result<()> {
     config::init_config()?;
 
     // 初始化日志系统
-    init_default_logging("entry")?;
+    if config::bool_or("logging", "aggregate", false) {
+        logging::init_aggregated_logging(
+            logging::LogConfig::new("entry"),
+            &config::string_or("logging", "ring_name", log_ring::DEFAULT_RING_NAME),
+        )?;
+    } else {
+        logging::init_default_logging("entry")?;
+    }
 
     info!("启动消息生产者 (Entry)");
 
+    // 按 [access] 设置共享内存的创建权限与连接握手策略
+    mi7::access::init_from_config()?;
+    // 按 [encryption] 加载负载密钥，此后创建或连接的共享内存管道加密读写
+    mi7::encryption::init_from_config()?;
+    // 按 [authentication] 加载消息认证密钥，此后创建或连接的共享内存管道校验每条消息
+    mi7::auth::init_from_config()?;
+    // 按 [wait] 设置队列空或满时的等待方式（休眠或轮询）
+    mi7::wait::init_from_config()?;
+    // 按 [memory] 锁定或预读共享内存段的页面
+    mi7::memory::init_from_config()?;
+
     // 使用配置中的队列名称
     let interface_name = config::string("worker", "interface_name");
     let interface_type = config::string("worker", "interface_type");
@@ -37,45 +72,236 @@ async fn main() -> anyhow::Result<()> {
         }
         Err(e) => {
             error!("连接管道失败: {:?}", e);
-            return Err(e);
+            return Err(e.into());
         }
     };
 
     info!("已连接到消息队列: {}", interface_name);
 
-    // 创建调度者
-    let scheduler = Scheduler::new(pipe.clone());
-    let counter = scheduler.get_counter();
-    let slot_sender = scheduler.get_slot_sender();
-
-    // 启动调度者协程
-    let scheduler_handle = tokio::spawn(async move {
-        info!("启动调度者协程");
-        scheduler.run().await;
-    });
-
-    let http_handle = tokio::spawn(async move {
-        // 使用配置中的 HTTP 服务器地址和端口
-        let bind_address = config::string("http", "bind_address");
-        let port = config::string("http", "port");
-        let addr: SocketAddr = format!("{}:{}", bind_address, port).parse().unwrap();
-        info!("启动 HTTP 服务器，监听地址: {}", addr);
-        http_server::run(addr, pipe, counter, slot_sender)
-            .await
-            .expect("http server failed");
-    });
-
-    // 启动后台响应处理循环
-    let response_handler_handle = tokio::spawn(async move {
-        info!("启动后台响应处理循环");
-        http_server::response_handler_loop().await;
-    });
-
-    // Wait for servers (they run forever)
-    let _ = tokio::try_join!(scheduler_handle, http_handle, response_handler_handle);
+    // 创建响应管道，worker 通过它回复请求
+    let response_name = config::string("entry", "interface_name");
+    let response_type = config::string("entry", "interface_type");
+    let response_pipe = match PipeFactory::create(&response_type, &response_name) {
+        Ok(pipe) => Arc::new(pipe),
+        Err(e) => {
+            error!("创建响应管道失败: {:?}", e);
+            return Err(e.into());
+        }
+    };
+    info!("已创建响应管道: {}", response_name);
+
+    let http_config: http_server::HttpConfig = config::section("http")?;
+    let rpc = Arc::new(
+        RpcChannel::new(pipe.clone(), response_pipe)
+            .with_timeout(Duration::from_secs(http_config.timeout_seconds.max(1))),
+    );
+
+    // 连接 worker 登记表，创建调度者；登记表不可用时只经由共享请求管道派发
+    let board_name = config::string_or("scheduler", "board_name", worker_board::DEFAULT_BOARD_NAME);
+    let heartbeat_timeout = Duration::from_secs(
+        config::int_or("scheduler", "heartbeat_timeout_seconds", 5).max(1) as u64,
+    );
+    let board = match WorkerBoard::open(&board_name) {
+        Ok(board) => Some(Arc::new(board.with_heartbeat_timeout(heartbeat_timeout))),
+        Err(e) => {
+            warn!(
+                "连接 worker 登记表 {} 失败，仅使用共享请求管道: {:?}",
+                board_name, e
+            );
+            None
+        }
+    };
+    let mut scheduler = Scheduler::new(rpc.clone(), pipe.clone(), board.clone(), &interface_type);
+
+    // 亲和路由：携带亲和键的请求总是派发给同一个 worker
+    if config::string_or("scheduler", "routing", "least_loaded") == "affinity" {
+        let ring_name = config::string_or(
+            "scheduler",
+            "affinity_ring_name",
+            affinity::DEFAULT_RING_NAME,
+        );
+        match AffinityRing::open(&ring_name) {
+            Ok(ring) => {
+                info!("已启用亲和路由，哈希环: {}", ring_name);
+                scheduler = scheduler.with_affinity(ring);
+            }
+            Err(e) => warn!("连接亲和哈希环 {} 失败，按负载派发: {:?}", ring_name, e),
+        }
+    }
+    // 跨进程限流：令牌桶由守护进程按 [rate_limit] 创建
+    if config::int_or("rate_limit", "rate", 0) > 0 {
+        let name = config::string_or("rate_limit", "name", rate_limit::DEFAULT_LIMITER_NAME);
+        match SharedRateLimiter::open(&name) {
+            Ok(limiter) => {
+                info!("已启用限流，令牌桶: {} (每秒 {} 个请求)", name, limiter.rate());
+                scheduler = scheduler.with_rate_limiter(limiter);
+            }
+            Err(e) => warn!("连接限流器 {} 失败，不限流: {:?}", name, e),
+        }
+    }
+    let scheduler = Arc::new(scheduler);
+
+    // 连接停止协调控制块，守护进程据此通知停止
+    let coordinator = Arc::new(ShutdownCoordinator::open(&config::string_or(
+        "shutdown",
+        "control_name",
+        "mi7_control",
+    ))?);
 
+    // 加入进程心跳区，/status 据此展示各进程是否存活
+    let (cluster, heartbeat_handle) = match cluster::from_config(false) {
+        Ok(view) => {
+            let view = Arc::new(view);
+            let handle = cluster::start_heartbeat(&view, ProcessRole::Entry, "entry")?;
+            (Some(view), Some(handle))
+        }
+        Err(e) => {
+            warn!("连接进程心跳区失败: {:?}", e);
+            (None, None)
+        }
+    };
+
+    // 配置了寄存箱名称时连接，/status/mailbox 输出其统计
+    let mailbox_name = config::string_or("entry", "mailbox_name", "");
+    let mailbox = if mailbox_name.is_empty() {
+        None
+    } else {
+        match SharedMemoryMailbox::connect(&mailbox_name) {
+            Ok(mailbox) => Some(Arc::new(mailbox)),
+            Err(e) => {
+                warn!("连接寄存箱 {} 失败: {:?}", mailbox_name, e);
+                None
+            }
+        }
+    };
+
+    // 启动后台响应分发任务
+    info!("启动后台响应分发任务");
+    let response_handler_handle = rpc.start();
+    // 按 worker 登记表发现各 worker 的专属响应管道，并读取其中的回复
+    let discovery_handle =
+        board.map(|board| rpc.discover(board, &response_type, Duration::from_secs(1)));
+
+    // 使用配置中的 HTTP 服务器地址和端口
+    let addr = http_config.addr()?;
+    info!("启动 HTTP 服务器，监听地址: {}", addr);
+
+    // 收到停止信号后不再接受新连接，已接受的请求处理完毕后 run 返回
+    let shutdown_signal = |protocol: &'static str| {
+        let coordinator = coordinator.clone();
+        async move {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => info!("收到 Ctrl+C，停止接受 {} 请求", protocol),
+                _ = coordinator.wait_for_shutdown() => info!("收到守护进程的停止请求，停止接受 {} 请求", protocol),
+            }
+        }
+    };
+
+    // 可选的协议服务：配置了对应配置段时启动，与 HTTP 共用调度者和响应通道
+    let has_section = |name: &str| config::get_config().get_keys(name).is_some();
+    let mut servers = Vec::new();
+    if has_section("grpc") {
+        let grpc_config: grpc_server::GrpcConfig = config::section("grpc")?;
+        servers.push(spawn_server(
+            "gRPC",
+            grpc_server::run(
+                grpc_config.addr()?,
+                pipe.clone(),
+                scheduler.clone(),
+                shutdown_signal("gRPC"),
+            ),
+        ));
+    }
+    // worker 的推送经响应管道转发给对应的 WebSocket 连接
+    if has_section("websocket") {
+        let ws_config: ws_server::WsConfig = config::section("websocket")?;
+        servers.push(spawn_server(
+            "WebSocket",
+            ws_server::run(
+                ws_config.addr()?,
+                pipe.clone(),
+                scheduler.clone(),
+                rpc.subscribe_pushes(),
+                shutdown_signal("WebSocket"),
+            ),
+        ));
+    }
+    if has_section("tcp") {
+        let tcp_config: tcp_server::TcpConfig = config::section("tcp")?;
+        servers.push(spawn_server(
+            "TCP",
+            tcp_server::run(
+                tcp_config,
+                pipe.clone(),
+                scheduler.clone(),
+                shutdown_signal("TCP"),
+            ),
+        ));
+    }
+    if has_section("udp") {
+        let udp_config: udp_server::UdpConfig = config::section("udp")?;
+        servers.push(spawn_server(
+            "UDP",
+            udp_server::run(
+                udp_config.addr()?,
+                pipe.clone(),
+                scheduler.clone(),
+                shutdown_signal("UDP"),
+            ),
+        ));
+    }
+    // 启用 mqtt 特性时订阅代理上的主题
     #[cfg(feature = "mqtt")]
-    let _ = mqtt_handle.unwrap();
+    if has_section("mqtt") {
+        let mqtt_config: protocols::mqtt_server::MqttConfig = config::section("mqtt")?;
+        servers.push(spawn_server(
+            "MQTT",
+            protocols::mqtt_server::run(
+                mqtt_config,
+                pipe.clone(),
+                scheduler.clone(),
+                shutdown_signal("MQTT"),
+            ),
+        ));
+    }
+
+    let http_shutdown = shutdown_signal("HTTP");
+    if let Err(e) = http_server::run(
+        addr,
+        pipe,
+        rpc.clone(),
+        scheduler,
+        cluster,
+        mailbox,
+        http_shutdown,
+    )
+    .await
+    {
+        error!("HTTP 服务器异常退出: {:?}", e);
+    }
+    for server in servers {
+        let _ = server.await;
+    }
+
+    // 等待在途请求的响应
+    let timeout = Duration::from_secs(config::int_or("shutdown", "timeout_seconds", 10).max(1) as u64);
+    let deadline = tokio::time::Instant::now() + timeout;
+    while rpc.pending_count() > 0 && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    if rpc.pending_count() > 0 {
+        warn!("停止超时，仍有 {} 个请求未收到响应", rpc.pending_count());
+    }
+
+    response_handler_handle.abort();
+    if let Some(handle) = discovery_handle {
+        handle.abort();
+    }
+    if let Some(handle) = heartbeat_handle {
+        handle.abort();
+    }
+    coordinator.acknowledge();
+    info!("Entry 已安全退出");
 
     Ok(())
 }
@@ -1,43 +1,92 @@
+use crate::scheduler::Scheduler;
+pub use mi7::command::{Command, RawTransport};
+use mi7::pipe::DynamicPipe;
+use mi7::{ErrorCode, Message, SchemaError, tracing_ipc};
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::Instrument;
 
-/// 跨进程消息命令枚举
-#[derive(Debug, Clone, Serialize, Deserialize, bincode::Encode, bincode::Decode)]
-pub enum Command {
-    /// HTTP 请求命令
-    HttpRequest {
-        id: u64,
-        path: String,
-        method: String,
-        body: Option<String>,
-        headers: Option<String>,
-    },
-    /// WebSocket 消息
-    WsMessage {
-        id: u64,
-        client: String,
-        payload: String,
-    },
-    /// TCP 数据包
-    TcpPacket {
-        id: u64,
-        peer: std::net::SocketAddr,
-        payload: Vec<u8>,
-    },
-    /// UDP 数据包
-    UdpPacket {
-        id: u64,
-        peer: std::net::SocketAddr,
-        payload: Vec<u8>,
-    },
-    /// MQTT 发布消息
-    MqttPublish {
-        id: u64,
-        topic: String,
-        payload: Vec<u8>,
-    },
+/// 把命令编码为发往 worker 的消息，过期时间由 RPC 通道按请求超时设置
+pub fn command_message(cmd: &Command) -> Result<Message, SchemaError> {
+    Ok(Message {
+        flag: cmd.flag(),
+        data: cmd.encode()?,
+        timestamp: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+        expires_at: 0,
+        trace: None,
+        affinity: 0,
+    })
+}
+
+/// 派发原始协议请求并等待 worker 回复，返回回复负载；失败时返回 JSON 格式的 [`ErrorResponse`]
+pub async fn dispatch_raw(
+    queue: &Arc<Box<dyn DynamicPipe>>,
+    scheduler: &Scheduler,
+    cmd: Command,
+    affinity_key: &[u8],
+) -> Vec<u8> {
+    let error = |error: String, code: u16| {
+        serde_json::to_vec(&ErrorResponse { error, code }).unwrap_or_default()
+    };
+    let message = match command_message(&cmd) {
+        Ok(message) => message,
+        Err(e) => return error(format!("序列化失败: {}", e), 500),
+    };
+    let mut message = message.with_affinity(affinity_key);
+    let trace_span = tracing_ipc::producer_span(&mut message);
+
+    // 队列占用超过高水位时直接拒绝，由客户端稍后重试
+    if queue.is_backpressured() {
+        return error("队列繁忙，请稍后重试".to_string(), 429);
+    }
+    match scheduler.call(message).instrument(trace_span).await {
+        Ok(reply) => match reply_body(&reply) {
+            Ok(body) => body,
+            Err(e) => serde_json::to_vec(&e).unwrap_or_default(),
+        },
+        Err(e) => serde_json::to_vec(&call_error(e)).unwrap_or_default(),
+    }
+}
+
+/// [`Scheduler::call`] 失败时返回给客户端的错误
+///
+/// 按 [`mi7::Error`] 的错误码区分：超过限流速率为 429，其余视为未能按时得到 worker 回复（504）。
+/// 各协议据此转换为自己的状态码（见 [`ErrorResponse::code`]）。
+pub fn call_error(e: mi7::Error) -> ErrorResponse {
+    match e.code() {
+        ErrorCode::RateLimited => ErrorResponse {
+            error: format!("{}", e),
+            code: 429,
+        },
+        _ => ErrorResponse {
+            error: format!("请求处理失败: {}", e),
+            code: 504,
+        },
+    }
+}
+
+/// 解析 worker 的回复（见 [`mi7::Response`]），成功时返回业务数据
+///
+/// worker 报告的错误沿用其状态码；回复无法解码（如 worker 使用更新的协议版本）时返回 502。
+pub fn reply_body(reply: &Message) -> Result<Vec<u8>, ErrorResponse> {
+    match mi7::Response::decode(&reply.data) {
+        Ok(mi7::Response::Ok { body, .. }) => Ok(body),
+        Ok(mi7::Response::Error { code, message, .. }) => Err(ErrorResponse {
+            error: message,
+            code,
+        }),
+        Err(e) => Err(ErrorResponse {
+            error: format!("无法解析 worker 回复: {}", e),
+            code: 502,
+        }),
+    }
 }
 
 /// HTTP 请求体结构
+#[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize)]
 pub struct HttpRequestBody {
     pub message: String,
@@ -45,6 +94,7 @@ pub struct HttpRequestBody {
 }
 
 /// HTTP 响应结构
+#[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize)]
 pub struct HttpResponse {
     pub success: bool,
@@ -56,5 +106,6 @@ pub struct HttpResponse {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ErrorResponse {
     pub error: String,
+    /// HTTP 语义的状态码
     pub code: u16,
-}
\ No newline at end of file
+}
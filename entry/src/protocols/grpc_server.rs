@@ -0,0 +1,333 @@
+//! gRPC 网关：与 HTTP 统一处理器共用命令路由
+//!
+//! 一元调用 `mi7.gateway.Gateway/Call` 转换为 [`Command::HttpRequest`] 写入请求管道，
+//! 由同一个调度者派发给 worker，并经同一个 RPC 通道的响应表等待回复。
+//! 协议定义见 `proto/gateway.proto`。
+
+use crate::protocols::common::{Command, call_error, command_message, reply_body};
+use crate::protocols::http_server::{AFFINITY_HEADER, REQ_ID, authenticate};
+use crate::scheduler::Scheduler;
+use mi7::pipe::DynamicPipe;
+use mi7::{TraceContext, tracing_ipc};
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use tonic::{Request, Response, Status};
+use tracing::{Instrument, debug, error, info, warn};
+
+mod pb {
+    tonic::include_proto!("mi7.gateway");
+}
+
+use pb::gateway_server::{Gateway, GatewayServer};
+use pb::{CallReply, CallRequest};
+
+/// `[grpc]` 配置段，缺省时不启动 gRPC 服务
+#[derive(Debug, Deserialize)]
+pub struct GrpcConfig {
+    #[serde(default = "GrpcConfig::default_bind_address")]
+    pub bind_address: String,
+    pub port: u16,
+}
+
+impl GrpcConfig {
+    fn default_bind_address() -> String {
+        "0.0.0.0".to_string()
+    }
+
+    /// 监听地址
+    pub fn addr(&self) -> anyhow::Result<SocketAddr> {
+        format!("{}:{}", self.bind_address, self.port)
+            .parse()
+            .map_err(|e| anyhow::anyhow!("无效的 gRPC 监听地址 {}: {}", self.bind_address, e))
+    }
+}
+
+/// gRPC 网关服务
+struct GatewayService {
+    queue: Arc<Box<dyn DynamicPipe>>,
+    scheduler: Arc<Scheduler>,
+}
+
+pub async fn run(
+    addr: SocketAddr,
+    queue: Arc<Box<dyn DynamicPipe>>,
+    scheduler: Arc<Scheduler>,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> anyhow::Result<()> {
+    let service = GatewayService { queue, scheduler };
+
+    info!("gRPC 服务器启动成功，监听地址: {}", addr);
+    tonic::transport::Server::builder()
+        .add_service(GatewayServer::new(service))
+        .serve_with_shutdown(addr, shutdown)
+        .await?;
+    info!("gRPC 服务器已停止");
+    Ok(())
+}
+
+#[tonic::async_trait]
+impl Gateway for GatewayService {
+    async fn call(&self, request: Request<CallRequest>) -> Result<Response<CallReply>, Status> {
+        let start_time = std::time::Instant::now();
+        let task_id = REQ_ID.fetch_add(1, Ordering::Relaxed);
+        let metadata = request.metadata();
+
+        let token = metadata
+            .get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("");
+        if token.is_empty() {
+            warn!(
+                "[GRPC_AUTH_FAILED] 任务ID: {}, 原因: 缺少 authorization 元数据",
+                task_id
+            );
+            return Err(Status::unauthenticated("缺少 authorization 元数据"));
+        }
+        if !authenticate(token).await {
+            warn!(
+                "[GRPC_AUTH_FAILED] 任务ID: {}, 原因: Token 验证失败",
+                task_id
+            );
+            return Err(Status::unauthenticated("鉴权失败"));
+        }
+
+        // 上游携带 traceparent 时延续其链路
+        let upstream_trace = metadata
+            .get("traceparent")
+            .and_then(|v| v.to_str().ok())
+            .and_then(TraceContext::from_traceparent);
+        let affinity_key = metadata.get(AFFINITY_HEADER).map(|v| v.as_bytes().to_vec());
+
+        let call = request.into_inner();
+        let method = if call.method.is_empty() {
+            "POST".to_string()
+        } else {
+            call.method.to_uppercase()
+        };
+        info!(
+            "[GRPC_START] 任务ID: {}, 方法: {}, 路径: {}",
+            task_id, method, call.path
+        );
+
+        let cmd = Command::HttpRequest {
+            id: task_id,
+            path: call.path.clone(),
+            method,
+            body: call.body,
+            headers: if call.params.is_empty() {
+                None
+            } else {
+                Some(serde_json::to_string(&call.params).unwrap_or_default())
+            },
+        };
+        let mut message = command_message(&cmd).map_err(|e| {
+            error!("[GRPC_SERIALIZE_ERROR] 任务ID: {}, 错误: {}", task_id, e);
+            Status::internal(format!("序列化失败: {}", e))
+        })?;
+        message.trace = upstream_trace;
+        if let Some(key) = affinity_key {
+            message = message.with_affinity(key);
+        }
+        let trace_span = tracing_ipc::producer_span(&mut message);
+
+        // 队列占用超过高水位时直接拒绝，由客户端稍后重试
+        if self.queue.is_backpressured() {
+            warn!("[GRPC_BACKPRESSURE] 任务ID: {}", task_id);
+            return Err(Status::resource_exhausted("队列繁忙，请稍后重试"));
+        }
+
+        // 客户端取消调用时 future 被丢弃，等待中的请求随之取消
+        match self.scheduler.call(message).instrument(trace_span).await {
+            Ok(reply) => {
+                info!(
+                    "[GRPC_SUCCESS] 任务ID: {}, 路径: {}, 总耗时: {:?}",
+                    task_id,
+                    call.path,
+                    start_time.elapsed()
+                );
+                debug!(
+                    "[GRPC_REPLY] 任务ID: {}, 回复大小: {} bytes",
+                    task_id,
+                    reply.data.len()
+                );
+                match reply_body(&reply) {
+                    Ok(data) => Ok(Response::new(CallReply { task_id, data })),
+                    Err(e) => Err(Status::new(status_code(e.code), e.error)),
+                }
+            }
+            Err(e) => {
+                error!(
+                    "[GRPC_FAILED] 任务ID: {}, 错误: {}, 耗时: {:?}",
+                    task_id,
+                    e,
+                    start_time.elapsed()
+                );
+                Err(call_status(e))
+            }
+        }
+    }
+}
+
+/// [`Scheduler::call`] 失败（限流、超时、管道错误）对应的 gRPC 状态
+fn call_status(e: mi7::Error) -> Status {
+    let e = call_error(e);
+    Status::new(status_code(e.code), e.error)
+}
+
+/// worker 报告的 HTTP 语义状态码对应的 gRPC 状态码
+fn status_code(code: u16) -> tonic::Code {
+    match code {
+        400 => tonic::Code::InvalidArgument,
+        401 => tonic::Code::Unauthenticated,
+        403 => tonic::Code::PermissionDenied,
+        404 => tonic::Code::NotFound,
+        429 => tonic::Code::ResourceExhausted,
+        501 => tonic::Code::Unimplemented,
+        503 => tonic::Code::Unavailable,
+        504 => tonic::Code::DeadlineExceeded,
+        _ => tonic::Code::Internal,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mi7::pipe::{PipeConfig, PipeTimeout};
+    use mi7::{HeapSlotPipe, Message, RateLimited, RpcChannel};
+    use std::time::Duration;
+
+    /// 请求管道没有 worker 读取时调用在 50ms 后超时；返回服务与请求、响应管道
+    fn service(tag: &str) -> (GatewayService, HeapSlotPipe, HeapSlotPipe) {
+        let name = |pipe: &str| format!("entry_test_grpc_{}_{}", tag, pipe);
+        let request = HeapSlotPipe::create(&name("request"), PipeConfig::new(8, 1024)).unwrap();
+        let response = HeapSlotPipe::create(&name("response"), PipeConfig::new(8, 1024)).unwrap();
+        let queue: Arc<Box<dyn DynamicPipe>> = Arc::new(Box::new(request.clone()));
+        let rpc = RpcChannel::new(queue.clone(), Arc::new(Box::new(response.clone())))
+            .with_timeout(Duration::from_millis(50));
+        rpc.start();
+        let scheduler = Arc::new(Scheduler::new(
+            Arc::new(rpc),
+            queue.clone(),
+            None,
+            "default",
+        ));
+        (GatewayService { queue, scheduler }, request, response)
+    }
+
+    fn request(token: &str) -> Request<CallRequest> {
+        let mut request = Request::new(CallRequest {
+            path: "/api/echo".to_string(),
+            ..Default::default()
+        });
+        if !token.is_empty() {
+            request
+                .metadata_mut()
+                .insert("authorization", token.parse().unwrap());
+        }
+        request
+    }
+
+    /// 读取一条请求并以 `data` 回复
+    fn reply(request: HeapSlotPipe, response: HeapSlotPipe, data: Vec<u8>) {
+        tokio::spawn(async move {
+            let index = request.fetch_async().await.unwrap();
+            let (request_id, _) = request.receive_tagged(index).unwrap();
+            let index = response.hold().unwrap();
+            let mut message = Message::init(String::new());
+            message.data = data;
+            response.send_tagged(index, request_id, message).unwrap();
+        });
+    }
+
+    #[test]
+    fn test_status_code() {
+        for (code, expected) in [
+            (400, tonic::Code::InvalidArgument),
+            (401, tonic::Code::Unauthenticated),
+            (403, tonic::Code::PermissionDenied),
+            (404, tonic::Code::NotFound),
+            (429, tonic::Code::ResourceExhausted),
+            (500, tonic::Code::Internal),
+            (501, tonic::Code::Unimplemented),
+            (502, tonic::Code::Internal),
+            (503, tonic::Code::Unavailable),
+            (504, tonic::Code::DeadlineExceeded),
+        ] {
+            assert_eq!(status_code(code), expected, "HTTP {}", code);
+        }
+    }
+
+    #[test]
+    fn test_call_status() {
+        let limited = RateLimited {
+            retry_after: Duration::from_millis(5),
+        };
+        assert_eq!(
+            call_status(limited.into()).code(),
+            tonic::Code::ResourceExhausted
+        );
+
+        let timeout = PipeTimeout::Receive(Duration::from_millis(50));
+        assert_eq!(
+            call_status(timeout.into()).code(),
+            tonic::Code::DeadlineExceeded
+        );
+
+        let pipe_error = mi7::Error::from(anyhow::anyhow!("管道已损坏"));
+        let status = call_status(pipe_error);
+        assert_eq!(status.code(), tonic::Code::DeadlineExceeded);
+        assert!(status.message().contains("管道已损坏"));
+    }
+
+    #[tokio::test]
+    async fn test_missing_authorization_rejected() {
+        let (service, request_pipe, _) = service("auth");
+        let status = service.call(request("")).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+        // 鉴权失败的调用不写入请求管道
+        assert_eq!(request_pipe.status().sent_count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_worker_error_status() {
+        let (service, request_pipe, response_pipe) = service("worker_error");
+        let error = mi7::Response::Error {
+            id: 0,
+            code: 404,
+            message: "not found".to_string(),
+        };
+        reply(request_pipe, response_pipe, error.encode().unwrap());
+        let status = service.call(request("token")).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+        assert_eq!(status.message(), "not found");
+    }
+
+    #[tokio::test]
+    async fn test_undecodable_reply_status() {
+        let (service, request_pipe, response_pipe) = service("undecodable");
+        reply(request_pipe, response_pipe, b"M7\xff\xff".to_vec());
+        let status = service.call(request("token")).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Internal);
+    }
+
+    #[tokio::test]
+    async fn test_timeout_status() {
+        let (service, _request_pipe, _) = service("timeout");
+        let status = service.call(request("token")).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::DeadlineExceeded);
+    }
+
+    #[tokio::test]
+    async fn test_successful_call() {
+        let (service, request_pipe, response_pipe) = service("ok");
+        let ok = mi7::Response::Ok {
+            id: 0,
+            body: b"{}".to_vec(),
+        };
+        reply(request_pipe, response_pipe, ok.encode().unwrap());
+        let reply = service.call(request("token")).await.unwrap().into_inner();
+        assert_eq!(reply.data, b"{}");
+    }
+}
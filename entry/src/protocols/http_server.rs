@@ -1,4 +1,5 @@
-use crate::protocols::common::{Command, ErrorResponse, HttpRequestBody, HttpResponse};
+use crate::protocols::common::{Command, ErrorResponse, call_error, reply_body};
+use crate::scheduler::{Scheduler, WaitStats};
 use axum::{
     Router,
     body::Body,
@@ -6,84 +7,55 @@ use axum::{
     http::{Method, StatusCode},
     response::{IntoResponse, Json as ResponseJson, Response},
 };
-use mi7::pipe::DynamicPipe;
-use mi7::{Message, shared_slot::SlotState};
+use mi7::pipe::{DynamicPipe, PipeStatus};
+use mi7::shared_box::SharedMemoryMailbox;
+use mi7::shared_slot::SlotState;
+use mi7::{ClusterView, Message, RpcChannel, TraceContext, tracing_ipc};
+use serde::Deserialize;
 use serde_json::Value;
 use std::{
     collections::HashMap,
     net::SocketAddr,
     sync::{
-        Arc, Mutex,
-        atomic::{AtomicI64, AtomicU64, Ordering},
+        Arc,
+        atomic::{AtomicU64, Ordering},
     },
 };
-use tokio::sync::{mpsc, oneshot};
-use tracing::{debug, error, info, warn};
+use tracing::{Instrument, debug, error, info, warn};
 
-// 全局响应映射表，用于存储等待响应的 oneshot 发送端
+/// 携带亲和键的请求头，相同取值的请求由同一个 worker 处理
+pub(crate) const AFFINITY_HEADER: &str = "x-affinity-key";
+
+// 全局请求 ID 生成器，gRPC 网关共用
 lazy_static::lazy_static! {
-    static ref REQ_ID: AtomicU64 = AtomicU64::new(1);
-    static ref RESPONSE_MAP: Arc<Mutex<HashMap<u64, oneshot::Sender<Value>>>> =
-        Arc::new(Mutex::new(HashMap::new()));
+    pub(crate) static ref REQ_ID: AtomicU64 = AtomicU64::new(1);
 }
 
-/// 后台响应处理循环
-pub async fn response_handler_loop() {
-    info!("[RESPONSE_HANDLER] 后台响应处理循环已启动");
-
-    loop {
-        // 每秒检查一次 RESPONSE_MAP
-        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-
-        let mut to_remove = Vec::new();
-
-        // 检查是否有待处理的响应
-        {
-            let response_map = RESPONSE_MAP.lock().unwrap();
-
-            if !response_map.is_empty() {
-                debug!(
-                    "[RESPONSE_HANDLER] 检查到 {} 个待处理响应",
-                    response_map.len()
-                );
+/// `[http]` 配置段
+#[derive(Debug, Deserialize)]
+pub struct HttpConfig {
+    #[serde(default = "HttpConfig::default_bind_address")]
+    pub bind_address: String,
+    pub port: u16,
+    /// 请求超时时间（秒）
+    #[serde(default = "HttpConfig::default_timeout_seconds")]
+    pub timeout_seconds: u64,
+}
 
-                // 简易处理：为每个请求生成一个模拟响应
-                for (&task_id, _) in response_map.iter() {
-                    to_remove.push(task_id);
-                }
-            }
-        }
+impl HttpConfig {
+    fn default_bind_address() -> String {
+        "0.0.0.0".to_string()
+    }
 
-        // 处理所有待处理的响应
-        for task_id in to_remove {
-            let tx = {
-                let mut response_map = RESPONSE_MAP.lock().unwrap();
-                response_map.remove(&task_id)
-            };
+    fn default_timeout_seconds() -> u64 {
+        30
+    }
 
-            if let Some(tx) = tx {
-                // 生成模拟响应
-                let result = serde_json::json!({
-                    "success": true,
-                    "message": "请求已由 worker 处理完成",
-                    "task_id": task_id,
-                    "processed_at": chrono::Utc::now().to_rfc3339()
-                });
-
-                // 发送响应
-                match tx.send(result) {
-                    Ok(_) => {
-                        info!("[RESPONSE_HANDLER] 任务ID: {} 响应已发送", task_id);
-                    }
-                    Err(_) => {
-                        warn!(
-                            "[RESPONSE_HANDLER] 任务ID: {} 响应发送失败，接收端已关闭",
-                            task_id
-                        );
-                    }
-                }
-            }
-        }
+    /// 监听地址
+    pub fn addr(&self) -> anyhow::Result<SocketAddr> {
+        format!("{}:{}", self.bind_address, self.port)
+            .parse()
+            .map_err(|e| anyhow::anyhow!("无效的 HTTP 监听地址 {}: {}", self.bind_address, e))
     }
 }
 
@@ -91,43 +63,66 @@ pub async fn response_handler_loop() {
 #[derive(Clone)]
 struct AppState {
     queue: Arc<Box<dyn DynamicPipe>>,
+    rpc: Arc<RpcChannel>,
     // 不需要鉴权的路径列表
     no_auth_paths: Arc<HashMap<String, bool>>,
-    // 调度者相关字段
-    counter: Arc<AtomicI64>,
-    slot_sender: mpsc::UnboundedSender<usize>,
+    scheduler: Arc<Scheduler>,
+    cluster: Option<Arc<ClusterView>>,
+    mailbox: Option<Arc<SharedMemoryMailbox>>,
+}
+
+impl AppState {
+    fn new(
+        queue: Arc<Box<dyn DynamicPipe>>,
+        rpc: Arc<RpcChannel>,
+        scheduler: Arc<Scheduler>,
+        cluster: Option<Arc<ClusterView>>,
+        mailbox: Option<Arc<SharedMemoryMailbox>>,
+    ) -> Self {
+        // 初始化免鉴权路径
+        let mut no_auth_paths = HashMap::new();
+        no_auth_paths.insert("/health".to_string(), true);
+        no_auth_paths.insert("/status".to_string(), true);
+        no_auth_paths.insert("/status/pipes".to_string(), true);
+        no_auth_paths.insert("/status/mailbox".to_string(), true);
+        no_auth_paths.insert("/ping".to_string(), true);
+
+        Self {
+            queue,
+            rpc,
+            no_auth_paths: Arc::new(no_auth_paths),
+            scheduler,
+            cluster,
+            mailbox,
+        }
+    }
 }
 
 pub async fn run(
     addr: SocketAddr,
     queue: Arc<Box<dyn DynamicPipe>>,
-    counter: Arc<AtomicI64>,
-    slot_sender: mpsc::UnboundedSender<usize>,
+    rpc: Arc<RpcChannel>,
+    scheduler: Arc<Scheduler>,
+    cluster: Option<Arc<ClusterView>>,
+    mailbox: Option<Arc<SharedMemoryMailbox>>,
+    shutdown: impl Future<Output = ()> + Send + 'static,
 ) -> anyhow::Result<()> {
-    // 初始化免鉴权路径
-    let mut no_auth_paths = HashMap::new();
-    no_auth_paths.insert("/health".to_string(), true);
-    no_auth_paths.insert("/status".to_string(), true);
-    no_auth_paths.insert("/ping".to_string(), true);
-
-    let state = AppState {
-        queue,
-        no_auth_paths: Arc::new(no_auth_paths),
-        counter,
-        slot_sender,
-    };
+    let state = AppState::new(queue, rpc, scheduler, cluster, mailbox);
 
     // 使用统一的处理器处理所有路由
     let app = Router::new().fallback(unified_handler).with_state(state);
 
     info!("HTTP 服务器启动成功，监听地址: {}", addr);
     let listener = tokio::net::TcpListener::bind(&addr).await?;
-    axum::serve(listener, app).await?;
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown)
+        .await?;
+    info!("HTTP 服务器已停止");
     Ok(())
 }
 
 /// 简易鉴权函数
-async fn authenticate(token: &str) -> bool {
+pub(crate) async fn authenticate(token: &str) -> bool {
     // 简易处理：所有鉴权都成功
     debug!("[AUTH] 开始验证 token，长度: {}", token.len());
 
@@ -203,6 +198,12 @@ async fn unified_handler(State(state): State<AppState>, request: Request<Body>)
         .and_then(|h| h.to_str().ok())
         .unwrap_or("");
 
+    // 上游携带 traceparent 时延续其链路
+    let upstream_trace = headers
+        .get("traceparent")
+        .and_then(|h| h.to_str().ok())
+        .and_then(TraceContext::from_traceparent);
+
     // 3. 判断是否需要鉴权
     let needs_auth = !state.no_auth_paths.contains_key(&path);
 
@@ -330,7 +331,7 @@ async fn unified_handler(State(state): State<AppState>, request: Request<Body>)
 
     // 6. 通过共享内存发送给 worker
     debug!("[SERIALIZE] 任务ID: {}", task_id);
-    let serialized = match bincode::encode_to_vec(&cmd, bincode::config::standard()) {
+    let serialized = match cmd.encode() {
         Ok(data) => {
             debug!(
                 "[SERIALIZE_OK] 任务ID: {}, 数据大小: {} bytes",
@@ -357,14 +358,22 @@ async fn unified_handler(State(state): State<AppState>, request: Request<Body>)
         }
     };
 
-    let message = Message {
-        flag: 0,
+    let mut message = Message {
+        flag: cmd.flag(),
         data: serialized,
         timestamp: std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
             .as_secs(),
+        // 过期时间由 RPC 通道按请求超时设置
+        expires_at: 0,
+        trace: upstream_trace,
+        affinity: 0,
     };
+    if let Some(key) = headers.get(AFFINITY_HEADER) {
+        message = message.with_affinity(key.as_bytes());
+    }
+    let trace_span = tracing_ipc::producer_span(&mut message);
 
     debug!(
         "[SEND_QUEUE] 任务ID: {}, 消息大小: {} bytes",
@@ -372,149 +381,291 @@ async fn unified_handler(State(state): State<AppState>, request: Request<Body>)
         message.data.len()
     );
 
-    // 使用新的调度者架构
-    // 1. 请求槽位 - 增加计数器信号，通知调度者需要更多槽位
-    debug!("[SLOT_REQUEST] 任务ID: {}, 请求槽位", task_id);
-    state.counter.fetch_add(1, Ordering::AcqRel);
-    debug!(
-        "[SLOT_WAIT] 任务ID: {}, 请求槽位，counter +1: {}",
-        task_id,
-        state.counter.load(Ordering::Acquire)
-    );
-
-    // 2. 获取空闲槽位
-    let slot_index = {
-        let mut retry_count = 0;
-        const MAX_RETRIES: u32 = 100; // 最多重试100次
-        const RETRY_DELAY_MS: u64 = 1; // 每次重试间隔1ms
-
-        loop {
-            // 尝试获取空闲槽位
-            if let Ok(index) = state.queue.hold() {
-                debug!(
-                    "[SLOT_ACQUIRED] 任务ID: {}, 槽位: {}, 状态: WRITING",
-                    task_id, index
-                );
-                break index;
-            } else {
-                retry_count += 1;
-                if retry_count >= MAX_RETRIES {
-                    let elapsed = start_time.elapsed();
-                    error!(
-                        "[SLOT_TIMEOUT] 任务ID: {}, 等待槽位超时，重试次数: {}, 耗时: {:?}",
-                        task_id, retry_count, elapsed
-                    );
-                    return (
-                        StatusCode::SERVICE_UNAVAILABLE,
-                        ResponseJson(ErrorResponse {
-                            error: "服务器繁忙，等待槽位超时".to_string(),
-                            code: 503,
-                        }),
-                    )
-                        .into_response();
-                }
-
-                // 短暂等待后重试
-                tokio::time::sleep(tokio::time::Duration::from_millis(RETRY_DELAY_MS)).await;
-            }
+    // 状态查询直接由 entry 回答，不经过 worker
+    let queue_status = state.queue.status();
+    match path.as_str() {
+        "/status" => {
+            // 保留 current_size / status 两个旧字段，其余为完整的 PipeStatus
+            let mut queue = serde_json::to_value(&queue_status).unwrap_or_default();
+            queue["current_size"] = queue_status.used_count.into();
+            queue["status"] = "connected".into();
+            let response = serde_json::json!({
+                "server": "Mi7Soft HTTP Server",
+                "status": "running",
+                "task_id": task_id,
+                "queue": queue,
+                "slot_histogram": slot_histogram(state.queue.as_ref().as_ref()),
+                "reclaim": reclaim_counters(&[
+                    queue_status.clone(),
+                    state.rpc.response_pipe().status(),
+                ]),
+                "mailbox": state.mailbox.as_ref().map(|mailbox| mailbox.get_stats()),
+                "pending_requests": state.rpc.pending_count(),
+                "response_pipes": state.rpc.response_pipes(),
+                "scheduler": state.scheduler.stats(),
+                "processes": state.cluster.as_ref().map(|cluster| cluster.processes())
+            });
+            info!(
+                "[STATUS_RESPONSE] 任务ID: {}, 队列: {}/{}",
+                task_id, queue_status.used_count, queue_status.capacity
+            );
+            return ResponseJson(response).into_response();
         }
-    };
+        "/status/pipes" => return ResponseJson(pipes_status(&state)).into_response(),
+        "/status/mailbox" => return mailbox_status(&state),
+        _ => {}
+    }
 
-    // 3. 写入数据到槽位
-    debug!(
-        "[SLOT_WRITE] 任务ID: {}, 槽位: {}, 写入数据",
-        task_id, slot_index
-    );
-    if let Err(e) = state.queue.send(slot_index, message) {
+    // 队列占用超过高水位时直接拒绝，由客户端稍后重试
+    if state.queue.is_backpressured() {
+        warn!(
+            "[BACKPRESSURE] 任务ID: {}, 队列: {}/{}",
+            task_id, queue_status.used_count, queue_status.capacity
+        );
         let elapsed = start_time.elapsed();
         error!(
-            "[SLOT_WRITE_ERROR] 任务ID: {}, 写入槽位失败: {}, 耗时: {:?}",
-            task_id, e, elapsed
+            "[REQUEST_END] 任务ID: {}, 状态: 429 Too Many Requests, 耗时: {:?}",
+            task_id, elapsed
         );
         return (
-            StatusCode::INTERNAL_SERVER_ERROR,
+            StatusCode::TOO_MANY_REQUESTS,
             ResponseJson(ErrorResponse {
-                error: "写入槽位失败".to_string(),
-                code: 500,
+                error: "队列繁忙，请稍后重试".to_string(),
+                code: 429,
             }),
         )
             .into_response();
     }
 
-    let elapsed = start_time.elapsed();
-    info!(
-        "[QUEUE_SEND_OK] 任务ID: {}, 方法: {}, 路径: {}, 耗时: {:?}",
-        task_id, method_str, path, elapsed
-    );
+    // 由调度者派发给负载最低的 worker 并等待响应（超时或客户端断开时自动取消）
+    match state.scheduler.call(message).instrument(trace_span).await {
+        Ok(reply) => {
+            let total_elapsed = start_time.elapsed();
+            info!(
+                "[REQUEST_SUCCESS] 任务ID: {}, 方法: {}, 路径: {}, 总耗时: {:?}",
+                task_id, method_str, path, total_elapsed
+            );
 
-    // 获取队列状态用于日志
-    let queue_status = state.queue.status();
-    debug!(
-        "[QUEUE_STATUS] 任务ID: {}, 当前消息数: {}/{}",
-        task_id, queue_status.used_count, queue_status.capacity
-    );
+            let body = match reply_body(&reply) {
+                Ok(body) => body,
+                Err(e) => {
+                    warn!(
+                        "[WORKER_ERROR] 任务ID: {}, 状态: {}, 错误: {}",
+                        task_id, e.code, e.error
+                    );
+                    let status = StatusCode::from_u16(e.code).unwrap_or(StatusCode::BAD_GATEWAY);
+                    return (status, ResponseJson(e)).into_response();
+                }
+            };
+            // worker 以 JSON 回复；非 JSON 内容按字符串返回
+            let result = serde_json::from_slice::<Value>(&body).unwrap_or_else(|_| {
+                serde_json::json!({
+                    "success": true,
+                    "task_id": task_id,
+                    "data": String::from_utf8_lossy(&body),
+                })
+            });
+            ResponseJson(result).into_response()
+        }
+        Err(e) => {
+            let total_elapsed = start_time.elapsed();
+            error!(
+                "[RPC_FAILED] 任务ID: {}, 错误: {}, 耗时: {:?}",
+                task_id, e, total_elapsed
+            );
 
-    // 特殊处理状态查询 - 立即返回，不等待 worker 响应
-    if path == "/status" {
-        let response = serde_json::json!({
-            "server": "Mi7Soft HTTP Server",
-            "status": "running",
-            "task_id": task_id,
-            "queue": {
-                "capacity": queue_status.capacity,
-                "current_size": queue_status.used_count,
-                "status": "connected"
-            }
-        });
-        info!(
-            "[STATUS_RESPONSE] 任务ID: {}, 队列: {}/{}",
-            task_id, queue_status.used_count, queue_status.capacity
-        );
-        ResponseJson(response).into_response()
-    } else {
-        // 创建 oneshot 通道等待 worker 响应
-        let (tx, rx) = oneshot::channel();
+            let e = call_error(e);
+            let status = StatusCode::from_u16(e.code).unwrap_or(StatusCode::GATEWAY_TIMEOUT);
+            (status, ResponseJson(e)).into_response()
+        }
+    }
+}
+
+/// `/status/pipes`：请求管道、响应管道与已连接的 worker 收件管道的完整状态
+fn pipes_status(state: &AppState) -> Value {
+    let mut pipes = vec![
+        ("request", state.queue.clone()),
+        ("response", state.rpc.response_pipe().clone()),
+    ];
+    let inboxes = state.scheduler.inbox_pipes();
+    pipes.extend(inboxes.into_iter().map(|(_, pipe)| ("inbox", pipe)));
+
+    let statuses: Vec<PipeStatus> = pipes.iter().map(|(_, pipe)| pipe.status()).collect();
+    let details: Vec<Value> = pipes
+        .iter()
+        .zip(&statuses)
+        .map(|((role, pipe), status)| {
+            serde_json::json!({
+                "name": pipe.name(),
+                "role": role,
+                "status": status,
+                "slot_histogram": slot_histogram(pipe.as_ref().as_ref()),
+                "latency": WaitStats::from(&pipe.metrics()),
+                "attached_processes": pipe.attached_processes(),
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "pipes": details,
+        "reclaim": reclaim_counters(&statuses),
+    })
+}
+
+/// `/status/mailbox`：`[entry] mailbox_name` 指定的寄存箱的统计与内存占用
+fn mailbox_status(state: &AppState) -> Response {
+    let Some(mailbox) = &state.mailbox else {
+        return (
+            StatusCode::NOT_FOUND,
+            ResponseJson(ErrorResponse {
+                error: "未配置寄存箱 ([entry] mailbox_name)".to_string(),
+                code: 404,
+            }),
+        )
+            .into_response();
+    };
+    ResponseJson(serde_json::json!({
+        "name": mailbox.name(),
+        "stats": mailbox.get_stats(),
+        "memory": mailbox.memory_usage(),
+    }))
+    .into_response()
+}
 
-        // 将发送端存储到全局映射表中
-        {
-            let mut response_map = RESPONSE_MAP.lock().unwrap();
-            response_map.insert(task_id, tx);
+/// 逐个槽位读取状态得到的直方图，管道不支持按槽位查询时为 `null`
+///
+/// 无锁模式下被放弃的槽位以 READY 发布但不含消息，单独计入 `ABANDONED`。
+fn slot_histogram(pipe: &dyn DynamicPipe) -> Value {
+    let mut counts = [0usize; 5];
+    let mut abandoned = 0;
+    for index in 0..pipe.capacity() {
+        match pipe.get_slot_state(index) {
+            Ok(SlotState::READY) if pipe.is_slot_abandoned(index) => abandoned += 1,
+            Ok(state) => counts[state as usize] += 1,
+            Err(_) => return Value::Null,
         }
+    }
+    serde_json::json!({
+        "EMPTY": counts[0],
+        "WRITING": counts[1],
+        "INPROGRESS": counts[2],
+        "READING": counts[3],
+        "READY": counts[4],
+        "ABANDONED": abandoned,
+    })
+}
 
-        debug!("[ONESHOT_CREATED] 任务ID: {}, 等待 worker 响应", task_id);
+/// 各管道回收、过期、重新投递、死信与覆盖丢弃的累计数量之和
+fn reclaim_counters(statuses: &[PipeStatus]) -> Value {
+    let sum = |count: fn(&PipeStatus) -> u64| statuses.iter().map(count).sum::<u64>();
+    serde_json::json!({
+        "reclaimed": sum(|s| s.reclaimed_count),
+        "expired": sum(|s| s.expired_count),
+        "redelivered": sum(|s| s.redelivered_count),
+        "dead_lettered": sum(|s| s.dead_lettered_count),
+        "dropped": sum(|s| s.dropped_count),
+    })
+}
 
-        // 异步等待 worker 响应
-        match rx.await {
-            Ok(result) => {
-                let total_elapsed = start_time.elapsed();
-                info!(
-                    "[REQUEST_SUCCESS] 任务ID: {}, 方法: {}, 路径: {}, 总耗时: {:?}",
-                    task_id, method_str, path, total_elapsed
-                );
-                ResponseJson(result).into_response()
-            }
-            Err(_) => {
-                let total_elapsed = start_time.elapsed();
-                error!(
-                    "[ONESHOT_TIMEOUT] 任务ID: {}, 等待响应超时, 耗时: {:?}",
-                    task_id, total_elapsed
-                );
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mi7::pipe::{DynCrossProcessPipe, PipeConfig};
+    use mi7::shared_slot::PipeMode;
+
+    fn pipe(tag: &str) -> DynCrossProcessPipe {
+        let name = format!("entry_test_http_{}_{}", tag, std::process::id());
+        DynCrossProcessPipe::create_with_config(
+            &name,
+            PipeConfig::new(4, 256).with_mode(PipeMode::LockFree),
+        )
+        .unwrap()
+    }
 
-                // 清理映射表中的条目
-                {
-                    let mut response_map = RESPONSE_MAP.lock().unwrap();
-                    response_map.remove(&task_id);
-                }
+    fn keys(value: &Value) -> Vec<&str> {
+        let mut keys: Vec<_> = value
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        keys.sort_unstable();
+        keys
+    }
 
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    ResponseJson(ErrorResponse {
-                        error: "请求处理超时".to_string(),
-                        code: 500,
-                    }),
-                )
-                    .into_response()
-            }
-        }
+    #[tokio::test]
+    async fn test_pipes_status_shape() {
+        // 一条待读取的消息与一个被放弃的槽位
+        let request = pipe("request");
+        request
+            .send_blocking(
+                Message::init("a".to_string()),
+                std::time::Duration::from_secs(1),
+            )
+            .unwrap();
+        let index = request.hold().unwrap();
+        assert!(
+            request
+                .try_send_with(index, |_| Err(anyhow::anyhow!("fill failed").into()))
+                .is_err()
+        );
+
+        let queue: Arc<Box<dyn DynamicPipe>> = Arc::new(Box::new(request));
+        let response: Arc<Box<dyn DynamicPipe>> = Arc::new(Box::new(pipe("response")));
+        let rpc = Arc::new(RpcChannel::new(queue.clone(), response));
+        let scheduler = Arc::new(Scheduler::new(rpc.clone(), queue.clone(), None, "default"));
+        let state = AppState::new(queue, rpc, scheduler, None, None);
+
+        let request = Request::get("/status/pipes").body(Body::empty()).unwrap();
+        let response = unified_handler(State(state), request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(keys(&json), ["pipes", "reclaim"]);
+        assert_eq!(
+            keys(&json["reclaim"]),
+            [
+                "dead_lettered",
+                "dropped",
+                "expired",
+                "reclaimed",
+                "redelivered"
+            ]
+        );
+        let pipes = json["pipes"].as_array().unwrap();
+        assert_eq!(pipes.len(), 2);
+        assert_eq!(
+            keys(&pipes[0]),
+            [
+                "attached_processes",
+                "latency",
+                "name",
+                "role",
+                "slot_histogram",
+                "status"
+            ]
+        );
+        assert_eq!(
+            (pipes[0]["role"].as_str(), pipes[1]["role"].as_str()),
+            (Some("request"), Some("response"))
+        );
+
+        let histogram = &pipes[0]["slot_histogram"];
+        assert_eq!(
+            keys(histogram),
+            [
+                "ABANDONED",
+                "EMPTY",
+                "INPROGRESS",
+                "READING",
+                "READY",
+                "WRITING"
+            ]
+        );
+        assert_eq!(histogram["READY"], 1);
+        assert_eq!(histogram["ABANDONED"], 1);
+        assert_eq!(histogram["EMPTY"], 2);
     }
 }
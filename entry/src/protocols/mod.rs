@@ -1,5 +1,7 @@
 pub mod common;
+pub mod grpc_server;
 pub mod http_server;
+#[cfg(feature = "mqtt")]
 pub mod mqtt_server;
 pub mod tcp_server;
 pub mod udp_server;
@@ -1,20 +1,502 @@
-use tracing::{debug, error, info};
-#[cfg(feature = "mqtt")]
-use rumqttd::{Broker, Config};
-
-#[cfg(feature = "mqtt")]
-pub async fn run_broker(_addr: std::net::SocketAddr, tx: tokio::sync::mpsc::UnboundedSender<crate::protocols::common::Command>) -> anyhow::Result<()> {
-    // default config - listens on 1883
-    let config = Config::default();
-    let mut broker = Broker::new(config);
-    // NOTE: rumqttd runs its own threads and does not directly expose a Rust async stream
-    // of incoming messages to integrate with our tx easily. Production integration often
-    // uses a plugin mechanism or listens to persisted messages. Here we just start it.
-    std::thread::spawn(move || {
-        broker.start().unwrap();
-    });
-    info!("MQTT broker started (rumqttd)");
-    // keep alive
-    futures::future::pending::<()>().await;
+//! MQTT 接入（`mqtt` 特性）：订阅代理上的主题，把收到的消息派发给 worker
+//!
+//! 每条 PUBLISH 转换为 [`Command::MqttPublish`] 经调度者派发，QoS 决定投递语义：
+//! - QoS 0：至多一次，只尝试派发一次，失败即丢弃
+//! - QoS 1（及 QoS 2）：至少一次，消息先写入进程内暂存管道（[`HeapSlotPipe`]），
+//!   派发失败时 `nack` 重新投递，worker 回复后才向代理确认；派发次数达到 `max_attempts`
+//!   的消息被丢弃并向代理确认。暂存中未确认的消息在重连后由代理重新投递（会话为持久会话）
+//!
+//! 配置了 `reply_topic_prefix` 时，worker 的回复发布到 `<reply_topic_prefix>/<原主题>`，
+//! QoS 与原消息相同。
+
+use crate::protocols::common::{Command, command_message, reply_body};
+use crate::protocols::http_server::REQ_ID;
+use crate::scheduler::Scheduler;
+use mi7::pipe::{DynamicPipe, PipeConfig};
+use mi7::{HeapSlotPipe, Message, tracing_ipc};
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, Publish, QoS};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{Instrument, debug, error, info, warn};
+
+/// 事件循环出错（如连接断开）后重新连接前的等待时间
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// QoS 1 消息派发失败后重新投递前的等待时间，避免队列繁忙时立即重试
+const REDELIVERY_DELAY: Duration = Duration::from_millis(100);
+
+/// `[mqtt]` 配置段，缺省时不启动 MQTT 接入
+#[derive(Debug, Deserialize)]
+pub struct MqttConfig {
+    /// 代理地址
+    #[serde(default = "MqttConfig::default_host")]
+    pub host: String,
+    #[serde(default = "MqttConfig::default_port")]
+    pub port: u16,
+    #[serde(default = "MqttConfig::default_client_id")]
+    pub client_id: String,
+    /// 订阅的主题过滤器，逗号分隔，支持 `+` / `#` 通配符
+    pub topics: String,
+    /// 订阅的最高 QoS（0 或 1）
+    #[serde(default = "MqttConfig::default_qos")]
+    pub qos: u8,
+    /// 回复主题前缀，为空时不发布回复
+    #[serde(default)]
+    pub reply_topic_prefix: String,
+    /// QoS 1 消息的最大派发次数，即暂存管道的最大投递次数
+    #[serde(default = "MqttConfig::default_max_attempts")]
+    pub max_attempts: u32,
+    #[serde(default = "MqttConfig::default_keep_alive_seconds")]
+    pub keep_alive_seconds: u64,
+}
+
+impl MqttConfig {
+    fn default_host() -> String {
+        "127.0.0.1".to_string()
+    }
+
+    fn default_port() -> u16 {
+        1883
+    }
+
+    fn default_client_id() -> String {
+        format!("mi7-entry-{}", std::process::id())
+    }
+
+    fn default_qos() -> u8 {
+        1
+    }
+
+    fn default_max_attempts() -> u32 {
+        3
+    }
+
+    fn default_keep_alive_seconds() -> u64 {
+        30
+    }
+
+    /// 订阅的主题过滤器
+    fn topic_filters(&self) -> Vec<String> {
+        self.topics
+            .split(',')
+            .map(str::trim)
+            .filter(|topic| !topic.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    fn subscribe_qos(&self) -> QoS {
+        if self.qos == 0 {
+            QoS::AtMostOnce
+        } else {
+            QoS::AtLeastOnce
+        }
+    }
+}
+
+/// MQTT 接入状态
+#[derive(Clone)]
+struct MqttState {
+    client: AsyncClient,
+    queue: Arc<Box<dyn DynamicPipe>>,
+    scheduler: Arc<Scheduler>,
+    reply_topic_prefix: Arc<str>,
+    /// QoS 1 消息的暂存管道，派发失败经 `nack` 重新投递，超过最大投递次数后丢弃
+    staging: HeapSlotPipe,
+    /// 暂存中的 PUBLISH，按暂存消息的 request_id（任务ID）索引，派发结束后向代理确认
+    pending: Arc<Mutex<HashMap<u64, Publish>>>,
+}
+
+impl MqttState {
+    fn new(
+        config: &MqttConfig,
+        client: AsyncClient,
+        queue: Arc<Box<dyn DynamicPipe>>,
+        scheduler: Arc<Scheduler>,
+    ) -> mi7::Result<Self> {
+        // 能写入请求管道的消息也能写入暂存管道
+        let staging = HeapSlotPipe::create(
+            &format!("mqtt_staging_{}", config.client_id),
+            PipeConfig::new(queue.capacity(), queue.slot_size())
+                .with_max_delivery_attempts(config.max_attempts.max(1)),
+        )?;
+        Ok(Self {
+            client,
+            queue,
+            scheduler,
+            reply_topic_prefix: config.reply_topic_prefix.trim_end_matches('/').into(),
+            staging,
+            pending: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+}
+
+/// 连接代理并持续接收消息，收到停止信号后断开连接
+pub async fn run(
+    config: MqttConfig,
+    queue: Arc<Box<dyn DynamicPipe>>,
+    scheduler: Arc<Scheduler>,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> anyhow::Result<()> {
+    let topics = config.topic_filters();
+    if topics.is_empty() {
+        return Err(anyhow::anyhow!("[mqtt] 未配置订阅主题 topics"));
+    }
+    let subscribe_qos = config.subscribe_qos();
+
+    let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+    options
+        .set_keep_alive(Duration::from_secs(config.keep_alive_seconds.max(5)))
+        // 持久会话：未确认的 QoS 1 消息在重连后由代理重新投递
+        .set_clean_session(false)
+        // worker 回复后才确认 QoS 1 消息
+        .set_manual_acks(true);
+    let (client, mut eventloop) = AsyncClient::new(options, 64);
+
+    let state = MqttState::new(&config, client.clone(), queue, scheduler)?;
+    let redelivery = tokio::spawn(redeliver(state.clone()));
+    info!(
+        "MQTT 接入启动，代理: {}:{}，主题: {:?}",
+        config.host, config.port, topics
+    );
+
+    tokio::pin!(shutdown);
+    loop {
+        let event = tokio::select! {
+            _ = &mut shutdown => break,
+            event = eventloop.poll() => event,
+        };
+        match event {
+            // 每次（重新）连接后订阅，代理未保留会话时也能恢复订阅
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                info!("已连接 MQTT 代理 {}:{}", config.host, config.port);
+                for topic in &topics {
+                    if let Err(e) = client.try_subscribe(topic.as_str(), subscribe_qos) {
+                        error!("订阅 MQTT 主题 {} 失败: {}", topic, e);
+                    }
+                }
+            }
+            Ok(Event::Incoming(Packet::Publish(publish))) => {
+                tokio::spawn(handle_publish(state.clone(), publish));
+            }
+            Ok(_) => {}
+            Err(e) => {
+                warn!("MQTT 连接异常，{:?} 后重连: {}", RECONNECT_DELAY, e);
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        }
+    }
+
+    // 暂存中的消息未向代理确认，重连后由代理重新投递
+    redelivery.abort();
+    let _ = state.staging.unlink();
+    if let Err(e) = client.try_disconnect() {
+        debug!("断开 MQTT 连接失败: {}", e);
+    }
+    info!("MQTT 接入已停止");
+    Ok(())
+}
+
+/// 派发一条 PUBLISH：QoS 0 只派发一次，QoS 1 写入暂存管道由 [`redeliver`] 派发
+async fn handle_publish(state: MqttState, publish: Publish) {
+    let task_id = REQ_ID.fetch_add(1, Ordering::Relaxed);
+    debug!(
+        "[MQTT_PUBLISH] 任务ID: {}, 主题: {}, QoS: {:?}, 大小: {} bytes",
+        task_id,
+        publish.topic,
+        publish.qos,
+        publish.payload.len()
+    );
+
+    let cmd = Command::MqttPublish {
+        id: task_id,
+        topic: publish.topic.clone(),
+        payload: publish.payload.to_vec(),
+    };
+    let message = match command_message(&cmd) {
+        Ok(message) => message,
+        Err(e) => {
+            // 无法编码的消息重试也不会成功，确认后丢弃
+            error!("[MQTT_SERIALIZE_ERROR] 任务ID: {}, 错误: {}", task_id, e);
+            let _ = state.client.ack(&publish).await;
+            return;
+        }
+    };
+
+    if publish.qos == QoS::AtMostOnce {
+        match dispatch(&state, &publish.topic, message).await {
+            Ok(reply) => complete(&state, task_id, &publish, &reply).await,
+            Err(e) => warn!(
+                "[MQTT_DROPPED] 任务ID: {}, QoS 0 消息派发失败: {}",
+                task_id, e
+            ),
+        }
+        return;
+    }
+
+    if let Err(e) = stage(&state, task_id, publish, message).await {
+        // 不确认：代理在重连后重新投递
+        error!("[MQTT_STAGE_FAILED] 任务ID: {}, 错误: {}", task_id, e);
+    }
+}
+
+/// 把 QoS 1 消息以任务ID为 request_id 写入暂存管道，暂存管道满时等待空槽位
+async fn stage(
+    state: &MqttState,
+    task_id: u64,
+    publish: Publish,
+    message: Message,
+) -> mi7::Result<()> {
+    let index = state.staging.hold_async().await?;
+    // 先登记再写入，读者取到消息时一定能找到对应的 PUBLISH
+    state.pending.lock().unwrap().insert(task_id, publish);
+    if let Err(e) = state.staging.send_tagged(index, task_id, message) {
+        state.pending.lock().unwrap().remove(&task_id);
+        return Err(e);
+    }
     Ok(())
-}
\ No newline at end of file
+}
+
+/// 持续从暂存管道取出 QoS 1 消息，每条消息在独立任务中派发
+async fn redeliver(state: MqttState) {
+    loop {
+        match state.staging.fetch_async().await {
+            Ok(index) => {
+                let state = state.clone();
+                tokio::spawn(async move { deliver(&state, index).await });
+            }
+            Err(e) => {
+                error!("[MQTT_STAGING_ERROR] 读取暂存管道失败: {}", e);
+                tokio::time::sleep(REDELIVERY_DELAY).await;
+            }
+        }
+    }
+}
+
+/// 派发暂存管道中的一条消息
+///
+/// worker 回复后确认暂存槽位与 PUBLISH；派发失败时 `nack`，未达到最大投递次数的消息
+/// 回到暂存管道等待再次派发，达到后被丢弃并向代理确认。
+async fn deliver(state: &MqttState, index: usize) {
+    let (task_id, message) = match state.staging.receive_unacked(index) {
+        Ok(received) => received,
+        Err(e) => {
+            error!("[MQTT_STAGING_ERROR] 槽位: {}, 错误: {}", index, e);
+            return;
+        }
+    };
+    let Some(publish) = state.pending.lock().unwrap().get(&task_id).cloned() else {
+        warn!(
+            "[MQTT_STAGING_ERROR] 任务ID: {} 没有对应的 PUBLISH，丢弃",
+            task_id
+        );
+        let _ = state.staging.ack(index);
+        return;
+    };
+
+    let e = match dispatch(state, &publish.topic, message).await {
+        Ok(reply) => {
+            if let Err(e) = state.staging.ack(index) {
+                warn!("[MQTT_STAGING_ERROR] 任务ID: {}, 确认失败: {}", task_id, e);
+            }
+            state.pending.lock().unwrap().remove(&task_id);
+            complete(state, task_id, &publish, &reply).await;
+            return;
+        }
+        Err(e) => e,
+    };
+
+    warn!("[MQTT_RETRY] 任务ID: {}, 派发失败: {}", task_id, e);
+    tokio::time::sleep(REDELIVERY_DELAY).await;
+    match state.staging.nack(index) {
+        Ok(true) => {}
+        Ok(false) => {
+            // 达到最大投递次数：向代理确认，消息不再投递
+            state.pending.lock().unwrap().remove(&task_id);
+            error!(
+                "[MQTT_FAILED] 任务ID: {}, 主题: {}, 派发次数已达上限，丢弃",
+                task_id, publish.topic
+            );
+            if let Err(e) = state.client.ack(&publish).await {
+                warn!("[MQTT_ACK_FAILED] 任务ID: {}, 错误: {}", task_id, e);
+            }
+        }
+        Err(e) => error!(
+            "[MQTT_STAGING_ERROR] 任务ID: {}, 重新投递失败: {}",
+            task_id, e
+        ),
+    }
+}
+
+/// worker 已回复：把回复发布到回复主题并向代理确认
+async fn complete(state: &MqttState, task_id: u64, publish: &Publish, reply: &Message) {
+    // worker 报告的错误重试也不会改变结果，照常确认并把错误发布到回复主题
+    let body = reply_body(reply).unwrap_or_else(|e| {
+        warn!(
+            "[MQTT_WORKER_ERROR] 任务ID: {}, 状态: {}, 错误: {}",
+            task_id, e.code, e.error
+        );
+        serde_json::to_vec(&e).unwrap_or_default()
+    });
+    if !state.reply_topic_prefix.is_empty() {
+        let topic = format!("{}/{}", state.reply_topic_prefix, publish.topic);
+        if let Err(e) = state
+            .client
+            .publish(topic.as_str(), publish.qos, false, body)
+            .await
+        {
+            warn!(
+                "[MQTT_REPLY_FAILED] 任务ID: {}, 主题: {}, 错误: {}",
+                task_id, topic, e
+            );
+        }
+    }
+    if let Err(e) = state.client.ack(publish).await {
+        warn!("[MQTT_ACK_FAILED] 任务ID: {}, 错误: {}", task_id, e);
+    }
+    info!(
+        "[MQTT_SUCCESS] 任务ID: {}, 主题: {}",
+        task_id, publish.topic
+    );
+}
+
+/// 派发给 worker 并等待回复
+async fn dispatch(state: &MqttState, topic: &str, message: Message) -> mi7::Result<Message> {
+    // 队列占用超过高水位时视为本次派发失败，由暂存管道重新投递
+    if state.queue.is_backpressured() {
+        return Err(anyhow::anyhow!("队列繁忙").into());
+    }
+    // 同一主题的消息由同一个 worker 处理（启用亲和路由时）
+    let mut message = message.with_affinity(topic);
+    let trace_span = tracing_ipc::producer_span(&mut message);
+    state.scheduler.call(message).instrument(trace_span).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mi7::RpcChannel;
+
+    fn parse(value: serde_json::Value) -> MqttConfig {
+        serde_json::from_value(value).unwrap()
+    }
+
+    /// 请求管道没有 worker 读取时 `call` 在 `timeout` 后失败；返回状态与请求管道
+    fn state(client_id: &str, max_attempts: u32, timeout: Duration) -> (MqttState, HeapSlotPipe) {
+        let name = |tag: &str| format!("entry_test_mqtt_{}_{}", client_id, tag);
+        let request = HeapSlotPipe::create(&name("request"), PipeConfig::new(8, 1024)).unwrap();
+        let response = HeapSlotPipe::create(&name("response"), PipeConfig::new(8, 1024)).unwrap();
+        let queue: Arc<Box<dyn DynamicPipe>> = Arc::new(Box::new(request.clone()));
+        let rpc = Arc::new(
+            RpcChannel::new(queue.clone(), Arc::new(Box::new(response))).with_timeout(timeout),
+        );
+        rpc.start();
+        let scheduler = Arc::new(Scheduler::new(rpc, queue.clone(), None, "default"));
+
+        let config = parse(serde_json::json!({
+            "topics": "sensors/#",
+            "client_id": client_id,
+            "max_attempts": max_attempts,
+        }));
+        // 不轮询事件循环：确认与回复只进入客户端的请求通道
+        let (client, _) = AsyncClient::new(MqttOptions::new(client_id, "127.0.0.1", 1883), 64);
+        let state = MqttState::new(&config, client, queue, scheduler).unwrap();
+        (state, request)
+    }
+
+    async fn wait_until(mut done: impl FnMut() -> bool) {
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        while !done() {
+            assert!(tokio::time::Instant::now() < deadline, "等待超时");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[test]
+    fn test_topic_filters() {
+        let config = parse(serde_json::json!({ "topics": " sensors/+/temp,, alerts/# , ," }));
+        assert_eq!(config.topic_filters(), ["sensors/+/temp", "alerts/#"]);
+
+        let config = parse(serde_json::json!({ "topics": " , " }));
+        assert!(config.topic_filters().is_empty());
+    }
+
+    #[test]
+    fn test_subscribe_qos() {
+        let qos = |qos: u8| parse(serde_json::json!({ "topics": "a", "qos": qos })).subscribe_qos();
+        assert_eq!(qos(0), QoS::AtMostOnce);
+        assert_eq!(qos(1), QoS::AtLeastOnce);
+        // 不支持 QoS 2，按至少一次订阅
+        assert_eq!(qos(2), QoS::AtLeastOnce);
+        assert_eq!(
+            parse(serde_json::json!({ "topics": "a" })).subscribe_qos(),
+            QoS::AtLeastOnce
+        );
+    }
+
+    #[tokio::test]
+    async fn test_at_most_once_is_not_staged() {
+        let (state, _request) = state("qos0", 3, Duration::from_millis(20));
+        let publish = Publish::new("sensors/a", QoS::AtMostOnce, "1");
+        handle_publish(state.clone(), publish).await;
+
+        let status = state.staging.status();
+        assert_eq!(status.sent_count, 0);
+        assert!(state.pending.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_at_least_once_dropped_after_max_attempts() {
+        let (state, _request) = state("qos1_failed", 2, Duration::from_millis(20));
+        tokio::spawn(redeliver(state.clone()));
+        handle_publish(
+            state.clone(),
+            Publish::new("sensors/a", QoS::AtLeastOnce, "1"),
+        )
+        .await;
+
+        wait_until(|| state.staging.status().dead_lettered_count == 1).await;
+        let status = state.staging.status();
+        assert_eq!(status.sent_count, 1);
+        assert_eq!(status.redelivered_count, 1);
+        assert_eq!(status.used_count, 0);
+        assert!(state.pending.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_at_least_once_redelivered_until_reply() {
+        let (state, request) = state("qos1_replied", 5, Duration::from_millis(20));
+        tokio::spawn(redeliver(state.clone()));
+        handle_publish(
+            state.clone(),
+            Publish::new("sensors/a", QoS::AtLeastOnce, "1"),
+        )
+        .await;
+
+        // 第一次派发无人回复，等它重新投递后再启动 worker
+        wait_until(|| state.staging.status().redelivered_count >= 1).await;
+        let response = HeapSlotPipe::connect("entry_test_mqtt_qos1_replied_response").unwrap();
+        tokio::spawn(async move {
+            loop {
+                let index = request.fetch_async().await.unwrap();
+                // 已超时的请求在读取时过期丢弃
+                let Ok((request_id, _)) = request.receive_tagged(index) else {
+                    continue;
+                };
+                let index = response.hold().unwrap();
+                response
+                    .send_tagged(index, request_id, Message::init("ok".to_string()))
+                    .unwrap();
+            }
+        });
+
+        wait_until(|| state.staging.status().received_count == 1).await;
+        let status = state.staging.status();
+        assert_eq!(status.dead_lettered_count, 0);
+        assert_eq!(status.used_count, 0);
+        assert!(state.pending.lock().unwrap().is_empty());
+    }
+}
@@ -1,42 +1,329 @@
-use tracing::{debug, error, info};
-use crate::protocols::common::Command;
+//! 原始 TCP 协议：长度前缀帧转发给 worker
+//!
+//! 帧格式为 4 字节大端长度 + 负载。每帧转换为 [`Command::RawRequest`] 经调度者派发给 worker，
+//! 回复以同样的帧格式写回。同一连接上的请求并发派发，回复按请求顺序写回，
+//! 客户端按发送顺序即可把回复与请求对应起来。
+//!
+//! 长度为 0 的帧是合法的空请求；长度超过 `max_frame_bytes` 或连接在帧中间关闭时，
+//! 写回已派发请求的回复后断开连接。
+
+use crate::protocols::common::{Command, RawTransport, dispatch_raw};
+use crate::protocols::http_server::REQ_ID;
+use crate::scheduler::Scheduler;
+use mi7::pipe::DynamicPipe;
+use serde::Deserialize;
 use std::net::SocketAddr;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::{TcpListener, UdpSocket};
-use tokio::sync::mpsc::UnboundedSender;
+use std::sync::Arc;
 use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+/// 连接 ID 生成器
+static CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// `[tcp]` 配置段，缺省时不启动 TCP 服务
+#[derive(Debug, Deserialize)]
+pub struct TcpConfig {
+    #[serde(default = "TcpConfig::default_bind_address")]
+    pub bind_address: String,
+    pub port: u16,
+    /// 单帧负载的最大长度，超过时断开连接
+    #[serde(default = "TcpConfig::default_max_frame_bytes")]
+    pub max_frame_bytes: usize,
+    /// 每个连接等待回复的最大请求数，达到后暂停读取
+    #[serde(default = "TcpConfig::default_max_in_flight")]
+    pub max_in_flight: usize,
+}
+
+impl TcpConfig {
+    fn default_bind_address() -> String {
+        "0.0.0.0".to_string()
+    }
+
+    fn default_max_frame_bytes() -> usize {
+        1024 * 1024
+    }
 
+    fn default_max_in_flight() -> usize {
+        32
+    }
 
-static TCP_ID: AtomicU64 = AtomicU64::new(1);
-static UDP_ID: AtomicU64 = AtomicU64::new(1);
+    /// 监听地址
+    pub fn addr(&self) -> anyhow::Result<SocketAddr> {
+        format!("{}:{}", self.bind_address, self.port)
+            .parse()
+            .map_err(|e| anyhow::anyhow!("无效的 TCP 监听地址 {}: {}", self.bind_address, e))
+    }
+}
 
+/// TCP 服务状态
+#[derive(Clone)]
+struct TcpState {
+    queue: Arc<Box<dyn DynamicPipe>>,
+    scheduler: Arc<Scheduler>,
+    max_frame_bytes: usize,
+    max_in_flight: usize,
+}
 
-pub async fn run_tcp(addr: SocketAddr, tx: UnboundedSender<Command>) -> anyhow::Result<()> {
+/// 运行 TCP 服务，收到停止信号后不再接受新连接
+pub async fn run(
+    config: TcpConfig,
+    queue: Arc<Box<dyn DynamicPipe>>,
+    scheduler: Arc<Scheduler>,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> anyhow::Result<()> {
+    let addr = config.addr()?;
     let listener = TcpListener::bind(addr).await?;
-    info!("TCP listening on {}", addr);
+    info!("TCP 服务器启动成功，监听地址: {}", addr);
+
+    let state = TcpState {
+        queue,
+        scheduler,
+        max_frame_bytes: config.max_frame_bytes,
+        max_in_flight: config.max_in_flight.max(1),
+    };
+
+    tokio::pin!(shutdown);
     loop {
-        let (mut socket, peer) = listener.accept().await?;
-        let tx = tx.clone();
+        tokio::select! {
+            _ = &mut shutdown => break,
+            accepted = listener.accept() => match accepted {
+                Ok((stream, peer)) => {
+                    tokio::spawn(handle_connection(state.clone(), stream, peer));
+                }
+                Err(e) => warn!("TCP 接受连接失败: {}", e),
+            },
+        }
+    }
+    info!("TCP 服务器已停止");
+    Ok(())
+}
+
+async fn handle_connection(state: TcpState, stream: TcpStream, peer: SocketAddr) {
+    let connection_id = CONNECTION_ID.fetch_add(1, Ordering::Relaxed);
+    info!("[TCP_CONNECT] 连接ID: {}, 客户端: {}", connection_id, peer);
+    let _ = stream.set_nodelay(true);
+    let (mut reader, writer) = stream.into_split();
+
+    // 派发任务按请求顺序排队，写任务依次等待并写回，保证回复顺序与请求一致
+    let (tx, rx) = mpsc::channel(state.max_in_flight);
+    let writer = tokio::spawn(write_replies(writer, rx));
+
+    loop {
+        let payload = match read_frame(&mut reader, state.max_frame_bytes).await {
+            Ok(Some(payload)) => payload,
+            Ok(None) => break,
+            Err(e) => {
+                warn!("[TCP_ERROR] 连接ID: {}, 错误: {}", connection_id, e);
+                break;
+            }
+        };
+        let task_id = REQ_ID.fetch_add(1, Ordering::Relaxed);
+        debug!(
+            "[TCP_FRAME] 任务ID: {}, 连接ID: {}, 大小: {} bytes",
+            task_id,
+            connection_id,
+            payload.len()
+        );
+        let cmd = Command::RawRequest {
+            id: task_id,
+            transport: RawTransport::Tcp,
+            peer,
+            connection: connection_id,
+            payload,
+        };
+        let state = state.clone();
+        // 同一连接的请求由同一个 worker 处理（启用亲和路由时）
+        let reply = tokio::spawn(async move {
+            dispatch_raw(
+                &state.queue,
+                &state.scheduler,
+                cmd,
+                &connection_id.to_le_bytes(),
+            )
+            .await
+        });
+        if tx.send(reply).await.is_err() {
+            break;
+        }
+    }
+
+    // 对端关闭写方向后仍写回已派发请求的回复
+    drop(tx);
+    let _ = writer.await;
+    info!(
+        "[TCP_DISCONNECT] 连接ID: {}, 客户端: {}",
+        connection_id, peer
+    );
+}
+
+async fn write_replies(
+    mut writer: OwnedWriteHalf,
+    mut replies: mpsc::Receiver<JoinHandle<Vec<u8>>>,
+) {
+    while let Some(reply) = replies.recv().await {
+        let Ok(reply) = reply.await else {
+            break;
+        };
+        let Ok(len) = u32::try_from(reply.len()) else {
+            warn!(
+                "[TCP_REPLY_TOO_LARGE] 回复 {} bytes 超过帧长度上限",
+                reply.len()
+            );
+            break;
+        };
+        if writer.write_all(&len.to_be_bytes()).await.is_err()
+            || writer.write_all(&reply).await.is_err()
+        {
+            break;
+        }
+    }
+}
+
+/// 读取一帧，对端在帧边界关闭连接时返回 None，在帧中间关闭时返回错误
+async fn read_frame(
+    reader: &mut (impl AsyncRead + Unpin),
+    max_len: usize,
+) -> anyhow::Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    let mut filled = 0;
+    while filled < len.len() {
+        match reader.read(&mut len[filled..]).await? {
+            0 if filled == 0 => return Ok(None),
+            0 => return Err(anyhow::anyhow!("长度前缀不完整: 只收到 {} 字节", filled)),
+            n => filled += n,
+        }
+    }
+    let len = u32::from_be_bytes(len) as usize;
+    if len > max_len {
+        return Err(anyhow::anyhow!("帧长度 {} 超过上限 {}", len, max_len));
+    }
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload).await?;
+    Ok(Some(payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mi7::pipe::PipeConfig;
+    use mi7::{HeapSlotPipe, Message, RpcChannel};
+    use std::time::Duration;
+
+    /// 以 4 字节大端长度前缀编码一帧
+    fn frame(payload: &[u8]) -> Vec<u8> {
+        let mut data = (payload.len() as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(payload);
+        data
+    }
+
+    #[tokio::test]
+    async fn test_read_frames() {
+        let mut data = frame(b"hello");
+        data.extend(frame(b""));
+        data.extend(frame(b"world"));
+        let mut reader = data.as_slice();
+
+        assert_eq!(
+            read_frame(&mut reader, 16).await.unwrap().unwrap(),
+            b"hello"
+        );
+        // 零长度帧是合法的空请求，不影响后续帧
+        assert_eq!(read_frame(&mut reader, 16).await.unwrap().unwrap(), b"");
+        assert_eq!(
+            read_frame(&mut reader, 16).await.unwrap().unwrap(),
+            b"world"
+        );
+        assert!(read_frame(&mut reader, 16).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_partial_frame() {
+        // 长度前缀不完整
+        let mut reader: &[u8] = &[0, 0];
+        assert!(read_frame(&mut reader, 16).await.is_err());
+
+        // 负载不完整
+        let data = frame(b"hello");
+        let mut reader = &data[..data.len() - 1];
+        assert!(read_frame(&mut reader, 16).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_oversized_length_prefix() {
+        let mut reader: &[u8] = &[0xff, 0xff, 0xff, 0xff];
+        let e = read_frame(&mut reader, 16).await.unwrap_err();
+        assert!(e.to_string().contains("超过上限"), "{}", e);
+
+        // 恰好等于上限的帧可以读取
+        let data = frame(&[7; 16]);
+        let mut reader = data.as_slice();
+        assert_eq!(read_frame(&mut reader, 16).await.unwrap().unwrap(), [7; 16]);
+    }
+
+    #[tokio::test]
+    async fn test_connection_framing() {
+        let name = |pipe: &str| format!("entry_test_tcp_{}", pipe);
+        let request = HeapSlotPipe::create(&name("request"), PipeConfig::new(8, 1024)).unwrap();
+        let response = HeapSlotPipe::create(&name("response"), PipeConfig::new(8, 1024)).unwrap();
+        let queue: Arc<Box<dyn DynamicPipe>> = Arc::new(Box::new(request.clone()));
+        let rpc = RpcChannel::new(queue.clone(), Arc::new(Box::new(response.clone())))
+            .with_timeout(Duration::from_secs(5));
+        rpc.start();
+        let scheduler = Arc::new(Scheduler::new(
+            Arc::new(rpc),
+            queue.clone(),
+            None,
+            "default",
+        ));
+        let state = TcpState {
+            queue,
+            scheduler,
+            max_frame_bytes: 16,
+            max_in_flight: 4,
+        };
+
+        // worker 原样回显请求负载
         tokio::spawn(async move {
-            let mut buf = vec![0u8; 4096];
             loop {
-                match socket.read(&mut buf).await {
-                    Ok(0) => break, // closed
-                    Ok(n) => {
-                        let id = TCP_ID.fetch_add(1, Ordering::Relaxed);
-                        let payload = buf[..n].to_vec();
-                        let _ = tx.send(Command::TcpPacket { id, peer, payload: payload.clone() });
-                        // echo back
-                        if socket.write_all(&payload).await.is_err() {
-                            break;
-                        }
-                    }
-                    Err(e) => {
-                        error!("tcp read err={} peer={}", e, peer);
-                        break;
-                    }
-                }
+                let index = request.fetch_async().await.unwrap();
+                let (request_id, message) = request.receive_tagged(index).unwrap();
+                let Ok(Command::RawRequest { id, payload, .. }) = Command::decode(&message.data)
+                else {
+                    panic!("不是原始协议请求");
+                };
+                let mut reply = Message::init(String::new());
+                reply.data = mi7::Response::Ok { id, body: payload }.encode().unwrap();
+                let index = response.hold().unwrap();
+                response.send_tagged(index, request_id, reply).unwrap();
             }
         });
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, peer) = listener.accept().await.unwrap();
+            handle_connection(state, stream, peer).await;
+        });
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut data = frame(b"");
+        data.extend(frame(b"ping"));
+        // 超过上限的长度前缀：服务端写回已派发请求的回复后断开连接
+        data.extend([0xff, 0xff, 0xff, 0xff]);
+        client.write_all(&data).await.unwrap();
+
+        let mut replies = Vec::new();
+        tokio::time::timeout(Duration::from_secs(5), client.read_to_end(&mut replies))
+            .await
+            .unwrap()
+            .unwrap();
+        let mut expected = frame(b"");
+        expected.extend(frame(b"ping"));
+        assert_eq!(replies, expected);
     }
-}
\ No newline at end of file
+}
@@ -1,27 +1,189 @@
-use tracing::{debug, error, info};
+//! 原始 UDP 协议：每个数据报转发给 worker
+//!
+//! 数据报转换为 [`Command::RawRequest`] 经调度者派发给 worker，回复以单个数据报发回来源地址。
+//! UDP 不保证送达与顺序，客户端需自行在负载中携带关联标识。
+//! 空数据报同样派发；超过数据报上限的回复不发送。
 
-use crate::protocols::common::Command;
+use crate::protocols::common::{Command, RawTransport, dispatch_raw};
+use crate::protocols::http_server::REQ_ID;
+use crate::scheduler::Scheduler;
+use mi7::pipe::DynamicPipe;
+use serde::Deserialize;
 use std::net::SocketAddr;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::{TcpListener, UdpSocket};
-use tokio::sync::mpsc::UnboundedSender;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use tokio::net::UdpSocket;
+use tracing::{debug, info, warn};
 
+/// UDP 数据报负载的最大长度
+const MAX_DATAGRAM: usize = 65507;
 
-static TCP_ID: AtomicU64 = AtomicU64::new(1);
-static UDP_ID: AtomicU64 = AtomicU64::new(1);
+/// `[udp]` 配置段，缺省时不启动 UDP 服务
+#[derive(Debug, Deserialize)]
+pub struct UdpConfig {
+    #[serde(default = "UdpConfig::default_bind_address")]
+    pub bind_address: String,
+    pub port: u16,
+}
 
+impl UdpConfig {
+    fn default_bind_address() -> String {
+        "0.0.0.0".to_string()
+    }
+
+    /// 监听地址
+    pub fn addr(&self) -> anyhow::Result<SocketAddr> {
+        format!("{}:{}", self.bind_address, self.port)
+            .parse()
+            .map_err(|e| anyhow::anyhow!("无效的 UDP 监听地址 {}: {}", self.bind_address, e))
+    }
+}
 
-pub async fn run_udp(addr: SocketAddr, tx: UnboundedSender<Command>) -> anyhow::Result<()> {
+/// 运行 UDP 服务，收到停止信号后不再接收数据报
+pub async fn run(
+    addr: SocketAddr,
+    queue: Arc<Box<dyn DynamicPipe>>,
+    scheduler: Arc<Scheduler>,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> anyhow::Result<()> {
     let socket = UdpSocket::bind(addr).await?;
-    info!("UDP listening on {}", addr);
-    let mut buf = vec![0u8; 2048];
+    info!("UDP 服务器启动成功，监听地址: {}", addr);
+    serve(Arc::new(socket), queue, scheduler, shutdown).await;
+    info!("UDP 服务器已停止");
+    Ok(())
+}
+
+/// 在已绑定的套接字上接收数据报，直到收到停止信号
+async fn serve(
+    socket: Arc<UdpSocket>,
+    queue: Arc<Box<dyn DynamicPipe>>,
+    scheduler: Arc<Scheduler>,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) {
+    let mut buf = vec![0u8; MAX_DATAGRAM];
+    tokio::pin!(shutdown);
     loop {
-        let (n, peer) = socket.recv_from(&mut buf).await?;
-        let id = UDP_ID.fetch_add(1, Ordering::Relaxed);
-        let payload = buf[..n].to_vec();
-        let _ = tx.send(Command::UdpPacket { id, peer, payload: payload.clone() });
-        // echo
-        let _ = socket.send_to(&payload, peer).await;
+        let (n, peer) = tokio::select! {
+            _ = &mut shutdown => break,
+            received = socket.recv_from(&mut buf) => match received {
+                Ok(received) => received,
+                Err(e) => {
+                    warn!("UDP 接收数据报失败: {}", e);
+                    continue;
+                }
+            },
+        };
+        let task_id = REQ_ID.fetch_add(1, Ordering::Relaxed);
+        debug!(
+            "[UDP_DATAGRAM] 任务ID: {}, 客户端: {}, 大小: {} bytes",
+            task_id, peer, n
+        );
+        let cmd = Command::RawRequest {
+            id: task_id,
+            transport: RawTransport::Udp,
+            peer,
+            connection: 0,
+            payload: buf[..n].to_vec(),
+        };
+
+        let socket = Arc::clone(&socket);
+        let queue = Arc::clone(&queue);
+        let scheduler = Arc::clone(&scheduler);
+        tokio::spawn(async move {
+            // 同一来源地址的数据报由同一个 worker 处理（启用亲和路由时）
+            let reply = dispatch_raw(&queue, &scheduler, cmd, peer.to_string().as_bytes()).await;
+            if reply.len() > MAX_DATAGRAM {
+                warn!(
+                    "[UDP_REPLY_TOO_LARGE] 任务ID: {}, 回复 {} bytes 超过数据报上限",
+                    task_id,
+                    reply.len()
+                );
+                return;
+            }
+            if let Err(e) = socket.send_to(&reply, peer).await {
+                warn!("[UDP_REPLY_FAILED] 任务ID: {}, 错误: {}", task_id, e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mi7::pipe::PipeConfig;
+    use mi7::{HeapSlotPipe, Message, RpcChannel};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_datagrams() {
+        let name = |pipe: &str| format!("entry_test_udp_{}", pipe);
+        let request = HeapSlotPipe::create(&name("request"), PipeConfig::new(8, 1024)).unwrap();
+        let response =
+            HeapSlotPipe::create(&name("response"), PipeConfig::new(8, 128 * 1024)).unwrap();
+        let queue: Arc<Box<dyn DynamicPipe>> = Arc::new(Box::new(request.clone()));
+        let rpc = RpcChannel::new(queue.clone(), Arc::new(Box::new(response.clone())))
+            .with_timeout(Duration::from_secs(5));
+        rpc.start();
+        let scheduler = Arc::new(Scheduler::new(
+            Arc::new(rpc),
+            queue.clone(),
+            None,
+            "default",
+        ));
+
+        // worker 回显请求负载，负载为 "big" 时回复超过数据报上限的内容
+        tokio::spawn(async move {
+            loop {
+                let index = request.fetch_async().await.unwrap();
+                let (request_id, message) = request.receive_tagged(index).unwrap();
+                let Ok(Command::RawRequest { id, payload, .. }) = Command::decode(&message.data)
+                else {
+                    panic!("不是原始协议请求");
+                };
+                let body = if payload == b"big" {
+                    vec![0; MAX_DATAGRAM + 1]
+                } else {
+                    payload
+                };
+                let mut reply = Message::init(String::new());
+                reply.data = mi7::Response::Ok { id, body }.encode().unwrap();
+                let index = response.hold().unwrap();
+                response.send_tagged(index, request_id, reply).unwrap();
+            }
+        });
+
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(serve(socket, queue, scheduler, std::future::pending()));
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.connect(addr).await.unwrap();
+        let mut buf = vec![0u8; MAX_DATAGRAM];
+        let wait = Duration::from_millis(500);
+
+        // 空数据报照常派发并得到空回复
+        client.send(b"").await.unwrap();
+        assert_eq!(
+            tokio::time::timeout(wait, client.recv(&mut buf))
+                .await
+                .unwrap()
+                .unwrap(),
+            0
+        );
+
+        // 超过上限的回复不发送
+        client.send(b"big").await.unwrap();
+        assert!(
+            tokio::time::timeout(wait, client.recv(&mut buf))
+                .await
+                .is_err()
+        );
+
+        client.send(b"ping").await.unwrap();
+        let n = tokio::time::timeout(wait, client.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&buf[..n], b"ping");
     }
-}
\ No newline at end of file
+}
@@ -1,57 +1,241 @@
-use tracing::{debug, error, info};
+//! WebSocket 服务：客户端帧转发给 worker，worker 可主动向指定连接推送消息
+//!
+//! 每个连接分配一个连接 ID。收到的文本帧转换为 [`Command::WsMessage`]，经调度者派发给
+//! worker，worker 的回复以文本帧发回该连接；worker 还可以通过 [`mi7::Pusher`] 向任意
+//! 连接 ID 推送消息，推送经响应管道到达 entry 后由本模块转发给对应的连接。
 
-use crate::protocols::common::Command;
+use crate::protocols::common::{Command, call_error, command_message, reply_body};
+use crate::protocols::http_server::REQ_ID;
+use crate::scheduler::Scheduler;
 use futures::{SinkExt, StreamExt};
+use mi7::pipe::DynamicPipe;
+use mi7::{Message, tracing_ipc};
+use serde::Deserialize;
+use std::collections::HashMap;
 use std::net::SocketAddr;
-use tokio::net::TcpListener;
-use tokio_tungstenite::tungstenite::Message;
-use tokio::sync::mpsc::UnboundedSender;
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message as Frame;
+use tracing::{Instrument, debug, error, info, warn};
 
+/// 连接 ID 生成器
+static CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
 
-static WS_ID: AtomicU64 = AtomicU64::new(1);
+/// `[websocket]` 配置段，缺省时不启动 WebSocket 服务
+#[derive(Debug, Deserialize)]
+pub struct WsConfig {
+    #[serde(default = "WsConfig::default_bind_address")]
+    pub bind_address: String,
+    pub port: u16,
+}
 
+impl WsConfig {
+    fn default_bind_address() -> String {
+        "0.0.0.0".to_string()
+    }
+
+    /// 监听地址
+    pub fn addr(&self) -> anyhow::Result<SocketAddr> {
+        format!("{}:{}", self.bind_address, self.port)
+            .parse()
+            .map_err(|e| anyhow::anyhow!("无效的 WebSocket 监听地址 {}: {}", self.bind_address, e))
+    }
+}
+
+/// 在线连接：连接 ID -> 该连接的待发送帧队列
+type Connections = Arc<Mutex<HashMap<u64, mpsc::UnboundedSender<Frame>>>>;
 
-pub async fn run(addr: SocketAddr, tx: UnboundedSender<Command>) -> anyhow::Result<()> {
+/// WebSocket 服务状态
+#[derive(Clone)]
+struct WsState {
+    queue: Arc<Box<dyn DynamicPipe>>,
+    scheduler: Arc<Scheduler>,
+    connections: Connections,
+}
+
+/// 运行 WebSocket 服务，`pushes` 为 worker 推送的消息（见 [`mi7::RpcChannel::subscribe_pushes`]）
+///
+/// 收到停止信号后不再接受新连接，并向在线连接发送关闭帧。
+pub async fn run(
+    addr: SocketAddr,
+    queue: Arc<Box<dyn DynamicPipe>>,
+    scheduler: Arc<Scheduler>,
+    mut pushes: mpsc::UnboundedReceiver<Message>,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> anyhow::Result<()> {
     let listener = TcpListener::bind(addr).await?;
-    info!("WebSocket listening on {}", addr);
+    info!("WebSocket 服务器启动成功，监听地址: {}", addr);
+
+    let state = WsState {
+        queue,
+        scheduler,
+        connections: Arc::new(Mutex::new(HashMap::new())),
+    };
 
+    // 把 worker 推送的消息转发给目标连接
+    let connections = Arc::clone(&state.connections);
+    let push_handle = tokio::spawn(async move {
+        while let Some(message) = pushes.recv().await {
+            let Some((connection_id, payload)) = message.as_push() else {
+                continue;
+            };
+            let sender = connections.lock().unwrap().get(&connection_id).cloned();
+            match sender {
+                Some(sender) => {
+                    let _ = sender.send(frame_from(payload.to_vec()));
+                }
+                None => debug!("[WS_PUSH] 连接 {} 不在线，丢弃推送", connection_id),
+            }
+        }
+    });
 
+    tokio::pin!(shutdown);
     loop {
-        let (stream, peer) = listener.accept().await?;
-        let tx = tx.clone();
-        tokio::spawn(async move {
-            let ws_stream = match tokio_tungstenite::accept_async(stream).await {
-                Ok(s) => s,
-                Err(e) => {
-                    error!("ws accept err={} peer={}", e, peer);
-                    return;
+        tokio::select! {
+            _ = &mut shutdown => break,
+            accepted = listener.accept() => match accepted {
+                Ok((stream, peer)) => {
+                    tokio::spawn(handle_connection(state.clone(), stream, peer));
                 }
-            };
-            let (mut write, mut read) = ws_stream.split();
-            while let Some(msg) = read.next().await {
-                match msg {
-                    Ok(Message::Text(t)) => {
-                        let id = WS_ID.fetch_add(1, Ordering::Relaxed);
-                        let client = peer.to_string();
-                        let _ = tx.send(Command::WsMessage { id, client, payload: t.clone() });
-
-
-                        // echo back simple acknowledgement
-                        if write.send(Message::Text(format!("recv id={}", id))).await.is_err() {
-                            break;
-                        }
-                    }
-                    Ok(Message::Binary(_)) => {}
-                    Ok(Message::Ping(_)) => {}
-                    Ok(Message::Close(_)) => break,
-                    Err(e) => {
-                        error!("ws err={} peer={}", e, peer);
-                        break;
-                    }
-                    _ => {}
+                Err(e) => warn!("WebSocket 接受连接失败: {}", e),
+            },
+        }
+    }
+
+    push_handle.abort();
+    for sender in state.connections.lock().unwrap().values() {
+        let _ = sender.send(Frame::Close(None));
+    }
+    info!("WebSocket 服务器已停止");
+    Ok(())
+}
+
+/// 合法 UTF-8 以文本帧发送，否则以二进制帧发送
+fn frame_from(data: Vec<u8>) -> Frame {
+    match String::from_utf8(data) {
+        Ok(text) => Frame::Text(text),
+        Err(e) => Frame::Binary(e.into_bytes()),
+    }
+}
+
+async fn handle_connection(state: WsState, stream: TcpStream, peer: SocketAddr) {
+    let ws_stream = match tokio_tungstenite::accept_async(stream).await {
+        Ok(s) => s,
+        Err(e) => {
+            error!("[WS_ACCEPT_ERROR] 客户端: {}, 错误: {}", peer, e);
+            return;
+        }
+    };
+    let connection_id = CONNECTION_ID.fetch_add(1, Ordering::Relaxed);
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    state
+        .connections
+        .lock()
+        .unwrap()
+        .insert(connection_id, tx.clone());
+    info!("[WS_CONNECT] 连接ID: {}, 客户端: {}", connection_id, peer);
+
+    let (mut write, mut read) = ws_stream.split();
+    // 回复与推送都经队列写出，写任务在发送关闭帧后退出
+    let writer = tokio::spawn(async move {
+        while let Some(frame) = rx.recv().await {
+            let close = matches!(frame, Frame::Close(_));
+            if write.send(frame).await.is_err() || close {
+                break;
+            }
+        }
+    });
+
+    while let Some(frame) = read.next().await {
+        let payload = match frame {
+            Ok(Frame::Text(text)) => text,
+            Ok(Frame::Binary(data)) => match String::from_utf8(data) {
+                Ok(text) => text,
+                Err(_) => {
+                    warn!("[WS_INVALID] 连接ID: {}, 二进制帧不是 UTF-8", connection_id);
+                    continue;
                 }
+            },
+            Ok(Frame::Close(_)) => break,
+            Ok(_) => continue,
+            Err(e) => {
+                warn!("[WS_ERROR] 连接ID: {}, 错误: {}", connection_id, e);
+                break;
+            }
+        };
+        // 每帧独立派发，慢请求不阻塞同一连接上的后续帧
+        tokio::spawn(forward(
+            state.clone(),
+            tx.clone(),
+            connection_id,
+            peer,
+            payload,
+        ));
+    }
+
+    state.connections.lock().unwrap().remove(&connection_id);
+    drop(tx);
+    writer.abort();
+    info!(
+        "[WS_DISCONNECT] 连接ID: {}, 客户端: {}",
+        connection_id, peer
+    );
+}
+
+/// 把一帧转换为命令派发给 worker，并将回复发回连接
+async fn forward(
+    state: WsState,
+    tx: mpsc::UnboundedSender<Frame>,
+    connection_id: u64,
+    peer: SocketAddr,
+    payload: String,
+) {
+    let task_id = REQ_ID.fetch_add(1, Ordering::Relaxed);
+    let reply_error = |error: String, code: u16| {
+        let body = serde_json::json!({ "error": error, "code": code, "task_id": task_id });
+        let _ = tx.send(Frame::Text(body.to_string()));
+    };
+
+    let cmd = Command::WsMessage {
+        id: task_id,
+        connection: connection_id,
+        client: peer.to_string(),
+        payload,
+    };
+    let message = match command_message(&cmd) {
+        Ok(message) => message,
+        Err(e) => {
+            error!("[WS_SERIALIZE_ERROR] 任务ID: {}, 错误: {}", task_id, e);
+            return reply_error(format!("序列化失败: {}", e), 500);
+        }
+    };
+    // 同一连接的消息由同一个 worker 处理（启用亲和路由时）
+    let mut message = message.with_affinity(connection_id.to_le_bytes());
+    let trace_span = tracing_ipc::producer_span(&mut message);
+
+    // 队列占用超过高水位时直接拒绝，由客户端稍后重试
+    if state.queue.is_backpressured() {
+        warn!("[WS_BACKPRESSURE] 任务ID: {}", task_id);
+        return reply_error("队列繁忙，请稍后重试".to_string(), 429);
+    }
+
+    debug!(
+        "[WS_FORWARD] 任务ID: {}, 连接ID: {}",
+        task_id, connection_id
+    );
+    match state.scheduler.call(message).instrument(trace_span).await {
+        Ok(reply) => match reply_body(&reply) {
+            Ok(body) => {
+                let _ = tx.send(frame_from(body));
             }
-        });
+            Err(e) => reply_error(e.error, e.code),
+        },
+        Err(e) => {
+            error!("[WS_RPC_FAILED] 任务ID: {}, 错误: {}", task_id, e);
+            let e = call_error(e);
+            reply_error(e.error, e.code);
+        }
     }
-}
\ No newline at end of file
+}
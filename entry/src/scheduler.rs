@@ -1,188 +1,547 @@
-use mi7::pipe::DynamicPipe;
-use mi7::shared_slot::SlotState;
-use std::sync::Arc;
-use std::sync::atomic::{AtomicI64, Ordering};
-use tokio::sync::mpsc;
-use tracing::{debug, error, info, warn};
-
-/// 调度者结构体，管理槽位分配
+use mi7::pipe::{DynamicPipe, PipeFactory, PipeMetrics};
+use mi7::shared_slot::LATENCY_BUCKETS;
+use mi7::{
+    AffinityRing, Message, RateLimiterStats, RpcChannel, SharedRateLimiter, WorkerBoard, WorkerInfo,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+type SharedPipe = Arc<Box<dyn DynamicPipe>>;
+
+/// 已连接的 worker 收件管道，按所属进程区分新旧
+struct InboxConnection {
+    pid: u32,
+    pipe: SharedPipe,
+}
+
+/// 调度者：把请求派发给在途任务最少的 worker
+///
+/// worker 在共享内存的 [`WorkerBoard`] 中登记心跳、在途数量与专属收件管道。
+/// 调度者选出在途任务最少的存活 worker，把请求写入其收件管道；没有可用 worker
+/// （登记表不可用、全部失联或收件管道无法写入）时退回共享请求管道，由任一 worker 领取。
+///
+/// 启用亲和路由（[`Scheduler::with_affinity`]）后，携带亲和键的请求派发给一致性哈希环上
+/// 该键的归属 worker；归属 worker 暂时无法接收时按负载改派，键的归属不变。
+///
+/// 启用限流（[`Scheduler::with_rate_limiter`]）后，每个请求派发前从共享令牌桶取令牌，
+/// 令牌不足时 [`Scheduler::call`] 返回 [`RateLimited`](mi7::RateLimited) 错误。
 pub struct Scheduler {
-    queue: Arc<Box<dyn DynamicPipe>>,
-    counter: Arc<AtomicI64>,
-    slot_sender: mpsc::UnboundedSender<usize>,
-    slot_receiver: mpsc::UnboundedReceiver<usize>,
+    rpc: Arc<RpcChannel>,
+    shared_pipe: SharedPipe,
+    board: Option<Arc<WorkerBoard>>,
+    affinity: Option<AffinityRing>,
+    rate_limiter: Option<SharedRateLimiter>,
+    inbox_type: String,
+    inboxes: Mutex<HashMap<String, InboxConnection>>,
+    dispatched: Mutex<HashMap<String, u64>>,
+    fallback: AtomicU64,
+    sticky: AtomicU64,
+    rerouted: AtomicU64,
 }
 
-impl Scheduler {
-    /// 创建新的调度者实例
-    pub fn new(queue: Arc<Box<dyn DynamicPipe>>) -> Self {
-        let (slot_sender, slot_receiver) = mpsc::unbounded_channel();
-        let counter = Arc::new(AtomicI64::new(0));
+/// 调度统计，通过 `/status` 输出
+#[derive(Debug, Serialize)]
+pub struct SchedulerStats {
+    /// 派发到 worker 收件管道的请求数量
+    pub dispatched: u64,
+    /// 退回共享请求管道的请求数量
+    pub fallback: u64,
+    /// 按亲和键派发给归属 worker 的请求数量
+    pub sticky: u64,
+    /// 归属 worker 无法接收而按负载改派的请求数量
+    pub rerouted: u64,
+    /// 本机所有 entry 合计的限流统计，未启用限流时为 `None`
+    pub rate_limit: Option<RateLimiterStats>,
+    /// 所有请求从写入管道到被 worker 取出的等待时间
+    pub queue_wait: WaitStats,
+    pub workers: Vec<WorkerStats>,
+}
 
+/// 单个 worker 的调度统计
+#[derive(Debug, Serialize)]
+pub struct WorkerStats {
+    pub worker_id: String,
+    pub pid: u32,
+    pub alive: bool,
+    pub in_flight: u32,
+    pub processed: u64,
+    /// 本 entry 派发给该 worker 的请求数量
+    pub dispatched: u64,
+    /// 自登记以来平均每秒完成的任务数量
+    pub throughput: f64,
+    /// 平均处理耗时（毫秒）
+    pub mean_service_ms: f64,
+    /// 收件管道中的等待时间
+    pub queue_wait: WaitStats,
+}
+
+/// 等待时间统计（毫秒）
+#[derive(Debug, Default, Serialize)]
+pub struct WaitStats {
+    pub count: u64,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+}
+
+impl From<&PipeMetrics> for WaitStats {
+    fn from(metrics: &PipeMetrics) -> Self {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
         Self {
-            queue,
-            counter,
-            slot_sender,
-            slot_receiver,
+            count: metrics.count,
+            mean_ms: ms(metrics.mean),
+            p50_ms: ms(metrics.p50),
+            p95_ms: ms(metrics.p95),
+            p99_ms: ms(metrics.p99),
         }
     }
+}
 
-    /// 获取计数器的克隆
-    pub fn get_counter(&self) -> Arc<AtomicI64> {
-        self.counter.clone()
+impl Scheduler {
+    /// 创建调度者，`board` 为 `None` 时所有请求都经由共享请求管道
+    pub fn new(
+        rpc: Arc<RpcChannel>,
+        shared_pipe: SharedPipe,
+        board: Option<Arc<WorkerBoard>>,
+        inbox_type: &str,
+    ) -> Self {
+        Self {
+            rpc,
+            shared_pipe,
+            board,
+            affinity: None,
+            rate_limiter: None,
+            inbox_type: inbox_type.to_string(),
+            inboxes: Mutex::new(HashMap::new()),
+            dispatched: Mutex::new(HashMap::new()),
+            fallback: AtomicU64::new(0),
+            sticky: AtomicU64::new(0),
+            rerouted: AtomicU64::new(0),
+        }
     }
 
-    /// 获取槽位发送器的克隆
-    pub fn get_slot_sender(&self) -> mpsc::UnboundedSender<usize> {
-        self.slot_sender.clone()
+    /// 启用亲和路由，携带亲和键的请求按一致性哈希环派发
+    pub fn with_affinity(mut self, ring: AffinityRing) -> Self {
+        self.affinity = Some(ring);
+        self
     }
 
-    /// 启动调度者协程
-    pub async fn run(self) {
-        info!("[SCHEDULER] 调度者协程已启动");
+    /// 启用跨进程限流：本机所有 entry 合计不超过共享令牌桶的速率
+    pub fn with_rate_limiter(mut self, limiter: SharedRateLimiter) -> Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
 
-        let mut last_counter_value = 0i64;
-        let mut check_interval = tokio::time::interval(tokio::time::Duration::from_millis(1));
+    /// 派发请求并等待响应，使用 RPC 通道的默认超时
+    ///
+    /// 启用限流且令牌不足时不派发，返回 [`RateLimited`](mi7::RateLimited) 错误。
+    pub async fn call(&self, message: Message) -> mi7::Result<Message> {
+        if let Some(limiter) = &self.rate_limiter {
+            limiter.check(1)?;
+        }
+        let Some((board, worker, inbox)) = self.pick(message.affinity) else {
+            self.fallback.fetch_add(1, Ordering::Relaxed);
+            return self.rpc.call(message).await;
+        };
 
-        loop {
-            check_interval.tick().await;
+        let start = Instant::now();
+        let reply = match self.rpc.request_via(&inbox, message).await {
+            Ok(reply) => reply,
+            Err(e) => {
+                board.unassign(&worker);
+                return Err(e);
+            }
+        };
+        *self
+            .dispatched
+            .lock()
+            .unwrap()
+            .entry(worker.worker_id.clone())
+            .or_default() += 1;
+        debug!(
+            "[SCHEDULER] 请求 {} 派发给 worker {} (在途 {})",
+            reply.request_id(),
+            worker.worker_id,
+            worker.in_flight + 1
+        );
+        reply
+            .wait(self.rpc.timeout().saturating_sub(start.elapsed()))
+            .await
+    }
 
-            // 检查 counter 信号是否有变化
-            let current_counter = self.counter.load(Ordering::Acquire);
+    /// 选出亲和键的归属 worker 或在途任务最少的 worker，并记录派发
+    fn pick(&self, affinity: u64) -> Option<(&Arc<WorkerBoard>, WorkerInfo, SharedPipe)> {
+        let board = self.board.as_ref()?;
+        let workers: Vec<WorkerInfo> = board
+            .workers()
+            .into_iter()
+            .filter(|worker| worker.alive && !worker.inbox.is_empty())
+            .collect();
 
-            if current_counter > last_counter_value {
-                debug!(
-                    "[SCHEDULER] 检测到新的槽位请求，counter: {} -> {}",
-                    last_counter_value, current_counter
-                );
+        if affinity != 0
+            && let Some(ring) = &self.affinity
+        {
+            // 成员按登记表确定，与本 entry 的连接状态无关，各 entry 得到相同的归属
+            ring.sync(&workers);
+            let owner = ring
+                .lookup(affinity)
+                .and_then(|pid| workers.iter().find(|worker| worker.pid == pid));
+            if let Some(worker) = owner
+                && let Some(inbox) = self.take(board, worker)
+            {
+                self.sticky.fetch_add(1, Ordering::Relaxed);
+                return Some((board, worker.clone(), inbox));
+            }
+            self.rerouted.fetch_add(1, Ordering::Relaxed);
+        }
 
-                // 尝试处理所有待处理的请求
-                let requests_to_process = current_counter - last_counter_value;
-                let mut processed = 0;
-
-                for _ in 0..requests_to_process {
-                    match self.find_and_reserve_empty_slot().await {
-                        Ok(slot_index) => {
-                            debug!(
-                                "[SCHEDULER] 找到空闲槽位: {}, 状态: EMPTY -> PENDINGWRITE",
-                                slot_index
-                            );
-
-                            // 发送槽位索引到通道（虽然当前架构中暂时不使用）
-                            if let Err(e) = self.slot_sender.send(slot_index) {
-                                error!("[SCHEDULER] 发送槽位索引失败: {:?}", e);
-                                break;
-                            }
-
-                            processed += 1;
-                        }
-                        Err(e) => {
-                            debug!("[SCHEDULER] 未找到可用槽位: {}", e);
-                            break; // 没有更多可用槽位，停止处理
-                        }
-                    }
-                }
+        let worker = workers
+            .into_iter()
+            .filter(|worker| self.ready(worker))
+            .min_by_key(|worker| (worker.in_flight, worker.processed))?;
+        let inbox = self.take(board, &worker)?;
+        Some((board, worker, inbox))
+    }
 
-                // 减少已处理的请求数量
-                if processed > 0 {
-                    let old_value = self.counter.fetch_sub(processed, Ordering::AcqRel);
-                    debug!(
-                        "[SCHEDULER] 处理了 {} 个请求，counter: {} -> {}",
-                        processed,
-                        old_value,
-                        self.counter.load(Ordering::Acquire)
-                    );
-                }
+    /// 专属响应管道尚未挂接时回复无人读取，暂不派发
+    fn ready(&self, worker: &WorkerInfo) -> bool {
+        worker.response_pipe.is_empty() || self.rpc.is_attached(&worker.response_pipe)
+    }
 
-                last_counter_value = self.counter.load(Ordering::Acquire);
-            } else if current_counter < last_counter_value {
-                // counter 被重置或减少了
-                last_counter_value = current_counter;
-            }
+    /// 占用 worker 的一个在途名额，收件管道不可写入时返回 `None`
+    fn take(&self, board: &WorkerBoard, worker: &WorkerInfo) -> Option<SharedPipe> {
+        if !self.ready(worker) {
+            return None;
+        }
+        let inbox = self.inbox(worker)?;
+        if inbox.is_backpressured() || !board.assign(worker) {
+            return None;
+        }
+        Some(inbox)
+    }
 
-            // 定期检查是否有槽位从其他状态变为 EMPTY，可以重新分配
-            if current_counter > 0 {
-                match self.find_and_reserve_empty_slot().await {
-                    Ok(slot_index) => {
-                        debug!(
-                            "[SCHEDULER] 发现新释放的槽位: {}, 状态: EMPTY -> PENDINGWRITE",
-                            slot_index
-                        );
-
-                        if let Err(e) = self.slot_sender.send(slot_index) {
-                            error!("[SCHEDULER] 发送槽位索引失败: {:?}", e);
-                            break;
-                        }
-
-                        let old_value = self.counter.fetch_sub(1, Ordering::AcqRel);
-                        debug!(
-                            "[SCHEDULER] 分配释放的槽位 {}, counter: {} -> {}",
-                            slot_index,
-                            old_value,
-                            self.counter.load(Ordering::Acquire)
-                        );
-
-                        last_counter_value = self.counter.load(Ordering::Acquire);
-                    }
-                    Err(_) => {
-                        // 没有可用槽位，继续等待
-                    }
-                }
+    /// 连接 worker 的收件管道，worker 重启后重新连接
+    fn inbox(&self, worker: &WorkerInfo) -> Option<SharedPipe> {
+        let mut inboxes = self.inboxes.lock().unwrap();
+        if let Some(connection) = inboxes.get(&worker.inbox)
+            && connection.pid == worker.pid
+        {
+            return Some(Arc::clone(&connection.pipe));
+        }
+        match PipeFactory::connect(&self.inbox_type, &worker.inbox, false) {
+            Ok(pipe) => {
+                let pipe = Arc::new(pipe);
+                inboxes.insert(
+                    worker.inbox.clone(),
+                    InboxConnection {
+                        pid: worker.pid,
+                        pipe: Arc::clone(&pipe),
+                    },
+                );
+                Some(pipe)
+            }
+            Err(e) => {
+                warn!(
+                    "[SCHEDULER] 连接 worker {} 的收件管道 {} 失败: {}",
+                    worker.worker_id, worker.inbox, e
+                );
+                None
             }
         }
+    }
 
-        warn!("[SCHEDULER] 调度者协程已退出");
+    /// 已连接的 worker 收件管道：(管道名称, 管道)
+    pub fn inbox_pipes(&self) -> Vec<(String, SharedPipe)> {
+        let mut pipes: Vec<_> = self
+            .inboxes
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, connection)| (name.clone(), Arc::clone(&connection.pipe)))
+            .collect();
+        pipes.sort_by(|a, b| a.0.cmp(&b.0));
+        pipes
     }
 
-    /// 查找并预留空闲槽位
-    async fn find_and_reserve_empty_slot(&self) -> Result<usize, &'static str> {
-        let slot_index = self.queue.hold().map_err(|_| "没有可用的 EMPTY 槽位")?;
+    /// 当前的调度统计
+    pub fn stats(&self) -> SchedulerStats {
+        let mut counts = [0u64; LATENCY_BUCKETS];
+        let mut sum_nanos = 0u64;
+        let mut merge = |metrics: &PipeMetrics| {
+            for (total, (_, count)) in counts.iter_mut().zip(&metrics.buckets) {
+                *total += count;
+            }
+            sum_nanos += metrics.sum.as_nanos() as u64;
+        };
+        merge(&self.shared_pipe.metrics());
+
+        let workers = self.board.as_ref().map(|b| b.workers()).unwrap_or_default();
+        let inboxes = self.inboxes.lock().unwrap();
+        let dispatched = self.dispatched.lock().unwrap();
+        let workers = workers
+            .into_iter()
+            .map(|worker| {
+                let queue_wait = inboxes
+                    .get(&worker.inbox)
+                    .filter(|connection| connection.pid == worker.pid)
+                    .map(|connection| connection.pipe.metrics())
+                    .unwrap_or_default();
+                merge(&queue_wait);
+                WorkerStats {
+                    dispatched: dispatched.get(&worker.worker_id).copied().unwrap_or(0),
+                    throughput: worker.throughput(),
+                    mean_service_ms: worker.busy.as_secs_f64() * 1000.0
+                        / worker.processed.max(1) as f64,
+                    queue_wait: WaitStats::from(&queue_wait),
+                    worker_id: worker.worker_id,
+                    pid: worker.pid,
+                    alive: worker.alive,
+                    in_flight: worker.in_flight,
+                    processed: worker.processed,
+                }
+            })
+            .collect();
 
-        debug!("调度者：找到空闲槽位 {} 并标记为 WRITING", slot_index);
-        Ok(slot_index)
+        SchedulerStats {
+            dispatched: dispatched.values().sum(),
+            fallback: self.fallback.load(Ordering::Relaxed),
+            sticky: self.sticky.load(Ordering::Relaxed),
+            rerouted: self.rerouted.load(Ordering::Relaxed),
+            rate_limit: self.rate_limiter.as_ref().map(|limiter| limiter.stats()),
+            queue_wait: WaitStats::from(&PipeMetrics::from_histogram(&counts, sum_nanos)),
+            workers,
+        }
     }
 }
 
-/// 槽位请求器，用于 handler 获取槽位
-pub struct SlotRequester {
-    counter: Arc<AtomicI64>,
-    slot_receiver: mpsc::UnboundedReceiver<usize>,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mi7::pipe::PipeConfig;
+    use mi7::{ErrorCode, HeapSlotPipe, RpcServer, WorkerRegistration};
 
-impl SlotRequester {
-    /// 创建新的槽位请求器
-    pub fn new(counter: Arc<AtomicI64>, slot_receiver: mpsc::UnboundedReceiver<usize>) -> Self {
-        Self {
-            counter,
-            slot_receiver,
-        }
+    /// 收件管道的布局，与 `inbox_type` 一致
+    const INBOX_TYPE: &str = "memory(4x256)";
+
+    fn unique_name(test: &str, tag: &str) -> String {
+        format!("entry_test_sched_{}_{}_{}", test, tag, std::process::id())
     }
 
-    /// 请求一个槽位索引
-    pub async fn request_slot(&mut self) -> Result<usize, &'static str> {
-        // 首先尝试从通道获取
-        if let Ok(slot_index) = self.slot_receiver.try_recv() {
-            debug!("[SLOT_REQUESTER] 直接获取到槽位: {}", slot_index);
-            return Ok(slot_index);
-        }
+    fn pipe(name: &str, config: PipeConfig) -> SharedPipe {
+        Arc::new(Box::new(HeapSlotPipe::create(name, config).unwrap()))
+    }
 
-        // 如果通道为空，增加计数器并等待
-        self.counter.fetch_add(1, Ordering::AcqRel);
-        debug!(
-            "[SLOT_REQUESTER] 通道为空，counter +1: {}",
-            self.counter.load(Ordering::Acquire)
-        );
+    /// 登记表、RPC 通道与各 worker 的登记和收件管道
+    struct Fixture {
+        board: Arc<WorkerBoard>,
+        rpc: Arc<RpcChannel>,
+        shared: SharedPipe,
+        registrations: Vec<WorkerRegistration>,
+        inboxes: Vec<Option<SharedPipe>>,
+    }
+
+    impl Fixture {
+        /// `workers` 为 (worker ID, 是否有专属收件管道)，收件管道有一条消息即进入背压
+        fn new(test: &str, workers: &[(&str, bool)]) -> Self {
+            let board = Arc::new(WorkerBoard::create(&unique_name(test, "board")).unwrap());
+            let shared = pipe(&unique_name(test, "request"), PipeConfig::new(4, 256));
+            let response = pipe(&unique_name(test, "response"), PipeConfig::new(4, 256));
+            let rpc = Arc::new(
+                RpcChannel::new(Arc::clone(&shared), response)
+                    .with_timeout(Duration::from_millis(200)),
+            );
 
-        // 等待槽位可用
-        match self.slot_receiver.recv().await {
-            Some(slot_index) => {
-                debug!("[SLOT_REQUESTER] 等待后获取到槽位: {}", slot_index);
-                Ok(slot_index)
+            let mut registrations = Vec::new();
+            let mut inboxes = Vec::new();
+            for (worker_id, private) in workers {
+                let inbox_name = if *private {
+                    unique_name(test, worker_id)
+                } else {
+                    String::new()
+                };
+                inboxes.push(
+                    private
+                        .then(|| pipe(&inbox_name, PipeConfig::new(4, 256).with_watermarks(1, 0))),
+                );
+                registrations.push(board.register(worker_id, &inbox_name, "").unwrap());
             }
-            None => {
-                error!("[SLOT_REQUESTER] 槽位通道已关闭");
-                Err("槽位通道已关闭")
+            Self {
+                board,
+                rpc,
+                shared,
+                registrations,
+                inboxes,
             }
         }
+
+        fn scheduler(&self) -> Scheduler {
+            Scheduler::new(
+                Arc::clone(&self.rpc),
+                Arc::clone(&self.shared),
+                Some(Arc::clone(&self.board)),
+                INBOX_TYPE,
+            )
+        }
+
+        /// 各 worker 的 (在途, 已处理)，按登记顺序
+        fn load(&self) -> Vec<(u32, u64)> {
+            self.board
+                .workers()
+                .iter()
+                .map(|worker| (worker.in_flight, worker.processed))
+                .collect()
+        }
+    }
+
+    fn picked(scheduler: &Scheduler, affinity: u64) -> Option<String> {
+        scheduler
+            .pick(affinity)
+            .map(|(_, worker, _)| worker.worker_id)
+    }
+
+    #[test]
+    fn test_picks_least_loaded_worker() {
+        let fixture = Fixture::new("least", &[("w1", true), ("w2", true), ("w3", true)]);
+        let scheduler = fixture.scheduler();
+        let [w1, w2, _] = &fixture.registrations[..] else {
+            unreachable!()
+        };
+        w1.start();
+        w1.start();
+        w2.start();
+        w2.complete(Duration::from_millis(1));
+        assert_eq!(fixture.load(), [(2, 0), (0, 1), (0, 0)]);
+
+        // 先比较在途数量，相同时取已处理较少者；每次选中都占用一个在途名额
+        assert_eq!(picked(&scheduler, 0).as_deref(), Some("w3"));
+        assert_eq!(fixture.load(), [(2, 0), (0, 1), (1, 0)]);
+        assert_eq!(picked(&scheduler, 0).as_deref(), Some("w2"));
+        assert_eq!(picked(&scheduler, 0).as_deref(), Some("w3"));
+        assert_eq!(fixture.load(), [(2, 0), (1, 1), (2, 0)]);
+
+        // 选中的 worker 收件管道处于背压时不派发，也不占用在途名额
+        let w2_inbox = fixture.inboxes[1].as_ref().unwrap();
+        w2_inbox.try_send(Message::init("busy".into())).unwrap();
+        assert!(w2_inbox.is_backpressured());
+        assert_eq!(picked(&scheduler, 0), None);
+        assert_eq!(fixture.load(), [(2, 0), (1, 1), (2, 0)]);
+    }
+
+    #[test]
+    fn test_affinity_sticks_and_reroutes() {
+        let mut fixture = Fixture::new("affinity", &[("w1", true), ("w2", true), ("w3", true)]);
+        let ring = AffinityRing::create(&unique_name("affinity", "ring")).unwrap();
+        let scheduler = fixture.scheduler().with_affinity(ring);
+        let key = mi7::affinity::key_hash(b"user-1");
+
+        // 相同亲和键的请求都派发给归属 worker，即使它的在途任务最多；
+        // 同一进程登记的 worker 共用 PID，环上的归属是登记表中第一个 worker
+        for _ in 0..3 {
+            assert_eq!(picked(&scheduler, key).as_deref(), Some("w1"));
+        }
+        assert_eq!(fixture.load(), [(3, 0), (0, 0), (0, 0)]);
+        let stats = scheduler.stats();
+        assert_eq!((stats.sticky, stats.rerouted), (3, 0));
+
+        // 归属 worker 暂时无法接收时按负载改派，键的归属不变
+        let w1_inbox = Arc::clone(fixture.inboxes[0].as_ref().unwrap());
+        let index = w1_inbox.hold().unwrap();
+        w1_inbox.send(index, Message::init("busy".into())).unwrap();
+        assert_eq!(picked(&scheduler, key).as_deref(), Some("w2"));
+        assert_eq!(fixture.load(), [(3, 0), (1, 0), (0, 0)]);
+        let stats = scheduler.stats();
+        assert_eq!((stats.sticky, stats.rerouted), (3, 1));
+
+        w1_inbox.receive(w1_inbox.fetch().unwrap()).unwrap();
+        assert!(!w1_inbox.is_backpressured());
+        assert_eq!(picked(&scheduler, key).as_deref(), Some("w1"));
+
+        // 归属 worker 离开后哈希环随登记表重建，键由剩余的 worker 接管并保持不变
+        fixture.registrations.remove(0);
+        for _ in 0..2 {
+            assert_eq!(picked(&scheduler, key).as_deref(), Some("w2"));
+        }
+        let stats = scheduler.stats();
+        assert_eq!((stats.sticky, stats.rerouted), (6, 1));
+
+        // 不携带亲和键的请求不经过哈希环
+        assert_eq!(picked(&scheduler, 0).as_deref(), Some("w3"));
+        assert_eq!(
+            (scheduler.stats().sticky, scheduler.stats().rerouted),
+            (6, 1)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_to_shared_pipe() {
+        // 没有 worker 登记专属收件管道
+        let fixture = Fixture::new("fallback", &[("w1", false), ("w2", false)]);
+        let scheduler = fixture.scheduler();
+        assert_eq!(picked(&scheduler, 0), None);
+
+        let err = scheduler
+            .call(Message::init("shared".into()))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("超时"), "{err}");
+        assert_eq!(fixture.shared.status().ready_count, 1);
+        assert_eq!(fixture.load(), [(0, 0), (0, 0)]);
+
+        // 没有登记表时同样经由共享请求管道
+        let without_board = Scheduler::new(
+            Arc::clone(&fixture.rpc),
+            Arc::clone(&fixture.shared),
+            None,
+            INBOX_TYPE,
+        );
+        assert!(
+            without_board
+                .call(Message::init("shared".into()))
+                .await
+                .is_err()
+        );
+        assert_eq!(fixture.shared.status().ready_count, 2);
+
+        let stats = scheduler.stats();
+        assert_eq!((stats.dispatched, stats.fallback), (0, 1));
+        assert_eq!(without_board.stats().fallback, 1);
+    }
+
+    #[tokio::test]
+    async fn test_unassigns_when_send_fails() {
+        let fixture = Fixture::new("unassign", &[("w1", true)]);
+        let scheduler = fixture.scheduler();
+        fixture.rpc.start();
+
+        // 消息超出收件管道的槽位，写入失败后撤销占用的在途名额
+        let err = scheduler
+            .call(Message::init("x".repeat(512)))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), ErrorCode::TooLarge);
+        assert_eq!(fixture.load(), [(0, 0)]);
+        assert_eq!(fixture.rpc.pending_count(), 0);
+        assert_eq!(scheduler.stats().dispatched, 0);
+
+        // 写入成功的请求保持占用，直到 worker 完成
+        let inbox = Arc::clone(fixture.inboxes[0].as_ref().unwrap());
+        let server = RpcServer::new(inbox, Arc::clone(fixture.rpc.response_pipe()));
+        let responder = tokio::spawn(async move {
+            let (message, responder) = server.next().await.unwrap();
+            responder.reply(message).await.unwrap();
+        });
+        let reply = scheduler.call(Message::init("ping".into())).await.unwrap();
+        assert_eq!(reply.data, b"ping");
+        responder.await.unwrap();
+        assert_eq!(fixture.load(), [(1, 0)]);
+
+        let stats = scheduler.stats();
+        assert_eq!((stats.dispatched, stats.fallback), (1, 0));
+        assert_eq!(stats.workers[0].dispatched, 1);
     }
 }
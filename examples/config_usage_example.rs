@@ -50,7 +50,7 @@ fn main() {
     println!("\n4. 演示动态配置扩展:");
     
     // 获取配置实例
-    let mut config_instance = config::get_config().clone();
+    let mut config_instance = (*config::get_config()).clone();
     
     // 动态添加新的配置项到 worker 段
     config_instance.set("worker", "hello", config::ConfigValue::String("world".to_string()));
@@ -75,22 +75,22 @@ fn main() {
                 println!("成功加载扩展配置");
                 
                 // 验证新添加的配置项
-                if let Some(version) = loaded_config.get("my_app", "version") {
-                    if let Some(version_str) = version.as_string() {
-                        println!("my_app.version: {}", version_str);
-                    }
+                if let Some(version) = loaded_config.get("my_app", "version")
+                    && let Some(version_str) = version.as_string()
+                {
+                    println!("my_app.version: {}", version_str);
                 }
                 
-                if let Some(max_users) = loaded_config.get("my_app", "max_users") {
-                    if let Some(max_users_int) = max_users.as_int() {
-                        println!("my_app.max_users: {}", max_users_int);
-                    }
+                if let Some(max_users) = loaded_config.get("my_app", "max_users")
+                    && let Some(max_users_int) = max_users.as_int()
+                {
+                    println!("my_app.max_users: {}", max_users_int);
                 }
                 
-                if let Some(enable_cache) = loaded_config.get("my_app", "enable_cache") {
-                    if let Some(enable_cache_bool) = enable_cache.as_bool() {
-                        println!("my_app.enable_cache: {}", enable_cache_bool);
-                    }
+                if let Some(enable_cache) = loaded_config.get("my_app", "enable_cache")
+                    && let Some(enable_cache_bool) = enable_cache.as_bool()
+                {
+                    println!("my_app.enable_cache: {}", enable_cache_bool);
                 }
             }
             Err(e) => {
@@ -0,0 +1,92 @@
+//! 伪共享对比基准
+//!
+//! 1. 两个线程分别递增相邻的两个计数器：紧密排列与 [`CachePadded`] 填充对比
+//! 2. 单生产者单消费者通过无锁管道收发消息的吞吐量（管道头部的生产、消费字段已按缓存行填充）
+//!
+//! 用 `cargo run --release -p examples --bin false_sharing_bench` 运行，可选参数为迭代次数。
+
+use anyhow::Result;
+use mi7::Message;
+use mi7::pipe::{DynCrossProcessPipe, PipeConfig};
+use mi7::shared_slot::PipeMode;
+use mi7::shm_sync::CachePadded;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// 两个计数器位于同一缓存行
+#[derive(Default)]
+struct Packed {
+    producer: AtomicU64,
+    consumer: AtomicU64,
+}
+
+/// 两个计数器各占一个缓存行
+#[derive(Default)]
+struct Padded {
+    producer: CachePadded<AtomicU64>,
+    consumer: CachePadded<AtomicU64>,
+}
+
+/// 两个线程各自递增一个计数器，返回耗时
+fn contend(producer: &AtomicU64, consumer: &AtomicU64, iterations: u64) -> Duration {
+    let start = Instant::now();
+    std::thread::scope(|scope| {
+        for counter in [producer, consumer] {
+            scope.spawn(move || {
+                for _ in 0..iterations {
+                    counter.fetch_add(1, Ordering::Relaxed);
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
+
+/// 单生产者单消费者收发 `count` 条消息，返回每秒消息数
+fn pipe_throughput(count: u64) -> Result<f64> {
+    let name = format!("mi7_false_sharing_bench_{}", std::process::id());
+    let config = PipeConfig::new(1024, 256).with_mode(PipeMode::LockFree);
+    let producer = DynCrossProcessPipe::create_with_config(&name, config)?;
+    let consumer = DynCrossProcessPipe::connect(&name)?;
+    let timeout = Duration::from_secs(5);
+
+    let start = Instant::now();
+    std::thread::scope(|scope| -> Result<()> {
+        let reader = scope.spawn(|| -> Result<()> {
+            for _ in 0..count {
+                consumer.receive_blocking(timeout)?;
+            }
+            Ok(())
+        });
+        for n in 0..count {
+            producer.send_blocking(Message::init(n.to_string()), timeout)?;
+        }
+        reader.join().unwrap()
+    })?;
+    Ok(count as f64 / start.elapsed().as_secs_f64())
+}
+
+fn main() -> Result<()> {
+    let iterations: u64 = std::env::args()
+        .nth(1)
+        .and_then(|arg| arg.parse().ok())
+        .unwrap_or(50_000_000);
+
+    println!("伪共享对比（每个线程递增 {} 次）", iterations);
+    let packed = Packed::default();
+    let packed_time = contend(&packed.producer, &packed.consumer, iterations);
+    println!("  同一缓存行:   {:?}", packed_time);
+
+    let padded = Padded::default();
+    let padded_time = contend(&padded.producer, &padded.consumer, iterations);
+    println!("  各占缓存行:   {:?}", padded_time);
+    println!(
+        "  加速比:       {:.2}x",
+        packed_time.as_secs_f64() / padded_time.as_secs_f64()
+    );
+
+    let messages = (iterations / 50).max(1000);
+    println!("\n无锁管道吞吐量（{} 条消息）", messages);
+    println!("  {:.0} 条/秒", pipe_throughput(messages)?);
+    Ok(())
+}
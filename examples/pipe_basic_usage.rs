@@ -1,7 +1,6 @@
 use anyhow::Result;
 use mi7::pipe::PipeFactory;
-use mi7::shared_slot::SlotState;
-use mi7::{CrossProcessPipe, Message};
+use mi7::Message;
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 struct TestMessage {
@@ -54,23 +53,15 @@ fn basic_send_receive_example() -> Result<()> {
     let slot_index = pipe.hold()?;
     println!("📦 获取到空槽位: {}", slot_index);
 
-    // 2. 设置槽位状态为 INPROGRESS（这是 send 方法所期望的状态）
-    pipe.set_slot_state(slot_index, SlotState::INPROGRESS)?;
-    println!("🔄 设置槽位状态为 INPROGRESS");
-
-    // 3. 发送消息到槽位
+    // 2. 发送消息到槽位（send 内部把槽位切换为 INPROGRESS）
     let request_id = pipe.send(slot_index, Message::init(message.content.clone()))?;
     println!("📤 发送消息成功，请求ID: {}", request_id);
 
-    // // 4. 接收消息
+    // // 3. 接收消息
     // let receive_index = pipe.fetch()?;
     // println!("📥 接收到消息槽位: {}", receive_index);
     //
-    // // 5. 设置槽位状态为 INPROGRESS，以便 receive 方法可以读取
-    // pipe.set_slot_state(receive_index, SlotState::INPROGRESS)?;
-    // println!("🔄 设置槽位状态为 INPROGRESS");
-    //
-    // // 6. 释放并获取消息内容
+    // // 4. 释放并获取消息内容
     // let received_message = pipe.receive(receive_index)?;
     // println!("✅ 接收到消息: {:?}", received_message);
 
@@ -60,7 +60,9 @@ fn run_writer() -> Result<()> {
         .set_count(BoxSize::Size5M, 2);  // 2个 5MB 的 box
 
     // 创建或连接到共享内存邮箱
-    let mailbox = SharedMemoryMailbox::new_shared("example_mailbox", config)?;
+    let mut mailbox = SharedMemoryMailbox::new_shared("example_mailbox", config)?;
+    // 写入进程退出后保留共享内存，交给读取进程处理
+    mailbox.persist();
     println!("✅ 共享内存邮箱创建/连接成功");
 
     // 显示初始统计信息
@@ -68,7 +70,7 @@ fn run_writer() -> Result<()> {
     println!("📊 初始统计: {:?}", initial_stats);
 
     // 写入一些示例数据
-    let messages = vec![
+    let messages = [
         ("Hello from writer!", BoxSize::Size1M),
         ("This is a longer message that demonstrates the shared memory functionality.", BoxSize::Size1M),
         ("Medium sized message for 2MB box.", BoxSize::Size2M),
@@ -147,7 +149,10 @@ fn run_reader() -> Result<()> {
     let final_stats = mailbox.get_stats();
     println!("📊 读取完成后统计: {:?}", final_stats);
     println!("🎉 读取进程完成，共读取 {} 条消息", read_count);
-    
+
+    // 读取完毕，删除共享内存
+    mailbox.destroy()?;
+
     Ok(())
 }
 
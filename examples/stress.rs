@@ -0,0 +1,339 @@
+//! 多进程压力测试：生产者 / 消费者进程随机被 SIGKILL，结束后校验消息与槽位
+//!
+//! 主进程创建管道并以子进程方式启动 N 个生产者与 M 个消费者（重新执行自身），
+//! 运行期间每隔一段时间随机 SIGKILL 一个子进程并启动同角色的替代者，
+//! 同时回收被杀进程遗留的槽位租约。结束后停止子进程、排空管道并校验：
+//!
+//! - 没有消息被重复读取
+//! - 丢失的消息不超过被杀消费者的数量（取走槽位或读取后尚未记账即被杀）
+//! - 来源未记账的消息不超过被杀生产者的数量（写入后尚未记账即被杀）
+//! - 所有槽位回到 EMPTY，没有泄漏
+//!
+//! 生产者与消费者把每条消息追加到各自的记账文件，主进程据此核对。
+//!
+//! ```bash
+//! cargo run --release -p examples --bin stress -- \
+//!     --producers 4 --consumers 4 --duration 30 --kill-interval 200 --mode lockfree
+//! ```
+
+use anyhow::{Context, Result, anyhow};
+use mi7::Message;
+use mi7::pipe::{DynCrossProcessPipe, PipeConfig};
+use mi7::shared_slot::PipeMode;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// 子进程收发的超时，超时后检查停止标志
+const POLL: Duration = Duration::from_millis(100);
+
+/// 运行期间回收槽位租约的超时，远大于正常的收发耗时
+const LEASE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// 命令行参数
+struct Options {
+    producers: usize,
+    consumers: usize,
+    duration: Duration,
+    kill_interval: Duration,
+    capacity: usize,
+    mode: PipeMode,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self> {
+        let mut options = Self {
+            producers: 4,
+            consumers: 4,
+            duration: Duration::from_secs(10),
+            kill_interval: Duration::from_millis(200),
+            capacity: 64,
+            mode: PipeMode::Locked,
+        };
+        let mut args = args.iter();
+        while let Some(key) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| anyhow!("参数 {} 缺少取值", key))?;
+            match key.as_str() {
+                "--producers" => options.producers = value.parse()?,
+                "--consumers" => options.consumers = value.parse()?,
+                "--duration" => options.duration = Duration::from_secs(value.parse()?),
+                "--kill-interval" => options.kill_interval = Duration::from_millis(value.parse()?),
+                "--capacity" => options.capacity = value.parse()?,
+                "--mode" => {
+                    options.mode = match value.as_str() {
+                        "locked" => PipeMode::Locked,
+                        "lockfree" => PipeMode::LockFree,
+                        other => return Err(anyhow!("未知的并发模式: {}", other)),
+                    }
+                }
+                other => return Err(anyhow!("未知参数: {}", other)),
+            }
+        }
+        if options.producers == 0 || options.consumers == 0 {
+            return Err(anyhow!("生产者与消费者数量不能为0"));
+        }
+        Ok(options)
+    }
+}
+
+/// 子进程角色
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Role {
+    Producer,
+    Consumer,
+}
+
+impl Role {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Role::Producer => "producer",
+            Role::Consumer => "consumer",
+        }
+    }
+}
+
+/// 简单的 xorshift 随机数，只用于挑选被杀的子进程
+struct Rng(u64);
+
+impl Rng {
+    fn new() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default();
+        Self(seed | 1)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
+
+/// 记账文件：每行一条消息，单次 `write` 追加，进程被杀时不会留下半行
+fn open_ledger(dir: &Path, role: Role, id: usize) -> Result<File> {
+    let path = dir.join(format!("{}_{}.log", role.as_str(), id));
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("打开记账文件 {} 失败", path.display()))
+}
+
+/// 生产者：持续发送 `p<id>:<seq>`，发送成功后记账
+fn run_producer(name: &str, dir: &Path, id: usize) -> Result<()> {
+    let pipe = DynCrossProcessPipe::connect(name)?;
+    let mut ledger = open_ledger(dir, Role::Producer, id)?;
+    let stop = dir.join("stop");
+    let mut seq = 0u64;
+    while !stop.exists() {
+        let key = format!("p{}:{}", id, seq);
+        if pipe.send_blocking(Message::init(key.clone()), POLL).is_ok() {
+            ledger.write_all(format!("{}\n", key).as_bytes())?;
+            seq += 1;
+        }
+    }
+    Ok(())
+}
+
+/// 消费者：持续接收，读取后记账
+fn run_consumer(name: &str, dir: &Path, id: usize) -> Result<()> {
+    let pipe = DynCrossProcessPipe::connect(name)?;
+    let mut ledger = open_ledger(dir, Role::Consumer, id)?;
+    let stop = dir.join("stop");
+    while !stop.exists() {
+        if let Ok(message) = pipe.receive_blocking(POLL) {
+            ledger.write_all(&message.data)?;
+            ledger.write_all(b"\n")?;
+        }
+    }
+    Ok(())
+}
+
+/// 读取某一角色所有记账文件中的消息
+fn read_ledgers(dir: &Path, role: Role) -> Result<Vec<String>> {
+    let mut keys = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_ledger = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with(role.as_str()));
+        if is_ledger {
+            keys.extend(fs::read_to_string(&path)?.lines().map(str::to_string));
+        }
+    }
+    Ok(keys)
+}
+
+/// 主进程：启动、随机杀死并替换子进程，结束后校验
+struct Harness {
+    name: String,
+    dir: PathBuf,
+    exe: PathBuf,
+    next_id: usize,
+    children: Vec<(Role, Child)>,
+    killed: HashMap<Role, usize>,
+}
+
+impl Harness {
+    fn spawn(&mut self, role: Role) -> Result<()> {
+        let child = Command::new(&self.exe)
+            .arg(role.as_str())
+            .arg(&self.name)
+            .arg(&self.dir)
+            .arg(self.next_id.to_string())
+            .spawn()
+            .with_context(|| format!("启动{}进程失败", role.as_str()))?;
+        self.next_id += 1;
+        self.children.push((role, child));
+        Ok(())
+    }
+
+    fn kill_random(&mut self, rng: &mut Rng) -> Result<()> {
+        let (role, mut child) = self.children.swap_remove(rng.below(self.children.len()));
+        child.kill()?;
+        child.wait()?;
+        *self.killed.entry(role).or_default() += 1;
+        self.spawn(role)
+    }
+
+    /// 通知子进程停止并等待退出，超时未退出的按被杀处理
+    fn stop(&mut self) -> Result<()> {
+        File::create(self.dir.join("stop"))?;
+        let deadline = Instant::now() + POLL * 20;
+        for (role, mut child) in self.children.drain(..) {
+            while child.try_wait()?.is_none() && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            if child.try_wait()?.is_none() {
+                child.kill()?;
+                child.wait()?;
+                *self.killed.entry(role).or_default() += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn run(options: &Options) -> Result<()> {
+    let name = format!("mi7_stress_{}", std::process::id());
+    let dir = std::env::temp_dir().join(&name);
+    fs::create_dir_all(&dir)?;
+
+    let config = PipeConfig::new(options.capacity, 256).with_mode(options.mode);
+    let pipe = DynCrossProcessPipe::create_with_config(&name, config)?;
+    let mut harness = Harness {
+        name: name.clone(),
+        dir: dir.clone(),
+        exe: std::env::current_exe()?,
+        next_id: 0,
+        children: Vec::new(),
+        killed: HashMap::new(),
+    };
+    for _ in 0..options.producers {
+        harness.spawn(Role::Producer)?;
+    }
+    for _ in 0..options.consumers {
+        harness.spawn(Role::Consumer)?;
+    }
+
+    let mut rng = Rng::new();
+    let start = Instant::now();
+    let mut reclaimed = 0;
+    while start.elapsed() < options.duration {
+        std::thread::sleep(options.kill_interval);
+        harness.kill_random(&mut rng)?;
+        reclaimed += pipe.reclaim_stuck(LEASE_TIMEOUT);
+    }
+    harness.stop()?;
+
+    // 所有子进程已退出：回收遗留的租约并排空管道
+    reclaimed += pipe.reclaim_stuck(Duration::ZERO);
+    let mut received = read_ledgers(&dir, Role::Consumer)?;
+    while let Ok(message) = pipe.receive_blocking(Duration::ZERO) {
+        received.push(String::from_utf8(message.data)?);
+    }
+    let sent: HashSet<String> = read_ledgers(&dir, Role::Producer)?.into_iter().collect();
+    let status = pipe.status();
+
+    let killed_producers = harness.killed.get(&Role::Producer).copied().unwrap_or(0);
+    let killed_consumers = harness.killed.get(&Role::Consumer).copied().unwrap_or(0);
+    let unique: HashSet<&String> = received.iter().collect();
+    let duplicated = received.len() - unique.len();
+    let lost = sent.iter().filter(|key| !unique.contains(key)).count();
+    let unlogged = unique.iter().filter(|key| !sent.contains(**key)).count();
+
+    println!("运行 {:?}，模式 {:?}", options.duration, options.mode);
+    println!(
+        "  被杀进程: 生产者 {}，消费者 {}",
+        killed_producers, killed_consumers
+    );
+    println!(
+        "  发送 {}，接收 {}，回收槽位 {}",
+        sent.len(),
+        received.len(),
+        reclaimed
+    );
+    println!(
+        "  重复 {}，丢失 {}，来源未记账 {}",
+        duplicated, lost, unlogged
+    );
+
+    let mut violations = Vec::new();
+    if duplicated > 0 {
+        violations.push(format!("{} 条消息被重复读取", duplicated));
+    }
+    if lost > killed_consumers {
+        violations.push(format!(
+            "丢失 {} 条消息，超过被杀消费者数量 {}",
+            lost, killed_consumers
+        ));
+    }
+    if unlogged > killed_producers {
+        violations.push(format!(
+            "{} 条消息来源未记账，超过被杀生产者数量 {}",
+            unlogged, killed_producers
+        ));
+    }
+    if status.empty_count != status.capacity {
+        violations.push(format!(
+            "槽位泄漏: {} 个槽位未回到 EMPTY ({:?})",
+            status.capacity - status.empty_count,
+            status
+        ));
+    }
+
+    fs::remove_dir_all(&dir)?;
+    if violations.is_empty() {
+        println!("通过");
+        Ok(())
+    } else {
+        Err(anyhow!("校验失败:\n  {}", violations.join("\n  ")))
+    }
+}
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some(role @ ("producer" | "consumer")) => {
+            let [_, name, dir, id] = args.as_slice() else {
+                return Err(anyhow!("子进程参数: <role> <管道名> <记账目录> <id>"));
+            };
+            let id = id.parse()?;
+            if role == "producer" {
+                run_producer(name, Path::new(dir), id)
+            } else {
+                run_consumer(name, Path::new(dir), id)
+            }
+        }
+        _ => run(&Options::parse(&args)?),
+    }
+}
@@ -0,0 +1,17 @@
+//! 构建时用 cbindgen 重新生成 `include/mi7.h`，内容不变时不会改写文件
+
+fn main() {
+    let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+
+    // 只解析本 crate 的源文件，不需要 cargo metadata
+    let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", crate_dir))
+        .expect("读取 cbindgen.toml 失败");
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(format!("{}/src/lib.rs", crate_dir))
+        .generate()
+        .expect("生成 C 头文件失败")
+        .write_to_file(format!("{}/include/mi7.h", crate_dir));
+}
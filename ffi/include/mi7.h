@@ -0,0 +1,106 @@
+/* mi7 共享内存管道的 C 接口，由 cbindgen 生成，请勿手工修改 */
+
+#ifndef MI7_H
+#define MI7_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// 成功
+#define MI7_OK 0
+
+// 参数无效（空指针、名称不是合法的 UTF-8 等）
+#define MI7_ERR_INVALID_ARGUMENT -1
+
+// 阻塞收发超时
+#define MI7_ERR_TIMEOUT -2
+
+// 管道操作失败，详见 `mi7_last_error`
+#define MI7_ERR_PIPE -3
+
+// 库内部发生 panic，已被拦截
+#define MI7_ERR_PANIC -4
+
+// 管道句柄，由 `mi7_pipe_create` / `mi7_pipe_connect` 返回，`mi7_pipe_close` 释放
+typedef struct Mi7Pipe Mi7Pipe;
+
+// 接收到的消息，`data` 由本库分配，用 `mi7_message_free` 释放
+typedef struct Mi7Message {
+  // 消息标志
+  uint8_t flag;
+  // 消息数据，没有数据时为 NULL
+  uint8_t *data;
+  // 数据长度（字节）
+  size_t len;
+  // 发送时间（UNIX 秒）
+  uint64_t timestamp;
+  // 过期时间（UNIX 毫秒），0 表示不过期
+  uint64_t expires_at;
+} Mi7Message;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// 创建管道（`capacity` 个槽位，每个 `slot_size` 字节），失败时返回 NULL
+//
+// 创建方关闭句柄时删除共享内存。
+//
+// # Safety
+// `name` 必须指向以 NUL 结尾的字符串
+struct Mi7Pipe *mi7_pipe_create(const char *name, size_t capacity, size_t slot_size);
+
+// 连接到已存在的管道，容量与槽位大小以共享内存头部记录的为准，失败时返回 NULL
+//
+// # Safety
+// `name` 必须指向以 NUL 结尾的字符串
+struct Mi7Pipe *mi7_pipe_connect(const char *name);
+
+// 发送一条消息，队列满时最多等待 `timeout_ms` 毫秒
+//
+// # Safety
+// `pipe` 必须是未关闭的句柄；`len` 不为 0 时 `data` 必须指向至少 `len` 字节
+int mi7_pipe_send(const struct Mi7Pipe *pipe,
+                  uint8_t flag,
+                  const uint8_t *data,
+                  size_t len,
+                  uint32_t timeout_ms);
+
+// 接收一条消息写入 `out`，队列空时最多等待 `timeout_ms` 毫秒
+//
+// 成功后 `out->data` 需要用 `mi7_message_free` 释放；失败时不修改 `out`。
+//
+// # Safety
+// `pipe` 必须是未关闭的句柄，`out` 必须指向可写的 `Mi7Message`
+int mi7_pipe_receive(const struct Mi7Pipe *pipe, struct Mi7Message *out, uint32_t timeout_ms);
+
+// 释放 `mi7_pipe_receive` 分配的消息数据，之后 `data` 置为 NULL，重复释放无害
+//
+// # Safety
+// `message` 为 NULL 或指向由 `mi7_pipe_receive` 填充的消息
+void mi7_message_free(struct Mi7Message *message);
+
+// 关闭管道句柄；创建方关闭时删除共享内存。`pipe` 为 NULL 时什么也不做
+//
+// # Safety
+// `pipe` 为 NULL 或未关闭的句柄，且没有其他线程仍在使用
+void mi7_pipe_close(struct Mi7Pipe *pipe);
+
+// 当前线程最近一次失败的错误描述，没有错误时返回 NULL
+//
+// 返回的字符串归本库所有，在当前线程下一次失败之前有效。
+const char *mi7_last_error(void);
+
+// 当前线程最近一次失败的稳定错误码（见 `mi7::ErrorCode`），参数无效或 panic 时为 0
+//
+// 千位表示类别：1 资源、2 数据损坏、3 超时、4 容量、5 系统 I/O，9999 为未归类错误。
+uint16_t mi7_last_error_code(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* MI7_H */
@@ -0,0 +1,332 @@
+//! mi7 共享内存管道的 C 接口
+//!
+//! 同一主机上的 C / C++ 服务通过这些函数与 Rust 进程共用一条 [`DynCrossProcessPipe`]，
+//! 头文件 `include/mi7.h` 由 cbindgen 在构建时生成。
+//!
+//! 约定：
+//! - 返回 `int` 的函数以 [`MI7_OK`] 表示成功，失败返回负的错误码
+//! - 失败时 [`mi7_last_error`] 返回当前线程最近一次的错误描述，[`mi7_last_error_code`]
+//!   返回对应的稳定错误码（[`mi7::ErrorCode`]）
+//! - [`mi7_pipe_receive`] 填充的消息数据由本库分配，必须用 [`mi7_message_free`] 释放
+//! - 同一个句柄可以被多个线程同时使用，[`mi7_pipe_close`] 之后不得再使用
+//!
+//! ```c
+//! Mi7Pipe *pipe = mi7_pipe_connect("mi7_pipe");
+//! if (pipe == NULL) {
+//!     fprintf(stderr, "%s\n", mi7_last_error());
+//! }
+//! mi7_pipe_send(pipe, 0, (const uint8_t *)"hello", 5, 1000);
+//! mi7_pipe_close(pipe);
+//! ```
+
+#[cfg(feature = "python")]
+mod python;
+
+use mi7::pipe::{DynCrossProcessPipe, PipeConfig};
+use mi7::{ErrorCode, Message};
+use std::cell::{Cell, RefCell};
+use std::ffi::{CStr, CString, c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::time::Duration;
+
+/// 成功
+pub const MI7_OK: c_int = 0;
+/// 参数无效（空指针、名称不是合法的 UTF-8 等）
+pub const MI7_ERR_INVALID_ARGUMENT: c_int = -1;
+/// 阻塞收发超时
+pub const MI7_ERR_TIMEOUT: c_int = -2;
+/// 管道操作失败，详见 `mi7_last_error`
+pub const MI7_ERR_PIPE: c_int = -3;
+/// 库内部发生 panic，已被拦截
+pub const MI7_ERR_PANIC: c_int = -4;
+
+/// 管道句柄，由 `mi7_pipe_create` / `mi7_pipe_connect` 返回，`mi7_pipe_close` 释放
+pub struct Mi7Pipe {
+    pipe: DynCrossProcessPipe,
+}
+
+/// 接收到的消息，`data` 由本库分配，用 `mi7_message_free` 释放
+#[repr(C)]
+pub struct Mi7Message {
+    /// 消息标志
+    pub flag: u8,
+    /// 消息数据，没有数据时为 NULL
+    pub data: *mut u8,
+    /// 数据长度（字节）
+    pub len: usize,
+    /// 发送时间（UNIX 秒）
+    pub timestamp: u64,
+    /// 过期时间（UNIX 毫秒），0 表示不过期
+    pub expires_at: u64,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+    static LAST_ERROR_CODE: Cell<u16> = const { Cell::new(0) };
+}
+
+fn set_last_error(message: impl ToString) {
+    set_last_error_with_code(message, 0);
+}
+
+fn set_last_error_with_code(message: impl ToString, code: u16) {
+    // 错误描述中不会出现 NUL，万一出现则截断
+    let mut bytes = message.to_string().into_bytes();
+    if let Some(end) = bytes.iter().position(|&b| b == 0) {
+        bytes.truncate(end);
+    }
+    let message = CString::new(bytes).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+    LAST_ERROR_CODE.set(code);
+}
+
+/// 执行 `f` 并把错误与 panic 转换为错误码
+fn guard(f: impl FnOnce() -> Result<(), c_int>) -> c_int {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => MI7_OK,
+        Ok(Err(code)) => code,
+        Err(_) => {
+            set_last_error("mi7 内部发生 panic");
+            MI7_ERR_PANIC
+        }
+    }
+}
+
+fn invalid_argument(message: &str) -> c_int {
+    set_last_error(message);
+    MI7_ERR_INVALID_ARGUMENT
+}
+
+fn pipe_error(e: mi7::Error) -> c_int {
+    let code = match e.code() {
+        ErrorCode::SendTimeout | ErrorCode::ReceiveTimeout => MI7_ERR_TIMEOUT,
+        _ => MI7_ERR_PIPE,
+    };
+    set_last_error_with_code(format!("{:#}", e), e.code().as_u16());
+    code
+}
+
+/// # Safety
+/// `name` 为 NULL 或指向以 NUL 结尾的字符串
+unsafe fn pipe_name<'a>(name: *const c_char) -> Result<&'a str, c_int> {
+    if name.is_null() {
+        return Err(invalid_argument("管道名称为 NULL"));
+    }
+    unsafe { CStr::from_ptr(name) }
+        .to_str()
+        .map_err(|_| invalid_argument("管道名称不是合法的 UTF-8"))
+}
+
+/// 打开管道句柄，失败时返回 NULL
+fn open_pipe(open: impl FnOnce() -> Result<DynCrossProcessPipe, c_int>) -> *mut Mi7Pipe {
+    let mut handle = ptr::null_mut();
+    guard(|| {
+        let pipe = open()?;
+        handle = Box::into_raw(Box::new(Mi7Pipe { pipe }));
+        Ok(())
+    });
+    handle
+}
+
+/// 创建管道（`capacity` 个槽位，每个 `slot_size` 字节），失败时返回 NULL
+///
+/// 创建方关闭句柄时删除共享内存。
+///
+/// # Safety
+/// `name` 必须指向以 NUL 结尾的字符串
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mi7_pipe_create(
+    name: *const c_char,
+    capacity: usize,
+    slot_size: usize,
+) -> *mut Mi7Pipe {
+    open_pipe(|| {
+        let name = unsafe { pipe_name(name) }?;
+        DynCrossProcessPipe::create_with_config(name, PipeConfig::new(capacity, slot_size))
+            .map_err(pipe_error)
+    })
+}
+
+/// 连接到已存在的管道，容量与槽位大小以共享内存头部记录的为准，失败时返回 NULL
+///
+/// # Safety
+/// `name` 必须指向以 NUL 结尾的字符串
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mi7_pipe_connect(name: *const c_char) -> *mut Mi7Pipe {
+    open_pipe(|| {
+        let name = unsafe { pipe_name(name) }?;
+        DynCrossProcessPipe::connect(name).map_err(pipe_error)
+    })
+}
+
+/// 发送一条消息，队列满时最多等待 `timeout_ms` 毫秒
+///
+/// # Safety
+/// `pipe` 必须是未关闭的句柄；`len` 不为 0 时 `data` 必须指向至少 `len` 字节
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mi7_pipe_send(
+    pipe: *const Mi7Pipe,
+    flag: u8,
+    data: *const u8,
+    len: usize,
+    timeout_ms: u32,
+) -> c_int {
+    guard(|| {
+        let Some(pipe) = (unsafe { pipe.as_ref() }) else {
+            return Err(invalid_argument("管道句柄为 NULL"));
+        };
+        let data = match (data.is_null(), len) {
+            (_, 0) => Vec::new(),
+            (true, _) => return Err(invalid_argument("消息数据为 NULL")),
+            (false, _) => unsafe { std::slice::from_raw_parts(data, len) }.to_vec(),
+        };
+        let message = Message {
+            flag,
+            data,
+            ..Message::init(String::new())
+        };
+        pipe.pipe
+            .send_blocking(message, Duration::from_millis(timeout_ms.into()))
+            .map(|_| ())
+            .map_err(pipe_error)
+    })
+}
+
+/// 接收一条消息写入 `out`，队列空时最多等待 `timeout_ms` 毫秒
+///
+/// 成功后 `out->data` 需要用 `mi7_message_free` 释放；失败时不修改 `out`。
+///
+/// # Safety
+/// `pipe` 必须是未关闭的句柄，`out` 必须指向可写的 `Mi7Message`
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mi7_pipe_receive(
+    pipe: *const Mi7Pipe,
+    out: *mut Mi7Message,
+    timeout_ms: u32,
+) -> c_int {
+    guard(|| {
+        let Some(pipe) = (unsafe { pipe.as_ref() }) else {
+            return Err(invalid_argument("管道句柄为 NULL"));
+        };
+        if out.is_null() {
+            return Err(invalid_argument("输出消息为 NULL"));
+        }
+        let message = pipe
+            .pipe
+            .receive_blocking(Duration::from_millis(timeout_ms.into()))
+            .map_err(pipe_error)?;
+
+        let len = message.data.len();
+        let data = if len == 0 {
+            ptr::null_mut()
+        } else {
+            Box::into_raw(message.data.into_boxed_slice()).cast::<u8>()
+        };
+        unsafe {
+            out.write(Mi7Message {
+                flag: message.flag,
+                data,
+                len,
+                timestamp: message.timestamp,
+                expires_at: message.expires_at,
+            })
+        };
+        Ok(())
+    })
+}
+
+/// 释放 `mi7_pipe_receive` 分配的消息数据，之后 `data` 置为 NULL，重复释放无害
+///
+/// # Safety
+/// `message` 为 NULL 或指向由 `mi7_pipe_receive` 填充的消息
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mi7_message_free(message: *mut Mi7Message) {
+    let Some(message) = (unsafe { message.as_mut() }) else {
+        return;
+    };
+    if !message.data.is_null() {
+        let data = ptr::slice_from_raw_parts_mut(message.data, message.len);
+        drop(unsafe { Box::from_raw(data) });
+    }
+    message.data = ptr::null_mut();
+    message.len = 0;
+}
+
+/// 关闭管道句柄；创建方关闭时删除共享内存。`pipe` 为 NULL 时什么也不做
+///
+/// # Safety
+/// `pipe` 为 NULL 或未关闭的句柄，且没有其他线程仍在使用
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mi7_pipe_close(pipe: *mut Mi7Pipe) {
+    if !pipe.is_null() {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(unsafe { Box::from_raw(pipe) })));
+    }
+}
+
+/// 当前线程最近一次失败的错误描述，没有错误时返回 NULL
+///
+/// 返回的字符串归本库所有，在当前线程下一次失败之前有效。
+#[unsafe(no_mangle)]
+pub extern "C" fn mi7_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// 当前线程最近一次失败的稳定错误码（见 `mi7::ErrorCode`），参数无效或 panic 时为 0
+///
+/// 千位表示类别：1 资源、2 数据损坏、3 超时、4 容量、5 系统 I/O，9999 为未归类错误。
+#[unsafe(no_mangle)]
+pub extern "C" fn mi7_last_error_code() -> u16 {
+    LAST_ERROR_CODE.get()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_c_api_round_trip() {
+        let name = CString::new(format!("mi7_ffi_test_{}", std::process::id())).unwrap();
+        unsafe {
+            let producer = mi7_pipe_create(name.as_ptr(), 4, 256);
+            assert!(!producer.is_null());
+            let consumer = mi7_pipe_connect(name.as_ptr());
+            assert!(!consumer.is_null());
+
+            let payload = b"hello from c";
+            assert_eq!(
+                mi7_pipe_send(producer, 7, payload.as_ptr(), payload.len(), 100),
+                MI7_OK
+            );
+
+            let mut message = std::mem::zeroed::<Mi7Message>();
+            assert_eq!(mi7_pipe_receive(consumer, &mut message, 100), MI7_OK);
+            assert_eq!(message.flag, 7);
+            assert_eq!(
+                std::slice::from_raw_parts(message.data, message.len),
+                payload
+            );
+            mi7_message_free(&mut message);
+            assert!(message.data.is_null());
+
+            // 队列已空：超时返回独立的错误码，并记录错误描述
+            assert_eq!(
+                mi7_pipe_receive(consumer, &mut message, 10),
+                MI7_ERR_TIMEOUT
+            );
+            assert!(!mi7_last_error().is_null());
+            assert_eq!(mi7_last_error_code(), ErrorCode::ReceiveTimeout.as_u16());
+            assert_eq!(
+                mi7_pipe_send(ptr::null(), 0, ptr::null(), 0, 0),
+                MI7_ERR_INVALID_ARGUMENT
+            );
+
+            mi7_pipe_close(consumer);
+            mi7_pipe_close(producer);
+        }
+    }
+}
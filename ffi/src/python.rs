@@ -0,0 +1,237 @@
+//! Python 绑定（`python` 特性）：`mi7_ipc` 模块
+//!
+//! Python 进程直接从共享内存管道收发消息，不必再经过 HTTP。阻塞调用期间释放 GIL，
+//! `*_async` 方法返回 asyncio 可等待对象，由内部的 tokio 运行时驱动。
+//!
+//! ```python
+//! import mi7_ipc
+//!
+//! pipe = mi7_ipc.Pipe.connect("mi7_pipe")
+//! pipe.send(b"hello", timeout=1.0)
+//! message = await pipe.receive_async(timeout=5.0)
+//! print(message.flag, message.data)
+//! ```
+//!
+//! 超时抛出 `TimeoutError`，其他失败抛出 `mi7_ipc.Mi7Error`。
+
+use mi7::Message;
+use mi7::pipe::{DynCrossProcessPipe, MessageExpired, PipeConfig, PipeTimeout};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyTimeoutError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use std::sync::Arc;
+use std::time::Duration;
+
+create_exception!(mi7_ipc, Mi7Error, PyException, "mi7 管道操作失败");
+
+/// 阻塞调用未指定超时时的等待时长
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+fn to_py_err(e: mi7::Error) -> PyErr {
+    if e.is::<PipeTimeout>() {
+        PyTimeoutError::new_err(e.to_string())
+    } else {
+        Mi7Error::new_err(format!("{:#}", e))
+    }
+}
+
+fn timeout_from(seconds: Option<f64>) -> PyResult<Duration> {
+    match seconds {
+        None => Ok(DEFAULT_TIMEOUT),
+        Some(seconds) => Duration::try_from_secs_f64(seconds)
+            .map_err(|_| Mi7Error::new_err(format!("无效的超时: {}", seconds))),
+    }
+}
+
+/// 接收到的消息
+#[pyclass(name = "Message", module = "mi7_ipc", frozen)]
+struct PyMessage {
+    #[pyo3(get)]
+    flag: u8,
+    data: Vec<u8>,
+    /// 发送时间（UNIX 秒）
+    #[pyo3(get)]
+    timestamp: u64,
+    /// 过期时间（UNIX 毫秒），0 表示不过期
+    #[pyo3(get)]
+    expires_at: u64,
+}
+
+#[pymethods]
+impl PyMessage {
+    #[getter]
+    fn data<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.data)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Message(flag={}, len={})", self.flag, self.data.len())
+    }
+}
+
+impl From<Message> for PyMessage {
+    fn from(message: Message) -> Self {
+        Self {
+            flag: message.flag,
+            data: message.data,
+            timestamp: message.timestamp,
+            expires_at: message.expires_at,
+        }
+    }
+}
+
+/// 共享内存管道句柄，创建方关闭（或被回收）时删除共享内存
+#[pyclass(name = "Pipe", module = "mi7_ipc")]
+struct PyPipe {
+    pipe: Option<Arc<DynCrossProcessPipe>>,
+}
+
+impl PyPipe {
+    fn pipe(&self) -> PyResult<Arc<DynCrossProcessPipe>> {
+        self.pipe
+            .clone()
+            .ok_or_else(|| Mi7Error::new_err("管道已关闭"))
+    }
+}
+
+fn message_from(data: Vec<u8>, flag: u8) -> Message {
+    Message {
+        flag,
+        data,
+        ..Message::init(String::new())
+    }
+}
+
+/// 异步接收：在通知 FIFO 上等待，不占用运行时线程，跳过已过期的消息
+async fn receive_async(pipe: &DynCrossProcessPipe) -> mi7::Result<Message> {
+    loop {
+        let index = pipe.fetch_async().await?;
+        match pipe.receive(index) {
+            Err(e) if e.is::<MessageExpired>() => continue,
+            received => return received,
+        }
+    }
+}
+
+#[pymethods]
+impl PyPipe {
+    /// 创建管道
+    #[staticmethod]
+    #[pyo3(signature = (name, capacity = 64, slot_size = 4096))]
+    fn create(py: Python<'_>, name: &str, capacity: usize, slot_size: usize) -> PyResult<Self> {
+        let config = PipeConfig::new(capacity, slot_size);
+        let pipe = py
+            .allow_threads(|| DynCrossProcessPipe::create_with_config(name, config))
+            .map_err(to_py_err)?;
+        Ok(Self {
+            pipe: Some(Arc::new(pipe)),
+        })
+    }
+
+    /// 连接到已存在的管道
+    #[staticmethod]
+    fn connect(py: Python<'_>, name: &str) -> PyResult<Self> {
+        let pipe = py
+            .allow_threads(|| DynCrossProcessPipe::connect(name))
+            .map_err(to_py_err)?;
+        Ok(Self {
+            pipe: Some(Arc::new(pipe)),
+        })
+    }
+
+    /// 发送消息，队列满时最多等待 `timeout` 秒，返回 request_id
+    #[pyo3(signature = (data, flag = 0, timeout = None))]
+    fn send(&self, py: Python<'_>, data: Vec<u8>, flag: u8, timeout: Option<f64>) -> PyResult<u64> {
+        let pipe = self.pipe()?;
+        let timeout = timeout_from(timeout)?;
+        py.allow_threads(|| pipe.send_blocking(message_from(data, flag), timeout))
+            .map_err(to_py_err)
+    }
+
+    /// 接收消息，队列空时最多等待 `timeout` 秒
+    #[pyo3(signature = (timeout = None))]
+    fn receive(&self, py: Python<'_>, timeout: Option<f64>) -> PyResult<PyMessage> {
+        let pipe = self.pipe()?;
+        let timeout = timeout_from(timeout)?;
+        py.allow_threads(|| pipe.receive_blocking(timeout))
+            .map(PyMessage::from)
+            .map_err(to_py_err)
+    }
+
+    /// 异步发送，返回可等待对象；等待空槽位在运行时的阻塞线程池中进行
+    #[pyo3(signature = (data, flag = 0, timeout = None))]
+    fn send_async<'py>(
+        &self,
+        py: Python<'py>,
+        data: Vec<u8>,
+        flag: u8,
+        timeout: Option<f64>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let pipe = self.pipe()?;
+        let timeout = timeout_from(timeout)?;
+        pyo3_async_runtimes::tokio::future_into_py(py, async move {
+            tokio::task::spawn_blocking(move || {
+                pipe.send_blocking(message_from(data, flag), timeout)
+            })
+            .await
+            .map_err(|e| Mi7Error::new_err(e.to_string()))?
+            .map_err(to_py_err)
+        })
+    }
+
+    /// 异步接收，返回可等待对象；`timeout` 为 None 时一直等待
+    #[pyo3(signature = (timeout = None))]
+    fn receive_async<'py>(
+        &self,
+        py: Python<'py>,
+        timeout: Option<f64>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let pipe = self.pipe()?;
+        let limit = timeout
+            .map(|seconds| timeout_from(Some(seconds)))
+            .transpose()?;
+        pyo3_async_runtimes::tokio::future_into_py(py, async move {
+            let received = match limit {
+                Some(limit) => tokio::time::timeout(limit, receive_async(&pipe))
+                    .await
+                    .unwrap_or_else(|_| Err(PipeTimeout::Receive(limit).into())),
+                None => receive_async(&pipe).await,
+            };
+            received.map(PyMessage::from).map_err(to_py_err)
+        })
+    }
+
+    /// 管道名称
+    #[getter]
+    fn name(&self) -> PyResult<String> {
+        Ok(self.pipe()?.name().to_string())
+    }
+
+    /// 关闭句柄；仍在进行的异步调用完成后才真正释放
+    fn close(&mut self) {
+        self.pipe = None;
+    }
+
+    fn __enter__(slf: Py<Self>) -> Py<Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        _exc_type: PyObject,
+        _exc_value: PyObject,
+        _traceback: PyObject,
+    ) -> bool {
+        self.close();
+        false
+    }
+}
+
+#[pymodule]
+fn mi7_ipc(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyPipe>()?;
+    m.add_class::<PyMessage>()?;
+    m.add("Mi7Error", m.py().get_type::<Mi7Error>())?;
+    Ok(())
+}
@@ -0,0 +1,36 @@
+//! 各编解码方式的消息编码与解码开销
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use mi7::{CodecKind, Message};
+use std::hint::black_box;
+
+fn codecs(c: &mut Criterion) {
+    let mut group = c.benchmark_group("codec");
+    for len in [64, 4096] {
+        let message = Message::init("x".repeat(len));
+        // JSON 把数据编码为数字数组，预留足够的空间
+        let mut buf = vec![0u8; len * 8 + 1024];
+        group.throughput(Throughput::Bytes(len as u64));
+
+        for kind in [CodecKind::Bincode, CodecKind::Json, CodecKind::Raw] {
+            let codec = kind.codec();
+            group.bench_with_input(
+                BenchmarkId::new(format!("encode_{}", kind), len),
+                &message,
+                |b, message| b.iter(|| codec.encode(black_box(message), &mut buf).unwrap()),
+            );
+
+            let written = codec.encode(&message, &mut buf).unwrap();
+            let encoded = buf[..written].to_vec();
+            group.bench_with_input(
+                BenchmarkId::new(format!("decode_{}", kind), len),
+                &encoded,
+                |b, encoded| b.iter(|| codec.decode(black_box(encoded)).unwrap()),
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, codecs);
+criterion_main!(benches);
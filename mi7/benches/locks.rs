@@ -0,0 +1,49 @@
+//! 锁的无竞争加锁/解锁开销对比，以 `std::sync::Mutex` 为基线
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use mi7::shm_sync::ShmMutex;
+use mi7::{IpcRwLock, IpcSemaphore, SeqLock};
+use std::hint::black_box;
+use std::sync::Mutex;
+
+fn locks(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lock");
+    let suffix = std::process::id();
+
+    let mutex = Mutex::new(0u64);
+    group.bench_function("std_mutex", |b| b.iter(|| *mutex.lock().unwrap() += 1));
+
+    let mut shm_mutex: Box<ShmMutex> = Box::new(unsafe { std::mem::zeroed() });
+    unsafe { shm_mutex.init().unwrap() };
+    group.bench_function("shm_mutex", |b| {
+        b.iter(|| unsafe {
+            black_box(shm_mutex.lock());
+            shm_mutex.unlock();
+        })
+    });
+
+    let rwlock = IpcRwLock::create(&format!("mi7_bench_rwlock_{}", suffix)).unwrap();
+    group.bench_function("ipc_rwlock_read", |b| {
+        b.iter(|| drop(black_box(rwlock.read())))
+    });
+    group.bench_function("ipc_rwlock_write", |b| {
+        b.iter(|| drop(black_box(rwlock.write())))
+    });
+
+    let semaphore = IpcSemaphore::create(&format!("mi7_bench_semaphore_{}", suffix), 1).unwrap();
+    group.bench_function("ipc_semaphore", |b| {
+        b.iter(|| drop(black_box(semaphore.acquire())))
+    });
+
+    let seqlock = SeqLock::new([0u64; 4]);
+    group.bench_function("seqlock_read", |b| {
+        b.iter(|| black_box(seqlock.read().unwrap()))
+    });
+    group.bench_function("seqlock_write", |b| {
+        b.iter(|| seqlock.write(|value| value[0] += 1))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, locks);
+criterion_main!(benches);
@@ -0,0 +1,37 @@
+//! 寄存箱 box 的分配与写入延迟
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use mi7::{BoxConfig, BoxSize, SharedMemoryMailbox};
+
+fn mailbox(c: &mut Criterion) {
+    let name = format!("mi7_bench_mailbox_{}", std::process::id());
+    let mut config = BoxConfig::new();
+    config
+        .set_count(BoxSize::Size1M, 16)
+        .set_count(BoxSize::Size10M, 2);
+    let mailbox = SharedMemoryMailbox::new_shared(&name, config).unwrap();
+
+    let mut group = c.benchmark_group("mailbox");
+    group.bench_function("allocate_discard", |b| {
+        b.iter(|| {
+            let box_id = mailbox.allocate(1024).unwrap();
+            mailbox.discard(box_id).unwrap();
+        })
+    });
+
+    for len in [4 * 1024, 1024 * 1024] {
+        let data = vec![0x5a; len];
+        group.throughput(Throughput::Bytes(len as u64));
+        group.bench_with_input(BenchmarkId::new("write_take", len), &data, |b, data| {
+            b.iter(|| {
+                let box_id = mailbox.allocate(data.len()).unwrap();
+                mailbox.write_data(box_id, data).unwrap();
+                mailbox.take_data(box_id).unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, mailbox);
+criterion_main!(benches);
@@ -0,0 +1,87 @@
+//! 管道吞吐量：单生产者单消费者，以及 1~16 对生产/消费线程的 MPMC 扩展性
+//!
+//! 线程与进程访问同一段共享内存的方式相同（同样的原子操作、futex 与 robust 互斥锁），
+//! 这里用线程代替进程以便在一个基准进程内完成。
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use mi7::Message;
+use mi7::pipe::{DynCrossProcessPipe, PipeConfig};
+use mi7::shared_slot::PipeMode;
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 每次测量收发的消息数量，可被 1~16 的线程数整除
+const MESSAGES: u64 = 1600;
+
+fn pipe(tag: &str, mode: PipeMode) -> (DynCrossProcessPipe, DynCrossProcessPipe) {
+    let name = format!("mi7_bench_{}_{}", tag, std::process::id());
+    let config = PipeConfig::new(1024, 256).with_mode(mode);
+    let producer = DynCrossProcessPipe::create_with_config(&name, config).unwrap();
+    let consumer = DynCrossProcessPipe::connect(&name).unwrap();
+    (producer, consumer)
+}
+
+/// `threads` 个生产者与 `threads` 个消费者收发 [`MESSAGES`] 条消息的耗时
+fn transfer(
+    producer: &DynCrossProcessPipe,
+    consumer: &DynCrossProcessPipe,
+    threads: u64,
+) -> Duration {
+    let per_thread = MESSAGES / threads;
+    let start = Instant::now();
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                for _ in 0..per_thread {
+                    consumer.receive_blocking(TIMEOUT).unwrap();
+                }
+            });
+            scope.spawn(|| {
+                for n in 0..per_thread {
+                    producer
+                        .send_blocking(Message::init(n.to_string()), TIMEOUT)
+                        .unwrap();
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
+
+fn spsc(c: &mut Criterion) {
+    let mut group = c.benchmark_group("pipe_spsc");
+    group.throughput(Throughput::Elements(MESSAGES));
+    for mode in [PipeMode::Locked, PipeMode::LockFree] {
+        let (producer, consumer) = pipe("spsc", mode);
+        group.bench_function(BenchmarkId::from_parameter(format!("{:?}", mode)), |b| {
+            b.iter_custom(|iters| (0..iters).map(|_| transfer(&producer, &consumer, 1)).sum())
+        });
+    }
+    group.finish();
+}
+
+fn mpmc(c: &mut Criterion) {
+    let mut group = c.benchmark_group("pipe_mpmc");
+    group.throughput(Throughput::Elements(MESSAGES));
+    for mode in [PipeMode::Locked, PipeMode::LockFree] {
+        let (producer, consumer) = pipe("mpmc", mode);
+        for threads in [1, 2, 4, 8, 16] {
+            group.bench_with_input(
+                BenchmarkId::new(format!("{:?}", mode), threads),
+                &threads,
+                |b, &threads| {
+                    b.iter_custom(|iters| {
+                        (0..iters)
+                            .map(|_| transfer(&producer, &consumer, threads))
+                            .sum()
+                    })
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, spsc, mpmc);
+criterion_main!(benches);
@@ -0,0 +1,76 @@
+//! 模拟对端写坏寄存箱头部与 box 元数据：连接时 `rebuild_index` 必须拒绝越界的布局
+//!
+//! 输入覆盖在共享内存开头（头部 + 元数据区）之上，数据区保持不变。
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mi7::shared_box::{BoxMetadata, MailboxHeader};
+use mi7::{BoxConfig, BoxSize, SharedMemoryMailbox};
+use std::fs::{File, OpenOptions};
+use std::mem;
+use std::os::unix::fs::FileExt;
+use std::sync::{Mutex, OnceLock};
+
+const TOTAL_BOXES: usize = 2;
+
+struct Fixture {
+    name: String,
+    config: BoxConfig,
+    file: File,
+    pristine: Vec<u8>,
+    _owner: SharedMemoryMailbox,
+}
+
+static FIXTURE: OnceLock<Mutex<Fixture>> = OnceLock::new();
+
+fn fixture() -> &'static Mutex<Fixture> {
+    FIXTURE.get_or_init(|| {
+        let name = format!("mi7_fuzz_mailbox_{}", std::process::id());
+        let mut config = BoxConfig::new();
+        config.set_count(BoxSize::Size1M, TOTAL_BOXES);
+        let owner = SharedMemoryMailbox::new_shared(&name, config.clone()).expect("创建寄存箱失败");
+
+        // 一个写满的 box，让连接成功时的读取路径也被覆盖到
+        let box_id = owner.allocate(5).unwrap();
+        owner.write_data(box_id, b"fuzz!").unwrap();
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(format!("/dev/shm/{}", name))
+            .expect("打开寄存箱共享内存失败");
+        let mut pristine = vec![
+            0u8;
+            mem::size_of::<MailboxHeader>()
+                + TOTAL_BOXES * mem::size_of::<BoxMetadata>()
+        ];
+        file.read_exact_at(&mut pristine, 0).unwrap();
+
+        Mutex::new(Fixture {
+            name,
+            config,
+            file,
+            pristine,
+            _owner: owner,
+        })
+    })
+}
+
+fuzz_target!(|data: &[u8]| {
+    let fixture = fixture().lock().unwrap();
+
+    let mut image = fixture.pristine.clone();
+    let len = data.len().min(image.len());
+    image[..len].copy_from_slice(&data[..len]);
+    fixture.file.write_all_at(&image, 0).unwrap();
+
+    if let Ok(mailbox) = SharedMemoryMailbox::new_shared(&fixture.name, fixture.config.clone()) {
+        let _ = mailbox.get_stats();
+        for box_id in mailbox.get_full_boxes() {
+            let _ = mailbox.take_data(box_id);
+        }
+    }
+
+    fixture.file.write_all_at(&fixture.pristine, 0).unwrap();
+});
@@ -0,0 +1,14 @@
+//! 管道中的消息由对端进程编码，任意字节交给各编解码器解码都不能 panic
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mi7::CodecKind;
+
+fuzz_target!(|data: &[u8]| {
+    for codec in [CodecKind::Bincode, CodecKind::Json, CodecKind::Raw] {
+        if let Ok(message) = codec.codec().decode(data) {
+            let _ = message.as_large_payload();
+        }
+    }
+});
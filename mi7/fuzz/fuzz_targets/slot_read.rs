@@ -0,0 +1,77 @@
+//! 模拟对端写坏槽位元数据：任意 `data_size` 与槽位内容交给 `read_with` + 解码
+//!
+//! 输入格式：`codec(u8) | data_size(u32 LE) | payload`
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mi7::shared_slot::{PipeHeader, SlotHeader};
+use mi7::{CodecKind, DynSharedSlotPipe, Integrity, MutexAttr, PipeMode};
+use std::mem;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+const SLOT_SIZE: usize = 256;
+const CODECS: [CodecKind; 3] = [CodecKind::Bincode, CodecKind::Json, CodecKind::Raw];
+
+static PIPES: OnceLock<Vec<Mutex<DynSharedSlotPipe>>> = OnceLock::new();
+
+fn pipes() -> &'static [Mutex<DynSharedSlotPipe>] {
+    PIPES.get_or_init(|| {
+        CODECS
+            .iter()
+            .map(|codec| {
+                let name = format!("mi7_fuzz_slot_{}_{}", codec, std::process::id());
+                let pipe = unsafe {
+                    DynSharedSlotPipe::create(
+                        &name,
+                        4,
+                        SLOT_SIZE,
+                        PipeMode::Locked,
+                        *codec,
+                        Integrity::None,
+                        MutexAttr::default(),
+                    )
+                }
+                .expect("创建管道失败");
+                Mutex::new(pipe)
+            })
+            .collect()
+    })
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&selector, rest)) = data.split_first() else {
+        return;
+    };
+    let Some((size, payload)) = rest.split_first_chunk::<4>() else {
+        return;
+    };
+    if payload.is_empty() {
+        return;
+    }
+    let codec = CODECS[selector as usize % CODECS.len()];
+    let mut pipe = pipes()[selector as usize % CODECS.len()].lock().unwrap();
+
+    unsafe {
+        let index = pipe.hold().expect("没有空槽位");
+        pipe.write_with(index, |buf| {
+            let len = payload.len().min(buf.len());
+            buf[..len].copy_from_slice(&payload[..len]);
+            len
+        })
+        .unwrap();
+
+        // 覆盖元数据中的数据长度，模拟被写坏的共享内存
+        let slot = (pipe.as_ptr() as *mut u8)
+            .add(mem::size_of::<PipeHeader>() + index * DynSharedSlotPipe::slot_stride(SLOT_SIZE));
+        slot.add(mem::offset_of!(SlotHeader, data_size))
+            .cast::<u32>()
+            .write(u32::from_le_bytes(*size));
+
+        let index = pipe
+            .fetch_timeout(Some(Duration::ZERO))
+            .expect("写入的槽位不可读");
+        let _ = pipe.read_with(index, |buf| codec.codec().decode(buf));
+    }
+});
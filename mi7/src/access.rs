@@ -0,0 +1,354 @@
+//! 共享内存访问控制
+//!
+//! 两层控制：
+//! - 文件权限：共享内存段、持久化文件与通知 FIFO 按 [`AccessPolicy::mode`] 创建，
+//!   可选地把属组改为 [`AccessPolicy::group`]，只有属主与该组的进程能够打开。
+//!   这是唯一由内核保证的访问控制
+//! - 连接握手：启用 [`AccessPolicy::handshake`] 时，创建者在管道对应的 Unix 套接字
+//!   （[`socket_path`]）上监听，连接方连接后由创建者经 `SO_PEERCRED` 取得内核提供的
+//!   PID / UID / GID，按允许列表授权后，连接方才登记连接并开始读写。
+//!   凭据无法伪造，但握手不能阻止能打开共享内存段的进程不经握手直接读写槽位，
+//!   它只让不在允许列表中的进程在连接时失败；需要隔离时以文件权限为准
+//!
+//! 进程启动时调用 [`init_from_config`] 从 `[access]` 配置段加载策略；未加载时保持
+//! 以往的行为（`0o666`，不握手）。
+
+use crate::config;
+use crate::error::Result;
+use crate::shm_sync;
+use anyhow::Context;
+use std::ffi::CString;
+use std::io::{Read, Write};
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+/// 未配置时的创建权限
+pub const DEFAULT_MODE: u32 = 0o666;
+
+/// 连接方的进程凭据
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerCredentials {
+    pub pid: u32,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl PeerCredentials {
+    /// 当前进程的有效凭据
+    pub fn current() -> Self {
+        Self {
+            pid: std::process::id(),
+            uid: unsafe { libc::geteuid() },
+            gid: unsafe { libc::getegid() },
+        }
+    }
+
+    /// 经 `SO_PEERCRED` 读取 Unix 套接字对端进程的凭据，由内核提供，对端无法伪造
+    #[cfg(target_os = "linux")]
+    pub fn of_socket(fd: RawFd) -> Result<Self> {
+        let mut cred: libc::ucred = unsafe { std::mem::zeroed() };
+        let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+        let ret = unsafe {
+            libc::getsockopt(
+                fd,
+                libc::SOL_SOCKET,
+                libc::SO_PEERCRED,
+                &mut cred as *mut libc::ucred as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        if ret == -1 {
+            return Err(anyhow::anyhow!(
+                "getsockopt(SO_PEERCRED) failed with errno: {}",
+                shm_sync::errno()
+            )
+            .into());
+        }
+        Ok(Self {
+            pid: cred.pid as u32,
+            uid: cred.uid,
+            gid: cred.gid,
+        })
+    }
+
+    /// 经 `getpeereid` 读取 Unix 套接字对端进程的有效 UID / GID，由内核提供，对端无法伪造
+    ///
+    /// 对端进程号在 Apple 平台上经 `LOCAL_PEERPID` 读取，其他平台无法获取时为 0。
+    #[cfg(not(target_os = "linux"))]
+    pub fn of_socket(fd: RawFd) -> Result<Self> {
+        let (mut uid, mut gid) = (0, 0);
+        if unsafe { libc::getpeereid(fd, &mut uid, &mut gid) } == -1 {
+            return Err(
+                anyhow::anyhow!("getpeereid failed with errno: {}", shm_sync::errno()).into(),
+            );
+        }
+        Ok(Self {
+            pid: peer_pid(fd),
+            uid,
+            gid,
+        })
+    }
+}
+
+/// 对端进程号，无法获取时为 0
+#[cfg(target_vendor = "apple")]
+fn peer_pid(fd: RawFd) -> u32 {
+    let mut pid: libc::pid_t = 0;
+    let mut len = std::mem::size_of::<libc::pid_t>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_LOCAL,
+            libc::LOCAL_PEERPID,
+            (&mut pid as *mut libc::pid_t).cast(),
+            &mut len,
+        )
+    };
+    if ret == 0 { pid as u32 } else { 0 }
+}
+
+#[cfg(not(any(target_os = "linux", target_vendor = "apple")))]
+fn peer_pid(_fd: RawFd) -> u32 {
+    0
+}
+
+/// 访问控制策略
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessPolicy {
+    /// 共享内存段与文件的创建权限
+    pub mode: u32,
+    /// 创建后改为该属组，`None` 保持进程的有效 GID
+    pub group: Option<u32>,
+    /// 连接前是否需要经创建者握手授权
+    pub handshake: bool,
+    /// 握手时允许的 UID（创建者自己的 UID 总是允许）
+    pub allowed_uids: Vec<u32>,
+    /// 握手时允许的 GID
+    pub allowed_gids: Vec<u32>,
+}
+
+impl Default for AccessPolicy {
+    fn default() -> Self {
+        Self {
+            mode: DEFAULT_MODE,
+            group: None,
+            handshake: false,
+            allowed_uids: Vec::new(),
+            allowed_gids: Vec::new(),
+        }
+    }
+}
+
+impl AccessPolicy {
+    /// 是否允许该连接方
+    pub fn allows(&self, peer: &PeerCredentials) -> bool {
+        peer.uid == unsafe { libc::geteuid() }
+            || self.allowed_uids.contains(&peer.uid)
+            || self.allowed_gids.contains(&peer.gid)
+    }
+
+    /// 按允许列表授权连接方，拒绝时记录日志
+    pub fn authorize(&self, peer: &PeerCredentials) -> bool {
+        let allowed = self.allows(peer);
+        if !allowed {
+            tracing::warn!(
+                "进程 {} (uid={}, gid={}) 不在允许列表中，拒绝连接",
+                peer.pid,
+                peer.uid,
+                peer.gid
+            );
+        }
+        allowed
+    }
+}
+
+/// 管道对应的握手套接字路径
+pub fn socket_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("mi7_{}.access", name.trim_start_matches('/')))
+}
+
+/// 连接方：连接管道的握手套接字并等待创建者授权，被拒绝或超时返回错误
+pub fn request_access(name: &str, timeout: Duration) -> Result<()> {
+    let path = socket_path(name);
+    let mut stream = UnixStream::connect(&path)
+        .with_context(|| format!("连接握手套接字 {} 失败", path.display()))?;
+    stream.set_read_timeout(Some(timeout))?;
+    let mut granted = [0u8; 1];
+    match stream.read_exact(&mut granted) {
+        Ok(()) if granted[0] == 1 => Ok(()),
+        Ok(()) => {
+            let me = PeerCredentials::current();
+            Err(anyhow::anyhow!("创建者拒绝连接 (uid={}, gid={})", me.uid, me.gid).into())
+        }
+        Err(e)
+            if matches!(
+                e.kind(),
+                std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+            ) =>
+        {
+            Err(anyhow::anyhow!("等待创建者授权超时").into())
+        }
+        Err(e) => Err(anyhow::Error::from(e).context("读取握手结果失败").into()),
+    }
+}
+
+/// 创建者：取得握手连接对端的凭据并答复是否授权，返回对端凭据与授权结果
+pub fn serve_access<F>(stream: &mut UnixStream, authorize: F) -> Result<(PeerCredentials, bool)>
+where
+    F: FnOnce(&PeerCredentials) -> bool,
+{
+    let peer = PeerCredentials::of_socket(stream.as_raw_fd())?;
+    let granted = authorize(&peer);
+    stream.write_all(&[granted as u8])?;
+    Ok((peer, granted))
+}
+
+/// 本进程使用的访问控制策略，由 [`install`] 或 [`init_from_config`] 设置
+static INSTALLED: OnceLock<Arc<AccessPolicy>> = OnceLock::new();
+
+/// 设置本进程的访问控制策略；已设置过时返回 `false`
+pub fn install(policy: AccessPolicy) -> bool {
+    INSTALLED.set(Arc::new(policy)).is_ok()
+}
+
+/// 本进程的访问控制策略，未设置时为默认策略
+pub fn policy() -> Arc<AccessPolicy> {
+    INSTALLED
+        .get()
+        .cloned()
+        .unwrap_or_else(|| Arc::new(AccessPolicy::default()))
+}
+
+/// 创建共享内存段与文件时使用的权限
+pub fn mode() -> libc::mode_t {
+    INSTALLED.get().map_or(DEFAULT_MODE, |policy| policy.mode) as libc::mode_t
+}
+
+/// 按策略设置本进程创建的对象的权限与属组
+///
+/// 创建时的权限会被 umask 削减，这里用 `fchmod` 设为配置的值。由其他用户创建的对象保持不变。
+pub fn restrict(fd: libc::c_int) -> Result<()> {
+    let Some(policy) = INSTALLED.get() else {
+        return Ok(());
+    };
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstat(fd, &mut stat) } == -1 {
+        return Err(anyhow::anyhow!("fstat failed with errno: {}", shm_sync::errno()).into());
+    }
+    if stat.st_uid != unsafe { libc::geteuid() } {
+        return Ok(());
+    }
+    if unsafe { libc::fchmod(fd, policy.mode as libc::mode_t) } == -1 {
+        return Err(anyhow::anyhow!("fchmod failed with errno: {}", shm_sync::errno()).into());
+    }
+    if let Some(gid) = policy.group
+        && stat.st_gid != gid
+        && unsafe { libc::fchown(fd, u32::MAX, gid) } == -1
+    {
+        return Err(anyhow::anyhow!("fchown failed with errno: {}", shm_sync::errno()).into());
+    }
+    Ok(())
+}
+
+/// 解析八进制权限，如 `0660`、`660` 或 `0o660`
+fn parse_mode(raw: &str) -> Result<u32> {
+    let digits = raw.trim().trim_start_matches("0o");
+    let mode = u32::from_str_radix(digits, 8)
+        .map_err(|_| anyhow::anyhow!("无效的权限 '{}'，应为八进制，如 0660", raw))?;
+    if mode > 0o777 {
+        return Err(anyhow::anyhow!("无效的权限 '{}'，超出 0777", raw).into());
+    }
+    Ok(mode)
+}
+
+/// 解析属组：数字 GID 或组名
+fn parse_group(raw: &str) -> Result<u32> {
+    if let Ok(gid) = raw.parse() {
+        return Ok(gid);
+    }
+    let name = CString::new(raw).map_err(|_| anyhow::anyhow!("无效的组名 '{}'", raw))?;
+    let group = unsafe { libc::getgrnam(name.as_ptr()) };
+    if group.is_null() {
+        return Err(anyhow::anyhow!("组 '{}' 不存在", raw).into());
+    }
+    Ok(unsafe { (*group).gr_gid })
+}
+
+/// 解析逗号分隔的 ID 列表
+fn parse_ids(raw: &str) -> Result<Vec<u32>> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(|id| {
+            id.parse()
+                .map_err(|_| anyhow::anyhow!("无效的 ID '{}'", id).into())
+        })
+        .collect()
+}
+
+/// 按 `[access]` 配置加载并设置本进程的访问控制策略
+///
+/// 未配置该段时保持默认策略，返回 `false`。
+pub fn init_from_config() -> Result<bool> {
+    let config = config::get_config();
+    if config.get_keys("access").is_none() {
+        return Ok(false);
+    }
+
+    let group = config::string_or("access", "group", "");
+    let policy = AccessPolicy {
+        mode: parse_mode(&config::string_or("access", "mode", "0666"))?,
+        group: if group.is_empty() {
+            None
+        } else {
+            Some(parse_group(&group)?)
+        },
+        handshake: config::bool_or("access", "handshake", false),
+        allowed_uids: parse_ids(&config::string_or("access", "allowed_uids", ""))?,
+        allowed_gids: parse_ids(&config::string_or("access", "allowed_gids", ""))?,
+    };
+    tracing::info!(
+        "共享内存访问控制: 权限 {:o}, 属组 {:?}, 握手 {}",
+        policy.mode,
+        policy.group,
+        policy.handshake
+    );
+    Ok(install(policy))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_parsing_and_credentials() {
+        assert_eq!(parse_mode("0660").unwrap(), 0o660);
+        assert_eq!(parse_mode("0o600").unwrap(), 0o600);
+        assert!(parse_mode("0888").is_err());
+        assert_eq!(parse_ids("1000, 1001,").unwrap(), vec![1000, 1001]);
+
+        // 内核提供的对端凭据与当前进程一致
+        let me = PeerCredentials::current();
+        let (a, _b) = UnixStream::pair().unwrap();
+        let peer = PeerCredentials::of_socket(a.as_raw_fd()).unwrap();
+        #[cfg(any(target_os = "linux", target_vendor = "apple"))]
+        assert_eq!(peer, me);
+        #[cfg(not(any(target_os = "linux", target_vendor = "apple")))]
+        assert_eq!((peer.uid, peer.gid), (me.uid, me.gid));
+        let policy = AccessPolicy::default();
+        assert!(policy.authorize(&me));
+        let other = PeerCredentials {
+            uid: me.uid + 1,
+            ..me
+        };
+        assert!(!policy.authorize(&other));
+        let policy = AccessPolicy {
+            allowed_uids: vec![me.uid + 1],
+            ..AccessPolicy::default()
+        };
+        assert!(policy.allows(&other));
+    }
+}
@@ -0,0 +1,267 @@
+//! 会话亲和：共享内存中的一致性哈希环
+//!
+//! 携带亲和键（[`Message::affinity`](crate::Message::affinity)）的请求总是派发给哈希环上
+//! 该键的归属 worker，使有状态的 worker 保持缓存命中。每个 worker 按 worker ID 在环上
+//! 放置 [`VIRTUAL_NODES`] 个虚拟节点，成员增减时只有相邻区间的键改变归属。
+//!
+//! 哈希环保存在共享内存中，多个 entry 进程看到同一份归属。成员变化时由首先发现的一方
+//! 占用重建权（记录其 PID，持有者退出后可被接管）并重建：重建期间序号为奇数，读取方
+//! 发现序号变化即重读（顺序锁）。哈希环由守护进程创建，entry 只连接已存在的哈希环。
+
+use crate::locks::Segment;
+use crate::shm_sync;
+use crate::worker_board::{MAX_WORKERS, WorkerInfo};
+
+use crate::error::Result;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering, fence};
+
+/// 每个 worker 在环上的虚拟节点数量
+pub const VIRTUAL_NODES: usize = 16;
+
+/// 环上节点的最大数量
+const MAX_POINTS: usize = MAX_WORKERS * VIRTUAL_NODES;
+
+/// 读取时等待重建完成的最大自旋次数，超过后视为无归属
+const MAX_READ_SPINS: u32 = 1 << 16;
+
+/// 默认的哈希环名称
+pub const DEFAULT_RING_NAME: &str = "mi7_affinity";
+
+/// 环上的一个虚拟节点
+#[repr(C)]
+struct Point {
+    hash: AtomicU64,
+    pid: AtomicU32, // 归属 worker 的进程
+    _reserved: u32,
+}
+
+#[repr(C)]
+struct RingBlock {
+    seq: AtomicU64,     // 顺序锁序号，奇数表示正在重建
+    members: AtomicU64, // 当前成员的指纹，0 表示尚未建立
+    len: AtomicU32,     // 有效节点数量
+    writer: AtomicU32,  // 正在重建的进程，0 表示空闲
+    points: [Point; MAX_POINTS],
+}
+
+/// 一致性哈希环
+pub struct AffinityRing {
+    segment: Segment<RingBlock>,
+}
+
+impl AffinityRing {
+    /// 创建（或清空）哈希环，由守护进程持有，Drop 时删除
+    pub fn create(name: &str) -> Result<Self> {
+        Ok(Self {
+            segment: Segment::create(name, |_| {})?,
+        })
+    }
+
+    /// 连接守护进程创建的哈希环
+    pub fn open(name: &str) -> Result<Self> {
+        Ok(Self {
+            segment: Segment::open(name)?,
+        })
+    }
+
+    fn block(&self) -> &RingBlock {
+        self.segment.get()
+    }
+
+    /// 哈希环名称
+    pub fn name(&self) -> &str {
+        self.segment.name()
+    }
+
+    /// 环上的有效节点数量
+    pub fn len(&self) -> usize {
+        self.block().len.load(Ordering::Acquire) as usize
+    }
+
+    /// 环上是否没有节点
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 按当前成员同步哈希环，成员未变化时不做任何事，返回是否重建
+    ///
+    /// 其他存活进程正在重建时直接返回 `false`，由其完成重建。
+    pub fn sync(&self, workers: &[WorkerInfo]) -> bool {
+        let mut members: Vec<(&str, u32)> = workers
+            .iter()
+            .take(MAX_WORKERS)
+            .map(|worker| (worker.worker_id.as_str(), worker.pid))
+            .collect();
+        members.sort_unstable();
+        let fingerprint = members
+            .iter()
+            .fold(FNV_OFFSET, |hash, (worker_id, pid)| {
+                fnv(fnv(hash, worker_id.as_bytes()), &pid.to_le_bytes())
+            })
+            .max(1);
+
+        let block = self.block();
+        if block.members.load(Ordering::Acquire) == fingerprint {
+            return false;
+        }
+        let pid = std::process::id();
+        if let Err(writer) =
+            block
+                .writer
+                .compare_exchange(0, pid, Ordering::AcqRel, Ordering::Acquire)
+        {
+            // 重建到一半退出的进程留下的重建权直接接管
+            if shm_sync::process_alive(writer)
+                || block
+                    .writer
+                    .compare_exchange(writer, pid, Ordering::AcqRel, Ordering::Acquire)
+                    .is_err()
+            {
+                return false;
+            }
+        }
+        let seq = block.seq.load(Ordering::Acquire) | 1;
+        block.seq.store(seq, Ordering::Release);
+
+        let mut points: Vec<(u64, u32)> = members
+            .iter()
+            .flat_map(|(worker_id, pid)| {
+                (0..VIRTUAL_NODES).map(move |node| (point_hash(worker_id, node), *pid))
+            })
+            .collect();
+        points.sort_unstable();
+        for (slot, (hash, pid)) in block.points.iter().zip(&points) {
+            slot.hash.store(*hash, Ordering::Relaxed);
+            slot.pid.store(*pid, Ordering::Relaxed);
+        }
+        block.len.store(points.len() as u32, Ordering::Relaxed);
+        block.members.store(fingerprint, Ordering::Relaxed);
+        block.seq.store(seq + 1, Ordering::Release);
+        block.writer.store(0, Ordering::Release);
+        true
+    }
+
+    /// 亲和键的归属 worker 进程，环为空或长时间处于重建中时返回 `None`
+    pub fn lookup(&self, key: u64) -> Option<u32> {
+        let block = self.block();
+        for _ in 0..MAX_READ_SPINS {
+            let seq = block.seq.load(Ordering::Acquire);
+            if seq % 2 == 1 {
+                std::hint::spin_loop();
+                continue;
+            }
+
+            let len = (block.len.load(Ordering::Relaxed) as usize).min(MAX_POINTS);
+            let owner = (len > 0).then(|| {
+                // 第一个哈希不小于键的节点，越过末尾时回到环首
+                let (mut low, mut high) = (0, len);
+                while low < high {
+                    let mid = (low + high) / 2;
+                    if block.points[mid].hash.load(Ordering::Relaxed) < key {
+                        low = mid + 1;
+                    } else {
+                        high = mid;
+                    }
+                }
+                block.points[low % len].pid.load(Ordering::Relaxed)
+            });
+
+            fence(Ordering::Acquire);
+            if block.seq.load(Ordering::Relaxed) == seq {
+                return owner;
+            }
+        }
+        None
+    }
+}
+
+/// 亲和键的哈希，不为 0（0 表示消息未携带亲和键）
+///
+/// 各进程对同一个键得到相同的哈希，与编译器版本和进程无关。
+pub fn key_hash(key: &[u8]) -> u64 {
+    mix(fnv(FNV_OFFSET, key)).max(1)
+}
+
+fn point_hash(worker_id: &str, node: usize) -> u64 {
+    mix(fnv(
+        fnv(FNV_OFFSET, worker_id.as_bytes()),
+        &(node as u32).to_le_bytes(),
+    ))
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// FNV-1a
+fn fnv(mut hash: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+/// 打散相近输入的哈希（murmur3 fmix64），使虚拟节点在环上均匀分布
+fn mix(mut hash: u64) -> u64 {
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^ (hash >> 33)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worker_board::WorkerBoard;
+
+    #[test]
+    fn test_keys_stick_to_owner() {
+        let name = format!("mi7_test_affinity_{}", std::process::id());
+        let board_name = format!("mi7_test_affinity_board_{}", std::process::id());
+        assert!(AffinityRing::open(&name).is_err(), "连接方不创建哈希环");
+        let ring = AffinityRing::create(&name).unwrap();
+        let other = AffinityRing::open(&name).unwrap();
+        let board = std::sync::Arc::new(WorkerBoard::create(&board_name).unwrap());
+        assert_eq!(ring.lookup(key_hash(b"user-1")), None);
+
+        let registrations: Vec<_> = ["w1", "w2", "w3"]
+            .iter()
+            .map(|id| board.register(id, "", "").unwrap())
+            .collect();
+        let mut workers = board.workers();
+        // 同一进程登记的 worker 用不同的 PID 区分
+        for (pid, worker) in workers.iter_mut().enumerate() {
+            worker.pid = 100 + pid as u32;
+        }
+        assert!(ring.sync(&workers));
+        assert!(!other.sync(&workers));
+        assert_eq!(ring.len(), 3 * VIRTUAL_NODES);
+
+        let keys: Vec<u64> = (0..200)
+            .map(|n| key_hash(format!("user-{}", n).as_bytes()))
+            .collect();
+        let owners: Vec<u32> = keys.iter().map(|key| ring.lookup(*key).unwrap()).collect();
+        for pid in [100, 101, 102] {
+            assert!(owners.contains(&pid));
+        }
+        // 另一个进程看到相同的归属
+        assert!(
+            keys.iter()
+                .zip(&owners)
+                .all(|(key, pid)| other.lookup(*key) == Some(*pid))
+        );
+
+        // 移除一个成员后，只有其名下的键改变归属
+        let removed = workers.remove(1).pid;
+        assert!(other.sync(&workers));
+        for (key, pid) in keys.iter().zip(&owners) {
+            let now = ring.lookup(*key).unwrap();
+            if *pid == removed {
+                assert_ne!(now, removed);
+            } else {
+                assert_eq!(now, *pid);
+            }
+        }
+        drop(registrations);
+    }
+}
@@ -0,0 +1,166 @@
+//! 消息认证（HMAC）
+//!
+//! [`Integrity`](crate::integrity::Integrity) 校验值只能发现意外损坏，任何能写入共享内存的进程
+//! 都可以重新计算并伪造。启用消息认证后，写入方以共享密钥计算 HMAC-SHA256，截断为 64 位
+//! 存放在槽位元数据中校验值的旁边；读取方用同一密钥重新计算，不一致时以
+//! [`TokioIPCError::AuthenticationFailed`] 拒绝该消息。认证覆盖 request_id 与数据，
+//! 消息不能被挪到其他请求名下。
+//!
+//! 与负载加密相同，密钥只保存在各进程内存中，共享内存头部只记录密钥标识。进程启动时调用
+//! [`init_from_config`] 从 `[authentication]` 配置段加载密钥。
+//!
+//! [`TokioIPCError::AuthenticationFailed`]: crate::shared_slot::TokioIPCError::AuthenticationFailed
+
+use crate::config;
+use crate::error::Result;
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::path::Path;
+use std::sync::OnceLock;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// 密钥的最短长度（字节）
+pub const MIN_KEY_LEN: usize = 16;
+
+/// 消息认证器：按共享密钥计算与校验消息的认证码
+pub struct MessageAuthenticator {
+    key_id: u64,
+    mac: HmacSha256,
+}
+
+impl MessageAuthenticator {
+    /// 由密钥创建，密钥短于 [`MIN_KEY_LEN`] 字节时返回错误
+    pub fn new(key: &[u8]) -> Result<Self> {
+        if key.len() < MIN_KEY_LEN {
+            return Err(anyhow::anyhow!(
+                "消息认证密钥至少需要 {} 字节，当前 {} 字节",
+                MIN_KEY_LEN,
+                key.len()
+            )
+            .into());
+        }
+        let mut input = Vec::with_capacity(key.len() + 13);
+        input.extend_from_slice(b"mi7-hmac-key:");
+        input.extend_from_slice(key);
+        let key_id = xxhash_rust::xxh64::xxh64(&input, 0).max(1);
+        input.fill(0);
+        let mac =
+            HmacSha256::new_from_slice(key).map_err(|_| anyhow::anyhow!("消息认证密钥无效"))?;
+        Ok(Self { key_id, mac })
+    }
+
+    /// 读取密钥文件，去掉末尾换行后整个文件内容作为密钥
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let mut data =
+            std::fs::read(path).with_context(|| format!("读取密钥文件 {} 失败", path.display()))?;
+        while data.last().is_some_and(|b| *b == b'\n' || *b == b'\r') {
+            data.pop();
+        }
+        let authenticator = Self::new(&data);
+        data.fill(0);
+        authenticator
+    }
+
+    /// 密钥标识，记录在共享内存头部，用于确认连接方持有同一把密钥（0 表示未启用）
+    pub fn key_id(&self) -> u64 {
+        self.key_id
+    }
+
+    /// 计算消息的认证码：HMAC-SHA256(request_id || 数据) 的前 64 位
+    pub fn tag(&self, request_id: u64, data: &[u8]) -> u64 {
+        let digest = self.compute(request_id, data).finalize().into_bytes();
+        u64::from_le_bytes(digest[..8].try_into().unwrap())
+    }
+
+    /// 以常数时间比较校验消息的认证码
+    pub fn verify(&self, request_id: u64, data: &[u8], tag: u64) -> bool {
+        self.compute(request_id, data)
+            .verify_truncated_left(&tag.to_le_bytes())
+            .is_ok()
+    }
+
+    fn compute(&self, request_id: u64, data: &[u8]) -> HmacSha256 {
+        let mut mac = self.mac.clone();
+        mac.update(&request_id.to_le_bytes());
+        mac.update(data);
+        mac
+    }
+}
+
+impl std::fmt::Debug for MessageAuthenticator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MessageAuthenticator")
+            .field("key_id", &format_args!("{:016x}", self.key_id))
+            .finish_non_exhaustive()
+    }
+}
+
+/// 本进程使用的消息认证器，由 [`install`] 或 [`init_from_config`] 设置
+static INSTALLED: OnceLock<MessageAuthenticator> = OnceLock::new();
+
+/// 设置本进程的消息认证器，此后创建的管道要求认证；已设置过时返回 `false`
+pub fn install(authenticator: MessageAuthenticator) -> bool {
+    INSTALLED.set(authenticator).is_ok()
+}
+
+/// 本进程的消息认证器
+pub fn installed() -> Option<&'static MessageAuthenticator> {
+    INSTALLED.get()
+}
+
+/// 按 `[authentication]` 配置加载密钥并设置本进程的消息认证器
+///
+/// 未配置该段或 `enabled = false` 时不启用；启用但密钥无法读取时返回错误。
+/// 密钥来自 `key_file`，或 `key_env` 指定的环境变量。
+pub fn init_from_config() -> Result<bool> {
+    let config = config::get_config();
+    if config.get_keys("authentication").is_none()
+        || !config::bool_or("authentication", "enabled", true)
+    {
+        return Ok(false);
+    }
+
+    let key_file = config::string_or("authentication", "key_file", "");
+    let key_env = config::string_or("authentication", "key_env", "");
+    let authenticator = if !key_file.is_empty() {
+        MessageAuthenticator::from_file(&key_file)?
+    } else if !key_env.is_empty() {
+        let key = std::env::var(&key_env)
+            .map_err(|_| anyhow::anyhow!("环境变量 {} 未设置消息认证密钥", key_env))?;
+        MessageAuthenticator::new(key.as_bytes())?
+    } else {
+        return Err(anyhow::anyhow!(
+            "[authentication] 启用了消息认证，但未配置 key_file 或 key_env"
+        )
+        .into());
+    };
+
+    tracing::info!(
+        "消息认证已启用 (HMAC-SHA256), 密钥标识 {:016x}",
+        authenticator.key_id()
+    );
+    Ok(install(authenticator))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tag_binds_key_request_and_data() {
+        let auth = MessageAuthenticator::new(b"0123456789abcdef").unwrap();
+        let tag = auth.tag(7, b"payload");
+        assert!(auth.verify(7, b"payload", tag));
+        assert!(!auth.verify(7, b"pay1oad", tag));
+        assert!(!auth.verify(8, b"payload", tag));
+
+        // 其他密钥算出的认证码不同，也无法通过校验
+        let other = MessageAuthenticator::new(b"0123456789abcdeF").unwrap();
+        assert_ne!(other.key_id(), auth.key_id());
+        assert!(!other.verify(7, b"payload", tag));
+        assert!(MessageAuthenticator::new(b"short").is_err());
+    }
+}
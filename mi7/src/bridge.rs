@@ -0,0 +1,338 @@
+//! 跨主机管道桥接：把本地管道的消息经 TCP 转发到远端主机的管道
+//!
+//! [`PipeBridge`] 从本地管道以 `receive_unacked` 取出消息，编码为帧（`u32` 小端长度 +
+//! bincode）发往远端的 [`BridgeReceiver`]；接收端把消息写入目标管道后逐条回复确认，
+//! 桥接端收到确认才 `ack` 本地槽位。连接断开时所有未确认的消息 `nack` 后重新投递，
+//! 桥接端按退避间隔重连。
+//!
+//! 背压沿链路传递：目标管道已满时接收端停止读取，未确认的消息达到窗口上限后
+//! 桥接端停止从本地管道取消息，本地管道随之被填满。
+//!
+//! ```no_run
+//! # use std::sync::Arc;
+//! # use mi7::bridge::{BridgeReceiver, PipeBridge};
+//! # use mi7::pipe::PipeFactory;
+//! # async fn run() -> anyhow::Result<()> {
+//! // 远端主机：把收到的消息写入本机的 worker 管道
+//! let target = Arc::new(PipeFactory::connect("default", "work_req_pipe", false)?);
+//! BridgeReceiver::bind("0.0.0.0:7100", target).await?.spawn();
+//!
+//! // 本地主机：转发本机管道中的消息
+//! let source = Arc::new(PipeFactory::connect("default", "remote_req_pipe", false)?);
+//! PipeBridge::new(source, "10.0.0.2:7100").spawn();
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::Message;
+use crate::codec::BincodeCodec;
+use crate::pipe::{DynamicPipe, MessageExpired, PipeTimeout};
+
+use crate::error::Result;
+use anyhow::{Context, anyhow};
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+/// 默认的未确认消息窗口
+const DEFAULT_WINDOW: usize = 64;
+
+/// 默认的重连退避：首次等待与最长等待
+const DEFAULT_BACKOFF: (Duration, Duration) = (Duration::from_millis(100), Duration::from_secs(10));
+
+/// 接收端写入目标管道时每次等待空槽位的时长，超时后继续等待
+const SEND_WAIT: Duration = Duration::from_millis(100);
+
+/// 单帧的最大长度，与 bincode 解码上限一致
+const MAX_FRAME: usize = BincodeCodec::DECODE_LIMIT;
+
+/// 接收端对每条消息的确认
+#[derive(Debug, bincode::Encode, bincode::Decode)]
+enum BridgeAck {
+    /// 已写入目标管道
+    Accepted,
+    /// 目标管道拒绝（如消息超过槽位大小），桥接端 `nack`
+    Rejected(String),
+}
+
+async fn write_frame<T: bincode::Encode>(
+    stream: &mut (impl AsyncWrite + Unpin),
+    value: &T,
+) -> Result<()> {
+    let payload = bincode::encode_to_vec(value, bincode::config::standard())?;
+    let mut frame = Vec::with_capacity(4 + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&payload);
+    stream.write_all(&frame).await?;
+    Ok(())
+}
+
+/// 读取一帧，对端关闭连接时返回 None
+async fn read_frame<T: bincode::Decode<()>>(
+    stream: &mut (impl AsyncRead + Unpin),
+) -> Result<Option<T>> {
+    let mut len = [0u8; 4];
+    match stream.read_exact(&mut len).await {
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        result => result?,
+    };
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_FRAME {
+        return Err(anyhow!("帧长度 {} 超过上限 {}", len, MAX_FRAME).into());
+    }
+    let mut payload = vec![0u8; len];
+    stream.read_exact(&mut payload).await?;
+    let (value, _) = bincode::decode_from_slice(&payload, bincode::config::standard())?;
+    Ok(Some(value))
+}
+
+/// 桥接端：把本地管道的消息转发到远端
+pub struct PipeBridge {
+    source: Arc<Box<dyn DynamicPipe>>,
+    remote: String,
+    window: usize,
+    backoff: (Duration, Duration),
+}
+
+impl PipeBridge {
+    /// 创建桥接端，`remote` 为远端 [`BridgeReceiver`] 的地址（`host:port`）
+    pub fn new(source: Arc<Box<dyn DynamicPipe>>, remote: impl Into<String>) -> Self {
+        Self {
+            source,
+            remote: remote.into(),
+            window: DEFAULT_WINDOW,
+            backoff: DEFAULT_BACKOFF,
+        }
+    }
+
+    /// 设置未确认消息的窗口（至少为 1），窗口内的消息占用本地槽位直到远端确认
+    pub fn with_window(mut self, window: usize) -> Self {
+        self.window = window.max(1);
+        self
+    }
+
+    /// 设置重连退避：首次等待 `initial`，每次失败翻倍，最长 `max`
+    pub fn with_reconnect_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.backoff = (initial, max.max(initial));
+        self
+    }
+
+    /// 在后台持续转发
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(self.run())
+    }
+
+    /// 持续转发，连接断开后按退避间隔重连，不会返回
+    pub async fn run(self) {
+        let (initial, max) = self.backoff;
+        let mut delay = initial;
+        loop {
+            match TcpStream::connect(&self.remote).await {
+                Ok(stream) => {
+                    info!("管道桥接已连接 {} -> {}", self.source.name(), self.remote);
+                    delay = initial;
+                    let _ = stream.set_nodelay(true);
+                    if let Err(e) = self.forward(stream).await {
+                        warn!("管道桥接 {} 连接中断: {:#}", self.remote, e);
+                    }
+                }
+                Err(e) => debug!("连接管道桥接 {} 失败: {}", self.remote, e),
+            }
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(max);
+        }
+    }
+
+    /// 在一条连接上转发，直到连接出错；返回前 `nack` 所有未确认的消息
+    async fn forward(&self, stream: TcpStream) -> Result<()> {
+        let (mut reader, mut writer) = stream.into_split();
+        // 确认在独立任务中读取：帧读取不是取消安全的，不能直接放进 select!
+        let (ack_tx, mut ack_rx) = mpsc::channel(self.window);
+        let ack_reader = tokio::spawn(async move {
+            loop {
+                let ack = read_frame::<BridgeAck>(&mut reader).await;
+                let done = !matches!(ack, Ok(Some(_)));
+                if ack_tx.send(ack).await.is_err() || done {
+                    break;
+                }
+            }
+        });
+
+        let mut inflight = VecDeque::with_capacity(self.window);
+        let result = self.pump(&mut writer, &mut ack_rx, &mut inflight).await;
+        ack_reader.abort();
+
+        for index in inflight {
+            if let Err(e) = self.source.nack(index) {
+                warn!("管道桥接归还槽位 {} 失败: {}", index, e);
+            }
+        }
+        result
+    }
+
+    async fn pump(
+        &self,
+        writer: &mut (impl AsyncWrite + Unpin),
+        acks: &mut mpsc::Receiver<Result<Option<BridgeAck>>>,
+        inflight: &mut VecDeque<usize>,
+    ) -> Result<()> {
+        loop {
+            tokio::select! {
+                ack = acks.recv() => {
+                    let ack = ack.ok_or_else(|| anyhow!("确认读取任务已退出"))??
+                        .ok_or_else(|| anyhow!("远端关闭了连接"))?;
+                    let index = inflight
+                        .pop_front()
+                        .ok_or_else(|| anyhow!("收到多余的确认"))?;
+                    match ack {
+                        BridgeAck::Accepted => self.source.ack(index)?,
+                        BridgeAck::Rejected(reason) => {
+                            warn!("远端拒绝了槽位 {} 的消息: {}", index, reason);
+                            self.source.nack(index)?;
+                        }
+                    }
+                }
+                index = self.source.fetch_async(), if inflight.len() < self.window => {
+                    let index = index?;
+                    let message = match self.source.receive_unacked(index) {
+                        Ok((_, message)) => message,
+                        // 已过期的消息槽位已释放
+                        Err(e) if e.is::<MessageExpired>() => continue,
+                        Err(e) => {
+                            // 未释放的槽位由租约回收处理
+                            warn!("管道桥接读取槽位 {} 失败: {}", index, e);
+                            continue;
+                        }
+                    };
+                    inflight.push_back(index);
+                    write_frame(writer, &message).await?;
+                }
+            }
+        }
+    }
+}
+
+/// 接收端：把远端桥接端发来的消息写入目标管道
+pub struct BridgeReceiver {
+    listener: TcpListener,
+    target: Arc<Box<dyn DynamicPipe>>,
+}
+
+impl BridgeReceiver {
+    /// 在 `addr` 上监听桥接连接
+    pub async fn bind(addr: &str, target: Arc<Box<dyn DynamicPipe>>) -> Result<Self> {
+        let listener = TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("管道桥接监听 {} 失败", addr))?;
+        Ok(Self { listener, target })
+    }
+
+    /// 实际监听的地址
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// 在后台接受连接
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(self.run())
+    }
+
+    /// 接受连接，每个连接由独立任务处理，不会返回
+    pub async fn run(self) {
+        loop {
+            match self.listener.accept().await {
+                Ok((stream, peer)) => {
+                    info!("管道桥接接入 {} -> {}", peer, self.target.name());
+                    let _ = stream.set_nodelay(true);
+                    let target = self.target.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = Self::receive(stream, target).await {
+                            warn!("管道桥接 {} 连接中断: {:#}", peer, e);
+                        }
+                    });
+                }
+                Err(e) => warn!("管道桥接接受连接失败: {}", e),
+            }
+        }
+    }
+
+    async fn receive(mut stream: TcpStream, target: Arc<Box<dyn DynamicPipe>>) -> Result<()> {
+        while let Some(message) = read_frame::<Message>(&mut stream).await? {
+            let ack = match Self::deliver(&target, message).await {
+                Ok(()) => BridgeAck::Accepted,
+                Err(e) => BridgeAck::Rejected(format!("{:#}", e)),
+            };
+            write_frame(&mut stream, &ack).await?;
+        }
+        Ok(())
+    }
+
+    /// 写入目标管道，管道满时一直等待（背压）
+    async fn deliver(target: &Arc<Box<dyn DynamicPipe>>, message: Message) -> Result<()> {
+        loop {
+            let pipe = target.clone();
+            let attempt = message.clone();
+            let sent = tokio::task::spawn_blocking(move || pipe.send_blocking(attempt, SEND_WAIT))
+                .await
+                .context("管道桥接发送任务异常退出")?;
+            match sent {
+                Err(e) if e.is::<PipeTimeout>() => continue,
+                sent => return sent.map(|_| ()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipe::PipeFactory;
+
+    #[tokio::test]
+    async fn test_bridge_forwards_and_reconnects() {
+        let source = Arc::new(PipeFactory::create("memory(8x256)", "bridge_source").unwrap());
+        let target = Arc::new(PipeFactory::create("memory(4x256)", "bridge_target").unwrap());
+
+        // 先启动桥接端，接收端稍后才开始监听：桥接端需要重连
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let bridge = PipeBridge::new(source.clone(), addr.to_string())
+            .with_window(2)
+            .with_reconnect_backoff(Duration::from_millis(10), Duration::from_millis(50))
+            .spawn();
+        for i in 0..8 {
+            source
+                .send_blocking(Message::init(format!("m{}", i)), Duration::from_secs(1))
+                .unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let receiver = BridgeReceiver::bind(&addr.to_string(), target.clone())
+            .await
+            .unwrap()
+            .spawn();
+
+        // 目标管道只有 4 个槽位，其余消息在背压下等待
+        for i in 0..8 {
+            let pipe = target.clone();
+            let message =
+                tokio::task::spawn_blocking(move || pipe.receive_blocking(Duration::from_secs(5)))
+                    .await
+                    .unwrap()
+                    .unwrap();
+            assert_eq!(message.data, format!("m{}", i).into_bytes());
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(source.status().empty_count, 8);
+
+        bridge.abort();
+        receiver.abort();
+    }
+}
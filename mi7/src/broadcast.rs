@@ -0,0 +1,180 @@
+//! 广播管道：每个订阅者都收到每一条消息
+//!
+//! 基于 [`StreamPipe`] 实现，每个 [`BroadcastReceiver`] 独占一个消费组（即独立的
+//! 读游标），槽位在所有订阅者都读过之后才被覆盖。订阅者只接收订阅之后发布的消息；
+//! 没有订阅者时发布的消息直接丢弃。订阅者进程崩溃后其游标在发布方等待空间时回收。
+
+use crate::Message;
+use crate::futex;
+use crate::stream::{StreamPipe, Subscription};
+
+use crate::error::Result;
+use anyhow::anyhow;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// 发布方等待空间时检查订阅者存活的间隔
+const RECLAIM_INTERVAL: Duration = Duration::from_millis(50);
+
+/// 本进程内订阅者编号，用于生成唯一的消费组名称
+static NEXT_RECEIVER: AtomicU64 = AtomicU64::new(0);
+
+/// 广播管道
+pub struct BroadcastPipe {
+    stream: Arc<StreamPipe>,
+}
+
+impl BroadcastPipe {
+    /// 创建（或重置）广播管道
+    pub fn create(name: &str, capacity: usize, slot_size: usize) -> Result<Self> {
+        Ok(Self::from_stream(StreamPipe::create(
+            name, capacity, slot_size,
+        )?))
+    }
+
+    /// 连接到已有广播管道
+    pub fn connect(name: &str) -> Result<Self> {
+        Ok(Self::from_stream(StreamPipe::connect(name)?))
+    }
+
+    fn from_stream(stream: StreamPipe) -> Self {
+        Self {
+            stream: Arc::new(stream),
+        }
+    }
+
+    /// 底层消息流
+    pub fn stream(&self) -> &Arc<StreamPipe> {
+        &self.stream
+    }
+
+    /// 当前订阅者数量
+    pub fn subscribers(&self) -> usize {
+        self.stream.groups().len()
+    }
+
+    /// 向所有订阅者发布消息，返回消息序号
+    ///
+    /// 最慢的订阅者未读完时等待，超时返回错误。
+    pub fn publish(&self, message: &Message, timeout: Duration) -> Result<u64> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(seq) = self.stream.try_publish(message)? {
+                return Ok(seq);
+            }
+
+            self.stream.reclaim_dead_groups()?;
+            self.stream.discard_unsubscribed()?;
+            if let Some(seq) = self.stream.try_publish(message)? {
+                return Ok(seq);
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(
+                    anyhow!("广播管道 {} 已满，等待订阅者读取超时", self.stream.name()).into(),
+                );
+            }
+            let stream = &self.stream;
+            futex::wait_until(
+                stream.consumed_word(),
+                Some(remaining.min(RECLAIM_INTERVAL)),
+                || stream.len() < stream.capacity(),
+            );
+        }
+    }
+
+    /// 订阅广播，只接收此后发布的消息
+    pub fn subscribe(&self) -> Result<BroadcastReceiver> {
+        let pid = std::process::id();
+        let group = format!(
+            "__bcast_{}_{}",
+            pid,
+            NEXT_RECEIVER.fetch_add(1, Ordering::Relaxed)
+        );
+        Ok(BroadcastReceiver {
+            subscription: self.stream.subscribe_with(&group, true, pid)?,
+        })
+    }
+}
+
+/// 广播订阅者，`Drop` 时退订
+pub struct BroadcastReceiver {
+    subscription: Subscription,
+}
+
+impl BroadcastReceiver {
+    /// 尚未读取的消息数量
+    pub fn lag(&self) -> u64 {
+        self.subscription.lag()
+    }
+
+    /// 读取下一条消息及其序号，没有新消息时返回 `Ok(None)`
+    pub fn try_receive(&self) -> Result<Option<(u64, Message)>> {
+        self.subscription.try_receive()
+    }
+
+    /// 阻塞读取下一条消息，超时返回错误
+    pub fn receive_blocking(&self, timeout: Duration) -> Result<(u64, Message)> {
+        self.subscription.receive_blocking(timeout)
+    }
+}
+
+impl Drop for BroadcastReceiver {
+    fn drop(&mut self) {
+        let pipe = self.subscription.pipe();
+        if let Err(e) = pipe.remove_group(self.subscription.group()) {
+            tracing::warn!("广播管道 {} 退订失败: {}", pipe.name(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_subscriber_receives_every_message() {
+        let name = format!("mi7_test_broadcast_{}", std::process::id());
+        let publisher = BroadcastPipe::create(&name, 2, 128).unwrap();
+        let timeout = Duration::from_millis(200);
+
+        // 没有订阅者时不阻塞
+        for i in 0..4 {
+            publisher
+                .publish(&Message::init(format!("dropped{}", i)), timeout)
+                .unwrap();
+        }
+
+        let worker = BroadcastPipe::connect(&name).unwrap();
+        let receivers = [publisher.subscribe().unwrap(), worker.subscribe().unwrap()];
+        assert_eq!(publisher.subscribers(), 2);
+
+        for i in 0..2 {
+            publisher
+                .publish(&Message::init(format!("config{}", i)), timeout)
+                .unwrap();
+        }
+        // 两个订阅者都未读取时槽位不能被覆盖
+        assert!(
+            publisher
+                .publish(
+                    &Message::init("full".to_string()),
+                    Duration::from_millis(20)
+                )
+                .is_err()
+        );
+
+        for receiver in &receivers {
+            for i in 0..2 {
+                let (_, message) = receiver.receive_blocking(timeout).unwrap();
+                assert_eq!(message.data, format!("config{}", i).into_bytes());
+            }
+            assert!(receiver.try_receive().unwrap().is_none());
+        }
+
+        drop(receivers);
+        assert_eq!(publisher.subscribers(), 0);
+    }
+}
@@ -0,0 +1,379 @@
+//! 共享内存中的进程心跳区
+//!
+//! 守护进程、entry 与 worker 启动后各自在心跳区占用一项，写入 PID、角色与名称，
+//! 并由后台任务定期更新心跳时间。[`ClusterView::processes`] 据此给出各进程是否存活：
+//! 进程仍存在且心跳未超时才视为存活，不会因 PID 被复用或进程卡死而误判。
+//! 回收逻辑（例如 worker 登记表的清理）应以此为准。心跳区由守护进程创建，其他进程只连接。
+
+use crate::config;
+use crate::locks::Segment;
+use crate::shm_sync;
+
+use crate::error::Result;
+use anyhow::anyhow;
+use serde::Serialize;
+use std::cell::UnsafeCell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// 心跳区可容纳的进程数量
+pub const MAX_PROCESSES: usize = 128;
+
+/// 进程名称的最大字节数
+pub const PROCESS_NAME_LEN: usize = 32;
+
+/// 默认的心跳区名称
+pub const DEFAULT_CLUSTER_NAME: &str = "mi7_cluster";
+
+/// 默认的心跳间隔
+pub const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 超过该时长未更新心跳的进程视为失联
+pub const DEFAULT_HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 进程角色
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(u32)]
+pub enum ProcessRole {
+    Daemon = 1,
+    Entry = 2,
+    Worker = 3,
+    Other = 4,
+}
+
+impl ProcessRole {
+    fn from_u32(value: u32) -> Self {
+        match value {
+            1 => Self::Daemon,
+            2 => Self::Entry,
+            3 => Self::Worker,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// 心跳区中的一项，`started_at` 非 0 表示已发布
+#[repr(C)]
+struct ProcessEntry {
+    pid: AtomicU32,        // 占用该项的进程，0 表示空闲
+    role: AtomicU32,       // ProcessRole
+    started_at: AtomicU64, // 加入时间（Unix 毫秒），0 表示尚未发布
+    last_beat: AtomicU64,  // 最近一次心跳（单调时钟毫秒）
+    name: UnsafeCell<[u8; PROCESS_NAME_LEN]>,
+}
+
+#[repr(C)]
+struct ClusterBlock {
+    processes: [ProcessEntry; MAX_PROCESSES],
+}
+
+/// 心跳区中一个进程的快照
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProcessInfo {
+    /// 在心跳区中的位置
+    #[serde(skip)]
+    pub index: usize,
+    pub pid: u32,
+    pub role: ProcessRole,
+    pub name: String,
+    /// 自加入以来的时长
+    #[serde(rename = "uptime_ms", serialize_with = "serialize_millis")]
+    pub uptime: Duration,
+    /// 距上次心跳的时长
+    #[serde(rename = "since_beat_ms", serialize_with = "serialize_millis")]
+    pub since_beat: Duration,
+    /// 进程存在且心跳未超时
+    pub alive: bool,
+}
+
+fn serialize_millis<S: serde::Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(duration.as_millis() as u64)
+}
+
+/// 进程心跳区
+pub struct ClusterView {
+    segment: Segment<ClusterBlock>,
+    heartbeat_timeout: Duration,
+}
+
+unsafe impl Send for ClusterView {}
+unsafe impl Sync for ClusterView {}
+
+impl ClusterView {
+    /// 创建（或清空）心跳区，由守护进程持有，Drop 时删除
+    pub fn create(name: &str) -> Result<Self> {
+        Ok(Self {
+            segment: Segment::create(name, |_| {})?,
+            heartbeat_timeout: DEFAULT_HEARTBEAT_TIMEOUT,
+        })
+    }
+
+    /// 连接守护进程创建的心跳区
+    pub fn open(name: &str) -> Result<Self> {
+        Ok(Self {
+            segment: Segment::open(name)?,
+            heartbeat_timeout: DEFAULT_HEARTBEAT_TIMEOUT,
+        })
+    }
+
+    /// 设置判定失联的心跳超时
+    pub fn with_heartbeat_timeout(mut self, timeout: Duration) -> Self {
+        self.heartbeat_timeout = timeout;
+        self
+    }
+
+    fn block(&self) -> &ClusterBlock {
+        self.segment.get()
+    }
+
+    /// 心跳区名称
+    pub fn name(&self) -> &str {
+        self.segment.name()
+    }
+
+    /// 当前进程以 `role` 与 `name` 加入心跳区，返回的 [`Heartbeat`] 被丢弃时退出
+    pub fn join(self: &Arc<Self>, role: ProcessRole, name: &str) -> Result<Heartbeat> {
+        if name.len() > PROCESS_NAME_LEN {
+            return Err(anyhow!("进程名称过长（最多 {} 字节）: {}", PROCESS_NAME_LEN, name).into());
+        }
+        let mut encoded = [0u8; PROCESS_NAME_LEN];
+        encoded[..name.len()].copy_from_slice(name.as_bytes());
+
+        let pid = std::process::id();
+        let index = self.claim(pid).or_else(|| {
+            self.prune();
+            self.claim(pid)
+        });
+        let index = index.ok_or_else(|| anyhow!("心跳区 {} 已满", self.name()))?;
+
+        let heartbeat = Heartbeat {
+            view: Arc::clone(self),
+            index,
+            pid,
+            role,
+            name: encoded,
+        };
+        heartbeat.publish();
+        Ok(heartbeat)
+    }
+
+    fn claim(&self, pid: u32) -> Option<usize> {
+        self.block().processes.iter().position(|entry| {
+            entry
+                .pid
+                .compare_exchange(0, pid, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+        })
+    }
+
+    /// 所有已加入的进程
+    pub fn processes(&self) -> Vec<ProcessInfo> {
+        let now = shm_sync::monotonic_millis();
+        let unix_now = unix_millis();
+        self.block()
+            .processes
+            .iter()
+            .enumerate()
+            .filter_map(|(index, entry)| {
+                let started_at = entry.started_at.load(Ordering::Acquire);
+                let pid = entry.pid.load(Ordering::Acquire);
+                if started_at == 0 || pid == 0 {
+                    return None;
+                }
+                let since_beat = Duration::from_millis(
+                    now.saturating_sub(entry.last_beat.load(Ordering::Acquire)),
+                );
+                let name = unsafe { &*entry.name.get() };
+                let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+                Some(ProcessInfo {
+                    index,
+                    pid,
+                    role: ProcessRole::from_u32(entry.role.load(Ordering::Acquire)),
+                    name: String::from_utf8_lossy(&name[..len]).into_owned(),
+                    uptime: Duration::from_millis(unix_now.saturating_sub(started_at)),
+                    since_beat,
+                    alive: since_beat <= self.heartbeat_timeout && shm_sync::process_alive(pid),
+                })
+            })
+            .collect()
+    }
+
+    /// 指定角色的进程
+    pub fn processes_with_role(&self, role: ProcessRole) -> Vec<ProcessInfo> {
+        let mut processes = self.processes();
+        processes.retain(|process| process.role == role);
+        processes
+    }
+
+    /// `pid` 是否存活：已加入心跳区的进程以心跳为准，未加入的退回检查进程是否存在
+    pub fn is_alive(&self, pid: u32) -> bool {
+        match self.processes().into_iter().find(|p| p.pid == pid) {
+            Some(process) => process.alive,
+            None => shm_sync::process_alive(pid),
+        }
+    }
+
+    /// 删除失联进程的记录，返回被删除的进程
+    pub fn prune(&self) -> Vec<ProcessInfo> {
+        let mut removed = self.processes();
+        removed.retain(|process| !process.alive && self.release(process.index, process.pid));
+        removed
+    }
+
+    fn release(&self, index: usize, pid: u32) -> bool {
+        let entry = &self.block().processes[index];
+        entry.started_at.store(0, Ordering::Release);
+        entry
+            .pid
+            .compare_exchange(pid, 0, Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok()
+    }
+}
+
+/// 当前进程在心跳区中的记录，Drop 时退出
+pub struct Heartbeat {
+    view: Arc<ClusterView>,
+    index: usize,
+    pid: u32,
+    role: ProcessRole,
+    name: [u8; PROCESS_NAME_LEN],
+}
+
+impl Heartbeat {
+    fn entry(&self) -> &ProcessEntry {
+        &self.view.block().processes[self.index]
+    }
+
+    fn publish(&self) {
+        let entry = self.entry();
+        entry.role.store(self.role as u32, Ordering::Relaxed);
+        unsafe { *entry.name.get() = self.name };
+        entry
+            .last_beat
+            .store(shm_sync::monotonic_millis(), Ordering::Relaxed);
+        entry.started_at.store(unix_millis(), Ordering::Release);
+    }
+
+    /// 更新心跳；记录因失联被清除后重新占用原来的位置
+    pub fn beat(&self) {
+        let entry = self.entry();
+        if entry.pid.load(Ordering::Acquire) == self.pid {
+            entry
+                .last_beat
+                .store(shm_sync::monotonic_millis(), Ordering::Release);
+        } else if entry
+            .pid
+            .compare_exchange(0, self.pid, Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok()
+        {
+            tracing::warn!("心跳记录已因失联被清除，重新加入心跳区");
+            self.publish();
+        }
+    }
+
+    /// 在后台按 `interval` 更新心跳，任务结束（被取消）时退出心跳区
+    pub fn start(self, interval: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                self.beat();
+            }
+        })
+    }
+}
+
+impl Drop for Heartbeat {
+    fn drop(&mut self) {
+        if self.entry().pid.load(Ordering::Acquire) == self.pid {
+            self.view.release(self.index, self.pid);
+        }
+    }
+}
+
+/// 按 `[cluster]` 配置创建（`owner` 为 `true`，由守护进程调用）或连接心跳区
+pub fn from_config(owner: bool) -> Result<ClusterView> {
+    let name = config::string_or("cluster", "name", DEFAULT_CLUSTER_NAME);
+    let view = if owner {
+        ClusterView::create(&name)?
+    } else {
+        ClusterView::open(&name)?
+    };
+    let timeout = config::int_or(
+        "cluster",
+        "heartbeat_timeout_seconds",
+        DEFAULT_HEARTBEAT_TIMEOUT.as_secs() as i64,
+    );
+    Ok(view.with_heartbeat_timeout(Duration::from_secs(timeout.max(1) as u64)))
+}
+
+/// 按 `[cluster]` 配置加入心跳区并在后台更新心跳，取消返回的任务即退出
+pub fn start_heartbeat(
+    view: &Arc<ClusterView>,
+    role: ProcessRole,
+    name: &str,
+) -> Result<JoinHandle<()>> {
+    let interval = config::int_or(
+        "cluster",
+        "heartbeat_interval_ms",
+        DEFAULT_HEARTBEAT_INTERVAL.as_millis() as i64,
+    );
+    Ok(view
+        .join(role, name)?
+        .start(Duration::from_millis(interval.max(10) as u64)))
+}
+
+fn unix_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+        .max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_processes_liveness() {
+        let name = format!("mi7_test_cluster_{}", std::process::id());
+        assert!(ClusterView::open(&name).is_err(), "连接方不创建心跳区");
+        let view = Arc::new(
+            ClusterView::create(&name)
+                .unwrap()
+                .with_heartbeat_timeout(Duration::from_millis(100)),
+        );
+        let peer = Arc::new(ClusterView::open(&name).unwrap());
+
+        let daemon = view.join(ProcessRole::Daemon, "daemon").unwrap();
+        let worker = peer.join(ProcessRole::Worker, "worker1").unwrap();
+        let processes = view.processes();
+        assert_eq!(processes.len(), 2);
+        assert!(processes.iter().all(|p| p.alive));
+        assert_eq!(
+            view.processes_with_role(ProcessRole::Worker)[0].name,
+            "worker1"
+        );
+
+        // 心跳超时即视为失联，即使进程仍存在
+        std::thread::sleep(Duration::from_millis(150));
+        daemon.beat();
+        let stale = view.processes_with_role(ProcessRole::Worker);
+        assert!(!stale[0].alive);
+        assert_eq!(view.prune()[0].index, stale[0].index);
+        assert_eq!(view.processes().len(), 1);
+
+        // 被清除后的心跳重新加入
+        worker.beat();
+        assert_eq!(view.processes().len(), 2);
+        drop(worker);
+        assert_eq!(view.processes().len(), 1);
+    }
+}
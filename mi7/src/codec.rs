@@ -0,0 +1,253 @@
+//! 消息编解码
+//!
+//! 管道创建时选定编解码方式并写入共享内存头部，连接方从头部读取，保证两端一致。
+
+use crate::error::Result;
+use anyhow::Context;
+use serde::Serialize;
+use std::io::Cursor;
+use std::str::FromStr;
+
+use crate::Message;
+use crate::shared_slot::PayloadTooLarge;
+use crate::tracing_ipc::TraceContext;
+
+/// 编解码方式，以 `u32` 记录在共享内存头部
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub enum CodecKind {
+    /// bincode 标准配置（默认）
+    #[default]
+    Bincode = 0,
+    /// JSON，便于调试与跨语言对接
+    Json = 1,
+    /// 原始字节：`flag(u8) | timestamp(u64 LE) | expires_at(u64 LE) | trace_id(u128 LE) | span_id(u64 LE) | affinity(u64 LE) | data`
+    Raw = 2,
+}
+
+impl CodecKind {
+    /// 从头部记录的值还原，未知值回退为 bincode
+    pub fn from_u32(value: u32) -> Self {
+        match value {
+            1 => CodecKind::Json,
+            2 => CodecKind::Raw,
+            _ => CodecKind::Bincode,
+        }
+    }
+
+    /// 获取对应的编解码器
+    pub fn codec(&self) -> &'static dyn Codec {
+        match self {
+            CodecKind::Bincode => &BincodeCodec,
+            CodecKind::Json => &JsonCodec,
+            CodecKind::Raw => &RawCodec,
+        }
+    }
+}
+
+impl FromStr for CodecKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "bincode" => Ok(CodecKind::Bincode),
+            "json" => Ok(CodecKind::Json),
+            "raw" => Ok(CodecKind::Raw),
+            _ => Err(format!(
+                "不支持的编解码方式: '{}'. 支持: bincode, json, raw",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for CodecKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CodecKind::Bincode => write!(f, "bincode"),
+            CodecKind::Json => write!(f, "json"),
+            CodecKind::Raw => write!(f, "raw"),
+        }
+    }
+}
+
+/// 消息编解码器，直接在槽位内存上读写
+pub trait Codec: Send + Sync {
+    /// 编解码方式
+    fn kind(&self) -> CodecKind;
+
+    /// 将消息编码到 `buf`，返回写入的字节数；空间不足时返回错误
+    fn encode(&self, message: &Message, buf: &mut [u8]) -> Result<usize>;
+
+    /// 从 `buf` 解码消息
+    fn decode(&self, buf: &[u8]) -> Result<Message>;
+}
+
+/// bincode 标准配置下编码后的字节数，用于报告超出槽位的数据大小
+pub(crate) fn bincode_len<T: bincode::Encode>(value: &T) -> usize {
+    let mut writer = bincode::enc::write::SizeWriter::default();
+    let _ = bincode::encode_into_writer(value, &mut writer, bincode::config::standard());
+    writer.bytes_written
+}
+
+/// bincode 编解码器
+pub struct BincodeCodec;
+
+impl BincodeCodec {
+    /// 解码时允许声明的最大字节数，远大于槽位上限（1MB）
+    ///
+    /// 缓冲区来自其他进程，被写坏的长度前缀不能触发超大内存分配。
+    pub(crate) const DECODE_LIMIT: usize = 64 * 1024 * 1024;
+}
+
+impl Codec for BincodeCodec {
+    fn kind(&self) -> CodecKind {
+        CodecKind::Bincode
+    }
+
+    fn encode(&self, message: &Message, buf: &mut [u8]) -> Result<usize> {
+        bincode::encode_into_slice(message, buf, bincode::config::standard()).map_err(|e| match e {
+            bincode::error::EncodeError::UnexpectedEnd => PayloadTooLarge {
+                size: bincode_len(message),
+                capacity: buf.len(),
+            }
+            .into(),
+            _ => anyhow::anyhow!("Serialization failed").into(),
+        })
+    }
+
+    fn decode(&self, buf: &[u8]) -> Result<Message> {
+        let config = bincode::config::standard().with_limit::<{ Self::DECODE_LIMIT }>();
+        bincode::decode_from_slice(buf, config)
+            .map(|(message, _)| message)
+            .map_err(|_| anyhow::anyhow!("Deserialization failed").into())
+    }
+}
+
+/// JSON 编解码器
+pub struct JsonCodec;
+
+impl Codec for JsonCodec {
+    fn kind(&self) -> CodecKind {
+        CodecKind::Json
+    }
+
+    fn encode(&self, message: &Message, buf: &mut [u8]) -> Result<usize> {
+        let capacity = buf.len();
+        let mut cursor = Cursor::new(buf);
+        serde_json::to_writer(&mut cursor, message).map_err(|e| -> crate::Error {
+            if e.is_io() {
+                PayloadTooLarge {
+                    size: serde_json::to_vec(message).map_or(0, |json| json.len()),
+                    capacity,
+                }
+                .into()
+            } else {
+                anyhow::anyhow!("Serialization failed: {}", e).into()
+            }
+        })?;
+        Ok(cursor.position() as usize)
+    }
+
+    fn decode(&self, buf: &[u8]) -> Result<Message> {
+        Ok(serde_json::from_slice(buf).context("Deserialization failed")?)
+    }
+}
+
+/// 原始字节编解码器，不依赖任何序列化框架
+pub struct RawCodec;
+
+impl RawCodec {
+    /// 追踪上下文的位置，trace_id 为 0 表示未携带
+    const TRACE_AT: usize = 1 + 8 + 8;
+    const AFFINITY_AT: usize = Self::TRACE_AT + 16 + 8;
+    const HEADER_LEN: usize = Self::AFFINITY_AT + 8;
+}
+
+impl Codec for RawCodec {
+    fn kind(&self) -> CodecKind {
+        CodecKind::Raw
+    }
+
+    fn encode(&self, message: &Message, buf: &mut [u8]) -> Result<usize> {
+        let len = Self::HEADER_LEN + message.data.len();
+        if len > buf.len() {
+            return Err(PayloadTooLarge {
+                size: len,
+                capacity: buf.len(),
+            }
+            .into());
+        }
+
+        buf[0] = message.flag;
+        buf[1..9].copy_from_slice(&message.timestamp.to_le_bytes());
+        buf[9..Self::TRACE_AT].copy_from_slice(&message.expires_at.to_le_bytes());
+        let trace = message.trace.unwrap_or(TraceContext {
+            trace_id: 0,
+            span_id: 0,
+        });
+        buf[Self::TRACE_AT..Self::TRACE_AT + 16].copy_from_slice(&trace.trace_id.to_le_bytes());
+        buf[Self::TRACE_AT + 16..Self::AFFINITY_AT].copy_from_slice(&trace.span_id.to_le_bytes());
+        buf[Self::AFFINITY_AT..Self::HEADER_LEN].copy_from_slice(&message.affinity.to_le_bytes());
+        buf[Self::HEADER_LEN..len].copy_from_slice(&message.data);
+        Ok(len)
+    }
+
+    fn decode(&self, buf: &[u8]) -> Result<Message> {
+        if buf.len() < Self::HEADER_LEN {
+            return Err(anyhow::anyhow!("Deserialization failed: truncated raw message").into());
+        }
+
+        let mut timestamp = [0u8; 8];
+        timestamp.copy_from_slice(&buf[1..9]);
+        let mut expires_at = [0u8; 8];
+        expires_at.copy_from_slice(&buf[9..Self::TRACE_AT]);
+        let mut trace_id = [0u8; 16];
+        trace_id.copy_from_slice(&buf[Self::TRACE_AT..Self::TRACE_AT + 16]);
+        let mut span_id = [0u8; 8];
+        span_id.copy_from_slice(&buf[Self::TRACE_AT + 16..Self::AFFINITY_AT]);
+        let mut affinity = [0u8; 8];
+        affinity.copy_from_slice(&buf[Self::AFFINITY_AT..Self::HEADER_LEN]);
+        let trace = TraceContext {
+            trace_id: u128::from_le_bytes(trace_id),
+            span_id: u64::from_le_bytes(span_id),
+        };
+        Ok(Message {
+            flag: buf[0],
+            data: buf[Self::HEADER_LEN..].to_vec(),
+            timestamp: u64::from_le_bytes(timestamp),
+            expires_at: u64::from_le_bytes(expires_at),
+            trace: (trace.trace_id != 0).then_some(trace),
+            affinity: u64::from_le_bytes(affinity),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codecs_roundtrip() {
+        let message = Message::new(7, "编解码".to_string())
+            .with_ttl(std::time::Duration::from_secs(60))
+            .with_affinity("user-42");
+
+        for kind in [CodecKind::Bincode, CodecKind::Json, CodecKind::Raw] {
+            let codec = kind.codec();
+            let mut buf = [0u8; 256];
+            let len = codec.encode(&message, &mut buf).unwrap();
+            let decoded = codec.decode(&buf[..len]).unwrap();
+
+            assert_eq!(codec.kind(), kind);
+            assert_eq!(decoded.flag, message.flag);
+            assert_eq!(decoded.data, message.data);
+            assert_eq!(decoded.timestamp, message.timestamp);
+            assert_eq!(decoded.expires_at, message.expires_at);
+            assert_eq!(decoded.affinity, message.affinity);
+
+            let mut small = [0u8; 4];
+            assert!(codec.encode(&message, &mut small).is_err());
+        }
+    }
+}
@@ -0,0 +1,350 @@
+//! entry 与 worker 之间的命令 / 回复协议
+//!
+//! 消息数据以 4 字节头部开始：魔数 `M7` + 协议版本（`u16` 小端），其后为 bincode 编码的
+//! [`Command`] 或 [`Response`]。解码时按版本处理：
+//! - 当前版本（[`SCHEMA_VERSION`]）：直接解码
+//! - 旧版本：按旧布局解码后转换为当前结构，新版 worker 可以继续处理旧版 entry 的请求
+//! - 更新的版本：返回 [`SchemaError::UnsupportedVersion`]，不会按错误的布局静默误读
+//!
+//! 版本历史：
+//! - 1：没有头部；回复为 worker 直接写入的原始字节
+//! - 2：引入头部与 [`Response`]；`WsMessage` 增加连接 ID；`TcpPacket` / `UdpPacket` 合并为
+//!   `RawRequest`
+//!
+//! 修改 [`Command`] 或 [`Response`] 的编码布局时必须递增 [`SCHEMA_VERSION`]，并把旧布局
+//! 保留为迁移来源。
+
+use crate::codec::BincodeCodec;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+
+/// 当前协议版本
+pub const SCHEMA_VERSION: u16 = 2;
+
+/// 头部魔数
+const MAGIC: [u8; 2] = *b"M7";
+
+/// 头部长度：魔数 + 版本
+const HEADER_LEN: usize = 4;
+
+/// HTTP 请求命令的消息标志，见 [`Command::flag`]
+pub const FLAG_HTTP: u8 = 1;
+
+/// WebSocket 消息命令的消息标志
+pub const FLAG_WEBSOCKET: u8 = 2;
+
+/// 原始协议请求命令的消息标志
+pub const FLAG_RAW: u8 = 3;
+
+/// MQTT 发布命令的消息标志
+pub const FLAG_MQTT: u8 = 4;
+
+/// 协议编解码失败
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SchemaError {
+    #[error("不支持的协议版本 {found}（最高支持 {supported}）")]
+    UnsupportedVersion { found: u16, supported: u16 },
+    #[error("协议数据解码失败: {0}")]
+    Decode(String),
+    #[error("协议数据编码失败: {0}")]
+    Encode(String),
+}
+
+/// 原始协议的传输层
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, bincode::Encode, bincode::Decode,
+)]
+pub enum RawTransport {
+    Tcp,
+    Udp,
+}
+
+/// entry 发往 worker 的命令
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, bincode::Encode, bincode::Decode)]
+pub enum Command {
+    /// HTTP 请求命令
+    HttpRequest {
+        id: u64,
+        path: String,
+        method: String,
+        body: Option<String>,
+        headers: Option<String>,
+    },
+    /// WebSocket 消息，worker 可经推送句柄向 `connection` 发送消息
+    WsMessage {
+        id: u64,
+        connection: u64,
+        client: String,
+        payload: String,
+    },
+    /// 原始协议请求：TCP 长度前缀帧或单个 UDP 数据报
+    RawRequest {
+        id: u64,
+        transport: RawTransport,
+        peer: SocketAddr,
+        /// TCP 连接 ID，UDP 为 0
+        connection: u64,
+        payload: Vec<u8>,
+    },
+    /// MQTT 发布消息
+    MqttPublish {
+        id: u64,
+        topic: String,
+        payload: Vec<u8>,
+    },
+}
+
+impl Command {
+    /// 任务 ID
+    pub fn id(&self) -> u64 {
+        match self {
+            Command::HttpRequest { id, .. }
+            | Command::WsMessage { id, .. }
+            | Command::RawRequest { id, .. }
+            | Command::MqttPublish { id, .. } => *id,
+        }
+    }
+
+    /// 携带该命令的消息所用的 [`Message::flag`](crate::Message::flag)
+    ///
+    /// worker 可按标志分派而不必先解码命令，见 [`Router`](crate::router::Router)；
+    /// 旧版 entry 发送的命令标志为 0。
+    pub fn flag(&self) -> u8 {
+        match self {
+            Command::HttpRequest { .. } => FLAG_HTTP,
+            Command::WsMessage { .. } => FLAG_WEBSOCKET,
+            Command::RawRequest { .. } => FLAG_RAW,
+            Command::MqttPublish { .. } => FLAG_MQTT,
+        }
+    }
+
+    /// 以当前协议版本编码
+    pub fn encode(&self) -> Result<Vec<u8>, SchemaError> {
+        encode_tagged(self)
+    }
+
+    /// 解码任意已知版本的命令
+    pub fn decode(data: &[u8]) -> Result<Self, SchemaError> {
+        match split_header(data)? {
+            (1, payload) => decode_exact::<v1::Command>(payload).map(Into::into),
+            (_, payload) => decode_exact(payload),
+        }
+    }
+}
+
+/// worker 对命令的回复
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, bincode::Encode, bincode::Decode)]
+pub enum Response {
+    /// 处理成功，`body` 为业务数据（通常为 JSON）
+    Ok { id: u64, body: Vec<u8> },
+    /// 处理失败，`code` 沿用 HTTP 状态码的含义
+    Error { id: u64, code: u16, message: String },
+}
+
+impl Response {
+    /// 以当前协议版本编码
+    pub fn encode(&self) -> Result<Vec<u8>, SchemaError> {
+        encode_tagged(self)
+    }
+
+    /// 解码任意已知版本的回复；版本 1 的原始字节视为成功回复
+    pub fn decode(data: &[u8]) -> Result<Self, SchemaError> {
+        match split_header(data)? {
+            (1, payload) => Ok(Response::Ok {
+                id: 0,
+                body: payload.to_vec(),
+            }),
+            (_, payload) => decode_exact(payload),
+        }
+    }
+}
+
+fn encode_tagged<T: bincode::Encode>(value: &T) -> Result<Vec<u8>, SchemaError> {
+    let mut data = Vec::with_capacity(64);
+    data.extend_from_slice(&MAGIC);
+    data.extend_from_slice(&SCHEMA_VERSION.to_le_bytes());
+    bincode::encode_into_std_write(value, &mut data, bincode::config::standard())
+        .map_err(|e| SchemaError::Encode(e.to_string()))?;
+    Ok(data)
+}
+
+/// 拆出协议版本与负载，没有头部的数据为版本 1
+///
+/// 版本 1 的命令以 bincode 枚举序号（小于 `M`）开头，不会被误认为头部。
+fn split_header(data: &[u8]) -> Result<(u16, &[u8]), SchemaError> {
+    if data.len() < HEADER_LEN || data[..2] != MAGIC {
+        return Ok((1, data));
+    }
+    let version = u16::from_le_bytes([data[2], data[3]]);
+    if version == 0 || version > SCHEMA_VERSION {
+        return Err(SchemaError::UnsupportedVersion {
+            found: version,
+            supported: SCHEMA_VERSION,
+        });
+    }
+    Ok((version, &data[HEADER_LEN..]))
+}
+
+/// 解码并要求恰好消耗全部数据，布局不一致时不会静默截断
+fn decode_exact<T: bincode::Decode<()>>(payload: &[u8]) -> Result<T, SchemaError> {
+    let config = bincode::config::standard().with_limit::<{ BincodeCodec::DECODE_LIMIT }>();
+    let (value, consumed) = bincode::decode_from_slice(payload, config)
+        .map_err(|e| SchemaError::Decode(e.to_string()))?;
+    if consumed != payload.len() {
+        return Err(SchemaError::Decode(format!(
+            "数据末尾多出 {} 字节",
+            payload.len() - consumed
+        )));
+    }
+    Ok(value)
+}
+
+/// 版本 1 的布局，仅用于解码旧数据
+mod v1 {
+    use super::RawTransport;
+    use std::net::SocketAddr;
+
+    #[derive(bincode::Encode, bincode::Decode)]
+    pub(super) enum Command {
+        HttpRequest {
+            id: u64,
+            path: String,
+            method: String,
+            body: Option<String>,
+            headers: Option<String>,
+        },
+        WsMessage {
+            id: u64,
+            client: String,
+            payload: String,
+        },
+        TcpPacket {
+            id: u64,
+            peer: SocketAddr,
+            payload: Vec<u8>,
+        },
+        UdpPacket {
+            id: u64,
+            peer: SocketAddr,
+            payload: Vec<u8>,
+        },
+        MqttPublish {
+            id: u64,
+            topic: String,
+            payload: Vec<u8>,
+        },
+    }
+
+    impl From<Command> for super::Command {
+        fn from(command: Command) -> Self {
+            match command {
+                Command::HttpRequest {
+                    id,
+                    path,
+                    method,
+                    body,
+                    headers,
+                } => Self::HttpRequest {
+                    id,
+                    path,
+                    method,
+                    body,
+                    headers,
+                },
+                Command::WsMessage {
+                    id,
+                    client,
+                    payload,
+                } => Self::WsMessage {
+                    id,
+                    connection: 0,
+                    client,
+                    payload,
+                },
+                Command::TcpPacket { id, peer, payload } => Self::RawRequest {
+                    id,
+                    transport: RawTransport::Tcp,
+                    peer,
+                    connection: 0,
+                    payload,
+                },
+                Command::UdpPacket { id, peer, payload } => Self::RawRequest {
+                    id,
+                    transport: RawTransport::Udp,
+                    peer,
+                    connection: 0,
+                    payload,
+                },
+                Command::MqttPublish { id, topic, payload } => {
+                    Self::MqttPublish { id, topic, payload }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_versioned_decode() {
+        let command = Command::MqttPublish {
+            id: 7,
+            topic: "devices/1".to_string(),
+            payload: b"on".to_vec(),
+        };
+        assert_eq!(
+            Command::decode(&command.encode().unwrap()).unwrap(),
+            command
+        );
+
+        // 版本 1 的命令没有头部，解码后迁移到当前结构
+        let peer: SocketAddr = "127.0.0.1:9000".parse().unwrap();
+        let legacy = bincode::encode_to_vec(
+            v1::Command::UdpPacket {
+                id: 3,
+                peer,
+                payload: b"ping".to_vec(),
+            },
+            bincode::config::standard(),
+        )
+        .unwrap();
+        assert_eq!(
+            Command::decode(&legacy).unwrap(),
+            Command::RawRequest {
+                id: 3,
+                transport: RawTransport::Udp,
+                peer,
+                connection: 0,
+                payload: b"ping".to_vec(),
+            }
+        );
+
+        // 更新的版本明确报错，而不是按当前布局误读
+        let mut newer = command.encode().unwrap();
+        newer[2..4].copy_from_slice(&(SCHEMA_VERSION + 1).to_le_bytes());
+        assert_eq!(
+            Command::decode(&newer).unwrap_err(),
+            SchemaError::UnsupportedVersion {
+                found: SCHEMA_VERSION + 1,
+                supported: SCHEMA_VERSION,
+            }
+        );
+
+        // 旧版 worker 的原始回复视为成功
+        assert_eq!(
+            Response::decode(b"{\"ok\":true}").unwrap(),
+            Response::Ok {
+                id: 0,
+                body: b"{\"ok\":true}".to_vec(),
+            }
+        );
+        let error = Response::Error {
+            id: 7,
+            code: 500,
+            message: "boom".to_string(),
+        };
+        assert_eq!(Response::decode(&error.encode().unwrap()).unwrap(), error);
+    }
+}
@@ -1,11 +1,22 @@
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::sync::OnceLock;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
 
-/// 全局配置实例
-static CONFIG: OnceLock<Config> = OnceLock::new();
+/// 全局配置实例，热加载时整体替换
+static CONFIG: OnceLock<RwLock<Arc<Config>>> = OnceLock::new();
+
+/// 配置变更订阅者：(配置段名称, 发送端)
+static SUBSCRIBERS: Mutex<Vec<(String, async_channel::Sender<ConfigChange>)>> =
+    Mutex::new(Vec::new());
+
+/// 按顺序查找的配置文件路径
+const CONFIG_PATHS: [&str; 2] = ["config.toml", "./config/config.toml"];
+
+/// 环境变量覆盖项的前缀，格式为 `MI7__SECTION__KEY`
+const ENV_PREFIX: &str = "MI7__";
 
 /// MI7 系统配置结构 - 基于 HashMap 的灵活配置系统
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,7 +27,7 @@ pub struct Config {
 }
 
 /// 配置值类型，支持多种数据类型
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum ConfigValue {
     String(String),
@@ -26,6 +37,29 @@ pub enum ConfigValue {
 }
 
 impl ConfigValue {
+    /// 从环境变量或命令行中的字符串推断类型：布尔、整数、浮点数，否则为字符串
+    pub fn parse(raw: &str) -> Self {
+        if let Ok(b) = raw.parse::<bool>() {
+            ConfigValue::Boolean(b)
+        } else if let Ok(i) = raw.parse::<i64>() {
+            ConfigValue::Integer(i)
+        } else if let Ok(f) = raw.parse::<f64>() {
+            ConfigValue::Float(f)
+        } else {
+            ConfigValue::String(raw.to_string())
+        }
+    }
+
+    /// 转换为 TOML 值
+    fn to_toml(&self) -> toml::Value {
+        match self {
+            ConfigValue::String(s) => toml::Value::String(s.clone()),
+            ConfigValue::Integer(i) => toml::Value::Integer(*i),
+            ConfigValue::Float(f) => toml::Value::Float(*f),
+            ConfigValue::Boolean(b) => toml::Value::Boolean(*b),
+        }
+    }
+
     /// 获取字符串值
     pub fn as_string(&self) -> Option<String> {
         match self {
@@ -118,6 +152,18 @@ impl Default for Config {
         http.insert("max_connections".to_string(), ConfigValue::Integer(1000));
         sections.insert("http".to_string(), http);
 
+        // 停止协调配置
+        let mut shutdown = HashMap::new();
+        shutdown.insert("control_name".to_string(), ConfigValue::String("mi7_control".to_string()));
+        shutdown.insert("timeout_seconds".to_string(), ConfigValue::Integer(10));
+        sections.insert("shutdown".to_string(), shutdown);
+
+        // 槽位租约回收配置
+        let mut janitor = HashMap::new();
+        janitor.insert("lease_timeout_seconds".to_string(), ConfigValue::Integer(30));
+        janitor.insert("interval_seconds".to_string(), ConfigValue::Integer(5));
+        sections.insert("janitor".to_string(), janitor);
+
         Self { sections }
     }
 }
@@ -154,7 +200,12 @@ impl Config {
                         section_map.insert(key, config_value);
                     }
                     
-                    config.sections.insert(section_name, section_map);
+                    // 按键合并，文件中未出现的键保留默认值
+                    config
+                        .sections
+                        .entry(section_name)
+                        .or_default()
+                        .extend(section_map);
                 }
             }
         }
@@ -171,13 +222,7 @@ impl Config {
             let mut section_table = toml::value::Table::new();
             
             for (key, value) in section_map {
-                let toml_value = match value {
-                    ConfigValue::String(s) => toml::Value::String(s.clone()),
-                    ConfigValue::Integer(i) => toml::Value::Integer(*i),
-                    ConfigValue::Float(f) => toml::Value::Float(*f),
-                    ConfigValue::Boolean(b) => toml::Value::Boolean(*b),
-                };
-                section_table.insert(key.clone(), toml_value);
+                section_table.insert(key.clone(), value.to_toml());
             }
             
             toml_table.insert(section_name.clone(), toml::Value::Table(section_table));
@@ -202,60 +247,54 @@ impl Config {
 
         // 验证entry配置
         if let Some(entry_section) = self.sections.get("entry") {
-            if let Some(interface_name) = entry_section.get("interface_name") {
-                if let Some(name) = interface_name.as_string() {
-                    if name.is_empty() {
-                        return Err(ConfigError::Validation("entry.interface_name 不能为空".to_string()));
-                    }
-                }
+            if let Some(interface_name) = entry_section.get("interface_name")
+                && let Some(name) = interface_name.as_string()
+                && name.is_empty()
+            {
+                return Err(ConfigError::Validation("entry.interface_name 不能为空".to_string()));
             }
 
-            if let Some(log_level) = entry_section.get("log_level") {
-                if let Some(level) = log_level.as_string() {
-                    if !valid_levels.contains(&level.as_str()) {
-                        return Err(ConfigError::Validation(format!(
-                            "无效的 entry.log_level: {}，有效值: {:?}",
-                            level, valid_levels
-                        )));
-                    }
-                }
+            if let Some(log_level) = entry_section.get("log_level")
+                && let Some(level) = log_level.as_string()
+                && !valid_levels.contains(&level.as_str())
+            {
+                return Err(ConfigError::Validation(format!(
+                    "无效的 entry.log_level: {}，有效值: {:?}",
+                    level, valid_levels
+                )));
             }
         }
 
         // 验证worker配置
         if let Some(worker_section) = self.sections.get("worker") {
-            if let Some(interface_name) = worker_section.get("interface_name") {
-                if let Some(name) = interface_name.as_string() {
-                    if name.is_empty() {
-                        return Err(ConfigError::Validation("worker.interface_name 不能为空".to_string()));
-                    }
-                }
+            if let Some(interface_name) = worker_section.get("interface_name")
+                && let Some(name) = interface_name.as_string()
+                && name.is_empty()
+            {
+                return Err(ConfigError::Validation("worker.interface_name 不能为空".to_string()));
             }
 
-            if let Some(log_level) = worker_section.get("log_level") {
-                if let Some(level) = log_level.as_string() {
-                    if !valid_levels.contains(&level.as_str()) {
-                        return Err(ConfigError::Validation(format!(
-                            "无效的 worker.log_level: {}，有效值: {:?}",
-                            level, valid_levels
-                        )));
-                    }
-                }
+            if let Some(log_level) = worker_section.get("log_level")
+                && let Some(level) = log_level.as_string()
+                && !valid_levels.contains(&level.as_str())
+            {
+                return Err(ConfigError::Validation(format!(
+                    "无效的 worker.log_level: {}，有效值: {:?}",
+                    level, valid_levels
+                )));
             }
         }
 
         // 验证logging配置
-        if let Some(logging_section) = self.sections.get("logging") {
-            if let Some(log_level) = logging_section.get("level") {
-                if let Some(level) = log_level.as_string() {
-                    if !valid_levels.contains(&level.as_str()) {
-                        return Err(ConfigError::Validation(format!(
-                            "无效的 logging.level: {}，有效值: {:?}",
-                            level, valid_levels
-                        )));
-                    }
-                }
-            }
+        if let Some(logging_section) = self.sections.get("logging")
+            && let Some(log_level) = logging_section.get("level")
+            && let Some(level) = log_level.as_string()
+            && !valid_levels.contains(&level.as_str())
+        {
+            return Err(ConfigError::Validation(format!(
+                "无效的 logging.level: {}，有效值: {:?}",
+                level, valid_levels
+            )));
         }
 
         Ok(())
@@ -270,7 +309,7 @@ impl Config {
     pub fn set(&mut self, section: &str, key: &str, value: ConfigValue) {
         self.sections
             .entry(section.to_string())
-            .or_insert_with(HashMap::new)
+            .or_default()
             .insert(key.to_string(), value);
     }
 
@@ -283,6 +322,141 @@ impl Config {
     pub fn get_keys(&self, section: &str) -> Option<Vec<&String>> {
         self.sections.get(section).map(|s| s.keys().collect())
     }
+
+    /// 将整个配置段反序列化为类型化结构
+    ///
+    /// 配置段不存在时按空表处理，字段可用 `#[serde(default)]` 提供默认值；
+    /// 缺少必填字段、类型不符或数值越界时返回 [`ConfigError::Parse`]。
+    pub fn section<T: DeserializeOwned>(&self, name: &str) -> Result<T, ConfigError> {
+        let table: toml::value::Table = self
+            .sections
+            .get(name)
+            .map(|section| {
+                section
+                    .iter()
+                    .map(|(key, value)| (key.clone(), value.to_toml()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        toml::Value::Table(table)
+            .try_into()
+            .map_err(|e| ConfigError::Parse(format!("配置段 [{}]: {}", name, e)))
+    }
+
+    /// 按层级加载配置：默认值 < 配置文件 < `MI7__SECTION__KEY` 环境变量 <
+    /// 命令行 `--set section.key=value`
+    ///
+    /// `args` 为命令行参数（不含程序名），其中与 `--set` 无关的参数被忽略。
+    pub fn load_layered<I, S>(args: I) -> Result<Self, ConfigError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut config = load_config()?;
+        config.apply_env(std::env::vars());
+        config.apply_args(args)?;
+        Ok(config)
+    }
+
+    /// 应用 `MI7__SECTION__KEY=value` 形式的环境变量，段名与键名转为小写
+    pub fn apply_env<I>(&mut self, vars: I)
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        for (name, value) in vars {
+            let Some(path) = name.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            match path.split_once("__") {
+                Some((section, key)) if !section.is_empty() && !key.is_empty() => {
+                    self.set(
+                        &section.to_lowercase(),
+                        &key.to_lowercase(),
+                        ConfigValue::parse(&value),
+                    );
+                }
+                _ => eprintln!("警告: 忽略格式无效的配置环境变量 {}", name),
+            }
+        }
+    }
+
+    /// 应用命令行中的 `--set section.key=value`（或 `--set=section.key=value`）覆盖项
+    pub fn apply_args<I, S>(&mut self, args: I) -> Result<(), ConfigError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let assignment = match arg.as_ref() {
+                "--set" => args
+                    .next()
+                    .map(|value| value.as_ref().to_string())
+                    .ok_or_else(|| {
+                        ConfigError::Parse("--set 缺少 section.key=value".to_string())
+                    })?,
+                other => match other.strip_prefix("--set=") {
+                    Some(value) => value.to_string(),
+                    None => continue,
+                },
+            };
+
+            let parsed = assignment
+                .split_once('=')
+                .and_then(|(path, value)| Some((path.split_once('.')?, value)));
+            match parsed {
+                Some(((section, key), value)) if !section.is_empty() && !key.is_empty() => {
+                    self.set(section, key, ConfigValue::parse(value));
+                }
+                _ => {
+                    return Err(ConfigError::Parse(format!(
+                        "无效的覆盖项 {}，格式应为 section.key=value",
+                        assignment
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 逐项比较，返回从 `self` 到 `other` 的变更（按段名、键名排序）
+    pub fn diff(&self, other: &Config) -> Vec<ConfigChange> {
+        let empty = HashMap::new();
+        let mut sections: Vec<&String> =
+            self.sections.keys().chain(other.sections.keys()).collect();
+        sections.sort();
+        sections.dedup();
+
+        let mut changes = Vec::new();
+        for section in sections {
+            let old = self.sections.get(section).unwrap_or(&empty);
+            let new = other.sections.get(section).unwrap_or(&empty);
+            let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let (old, new) = (old.get(key), new.get(key));
+                if old != new {
+                    changes.push(ConfigChange {
+                        section: section.clone(),
+                        key: key.clone(),
+                        old: old.cloned(),
+                        new: new.cloned(),
+                    });
+                }
+            }
+        }
+        changes
+    }
+}
+
+/// 配置项的一次变更，`old` 为 `None` 表示新增，`new` 为 `None` 表示删除
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigChange {
+    pub section: String,
+    pub key: String,
+    pub old: Option<ConfigValue>,
+    pub new: Option<ConfigValue>,
 }
 
 /// 配置错误类型
@@ -298,30 +472,55 @@ pub enum ConfigError {
     Serialize(String),
     #[error("配置验证错误: {0}")]
     Validation(String),
+    #[error("配置监听错误: {0}")]
+    Watch(String),
 }
 
 /// 初始化全局配置
+///
+/// 按 [`Config::load_layered`] 的层级加载，命令行覆盖项取自当前进程的参数。
 pub fn init_config() -> Result<(), ConfigError> {
-    let config = load_config()?;
+    let config = load_process_config()?;
     config.validate()?;
 
     CONFIG
-        .set(config)
+        .set(RwLock::new(Arc::new(config)))
         .map_err(|_| ConfigError::Validation("配置已经初始化".to_string()))?;
 
     Ok(())
 }
 
+/// 按层级加载当前进程的配置，命令行参数取自 `std::env::args`
+fn load_process_config() -> Result<Config, ConfigError> {
+    Config::load_layered(std::env::args().skip(1))
+}
+
+/// 去掉 `--set` 覆盖项后剩余的命令行参数
+pub fn positional_args<I, S>(args: I) -> Vec<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut args = args.into_iter();
+    let mut positional = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--set" => {
+                args.next();
+            }
+            other if other.starts_with("--set=") => {}
+            other => positional.push(other.to_string()),
+        }
+    }
+    positional
+}
+
 /// 从文件或默认值加载配置
 pub fn load_config() -> Result<Config, ConfigError> {
-    let config_paths = ["config.toml", "./config/config.toml"];
-
     // 尝试从配置文件加载
-    for path in &config_paths {
-        if Path::new(path).exists() {
-            println!("从配置文件加载: {}", path);
-            return Config::load_from_file(path);
-        }
+    if let Some(path) = config_path() {
+        println!("从配置文件加载: {}", path.display());
+        return Config::load_from_file(path);
     }
 
     // 如果没有找到配置文件，使用默认配置
@@ -329,9 +528,144 @@ pub fn load_config() -> Result<Config, ConfigError> {
     Ok(Config::default())
 }
 
-/// 获取全局配置实例
-pub fn get_config() -> &'static Config {
-    CONFIG.get().expect("配置未初始化，请先调用 init_config()")
+/// 第一个存在的配置文件路径
+fn config_path() -> Option<PathBuf> {
+    CONFIG_PATHS
+        .iter()
+        .map(PathBuf::from)
+        .find(|path| path.exists())
+}
+
+/// 获取全局配置实例的快照，热加载后再次调用才能看到新配置
+pub fn get_config() -> Arc<Config> {
+    CONFIG
+        .get()
+        .expect("配置未初始化，请先调用 init_config()")
+        .read()
+        .unwrap()
+        .clone()
+}
+
+/// 订阅指定配置段的变更，热加载时该段每个变更项单独投递
+///
+/// 接收端既可在异步任务中 `recv().await`，也可在普通线程中 `recv_blocking()`；
+/// 接收端被丢弃后自动取消订阅。
+pub fn subscribe(section: &str) -> async_channel::Receiver<ConfigChange> {
+    let (tx, rx) = async_channel::unbounded();
+    SUBSCRIBERS.lock().unwrap().push((section.to_string(), tx));
+    rx
+}
+
+/// 将变更投递给对应配置段的订阅者
+fn notify_subscribers(changes: &[ConfigChange]) {
+    let mut subscribers = SUBSCRIBERS.lock().unwrap();
+    subscribers.retain(|(section, tx)| {
+        changes
+            .iter()
+            .filter(|change| &change.section == section)
+            .all(|change| tx.try_send(change.clone()).is_ok())
+    });
+}
+
+/// 重新加载配置文件：校验通过后替换全局配置并通知订阅者，返回变更列表
+///
+/// 环境变量与命令行覆盖项重新应用，仍优先于配置文件。解析或校验失败时保留当前配置。
+pub fn reload() -> Result<Vec<ConfigChange>, ConfigError> {
+    let lock = CONFIG
+        .get()
+        .ok_or_else(|| ConfigError::Validation("配置未初始化".to_string()))?;
+
+    let config = load_process_config()?;
+    config.validate()?;
+
+    let changes = {
+        let mut current = lock.write().unwrap();
+        let changes = current.diff(&config);
+        *current = Arc::new(config);
+        changes
+    };
+    notify_subscribers(&changes);
+    Ok(changes)
+}
+
+/// 配置文件监听句柄，`Drop` 时停止监听
+pub struct ConfigWatcher {
+    _watcher: notify::RecommendedWatcher,
+    path: PathBuf,
+}
+
+impl ConfigWatcher {
+    /// 被监听的配置文件
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// 监听配置文件，文件变化时自动 [`reload`]
+///
+/// 监听的是配置文件所在目录，编辑器以“写临时文件再改名”的方式保存时同样生效。
+pub fn watch() -> Result<ConfigWatcher, ConfigError> {
+    use notify::{EventKind, RecursiveMode, Watcher};
+
+    let path = config_path().ok_or_else(|| ConfigError::Watch("未找到配置文件".to_string()))?;
+    let file_name = path.file_name().map(|name| name.to_os_string());
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+            || !event
+                .paths
+                .iter()
+                .any(|changed| changed.file_name().map(|name| name.to_os_string()) == file_name)
+        {
+            return;
+        }
+        match reload() {
+            Ok(changes) if !changes.is_empty() => {
+                tracing::info!("配置已重新加载，{} 项变更", changes.len());
+                for change in &changes {
+                    tracing::debug!("配置变更: {:?}", change);
+                }
+            }
+            Ok(_) => {}
+            Err(e) => tracing::warn!("重新加载配置失败，保留当前配置: {}", e),
+        }
+    })
+    .map_err(|e| ConfigError::Watch(e.to_string()))?;
+    watcher
+        .watch(&dir, RecursiveMode::NonRecursive)
+        .map_err(|e| ConfigError::Watch(e.to_string()))?;
+
+    Ok(ConfigWatcher {
+        _watcher: watcher,
+        path,
+    })
+}
+
+/// 将全局配置中的整个配置段反序列化为类型化结构，见 [`Config::section`]
+///
+/// # 示例
+/// ```rust,no_run
+/// use mi7::config;
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct HttpConfig {
+///     port: u16,
+///     #[serde(default)]
+///     bind_address: String,
+/// }
+///
+/// let http: HttpConfig = config::section("http").unwrap();
+/// ```
+pub fn section<T: DeserializeOwned>(name: &str) -> Result<T, ConfigError> {
+    get_config().section(name)
 }
 
 /// 通用配置读取函数：获取字符串值
@@ -341,7 +675,9 @@ pub fn get_config() -> &'static Config {
 /// * `key` - 配置键名称 (如 "name", "level", "port", "interface_name")
 ///
 /// # 示例
-/// ```rust
+/// ```rust,no_run
+/// use mi7::config;
+///
 /// let queue_name = config::string("shared_memory", "name");
 /// let log_level = config::string("logging", "level");
 /// let hello_value = config::string("worker", "hello"); // 动态配置项
@@ -367,7 +703,9 @@ pub fn string(section: &str, key: &str) -> String {
 /// * `default` - 默认值
 ///
 /// # 示例
-/// ```rust
+/// ```rust,no_run
+/// use mi7::config;
+///
 /// let hello_value = config::string_or("worker", "hello", "world");
 /// ```
 pub fn string_or(section: &str, key: &str, default: &str) -> String {
@@ -386,7 +724,9 @@ pub fn string_or(section: &str, key: &str, default: &str) -> String {
 /// * `key` - 配置键名称 (如 "slot_size", "port", "capacity")
 ///
 /// # 示例
-/// ```rust
+/// ```rust,no_run
+/// use mi7::config;
+///
 /// let slot_size = config::int("shared_memory", "slot_size");
 /// let http_port = config::int("http", "port");
 /// ```
@@ -410,7 +750,9 @@ pub fn int(section: &str, key: &str) -> i64 {
 /// * `default` - 默认值
 ///
 /// # 示例
-/// ```rust
+/// ```rust,no_run
+/// use mi7::config;
+///
 /// let port = config::int_or("http", "port", 8080);
 /// ```
 pub fn int_or(section: &str, key: &str, default: i64) -> i64 {
@@ -429,7 +771,9 @@ pub fn int_or(section: &str, key: &str, default: i64) -> i64 {
 /// * `key` - 配置键名称 (如 "console_output", "persistent")
 ///
 /// # 示例
-/// ```rust
+/// ```rust,no_run
+/// use mi7::config;
+///
 /// let console_output = config::bool("logging", "console_output");
 /// let persistent = config::bool("queue", "persistent");
 /// ```
@@ -453,7 +797,9 @@ pub fn bool(section: &str, key: &str) -> bool {
 /// * `default` - 默认值
 ///
 /// # 示例
-/// ```rust
+/// ```rust,no_run
+/// use mi7::config;
+///
 /// let debug_enabled = config::bool_or("logging", "debug", false);
 /// ```
 pub fn bool_or(section: &str, key: &str, default: bool) -> bool {
@@ -475,7 +821,9 @@ impl ConfigAccessor {
     /// * `key` - 配置键，格式为 "section.key" 或直接为 "key"（默认在 worker 段中查找）
     /// 
     /// # 示例
-    /// ```rust
+    /// ```rust,no_run
+    /// use mi7::config;
+    ///
     /// let config = config::ConfigAccessor;
     /// let hello = config.string("hello");  // 在 worker 段中查找
     /// let name = config.string("shared_memory.name");  // 在 shared_memory 段中查找
@@ -537,3 +885,120 @@ impl ConfigAccessor {
 
 /// 全局配置访问器实例
 pub static CONFIG_ACCESSOR: ConfigAccessor = ConfigAccessor;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_delivered_to_section_subscribers() {
+        let old = Config::default();
+        let mut new = old.clone();
+        new.set("queue", "high_watermark", ConfigValue::Integer(80));
+        new.set("logging", "level", ConfigValue::String("debug".to_string()));
+        new.sections.get_mut("http").unwrap().remove("port");
+
+        let changes = old.diff(&new);
+        assert_eq!(changes.len(), 3);
+        assert_eq!(
+            changes[0],
+            ConfigChange {
+                section: "http".to_string(),
+                key: "port".to_string(),
+                old: Some(ConfigValue::Integer(8888)),
+                new: None,
+            }
+        );
+        assert_eq!(
+            changes[1].old,
+            Some(ConfigValue::String("info".to_string()))
+        );
+        assert_eq!(changes[2].old, None);
+        assert!(new.diff(&new).is_empty());
+
+        let queue = subscribe("queue");
+        notify_subscribers(&changes);
+        assert_eq!(queue.try_recv().unwrap().key, "high_watermark");
+        assert!(queue.try_recv().is_err());
+
+        // 接收端丢弃后自动取消订阅
+        drop(queue);
+        notify_subscribers(&changes);
+        assert!(
+            SUBSCRIBERS
+                .lock()
+                .unwrap()
+                .iter()
+                .all(|(section, _)| section != "queue")
+        );
+    }
+
+    #[test]
+    fn test_layered_overrides() {
+        let mut config = Config::default();
+        config.set("http", "port", ConfigValue::Integer(9000));
+
+        // 环境变量覆盖配置文件
+        config.apply_env([
+            ("MI7__HTTP__PORT".to_string(), "9100".to_string()),
+            ("MI7__LOGGING__LEVEL".to_string(), "debug".to_string()),
+            ("MI7__INVALID".to_string(), "x".to_string()),
+            ("PATH".to_string(), "/usr/bin".to_string()),
+        ]);
+        assert_eq!(
+            config.get("http", "port").and_then(ConfigValue::as_int),
+            Some(9100)
+        );
+
+        // 命令行覆盖环境变量
+        let args = [
+            "worker-1",
+            "--set",
+            "http.port=9200",
+            "--set=queue.enabled=false",
+        ];
+        config.apply_args(args).unwrap();
+        assert_eq!(
+            config.get("http", "port").and_then(ConfigValue::as_int),
+            Some(9200)
+        );
+        assert_eq!(
+            config.get("logging", "level"),
+            Some(&ConfigValue::String("debug".to_string()))
+        );
+        assert_eq!(
+            config.get("queue", "enabled"),
+            Some(&ConfigValue::Boolean(false))
+        );
+        assert_eq!(positional_args(args), vec!["worker-1".to_string()]);
+
+        assert!(config.apply_args(["--set", "no_section=1"]).is_err());
+        assert!(config.apply_args(["--set"]).is_err());
+        assert_eq!(ConfigValue::parse("1.5"), ConfigValue::Float(1.5));
+    }
+
+    #[test]
+    fn test_typed_section() {
+        #[derive(Debug, Deserialize)]
+        struct Http {
+            port: u16,
+            bind_address: String,
+            #[serde(default = "default_retries")]
+            retries: u32,
+        }
+        fn default_retries() -> u32 {
+            3
+        }
+
+        let mut config = Config::default();
+        let http: Http = config.section("http").unwrap();
+        assert_eq!(http.port, 8888);
+        assert_eq!(http.bind_address, "0.0.0.0");
+        assert_eq!(http.retries, 3);
+
+        // 数值越界与缺少必填字段都报错，而不是静默回退
+        config.set("http", "port", ConfigValue::Integer(70000));
+        assert!(config.section::<Http>("http").is_err());
+        assert!(config.section::<Http>("missing").is_err());
+    }
+}
@@ -0,0 +1,281 @@
+//! 负载加密（静态加密）
+//!
+//! 共享内存段对同一主机上有权限的进程可见。启用加密后，写入槽位与寄存箱 box 的数据
+//! 以 XChaCha20-Poly1305 加密，读取时解密并校验认证标签；认证失败（数据被篡改或密钥不符）
+//! 以 [`TokioIPCError::CorruptedData`] 报告。
+//!
+//! 密钥只保存在各进程内存中：共享内存头部只记录加密算法与密钥标识（[`PayloadKey::id`]），
+//! 连接方据此确认自己持有同一把密钥。进程启动时调用 [`init_from_config`] 从 `[encryption]`
+//! 配置段加载密钥，此后本进程创建的共享内存管道自动加密。
+//!
+//! 密文布局：`密文 || nonce (24 字节) || 认证标签 (16 字节)`，每条数据使用随机 nonce。
+
+use crate::config;
+use crate::error::Result;
+use crate::shared_slot::TokioIPCError;
+use anyhow::Context;
+use chacha20poly1305::aead::{AeadCore, AeadInPlace, KeyInit, OsRng};
+use chacha20poly1305::{Tag, XChaCha20Poly1305, XNonce};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+
+/// 加密算法，以 `u32` 记录在共享内存头部
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encryption {
+    /// 不加密（默认）
+    #[default]
+    None = 0,
+    /// XChaCha20-Poly1305，随机 nonce 不会重复
+    XChaCha20Poly1305 = 1,
+}
+
+impl Encryption {
+    /// 从头部记录的值还原，未知值返回 `None`（无法处理的算法不能当作明文读取）
+    pub fn from_u32(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(Encryption::None),
+            1 => Some(Encryption::XChaCha20Poly1305),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Encryption {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(Encryption::None),
+            "xchacha20poly1305" | "xchacha20-poly1305" | "xchacha20" => {
+                Ok(Encryption::XChaCha20Poly1305)
+            }
+            _ => Err(format!(
+                "不支持的加密算法: '{}'. 支持: none, xchacha20poly1305",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Encryption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Encryption::None => write!(f, "none"),
+            Encryption::XChaCha20Poly1305 => write!(f, "xchacha20poly1305"),
+        }
+    }
+}
+
+/// 256 位负载密钥，丢弃时清零
+pub struct PayloadKey([u8; 32]);
+
+impl PayloadKey {
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// 解析 64 个十六进制字符
+    pub fn from_hex(hex: &str) -> Result<Self> {
+        let hex = hex.trim();
+        if hex.len() != 64 || !hex.is_ascii() {
+            return Err(anyhow::anyhow!("密钥应为 64 个十六进制字符").into());
+        }
+        let mut bytes = [0u8; 32];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+                .map_err(|_| anyhow::anyhow!("密钥包含非十六进制字符"))?;
+        }
+        Ok(Self(bytes))
+    }
+
+    /// 读取密钥文件：32 字节原始密钥，或 64 个十六进制字符
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let data =
+            std::fs::read(path).with_context(|| format!("读取密钥文件 {} 失败", path.display()))?;
+        if let Ok(bytes) = <[u8; 32]>::try_from(data.as_slice()) {
+            return Ok(Self(bytes));
+        }
+        let text = std::str::from_utf8(&data)
+            .map_err(|_| anyhow::anyhow!("密钥文件 {} 格式无效", path.display()))?;
+        Self::from_hex(text).map_err(|e| e.context(format!("密钥文件 {} 格式无效", path.display())))
+    }
+
+    /// 密钥标识，记录在共享内存头部，用于确认连接方持有同一把密钥
+    ///
+    /// 由密钥派生的单向摘要，不能还原密钥。
+    pub fn id(&self) -> u64 {
+        let mut input = Vec::with_capacity(48);
+        input.extend_from_slice(b"mi7-payload-key:");
+        input.extend_from_slice(&self.0);
+        let id = xxhash_rust::xxh64::xxh64(&input, 0);
+        input.fill(0);
+        // 0 表示未加密
+        id.max(1)
+    }
+}
+
+impl Drop for PayloadKey {
+    fn drop(&mut self) {
+        for byte in self.0.iter_mut() {
+            unsafe { std::ptr::write_volatile(byte, 0) };
+        }
+    }
+}
+
+/// 负载加解密器
+pub struct PayloadCipher {
+    algorithm: Encryption,
+    key_id: u64,
+    cipher: XChaCha20Poly1305,
+}
+
+impl PayloadCipher {
+    /// 每条数据增加的字节数：nonce + 认证标签
+    pub const OVERHEAD: usize = 24 + 16;
+
+    pub fn new(key: &PayloadKey) -> Self {
+        Self {
+            algorithm: Encryption::XChaCha20Poly1305,
+            key_id: key.id(),
+            cipher: XChaCha20Poly1305::new((&key.0).into()),
+        }
+    }
+
+    /// 加密算法
+    pub fn algorithm(&self) -> Encryption {
+        self.algorithm
+    }
+
+    /// 密钥标识，见 [`PayloadKey::id`]
+    pub fn key_id(&self) -> u64 {
+        self.key_id
+    }
+
+    /// 原地加密 `buf[..len]`，nonce 与认证标签追加在密文之后，返回密文总长度
+    pub fn seal_in_place(&self, buf: &mut [u8], len: usize) -> Result<usize> {
+        let total = len + Self::OVERHEAD;
+        if total > buf.len() {
+            return Err(
+                anyhow::anyhow!("加密后数据 {} 字节超过缓冲区 {} 字节", total, buf.len()).into(),
+            );
+        }
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let tag = self
+            .cipher
+            .encrypt_in_place_detached(&nonce, b"", &mut buf[..len])
+            .map_err(|_| anyhow::anyhow!("加密失败"))?;
+        buf[len..len + 24].copy_from_slice(&nonce);
+        buf[len + 24..total].copy_from_slice(&tag);
+        Ok(total)
+    }
+
+    /// 加密数据，返回密文（含 nonce 与认证标签）
+    pub fn seal(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut buf = vec![0u8; data.len() + Self::OVERHEAD];
+        buf[..data.len()].copy_from_slice(data);
+        self.seal_in_place(&mut buf, data.len())?;
+        Ok(buf)
+    }
+
+    /// 解密并校验认证标签，失败时返回 [`TokioIPCError::CorruptedData`]
+    ///
+    /// 密文保持不变（仍在共享内存中，可能被重新投递），明文复制到新的缓冲区。
+    pub fn open(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let Some(len) = data.len().checked_sub(Self::OVERHEAD) else {
+            return Err(TokioIPCError::CorruptedData.into());
+        };
+        let nonce = XNonce::from_slice(&data[len..len + 24]);
+        let tag = Tag::from_slice(&data[len + 24..]);
+        let mut plain = data[..len].to_vec();
+        self.cipher
+            .decrypt_in_place_detached(nonce, b"", &mut plain, tag)
+            .map_err(|_| TokioIPCError::CorruptedData)?;
+        Ok(plain)
+    }
+}
+
+/// 本进程使用的负载加密器，由 [`install`] 或 [`init_from_config`] 设置
+static INSTALLED: OnceLock<Arc<PayloadCipher>> = OnceLock::new();
+
+/// 设置本进程的负载加密器，此后创建的共享内存管道自动加密；已设置过时返回 `false`
+pub fn install(cipher: PayloadCipher) -> bool {
+    INSTALLED.set(Arc::new(cipher)).is_ok()
+}
+
+/// 本进程的负载加密器
+pub fn installed() -> Option<Arc<PayloadCipher>> {
+    INSTALLED.get().cloned()
+}
+
+/// 按 `[encryption]` 配置加载密钥并设置本进程的负载加密器
+///
+/// 未配置该段或 `algorithm = "none"` 时不加密；配置了算法但密钥无法读取时返回错误，
+/// 不会退化为明文。密钥来自 `key_file`，或 `key_env` 指定的环境变量（64 个十六进制字符）。
+pub fn init_from_config() -> Result<bool> {
+    let config = config::get_config();
+    if config.get_keys("encryption").is_none() {
+        return Ok(false);
+    }
+    let algorithm: Encryption = config::string_or("encryption", "algorithm", "xchacha20poly1305")
+        .parse()
+        .map_err(|e: String| anyhow::anyhow!(e))?;
+    if algorithm == Encryption::None {
+        return Ok(false);
+    }
+
+    let key_file = config::string_or("encryption", "key_file", "");
+    let key_env = config::string_or("encryption", "key_env", "");
+    let key = if !key_file.is_empty() {
+        PayloadKey::from_file(&key_file)?
+    } else if !key_env.is_empty() {
+        let hex = std::env::var(&key_env)
+            .map_err(|_| anyhow::anyhow!("环境变量 {} 未设置负载密钥", key_env))?;
+        PayloadKey::from_hex(&hex)?
+    } else {
+        return Err(
+            anyhow::anyhow!("[encryption] 启用了负载加密，但未配置 key_file 或 key_env").into(),
+        );
+    };
+
+    let cipher = PayloadCipher::new(&key);
+    tracing::info!(
+        "负载加密已启用 ({}), 密钥标识 {:016x}",
+        algorithm,
+        cipher.key_id()
+    );
+    Ok(install(cipher))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_open() {
+        let cipher = PayloadCipher::new(&PayloadKey::from_bytes([7; 32]));
+        let sealed = cipher.seal(b"id=42;name=alice").unwrap();
+        assert_eq!(sealed.len(), 16 + PayloadCipher::OVERHEAD);
+        assert!(!sealed.windows(5).any(|w| w == b"alice"));
+        assert_eq!(cipher.open(&sealed).unwrap(), b"id=42;name=alice");
+
+        // 篡改密文或使用其他密钥时认证失败
+        let mut tampered = sealed.clone();
+        tampered[0] ^= 1;
+        let other = PayloadCipher::new(&PayloadKey::from_bytes([8; 32]));
+        for (cipher, data) in [(&cipher, &tampered), (&other, &sealed)] {
+            let err = cipher.open(data).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<TokioIPCError>(),
+                Some(TokioIPCError::CorruptedData)
+            ));
+        }
+        assert_ne!(cipher.key_id(), other.key_id());
+        assert_eq!(
+            PayloadKey::from_hex(&"07".repeat(32)).unwrap().id(),
+            cipher.key_id()
+        );
+    }
+}
@@ -0,0 +1,428 @@
+//! 统一的错误类型与稳定错误码
+//!
+//! 公开接口统一返回 [`Result<T>`]（即 `Result<T, mi7::Error>`）。具体失败以类型化错误
+//! （[`PipeTimeout`]、[`MessageExpired`]、[`StaleHandle`] 等）放在错误链中，下游按
+//! [`ErrorCode`] 或 [`ErrorCategory`] 匹配，而不必逐个尝试 downcast：
+//!
+//! ```no_run
+//! # use mi7::pipe::DynCrossProcessPipe;
+//! # use mi7::{ErrorCategory, Message};
+//! # use std::time::Duration;
+//! # fn example(pipe: &DynCrossProcessPipe) {
+//! match pipe.send_blocking(Message::init("ping".to_string()), Duration::from_secs(1)) {
+//!     Ok(_) => {}
+//!     Err(e) => match e.category() {
+//!         ErrorCategory::Timeout | ErrorCategory::Capacity => { /* 稍后重试 */ }
+//!         ErrorCategory::Corruption => { /* 告警 */ }
+//!         _ => { /* 重新连接 */ }
+//!     },
+//! }
+//! # }
+//! ```
+//!
+//! 错误码是跨版本稳定的数值（例如通过 C 接口或日志上报），只会新增、不会改变含义。
+//! 各类型化错误都可以直接 `into()` 为 [`Error`]；库内部仍以 `anyhow` 拼接上下文，
+//! 经 `From<anyhow::Error>` 转换时按错误链分类。[`Error`] 实现了 `std::error::Error`，
+//! 可以用 `?` 传回返回 `anyhow::Result` 的代码。
+
+use crate::command::SchemaError;
+use crate::config::ConfigError;
+use crate::locks::{LockTimeout, SeqLockPoisoned};
+use crate::pipe::{MessageExpired, PipeTimeout};
+use crate::rate_limit::RateLimited;
+use crate::router::RouteError;
+use crate::shared_map::MapFull;
+use crate::shared_slot::{LayoutMismatch, PayloadTooLarge, StaleHandle, TokioIPCError};
+use crate::shm_arena::ArenaError;
+use crate::typed_pipe::TypeMismatch;
+use std::fmt;
+use std::io;
+
+/// 以 [`Error`] 为错误类型的 `Result`
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// 错误类别
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCategory {
+    /// 共享内存段、句柄或权限等资源不存在、不匹配或已失效
+    Resource,
+    /// 数据校验、认证或解码失败
+    Corruption,
+    /// 等待超时或消息过期
+    Timeout,
+    /// 队列、表或分配区已满（或为空）
+    Capacity,
+    /// 系统调用与文件读写失败
+    Io,
+    /// 未归类的错误
+    Other,
+}
+
+/// 稳定的数值错误码，千位表示类别
+#[repr(u16)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    /// 共享内存段或文件不存在
+    NotFound = 1001,
+    /// 没有访问权限，或连接被创建者拒绝
+    AccessDenied = 1002,
+    /// 共享内存布局与期望不一致
+    LayoutMismatch = 1003,
+    /// 槽位或分配区句柄已失效
+    StaleHandle = 1004,
+    /// 消息类型与类型化管道不一致
+    TypeMismatch = 1005,
+    /// 加锁失败
+    LockFailed = 1006,
+    /// 配置文件或命令格式无效
+    InvalidConfig = 1007,
+
+    /// 数据校验和不符
+    ChecksumMismatch = 2001,
+    /// 消息认证码校验失败
+    AuthenticationFailed = 2002,
+    /// 解密后的认证标签校验失败
+    CorruptedData = 2003,
+    /// 编码或解码失败
+    Serialization = 2004,
+
+    /// 等待空槽位超时
+    SendTimeout = 3001,
+    /// 等待消息超时
+    ReceiveTimeout = 3002,
+    /// 等待锁超时
+    LockTimeout = 3003,
+    /// 消息已过期
+    MessageExpired = 3004,
+
+    /// 队列已满
+    QueueFull = 4001,
+    /// 队列为空
+    QueueEmpty = 4002,
+    /// 共享哈希表已满
+    MapFull = 4003,
+    /// 分配区没有足够大的空闲块
+    ArenaExhausted = 4004,
+    /// 数据超过单个槽位或块的大小
+    TooLarge = 4005,
+    /// 请求超过限流速率
+    RateLimited = 4006,
+
+    /// 其他系统调用或文件读写失败
+    Io = 5001,
+    /// 共享内存打开或映射失败
+    SharedMemory = 5002,
+
+    /// 未归类的错误
+    Unknown = 9999,
+}
+
+impl ErrorCode {
+    /// 数值错误码
+    pub fn as_u16(self) -> u16 {
+        self as u16
+    }
+
+    /// 错误码所属类别
+    pub fn category(self) -> ErrorCategory {
+        match self as u16 / 1000 {
+            1 => ErrorCategory::Resource,
+            2 => ErrorCategory::Corruption,
+            3 => ErrorCategory::Timeout,
+            4 => ErrorCategory::Capacity,
+            5 => ErrorCategory::Io,
+            _ => ErrorCategory::Other,
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "E{}", self.as_u16())
+    }
+}
+
+/// 带错误码的库错误
+///
+/// 保留原始错误链：`Display` 与原错误相同（`{:#}` 输出完整链），可以用
+/// [`Error::downcast_ref`] 取出具体的类型化错误。
+pub struct Error {
+    code: ErrorCode,
+    inner: anyhow::Error,
+}
+
+impl Error {
+    /// 以指定错误码包装错误
+    pub fn new(code: ErrorCode, error: impl Into<anyhow::Error>) -> Self {
+        Self {
+            code,
+            inner: error.into(),
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        self.code
+    }
+
+    pub fn category(&self) -> ErrorCategory {
+        self.code.category()
+    }
+
+    /// 附加上下文，错误码不变
+    pub fn context<C>(self, context: C) -> Self
+    where
+        C: fmt::Display + Send + Sync + 'static,
+    {
+        Self {
+            code: self.code,
+            inner: self.inner.context(context),
+        }
+    }
+
+    /// 错误链中是否有 `E` 类型的错误
+    pub fn is<E>(&self) -> bool
+    where
+        E: fmt::Display + fmt::Debug + Send + Sync + 'static,
+    {
+        self.downcast_ref::<E>().is_some()
+    }
+
+    /// 取出错误链中 `E` 类型的错误，包括经 `anyhow` 上下文再次包装的 [`Error`] 内部的错误
+    pub fn downcast_ref<E>(&self) -> Option<&E>
+    where
+        E: fmt::Display + fmt::Debug + Send + Sync + 'static,
+    {
+        self.inner.downcast_ref::<E>().or_else(|| {
+            self.inner
+                .chain()
+                .find_map(|cause| cause.downcast_ref::<Error>())
+                .and_then(|nested| nested.downcast_ref::<E>())
+        })
+    }
+
+    /// 取出 `E` 类型的错误，类型不符时原样返回
+    pub fn downcast<E>(self) -> Result<E, Self>
+    where
+        E: fmt::Display + fmt::Debug + Send + Sync + 'static,
+    {
+        self.inner.downcast::<E>().map_err(|inner| Self {
+            code: self.code,
+            inner,
+        })
+    }
+
+    /// 原始错误
+    pub fn into_inner(self) -> anyhow::Error {
+        self.inner
+    }
+}
+
+/// 按错误链中第一个可识别的类型化错误确定错误码
+fn classify(error: &anyhow::Error) -> ErrorCode {
+    error
+        .chain()
+        .find_map(|cause| {
+            if let Some(e) = cause.downcast_ref::<Error>() {
+                return Some(e.code);
+            }
+            if let Some(e) = cause.downcast_ref::<PipeTimeout>() {
+                return Some(match e {
+                    PipeTimeout::Send(_) => ErrorCode::SendTimeout,
+                    PipeTimeout::Receive(_) => ErrorCode::ReceiveTimeout,
+                });
+            }
+            if let Some(e) = cause.downcast_ref::<TokioIPCError>() {
+                return Some(match e {
+                    TokioIPCError::ShmOpenFailed(_) | TokioIPCError::MmapFailed => {
+                        ErrorCode::SharedMemory
+                    }
+                    TokioIPCError::MutexLockFailed => ErrorCode::LockFailed,
+                    TokioIPCError::QueueFull => ErrorCode::QueueFull,
+                    TokioIPCError::QueueEmpty => ErrorCode::QueueEmpty,
+                    TokioIPCError::SerializationFailed => ErrorCode::Serialization,
+                    TokioIPCError::ChecksumMismatch => ErrorCode::ChecksumMismatch,
+                    TokioIPCError::SlotNotReady => ErrorCode::StaleHandle,
+                    TokioIPCError::CorruptedData => ErrorCode::CorruptedData,
+                    TokioIPCError::AuthenticationFailed => ErrorCode::AuthenticationFailed,
+                });
+            }
+            if let Some(e) = cause.downcast_ref::<ArenaError>() {
+                return Some(match e {
+                    ArenaError::TooLarge { .. } => ErrorCode::TooLarge,
+                    ArenaError::Exhausted { .. } => ErrorCode::ArenaExhausted,
+                    ArenaError::StaleHandle { .. } => ErrorCode::StaleHandle,
+                });
+            }
+            if let Some(e) = cause.downcast_ref::<io::Error>() {
+                return Some(match e.kind() {
+                    io::ErrorKind::NotFound => ErrorCode::NotFound,
+                    io::ErrorKind::PermissionDenied => ErrorCode::AccessDenied,
+                    _ => ErrorCode::Io,
+                });
+            }
+            if cause.is::<MessageExpired>() {
+                Some(ErrorCode::MessageExpired)
+            } else if cause.is::<LockTimeout>() {
+                Some(ErrorCode::LockTimeout)
+            } else if cause.is::<SeqLockPoisoned>() {
+                Some(ErrorCode::LockFailed)
+            } else if cause.is::<StaleHandle>() {
+                Some(ErrorCode::StaleHandle)
+            } else if cause.is::<PayloadTooLarge>() {
+                Some(ErrorCode::TooLarge)
+            } else if cause.is::<LayoutMismatch>() {
+                Some(ErrorCode::LayoutMismatch)
+            } else if cause.is::<TypeMismatch>() {
+                Some(ErrorCode::TypeMismatch)
+            } else if cause.is::<MapFull>() {
+                Some(ErrorCode::MapFull)
+            } else if cause.is::<RateLimited>() {
+                Some(ErrorCode::RateLimited)
+            } else if cause.is::<ConfigError>() || cause.is::<SchemaError>() {
+                Some(ErrorCode::InvalidConfig)
+            } else if cause.is::<bincode::error::EncodeError>()
+                || cause.is::<bincode::error::DecodeError>()
+            {
+                Some(ErrorCode::Serialization)
+            } else {
+                None
+            }
+        })
+        .unwrap_or(ErrorCode::Unknown)
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(inner: anyhow::Error) -> Self {
+        // 经 `?` 传入 anyhow 后又转回的错误直接取回，不再嵌套；
+        // 外层带上下文时 `downcast` 也能取到内层错误，需先确认最外层就是本类型
+        if inner.chain().next().is_some_and(|e| e.is::<Error>()) {
+            return inner.downcast::<Error>().expect("最外层错误已确认为 Error");
+        }
+        Self {
+            code: classify(&inner),
+            inner,
+        }
+    }
+}
+
+macro_rules! impl_from {
+    ($($error:ty),* $(,)?) => {
+        $(
+            impl From<$error> for Error {
+                fn from(error: $error) -> Self {
+                    anyhow::Error::from(error).into()
+                }
+            }
+        )*
+    };
+}
+
+impl_from!(
+    PipeTimeout,
+    MessageExpired,
+    TokioIPCError,
+    StaleHandle,
+    PayloadTooLarge,
+    LayoutMismatch,
+    TypeMismatch,
+    MapFull,
+    RateLimited,
+    RouteError,
+    ArenaError,
+    LockTimeout,
+    SeqLockPoisoned,
+    ConfigError,
+    SchemaError,
+    io::Error,
+    bincode::error::EncodeError,
+    bincode::error::DecodeError,
+);
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {:?}", self.code, self.inner)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.inner, f)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.inner.source()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Message;
+    use crate::heap_pipe::HeapSlotPipe;
+    use crate::pipe::{DynCrossProcessPipe, DynamicPipe, PipeConfig};
+    use std::time::Duration;
+
+    #[test]
+    fn test_error_codes_from_pipe_failures() {
+        let name = format!("mi7_test_error_codes_{}", std::process::id());
+        let pipe = DynCrossProcessPipe::create_with_config(&name, PipeConfig::new(1, 64)).unwrap();
+
+        let timeout = Duration::from_millis(1);
+        let err = pipe.receive_blocking(timeout).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::ReceiveTimeout);
+        assert_eq!(err.downcast_ref(), Some(&PipeTimeout::Receive(timeout)));
+
+        // 底层错误以上下文包装，确认未持有的槽位仍可分类
+        let err = pipe.ack(0).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::StaleHandle);
+        assert_eq!(err.to_string(), "确认消息失败");
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(TokioIPCError::SlotNotReady)
+        ));
+
+        // 写入路径同样以上下文包装，不丢失类型化错误
+        let err = pipe.send(0, Message::init("unheld".into())).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::StaleHandle);
+        assert_eq!(err.to_string(), "写入消息失败");
+
+        let err = pipe.try_send(Message::init("x".repeat(128))).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::TooLarge);
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(PayloadTooLarge { size, capacity: 64 }) if *size > 64
+        ));
+
+        pipe.hold().unwrap();
+        assert_eq!(pipe.hold().unwrap_err().code(), ErrorCode::QueueFull);
+
+        let heap = HeapSlotPipe::create(&format!("{}_heap", name), PipeConfig::new(1, 64)).unwrap();
+        let err = heap.fetch().unwrap_err();
+        assert_eq!(err.code(), ErrorCode::QueueEmpty);
+        assert_eq!(err.category(), ErrorCategory::Capacity);
+
+        // 上下文不影响分类，显示内容与原错误相同
+        let missing = DynCrossProcessPipe::connect(&format!("{}_missing", name))
+            .err()
+            .unwrap();
+        let err = missing.context("重新连接失败");
+        assert_eq!(err.code(), ErrorCode::NotFound);
+        assert_eq!(err.category(), ErrorCategory::Resource);
+        assert_eq!(err.to_string(), "重新连接失败");
+
+        let limited = RateLimited {
+            retry_after: Duration::from_millis(5),
+        };
+        let err = Error::from(anyhow::Error::from(limited).context("限流"));
+        assert_eq!(err.code(), ErrorCode::RateLimited);
+        assert_eq!(err.category(), ErrorCategory::Capacity);
+
+        assert_eq!(
+            Error::from(anyhow::anyhow!("something else")).code(),
+            ErrorCode::Unknown
+        );
+        assert_eq!(ErrorCode::ChecksumMismatch.to_string(), "E2001");
+    }
+}
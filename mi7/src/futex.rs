@@ -0,0 +1,99 @@
+//! 跨进程 futex 等待/唤醒
+//!
+//! 共享内存中的 `AtomicU32` 作为 futex 字：写者修改后调用 [`futex_wake`]，
+//! 等待者通过 [`futex_wait`] 在内核中休眠，直到值发生变化。
+//! Linux 使用非 PRIVATE 的 `FUTEX_WAIT`/`FUTEX_WAKE`，可跨进程；
+//! 其他平台退化为短睡眠轮询。
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+
+/// 当 `word` 的值仍等于 `expected` 时休眠，直到被唤醒或超时
+///
+/// 返回 `false` 表示超时；被唤醒、值已变化或被信号打断均返回 `true`，调用者需自行重新检查条件
+#[cfg(target_os = "linux")]
+pub fn futex_wait(word: &AtomicU32, expected: u32, timeout: Option<Duration>) -> bool {
+    let ts = timeout.map(|t| libc::timespec {
+        tv_sec: t.as_secs() as libc::time_t,
+        tv_nsec: t.subsec_nanos() as libc::c_long,
+    });
+    let ts_ptr = ts
+        .as_ref()
+        .map_or(std::ptr::null(), |ts| ts as *const libc::timespec);
+
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            word.as_ptr(),
+            libc::FUTEX_WAIT,
+            expected,
+            ts_ptr,
+            std::ptr::null::<u32>(),
+            0u32,
+        )
+    };
+    !(result == -1 && crate::shm_sync::errno() == libc::ETIMEDOUT)
+}
+
+/// 唤醒最多 `count` 个在 `word` 上等待的线程（跨进程），返回被唤醒的数量
+#[cfg(target_os = "linux")]
+pub fn futex_wake(word: &AtomicU32, count: u32) -> usize {
+    let count = count.min(i32::MAX as u32) as i32;
+    let result = unsafe { libc::syscall(libc::SYS_futex, word.as_ptr(), libc::FUTEX_WAKE, count) };
+    result.max(0) as usize
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn futex_wait(word: &AtomicU32, expected: u32, timeout: Option<Duration>) -> bool {
+    let deadline = timeout.map(|t| Instant::now() + t);
+    let mut backoff = Duration::from_micros(10);
+    while word.load(Ordering::Acquire) == expected {
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            return false;
+        }
+        std::thread::sleep(backoff);
+        backoff = (backoff * 2).min(Duration::from_millis(1));
+    }
+    true
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn futex_wake(_word: &AtomicU32, _count: u32) -> usize {
+    // 轮询实现无需显式唤醒
+    0
+}
+
+/// 递增 futex 字并唤醒所有等待者
+pub fn bump_and_wake(word: &AtomicU32) {
+    word.fetch_add(1, Ordering::Release);
+    futex_wake(word, u32::MAX);
+}
+
+/// 等待 `condition` 成立，`word` 为条件变化时会被 [`bump_and_wake`] 的计数字
+///
+/// `timeout` 为 `None` 时无限等待，超时返回 `false`
+pub fn wait_until<F>(word: &AtomicU32, timeout: Option<Duration>, condition: F) -> bool
+where
+    F: Fn() -> bool,
+{
+    let deadline = timeout.map(|t| Instant::now() + t);
+    loop {
+        // 先读计数再检查条件，避免检查之后、休眠之前的唤醒丢失
+        let seen = word.load(Ordering::Acquire);
+        if condition() {
+            return true;
+        }
+
+        let remaining = match deadline {
+            Some(deadline) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    return false;
+                }
+                Some(deadline - now)
+            }
+            None => None,
+        };
+        futex_wait(word, seen, remaining);
+    }
+}
@@ -0,0 +1,759 @@
+//! 进程内管道：单元测试用的 [`DynCrossProcessPipe`] 替身
+//!
+//! [`HeapSlotPipe`] 的槽位、状态与统计全部放在堆内存中，由 `Mutex` + `Condvar` 保护，
+//! 不使用共享内存、mmap 或 `unsafe`，entry / worker 逻辑的单元测试可以在 Miri 下、
+//! 或没有 `/dev/shm` 的 CI 容器中运行。槽位状态机、编解码、过期、背压与 ack/nack
+//! 语义与锁模式的共享内存管道一致。
+//!
+//! 同名管道在进程内登记，[`PipeFactory`] 以 `memory` 或 `memory(容量x槽位大小)`
+//! 类型创建与连接，所有句柄共享同一个队列。
+//!
+//! [`DynCrossProcessPipe`]: crate::pipe::DynCrossProcessPipe
+//! [`PipeFactory`]: crate::pipe::PipeFactory
+
+use crate::Message;
+use crate::pipe::{
+    DEAD_LETTER_TIMEOUT, DynamicPipe, MessageExpired, PipeConfig, PipeMetrics, PipeStatus,
+    PipeTimeout, is_queue_full,
+};
+use crate::shared_slot::{FullPolicy, LATENCY_BUCKETS, PipeMode, SlotState, TokioIPCError};
+
+use crate::error::Result;
+use anyhow::anyhow;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, LazyLock, Mutex, MutexGuard, Weak};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+/// 进程内登记的管道，按名称查找
+static REGISTRY: LazyLock<Mutex<HashMap<String, Weak<Shared>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// 单个槽位
+struct HeapSlot {
+    state: SlotState,
+    request_id: u64,
+    delivery_attempts: u32,
+    enqueued_at: Instant,
+    leased_at: Option<Instant>, // 被写者或读者抢占的时间，None 表示未被持有
+    data: Vec<u8>,
+}
+
+impl HeapSlot {
+    fn new(slot_size: usize) -> Self {
+        Self {
+            state: SlotState::EMPTY,
+            request_id: 0,
+            delivery_attempts: 0,
+            enqueued_at: Instant::now(),
+            leased_at: None,
+            data: Vec::with_capacity(slot_size),
+        }
+    }
+
+    /// 槽位回到 EMPTY 并清空数据
+    fn clear(&mut self) {
+        self.state = SlotState::EMPTY;
+        self.request_id = 0;
+        self.delivery_attempts = 0;
+        self.leased_at = None;
+        self.data.clear();
+    }
+}
+
+/// 互斥锁保护的队列状态
+struct State {
+    slots: Vec<HeapSlot>,
+    write_pointer: usize,
+    read_pointer: usize,
+    seq: u64,
+    backpressured: bool,
+    reclaimed_count: u64,
+    expired_count: u64,
+    redelivered_count: u64,
+    dead_lettered_count: u64,
+    sent_count: u64,
+    received_count: u64,
+    dropped_count: u64,
+    latency_buckets: [u64; LATENCY_BUCKETS],
+    latency_sum_nanos: u64,
+}
+
+impl State {
+    /// 按状态抢占槽位，从 `start` 开始环形扫描
+    fn claim(&mut self, start: usize, from: SlotState, to: SlotState) -> Option<usize> {
+        let capacity = self.slots.len();
+        let index = (0..capacity)
+            .map(|i| (start + i) % capacity)
+            .find(|&i| self.slots[i].state == from)?;
+        let slot = &mut self.slots[index];
+        slot.state = to;
+        slot.leased_at = Some(Instant::now());
+        Some(index)
+    }
+
+    fn claim_empty(&mut self) -> Option<usize> {
+        let index = self.claim(self.write_pointer, SlotState::EMPTY, SlotState::WRITING)?;
+        self.write_pointer = (index + 1) % self.slots.len();
+        Some(index)
+    }
+
+    fn claim_ready(&mut self) -> Option<usize> {
+        let index = self.claim(self.read_pointer, SlotState::READY, SlotState::READING)?;
+        self.read_pointer = (index + 1) % self.slots.len();
+        Some(index)
+    }
+
+    /// 校验索引，把抢占时的状态 `held`（WRITING / READING）切换为 INPROGRESS 并续约
+    ///
+    /// 已处于 INPROGRESS 的槽位直接通过，其他状态说明槽位不归调用者持有。
+    fn in_progress(&mut self, index: usize, held: SlotState) -> Result<&mut HeapSlot> {
+        let slot = self
+            .slots
+            .get_mut(index)
+            .ok_or_else(|| anyhow!("Slot index out of bounds"))?;
+        if slot.state == held {
+            slot.state = SlotState::INPROGRESS;
+            if let Some(leased_at) = &mut slot.leased_at {
+                *leased_at = Instant::now();
+            }
+        } else if slot.state != SlotState::INPROGRESS {
+            return Err(anyhow::Error::from(TokioIPCError::SlotNotReady)
+                .context("Slot not in progress")
+                .into());
+        }
+        Ok(slot)
+    }
+
+    fn used_slots(&self) -> usize {
+        self.slots
+            .iter()
+            .filter(|slot| slot.state != SlotState::EMPTY)
+            .count()
+    }
+
+    /// 与共享内存管道相同的 2 的幂次微秒分桶
+    fn record_latency(&mut self, enqueued_at: Instant) {
+        let nanos = enqueued_at.elapsed().as_nanos() as u64;
+        let micros = nanos / 1000;
+        let bucket = ((u64::BITS - micros.leading_zeros()) as usize)
+            .saturating_sub(1)
+            .min(LATENCY_BUCKETS - 1);
+        self.latency_buckets[bucket] += 1;
+        self.latency_sum_nanos += nanos;
+    }
+}
+
+/// 同名句柄共享的管道
+struct Shared {
+    name: String,
+    config: PipeConfig,
+    state: Mutex<State>,
+    /// 有槽位变为 READY
+    ready: Condvar,
+    /// 有槽位变为 EMPTY
+    empty: Condvar,
+    /// 唤醒异步读者
+    notify: Notify,
+    /// 每释放一个槽位唤醒一个异步写者
+    space: Notify,
+}
+
+/// 完全位于进程内存中的管道，实现 [`DynamicPipe`]
+///
+/// 克隆或通过 [`HeapSlotPipe::connect`] 得到的句柄共享同一个队列；
+/// 最后一个句柄释放后队列随之销毁。
+#[derive(Clone)]
+pub struct HeapSlotPipe {
+    shared: Arc<Shared>,
+    dead_letter: Option<Arc<dyn DynamicPipe>>,
+}
+
+impl HeapSlotPipe {
+    /// 按配置创建管道并以 `name` 登记，同名的旧管道被替换（已有句柄不受影响）
+    ///
+    /// 并发模式固定为锁模式，校验算法只记录在配置中：进程内存不会被其他进程写坏。
+    pub fn create(name: &str, config: PipeConfig) -> Result<Self> {
+        config
+            .validate()
+            .map_err(|e| anyhow!("配置验证失败: {}", e))?;
+        let config = config.with_mode(PipeMode::Locked);
+
+        let shared = Arc::new(Shared {
+            name: name.to_string(),
+            config,
+            state: Mutex::new(State {
+                slots: (0..config.capacity)
+                    .map(|_| HeapSlot::new(config.slot_size))
+                    .collect(),
+                write_pointer: 0,
+                read_pointer: 0,
+                seq: 0,
+                backpressured: false,
+                reclaimed_count: 0,
+                expired_count: 0,
+                redelivered_count: 0,
+                dead_lettered_count: 0,
+                sent_count: 0,
+                received_count: 0,
+                dropped_count: 0,
+                latency_buckets: [0; LATENCY_BUCKETS],
+                latency_sum_nanos: 0,
+            }),
+            ready: Condvar::new(),
+            empty: Condvar::new(),
+            notify: Notify::new(),
+            space: Notify::new(),
+        });
+        REGISTRY
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), Arc::downgrade(&shared));
+        Ok(Self {
+            shared,
+            dead_letter: None,
+        })
+    }
+
+    /// 连接到本进程中已登记的同名管道
+    pub fn connect(name: &str) -> Result<Self> {
+        let shared = REGISTRY
+            .lock()
+            .unwrap()
+            .get(name)
+            .and_then(Weak::upgrade)
+            .ok_or_else(|| anyhow!("内存管道 {} 不存在", name))?;
+        Ok(Self {
+            shared,
+            dead_letter: None,
+        })
+    }
+
+    /// 连接到同名管道，容量或槽位大小与期望不一致时返回错误
+    pub fn connect_with_layout(name: &str, capacity: usize, slot_size: usize) -> Result<Self> {
+        let pipe = Self::connect(name)?;
+        if pipe.capacity() != capacity || pipe.slot_size() != slot_size {
+            return Err(anyhow!(
+                "内存管道 {} 的布局为 {}x{}，期望 {}x{}",
+                name,
+                pipe.capacity(),
+                pipe.slot_size(),
+                capacity,
+                slot_size
+            )
+            .into());
+        }
+        Ok(pipe)
+    }
+
+    /// 关联死信管道，超过最大投递次数的消息经 `nack` 转发到该管道
+    pub fn with_dead_letter(mut self, dead_letter: Arc<dyn DynamicPipe>) -> Self {
+        self.dead_letter = Some(dead_letter);
+        self
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.shared.state.lock().unwrap()
+    }
+
+    /// 槽位变为 READY 后唤醒同步与异步读者
+    fn notify_ready(&self) {
+        self.shared.ready.notify_all();
+        self.shared.notify.notify_waiters();
+    }
+
+    /// 按配置的水位刷新背压状态
+    fn update_backpressure(&self, state: &mut State) -> bool {
+        let config = &self.shared.config;
+        if config.high_watermark == 0 {
+            return false;
+        }
+        let used = state.used_slots();
+        if !state.backpressured && used >= config.high_watermark {
+            state.backpressured = true;
+        } else if state.backpressured && used <= config.low_watermark {
+            state.backpressured = false;
+        }
+        state.backpressured
+    }
+
+    /// 释放槽位为 EMPTY 并唤醒等待空槽位的写者
+    fn release(&self, state: &mut State, index: usize) {
+        state.slots[index].clear();
+        self.update_backpressure(state);
+        self.shared.empty.notify_all();
+        self.shared.space.notify_one();
+    }
+
+    /// 抢占空槽位；覆盖模式下队列满时丢弃最早的 READY 消息后重试
+    fn claim_empty(&self, state: &mut State) -> Option<usize> {
+        if let Some(index) = state.claim_empty() {
+            return Some(index);
+        }
+        if self.shared.config.full_policy != FullPolicy::OverwriteOldest {
+            return None;
+        }
+        let oldest = state.claim_ready()?;
+        state.slots[oldest].clear();
+        state.dropped_count += 1;
+        state.claim_empty()
+    }
+
+    fn write_message(
+        &self,
+        index: usize,
+        request_id: Option<u64>,
+        message: Message,
+    ) -> Result<u64> {
+        let codec = self.shared.config.codec.codec();
+        let mut buf = vec![0u8; self.slot_size()];
+        let encoded = codec.encode(&message, &mut buf);
+
+        let mut state = self.state();
+        state.in_progress(index, SlotState::WRITING)?;
+        let len = match encoded {
+            Ok(0) => Err(anyhow!("Empty payload").into()),
+            Ok(len) => Ok(len),
+            Err(e) => Err(e),
+        };
+        let len = match len {
+            Ok(len) => len,
+            Err(e) => {
+                self.release(&mut state, index);
+                return Err(e.context("写入消息失败"));
+            }
+        };
+
+        let request_id = request_id.unwrap_or_else(|| {
+            state.seq += 1;
+            state.seq - 1
+        });
+        state.sent_count += 1;
+        let slot = &mut state.slots[index];
+        slot.data.clear();
+        slot.data.extend_from_slice(&buf[..len]);
+        slot.request_id = request_id;
+        slot.delivery_attempts = 0;
+        slot.enqueued_at = Instant::now();
+        slot.leased_at = None;
+        slot.state = SlotState::READY;
+        drop(state);
+
+        self.notify_ready();
+        Ok(request_id)
+    }
+
+    /// 解码 INPROGRESS 槽位中的消息，返回 request_id、已失败的投递次数与消息
+    ///
+    /// 解码失败时槽位被释放。
+    fn decode_in_progress(&self, state: &mut State, index: usize) -> Result<(u64, u32, Message)> {
+        let codec = self.shared.config.codec.codec();
+        let slot = state.in_progress(index, SlotState::READING)?;
+        let (request_id, attempts, enqueued_at) =
+            (slot.request_id, slot.delivery_attempts, slot.enqueued_at);
+        match codec.decode(&slot.data) {
+            Ok(message) => {
+                // 重新投递的消息不重复计入延迟
+                if attempts == 0 {
+                    state.record_latency(enqueued_at);
+                }
+                Ok((request_id, attempts, message))
+            }
+            Err(e) => {
+                self.release(state, index);
+                Err(e.context("读取消息失败"))
+            }
+        }
+    }
+
+    /// 已过期的消息：释放槽位并计入 `expired_count`
+    fn expire(
+        &self,
+        state: &mut State,
+        index: usize,
+        request_id: u64,
+        message: &Message,
+    ) -> anyhow::Error {
+        state.received_count += 1;
+        state.expired_count += 1;
+        self.release(state, index);
+        tracing::debug!("管道 {} 丢弃已过期的消息 {}", self.shared.name, request_id);
+        MessageExpired {
+            request_id,
+            expires_at: message.expires_at,
+        }
+        .into()
+    }
+}
+
+impl DynamicPipe for HeapSlotPipe {
+    fn hold(&self) -> Result<usize> {
+        let mut state = self.state();
+        let held = self.claim_empty(&mut state);
+        self.update_backpressure(&mut state);
+        Ok(held.ok_or_else(|| {
+            anyhow::Error::from(TokioIPCError::QueueFull).context("队列已满，无法获取空槽位")
+        })?)
+    }
+
+    fn send(&self, index: usize, message: Message) -> Result<u64> {
+        self.write_message(index, None, message)
+    }
+
+    fn fetch(&self) -> Result<usize> {
+        Ok(self.state().claim_ready().ok_or_else(|| {
+            anyhow::Error::from(TokioIPCError::QueueEmpty).context("队列为空，无法获取消息")
+        })?)
+    }
+
+    fn fetch_async(&self) -> Pin<Box<dyn Future<Output = Result<usize>> + Send + '_>> {
+        Box::pin(async move {
+            loop {
+                // 先登记等待再检查队列，检查与等待之间的通知不会丢失
+                let notified = self.shared.notify.notified();
+                tokio::pin!(notified);
+                notified.as_mut().enable();
+                if let Some(index) = self.state().claim_ready() {
+                    return Ok(index);
+                }
+                notified.await;
+            }
+        })
+    }
+
+    fn hold_async(&self) -> Pin<Box<dyn Future<Output = Result<usize>> + Send + '_>> {
+        Box::pin(async move {
+            loop {
+                // 先登记等待再检查队列，检查与等待之间释放的槽位不会丢失
+                let notified = self.shared.space.notified();
+                tokio::pin!(notified);
+                notified.as_mut().enable();
+                match self.hold() {
+                    Err(e)
+                        if is_queue_full(&e)
+                            && self.shared.config.full_policy != FullPolicy::Reject =>
+                    {
+                        notified.await
+                    }
+                    held => return held,
+                }
+            }
+        })
+    }
+
+    fn receive(&self, index: usize) -> Result<Message> {
+        self.receive_tagged(index).map(|(_, message)| message)
+    }
+
+    fn send_tagged(&self, index: usize, request_id: u64, message: Message) -> Result<u64> {
+        self.write_message(index, Some(request_id), message)
+    }
+
+    fn receive_tagged(&self, index: usize) -> Result<(u64, Message)> {
+        let mut state = self.state();
+        let (request_id, _, message) = self.decode_in_progress(&mut state, index)?;
+        if message.is_expired() {
+            return Err(self.expire(&mut state, index, request_id, &message).into());
+        }
+        state.received_count += 1;
+        self.release(&mut state, index);
+        Ok((request_id, message))
+    }
+
+    fn send_blocking(&self, message: Message, timeout: Duration) -> Result<u64> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.state();
+        let index = loop {
+            if let Some(index) = self.claim_empty(&mut state) {
+                break index;
+            }
+            if self.shared.config.full_policy == FullPolicy::Reject {
+                return Err(anyhow::Error::from(TokioIPCError::QueueFull)
+                    .context("队列已满，无法获取空槽位")
+                    .into());
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(PipeTimeout::Send(timeout).into());
+            }
+            state = self.shared.empty.wait_timeout(state, remaining).unwrap().0;
+        };
+        self.update_backpressure(&mut state);
+        drop(state);
+
+        self.send(index, message)
+    }
+
+    fn receive_blocking(&self, timeout: Duration) -> Result<Message> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let mut state = self.state();
+            let index = loop {
+                if let Some(index) = state.claim_ready() {
+                    break index;
+                }
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    return Err(PipeTimeout::Receive(timeout).into());
+                }
+                state = self.shared.ready.wait_timeout(state, remaining).unwrap().0;
+            };
+            drop(state);
+
+            match self.receive(index) {
+                Err(e) if e.is::<MessageExpired>() => continue,
+                received => return received,
+            }
+        }
+    }
+
+    fn get_slot_state(&self, index: usize) -> Result<SlotState> {
+        self.state()
+            .slots
+            .get(index)
+            .map(|slot| slot.state)
+            .ok_or_else(|| anyhow!("Slot index out of bounds").into())
+    }
+
+    fn status(&self) -> PipeStatus {
+        let state = self.state();
+        let count = |target: SlotState| {
+            state
+                .slots
+                .iter()
+                .filter(|slot| slot.state == target)
+                .count()
+        };
+        let empty_count = count(SlotState::EMPTY);
+        PipeStatus {
+            capacity: self.capacity(),
+            slot_size: self.slot_size(),
+            write_pointer: state.write_pointer,
+            read_pointer: state.read_pointer,
+            empty_count,
+            writing_count: count(SlotState::WRITING),
+            in_progress_count: count(SlotState::INPROGRESS),
+            reading_count: count(SlotState::READING),
+            ready_count: count(SlotState::READY),
+            used_count: self.capacity() - empty_count,
+            mode: self.shared.config.mode,
+            codec: self.shared.config.codec,
+            integrity: self.shared.config.integrity,
+            reclaimed_count: state.reclaimed_count,
+            backpressured: state.backpressured,
+            expired_count: state.expired_count,
+            redelivered_count: state.redelivered_count,
+            dead_lettered_count: state.dead_lettered_count,
+            sent_count: state.sent_count,
+            received_count: state.received_count,
+            lock_contended_count: 0,
+            dropped_count: state.dropped_count,
+        }
+    }
+
+    fn metrics(&self) -> PipeMetrics {
+        let state = self.state();
+        PipeMetrics::from_histogram(&state.latency_buckets, state.latency_sum_nanos)
+    }
+
+    fn config(&self) -> PipeConfig {
+        self.shared.config
+    }
+
+    fn capacity(&self) -> usize {
+        self.shared.config.capacity
+    }
+
+    fn slot_size(&self) -> usize {
+        self.shared.config.slot_size
+    }
+
+    fn name(&self) -> &str {
+        &self.shared.name
+    }
+
+    fn unlink(&self) -> Result<()> {
+        let mut registry = REGISTRY.lock().unwrap();
+        if registry
+            .get(&self.shared.name)
+            .is_some_and(|shared| shared.as_ptr() == Arc::as_ptr(&self.shared))
+        {
+            registry.remove(&self.shared.name);
+        }
+        Ok(())
+    }
+
+    fn attached_processes(&self) -> Vec<u32> {
+        vec![std::process::id()]
+    }
+
+    fn reclaim_stuck(&self, timeout: Duration) -> usize {
+        let mut state = self.state();
+        let stuck: Vec<usize> = state
+            .slots
+            .iter()
+            .enumerate()
+            .filter(|(_, slot)| {
+                slot.leased_at
+                    .is_some_and(|leased_at| leased_at.elapsed() >= timeout)
+            })
+            .map(|(index, _)| index)
+            .collect();
+        for &index in &stuck {
+            self.release(&mut state, index);
+        }
+        state.reclaimed_count += stuck.len() as u64;
+        if !stuck.is_empty() {
+            tracing::warn!(
+                "管道 {} 回收了 {} 个租约超时的槽位",
+                self.shared.name,
+                stuck.len()
+            );
+        }
+        stuck.len()
+    }
+
+    fn is_backpressured(&self) -> bool {
+        let mut state = self.state();
+        self.update_backpressure(&mut state)
+    }
+
+    fn receive_unacked(&self, index: usize) -> Result<(u64, Message)> {
+        let mut state = self.state();
+        let (request_id, _, message) = self.decode_in_progress(&mut state, index)?;
+        if message.is_expired() {
+            return Err(self.expire(&mut state, index, request_id, &message).into());
+        }
+        Ok((request_id, message))
+    }
+
+    fn ack(&self, index: usize) -> Result<()> {
+        let mut state = self.state();
+        state.in_progress(index, SlotState::READING)?;
+        state.received_count += 1;
+        self.release(&mut state, index);
+        Ok(())
+    }
+
+    fn nack(&self, index: usize) -> Result<bool> {
+        let max_attempts = self.shared.config.max_delivery_attempts;
+        let mut state = self.state();
+        let slot = state.in_progress(index, SlotState::READING)?;
+        let attempts = slot.delivery_attempts;
+        let request_id = slot.request_id;
+
+        // 未达到最大投递次数：槽位原地回到 READY
+        if max_attempts == 0 || attempts + 1 < max_attempts {
+            slot.delivery_attempts += 1;
+            slot.leased_at = None;
+            slot.state = SlotState::READY;
+            state.redelivered_count += 1;
+            drop(state);
+            self.notify_ready();
+            return Ok(true);
+        }
+
+        let message = self.shared.config.codec.codec().decode(&slot.data);
+        state.received_count += 1;
+        state.dead_lettered_count += 1;
+        self.release(&mut state, index);
+        drop(state);
+
+        match (&self.dead_letter, message) {
+            (Some(dead_letter), Ok(message)) => {
+                if let Err(e) = dead_letter.send_blocking(message, DEAD_LETTER_TIMEOUT) {
+                    tracing::error!(
+                        "管道 {} 转发消息 {} 到死信管道 {} 失败: {}",
+                        self.shared.name,
+                        request_id,
+                        dead_letter.name(),
+                        e
+                    );
+                }
+            }
+            _ => tracing::warn!(
+                "管道 {} 的消息 {} 投递失败 {} 次，未转发到死信管道，丢弃",
+                self.shared.name,
+                request_id,
+                attempts + 1
+            ),
+        }
+        Ok(false)
+    }
+
+    fn peek(&self) -> Result<Option<(u64, Message)>> {
+        let state = self.state();
+        let capacity = state.slots.len();
+        let Some(index) = (0..capacity)
+            .map(|i| (state.read_pointer + i) % capacity)
+            .find(|&i| state.slots[i].state == SlotState::READY)
+        else {
+            return Ok(None);
+        };
+        drop(state);
+        self.peek_at(index)
+    }
+
+    fn peek_at(&self, index: usize) -> Result<Option<(u64, Message)>> {
+        let state = self.state();
+        let slot = state
+            .slots
+            .get(index)
+            .ok_or_else(|| anyhow!("Slot index out of bounds"))?;
+        // 写入方持有的槽位在发送前没有数据
+        let occupied = matches!(
+            slot.state,
+            SlotState::READY | SlotState::READING | SlotState::INPROGRESS
+        );
+        if !occupied || slot.data.is_empty() {
+            return Ok(None);
+        }
+        let message = self.shared.config.codec.codec().decode(&slot.data)?;
+        Ok(Some((slot.request_id, message)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipe::PipeFactory;
+
+    #[test]
+    fn test_memory_pipe_round_trip() {
+        let pipe = PipeFactory::create("memory(4x256)", "mi7_test_heap_pipe").unwrap();
+        let peer = PipeFactory::connect("memory(4x256)", "mi7_test_heap_pipe", false).unwrap();
+        assert!(PipeFactory::connect("memory", "mi7_test_heap_pipe", false).is_err());
+
+        let consumer = std::thread::spawn(move || {
+            let message = peer.receive_blocking(Duration::from_secs(5));
+            (message, peer)
+        });
+        pipe.send_blocking(Message::init("first".to_string()), Duration::from_secs(1))
+            .unwrap();
+        let (first, peer) = consumer.join().unwrap();
+        assert_eq!(first.unwrap().data, b"first");
+
+        pipe.send_blocking(Message::init("second".to_string()), Duration::from_secs(1))
+            .unwrap();
+        let (_, peeked) = pipe.peek().unwrap().unwrap();
+        assert_eq!(peeked.data, b"second");
+        let index = peer.fetch().unwrap();
+        let (_, second) = peer.receive_unacked(index).unwrap();
+        assert_eq!(second.data, b"second");
+        assert!(peer.nack(index).unwrap());
+
+        // nack 后原地重新投递
+        let redelivered = peer.receive_blocking(Duration::from_secs(1)).unwrap();
+        assert_eq!(redelivered.data, b"second");
+        let status = pipe.status();
+        assert_eq!(status.empty_count, 4);
+        assert_eq!(status.redelivered_count, 1);
+        assert_eq!(status.received_count, 2);
+        assert_eq!(pipe.metrics().count, 2);
+
+        pipe.unlink().unwrap();
+        assert!(PipeFactory::connect("memory(4x256)", "mi7_test_heap_pipe", false).is_err());
+    }
+}
@@ -0,0 +1,92 @@
+//! 数据完整性校验
+//!
+//! 管道与寄存箱创建时选定校验算法并写入共享内存头部，写入方计算校验值与数据
+//! 一同存放，读取方按头部记录的算法重新计算并比对。
+
+use serde::Serialize;
+use std::str::FromStr;
+
+/// 校验算法，以 `u32` 记录在共享内存头部
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub enum Integrity {
+    /// 不校验，校验值恒为 0
+    None = 0,
+    /// CRC32 (IEEE)（默认）
+    #[default]
+    Crc32 = 1,
+    /// xxHash64，大数据量时更快
+    XxHash64 = 2,
+}
+
+impl Integrity {
+    /// 从头部记录的值还原，未知值回退为 CRC32
+    pub fn from_u32(value: u32) -> Self {
+        match value {
+            0 => Integrity::None,
+            2 => Integrity::XxHash64,
+            _ => Integrity::Crc32,
+        }
+    }
+
+    /// 计算数据的校验值
+    pub fn checksum(&self, data: &[u8]) -> u64 {
+        match self {
+            Integrity::None => 0,
+            Integrity::Crc32 => crc32fast::hash(data) as u64,
+            Integrity::XxHash64 => xxhash_rust::xxh64::xxh64(data, 0),
+        }
+    }
+
+    /// 校验数据是否与记录的校验值一致
+    pub fn verify(&self, data: &[u8], checksum: u64) -> bool {
+        self.checksum(data) == checksum
+    }
+}
+
+impl FromStr for Integrity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(Integrity::None),
+            "crc32" => Ok(Integrity::Crc32),
+            "xxhash64" | "xxh64" => Ok(Integrity::XxHash64),
+            _ => Err(format!(
+                "不支持的校验算法: '{}'. 支持: none, crc32, xxhash64",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Integrity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Integrity::None => write!(f, "none"),
+            Integrity::Crc32 => write!(f, "crc32"),
+            Integrity::XxHash64 => write!(f, "xxhash64"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_transposition() {
+        let data = b"mi7 integrity";
+        let swapped = b"mi7 intgerity";
+
+        for integrity in [Integrity::Crc32, Integrity::XxHash64] {
+            let checksum = integrity.checksum(data);
+            assert!(integrity.verify(data, checksum));
+            assert!(!integrity.verify(swapped, checksum));
+            assert_eq!(integrity.to_string().parse::<Integrity>(), Ok(integrity));
+        }
+        assert!(Integrity::None.verify(swapped, 0));
+        // CRC32 (IEEE) 标准检验值
+        assert_eq!(Integrity::Crc32.checksum(b"123456789"), 0xCBF4_3926);
+    }
+}
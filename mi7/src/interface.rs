@@ -1,25 +1,69 @@
+use crate::error::Result;
 use crate::pipe::{DynamicPipe, PipeFactory};
-use crate::shared_slot::SlotState;
+use crate::router;
+use crate::rpc::{Pusher, RpcServer};
+use crate::worker_board::{self, WorkerBoard, WorkerRegistration};
 use crate::{Message, Version, config};
-use anyhow::{Error, Result};
 use async_channel::{Receiver, Sender, bounded};
 use std::str::FromStr;
 use std::sync::Arc;
-use tracing::{error, info};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+use tracing::{error, info, warn};
 
 pub trait InterfaceApi: Send + Sync {
     fn handle(&self, message: Message) -> Result<Message>;
 }
 
+impl<T: InterfaceApi + ?Sized> InterfaceApi for Arc<T> {
+    fn handle(&self, message: Message) -> Result<Message> {
+        (**self).handle(message)
+    }
+}
+
+/// 停止时检查在途任务的间隔
+const DRAIN_POLL: Duration = Duration::from_millis(10);
+
+/// 在 worker 登记表中更新心跳的间隔
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 任务来源：共享请求管道或调度者派发到本 worker 的收件管道
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Source {
+    Shared,
+    Inbox,
+}
+
+/// 本 worker 的收件管道及其在登记表中的记录
+struct Inbox {
+    pipe: Arc<Box<dyn DynamicPipe>>,
+    server: Arc<RpcServer>,
+    registration: Arc<WorkerRegistration>,
+}
+
+/// 在途任务计数，处理结束（包括出错）时自动减一
+struct InFlight(Arc<AtomicUsize>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
 pub struct Interface {
     version: Version,
     pipe: Arc<Box<dyn DynamicPipe>>,
-    tx: Sender<usize>,
-    rx: Receiver<usize>,
+    response_pipe: Arc<Box<dyn DynamicPipe>>,
+    server: Arc<RpcServer>,
+    inbox: Option<Inbox>,
+    tx: Sender<(Source, usize)>,
+    rx: Receiver<(Source, usize)>,
+    stopping: Arc<AtomicBool>,
+    in_flight: Arc<AtomicUsize>,
 }
 
 impl Interface {
-    pub fn new(version: &str) -> std::result::Result<Interface, Error> {
+    pub fn new(version: &str) -> Result<Interface> {
         info!("启动 Worker Interface");
 
         // 使用新的通用配置读取方式获取配置信息
@@ -27,7 +71,7 @@ impl Interface {
         let interface_type = config::string("worker", "interface_type");
 
         // 创建一个生产者-多个消费者的消息队列
-        let (tx, rx) = bounded::<usize>(100); // 创建一个缓冲大小为 100 的通道
+        let (tx, rx) = bounded::<(Source, usize)>(100); // 创建一个缓冲大小为 100 的通道
 
         // 创建 pipe
         let pipe = match PipeFactory::connect(&interface_type, &interface_name, true) {
@@ -46,30 +90,146 @@ impl Interface {
             }
         };
 
-        let version = Version::from_str(&version).unwrap();
+        // 连接 entry 的响应管道，用于回复请求
+        let response_name = config::string("entry", "interface_name");
+        let response_type = config::string("entry", "interface_type");
+        let response_pipe = match PipeFactory::connect(&response_type, &response_name, true) {
+            Ok(pipe) => Arc::new(pipe),
+            Err(e) => {
+                error!("连接响应管道失败: {:?}", e);
+                return Err(e);
+            }
+        };
+        let server = Arc::new(RpcServer::new(
+            Arc::clone(&pipe),
+            Arc::clone(&response_pipe),
+        ));
+
+        let version = Version::from_str(version).unwrap();
         Ok(Interface {
             version,
             pipe,
+            response_pipe,
+            server,
+            inbox: None,
             tx,
             rx,
+            stopping: Arc::new(AtomicBool::new(false)),
+            in_flight: Arc::new(AtomicUsize::new(0)),
         })
     }
 
+    /// 获取接口版本
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// 在 worker 登记表中登记，并创建专属收件管道与响应管道
+    ///
+    /// 收件管道名为 `<worker.interface_name>_<worker_id>`，接收调度者派发的任务；
+    /// 响应管道名为 `resp_pipe_<worker_id>`，此后所有回复都写入该管道，由 entry 按登记表发现。
+    /// 需在 [`load`](Self::load) 与 [`start`](Self::start) 之前调用。
+    pub fn register(&mut self, worker_id: &str) -> Result<()> {
+        let interface_name = config::string("worker", "interface_name");
+        let interface_type = config::string("worker", "interface_type");
+        let inbox_name = format!("{}_{}", interface_name, worker_id);
+        let pipe: Arc<Box<dyn DynamicPipe>> =
+            Arc::new(PipeFactory::create(&interface_type, &inbox_name)?);
+        let response_name = worker_board::response_pipe_name(worker_id);
+        let response_pipe: Arc<Box<dyn DynamicPipe>> = Arc::new(PipeFactory::create(
+            &config::string("entry", "interface_type"),
+            &response_name,
+        )?);
+
+        let board_name =
+            config::string_or("scheduler", "board_name", worker_board::DEFAULT_BOARD_NAME);
+        let board = Arc::new(WorkerBoard::open(&board_name)?);
+        let registration = Arc::new(board.register(worker_id, &inbox_name, &response_name)?);
+        info!(
+            "已在 worker 登记表 {} 中登记，收件管道: {}，响应管道: {}",
+            board_name, inbox_name, response_name
+        );
+
+        self.server = Arc::new(RpcServer::new(
+            Arc::clone(&self.pipe),
+            Arc::clone(&response_pipe),
+        ));
+        self.inbox = Some(Inbox {
+            server: Arc::new(RpcServer::new(
+                Arc::clone(&pipe),
+                Arc::clone(&response_pipe),
+            )),
+            pipe,
+            registration,
+        });
+        self.response_pipe = response_pipe;
+        Ok(())
+    }
+
+    /// 主动推送消息的句柄，推送经本 worker 的响应管道交给 entry 中对应的客户端连接
+    ///
+    /// 需在 [`register`](Self::register) 之后调用，否则推送写入共享响应管道。
+    pub fn pusher(&self) -> Pusher {
+        self.server.pusher()
+    }
+
+    /// 停止从共享内存获取新任务，已获取的任务继续处理
+    pub fn stop(&self) {
+        self.stopping.store(true, Ordering::Release);
+    }
+
+    /// 是否已停止获取新任务（调用过 `stop` 或收到停止控制消息）
+    pub fn is_stopping(&self) -> bool {
+        self.stopping.load(Ordering::Acquire)
+    }
+
+    /// 异步等待进入停止状态
+    pub async fn stopped(&self) {
+        while !self.is_stopping() {
+            tokio::time::sleep(DRAIN_POLL * 10).await;
+        }
+    }
+
+    /// 当前在途（已获取、尚未回复）的任务数量
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Acquire)
+    }
+
+    /// 停止获取新任务并等待在途任务处理完成，超时返回 `false`
+    pub async fn drain(&self, timeout: Duration) -> bool {
+        self.stop();
+        let deadline = tokio::time::Instant::now() + timeout;
+        while self.in_flight() > 0 {
+            if tokio::time::Instant::now() >= deadline {
+                return false;
+            }
+            tokio::time::sleep(DRAIN_POLL).await;
+        }
+        true
+    }
+
     // 从  async_channel 获取任务
     // 处理
     // 返回
-    // , api: Arc<Box<dyn InterfaceApi>>
-    pub fn load(&self, consumer_count: i32) -> Result<()> {
+    pub fn load(&self, consumer_count: i32, api: Arc<Box<dyn InterfaceApi>>) -> Result<()> {
         info!("启动 Worker Interface");
         for i in 0..consumer_count {
             let work_rx = self.rx.clone();
-            let pipe_for_work = Arc::clone(&self.pipe);
+            let server = Arc::clone(&self.server);
+            let inbox = self
+                .inbox
+                .as_ref()
+                .map(|inbox| (Arc::clone(&inbox.server), Arc::clone(&inbox.registration)));
+            let api = Arc::clone(&api);
+            let stopping = Arc::clone(&self.stopping);
+            let in_flight = Arc::clone(&self.in_flight);
 
             tokio::spawn(async move {
                 loop {
                     // info!("消费者 {} 开始等待接收消息...", i);
                     match work_rx.recv().await {
-                        Ok(slot_index) => {
+                        Ok((source, slot_index)) => {
+                            let _in_flight = InFlight(Arc::clone(&in_flight));
                             info!(
                                 "消费者 {} 接收到消息: {} (时间戳: {:?})",
                                 i,
@@ -77,30 +237,62 @@ impl Interface {
                                 std::time::SystemTime::now()
                             );
 
-                            if let Err(_) =
-                                pipe_for_work.set_slot_state(slot_index, SlotState::INPROGRESS)
-                            {
-                                error!("Listener {} 设置槽位状态失败", slot_index);
-                                continue;
-                            }
+                            let (server, registration) = match (source, &inbox) {
+                                (Source::Inbox, Some((server, registration))) => {
+                                    (server, Some(registration))
+                                }
+                                _ => (&server, inbox.as_ref().map(|(_, r)| r)),
+                            };
 
-                            // // 接收消息
-                            let message = match pipe_for_work.receive(slot_index) {
-                                Ok(msg) => msg,
+                            // 接收消息及回复句柄
+                            let (message, responder) = match server.receive(slot_index) {
+                                Ok(request) => request,
                                 Err(e) => {
                                     error!("Listener {} 读取消息失败 {}", slot_index, e);
+                                    // 派发时计入的在途任务不再处理
+                                    if source == Source::Inbox
+                                        && let Some(registration) = registration
+                                    {
+                                        registration.abandon();
+                                    }
                                     continue;
                                 }
                             };
                             info!(
-                                "Listener {} 收到任务 flag={}: {}",
+                                "Listener {} 收到任务 request_id={} flag={}",
                                 slot_index,
-                                message.flag,
-                                String::from_utf8_lossy(&message.data)
+                                responder.request_id(),
+                                message.flag
                             );
 
-                            // 这里可以添加实际的消息处理逻辑
-                            // 比如调用 router 处理消息
+                            // 停止控制消息：确认后停止获取新任务
+                            if message.is_shutdown() {
+                                info!("消费者 {} 收到停止控制消息", i);
+                                stopping.store(true, Ordering::Release);
+                                if let Err(e) = responder.reply(Message::shutdown()).await {
+                                    error!("停止控制消息确认失败: {}", e);
+                                }
+                                continue;
+                            }
+
+                            // 交给业务处理并回复请求方
+                            let started = std::time::Instant::now();
+                            if source == Source::Shared
+                                && let Some(registration) = registration
+                            {
+                                registration.start();
+                            }
+                            let request_id = responder.request_id();
+                            let reply = api.handle(message).unwrap_or_else(|e| {
+                                warn!("请求 {} 处理失败: {}", request_id, e);
+                                router::error_reply(&e)
+                            });
+                            if let Err(e) = responder.reply(reply).await {
+                                error!("请求 {} 回复失败: {}", request_id, e);
+                            }
+                            if let Some(registration) = registration {
+                                registration.complete(started.elapsed());
+                            }
                         }
                         Err(e) => {
                             error!("消费者 {} 接收消息失败: {:?}", i, e);
@@ -117,24 +309,50 @@ impl Interface {
     // 启动
     // 从  从共享内存中 获取任务 ，发送到 async_channel
     pub async fn start(&self) -> Result<()> {
+        self.spawn_listener(Arc::clone(&self.pipe), Source::Shared);
+
+        if let Some(inbox) = &self.inbox {
+            self.spawn_listener(Arc::clone(&inbox.pipe), Source::Inbox);
+
+            // 定期更新心跳，调度者据此判断本 worker 是否存活
+            let registration = Arc::clone(&inbox.registration);
+            let stopping = Arc::clone(&self.stopping);
+            tokio::spawn(async move {
+                while !stopping.load(Ordering::Acquire) {
+                    registration.beat();
+                    tokio::time::sleep(HEARTBEAT_INTERVAL).await;
+                }
+            });
+        }
+
+        Ok(())
+    }
+
+    fn spawn_listener(&self, pipe_for_listener: Arc<Box<dyn DynamicPipe>>, source: Source) {
         let work_tx = self.tx.clone();
-        let pipe_for_listener = Arc::clone(&self.pipe);
+        let stopping = Arc::clone(&self.stopping);
+        let in_flight = Arc::clone(&self.in_flight);
         tokio::spawn(async move {
-            loop {
+            // 定时醒来检查停止标志
+            while !stopping.load(Ordering::Acquire) {
                 // 尝试从共享内存中fetch任务的slot_index
-                let slot_index = match pipe_for_listener.fetch() {
-                    Ok(index) => index,
-                    Err(_) => {
-                        // fetch中已有 短暂等待
-                        continue;
-                    }
-                };
+                let slot_index =
+                    match tokio::time::timeout(DRAIN_POLL * 10, pipe_for_listener.fetch_async())
+                        .await
+                    {
+                        Ok(Ok(index)) => index,
+                        _ => {
+                            // fetch_async 中已有等待
+                            continue;
+                        }
+                    };
+                in_flight.fetch_add(1, Ordering::AcqRel);
                 info!("Listener 获取任务 {} ", slot_index); ////// 
 
                 // 将获取的 slot_index 发送到 async_channel
                 match tokio::time::timeout(
                     std::time::Duration::from_secs(30),
-                    work_tx.send(slot_index),
+                    work_tx.send((source, slot_index)),
                 )
                 .await
                 {
@@ -144,16 +362,17 @@ impl Interface {
                     }
                     Ok(Err(e)) => {
                         // 通道发送错误
+                        in_flight.fetch_sub(1, Ordering::AcqRel);
                         eprintln!("Failed to send slot index: {:?}", e);
                     }
                     Err(_) => {
                         // 超时
+                        in_flight.fetch_sub(1, Ordering::AcqRel);
                         eprintln!("Timeout while sending slot index");
                     }
                 }
             }
+            info!("Listener 已停止获取任务");
         });
-
-        Ok(())
     }
 }
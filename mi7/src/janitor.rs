@@ -0,0 +1,117 @@
+//! 槽位租约回收
+//!
+//! 进程在 `hold`/`fetch` 之后、完成写入/读取之前崩溃，槽位会永久停留在
+//! WRITING / READING / INPROGRESS。[`SlotJanitor`] 定期扫描所监视的管道，
+//! 将租约超时的槽位归还，避免队列容量被逐渐耗尽。
+
+use crate::pipe::{DynamicPipe, PipeFactory};
+
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{debug, info};
+
+/// 默认扫描间隔
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(5);
+
+enum Target {
+    /// 已连接的管道
+    Connected(Arc<Box<dyn DynamicPipe>>),
+    /// 按名称监视的管道，首次扫描成功连接后保留连接
+    Named {
+        pipe_type: String,
+        name: String,
+        pipe: Option<Box<dyn DynamicPipe>>,
+    },
+}
+
+/// 槽位回收器
+pub struct SlotJanitor {
+    targets: Vec<Target>,
+    lease_timeout: Duration,
+    interval: Duration,
+}
+
+impl SlotJanitor {
+    /// 创建回收器，被持有超过 `lease_timeout` 的槽位视为卡死
+    pub fn new(lease_timeout: Duration) -> Self {
+        Self {
+            targets: Vec::new(),
+            lease_timeout,
+            interval: DEFAULT_INTERVAL,
+        }
+    }
+
+    /// 设置扫描间隔
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// 监视已连接的管道
+    pub fn watch(mut self, pipe: Arc<Box<dyn DynamicPipe>>) -> Self {
+        self.targets.push(Target::Connected(pipe));
+        self
+    }
+
+    /// 按类型与名称监视管道，管道尚不存在时在后续扫描中重试连接
+    pub fn watch_named(mut self, pipe_type: &str, name: &str) -> Self {
+        self.targets.push(Target::Named {
+            pipe_type: pipe_type.to_string(),
+            name: name.to_string(),
+            pipe: None,
+        });
+        self
+    }
+
+    /// 租约超时时间
+    pub fn lease_timeout(&self) -> Duration {
+        self.lease_timeout
+    }
+
+    /// 扫描一次所有管道，返回本次回收的槽位数量
+    pub fn run_once(&mut self) -> usize {
+        let lease_timeout = self.lease_timeout;
+        let mut reclaimed = 0;
+
+        for target in self.targets.iter_mut() {
+            match target {
+                Target::Connected(pipe) => reclaimed += pipe.reclaim_stuck(lease_timeout),
+                Target::Named {
+                    pipe_type,
+                    name,
+                    pipe,
+                } => {
+                    if pipe.is_none() {
+                        match PipeFactory::connect(pipe_type, name, false) {
+                            Ok(connected) => {
+                                info!("槽位回收器已连接管道: {}", name);
+                                *pipe = Some(connected);
+                            }
+                            Err(e) => {
+                                debug!("槽位回收器暂未连接管道 {}: {}", name, e);
+                                continue;
+                            }
+                        }
+                    }
+                    if let Some(pipe) = pipe {
+                        reclaimed += pipe.reclaim_stuck(lease_timeout);
+                    }
+                }
+            }
+        }
+
+        reclaimed
+    }
+
+    /// 在后台定期扫描
+    pub fn spawn(mut self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.interval);
+            loop {
+                interval.tick().await;
+                self.run_once();
+            }
+        })
+    }
+}
@@ -0,0 +1,739 @@
+//! 预写日志（WAL）：entry 与 worker 之间的恰好一次投递
+//!
+//! [`JournaledPipe`] 包装任意 [`DynamicPipe`]：每次发送前先把 `(request_id, 负载哈希, 负载)`
+//! 追加到 mmap 映射的日志文件，worker 处理完成后追加完成记录。进程重启后，
+//! 没有完成记录的请求可以通过 [`JournaledPipe::replay`] 以原 request_id 重新投递，
+//! worker 用 [`JournaledPipe::is_completed`] 跳过已处理过的重复请求。
+//!
+//! 同一进程内的线程通过互斥锁串行访问日志，进程之间通过日志文件上的 `flock` 互斥，
+//! 持有进程崩溃时由内核释放；记录完整写入后才推进尾指针，写到一半崩溃的记录不会被读到。
+//!
+//! 日志文件有两个记录区，任一时刻只使用其中一个。记录区写满时把未完成的发送记录复制到
+//! 另一个记录区，并记下已完成 request_id 的水位线，最后切换当前记录区：压缩中途崩溃时
+//! 原记录区保持不变，压缩后已完成的请求仍然被识别为已完成。
+
+use crate::Message;
+use crate::integrity::Integrity;
+use crate::locks::Mapping;
+use crate::pipe::{DynamicPipe, PipeConfig, PipeMetrics, PipeStatus};
+use crate::shared_slot::SlotState;
+use crate::wait::{Backoff, WaitStrategy};
+
+use crate::error::Result;
+use anyhow::{Context, anyhow};
+use fs2::FileExt;
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::future::Future;
+use std::os::unix::io::RawFd;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use std::{mem, ptr};
+
+/// 日志文件标识
+const JOURNAL_MAGIC: u64 = u64::from_le_bytes(*b"MI7WAL\0\0");
+
+/// 日志文件布局版本
+const JOURNAL_VERSION: u32 = 2;
+
+/// 负载哈希使用的算法
+const PAYLOAD_HASH: Integrity = Integrity::XxHash64;
+
+/// 日志文件头部，其后紧跟两个大小为 `capacity` 的记录区
+#[repr(C)]
+struct JournalHeader {
+    magic: u64,
+    version: u32,
+    _reserved: u32,
+    capacity: u64,      // 每个记录区的字节数
+    epoch: AtomicU64,   // 压缩次数，当前记录区为 epoch % 2
+    next_id: AtomicU64, // request_id 生成器，跨进程、跨重启递增
+    regions: [RegionHeader; 2],
+}
+
+/// 记录区头部
+#[repr(C)]
+struct RegionHeader {
+    tail: AtomicU64, // 记录区已使用的字节数
+    /// 压缩时写入的水位线：小于该值且在本记录区中没有发送记录的 request_id 均已完成
+    completed_below: AtomicU64,
+}
+
+/// 记录类型
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RecordKind {
+    /// 发送前写入，携带负载
+    Send = 1,
+    /// worker 处理完成
+    Complete = 2,
+    /// 发送失败，请求未进入管道
+    Abort = 3,
+}
+
+/// 记录头部，其后紧跟 `len` 字节负载（按 8 字节对齐）
+#[repr(C)]
+struct RecordHeader {
+    kind: u32,
+    len: u32,
+    request_id: u64,
+    hash: u64,
+}
+
+/// 当前记录区的内存索引
+///
+/// 打开日志时建立，之后每次加锁只读入其他进程新追加的记录；其他进程压缩过日志时重建。
+#[derive(Default)]
+struct Index {
+    epoch: Option<u64>,
+    scanned: usize, // 已读入索引的字节数
+    completed_below: u64,
+    max_id: u64,
+    pending: HashMap<u64, usize>, // 未完成的发送记录在记录区中的偏移
+    done: HashSet<u64>,
+}
+
+impl Index {
+    fn apply(&mut self, kind: RecordKind, request_id: u64, offset: usize) {
+        self.max_id = self.max_id.max(request_id);
+        match kind {
+            RecordKind::Send => {
+                if !self.done.contains(&request_id) {
+                    self.pending.insert(request_id, offset);
+                }
+            }
+            RecordKind::Complete | RecordKind::Abort => {
+                self.pending.remove(&request_id);
+                self.done.insert(request_id);
+            }
+        }
+    }
+
+    /// 索引对应的压缩次数，打开日志时已建立索引
+    fn epoch(&self) -> u64 {
+        self.epoch.unwrap_or_default()
+    }
+
+    fn is_completed(&self, request_id: u64) -> bool {
+        self.done.contains(&request_id)
+            || (request_id < self.completed_below && !self.pending.contains_key(&request_id))
+    }
+
+    /// 未完成发送记录的偏移（按写入顺序）
+    fn pending_offsets(&self) -> Vec<usize> {
+        let mut offsets: Vec<usize> = self.pending.values().copied().collect();
+        offsets.sort_unstable();
+        offsets
+    }
+}
+
+/// mmap 映射的日志文件
+struct Journal {
+    file: File,
+    mapping: Mapping,
+    index: Mutex<Index>,
+}
+
+impl Journal {
+    /// 打开或创建日志文件，`capacity` 为每个记录区的大小，仅在创建时生效
+    fn open(path: &Path, capacity: usize) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .with_context(|| format!("打开日志文件 {} 失败", path.display()))?;
+        file.lock_exclusive()?;
+
+        let result = Self::map(file, capacity);
+        if let Ok(journal) = &result {
+            FileExt::unlock(&journal.file)?;
+        }
+        result
+    }
+
+    /// 映射日志文件并建立索引，调用者持有排他的 `flock`（失败时随文件关闭释放）
+    fn map(file: File, capacity: usize) -> Result<Self> {
+        let existing = file.metadata()?.len() as usize;
+        let size = if existing >= mem::size_of::<JournalHeader>() {
+            existing
+        } else {
+            let size = mem::size_of::<JournalHeader>() + 2 * capacity;
+            file.set_len(size as u64)?;
+            size
+        };
+
+        let journal = Self {
+            mapping: Mapping::map_file(&file, size)?,
+            file,
+            index: Mutex::new(Index::default()),
+        };
+        journal.init_or_validate()?;
+        journal.sync_locked(&mut journal.index.lock().unwrap());
+        Ok(journal)
+    }
+
+    fn init_or_validate(&self) -> Result<()> {
+        let size = self.mapping.len();
+        let header = unsafe { &mut *(self.mapping.as_ptr() as *mut JournalHeader) };
+        if header.magic == 0 {
+            header.version = JOURNAL_VERSION;
+            header.capacity = ((size - mem::size_of::<JournalHeader>()) / 2) as u64;
+            header.epoch.store(0, Ordering::Relaxed);
+            header.next_id.store(1, Ordering::Relaxed);
+            header.magic = JOURNAL_MAGIC;
+            return Ok(());
+        }
+
+        if header.magic != JOURNAL_MAGIC || header.version != JOURNAL_VERSION {
+            return Err(anyhow!("不是有效的日志文件或版本不匹配").into());
+        }
+        if mem::size_of::<JournalHeader>() + 2 * header.capacity as usize > size {
+            return Err(anyhow!("日志文件大小与头部记录不一致").into());
+        }
+        Ok(())
+    }
+
+    fn header(&self) -> &JournalHeader {
+        unsafe { &*(self.mapping.as_ptr() as *const JournalHeader) }
+    }
+
+    fn region(&self, epoch: u64) -> &RegionHeader {
+        &self.header().regions[(epoch % 2) as usize]
+    }
+
+    /// 记录区已使用的字节数
+    fn tail(&self, epoch: u64) -> usize {
+        self.region(epoch).tail.load(Ordering::Acquire) as usize
+    }
+
+    /// 第 `epoch % 2` 个记录区的起始地址
+    fn records(&self, epoch: u64) -> *mut u8 {
+        let offset = mem::size_of::<JournalHeader>()
+            + (epoch % 2) as usize * self.header().capacity as usize;
+        unsafe { self.mapping.as_ptr().add(offset) }
+    }
+
+    fn record_size(len: usize) -> usize {
+        mem::size_of::<RecordHeader>() + len.div_ceil(8) * 8
+    }
+
+    /// 分配新的 request_id
+    fn next_id(&self) -> u64 {
+        self.header().next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// 持有进程内互斥锁与 `flock` 执行 `f`，执行前索引已追上日志
+    ///
+    /// `flock` 属于打开的文件描述，同一进程的线程之间并不互斥，且同一文件描述上的
+    /// `lock_shared` 会把其他线程持有的排他锁降级，因此所有加锁都在互斥锁内进行。
+    fn with_lock<R>(&self, exclusive: bool, f: impl FnOnce(&mut Index) -> R) -> Result<R> {
+        let mut index = self.index.lock().unwrap();
+        if exclusive {
+            self.file.lock_exclusive()?;
+        } else {
+            self.file.lock_shared()?;
+        }
+        self.sync_locked(&mut index);
+        let result = f(&mut index);
+        FileExt::unlock(&self.file)?;
+        Ok(result)
+    }
+
+    /// 读入其他进程追加的记录；当前记录区已切换时重建索引
+    fn sync_locked(&self, index: &mut Index) {
+        let epoch = self.header().epoch.load(Ordering::Acquire);
+        if index.epoch != Some(epoch) {
+            *index = Index {
+                epoch: Some(epoch),
+                completed_below: self.region(epoch).completed_below.load(Ordering::Acquire),
+                ..Index::default()
+            };
+        }
+
+        let records = self.records(epoch);
+        let tail = self.tail(epoch);
+        let mut offset = index.scanned;
+        while offset + mem::size_of::<RecordHeader>() <= tail {
+            let record = unsafe { &*(records.add(offset) as *const RecordHeader) };
+            let len = record.len as usize;
+            let size = Self::record_size(len);
+            if offset + size > tail {
+                break;
+            }
+
+            let kind = match record.kind {
+                1 => RecordKind::Send,
+                2 => RecordKind::Complete,
+                _ => RecordKind::Abort,
+            };
+            let payload = unsafe {
+                std::slice::from_raw_parts(
+                    records.add(offset + mem::size_of::<RecordHeader>()),
+                    len,
+                )
+            };
+            if kind == RecordKind::Send && !PAYLOAD_HASH.verify(payload, record.hash) {
+                tracing::warn!("日志中请求 {} 的负载校验失败，已忽略", record.request_id);
+            } else {
+                index.apply(kind, record.request_id, offset);
+            }
+            offset += size;
+        }
+        index.scanned = offset;
+    }
+
+    /// 追加一条记录
+    fn append(&self, kind: RecordKind, request_id: u64, payload: &[u8]) -> Result<()> {
+        self.with_lock(true, |index| {
+            self.append_locked(index, kind, request_id, payload)
+        })?
+    }
+
+    /// 追加一条记录，记录区不足时先压缩
+    fn append_locked(
+        &self,
+        index: &mut Index,
+        kind: RecordKind,
+        request_id: u64,
+        payload: &[u8],
+    ) -> Result<()> {
+        let size = Self::record_size(payload.len());
+        let capacity = self.header().capacity as usize;
+        if self.tail(index.epoch()) + size > capacity {
+            self.compact_locked(index);
+        }
+
+        let epoch = index.epoch();
+        let tail = self.tail(epoch);
+        if tail + size > capacity {
+            return Err(anyhow!("日志已满: 已使用 {} 字节，容量 {} 字节", tail, capacity).into());
+        }
+
+        unsafe {
+            let record = self.records(epoch).add(tail);
+            ptr::write(
+                record as *mut RecordHeader,
+                RecordHeader {
+                    kind: kind as u32,
+                    len: payload.len() as u32,
+                    request_id,
+                    hash: PAYLOAD_HASH.checksum(payload),
+                },
+            );
+            ptr::copy_nonoverlapping(
+                payload.as_ptr(),
+                record.add(mem::size_of::<RecordHeader>()),
+                payload.len(),
+            );
+        }
+        // 记录写完后才推进尾指针
+        self.region(epoch)
+            .tail
+            .store((tail + size) as u64, Ordering::Release);
+        index.apply(kind, request_id, tail);
+        index.scanned = tail + size;
+        Ok(())
+    }
+
+    /// 把未完成的发送记录复制到另一个记录区，随后切换当前记录区
+    ///
+    /// 已完成请求的记录不再保留，改由水位线识别：切换前的所有 request_id 中，
+    /// 不在未完成记录里的都已完成。切换之前崩溃时原记录区保持不变。
+    fn compact_locked(&self, index: &mut Index) {
+        let epoch = index.epoch();
+        let from = self.records(epoch);
+        let to = self.records(epoch + 1);
+
+        let mut tail = 0;
+        for offset in index.pending_offsets() {
+            let len = unsafe { (*(from.add(offset) as *const RecordHeader)).len } as usize;
+            let size = Self::record_size(len);
+            // 未完成记录原本就在同样大小的记录区内，复制后必然放得下
+            unsafe { ptr::copy_nonoverlapping(from.add(offset), to.add(tail), size) };
+            tail += size;
+        }
+
+        let completed_below = self
+            .header()
+            .next_id
+            .load(Ordering::Acquire)
+            .max(index.max_id + 1)
+            .max(index.completed_below);
+        let region = self.region(epoch + 1);
+        region.tail.store(tail as u64, Ordering::Release);
+        region
+            .completed_below
+            .store(completed_below, Ordering::Release);
+        self.header().epoch.store(epoch + 1, Ordering::Release);
+        self.sync_locked(index);
+    }
+
+    /// 未完成的发送记录（按写入顺序）
+    fn pending(&self) -> Result<Vec<(u64, Vec<u8>)>> {
+        self.with_lock(false, |index| {
+            let records = self.records(index.epoch());
+            index
+                .pending_offsets()
+                .into_iter()
+                .map(|offset| unsafe {
+                    let record = &*(records.add(offset) as *const RecordHeader);
+                    let payload = std::slice::from_raw_parts(
+                        records.add(offset + mem::size_of::<RecordHeader>()),
+                        record.len as usize,
+                    );
+                    (record.request_id, payload.to_vec())
+                })
+                .collect()
+        })
+    }
+
+    fn is_completed(&self, request_id: u64) -> Result<bool> {
+        self.with_lock(false, |index| index.is_completed(request_id))
+    }
+}
+
+/// 带预写日志的管道
+pub struct JournaledPipe {
+    inner: Box<dyn DynamicPipe>,
+    journal: Journal,
+}
+
+impl JournaledPipe {
+    /// 默认日志记录区大小
+    pub const DEFAULT_CAPACITY: usize = 16 * 1024 * 1024;
+
+    /// 包装管道并打开（或创建）日志文件
+    ///
+    /// 发送方与接收方打开同一个日志文件：发送方写入发送记录，接收方写入完成记录。
+    pub fn open(inner: Box<dyn DynamicPipe>, path: impl AsRef<Path>) -> Result<Self> {
+        Self::open_with_capacity(inner, path, Self::DEFAULT_CAPACITY)
+    }
+
+    /// 使用指定的记录区大小打开日志，仅在创建日志文件时生效
+    ///
+    /// 日志文件包含两个这样大小的记录区。
+    pub fn open_with_capacity(
+        inner: Box<dyn DynamicPipe>,
+        path: impl AsRef<Path>,
+        capacity: usize,
+    ) -> Result<Self> {
+        Ok(Self {
+            inner,
+            journal: Journal::open(path.as_ref(), capacity)?,
+        })
+    }
+
+    /// 被包装的管道
+    pub fn inner(&self) -> &dyn DynamicPipe {
+        self.inner.as_ref()
+    }
+
+    /// 记录请求已处理完成，之后不会再被重放
+    pub fn complete(&self, request_id: u64) -> Result<()> {
+        self.journal.append(RecordKind::Complete, request_id, &[])
+    }
+
+    /// 请求是否已处理完成，worker 据此跳过重放产生的重复请求
+    pub fn is_completed(&self, request_id: u64) -> bool {
+        self.journal.is_completed(request_id).unwrap_or(false)
+    }
+
+    /// 尚未完成的请求（按发送顺序）
+    pub fn pending(&self) -> Result<Vec<(u64, Message)>> {
+        self.journal
+            .pending()?
+            .into_iter()
+            .map(|(request_id, payload)| Ok((request_id, Self::decode(&payload)?)))
+            .collect()
+    }
+
+    /// 以原 request_id 重新投递所有未完成的请求，返回投递数量
+    ///
+    /// 重启后调用；重放不再追加发送记录。
+    pub fn replay(&self, timeout: Duration) -> Result<usize> {
+        let deadline = Instant::now() + timeout;
+        let pending = self.pending()?;
+        let count = pending.len();
+        for (request_id, message) in pending {
+            self.deliver(request_id, message, deadline)?;
+        }
+        if count > 0 {
+            tracing::info!("管道 {} 已重放 {} 个未完成请求", self.inner.name(), count);
+        }
+        Ok(count)
+    }
+
+    /// 先写日志再发送，发送失败时写入中止记录
+    fn journaled_send(
+        &self,
+        request_id: u64,
+        message: Message,
+        send: impl FnOnce(Message) -> Result<u64>,
+    ) -> Result<u64> {
+        let payload = bincode::encode_to_vec(&message, bincode::config::standard())
+            .context("编码消息失败")?;
+        self.journal
+            .append(RecordKind::Send, request_id, &payload)?;
+
+        send(message).inspect_err(|_| {
+            let _ = self.journal.append(RecordKind::Abort, request_id, &[]);
+        })
+    }
+
+    /// 等待空槽位并以指定 request_id 发送
+    fn deliver(&self, request_id: u64, message: Message, deadline: Instant) -> Result<u64> {
+        // 被包装的管道没有通用的阻塞等待接口，默认的 Blocking 以短间隔退避轮询代替
+        let wait = match self.inner.config().wait_strategy {
+            WaitStrategy::Blocking => WaitStrategy::SLEEP_BACKOFF,
+            wait => wait,
+        };
+        let mut backoff = Backoff::new(wait);
+        let index = loop {
+            if let Ok(index) = self.inner.hold() {
+                break index;
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(anyhow!("请求 {} 等待空槽位超时", request_id).into());
+            }
+            backoff.snooze(Some(remaining));
+        };
+
+        self.inner.send_tagged(index, request_id, message)
+    }
+
+    fn decode(payload: &[u8]) -> Result<Message> {
+        let (message, _) = bincode::decode_from_slice(payload, bincode::config::standard())
+            .context("解码日志中的消息失败")?;
+        Ok(message)
+    }
+}
+
+impl DynamicPipe for JournaledPipe {
+    fn hold(&self) -> Result<usize> {
+        self.inner.hold()
+    }
+
+    fn send(&self, index: usize, message: Message) -> Result<u64> {
+        let request_id = self.journal.next_id();
+        self.send_tagged(index, request_id, message)
+    }
+
+    fn fetch(&self) -> Result<usize> {
+        self.inner.fetch()
+    }
+
+    fn fetch_async(&self) -> Pin<Box<dyn Future<Output = Result<usize>> + Send + '_>> {
+        self.inner.fetch_async()
+    }
+
+    fn hold_async(&self) -> Pin<Box<dyn Future<Output = Result<usize>> + Send + '_>> {
+        self.inner.hold_async()
+    }
+
+    fn receive(&self, index: usize) -> Result<Message> {
+        self.inner.receive(index)
+    }
+
+    fn send_tagged(&self, index: usize, request_id: u64, message: Message) -> Result<u64> {
+        self.journaled_send(request_id, message, |message| {
+            self.inner.send_tagged(index, request_id, message)
+        })
+    }
+
+    fn receive_tagged(&self, index: usize) -> Result<(u64, Message)> {
+        self.inner.receive_tagged(index)
+    }
+
+    fn send_blocking(&self, message: Message, timeout: Duration) -> Result<u64> {
+        let deadline = Instant::now() + timeout;
+        let request_id = self.journal.next_id();
+        self.journaled_send(request_id, message, |message| {
+            self.deliver(request_id, message, deadline)
+        })
+    }
+
+    fn receive_blocking(&self, timeout: Duration) -> Result<Message> {
+        self.inner.receive_blocking(timeout)
+    }
+
+    fn get_slot_state(&self, index: usize) -> Result<SlotState> {
+        self.inner.get_slot_state(index)
+    }
+
+    fn is_slot_abandoned(&self, index: usize) -> bool {
+        self.inner.is_slot_abandoned(index)
+    }
+
+    fn status(&self) -> PipeStatus {
+        self.inner.status()
+    }
+
+    fn metrics(&self) -> PipeMetrics {
+        self.inner.metrics()
+    }
+
+    fn config(&self) -> PipeConfig {
+        self.inner.config()
+    }
+
+    fn capacity(&self) -> usize {
+        self.inner.capacity()
+    }
+
+    fn slot_size(&self) -> usize {
+        self.inner.slot_size()
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn unlink(&self) -> Result<()> {
+        self.inner.unlink()
+    }
+
+    fn attached_processes(&self) -> Vec<u32> {
+        self.inner.attached_processes()
+    }
+
+    fn reclaim_stuck(&self, timeout: Duration) -> usize {
+        self.inner.reclaim_stuck(timeout)
+    }
+    fn is_backpressured(&self) -> bool {
+        self.inner.is_backpressured()
+    }
+    fn receive_unacked(&self, index: usize) -> Result<(u64, Message)> {
+        self.inner.receive_unacked(index)
+    }
+
+    fn ack(&self, index: usize) -> Result<()> {
+        self.inner.ack(index)
+    }
+
+    fn nack(&self, index: usize) -> Result<bool> {
+        self.inner.nack(index)
+    }
+
+    fn peek(&self) -> Result<Option<(u64, Message)>> {
+        self.inner.peek()
+    }
+
+    fn peek_at(&self, index: usize) -> Result<Option<(u64, Message)>> {
+        self.inner.peek_at(index)
+    }
+
+    fn has_ready(&self) -> bool {
+        self.inner.has_ready()
+    }
+
+    fn notify_fd(&self) -> Option<RawFd> {
+        self.inner.notify_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipe::{DynCrossProcessPipe, PipeConfig};
+
+    fn pipe(tag: &str) -> Box<dyn DynamicPipe> {
+        let name = format!("mi7_test_journal_{}_{}", tag, std::process::id());
+        Box::new(DynCrossProcessPipe::create_with_config(&name, PipeConfig::new(8, 256)).unwrap())
+    }
+
+    #[test]
+    fn test_replay_unacknowledged_after_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("entry.wal");
+        let timeout = Duration::from_secs(1);
+
+        let first = {
+            let journaled = JournaledPipe::open_with_capacity(pipe("before"), &path, 1024).unwrap();
+            let mut ids = Vec::new();
+            for text in ["a", "b", "c"] {
+                ids.push(
+                    journaled
+                        .send_blocking(Message::init(text.to_string()), timeout)
+                        .unwrap(),
+                );
+            }
+
+            // worker 处理完第一条
+            let index = journaled.fetch().unwrap();
+            let (request_id, message) = journaled.receive_tagged(index).unwrap();
+            assert_eq!((request_id, message.data.as_slice()), (ids[0], &b"a"[..]));
+            journaled.complete(request_id).unwrap();
+            assert!(journaled.is_completed(request_id));
+
+            // 反复发送并完成，超出记录区容量后自动压缩
+            for _ in 0..50 {
+                let id = journaled
+                    .send_blocking(Message::init("x".to_string()), timeout)
+                    .unwrap();
+                journaled.receive_blocking(timeout).unwrap();
+                journaled.complete(id).unwrap();
+            }
+            // 压缩后已完成的请求仍然被识别为已完成，未完成的不会
+            assert!(journaled.is_completed(ids[0]));
+            assert!(!journaled.is_completed(ids[1]));
+            ids
+        };
+
+        // 重启：新的管道中没有消息，未完成的请求以原 request_id 重放
+        let journaled = JournaledPipe::open(pipe("after"), &path).unwrap();
+        let pending = journaled.pending().unwrap();
+        assert_eq!(
+            pending.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            &first[1..]
+        );
+        assert_eq!(journaled.replay(timeout).unwrap(), 2);
+
+        for (expected_id, expected) in first[1..].iter().zip(["b", "c"]) {
+            let index = journaled.fetch().unwrap();
+            let (request_id, message) = journaled.receive_tagged(index).unwrap();
+            assert_eq!(request_id, *expected_id);
+            assert_eq!(message.data, expected.as_bytes());
+            journaled.complete(request_id).unwrap();
+        }
+        assert!(journaled.pending().unwrap().is_empty());
+        assert!(journaled.is_completed(first[0]));
+    }
+
+    #[test]
+    fn test_concurrent_threads_append_without_losing_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("threads.wal");
+        let journaled = JournaledPipe::open_with_capacity(pipe("threads"), &path, 4096).unwrap();
+
+        // 多个线程同时发送并完成，其间反复压缩
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..200 {
+                        let request_id = journaled.journal.next_id();
+                        journaled
+                            .journal
+                            .append(RecordKind::Send, request_id, b"payload")
+                            .unwrap();
+                        assert!(!journaled.is_completed(request_id));
+                        journaled.complete(request_id).unwrap();
+                        assert!(journaled.is_completed(request_id));
+                    }
+                });
+            }
+        });
+        assert!(journaled.pending().unwrap().is_empty());
+
+        // 另一个打开同一日志的实例看到相同的状态
+        let other = JournaledPipe::open(pipe("threads_other"), &path).unwrap();
+        assert!(other.pending().unwrap().is_empty());
+        assert!(other.is_completed(1));
+        assert!(!other.is_completed(other.journal.next_id()));
+    }
+}
@@ -0,0 +1,262 @@
+//! 按负载单独创建共享内存段的大数据存储
+//!
+//! 寄存箱的 box 大小固定、数量有限；[`LargeDataManager`] 为每份数据创建一个
+//! 恰好容纳它的命名共享内存段，只把 [`DataReference`] 通过管道发送给对方。
+//! 消费方以只读方式映射读取，用完后调用 [`LargeDataManager::release`] 删除该段。
+//!
+//! 消费方崩溃或忘记释放时段会一直留在 `/dev/shm`。每个段可以带 TTL，到期时间
+//! 编码在段名称末尾，任何进程（通常是守护进程）都可以通过 [`LargeDataManager::gc`]
+//! 或 [`LargeDataManager::spawn_gc`] 删除已过期的段。
+
+use crate::error::Result;
+use anyhow::anyhow;
+use std::ops::Deref;
+use std::ptr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+use crate::integrity::Integrity;
+use crate::locks::Mapping;
+use crate::shm_registry::SharedMemoryRegistry;
+
+/// 存放在共享内存段中的数据描述
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataReference {
+    /// 共享内存段名称
+    pub name: String,
+    /// 数据长度
+    pub size: u64,
+    /// 数据校验值
+    pub checksum: u64,
+    /// 过期时间（UNIX 秒），`None` 表示不过期
+    pub expires_at: Option<u64>,
+}
+
+impl DataReference {
+    /// 是否已过期
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|at| at <= unix_now())
+    }
+}
+
+/// 大数据存储管理器
+pub struct LargeDataManager {
+    prefix: String,
+    integrity: Integrity,
+    default_ttl: Option<Duration>,
+    next_id: AtomicU64,
+}
+
+impl LargeDataManager {
+    /// 创建管理器，段名称为 `{prefix}_{pid}_{序号}_{过期时间}`
+    pub fn new(prefix: &str) -> Self {
+        Self {
+            prefix: prefix.trim_start_matches('/').to_string(),
+            integrity: Integrity::default(),
+            default_ttl: None,
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// 设置校验算法，读写双方需使用相同的算法
+    pub fn with_integrity(mut self, integrity: Integrity) -> Self {
+        self.integrity = integrity;
+        self
+    }
+
+    /// 设置 [`LargeDataManager::store_data`] 使用的默认 TTL
+    pub fn with_default_ttl(mut self, ttl: Duration) -> Self {
+        self.default_ttl = Some(ttl);
+        self
+    }
+
+    /// 段名称前缀
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// 将数据写入新建的共享内存段，使用默认 TTL
+    ///
+    /// 段不登记到 [`SharedMemoryRegistry`]：删除的责任随引用交给消费方。
+    pub fn store_data(&self, data: &[u8]) -> Result<DataReference> {
+        self.store_data_with_ttl(data, self.default_ttl)
+    }
+
+    /// 将数据写入新建的共享内存段，`ttl` 为 `None` 时不过期
+    pub fn store_data_with_ttl(&self, data: &[u8], ttl: Option<Duration>) -> Result<DataReference> {
+        let expires_at = ttl.map(|ttl| unix_now() + ttl.as_secs().max(1));
+        let name = format!(
+            "{}_{}_{}_{}",
+            self.prefix,
+            std::process::id(),
+            self.next_id.fetch_add(1, Ordering::Relaxed),
+            expires_at.unwrap_or(0)
+        );
+
+        let mapping = Mapping::create_new(&name, data.len())?;
+        unsafe { ptr::copy_nonoverlapping(data.as_ptr(), mapping.as_ptr(), data.len()) };
+
+        Ok(DataReference {
+            name,
+            size: data.len() as u64,
+            checksum: self.integrity.checksum(data),
+            expires_at,
+        })
+    }
+
+    /// 以只读方式映射引用指向的数据，校验长度与校验值
+    pub fn load_data(&self, reference: &DataReference) -> Result<MappedData> {
+        let mapping = Mapping::open_read_only(&reference.name)?;
+        if mapping.len() as u64 != reference.size {
+            return Err(anyhow!(
+                "共享内存段 {} 大小不一致: 期望 {}, 实际 {}",
+                reference.name,
+                reference.size,
+                mapping.len()
+            )
+            .into());
+        }
+
+        let data = MappedData { mapping };
+        if !self.integrity.verify(&data, reference.checksum) {
+            return Err(anyhow!("共享内存段 {} 校验失败", reference.name).into());
+        }
+        Ok(data)
+    }
+
+    /// 删除引用指向的共享内存段，已映射的 [`MappedData`] 仍可继续读取
+    pub fn release(&self, reference: &DataReference) -> Result<()> {
+        SharedMemoryRegistry::unlink(&reference.name)
+    }
+
+    /// 删除本前缀下已过期的段，返回被删除的名称
+    ///
+    /// 通过扫描 `/dev/shm` 实现，可由任意进程调用；非 Linux 平台直接返回空列表。
+    pub fn gc(&self) -> Result<Vec<String>> {
+        #[cfg(target_os = "linux")]
+        {
+            let prefix = format!("{}_", self.prefix);
+            let now = unix_now();
+            let mut removed = Vec::new();
+
+            for entry in std::fs::read_dir("/dev/shm")? {
+                let name = entry?.file_name().to_string_lossy().into_owned();
+                if !name.starts_with(&prefix) {
+                    continue;
+                }
+                let Some(expires_at) = Self::expiry_of(&name) else {
+                    continue;
+                };
+                if expires_at == 0 || expires_at > now {
+                    continue;
+                }
+
+                match SharedMemoryRegistry::unlink(&name) {
+                    Ok(()) => removed.push(name),
+                    Err(e) => warn!("删除过期共享内存段失败: {}", e),
+                }
+            }
+
+            if !removed.is_empty() {
+                debug!("已删除 {} 个过期共享内存段", removed.len());
+            }
+            Ok(removed)
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            Ok(Vec::new())
+        }
+    }
+
+    /// 在后台定期执行 [`LargeDataManager::gc`]
+    pub fn spawn_gc(self: &Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        let manager = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            loop {
+                interval.tick().await;
+                if let Err(e) = manager.gc() {
+                    warn!("清理过期共享内存段失败: {}", e);
+                }
+            }
+        })
+    }
+
+    /// 从段名称末尾解析过期时间，0 表示不过期
+    fn expiry_of(name: &str) -> Option<u64> {
+        name.rsplit('_').next()?.parse().ok()
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// 只读映射的数据，`Drop` 时解除映射
+pub struct MappedData {
+    mapping: Mapping,
+}
+
+impl Deref for MappedData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.mapping.as_ptr(), self.mapping.len()) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_load_release() {
+        let manager = LargeDataManager::new(&format!("mi7_test_large_{}", std::process::id()));
+
+        let payload: Vec<u8> = (0..3 * 1024 * 1024 + 7).map(|i| (i % 251) as u8).collect();
+        let reference = manager.store_data(&payload).unwrap();
+        assert_eq!(reference.size, payload.len() as u64);
+
+        let data = manager.load_data(&reference).unwrap();
+        assert_eq!(&data[..], &payload[..]);
+
+        // 删除名称后已有映射仍然有效，再次加载失败
+        manager.release(&reference).unwrap();
+        assert_eq!(data.len(), payload.len());
+        assert!(manager.load_data(&reference).is_err());
+
+        let empty = manager.store_data(&[]).unwrap();
+        assert!(manager.load_data(&empty).unwrap().is_empty());
+        manager.release(&empty).unwrap();
+    }
+
+    #[test]
+    fn test_gc_removes_expired_segments() {
+        let manager = LargeDataManager::new(&format!("mi7_test_large_gc_{}", std::process::id()));
+
+        let kept = manager.store_data(b"kept").unwrap();
+        let fresh = manager
+            .store_data_with_ttl(b"fresh", Some(Duration::from_secs(3600)))
+            .unwrap();
+        let expired = manager
+            .store_data_with_ttl(b"expired", Some(Duration::from_secs(1)))
+            .unwrap();
+        assert!(kept.expires_at.is_none() && !fresh.is_expired());
+
+        std::thread::sleep(Duration::from_millis(2100));
+        assert!(expired.is_expired());
+        assert_eq!(manager.gc().unwrap(), vec![expired.name.clone()]);
+        assert!(manager.load_data(&expired).is_err());
+        assert_eq!(&manager.load_data(&fresh).unwrap()[..], b"fresh");
+
+        manager.release(&kept).unwrap();
+        manager.release(&fresh).unwrap();
+    }
+}
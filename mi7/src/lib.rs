@@ -1,28 +1,109 @@
+pub mod access;
+pub mod affinity;
+pub mod auth;
+pub mod bridge;
+pub mod broadcast;
+pub mod cluster;
+pub mod codec;
+pub mod command;
 pub mod config;
+pub mod encryption;
+pub mod error;
+pub mod futex;
+pub mod heap_pipe;
+pub mod integrity;
+pub mod janitor;
+pub mod journal;
+pub mod large_data;
+pub mod locks;
+pub mod lock_debug;
+pub mod log_ring;
 pub mod logging;
+pub mod memory;
+pub mod metrics;
+pub mod notify;
+pub mod resizable;
+pub mod segmented;
 pub mod shared_box;
+pub mod sharded;
+pub mod shared_map;
 pub mod version;
 
 pub mod pipe;
+pub mod pipe_set;
+pub mod pipe_stream;
+pub mod rate_limit;
+pub mod rpc;
 pub mod shared_slot;
+pub mod shm_arena;
+pub mod shm_registry;
+pub mod shm_sync;
+pub mod stream;
+pub mod shutdown;
+pub mod snapshot;
+pub mod topic;
+pub mod tracing_ipc;
+pub mod typed_pipe;
+pub mod uds_pipe;
+pub mod wait;
+pub mod worker_board;
 
 // 接口
 pub mod interface;
+pub mod router;
 
 // Re-export the config types and functions
+pub use access::{AccessPolicy, PeerCredentials};
+pub use affinity::AffinityRing;
+pub use auth::MessageAuthenticator;
+pub use broadcast::{BroadcastPipe, BroadcastReceiver};
+pub use cluster::{ClusterView, Heartbeat, ProcessInfo, ProcessRole};
+pub use codec::{Codec, CodecKind};
+pub use command::{Command, RawTransport, Response, SCHEMA_VERSION, SchemaError};
+pub use integrity::Integrity;
+pub use pipe_set::PipeSet;
+pub use pipe_stream::{PipeSink, PipeStream};
+pub use router::{RouteError, RouteMetrics, Router};
+pub use typed_pipe::{TypeMismatch, TypedPipe};
+pub use locks::{
+    IpcBarrier, IpcRwLock, IpcSemaphore, IpcSeqLock, LockStats, LockTimeout, SeqLock,
+    SeqLockPoisoned,
+};
 pub use config::{Config, ConfigError, bool, get_config, init_config, int, string};
+pub use encryption::{Encryption, PayloadCipher, PayloadKey};
+pub use error::{Error, ErrorCategory, ErrorCode, Result};
 
 /// 消息结构体，支持bincode序列化
-#[derive(Debug, Clone, bincode::Encode, bincode::Decode)]
+#[derive(
+    Debug, Clone, bincode::Encode, bincode::Decode, serde::Serialize, serde::Deserialize,
+)]
 pub struct Message {
     pub flag: u8,
     pub data: Vec<u8>,
     pub timestamp: u64,
+    /// 过期时间（UNIX 毫秒），0 表示不过期；接收方跳过已过期的消息
+    #[serde(default)]
+    pub expires_at: u64,
+    /// 链路追踪上下文，见 [`tracing_ipc`]
+    #[serde(default)]
+    pub trace: Option<TraceContext>,
+    /// 亲和键哈希，0 表示未携带；携带相同亲和键的消息派发给同一个 worker，见 [`affinity`]
+    #[serde(default)]
+    pub affinity: u64,
 }
 
 impl Message {
     const DEFAULT_FLAG: u8 = 0;
 
+    /// 控制消息：请求接收方停止
+    pub const CONTROL_SHUTDOWN: u8 = 0xFF;
+
+    /// 大负载引用：数据存放在共享寄存箱中，消息只携带 box 描述
+    pub const LARGE_PAYLOAD: u8 = 0xFE;
+
+    /// 服务端推送：worker 主动发往某个客户端连接的消息，数据前 8 字节为连接 ID
+    pub const PUSH: u8 = 0xFD;
+
     pub fn new(flag: u8, data: String) -> Self {
         Self {
             flag,
@@ -31,12 +112,117 @@ impl Message {
                 .duration_since(std::time::UNIX_EPOCH)
                 .unwrap()
                 .as_secs(),
+            expires_at: 0,
+            trace: None,
+            affinity: 0,
         }
     }
 
     pub fn init(data: String) -> Self {
         Self::new(Self::DEFAULT_FLAG, data)
     }
+
+    /// 设置存活时间，从现在起超过 `ttl` 后消息过期
+    pub fn with_ttl(self, ttl: std::time::Duration) -> Self {
+        self.with_deadline(std::time::SystemTime::now() + ttl)
+    }
+
+    /// 设置过期时间点
+    pub fn with_deadline(mut self, deadline: std::time::SystemTime) -> Self {
+        self.expires_at = deadline
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default()
+            .max(1);
+        self
+    }
+
+    /// 携带追踪上下文，生产方 span 由 [`tracing_ipc::producer_span`] 在此基础上派生
+    pub fn with_trace(mut self, trace: TraceContext) -> Self {
+        self.trace = Some(trace);
+        self
+    }
+
+    /// 携带亲和键（如用户 ID），相同亲和键的消息由同一个 worker 处理
+    pub fn with_affinity(mut self, key: impl AsRef<[u8]>) -> Self {
+        self.affinity = affinity::key_hash(key.as_ref());
+        self
+    }
+
+    /// 是否已过期
+    pub fn is_expired(&self) -> bool {
+        self.expires_at != 0
+            && std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or_default()
+                >= self.expires_at
+    }
+
+    /// 停止控制消息
+    pub fn shutdown() -> Self {
+        Self::new(Self::CONTROL_SHUTDOWN, String::new())
+    }
+
+    /// 是否为停止控制消息
+    pub fn is_shutdown(&self) -> bool {
+        self.flag == Self::CONTROL_SHUTDOWN
+    }
+
+    /// 指向寄存箱中大负载的引用消息
+    pub fn large_payload(payload: LargePayload) -> Self {
+        let mut data = Vec::with_capacity(LargePayload::ENCODED_LEN);
+        data.extend_from_slice(&payload.box_id.to_le_bytes());
+        data.extend_from_slice(&payload.size.to_le_bytes());
+        data.extend_from_slice(&payload.checksum.to_le_bytes());
+        let mut message = Self::new(Self::LARGE_PAYLOAD, String::new());
+        message.data = data;
+        message
+    }
+
+    /// 解析大负载引用，不是大负载消息时返回 `None`
+    pub fn as_large_payload(&self) -> Option<LargePayload> {
+        if self.flag != Self::LARGE_PAYLOAD || self.data.len() != LargePayload::ENCODED_LEN {
+            return None;
+        }
+        Some(LargePayload {
+            box_id: u32::from_le_bytes(self.data[0..4].try_into().ok()?),
+            size: u64::from_le_bytes(self.data[4..12].try_into().ok()?),
+            checksum: u64::from_le_bytes(self.data[12..20].try_into().ok()?),
+        })
+    }
+
+    /// 发往连接 `connection_id` 的推送消息
+    pub fn push(connection_id: u64, payload: impl AsRef<[u8]>) -> Self {
+        let payload = payload.as_ref();
+        let mut data = Vec::with_capacity(8 + payload.len());
+        data.extend_from_slice(&connection_id.to_le_bytes());
+        data.extend_from_slice(payload);
+        let mut message = Self::new(Self::PUSH, String::new());
+        message.data = data;
+        message
+    }
+
+    /// 解析推送消息，返回目标连接 ID 与负载；不是推送消息时返回 `None`
+    pub fn as_push(&self) -> Option<(u64, &[u8])> {
+        if self.flag != Self::PUSH || self.data.len() < 8 {
+            return None;
+        }
+        let connection_id = u64::from_le_bytes(self.data[..8].try_into().ok()?);
+        Some((connection_id, &self.data[8..]))
+    }
+}
+
+/// 存放在共享寄存箱中的大负载描述
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LargePayload {
+    pub box_id: u32,
+    pub size: u64,
+    pub checksum: u64,
+}
+
+impl LargePayload {
+    const ENCODED_LEN: usize = 20;
 }
 
 /// 队列状态信息
@@ -46,7 +232,35 @@ pub struct QueueStatus {
     pub message_count: usize,
 }
 
-pub use pipe::{CrossProcessPipe, PipeConfig, PipeStatus};
-pub use shared_slot::{SharedSlotPipe, Slot};
-pub use shared_box::{SharedMemoryMailbox, BoxState, BoxSize, MailboxStats, MailboxLock, BoxConfig};
+pub use pipe::{
+    CrossProcessPipe, DynCrossProcessPipe, MessageExpired, PinnedSlot, PipeConfig, PipeMetrics,
+    PipeStatus, SlotInfo,
+};
+pub use rate_limit::{RateLimited, RateLimiterStats, SharedRateLimiter};
+pub use rpc::{PendingReply, Pusher, Responder, RpcChannel, RpcServer};
+pub use shared_slot::{
+    DynSharedSlotPipe, FullPolicy, LayoutMismatch, PayloadTooLarge, PipeMode, SharedSlotPipe, Slot,
+    SlotHandle, SlotMapping, StaleHandle,
+};
+pub use heap_pipe::HeapSlotPipe;
+pub use uds_pipe::UdsPipe;
+pub use janitor::SlotJanitor;
+pub use journal::JournaledPipe;
+pub use large_data::{DataReference, LargeDataManager, MappedData};
+pub use memory::{MemoryAdvice, MemoryUsage};
+pub use resizable::ResizablePipe;
+pub use segmented::SegmentedPipe;
+pub use sharded::ShardedPipe;
+pub use shm_arena::{ArenaError, ArenaHandle, ArenaStats, ShmArena};
+pub use shm_registry::SharedMemoryRegistry;
+pub use shm_sync::MutexAttr;
+pub use shutdown::ShutdownCoordinator;
+pub use snapshot::{QueueSnapshot, SnapshotEntry};
+pub use stream::{StreamPipe, Subscription};
+pub use topic::{TopicPipe, TopicSubscriber};
+pub use tracing_ipc::TraceContext;
+pub use wait::WaitStrategy;
+pub use worker_board::{WorkerBoard, WorkerInfo, WorkerRegistration};
+pub use shared_map::{MapFull, MapStats, SharedHashMap};
+pub use shared_box::{SharedMemoryMailbox, BoxState, BoxSize, MailboxStats, MailboxLock, BoxConfig, BoxReader, BoxWriter, HugePages};
 pub use version::{Version, VersionParseError};
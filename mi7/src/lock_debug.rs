@@ -0,0 +1,718 @@
+//! 锁调试诊断（`lock_debug` feature）
+//!
+//! 开启后，共享内存中的互斥锁（[`ShmMutex`](crate::shm_sync::ShmMutex)）、读写锁
+//! （[`IpcRwLock`](crate::locks::IpcRwLock)）与寄存箱全局锁在等待、获取与释放时写入
+//! 共享的诊断区（默认 `mi7_lock_debug`，可用环境变量 `MI7_LOCK_DEBUG_NAME` 覆盖）：
+//!
+//! - 持有表：各线程当前持有或正在等待的锁、PID/TID 与起始时间
+//! - 加锁顺序：同一线程持有 A 时获取 B 记为 A→B，出现反向顺序 B→A 时告警（潜在死锁）
+//! - 事件环：最近的等待、获取与释放事件
+//!
+//! 锁以所在共享内存段（`/proc/self/maps` 中的设备号与 inode）加段内偏移标识，不同进程把同一个段
+//! 映射到不同地址也能对应到同一把锁。[`report`] 汇总当前状态：等待环（死锁）、顺序反转与各锁的
+//! 持有时长；[`spawn_watchdog`] 定期检查并在发现问题时输出报告。诊断区不会被删除，进程卡死后
+//! 仍可由其他进程读取。未开启 feature 时所有钩子都是空操作。
+
+/// 开始等待锁
+#[inline]
+pub(crate) fn waiting<T>(lock: *const T) {
+    #[cfg(feature = "lock_debug")]
+    imp::record(lock as usize, imp::Hook::Wait);
+    #[cfg(not(feature = "lock_debug"))]
+    let _ = lock;
+}
+
+/// 已获取锁
+#[inline]
+pub(crate) fn acquired<T>(lock: *const T) {
+    #[cfg(feature = "lock_debug")]
+    imp::record(lock as usize, imp::Hook::Acquire);
+    #[cfg(not(feature = "lock_debug"))]
+    let _ = lock;
+}
+
+/// 放弃等待（超时或失败）
+#[inline]
+pub(crate) fn abandoned<T>(lock: *const T) {
+    #[cfg(feature = "lock_debug")]
+    imp::record(lock as usize, imp::Hook::Abandon);
+    #[cfg(not(feature = "lock_debug"))]
+    let _ = lock;
+}
+
+/// 已释放锁
+#[inline]
+pub(crate) fn released<T>(lock: *const T) {
+    #[cfg(feature = "lock_debug")]
+    imp::record(lock as usize, imp::Hook::Release);
+    #[cfg(not(feature = "lock_debug"))]
+    let _ = lock;
+}
+
+#[cfg(feature = "lock_debug")]
+pub use imp::{HeldLock, LockEvent, LockReport, report, spawn_watchdog};
+
+#[cfg(feature = "lock_debug")]
+mod imp {
+    use crate::locks::Segment;
+    use crate::shm_sync;
+
+    use std::cell::{Cell, RefCell, UnsafeCell};
+    use std::collections::{HashMap, HashSet};
+    use std::fmt;
+    use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+    use std::sync::{Mutex, OnceLock};
+    use std::time::Duration;
+
+    /// 默认的诊断区名称
+    const DEFAULT_NAME: &str = "mi7_lock_debug";
+    const MAX_HELD: usize = 512;
+    const MAX_EDGES: usize = 1024;
+    const MAX_NAMES: usize = 256;
+    const EVENT_RING: usize = 1024;
+    const NAME_LEN: usize = 56;
+    /// 报告中列出的最近事件数量
+    const RECENT_EVENTS: usize = 32;
+
+    const STATE_WAITING: u32 = 1;
+    const STATE_HOLDING: u32 = 2;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub(crate) enum Hook {
+        Wait = 1,
+        Acquire,
+        Abandon,
+        Release,
+    }
+
+    #[repr(C)]
+    struct HeldEntry {
+        pid: AtomicU32, // 占用该项的进程，0 表示空闲
+        tid: AtomicU32,
+        state: AtomicU32, // STATE_WAITING / STATE_HOLDING，0 表示尚未发布
+        _reserved: u32,
+        lock: AtomicU64,
+        since: AtomicU64, // 开始等待或持有的时间（单调时钟纳秒）
+    }
+
+    /// 加锁顺序：持有 `from` 时获取了 `to`
+    #[repr(C)]
+    struct Edge {
+        from: AtomicU64,
+        to: AtomicU64, // 0 表示尚未写完
+    }
+
+    #[repr(C)]
+    struct NameEntry {
+        lock: AtomicU64,
+        ready: AtomicU32,
+        _reserved: u32,
+        name: UnsafeCell<[u8; NAME_LEN]>,
+    }
+
+    #[repr(C)]
+    struct Event {
+        seq: AtomicU64, // 事件序号 + 1，写完后发布
+        lock: AtomicU64,
+        at: AtomicU64,
+        pid: AtomicU32,
+        tid: AtomicU32,
+        hook: AtomicU32,
+        _reserved: u32,
+    }
+
+    #[repr(C)]
+    struct DebugBlock {
+        held: [HeldEntry; MAX_HELD],
+        edges: [Edge; MAX_EDGES],
+        names: [NameEntry; MAX_NAMES],
+        next_event: AtomicU64,
+        events: [Event; EVENT_RING],
+    }
+
+    unsafe impl Sync for DebugBlock {}
+
+    fn region() -> Option<&'static DebugBlock> {
+        static REGION: OnceLock<Option<Segment<DebugBlock>>> = OnceLock::new();
+        REGION
+            .get_or_init(|| {
+                let name =
+                    std::env::var("MI7_LOCK_DEBUG_NAME").unwrap_or_else(|_| DEFAULT_NAME.into());
+                match Segment::shared(&name) {
+                    Ok(segment) => Some(segment),
+                    Err(e) => {
+                        eprintln!("警告: 连接锁诊断区 {} 失败: {}", name, e);
+                        None
+                    }
+                }
+            })
+            .as_ref()
+            .map(|segment| segment.get())
+    }
+
+    thread_local! {
+        /// 本线程持有的锁与其在持有表中的位置，按获取顺序排列
+        static HELD: RefCell<Vec<(u64, usize)>> = const { RefCell::new(Vec::new()) };
+        /// 本线程正在等待的锁在持有表中的位置
+        static WAITING: Cell<Option<usize>> = const { Cell::new(None) };
+        /// 防止诊断过程中的加锁（如日志输出）再次进入钩子
+        static IN_HOOK: Cell<bool> = const { Cell::new(false) };
+    }
+
+    pub(crate) fn record(addr: usize, hook: Hook) {
+        if IN_HOOK.with(|flag| flag.replace(true)) {
+            return;
+        }
+        if let Some(block) = region() {
+            let lock = identify(block, addr);
+            match hook {
+                Hook::Wait => wait(block, lock),
+                Hook::Acquire => acquire(block, lock),
+                Hook::Abandon => abandon(block),
+                Hook::Release => release(block, lock),
+            }
+            push_event(block, lock, hook);
+        }
+        IN_HOOK.with(|flag| flag.set(false));
+    }
+
+    fn wait(block: &DebugBlock, lock: u64) {
+        abandon(block);
+        WAITING.with(|waiting| waiting.set(claim(block, lock, STATE_WAITING)));
+    }
+
+    fn acquire(block: &DebugBlock, lock: u64) {
+        let entry = match WAITING.with(|waiting| waiting.take()) {
+            Some(index) if block.held[index].lock.load(Ordering::Acquire) == lock => {
+                let entry = &block.held[index];
+                entry
+                    .since
+                    .store(shm_sync::monotonic_nanos(), Ordering::Relaxed);
+                entry.state.store(STATE_HOLDING, Ordering::Release);
+                Some(index)
+            }
+            other => {
+                if let Some(index) = other {
+                    free(block, index);
+                }
+                claim(block, lock, STATE_HOLDING)
+            }
+        };
+
+        HELD.with(|held| {
+            let mut held = held.borrow_mut();
+            for &(earlier, _) in held.iter() {
+                if earlier != lock {
+                    add_edge(block, earlier, lock);
+                }
+            }
+            held.push((lock, entry.unwrap_or(usize::MAX)));
+        });
+    }
+
+    fn abandon(block: &DebugBlock) {
+        if let Some(index) = WAITING.with(|waiting| waiting.take()) {
+            free(block, index);
+        }
+    }
+
+    fn release(block: &DebugBlock, lock: u64) {
+        let entry = HELD.with(|held| {
+            let mut held = held.borrow_mut();
+            let position = held.iter().rposition(|(held, _)| *held == lock)?;
+            Some(held.remove(position).1)
+        });
+        if let Some(index) = entry.filter(|index| *index < MAX_HELD) {
+            free(block, index);
+        }
+    }
+
+    /// 在持有表中占用一项
+    fn claim(block: &DebugBlock, lock: u64, state: u32) -> Option<usize> {
+        let pid = std::process::id();
+        let index = block.held.iter().position(|entry| {
+            entry
+                .pid
+                .compare_exchange(0, pid, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+        })?;
+        let entry = &block.held[index];
+        entry.tid.store(current_tid(), Ordering::Relaxed);
+        entry.lock.store(lock, Ordering::Relaxed);
+        entry
+            .since
+            .store(shm_sync::monotonic_nanos(), Ordering::Relaxed);
+        entry.state.store(state, Ordering::Release);
+        Some(index)
+    }
+
+    fn free(block: &DebugBlock, index: usize) {
+        let entry = &block.held[index];
+        entry.state.store(0, Ordering::Release);
+        entry.pid.store(0, Ordering::Release);
+    }
+
+    /// 记录加锁顺序，发现反向顺序时告警（每个进程每对锁只告警一次）
+    fn add_edge(block: &DebugBlock, from: u64, to: u64) {
+        static REPORTED: OnceLock<Mutex<HashSet<(u64, u64)>>> = OnceLock::new();
+
+        let mut known = false;
+        let mut inverted = false;
+        for edge in &block.edges {
+            let (a, b) = (
+                edge.from.load(Ordering::Acquire),
+                edge.to.load(Ordering::Acquire),
+            );
+            known |= a == from && b == to;
+            inverted |= a == to && b == from;
+        }
+        if inverted
+            && REPORTED
+                .get_or_init(Default::default)
+                .lock()
+                .unwrap()
+                .insert((from.min(to), from.max(to)))
+        {
+            tracing::warn!(
+                "[LOCK_DEBUG] 加锁顺序反转（潜在死锁）: {} -> {}，此前出现过 {} -> {}",
+                label(block, from),
+                label(block, to),
+                label(block, to),
+                label(block, from)
+            );
+        }
+        if known {
+            return;
+        }
+        if let Some(edge) = block.edges.iter().find(|edge| {
+            edge.from
+                .compare_exchange(0, from, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+        }) {
+            edge.to.store(to, Ordering::Release);
+        }
+    }
+
+    fn push_event(block: &DebugBlock, lock: u64, hook: Hook) {
+        let seq = block.next_event.fetch_add(1, Ordering::AcqRel);
+        let event = &block.events[seq as usize % EVENT_RING];
+        event.seq.store(0, Ordering::Release);
+        event.lock.store(lock, Ordering::Relaxed);
+        event
+            .at
+            .store(shm_sync::monotonic_nanos(), Ordering::Relaxed);
+        event.pid.store(std::process::id(), Ordering::Relaxed);
+        event.tid.store(current_tid(), Ordering::Relaxed);
+        event.hook.store(hook as u32, Ordering::Relaxed);
+        event.seq.store(seq + 1, Ordering::Release);
+    }
+
+    #[cfg(target_os = "linux")]
+    fn current_tid() -> u32 {
+        unsafe { libc::gettid() as u32 }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn current_tid() -> u32 {
+        0
+    }
+
+    /// 共享内存段的一个文件映射
+    struct Mapping {
+        start: usize,
+        end: usize,
+        offset: u64,
+        dev: String,
+        inode: u64,
+        path: String,
+    }
+
+    /// 锁的跨进程标识，同时在诊断区登记可读名称
+    fn identify(block: &DebugBlock, addr: usize) -> u64 {
+        static CACHE: OnceLock<Mutex<HashMap<usize, u64>>> = OnceLock::new();
+        let cache = CACHE.get_or_init(Default::default);
+        if let Some(lock) = cache.lock().unwrap().get(&addr) {
+            return *lock;
+        }
+
+        let (key, name) = match mappings()
+            .into_iter()
+            .find(|m| m.start <= addr && addr < m.end)
+        {
+            Some(m) => {
+                let offset = m.offset + (addr - m.start) as u64;
+                let file = m.path.rsplit('/').next().unwrap_or_default().to_string();
+                (
+                    format!("{}:{}:{:#x}", m.dev, m.inode, offset),
+                    format!("{}+{:#x}", file, offset),
+                )
+            }
+            // 不在文件映射中（进程内的锁）：只在本进程内唯一
+            None => (
+                format!("{}:{:#x}", std::process::id(), addr),
+                format!("pid{}@{:#x}", std::process::id(), addr),
+            ),
+        };
+        let lock = crate::affinity::key_hash(key.as_bytes());
+        register_name(block, lock, &name);
+        cache.lock().unwrap().insert(addr, lock);
+        lock
+    }
+
+    #[cfg(target_os = "linux")]
+    fn mappings() -> Vec<Mapping> {
+        let maps = std::fs::read_to_string("/proc/self/maps").unwrap_or_default();
+        maps.lines()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                let (start, end) = fields.next()?.split_once('-')?;
+                let _perms = fields.next()?;
+                let offset = u64::from_str_radix(fields.next()?, 16).ok()?;
+                let dev = fields.next()?.to_string();
+                let inode = fields.next()?.parse().ok()?;
+                let path = fields.collect::<Vec<_>>().join(" ");
+                (inode != 0).then_some(Mapping {
+                    start: usize::from_str_radix(start, 16).ok()?,
+                    end: usize::from_str_radix(end, 16).ok()?,
+                    offset,
+                    dev,
+                    inode,
+                    path,
+                })
+            })
+            .collect()
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn mappings() -> Vec<Mapping> {
+        Vec::new()
+    }
+
+    fn register_name(block: &DebugBlock, lock: u64, name: &str) {
+        for entry in &block.names {
+            match entry
+                .lock
+                .compare_exchange(0, lock, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => {
+                    let mut bytes = [0u8; NAME_LEN];
+                    let len = name.len().min(NAME_LEN);
+                    bytes[..len].copy_from_slice(&name.as_bytes()[..len]);
+                    unsafe { *entry.name.get() = bytes };
+                    entry.ready.store(1, Ordering::Release);
+                    return;
+                }
+                Err(existing) if existing == lock => return,
+                Err(_) => {}
+            }
+        }
+    }
+
+    fn label(block: &DebugBlock, lock: u64) -> String {
+        block
+            .names
+            .iter()
+            .find(|entry| {
+                entry.lock.load(Ordering::Acquire) == lock
+                    && entry.ready.load(Ordering::Acquire) == 1
+            })
+            .map(|entry| {
+                let bytes = unsafe { *entry.name.get() };
+                let len = bytes.iter().position(|b| *b == 0).unwrap_or(NAME_LEN);
+                String::from_utf8_lossy(&bytes[..len]).into_owned()
+            })
+            .unwrap_or_else(|| format!("{:016x}", lock))
+    }
+
+    /// 持有或正在等待的锁
+    #[derive(Debug, Clone)]
+    pub struct HeldLock {
+        pub lock: String,
+        pub pid: u32,
+        pub tid: u32,
+        /// 正在等待（尚未获取）
+        pub waiting: bool,
+        /// 已持有或已等待的时长
+        pub duration: Duration,
+        /// 所属进程是否仍然存活
+        pub alive: bool,
+        id: u64,
+    }
+
+    /// 最近的一个加锁事件
+    #[derive(Debug, Clone)]
+    pub struct LockEvent {
+        pub lock: String,
+        pub pid: u32,
+        pub tid: u32,
+        pub kind: &'static str,
+        /// 距今的时长
+        pub age: Duration,
+    }
+
+    /// 锁诊断报告
+    #[derive(Debug, Clone, Default)]
+    pub struct LockReport {
+        /// 当前持有或等待的锁，按时长从长到短排列
+        pub held: Vec<HeldLock>,
+        /// 出现过相反顺序的锁对
+        pub inversions: Vec<(String, String)>,
+        /// 等待环：环中每个线程等待的锁都被下一个线程持有
+        pub deadlocks: Vec<Vec<HeldLock>>,
+        /// 最近的事件，从新到旧
+        pub recent: Vec<LockEvent>,
+    }
+
+    impl LockReport {
+        /// 持有时长超过 `threshold` 的锁
+        pub fn long_held(&self, threshold: Duration) -> impl Iterator<Item = &HeldLock> {
+            self.held
+                .iter()
+                .filter(move |held| !held.waiting && held.duration >= threshold)
+        }
+    }
+
+    impl fmt::Display for LockReport {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            writeln!(f, "锁诊断报告")?;
+            for cycle in &self.deadlocks {
+                writeln!(f, "  死锁:")?;
+                for held in cycle {
+                    writeln!(
+                        f,
+                        "    pid {} tid {} 等待 {} ({:?})",
+                        held.pid, held.tid, held.lock, held.duration
+                    )?;
+                }
+            }
+            for (first, second) in &self.inversions {
+                writeln!(f, "  顺序反转: {} <-> {}", first, second)?;
+            }
+            for held in &self.held {
+                writeln!(
+                    f,
+                    "  {} {} pid {}{} tid {} {:?}",
+                    if held.waiting { "等待" } else { "持有" },
+                    held.lock,
+                    held.pid,
+                    if held.alive { "" } else { "（已退出）" },
+                    held.tid,
+                    held.duration
+                )?;
+            }
+            for event in &self.recent {
+                writeln!(
+                    f,
+                    "  [{:?} 前] pid {} tid {} {} {}",
+                    event.age, event.pid, event.tid, event.kind, event.lock
+                )?;
+            }
+            Ok(())
+        }
+    }
+
+    /// 汇总诊断区的当前状态
+    pub fn report() -> LockReport {
+        let Some(block) = region() else {
+            return LockReport::default();
+        };
+        let now = shm_sync::monotonic_nanos();
+        let since = |nanos: u64| Duration::from_nanos(now.saturating_sub(nanos));
+
+        let mut held: Vec<HeldLock> = block
+            .held
+            .iter()
+            .filter_map(|entry| {
+                let state = entry.state.load(Ordering::Acquire);
+                let pid = entry.pid.load(Ordering::Acquire);
+                if state == 0 || pid == 0 {
+                    return None;
+                }
+                let id = entry.lock.load(Ordering::Relaxed);
+                Some(HeldLock {
+                    lock: label(block, id),
+                    pid,
+                    tid: entry.tid.load(Ordering::Relaxed),
+                    waiting: state == STATE_WAITING,
+                    duration: since(entry.since.load(Ordering::Relaxed)),
+                    alive: shm_sync::process_alive(pid),
+                    id,
+                })
+            })
+            .collect();
+        held.sort_by_key(|held| std::cmp::Reverse(held.duration));
+
+        let edges: HashSet<(u64, u64)> = block
+            .edges
+            .iter()
+            .map(|edge| {
+                (
+                    edge.from.load(Ordering::Acquire),
+                    edge.to.load(Ordering::Acquire),
+                )
+            })
+            .filter(|(from, to)| *from != 0 && *to != 0)
+            .collect();
+        let inversions = edges
+            .iter()
+            .filter(|(from, to)| from < to && edges.contains(&(*to, *from)))
+            .map(|(from, to)| (label(block, *from), label(block, *to)))
+            .collect();
+
+        let mut events: Vec<(u64, LockEvent)> = block
+            .events
+            .iter()
+            .filter_map(|event| {
+                let seq = event.seq.load(Ordering::Acquire);
+                (seq != 0).then(|| {
+                    let kind = match event.hook.load(Ordering::Relaxed) {
+                        1 => "等待",
+                        2 => "获取",
+                        3 => "放弃",
+                        _ => "释放",
+                    };
+                    let lock = event.lock.load(Ordering::Relaxed);
+                    (
+                        seq,
+                        LockEvent {
+                            lock: label(block, lock),
+                            pid: event.pid.load(Ordering::Relaxed),
+                            tid: event.tid.load(Ordering::Relaxed),
+                            kind,
+                            age: since(event.at.load(Ordering::Relaxed)),
+                        },
+                    )
+                })
+            })
+            .collect();
+        events.sort_by_key(|(seq, _)| std::cmp::Reverse(*seq));
+
+        LockReport {
+            deadlocks: wait_cycles(&held),
+            inversions,
+            recent: events
+                .into_iter()
+                .take(RECENT_EVENTS)
+                .map(|(_, event)| event)
+                .collect(),
+            held,
+        }
+    }
+
+    /// 在等待图中查找环：线程等待的锁被另一个线程持有
+    fn wait_cycles(held: &[HeldLock]) -> Vec<Vec<HeldLock>> {
+        let thread = |held: &HeldLock| (held.pid, held.tid);
+        let waiting: HashMap<(u32, u32), &HeldLock> = held
+            .iter()
+            .filter(|held| held.waiting)
+            .map(|held| (thread(held), held))
+            .collect();
+        let holders: HashMap<u64, (u32, u32)> = held
+            .iter()
+            .filter(|held| !held.waiting)
+            .map(|held| (held.id, thread(held)))
+            .collect();
+
+        let mut cycles = Vec::new();
+        let mut seen = HashSet::new();
+        for &start in waiting.keys() {
+            let mut path = vec![start];
+            let mut current = start;
+            while let Some(next) = waiting
+                .get(&current)
+                .and_then(|wait| holders.get(&wait.id))
+                .copied()
+            {
+                if next == start {
+                    // 同一个环只从编号最小的线程报告一次
+                    let key = *path.iter().min().unwrap();
+                    if seen.insert(key) {
+                        cycles.push(path.iter().map(|t| waiting[t].clone()).collect());
+                    }
+                    break;
+                }
+                if path.contains(&next) || !waiting.contains_key(&next) {
+                    break;
+                }
+                path.push(next);
+                current = next;
+            }
+        }
+        cycles
+    }
+
+    /// 启动后台检查线程：发现死锁、顺序反转或持有超过 `threshold` 的锁时输出报告
+    pub fn spawn_watchdog(threshold: Duration, interval: Duration) -> std::thread::JoinHandle<()> {
+        std::thread::spawn(move || {
+            let mut reported = 0;
+            loop {
+                std::thread::sleep(interval);
+                let report = report();
+                let problems = report.deadlocks.len()
+                    + report.inversions.len()
+                    + report.long_held(threshold).count();
+                if problems > 0 && problems != reported {
+                    tracing::warn!("[LOCK_DEBUG] {}", report);
+                }
+                reported = problems;
+            }
+        })
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::shm_sync::ShmMutex;
+
+        #[test]
+        fn test_inversion_and_deadlock_reported() {
+            let mut first: ShmMutex = unsafe { std::mem::zeroed() };
+            let mut second: ShmMutex = unsafe { std::mem::zeroed() };
+            unsafe {
+                first.init().unwrap();
+                second.init().unwrap();
+
+                // first -> second，然后 second -> first
+                first.lock();
+                second.lock();
+                second.unlock();
+                first.unlock();
+                second.lock();
+                first.lock();
+                first.unlock();
+                second.unlock();
+            }
+            let report = report();
+            let name =
+                |m: &ShmMutex| format!("pid{}@{:#x}", std::process::id(), m as *const _ as usize);
+            let pair = (name(&first), name(&second));
+            assert!(
+                report
+                    .inversions
+                    .iter()
+                    .any(|(a, b)| (a, b) == (&pair.0, &pair.1) || (a, b) == (&pair.1, &pair.0))
+            );
+
+            // 两个线程互相等待对方持有的锁
+            let lock = |id: u64, waiting: bool, tid: u32| HeldLock {
+                lock: id.to_string(),
+                pid: 1,
+                tid,
+                waiting,
+                duration: Duration::ZERO,
+                alive: true,
+                id,
+            };
+            let held = [
+                lock(10, false, 1),
+                lock(20, true, 1),
+                lock(20, false, 2),
+                lock(10, true, 2),
+                lock(30, true, 3),
+            ];
+            let cycles = wait_cycles(&held);
+            assert_eq!(cycles.len(), 1);
+            assert_eq!(cycles[0].len(), 2);
+        }
+    }
+}
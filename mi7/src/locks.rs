@@ -0,0 +1,1211 @@
+//! 跨进程同步原语
+//!
+//! 每个原语占用一个命名共享内存段：创建者初始化后写入魔数发布，其他进程按名称连接，
+//! 连接时段尚未发布则返回错误。等待基于 [`futex`](crate::futex)，Linux 上在内核中
+//! 休眠，其他平台退化为短睡眠轮询。
+//!
+//! - [`IpcSemaphore`]：计数信号量，限制同时进入临界区的进程数量
+//! - [`IpcBarrier`]：屏障，N 个协作进程全部到达后同时放行，可重复使用
+//! - [`IpcRwLock`]：读写锁，多个读者或一个写者，持有者崩溃后可恢复
+//! - [`SeqLock`] / [`IpcSeqLock`]：顺序锁，读多写少的状态块，读者从不阻塞写者
+//!
+//! 各原语在共享内存中累计 [`LockStats`]（获取次数、竞争次数、超时次数与等待耗时），
+//! 任一连接方读到的都是所有进程的合计。
+
+use crate::futex;
+use crate::lock_debug;
+use crate::shm_registry::SharedMemoryRegistry;
+use crate::shm_sync::{self, ShmMutex};
+
+use crate::error::Result;
+use anyhow::anyhow;
+use libc::{MAP_FAILED, MAP_SHARED, O_CREAT, O_EXCL, O_RDONLY, O_RDWR, PROT_READ, PROT_WRITE};
+use std::cell::UnsafeCell;
+use std::ffi::CString;
+use std::fs::File;
+use std::marker::PhantomData;
+use std::mem::{MaybeUninit, size_of};
+use std::os::unix::io::AsRawFd;
+use std::ptr::{self, NonNull};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering, fence};
+use std::time::{Duration, Instant};
+
+/// 共享内存段已初始化的标记
+const SEGMENT_MAGIC: u32 = 0x4d49_374c; // "MI7L"
+
+/// 等待锁（或信号量、屏障）超时
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("等待锁超时（{timeout:?}）")]
+pub struct LockTimeout {
+    /// 调用者指定的等待时间
+    pub timeout: Duration,
+}
+
+/// 顺序锁的写者进程在写入中途退出，数据可能只写了一半
+///
+/// 之后任一写者完成一次写入即恢复。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("顺序锁的写者进程 {pid} 在写入中途退出")]
+pub struct SeqLockPoisoned {
+    /// 退出的写者 PID
+    pub pid: u32,
+}
+
+/// 锁的使用统计
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LockStats {
+    /// 成功获取（或通过屏障）的次数
+    pub acquisitions: u64,
+    /// 需要等待才获取成功的次数
+    pub contended: u64,
+    /// 等待超时的次数
+    pub timeouts: u64,
+    /// 累计等待耗时
+    pub total_wait: Duration,
+    /// 单次最长等待耗时
+    pub max_wait: Duration,
+}
+
+impl LockStats {
+    /// 每次竞争的平均等待耗时
+    pub fn mean_wait(&self) -> Duration {
+        self.total_wait
+            .checked_div(self.contended.max(1) as u32)
+            .unwrap_or_default()
+    }
+}
+
+/// 共享内存中的统计计数
+#[repr(C)]
+struct StatsBlock {
+    acquisitions: AtomicU64,
+    contended: AtomicU64,
+    timeouts: AtomicU64,
+    wait_nanos: AtomicU64,
+    max_wait_nanos: AtomicU64,
+}
+
+impl StatsBlock {
+    fn acquired(&self) {
+        self.acquisitions.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn acquired_after(&self, waited: Duration) {
+        let nanos = waited.as_nanos() as u64;
+        self.acquisitions.fetch_add(1, Ordering::Relaxed);
+        self.contended.fetch_add(1, Ordering::Relaxed);
+        self.wait_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.max_wait_nanos.fetch_max(nanos, Ordering::Relaxed);
+    }
+
+    fn timed_out(&self) {
+        self.timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> LockStats {
+        LockStats {
+            acquisitions: self.acquisitions.load(Ordering::Relaxed),
+            contended: self.contended.load(Ordering::Relaxed),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+            total_wait: Duration::from_nanos(self.wait_nanos.load(Ordering::Relaxed)),
+            max_wait: Duration::from_nanos(self.max_wait_nanos.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// 命名共享内存段的映射，Drop 时解除映射
+///
+/// 创建者以 `O_CREAT` 打开段并设置长度；连接者只打开已存在的段，并在映射前校验长度：
+/// 创建者尚未设置长度时访问映射会触发 SIGBUS。长度为 0 的映射不调用 `mmap`。
+pub(crate) struct Mapping {
+    addr: NonNull<u8>,
+    len: usize,
+}
+
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    /// 创建（或打开已存在的）段并把长度设为 `len`，新增的部分被零填充
+    pub(crate) fn create(name: &str, len: usize) -> Result<Self> {
+        let fd = Self::shm_open(name, O_CREAT | O_RDWR)?;
+        Self::resize_and_map(fd, len)
+    }
+
+    /// 创建新段并把长度设为 `len`，同名段已存在时返回错误；之后的步骤失败时删除该段
+    pub(crate) fn create_new(name: &str, len: usize) -> Result<Self> {
+        let fd = Self::shm_open(name, O_CREAT | O_EXCL | O_RDWR)?;
+        Self::resize_and_map(fd, len).inspect_err(|_| {
+            let _ = SharedMemoryRegistry::unlink(name);
+        })
+    }
+
+    /// 打开已存在的段并映射前 `len` 字节，段不存在或长度不足时返回错误
+    pub(crate) fn open(name: &str, len: usize) -> Result<Self> {
+        let fd = Self::shm_open(name, O_RDWR)?;
+        match Self::segment_len(fd) {
+            Some(actual) if actual >= len => Self::map(fd, len, PROT_READ | PROT_WRITE),
+            _ => {
+                unsafe { libc::close(fd) };
+                Err(anyhow!("共享内存段 {} 的长度不正确", name).into())
+            }
+        }
+    }
+
+    /// 以只读方式打开已存在的段并映射全部内容
+    pub(crate) fn open_read_only(name: &str) -> Result<Self> {
+        let fd = Self::shm_open(name, O_RDONLY)?;
+        let Some(len) = Self::segment_len(fd) else {
+            let e = anyhow!("fstat failed with errno: {}", shm_sync::errno());
+            unsafe { libc::close(fd) };
+            return Err(e.into());
+        };
+        Self::map(fd, len, PROT_READ)
+    }
+
+    /// 映射已打开文件的前 `len` 字节，文件仍由调用者持有
+    pub(crate) fn map_file(file: &File, len: usize) -> Result<Self> {
+        Self::mmap(file.as_raw_fd(), len, PROT_READ | PROT_WRITE)
+    }
+
+    fn shm_open(name: &str, flags: libc::c_int) -> Result<libc::c_int> {
+        let cname = CString::new(format!("/{}", name.trim_start_matches('/')))
+            .map_err(|_| anyhow!("Failed to create CString from name"))?;
+        let fd = unsafe { libc::shm_open(cname.as_ptr(), flags, crate::access::mode()) };
+        if fd == -1 {
+            return Err(
+                anyhow!("shm_open {} failed with errno: {}", name, shm_sync::errno()).into(),
+            );
+        }
+        Ok(fd)
+    }
+
+    fn segment_len(fd: libc::c_int) -> Option<usize> {
+        let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+        (unsafe { libc::fstat(fd, &mut stat) } != -1).then_some(stat.st_size as usize)
+    }
+
+    /// 按访问控制策略设置权限，把长度设为 `len` 后映射
+    fn resize_and_map(fd: libc::c_int, len: usize) -> Result<Self> {
+        if let Err(e) = crate::access::restrict(fd) {
+            unsafe { libc::close(fd) };
+            return Err(e);
+        }
+        if unsafe { libc::ftruncate(fd, len as libc::off_t) } == -1 {
+            let e = anyhow!("ftruncate failed with errno: {}", shm_sync::errno());
+            unsafe { libc::close(fd) };
+            return Err(e.into());
+        }
+        Self::map(fd, len, PROT_READ | PROT_WRITE)
+    }
+
+    /// 映射 `len` 字节并关闭 fd
+    fn map(fd: libc::c_int, len: usize, prot: libc::c_int) -> Result<Self> {
+        let mapping = Self::mmap(fd, len, prot);
+        unsafe { libc::close(fd) };
+        mapping
+    }
+
+    fn mmap(fd: libc::c_int, len: usize, prot: libc::c_int) -> Result<Self> {
+        if len == 0 {
+            return Ok(Self {
+                addr: NonNull::dangling(),
+                len,
+            });
+        }
+        let addr = unsafe { libc::mmap(ptr::null_mut(), len, prot, MAP_SHARED, fd, 0) };
+        if addr == MAP_FAILED {
+            return Err(anyhow!("mmap failed with errno: {}", shm_sync::errno()).into());
+        }
+        Ok(Self {
+            addr: unsafe { NonNull::new_unchecked(addr as *mut u8) },
+            len,
+        })
+    }
+
+    pub(crate) fn as_ptr(&self) -> *mut u8 {
+        self.addr.as_ptr()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            unsafe { libc::munmap(self.addr.as_ptr() as *mut libc::c_void, self.len) };
+        }
+    }
+}
+
+#[repr(C)]
+struct SegmentBlock<T> {
+    magic: AtomicU32,
+    _reserved: u32,
+    inner: T,
+}
+
+/// 存放一个共享状态块的命名共享内存段，创建者 Drop 时删除
+///
+/// 同步原语以及守护进程创建、其他进程连接的控制块（停止协调、worker 登记表等）都放在这样的段中。
+pub(crate) struct Segment<T> {
+    mapping: Mapping,
+    name: String,
+    owner: bool,
+    _block: PhantomData<T>,
+}
+
+unsafe impl<T: Sync> Send for Segment<T> {}
+unsafe impl<T: Sync> Sync for Segment<T> {}
+
+impl<T> Segment<T> {
+    /// 创建（或清空）段，`init` 在发布前初始化全零的内容
+    pub(crate) fn create(name: &str, init: impl FnOnce(&T)) -> Result<Self> {
+        let mapping = Mapping::create(name, size_of::<SegmentBlock<T>>())?;
+        unsafe { ptr::write_bytes(mapping.as_ptr(), 0, mapping.len()) };
+        let segment = Self {
+            mapping,
+            name: name.to_string(),
+            owner: true,
+            _block: PhantomData,
+        };
+        SharedMemoryRegistry::register(name);
+        init(segment.get());
+        segment
+            .block()
+            .magic
+            .store(SEGMENT_MAGIC, Ordering::Release);
+        Ok(segment)
+    }
+
+    /// 连接段，不存在时以全零内容创建；只适用于全零即为有效初始状态的内容，不删除该段
+    #[cfg(feature = "lock_debug")]
+    pub(crate) fn shared(name: &str) -> Result<Self> {
+        let segment = Self {
+            mapping: Mapping::create(name, size_of::<SegmentBlock<T>>())?,
+            name: name.to_string(),
+            owner: false,
+            _block: PhantomData,
+        };
+        segment
+            .block()
+            .magic
+            .store(SEGMENT_MAGIC, Ordering::Release);
+        Ok(segment)
+    }
+
+    /// 连接已发布的段，段不存在或尚未发布时返回错误
+    pub(crate) fn open(name: &str) -> Result<Self> {
+        let segment = Self {
+            mapping: Mapping::open(name, size_of::<SegmentBlock<T>>())?,
+            name: name.to_string(),
+            owner: false,
+            _block: PhantomData,
+        };
+        if segment.block().magic.load(Ordering::Acquire) != SEGMENT_MAGIC {
+            return Err(anyhow!("共享内存段 {} 尚未初始化", name).into());
+        }
+        Ok(segment)
+    }
+
+    fn block(&self) -> &SegmentBlock<T> {
+        unsafe { &*(self.mapping.as_ptr() as *const SegmentBlock<T>) }
+    }
+
+    pub(crate) fn get(&self) -> &T {
+        &self.block().inner
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 是否为创建者
+    pub(crate) fn is_owner(&self) -> bool {
+        self.owner
+    }
+}
+
+impl<T> Drop for Segment<T> {
+    fn drop(&mut self) {
+        if self.owner
+            && let Err(e) = SharedMemoryRegistry::unlink(&self.name)
+        {
+            tracing::warn!("删除共享内存段 {} 失败: {}", self.name, e);
+        }
+    }
+}
+
+/// 剩余等待时间，`None` 表示无限等待
+fn remaining(deadline: Option<Instant>) -> Option<Duration> {
+    deadline.map(|d| d.saturating_duration_since(Instant::now()))
+}
+
+#[repr(C)]
+struct SemaphoreBlock {
+    permits: AtomicU32, // 可用许可数量，兼作 futex 字
+    waiters: AtomicU32,
+    stats: StatsBlock,
+}
+
+impl SemaphoreBlock {
+    fn try_take(&self) -> bool {
+        self.permits
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok()
+    }
+}
+
+/// 跨进程计数信号量
+///
+/// 持有许可的进程崩溃时许可不会归还，需要由守护进程重建信号量。
+pub struct IpcSemaphore {
+    segment: Segment<SemaphoreBlock>,
+}
+
+impl IpcSemaphore {
+    /// 创建（或重置）信号量，初始有 `permits` 个许可
+    pub fn create(name: &str, permits: u32) -> Result<Self> {
+        Ok(Self {
+            segment: Segment::create(name, |block: &SemaphoreBlock| {
+                block.permits.store(permits, Ordering::Release)
+            })?,
+        })
+    }
+
+    /// 连接已创建的信号量
+    pub fn open(name: &str) -> Result<Self> {
+        Ok(Self {
+            segment: Segment::open(name)?,
+        })
+    }
+
+    /// 当前可用的许可数量
+    pub fn available(&self) -> u32 {
+        self.segment.get().permits.load(Ordering::Acquire)
+    }
+
+    /// 获取一个许可，没有可用许可时一直等待
+    pub fn acquire(&self) -> SemaphorePermit<'_> {
+        loop {
+            if let Some(permit) = self.acquire_until(None) {
+                return permit;
+            }
+        }
+    }
+
+    /// 获取一个许可，最多等待 `timeout`
+    pub fn acquire_timeout(&self, timeout: Duration) -> Result<SemaphorePermit<'_>, LockTimeout> {
+        self.acquire_until(Some(Instant::now() + timeout))
+            .ok_or(LockTimeout { timeout })
+    }
+
+    /// 尝试获取一个许可，不等待
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        let block = self.segment.get();
+        if block.try_take() {
+            block.stats.acquired();
+            return Some(SemaphorePermit { semaphore: self });
+        }
+        None
+    }
+
+    fn acquire_until(&self, deadline: Option<Instant>) -> Option<SemaphorePermit<'_>> {
+        if let Some(permit) = self.try_acquire() {
+            return Some(permit);
+        }
+
+        let block = self.segment.get();
+        let start = Instant::now();
+        loop {
+            // 先登记等待者再检查许可，与 release 的先加许可再读等待者配对，避免唤醒丢失
+            block.waiters.fetch_add(1, Ordering::SeqCst);
+            let wait = remaining(deadline);
+            if wait != Some(Duration::ZERO) {
+                futex::futex_wait(&block.permits, 0, wait);
+            }
+            block.waiters.fetch_sub(1, Ordering::SeqCst);
+
+            if block.try_take() {
+                block.stats.acquired_after(start.elapsed());
+                return Some(SemaphorePermit { semaphore: self });
+            }
+            if remaining(deadline) == Some(Duration::ZERO) {
+                block.stats.timed_out();
+                return None;
+            }
+        }
+    }
+
+    fn release(&self) {
+        let block = self.segment.get();
+        block.permits.fetch_add(1, Ordering::SeqCst);
+        if block.waiters.load(Ordering::SeqCst) > 0 {
+            futex::futex_wake(&block.permits, 1);
+        }
+    }
+
+    /// 所有进程合计的使用统计
+    pub fn stats(&self) -> LockStats {
+        self.segment.get().stats.snapshot()
+    }
+}
+
+/// 信号量许可，Drop 时归还
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a IpcSemaphore,
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        self.semaphore.release();
+    }
+}
+
+#[repr(C)]
+struct BarrierBlock {
+    parties: AtomicU32,
+    seq: AtomicU32,   // 放行时递增的 futex 字
+    state: AtomicU64, // 高 32 位为轮次，低 32 位为本轮已到达的数量
+    stats: StatsBlock,
+}
+
+/// 跨进程屏障，`parties` 个参与方全部到达后同时放行，随后进入下一轮
+pub struct IpcBarrier {
+    segment: Segment<BarrierBlock>,
+}
+
+/// [`IpcBarrier::wait`] 的结果
+#[derive(Debug, Clone, Copy)]
+pub struct BarrierWaitResult {
+    leader: bool,
+}
+
+impl BarrierWaitResult {
+    /// 是否为本轮最后到达、负责放行的参与方（每轮恰好一个）
+    pub fn is_leader(&self) -> bool {
+        self.leader
+    }
+}
+
+impl IpcBarrier {
+    /// 创建（或重置）屏障
+    pub fn create(name: &str, parties: u32) -> Result<Self> {
+        if parties == 0 {
+            return Err(anyhow!("屏障的参与方数量必须大于 0").into());
+        }
+        Ok(Self {
+            segment: Segment::create(name, |block: &BarrierBlock| {
+                block.parties.store(parties, Ordering::Release)
+            })?,
+        })
+    }
+
+    /// 连接已创建的屏障
+    pub fn open(name: &str) -> Result<Self> {
+        Ok(Self {
+            segment: Segment::open(name)?,
+        })
+    }
+
+    /// 参与方数量
+    pub fn parties(&self) -> u32 {
+        self.segment.get().parties.load(Ordering::Acquire)
+    }
+
+    /// 到达屏障并等待其余参与方
+    pub fn wait(&self) -> BarrierWaitResult {
+        self.wait_until(None).expect("无限等待的屏障不会超时")
+    }
+
+    /// 到达屏障，最多等待 `timeout`；超时时撤回本次到达
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<BarrierWaitResult, LockTimeout> {
+        self.wait_until(Some(timeout))
+            .ok_or(LockTimeout { timeout })
+    }
+
+    fn wait_until(&self, timeout: Option<Duration>) -> Option<BarrierWaitResult> {
+        let block = self.segment.get();
+        let parties = block.parties.load(Ordering::Acquire);
+        let start = Instant::now();
+
+        let mut round = 0;
+        let mut leader = false;
+        let _ = block
+            .state
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |state| {
+                let (r, arrived) = split(state);
+                round = r;
+                leader = arrived + 1 >= parties;
+                Some(if leader {
+                    join(r.wrapping_add(1), 0)
+                } else {
+                    join(r, arrived + 1)
+                })
+            });
+        if leader {
+            futex::bump_and_wake(&block.seq);
+            block.stats.acquired();
+            return Some(BarrierWaitResult { leader: true });
+        }
+
+        let released = || split(block.state.load(Ordering::Acquire)).0 != round;
+        if futex::wait_until(&block.seq, timeout, released) {
+            block.stats.acquired_after(start.elapsed());
+            return Some(BarrierWaitResult { leader: false });
+        }
+
+        // 超时：撤回本次到达；屏障恰在此时放行则视为通过
+        let withdrawn = block
+            .state
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |state| {
+                let (r, arrived) = split(state);
+                (r == round).then(|| join(r, arrived.saturating_sub(1)))
+            })
+            .is_ok();
+        if withdrawn {
+            block.stats.timed_out();
+            return None;
+        }
+        block.stats.acquired_after(start.elapsed());
+        Some(BarrierWaitResult { leader: false })
+    }
+
+    /// 所有进程合计的使用统计
+    pub fn stats(&self) -> LockStats {
+        self.segment.get().stats.snapshot()
+    }
+}
+
+/// 读写锁在一个时间片内拿不到锁（顺序锁读不到一致的快照）时检查持有者是否已退出
+const RECOVERY_SLICE: Duration = Duration::from_millis(50);
+
+/// 读写锁登记持有者 PID 的读者槽位数量，超出的读者不登记
+pub const MAX_TRACKED_READERS: usize = 64;
+
+#[repr(C)]
+struct RwLockBlock {
+    raw: UnsafeCell<libc::pthread_rwlock_t>,
+    recovery: UnsafeCell<ShmMutex>, // 串行化持有者退出后的重建
+    generation: AtomicU32,          // 每次重建递增，旧锁上的守卫不再解锁
+    writer: AtomicU32,              // 写者 PID，0 表示没有写者
+    readers: [AtomicU32; MAX_TRACKED_READERS],
+    stats: StatsBlock,
+}
+
+unsafe impl Sync for RwLockBlock {}
+
+impl RwLockBlock {
+    /// 初始化进程共享的 pthread 读写锁
+    ///
+    /// # Safety
+    /// 没有任何进程持有或等待该锁时才能调用。
+    unsafe fn init_raw(&self) -> Result<()> {
+        let mut attr: libc::pthread_rwlockattr_t = unsafe { std::mem::zeroed() };
+        unsafe {
+            libc::pthread_rwlockattr_init(&mut attr);
+            libc::pthread_rwlockattr_setpshared(&mut attr, libc::PTHREAD_PROCESS_SHARED);
+            let result = libc::pthread_rwlock_init(self.raw.get(), &attr);
+            libc::pthread_rwlockattr_destroy(&mut attr);
+            if result != 0 {
+                return Err(anyhow!("Failed to initialize rwlock: {}", result).into());
+            }
+        }
+        Ok(())
+    }
+
+    fn try_lock(&self, write: bool) -> bool {
+        let result = unsafe {
+            if write {
+                libc::pthread_rwlock_trywrlock(self.raw.get())
+            } else {
+                libc::pthread_rwlock_tryrdlock(self.raw.get())
+            }
+        };
+        result == 0
+    }
+
+    /// 最多等待 `slice` 加锁
+    #[cfg(target_os = "linux")]
+    fn lock_for(&self, write: bool, slice: Duration) -> bool {
+        let abstime = shm_sync::realtime_after(slice);
+        let result = unsafe {
+            if write {
+                pthread_rwlock_timedwrlock(self.raw.get(), &abstime)
+            } else {
+                pthread_rwlock_timedrdlock(self.raw.get(), &abstime)
+            }
+        };
+        result == 0
+    }
+
+    /// 没有带超时的 pthread 读写锁接口时轮询加锁
+    #[cfg(not(target_os = "linux"))]
+    fn lock_for(&self, write: bool, slice: Duration) -> bool {
+        let deadline = Instant::now() + slice;
+        let mut backoff = Duration::from_micros(10);
+        loop {
+            if self.try_lock(write) {
+                return true;
+            }
+            if Instant::now() >= deadline {
+                return false;
+            }
+            std::thread::sleep(backoff);
+            backoff = (backoff * 2).min(Duration::from_millis(1));
+        }
+    }
+}
+
+/// 跨进程读写锁：进程共享的 `pthread_rwlock_t`，多个读者或一个写者
+///
+/// pthread 读写锁没有 robust 属性，持有者进程崩溃后锁会一直被占用。这里在共享内存中
+/// 登记持有者 PID，等待者每个时间片拿不到锁时检查一次：登记的持有者全部已退出时
+/// 重新初始化该锁，各等待者在下一个时间片进入新锁。
+pub struct IpcRwLock {
+    segment: Segment<RwLockBlock>,
+}
+
+impl IpcRwLock {
+    /// 创建（或重置）读写锁
+    pub fn create(name: &str) -> Result<Self> {
+        let mut result = Ok(());
+        let segment = Segment::create(name, |block: &RwLockBlock| {
+            result =
+                unsafe { block.init_raw() }.and_then(|_| unsafe { (*block.recovery.get()).init() });
+        })?;
+        result?;
+        Ok(Self { segment })
+    }
+
+    /// 连接已创建的读写锁
+    pub fn open(name: &str) -> Result<Self> {
+        Ok(Self {
+            segment: Segment::open(name)?,
+        })
+    }
+
+    /// 获取读锁，一直等待
+    pub fn read(&self) -> IpcReadGuard<'_> {
+        let (generation, slot) = self.lock_until(false, None).expect("无限等待不会超时");
+        IpcReadGuard {
+            lock: self,
+            generation,
+            slot,
+        }
+    }
+
+    /// 获取读锁，最多等待 `timeout`
+    pub fn read_timeout(&self, timeout: Duration) -> Result<IpcReadGuard<'_>, LockTimeout> {
+        let (generation, slot) = self
+            .lock_until(false, Some(Instant::now() + timeout))
+            .ok_or(LockTimeout { timeout })?;
+        Ok(IpcReadGuard {
+            lock: self,
+            generation,
+            slot,
+        })
+    }
+
+    /// 获取写锁，一直等待
+    pub fn write(&self) -> IpcWriteGuard<'_> {
+        let (generation, _) = self.lock_until(true, None).expect("无限等待不会超时");
+        IpcWriteGuard {
+            lock: self,
+            generation,
+        }
+    }
+
+    /// 获取写锁，最多等待 `timeout`
+    pub fn write_timeout(&self, timeout: Duration) -> Result<IpcWriteGuard<'_>, LockTimeout> {
+        let (generation, _) = self
+            .lock_until(true, Some(Instant::now() + timeout))
+            .ok_or(LockTimeout { timeout })?;
+        Ok(IpcWriteGuard {
+            lock: self,
+            generation,
+        })
+    }
+
+    /// 加锁并登记持有者，返回加锁时的重建代数与读者槽位
+    fn lock_until(&self, write: bool, deadline: Option<Instant>) -> Option<(u32, Option<usize>)> {
+        let block = self.segment.get();
+        let start = Instant::now();
+        let mut contended = false;
+        loop {
+            if block.try_lock(write) {
+                break;
+            }
+            let slice = remaining(deadline).map_or(RECOVERY_SLICE, |r| r.min(RECOVERY_SLICE));
+            if !contended {
+                lock_debug::waiting(block.raw.get());
+            }
+            contended = true;
+            if !slice.is_zero() && block.lock_for(write, slice) {
+                break;
+            }
+            self.recover();
+            if remaining(deadline) == Some(Duration::ZERO) {
+                lock_debug::abandoned(block.raw.get());
+                block.stats.timed_out();
+                return None;
+            }
+        }
+        lock_debug::acquired(block.raw.get());
+
+        let pid = std::process::id();
+        let slot = if write {
+            block.writer.store(pid, Ordering::Release);
+            None
+        } else {
+            block.readers.iter().position(|slot| {
+                slot.compare_exchange(0, pid, Ordering::AcqRel, Ordering::Relaxed)
+                    .is_ok()
+            })
+        };
+        if contended {
+            block.stats.acquired_after(start.elapsed());
+        } else {
+            block.stats.acquired();
+        }
+        Some((block.generation.load(Ordering::Acquire), slot))
+    }
+
+    /// 登记的持有者全部已退出时重新初始化锁
+    fn recover(&self) {
+        let block = self.segment.get();
+        let recovery = unsafe { &mut *block.recovery.get() };
+        if !unsafe { recovery.try_lock() } {
+            return;
+        }
+
+        let holders: Vec<u32> = std::iter::once(&block.writer)
+            .chain(&block.readers)
+            .map(|slot| slot.load(Ordering::Acquire))
+            .filter(|pid| *pid != 0)
+            .collect();
+        let (dead, alive): (Vec<u32>, Vec<u32>) = holders
+            .into_iter()
+            .partition(|pid| !shm_sync::process_alive(*pid));
+        if !dead.is_empty() && alive.is_empty() {
+            tracing::warn!(
+                "读写锁 {} 的持有进程 {:?} 已退出，重新初始化",
+                self.segment.name(),
+                dead
+            );
+            block.generation.fetch_add(1, Ordering::AcqRel);
+            block.writer.store(0, Ordering::Release);
+            for slot in &block.readers {
+                slot.store(0, Ordering::Release);
+            }
+            if let Err(e) = unsafe { block.init_raw() } {
+                tracing::warn!("重新初始化读写锁 {} 失败: {}", self.segment.name(), e);
+            }
+        }
+        unsafe { recovery.unlock() };
+    }
+
+    /// 解锁，锁已在持有期间被重建时不再解锁
+    fn unlock(&self, generation: u32, slot: Option<usize>, write: bool) {
+        let block = self.segment.get();
+        lock_debug::released(block.raw.get());
+        if block.generation.load(Ordering::Acquire) != generation {
+            return;
+        }
+        // 先撤销登记再解锁，避免抹掉下一个持有者的登记
+        if write {
+            block.writer.store(0, Ordering::Release);
+        } else if let Some(slot) = slot {
+            block.readers[slot].store(0, Ordering::Release);
+        }
+        unsafe { libc::pthread_rwlock_unlock(block.raw.get()) };
+    }
+
+    /// 所有进程合计的使用统计
+    pub fn stats(&self) -> LockStats {
+        self.segment.get().stats.snapshot()
+    }
+}
+
+/// 读锁守卫，Drop 时解锁
+pub struct IpcReadGuard<'a> {
+    lock: &'a IpcRwLock,
+    generation: u32,
+    slot: Option<usize>,
+}
+
+impl Drop for IpcReadGuard<'_> {
+    fn drop(&mut self) {
+        self.lock.unlock(self.generation, self.slot, false);
+    }
+}
+
+/// 写锁守卫，Drop 时解锁
+pub struct IpcWriteGuard<'a> {
+    lock: &'a IpcRwLock,
+    generation: u32,
+}
+
+impl Drop for IpcWriteGuard<'_> {
+    fn drop(&mut self) {
+        self.lock.unlock(self.generation, None, true);
+    }
+}
+
+// libc crate 未导出带超时的读写锁接口
+#[cfg(target_os = "linux")]
+unsafe extern "C" {
+    fn pthread_rwlock_timedrdlock(
+        lock: *mut libc::pthread_rwlock_t,
+        abstime: *const libc::timespec,
+    ) -> libc::c_int;
+    fn pthread_rwlock_timedwrlock(
+        lock: *mut libc::pthread_rwlock_t,
+        abstime: *const libc::timespec,
+    ) -> libc::c_int;
+}
+
+/// 顺序锁：读多写少的共享状态，读者从不阻塞写者
+///
+/// 写者递增序号为奇数后修改数据，完成后再递增为偶数；读者复制数据，序号为奇数或复制前后
+/// 不一致时重试。可以直接嵌入共享内存中的 `repr(C)` 结构，也可以用 [`IpcSeqLock`] 单独
+/// 占用一个共享内存段。`T` 应为任意位模式都有效的纯数据（整数、数组及其组合）。
+///
+/// 写者先登记 PID 再递增序号，写完恢复序号后才撤销登记。写者进程在写入中途退出时序号
+/// 停在奇数：读者每个时间片检查一次登记的写者，已退出时返回 [`SeqLockPoisoned`]，
+/// 后来的写者接管登记并完成写入。
+#[repr(C)]
+pub struct SeqLock<T: Copy> {
+    seq: AtomicU64,
+    writer: AtomicU32, // 写者 PID，0 表示没有写者
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: Copy + Send> Sync for SeqLock<T> {}
+
+impl<T: Copy> SeqLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            seq: AtomicU64::new(0),
+            writer: AtomicU32::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// 读取一致的快照，写者正在写入时重试
+    ///
+    /// 登记的写者进程已在写入中途退出时返回 [`SeqLockPoisoned`]。
+    pub fn read(&self) -> Result<T, SeqLockPoisoned> {
+        let mut spins = 0u32;
+        let mut next_check = None;
+        loop {
+            if let Some(value) = self.try_read() {
+                return Ok(value);
+            }
+            spins = spins.saturating_add(1);
+            if spins < 64 {
+                std::hint::spin_loop();
+                continue;
+            }
+            let now = Instant::now();
+            let check_at = *next_check.get_or_insert(now + RECOVERY_SLICE);
+            if now >= check_at {
+                if let Some(pid) = self.dead_writer() {
+                    return Err(SeqLockPoisoned { pid });
+                }
+                next_check = Some(now + RECOVERY_SLICE);
+            }
+            std::thread::yield_now();
+        }
+    }
+
+    /// 读取一次，写者正在写入或读取期间发生写入时返回 `None`
+    pub fn try_read(&self) -> Option<T> {
+        let seq = self.seq.load(Ordering::Acquire);
+        if seq % 2 == 1 {
+            return None;
+        }
+        // 可能与写者并发，先按未初始化内存复制，确认序号未变后才视为有效值
+        let value = unsafe { ptr::read_volatile(self.value.get() as *const MaybeUninit<T>) };
+        fence(Ordering::Acquire);
+        (self.seq.load(Ordering::Relaxed) == seq).then(|| unsafe { value.assume_init() })
+    }
+
+    /// 修改数据，多个写者之间串行执行
+    pub fn write<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        let _writing = self.begin_write();
+        let mut value = unsafe { ptr::read_volatile(self.value.get()) };
+        let result = f(&mut value);
+        unsafe { ptr::write_volatile(self.value.get(), value) };
+        result
+    }
+
+    /// 整体替换数据
+    pub fn store(&self, value: T) {
+        let _writing = self.begin_write();
+        unsafe { ptr::write_volatile(self.value.get(), value) };
+    }
+
+    /// 已完成的写入次数，可用于判断数据是否更新
+    pub fn version(&self) -> u64 {
+        self.seq.load(Ordering::Acquire) / 2
+    }
+
+    /// 序号停在奇数且登记的写者进程已退出时返回其 PID
+    fn dead_writer(&self) -> Option<u32> {
+        let pid = self.writer.load(Ordering::Acquire);
+        let writing = self.seq.load(Ordering::Acquire) % 2 == 1;
+        (writing && pid != 0 && !shm_sync::process_alive(pid)).then_some(pid)
+    }
+
+    fn begin_write(&self) -> SeqWriteGuard<'_> {
+        let pid = std::process::id();
+        let mut spins = 0u32;
+        loop {
+            let holder = self.writer.load(Ordering::Relaxed);
+            // 登记的写者已退出时接管，否则等待其写完
+            let free = holder == 0 || (spins >= 64 && !shm_sync::process_alive(holder));
+            if free
+                && self
+                    .writer
+                    .compare_exchange_weak(holder, pid, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                // 前一个写者在写入中途退出时序号已是奇数，保持为奇数
+                let seq = self.seq.load(Ordering::Relaxed) | 1;
+                self.seq.store(seq, Ordering::Relaxed);
+                // 序号先于数据对读者可见
+                fence(Ordering::Release);
+                return SeqWriteGuard {
+                    seq: &self.seq,
+                    writer: &self.writer,
+                    next: seq + 1,
+                };
+            }
+            spins = spins.saturating_add(1);
+            if spins < 64 {
+                std::hint::spin_loop();
+            } else {
+                std::thread::yield_now();
+            }
+        }
+    }
+}
+
+/// 写入结束（包括写入闭包 panic）时把序号恢复为偶数，再撤销写者登记
+struct SeqWriteGuard<'a> {
+    seq: &'a AtomicU64,
+    writer: &'a AtomicU32,
+    next: u64,
+}
+
+impl Drop for SeqWriteGuard<'_> {
+    fn drop(&mut self) {
+        self.seq.store(self.next, Ordering::Release);
+        self.writer.store(0, Ordering::Release);
+    }
+}
+
+/// 单独占用一个命名共享内存段的 [`SeqLock`]，例如守护进程发布、其他进程读取的状态块
+pub struct IpcSeqLock<T: Copy> {
+    segment: Segment<SeqLock<T>>,
+}
+
+impl<T: Copy + Send> IpcSeqLock<T> {
+    /// 创建（或重置）状态块，初始值为 `value`
+    pub fn create(name: &str, value: T) -> Result<Self> {
+        Ok(Self {
+            segment: Segment::create(name, |lock: &SeqLock<T>| lock.store(value))?,
+        })
+    }
+
+    /// 连接已创建的状态块，`T` 必须与创建者一致
+    pub fn open(name: &str) -> Result<Self> {
+        Ok(Self {
+            segment: Segment::open(name)?,
+        })
+    }
+}
+
+impl<T: Copy + Send> std::ops::Deref for IpcSeqLock<T> {
+    type Target = SeqLock<T>;
+
+    fn deref(&self) -> &SeqLock<T> {
+        self.segment.get()
+    }
+}
+
+fn split(state: u64) -> (u32, u32) {
+    ((state >> 32) as u32, state as u32)
+}
+
+fn join(round: u32, arrived: u32) -> u64 {
+    ((round as u64) << 32) | arrived as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn test_semaphore_and_barrier() {
+        let name = format!("mi7_test_semaphore_{}", std::process::id());
+        let semaphore = Arc::new(IpcSemaphore::create(&name, 2).unwrap());
+        let holders = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let threads: Vec<_> = (0..6)
+            .map(|_| {
+                let semaphore = IpcSemaphore::open(&name).unwrap();
+                let (holders, peak) = (Arc::clone(&holders), Arc::clone(&peak));
+                std::thread::spawn(move || {
+                    for _ in 0..5 {
+                        let _permit = semaphore.acquire();
+                        let now = holders.fetch_add(1, Ordering::SeqCst) + 1;
+                        peak.fetch_max(now, Ordering::SeqCst);
+                        std::thread::sleep(Duration::from_millis(1));
+                        holders.fetch_sub(1, Ordering::SeqCst);
+                    }
+                })
+            })
+            .collect();
+        threads.into_iter().for_each(|t| t.join().unwrap());
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        assert_eq!(semaphore.available(), 2);
+        assert_eq!(semaphore.stats().acquisitions, 30);
+
+        let _a = semaphore.try_acquire().unwrap();
+        let _b = semaphore.try_acquire().unwrap();
+        assert!(semaphore.try_acquire().is_none());
+        assert_eq!(
+            semaphore.acquire_timeout(Duration::from_millis(20)).err(),
+            Some(LockTimeout {
+                timeout: Duration::from_millis(20)
+            })
+        );
+        assert_eq!(semaphore.stats().timeouts, 1);
+
+        let name = format!("mi7_test_barrier_{}", std::process::id());
+        let barrier = IpcBarrier::create(&name, 3).unwrap();
+        // 参与方不足时超时并撤回到达，不影响后续轮次
+        assert!(barrier.wait_timeout(Duration::from_millis(20)).is_err());
+
+        let leaders = Arc::new(AtomicUsize::new(0));
+        let threads: Vec<_> = (0..3)
+            .map(|_| {
+                let barrier = IpcBarrier::open(&name).unwrap();
+                let leaders = Arc::clone(&leaders);
+                std::thread::spawn(move || {
+                    for _ in 0..3 {
+                        if barrier.wait().is_leader() {
+                            leaders.fetch_add(1, Ordering::SeqCst);
+                        }
+                    }
+                })
+            })
+            .collect();
+        threads.into_iter().for_each(|t| t.join().unwrap());
+        assert_eq!(leaders.load(Ordering::SeqCst), 3);
+        assert_eq!(barrier.stats().acquisitions, 9);
+        assert_eq!(barrier.stats().timeouts, 1);
+    }
+
+    #[test]
+    fn test_rwlock_recovers_from_dead_writer() {
+        let name = format!("mi7_test_rwlock_{}", std::process::id());
+        let lock = IpcRwLock::create(&name).unwrap();
+        let other = IpcRwLock::open(&name).unwrap();
+
+        let first = lock.read();
+        let second = other.read_timeout(Duration::from_millis(20)).unwrap();
+        assert!(lock.write_timeout(Duration::from_millis(20)).is_err());
+        drop((first, second));
+
+        // 模拟写者进程崩溃：锁未释放，登记的写者已不存在
+        std::mem::forget(lock.write());
+        lock.segment
+            .get()
+            .writer
+            .store(0x3fff_fff0, Ordering::Release);
+        assert!(other.read_timeout(Duration::from_secs(2)).is_ok());
+        drop(other.write_timeout(Duration::from_millis(20)).unwrap());
+
+        let stats = lock.stats();
+        assert_eq!(stats.timeouts, 1);
+        assert_eq!(stats.acquisitions, 5);
+    }
+
+    #[test]
+    fn test_seqlock_snapshots_are_consistent() {
+        let name = format!("mi7_test_seqlock_{}", std::process::id());
+        let status = IpcSeqLock::create(&name, [0u64; 3]).unwrap();
+        let reader = IpcSeqLock::<[u64; 3]>::open(&name).unwrap();
+
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                for n in 1..=10_000u64 {
+                    status.write(|value| *value = [n, n * 2, n * 3]);
+                }
+            });
+            scope.spawn(|| {
+                let mut last = 0;
+                while last < 10_000 {
+                    let [a, b, c] = reader.read().unwrap();
+                    assert!(b == a * 2 && c == a * 3, "读到不一致的快照");
+                    assert!(a >= last);
+                    last = a;
+                }
+            });
+        });
+        assert_eq!(reader.version(), 10_001);
+        assert_eq!(reader.try_read(), Some([10_000, 20_000, 30_000]));
+    }
+
+    #[test]
+    fn test_seqlock_detects_dead_writer() {
+        let name = format!("mi7_test_seqlock_dead_{}", std::process::id());
+        let status = IpcSeqLock::create(&name, [1u64; 2]).unwrap();
+        let reader = IpcSeqLock::<[u64; 2]>::open(&name).unwrap();
+
+        // 模拟写者进程在写入中途崩溃：序号停在奇数，登记的写者已不存在
+        std::mem::forget(status.begin_write());
+        status.writer.store(0x3fff_fff0, Ordering::Release);
+        assert_eq!(reader.try_read(), None);
+        assert_eq!(reader.read(), Err(SeqLockPoisoned { pid: 0x3fff_fff0 }));
+
+        // 后来的写者接管，写完后读者恢复
+        reader.store([2, 2]);
+        assert_eq!(status.read(), Ok([2, 2]));
+        assert_eq!(status.writer.load(Ordering::Acquire), 0);
+    }
+
+    #[test]
+    fn test_segment_open_requires_existing_segment() {
+        let name = format!("mi7_test_segment_{}", std::process::id());
+        assert!(Segment::<[AtomicU64; 4]>::open(&name).is_err());
+        assert!(
+            Mapping::open(&name, 8).is_err(),
+            "连接方不创建段，失败的连接不留下空段"
+        );
+
+        // 段的长度不足时拒绝连接，而不是映射后访问时触发 SIGBUS
+        let short = Mapping::create(&name, 8).unwrap();
+        assert!(Segment::<[AtomicU64; 4]>::open(&name).is_err());
+        assert_eq!(Mapping::open(&name, 8).unwrap().len(), short.len());
+        SharedMemoryRegistry::unlink(&name).unwrap();
+
+        let segment = Segment::create(&name, |block: &[AtomicU64; 4]| {
+            block[3].store(7, Ordering::Relaxed)
+        })
+        .unwrap();
+        let peer = Segment::<[AtomicU64; 4]>::open(&name).unwrap();
+        assert_eq!(peer.get()[3].load(Ordering::Relaxed), 7);
+        assert!(segment.is_owner() && !peer.is_owner());
+        drop(segment);
+        assert!(Segment::<[AtomicU64; 4]>::open(&name).is_err());
+    }
+}
@@ -0,0 +1,300 @@
+//! 多进程日志汇聚
+//!
+//! entry / worker 通过 [`LogRingLayer`] 把结构化日志事件追加到共享内存中的日志环
+//! （一个 [`StreamPipe`]），守护进程用 [`LogAggregator`] 按序号取出并写入同一个
+//! 按 [`RotationPolicy`](crate::logging::RotationPolicy) 切分的文件。序号在追加时由日志环统一分配，因此文件中的顺序就是各进程
+//! 写入日志环的全局顺序。日志环写满时丢弃新事件而不阻塞业务线程。
+
+use crate::Message;
+use crate::logging::RotatingFileWriter;
+use crate::stream::{StreamPipe, Subscription};
+
+use crate::error::Result;
+use anyhow::Context as _;
+use std::cell::Cell;
+use std::fmt::Write as _;
+use std::io::Write;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// 默认的日志环名称
+pub const DEFAULT_RING_NAME: &str = "mi7_log";
+
+/// 日志环的默认容量（事件数量）
+pub const DEFAULT_RING_CAPACITY: usize = 4096;
+
+/// 日志环的默认槽位大小
+pub const DEFAULT_RING_SLOT_SIZE: usize = 1024;
+
+/// 守护进程读取日志环使用的消费组
+const AGGREGATOR_GROUP: &str = "aggregator";
+
+/// 槽位中为记录其余字段与编码开销预留的字节数
+const RECORD_OVERHEAD: usize = 256;
+
+thread_local! {
+    /// 防止追加日志的过程中产生的日志再次进入本层
+    static IN_LAYER: Cell<bool> = const { Cell::new(false) };
+}
+
+/// 日志环中的一条结构化日志事件
+#[derive(Debug, Clone, PartialEq, bincode::Encode, bincode::Decode)]
+pub struct LogRecord {
+    /// UNIX 微秒时间戳
+    pub timestamp_micros: i64,
+    pub level: String,
+    /// 进程名称（日志文件前缀）
+    pub process: String,
+    pub pid: u32,
+    pub target: String,
+    /// `message` 字段与其余字段（`key=value`）
+    pub message: String,
+}
+
+impl LogRecord {
+    /// 输出到汇聚文件的一行
+    pub fn to_line(&self) -> String {
+        format!(
+            "{} {:>5} {}[{}] {}: {}\n",
+            self.local_time().format("%Y-%m-%dT%H:%M:%S%.6f%:z"),
+            self.level,
+            self.process,
+            self.pid,
+            self.target,
+            self.message
+        )
+    }
+
+    fn local_time(&self) -> chrono::DateTime<chrono::Local> {
+        chrono::DateTime::from_timestamp_micros(self.timestamp_micros)
+            .unwrap_or_default()
+            .with_timezone(&chrono::Local)
+    }
+
+    fn encode(&self) -> Result<Message> {
+        let mut message = Message::init(String::new());
+        message.data = bincode::encode_to_vec(self, bincode::config::standard())?;
+        Ok(message)
+    }
+
+    fn decode(message: &Message) -> Result<Self> {
+        let (record, _) = bincode::decode_from_slice(&message.data, bincode::config::standard())?;
+        Ok(record)
+    }
+}
+
+/// 创建日志环，通常由守护进程在启动子进程之前调用
+pub fn create(name: &str) -> Result<Arc<StreamPipe>> {
+    Ok(Arc::new(StreamPipe::create(
+        name,
+        DEFAULT_RING_CAPACITY,
+        DEFAULT_RING_SLOT_SIZE,
+    )?))
+}
+
+/// 把日志事件追加到日志环的 tracing 层
+pub struct LogRingLayer {
+    stream: Arc<StreamPipe>,
+    process: String,
+    dropped: AtomicU64,
+}
+
+impl LogRingLayer {
+    /// 连接到已有日志环
+    pub fn connect(name: &str, process: impl Into<String>) -> Result<Self> {
+        let stream =
+            StreamPipe::connect(name).with_context(|| format!("连接日志环 {} 失败", name))?;
+        Ok(Self::new(Arc::new(stream), process))
+    }
+
+    /// 使用已打开的日志环，`process` 为写入记录的进程名称
+    pub fn new(stream: Arc<StreamPipe>, process: impl Into<String>) -> Self {
+        Self {
+            stream,
+            process: process.into(),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// 因日志环已满或追加失败而丢弃的事件数量
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    fn publish(&self, record: &LogRecord) -> Result<bool> {
+        Ok(self.stream.try_publish(&record.encode()?)?.is_some())
+    }
+}
+
+impl<S: Subscriber> Layer<S> for LogRingLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        if IN_LAYER.with(|flag| flag.replace(true)) {
+            return;
+        }
+
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+        let mut message = visitor.finish();
+        truncate(
+            &mut message,
+            self.stream.slot_size().saturating_sub(RECORD_OVERHEAD),
+        );
+
+        let metadata = event.metadata();
+        let record = LogRecord {
+            timestamp_micros: chrono::Utc::now().timestamp_micros(),
+            level: metadata.level().to_string(),
+            process: self.process.clone(),
+            pid: std::process::id(),
+            target: metadata.target().to_string(),
+            message,
+        };
+        if !matches!(self.publish(&record), Ok(true)) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+
+        IN_LAYER.with(|flag| flag.set(false));
+    }
+}
+
+/// 把事件字段拼成一行文本
+#[derive(Default)]
+struct FieldVisitor {
+    message: String,
+    fields: String,
+}
+
+impl FieldVisitor {
+    fn finish(mut self) -> String {
+        if !self.fields.is_empty() {
+            if !self.message.is_empty() {
+                self.message.push(' ');
+            }
+            self.message.push_str(self.fields.trim_start());
+        }
+        self.message
+    }
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            let _ = write!(self.fields, " {}={}", field.name(), value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{:?}", value);
+        } else {
+            let _ = write!(self.fields, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+/// 截断到不超过 `max` 字节的字符边界
+fn truncate(text: &mut String, max: usize) {
+    if text.len() > max {
+        let mut end = max;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+    }
+}
+
+/// 守护进程侧：按序号取出日志环中的事件，写入汇聚文件
+pub struct LogAggregator {
+    subscription: Subscription,
+    writer: RotatingFileWriter,
+}
+
+impl LogAggregator {
+    /// 汇聚到 `writer`，文件的切分与保留由其 [`RotationPolicy`](crate::logging::RotationPolicy) 决定
+    pub fn new(stream: &Arc<StreamPipe>, writer: RotatingFileWriter) -> Result<Self> {
+        Ok(Self {
+            subscription: stream.subscribe(AGGREGATOR_GROUP)?,
+            writer,
+        })
+    }
+
+    /// 写入当前积压的全部事件，没有事件时最多等待 `timeout`，返回写入的数量
+    pub fn drain(&mut self, timeout: Duration) -> Result<usize> {
+        let mut next = if self.subscription.lag() > 0 {
+            self.subscription.try_receive()?
+        } else {
+            self.subscription.receive_blocking(timeout).ok()
+        };
+
+        let mut written = 0;
+        while let Some((seq, message)) = next {
+            match LogRecord::decode(&message) {
+                Ok(record) => {
+                    self.writer
+                        .write_all(record.to_line().as_bytes())
+                        .context("写入汇聚日志失败")?;
+                    written += 1;
+                }
+                Err(e) => eprintln!("警告: 日志环中的事件 {} 无法解析: {}", seq, e),
+            }
+            next = self.subscription.try_receive()?;
+        }
+        self.writer.flush()?;
+        Ok(written)
+    }
+
+    /// 持续汇聚直到 `stop` 被置位，退出前写完剩余事件
+    pub fn run(mut self, stop: Arc<AtomicBool>) {
+        while !stop.load(Ordering::Acquire) {
+            if let Err(e) = self.drain(Duration::from_millis(200)) {
+                eprintln!("警告: 汇聚日志失败: {}", e);
+                std::thread::sleep(Duration::from_secs(1));
+            }
+        }
+        if let Err(e) = self.drain(Duration::ZERO) {
+            eprintln!("警告: 汇聚日志失败: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_events_aggregated_in_order() {
+        let name = format!("mi7_test_log_ring_{}", std::process::id());
+        let stream = Arc::new(StreamPipe::create(&name, 8, 512).unwrap());
+        let dir = tempfile::tempdir().unwrap();
+        let writer = RotatingFileWriter::new(dir.path(), "all", Default::default()).unwrap();
+        let mut aggregator = LogAggregator::new(&stream, writer).unwrap();
+
+        for process in ["entry", "worker"] {
+            let subscriber = tracing_subscriber::registry()
+                .with(LogRingLayer::new(Arc::clone(&stream), process));
+            tracing::subscriber::with_default(subscriber, || {
+                tracing::info!(slot = 3, "来自 {}", process);
+                tracing::warn!("{}", "x".repeat(1024));
+            });
+        }
+        assert_eq!(aggregator.drain(Duration::from_millis(100)).unwrap(), 4);
+
+        let date = chrono::Local::now().format("%Y-%m-%d");
+        let text = std::fs::read_to_string(dir.path().join(format!("all.{}.log", date))).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].contains(&format!("INFO entry[{}]", std::process::id())));
+        assert!(lines[0].ends_with("来自 entry slot=3"));
+        // 超长消息被截断而不是丢弃
+        assert!(lines[1].contains(" WARN entry["));
+        assert!(lines[2].ends_with("来自 worker slot=3"));
+        assert_eq!(aggregator.drain(Duration::ZERO).unwrap(), 0);
+    }
+}
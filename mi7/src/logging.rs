@@ -1,12 +1,39 @@
 use fs2::FileExt;
 use std::fs::{File, OpenOptions};
 use std::io::{self, Write};
-use std::path::PathBuf;
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
+use serde::Deserialize;
+use crate::log_ring::LogRingLayer;
 use tracing_appender::{non_blocking, rolling};
+use tracing_subscriber::filter::LevelFilter;
 use tracing_subscriber::fmt::MakeWriter;
-use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt};
-use anyhow::Result;
+use tracing_subscriber::{Registry, fmt, layer::SubscriberExt, reload, util::SubscriberInitExt};
+use crate::error::Result;
+
+/// 运行时调整日志级别的句柄，日志系统初始化后可用
+static LEVEL_HANDLE: OnceLock<reload::Handle<LevelFilter, Registry>> = OnceLock::new();
+
+/// 可在运行时调整的日志级别过滤层，初始不过滤
+fn level_layer() -> reload::Layer<LevelFilter, Registry> {
+    let (layer, handle) = reload::Layer::new(LevelFilter::TRACE);
+    let _ = LEVEL_HANDLE.set(handle);
+    layer
+}
+
+/// 调整日志级别（trace / debug / info / warn / error / off），无需重新初始化日志系统
+pub fn set_level(level: &str) -> Result<()> {
+    let filter =
+        LevelFilter::from_str(level).map_err(|_| anyhow::anyhow!("无效的日志级别: {}", level))?;
+    let handle = LEVEL_HANDLE
+        .get()
+        .ok_or_else(|| anyhow::anyhow!("日志系统未初始化"))?;
+    handle
+        .reload(filter)
+        .map_err(|e| anyhow::anyhow!("调整日志级别失败: {}", e).into())
+}
 
 /// 日志初始化配置
 pub struct LogConfig {
@@ -103,6 +130,237 @@ impl<'a> MakeWriter<'a> for SafeFileWriter {
     }
 }
 
+/// 按时间切分日志文件的周期
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RotationPeriod {
+    /// 不按时间切分，文件名为 `<prefix>.log`
+    Never,
+    /// 每小时一个文件，`<prefix>.<YYYY-MM-DD-HH>.log`
+    Hourly,
+    /// 每天一个文件，`<prefix>.<YYYY-MM-DD>.log`
+    #[default]
+    Daily,
+}
+
+impl RotationPeriod {
+    /// 文件名中的时间部分
+    fn stamp(&self, now: chrono::DateTime<chrono::Local>) -> String {
+        match self {
+            RotationPeriod::Never => String::new(),
+            RotationPeriod::Hourly => now.format("%Y-%m-%d-%H").to_string(),
+            RotationPeriod::Daily => now.format("%Y-%m-%d").to_string(),
+        }
+    }
+}
+
+/// 日志切分与保留策略，可直接从 `[logging]` 配置段读取：
+///
+/// ```rust,no_run
+/// use mi7::config;
+/// use mi7::logging::RotationPolicy;
+///
+/// let policy: RotationPolicy = config::section("logging").unwrap_or_default();
+/// ```
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default)]
+pub struct RotationPolicy {
+    /// 按时间切分的周期
+    pub rotation: RotationPeriod,
+    /// 单个文件的最大字节数，超过后在同一周期内切换到 `<prefix>.<时间>.<N>.log`；0 表示不限制
+    pub max_file_size: u64,
+    /// 最多保留的文件数量（含当前文件），0 表示不限制
+    pub max_files: usize,
+    /// 最多保留的天数，0 表示不限制
+    pub max_age_days: u64,
+}
+
+impl RotationPolicy {
+    /// 设置切分周期
+    pub fn with_rotation(mut self, rotation: RotationPeriod) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    /// 设置单个文件的最大字节数
+    pub fn with_max_file_size(mut self, bytes: u64) -> Self {
+        self.max_file_size = bytes;
+        self
+    }
+
+    /// 设置最多保留的文件数量
+    pub fn with_max_files(mut self, count: usize) -> Self {
+        self.max_files = count;
+        self
+    }
+
+    /// 设置最多保留的天数
+    pub fn with_max_age_days(mut self, days: u64) -> Self {
+        self.max_age_days = days;
+        self
+    }
+}
+
+/// 按 [`RotationPolicy`] 切分并清理旧文件的日志写入器
+///
+/// 切分状态只在进程内维护，多个进程不应共用同一个文件前缀。
+#[derive(Clone)]
+pub struct RotatingFileWriter {
+    state: Arc<Mutex<RotatingState>>,
+}
+
+struct RotatingState {
+    log_dir: PathBuf,
+    file_prefix: String,
+    policy: RotationPolicy,
+    /// 当前文件的时间部分与同一周期内的序号
+    stamp: String,
+    index: u32,
+    file: Option<File>,
+    size: u64,
+}
+
+impl RotatingFileWriter {
+    /// 在 `log_dir` 下写入以 `file_prefix` 为前缀的日志文件
+    pub fn new(
+        log_dir: impl Into<PathBuf>,
+        file_prefix: impl Into<String>,
+        policy: RotationPolicy,
+    ) -> io::Result<Self> {
+        let log_dir = log_dir.into();
+        std::fs::create_dir_all(&log_dir)?;
+        Ok(Self {
+            state: Arc::new(Mutex::new(RotatingState {
+                log_dir,
+                file_prefix: file_prefix.into(),
+                policy,
+                stamp: String::new(),
+                index: 0,
+                file: None,
+                size: 0,
+            })),
+        })
+    }
+
+    /// 当前写入的文件路径，尚未写入时返回 `None`
+    pub fn current_path(&self) -> Option<PathBuf> {
+        let state = self.state.lock().unwrap();
+        state.file.as_ref().map(|_| state.path())
+    }
+}
+
+impl RotatingState {
+    fn path(&self) -> PathBuf {
+        let mut name = self.file_prefix.clone();
+        if !self.stamp.is_empty() {
+            name.push('.');
+            name.push_str(&self.stamp);
+        }
+        if self.index > 0 {
+            name.push_str(&format!(".{}", self.index));
+        }
+        self.log_dir.join(name + ".log")
+    }
+
+    /// 打开当前序号的文件，已写满时顺延到下一个序号
+    fn open(&mut self) -> io::Result<()> {
+        loop {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(self.path())?;
+            self.size = file.metadata()?.len();
+            if self.policy.max_file_size == 0 || self.size < self.policy.max_file_size {
+                self.file = Some(file);
+                break;
+            }
+            self.index += 1;
+        }
+        self.prune()
+    }
+
+    /// 按保留策略删除旧文件
+    fn prune(&self) -> io::Result<()> {
+        if self.policy.max_files == 0 && self.policy.max_age_days == 0 {
+            return Ok(());
+        }
+        let current = self.path();
+        let prefix = format!("{}.", self.file_prefix);
+        let mut files: Vec<(SystemTime, PathBuf)> = std::fs::read_dir(&self.log_dir)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+                name.starts_with(&prefix) && name.ends_with(".log")
+            })
+            .map(|entry| entry.path())
+            .filter(|path| *path != current)
+            .filter_map(|path| Some((path.metadata().ok()?.modified().ok()?, path)))
+            .collect();
+        // 新文件在前
+        files.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+
+        let max_age = Duration::from_secs(self.policy.max_age_days * 24 * 3600);
+        let now = SystemTime::now();
+        for (i, (modified, path)) in files.iter().enumerate() {
+            let too_many = self.policy.max_files > 0 && i + 1 >= self.policy.max_files;
+            let too_old = self.policy.max_age_days > 0
+                && now.duration_since(*modified).unwrap_or_default() > max_age;
+            if too_many || too_old {
+                remove_log_file(path);
+            }
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let stamp = self.policy.rotation.stamp(chrono::Local::now());
+        if self.file.is_none() || stamp != self.stamp {
+            self.stamp = stamp;
+            self.index = 0;
+            self.open()?;
+        } else if self.policy.max_file_size > 0
+            && self.size > 0
+            && self.size + buf.len() as u64 > self.policy.max_file_size
+        {
+            self.index += 1;
+            self.open()?;
+        }
+
+        let file = self.file.as_mut().expect("日志文件已打开");
+        file.write_all(buf)?;
+        self.size += buf.len() as u64;
+        Ok(buf.len())
+    }
+}
+
+fn remove_log_file(path: &Path) {
+    if let Err(e) = std::fs::remove_file(path) {
+        eprintln!("警告: 删除旧日志文件 {} 失败: {}", path.display(), e);
+    }
+}
+
+impl Write for RotatingFileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.state.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.state.lock().unwrap().file.as_mut() {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'a> MakeWriter<'a> for RotatingFileWriter {
+    type Writer = RotatingFileWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
 /// 初始化日志系统
 ///
 /// 这个函数会：
@@ -119,14 +377,17 @@ impl<'a> MakeWriter<'a> for SafeFileWriter {
 /// - `Err(anyhow::Error)`: 初始化失败
 ///
 /// # 示例
-/// ```rust
+/// ```rust,no_run
 /// use mi7::logging::{init_logging, LogConfig};
 ///
+/// # fn main() -> anyhow::Result<()> {
 /// // 基本用法
 /// init_logging(LogConfig::new("my-app"))?;
 ///
 /// // 自定义日志目录
 /// init_logging(LogConfig::new("my-app").with_log_dir("custom-logs"))?;
+/// # Ok(())
+/// # }
 /// ```
 pub fn init_logging(config: LogConfig) -> Result<()> {
     // 创建日志目录
@@ -138,6 +399,7 @@ pub fn init_logging(config: LogConfig) -> Result<()> {
 
     // 初始化 tracing subscriber
     tracing_subscriber::registry()
+        .with(level_layer())
         .with(
             // 文件日志层
             fmt::layer()
@@ -167,6 +429,55 @@ pub fn init_default_logging(app_name: impl Into<String>) -> Result<()> {
     init_logging(LogConfig::new(app_name))
 }
 
+/// 初始化按策略切分与清理的日志系统
+///
+/// 与 [`init_logging`] 相同，但文件按 [`RotationPolicy`] 切分，并删除超出保留
+/// 数量或天数的旧文件。
+///
+/// # 示例
+/// ```rust,no_run
+/// use mi7::logging::{init_with_rotation, RotationPeriod, RotationPolicy};
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let policy = RotationPolicy::default()
+///     .with_rotation(RotationPeriod::Hourly)
+///     .with_max_file_size(64 * 1024 * 1024)
+///     .with_max_files(48);
+/// init_with_rotation("daemon", policy)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn init_with_rotation(prefix: impl Into<String>, policy: RotationPolicy) -> Result<()> {
+    init_logging_with_rotation(LogConfig::new(prefix), policy)
+}
+
+/// 按 `config` 指定的目录与前缀初始化切分日志，见 [`init_with_rotation`]
+pub fn init_logging_with_rotation(config: LogConfig, policy: RotationPolicy) -> Result<()> {
+    let writer = RotatingFileWriter::new(&config.log_dir, &config.file_prefix, policy)?;
+    let (non_blocking, guard) = non_blocking(writer);
+
+    tracing_subscriber::registry()
+        .with(level_layer())
+        .with(
+            // 文件日志层
+            fmt::layer()
+                .with_writer(non_blocking)
+                .with_ansi(false) // 文件中不使用颜色
+                .with_target(false) // 不显示目标模块
+                .with_thread_names(true), // 显示线程名
+        )
+        .with(
+            // 控制台日志层
+            fmt::layer().with_writer(io::stdout).with_ansi(true), // 控制台使用颜色
+        )
+        .init();
+
+    // 与 init_logging 相同，写入器的 guard 需要存活到进程退出
+    std::mem::forget(guard);
+
+    Ok(())
+}
+
 /// 初始化安全的多进程日志系统
 ///
 /// 这个函数专门用于多个进程需要写入同一个日志文件的场景。
@@ -180,11 +491,14 @@ pub fn init_default_logging(app_name: impl Into<String>) -> Result<()> {
 /// - `Err(anyhow::Error)`: 初始化失败
 ///
 /// # 示例
-/// ```rust
+/// ```rust,no_run
 /// use mi7::logging::{init_safe_multiprocess_logging, LogConfig};
 ///
+/// # fn main() -> anyhow::Result<()> {
 /// // 多个 worker 进程使用相同的日志文件
 /// init_safe_multiprocess_logging(LogConfig::new("workers"))?;
+/// # Ok(())
+/// # }
 /// ```
 pub fn init_safe_multiprocess_logging(config: LogConfig) -> Result<()> {
     // 创建日志目录
@@ -201,6 +515,7 @@ pub fn init_safe_multiprocess_logging(config: LogConfig) -> Result<()> {
 
     // 初始化 tracing subscriber
     tracing_subscriber::registry()
+        .with(level_layer())
         .with(
             // 文件日志层 - 使用安全的多进程写入器
             fmt::layer()
@@ -219,6 +534,42 @@ pub fn init_safe_multiprocess_logging(config: LogConfig) -> Result<()> {
     Ok(())
 }
 
+/// 初始化汇聚日志系统
+///
+/// 日志事件写入守护进程创建的共享内存日志环 `ring_name`，由守护进程统一写入
+/// 同一个文件，见 [`crate::log_ring`]。日志环不存在（守护进程未启动汇聚）时
+/// 回退到 [`init_safe_multiprocess_logging`]。
+///
+/// # 示例
+/// ```rust,no_run
+/// use mi7::logging::{init_aggregated_logging, LogConfig};
+///
+/// # fn main() -> anyhow::Result<()> {
+/// init_aggregated_logging(LogConfig::new("workers"), "mi7_log")?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn init_aggregated_logging(config: LogConfig, ring_name: &str) -> Result<()> {
+    let ring_layer = match LogRingLayer::connect(ring_name, config.file_prefix.clone()) {
+        Ok(layer) => layer,
+        Err(e) => {
+            eprintln!("警告: {}，改为写入本地日志文件", e);
+            return init_safe_multiprocess_logging(config);
+        }
+    };
+
+    tracing_subscriber::registry()
+        .with(level_layer())
+        .with(ring_layer)
+        .with(
+            // 控制台日志层
+            fmt::layer().with_writer(io::stdout).with_ansi(true), // 控制台使用颜色
+        )
+        .init();
+
+    Ok(())
+}
+
 /// 便捷函数：使用默认配置初始化安全的多进程日志
 ///
 /// 等价于 `init_safe_multiprocess_logging(LogConfig::new(app_name))`
@@ -227,3 +578,31 @@ pub fn init_safe_multiprocess_default_logging(
 ) -> Result<()> {
     init_safe_multiprocess_logging(LogConfig::new(app_name))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotation_by_size_with_retention() {
+        let dir = tempfile::tempdir().unwrap();
+        let policy = RotationPolicy::default()
+            .with_rotation(RotationPeriod::Never)
+            .with_max_file_size(100)
+            .with_max_files(2);
+        let mut writer = RotatingFileWriter::new(dir.path(), "app", policy).unwrap();
+
+        let line = [b'x'; 60];
+        writer.write_all(&line).unwrap();
+        assert_eq!(writer.current_path(), Some(dir.path().join("app.log")));
+        writer.write_all(&line).unwrap();
+        assert_eq!(writer.current_path(), Some(dir.path().join("app.1.log")));
+        writer.write_all(&line).unwrap();
+        assert_eq!(writer.current_path(), Some(dir.path().join("app.2.log")));
+
+        // 只保留 2 个文件，当前文件不会被删除
+        let files = std::fs::read_dir(dir.path()).unwrap().count();
+        assert_eq!(files, 2);
+        assert!(dir.path().join("app.2.log").exists());
+    }
+}
@@ -0,0 +1,204 @@
+//! 共享内存段的内存建议：锁定页面、预读与排空后释放
+//!
+//! 软实时部署中，管道或寄存箱的页面被换出后，下一次访问会在缺页处理上停顿。
+//! [`MemoryAdvice`] 控制本进程对映射的处理：
+//!
+//! - `lock`：`mlock` 锁定整个映射，页面不会被换出。受 `RLIMIT_MEMLOCK` 限制，
+//!   超出限制时输出警告并继续使用未锁定的映射
+//! - `will_need`：映射后 `madvise(MADV_WILLNEED)`，提前读入页面
+//! - `release_on_drain`：队列排空（或寄存箱的 box 归还为空）后对不再使用的页面
+//!   `madvise(MADV_DONTNEED)`，降低本进程的 RSS。共享内存的内容保持不变，之后访问时重新建立映射；
+//!   与 `lock` 同时设置时不生效
+//!
+//! 内存建议只作用于本进程的映射，每个进程各自设置。[`usage`] 用 `mincore` 统计段中驻留内存的页面，
+//! 与由哪个进程映射无关，mi7ctl status 据此输出每个段的驻留大小。
+
+use crate::config;
+use crate::error::Result;
+use serde::Serialize;
+use std::sync::OnceLock;
+
+/// 映射共享内存段时的内存建议，见模块文档
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MemoryAdvice {
+    /// 锁定映射的全部页面
+    pub lock: bool,
+    /// 映射后提前读入页面
+    pub will_need: bool,
+    /// 排空后释放不再使用的页面
+    pub release_on_drain: bool,
+}
+
+impl MemoryAdvice {
+    /// 软实时配置：锁定并预读全部页面
+    pub const REALTIME: Self = Self {
+        lock: true,
+        will_need: true,
+        release_on_drain: false,
+    };
+
+    /// 排空后是否释放页面：锁定的页面不能释放
+    pub fn releases_on_drain(&self) -> bool {
+        self.release_on_drain && !self.lock
+    }
+}
+
+/// 共享内存段的内存占用
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub struct MemoryUsage {
+    /// 映射的字节数
+    pub mapped: usize,
+    /// 驻留在内存中的字节数（按页统计）
+    pub resident: usize,
+}
+
+/// 按 `advice` 处理映射，`previous` 为此前生效的建议
+///
+/// 取消锁定时 `munlock`。失败只输出警告，映射仍可正常使用。
+pub(crate) fn advise(
+    name: &str,
+    addr: *mut u8,
+    len: usize,
+    previous: MemoryAdvice,
+    advice: MemoryAdvice,
+) {
+    let addr = addr as *mut libc::c_void;
+    if advice.will_need && unsafe { libc::madvise(addr, len, libc::MADV_WILLNEED) } == -1 {
+        tracing::warn!(
+            "共享内存段 {} madvise(WILLNEED) 失败, errno: {}",
+            name,
+            crate::shm_sync::errno()
+        );
+    }
+    if advice.lock && !previous.lock {
+        if unsafe { libc::mlock(addr, len) } == -1 {
+            tracing::warn!(
+                "共享内存段 {} 锁定 {} 字节失败 (errno: {})，可能超出 RLIMIT_MEMLOCK",
+                name,
+                len,
+                crate::shm_sync::errno()
+            );
+        }
+    } else if !advice.lock && previous.lock {
+        unsafe { libc::munlock(addr, len) };
+    }
+}
+
+/// 释放 `[addr, addr + len)` 中完整包含的页面，不足一页时不做处理
+pub(crate) fn release(addr: *mut u8, len: usize) {
+    let page = page_size();
+    let start = (addr as usize).next_multiple_of(page);
+    let end = (addr as usize + len) / page * page;
+    if end > start {
+        unsafe { libc::madvise(start as *mut libc::c_void, end - start, libc::MADV_DONTNEED) };
+    }
+}
+
+/// 统计映射中驻留内存的页面，`addr` 需按页对齐
+pub(crate) fn usage(addr: *mut u8, len: usize) -> MemoryUsage {
+    let page = page_size();
+    // 页面标志在 Linux 上为 `u8`，BSD 与 Apple 平台上为 `c_char`，只看最低位
+    let mut pages = vec![0u8; len.div_ceil(page)];
+    let flags = pages.as_mut_ptr().cast();
+    let resident = if unsafe { libc::mincore(addr as *mut libc::c_void, len, flags) } == 0 {
+        pages.iter().filter(|&&flag| flag & 1 != 0).count() * page
+    } else {
+        0
+    };
+    MemoryUsage {
+        mapped: len,
+        resident: resident.min(len),
+    }
+}
+
+fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+/// 本进程的默认内存建议，由 [`install`] 或 [`init_from_config`] 设置
+static INSTALLED: OnceLock<MemoryAdvice> = OnceLock::new();
+
+/// 设置本进程的默认内存建议，此后新建的 [`PipeConfig`](crate::pipe::PipeConfig)、
+/// [`BoxConfig`](crate::shared_box::BoxConfig) 与连接的管道使用它；已设置过时返回 `false`
+pub fn install(advice: MemoryAdvice) -> bool {
+    INSTALLED.set(advice).is_ok()
+}
+
+/// 本进程的默认内存建议，未设置时不做任何处理
+pub fn installed() -> MemoryAdvice {
+    INSTALLED.get().copied().unwrap_or_default()
+}
+
+/// 按 `[memory]` 配置设置本进程的默认内存建议
+///
+/// 未配置该段时返回 `false`。
+pub fn init_from_config() -> Result<bool> {
+    if config::get_config().get_keys("memory").is_none() {
+        return Ok(false);
+    }
+    let advice = MemoryAdvice {
+        lock: config::bool_or("memory", "lock", false),
+        will_need: config::bool_or("memory", "will_need", false),
+        release_on_drain: config::bool_or("memory", "release_on_drain", false),
+    };
+    tracing::info!("共享内存建议: {:?}", advice);
+    Ok(install(advice))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Message;
+    use crate::pipe::{DynCrossProcessPipe, PipeConfig};
+    use crate::shared_box::{BoxConfig, BoxSize, SharedMemoryMailbox};
+    use std::time::Duration;
+
+    #[test]
+    fn test_memory_advice_and_usage() {
+        let name = format!("mi7_test_memory_{}", std::process::id());
+        let timeout = Duration::from_secs(1);
+        let advice = MemoryAdvice {
+            will_need: true,
+            release_on_drain: true,
+            ..Default::default()
+        };
+        let config = PipeConfig::new(64, 16 * 1024).with_memory_advice(advice);
+        let pipe = DynCrossProcessPipe::create_with_config(&name, config).unwrap();
+        assert_eq!(pipe.config().memory, advice);
+
+        for i in 0..64 {
+            pipe.send_blocking(Message::init(format!("m{}", i)), timeout)
+                .unwrap();
+        }
+        let usage = pipe.memory_usage();
+        assert!(usage.mapped >= 64 * 16 * 1024);
+        assert!(usage.resident > 0 && usage.resident <= usage.mapped);
+
+        // 排空后释放本进程的页面，共享内存的内容不受影响
+        for _ in 0..64 {
+            pipe.receive_blocking(timeout).unwrap();
+        }
+        pipe.send_blocking(Message::init("after".to_string()), timeout)
+            .unwrap();
+        assert_eq!(pipe.receive_blocking(timeout).unwrap().data, b"after");
+
+        // 锁定失败（RLIMIT_MEMLOCK）只输出警告，管道照常使用
+        let pipe = pipe.with_memory_advice(MemoryAdvice::REALTIME);
+        assert!(!pipe.config().memory.releases_on_drain());
+        pipe.send_blocking(Message::init("locked".to_string()), timeout)
+            .unwrap();
+        assert_eq!(pipe.receive_blocking(timeout).unwrap().data, b"locked");
+
+        let mut config = BoxConfig::new();
+        config
+            .set_count(BoxSize::Size1M, 1)
+            .set_memory_advice(advice);
+        let mailbox = SharedMemoryMailbox::new_shared(&format!("{}_box", name), config).unwrap();
+        let box_id = mailbox.get_empty_box(BoxSize::Size1M).unwrap();
+        mailbox.write_data(box_id, &vec![7u8; 512 * 1024]).unwrap();
+        let written = mailbox.memory_usage().resident;
+        assert!(written >= 512 * 1024);
+        assert_eq!(mailbox.take_data(box_id).unwrap().len(), 512 * 1024);
+        assert!(mailbox.memory_usage().resident <= written);
+    }
+}
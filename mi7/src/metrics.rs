@@ -0,0 +1,444 @@
+//! Prometheus 指标导出
+//!
+//! 进程内登记的管道与消息流在抓取时读取共享内存头部的计数器，输出 Prometheus
+//! 文本格式（`text/plain; version=0.0.4`）。计数器位于共享内存中，由所有连接方
+//! 共同累加，因此只需在一个进程（通常是守护进程）中登记并通过 [`serve`] 暴露。
+//! 通过 [`register_status`] 登记的状态以 JSON 形式由同一端点的 `GET /status` 输出。
+
+use crate::pipe::{DynamicPipe, PipeMetrics, PipeStatus};
+use crate::stream::StreamPipe;
+
+use crate::error::Result;
+use anyhow::Context;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, ToSocketAddrs};
+
+/// 请求头的最大读取长度
+const MAX_REQUEST_LEN: usize = 8192;
+
+static PIPES: OnceLock<Mutex<Vec<Arc<dyn DynamicPipe>>>> = OnceLock::new();
+static STREAMS: OnceLock<Mutex<Vec<Arc<StreamPipe>>>> = OnceLock::new();
+static STATUS: OnceLock<Mutex<StatusProviders>> = OnceLock::new();
+
+/// 抓取 `/status` 时调用的状态提供者
+type StatusProvider = dyn Fn() -> serde_json::Value + Send + Sync;
+type StatusProviders = Vec<(String, Arc<StatusProvider>)>;
+
+fn pipes() -> &'static Mutex<Vec<Arc<dyn DynamicPipe>>> {
+    PIPES.get_or_init(|| Mutex::new(Vec::new()))
+}
+
+fn streams() -> &'static Mutex<Vec<Arc<StreamPipe>>> {
+    STREAMS.get_or_init(|| Mutex::new(Vec::new()))
+}
+
+fn status() -> &'static Mutex<StatusProviders> {
+    STATUS.get_or_init(|| Mutex::new(Vec::new()))
+}
+
+/// 登记需要导出指标的管道
+pub fn register(pipe: Arc<dyn DynamicPipe>) {
+    pipes().lock().unwrap().push(pipe);
+}
+
+/// 登记需要导出指标的消息流
+pub fn register_stream(stream: Arc<StreamPipe>) {
+    streams().lock().unwrap().push(stream);
+}
+
+/// 登记 `GET /status` 中名为 `name` 的一项状态，同名的旧登记被替换
+pub fn register_status(
+    name: &str,
+    provider: impl Fn() -> serde_json::Value + Send + Sync + 'static,
+) {
+    let mut status = status().lock().unwrap();
+    status.retain(|(existing, _)| existing != name);
+    status.push((name.to_string(), Arc::new(provider)));
+}
+
+/// 取消登记指定名称的管道、消息流与状态
+pub fn deregister(name: &str) {
+    status()
+        .lock()
+        .unwrap()
+        .retain(|(existing, _)| existing != name);
+    pipes().lock().unwrap().retain(|pipe| pipe.name() != name);
+    streams()
+        .lock()
+        .unwrap()
+        .retain(|stream| stream.name() != name);
+}
+
+/// 管道的单项指标：名称、类型、说明与取值
+type PipeMetric = (
+    &'static str,
+    &'static str,
+    &'static str,
+    fn(&PipeStatus) -> u64,
+);
+
+const PIPE_METRICS: &[PipeMetric] = &[
+    ("mi7_pipe_capacity", "gauge", "槽位总数", |s| {
+        s.capacity as u64
+    }),
+    (
+        "mi7_pipe_used_slots",
+        "gauge",
+        "非 EMPTY 的槽位数量",
+        |s| s.used_count as u64,
+    ),
+    (
+        "mi7_pipe_backpressured",
+        "gauge",
+        "是否处于背压",
+        |s| s.backpressured as u64,
+    ),
+    (
+        "mi7_pipe_sent_total",
+        "counter",
+        "写入的消息累计数量",
+        |s| s.sent_count,
+    ),
+    (
+        "mi7_pipe_received_total",
+        "counter",
+        "读取并释放的消息累计数量",
+        |s| s.received_count,
+    ),
+    (
+        "mi7_pipe_lock_contended_total",
+        "counter",
+        "加锁时锁已被占用的累计次数",
+        |s| s.lock_contended_count,
+    ),
+    (
+        "mi7_pipe_reclaimed_total",
+        "counter",
+        "因租约超时被回收的槽位累计数量",
+        |s| s.reclaimed_count,
+    ),
+    (
+        "mi7_pipe_expired_total",
+        "counter",
+        "接收时因过期被丢弃的消息累计数量",
+        |s| s.expired_count,
+    ),
+    (
+        "mi7_pipe_redelivered_total",
+        "counter",
+        "重新投递的消息累计数量",
+        |s| s.redelivered_count,
+    ),
+    (
+        "mi7_pipe_dead_lettered_total",
+        "counter",
+        "转入死信的消息累计数量",
+        |s| s.dead_lettered_count,
+    ),
+    (
+        "mi7_pipe_dropped_total",
+        "counter",
+        "覆盖模式下被丢弃的最早消息累计数量",
+        |s| s.dropped_count,
+    ),
+];
+
+/// 管道的时间间隔指标（秒）：名称、说明与取值
+type PipeInterval = (&'static str, &'static str, fn(&PipeMetrics) -> Duration);
+
+const PIPE_INTERVALS: &[PipeInterval] = &[
+    (
+        "mi7_pipe_arrival_interval_seconds",
+        "最近相邻写入间隔的滑动平均",
+        |m| m.arrival_gap,
+    ),
+    (
+        "mi7_pipe_poll_interval_seconds",
+        "自适应等待按当前到达速率选用的轮询间隔，0 表示忙等",
+        |m| m.poll_interval,
+    ),
+];
+
+/// 输出所有已登记管道与消息流的指标
+pub fn render() -> String {
+    let pipes = pipes().lock().unwrap().clone();
+    let streams = streams().lock().unwrap().clone();
+    let mut out = render_pipes(&pipes);
+    out.push_str(&render_streams(&streams));
+    out
+}
+
+/// 输出指定管道的指标
+pub fn render_pipes(pipes: &[Arc<dyn DynamicPipe>]) -> String {
+    let mut out = String::new();
+    if pipes.is_empty() {
+        return out;
+    }
+    let statuses: Vec<(&str, PipeStatus)> = pipes
+        .iter()
+        .map(|pipe| (pipe.name(), pipe.status()))
+        .collect();
+
+    for (metric, kind, help, value) in PIPE_METRICS {
+        family(&mut out, metric, kind, help);
+        for (name, status) in &statuses {
+            let _ = writeln!(
+                out,
+                "{}{{pipe=\"{}\"}} {}",
+                metric,
+                escape(name),
+                value(status)
+            );
+        }
+    }
+
+    family(&mut out, "mi7_pipe_slots", "gauge", "各状态的槽位数量");
+    for (name, status) in &statuses {
+        for (state, count) in [
+            ("empty", status.empty_count),
+            ("writing", status.writing_count),
+            ("in_progress", status.in_progress_count),
+            ("reading", status.reading_count),
+            ("ready", status.ready_count),
+        ] {
+            let _ = writeln!(
+                out,
+                "mi7_pipe_slots{{pipe=\"{}\",state=\"{}\"}} {}",
+                escape(name),
+                state,
+                count
+            );
+        }
+    }
+
+    family(
+        &mut out,
+        "mi7_pipe_latency_seconds",
+        "histogram",
+        "消息从写入到被读取的延迟",
+    );
+    let metrics: Vec<(&str, PipeMetrics)> = pipes
+        .iter()
+        .map(|pipe| (pipe.name(), pipe.metrics()))
+        .collect();
+    for (name, metrics) in &metrics {
+        latency_histogram(&mut out, name, metrics);
+    }
+
+    for (metric, help, value) in PIPE_INTERVALS {
+        family(&mut out, metric, "gauge", help);
+        for (name, metrics) in &metrics {
+            let _ = writeln!(
+                out,
+                "{}{{pipe=\"{}\"}} {}",
+                metric,
+                escape(name),
+                value(metrics).as_secs_f64()
+            );
+        }
+    }
+    out
+}
+
+/// 输出所有已登记的状态，每项以登记名称为键
+pub fn render_status() -> String {
+    let providers = status().lock().unwrap().clone();
+    let status: serde_json::Map<String, serde_json::Value> = providers
+        .into_iter()
+        .map(|(name, provider)| (name, provider()))
+        .collect();
+    serde_json::Value::Object(status).to_string()
+}
+
+fn latency_histogram(out: &mut String, name: &str, metrics: &PipeMetrics) {
+    let name = escape(name);
+    let mut cumulative = 0;
+    for (upper, count) in &metrics.buckets {
+        cumulative += count;
+        let _ = writeln!(
+            out,
+            "mi7_pipe_latency_seconds_bucket{{pipe=\"{}\",le=\"{}\"}} {}",
+            name,
+            upper.as_secs_f64(),
+            cumulative
+        );
+    }
+    let _ = writeln!(
+        out,
+        "mi7_pipe_latency_seconds_bucket{{pipe=\"{}\",le=\"+Inf\"}} {}",
+        name, metrics.count
+    );
+    let _ = writeln!(
+        out,
+        "mi7_pipe_latency_seconds_sum{{pipe=\"{}\"}} {}",
+        name,
+        metrics.sum.as_secs_f64()
+    );
+    let _ = writeln!(
+        out,
+        "mi7_pipe_latency_seconds_count{{pipe=\"{}\"}} {}",
+        name, metrics.count
+    );
+}
+
+/// 输出指定消息流的指标
+pub fn render_streams(streams: &[Arc<StreamPipe>]) -> String {
+    let mut out = String::new();
+    if streams.is_empty() {
+        return out;
+    }
+
+    family(&mut out, "mi7_stream_capacity", "gauge", "消息流槽位总数");
+    for stream in streams {
+        let _ = writeln!(
+            out,
+            "mi7_stream_capacity{{stream=\"{}\"}} {}",
+            escape(stream.name()),
+            stream.capacity()
+        );
+    }
+    family(
+        &mut out,
+        "mi7_stream_messages",
+        "gauge",
+        "消息流中保留的消息数量",
+    );
+    for stream in streams {
+        let _ = writeln!(
+            out,
+            "mi7_stream_messages{{stream=\"{}\"}} {}",
+            escape(stream.name()),
+            stream.len()
+        );
+    }
+    family(
+        &mut out,
+        "mi7_stream_group_lag",
+        "gauge",
+        "消费组积压的消息数量",
+    );
+    for stream in streams {
+        for (group, lag) in stream.groups() {
+            let _ = writeln!(
+                out,
+                "mi7_stream_group_lag{{stream=\"{}\",group=\"{}\"}} {}",
+                escape(stream.name()),
+                escape(&group),
+                lag
+            );
+        }
+    }
+    out
+}
+
+fn family(out: &mut String, metric: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", metric, help);
+    let _ = writeln!(out, "# TYPE {} {}", metric, kind);
+}
+
+/// 转义标签值中的反斜杠、双引号与换行
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// 在 `addr` 上提供 `GET /metrics` 与 `GET /status` 端点，直到任务被取消
+///
+/// 只实现抓取所需的最小 HTTP/1.1 子集：每个连接处理一个请求后关闭。
+pub async fn serve(addr: impl ToSocketAddrs) -> Result<()> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .context("绑定指标端点地址失败")?;
+    tracing::info!("指标端点已启动: http://{}/metrics", listener.local_addr()?);
+
+    loop {
+        let (mut stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                tracing::warn!("接受指标请求失败: {}", e);
+                continue;
+            }
+        };
+        tokio::spawn(async move {
+            if let Err(e) = respond(&mut stream).await {
+                tracing::debug!("响应指标请求 {} 失败: {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn respond(stream: &mut tokio::net::TcpStream) -> Result<()> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST_LEN {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+
+    let line = request.split(|&b| b == b'\n').next().unwrap_or_default();
+    let mut parts = std::str::from_utf8(line)
+        .unwrap_or_default()
+        .split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => (
+            "200 OK",
+            "text/plain; version=0.0.4; charset=utf-8",
+            render(),
+        ),
+        (Some("GET"), Some("/status")) => ("200 OK", "application/json", render_status()),
+        _ => ("404 Not Found", "text/plain", "Not Found\n".to_string()),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Message;
+    use crate::pipe::CrossProcessPipe;
+    use std::time::Duration;
+
+    #[test]
+    fn test_render_pipe_metrics() {
+        let name = format!("mi7_test_metrics_{}", std::process::id());
+        let pipe: Arc<dyn DynamicPipe> =
+            Arc::new(CrossProcessPipe::<4, 256>::create(&name).unwrap());
+        pipe.send_blocking(Message::init("a".to_string()), Duration::from_secs(1))
+            .unwrap();
+        pipe.send_blocking(Message::init("b".to_string()), Duration::from_secs(1))
+            .unwrap();
+        pipe.receive_blocking(Duration::from_secs(1)).unwrap();
+
+        let text = render_pipes(&[pipe]);
+        assert!(text.contains("# TYPE mi7_pipe_sent_total counter"));
+        assert!(text.contains(&format!("mi7_pipe_sent_total{{pipe=\"{}\"}} 2", name)));
+        assert!(text.contains(&format!("mi7_pipe_received_total{{pipe=\"{}\"}} 1", name)));
+        assert!(text.contains(&format!(
+            "mi7_pipe_latency_seconds_count{{pipe=\"{}\"}} 1",
+            name
+        )));
+        assert!(text.contains(&format!(
+            "mi7_pipe_slots{{pipe=\"{}\",state=\"ready\"}} 1",
+            name
+        )));
+        assert_eq!(escape("a\"b\\c"), "a\\\"b\\\\c");
+    }
+}
@@ -0,0 +1,127 @@
+//! 管道的跨进程就绪通知
+//!
+//! 每个管道对应一个命名 FIFO，写者写入数据后向 FIFO 写 1 字节，
+//! 异步读者通过 tokio 的 `AsyncFd` 等待 FIFO 可读，而不是轮询共享内存。
+//! FIFO 以 `O_RDWR | O_NONBLOCK` 打开：没有读者时写入不会失败，缓冲区满时直接丢弃通知。
+//!
+//! 反方向另有一个空槽位 FIFO（[`PipeNotifier::open_space`]）：队列满时异步写者在其上等待，
+//! 读者释放槽位后写入 1 字节，每个字节只唤醒一个写者。
+
+use crate::error::Result;
+use std::ffi::CString;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::path::{Path, PathBuf};
+use tokio::io::Interest;
+use tokio::io::unix::AsyncFd;
+
+use crate::shm_sync;
+
+/// 基于命名 FIFO 的就绪通知器
+pub struct PipeNotifier {
+    fd: OwnedFd,
+}
+
+impl PipeNotifier {
+    /// 管道对应的 FIFO 路径
+    pub fn path_for(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("mi7_{}.notify", name.trim_start_matches('/')))
+    }
+
+    /// 管道对应的空槽位通知 FIFO 路径
+    pub fn space_path_for(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("mi7_{}.space", name.trim_start_matches('/')))
+    }
+
+    /// 打开（必要时创建）管道对应的 FIFO
+    pub fn open(name: &str) -> Result<Self> {
+        Self::open_path(&Self::path_for(name))
+    }
+
+    /// 打开（必要时创建）管道对应的空槽位通知 FIFO
+    pub fn open_space(name: &str) -> Result<Self> {
+        Self::open_path(&Self::space_path_for(name))
+    }
+
+    fn open_path(path: &Path) -> Result<Self> {
+        let cpath = CString::new(path.to_string_lossy().as_bytes())
+            .map_err(|_| anyhow::anyhow!("Failed to create CString from path"))?;
+
+        if unsafe { libc::mkfifo(cpath.as_ptr(), crate::access::mode()) } == -1 {
+            let errno = shm_sync::errno();
+            if errno != libc::EEXIST {
+                return Err(anyhow::anyhow!("mkfifo failed with errno: {}", errno).into());
+            }
+        }
+
+        let fd = unsafe { libc::open(cpath.as_ptr(), libc::O_RDWR | libc::O_NONBLOCK) };
+        if fd == -1 {
+            return Err(anyhow::anyhow!(
+                "open notify fifo failed with errno: {}",
+                shm_sync::errno()
+            )
+            .into());
+        }
+        if let Err(e) = crate::access::restrict(fd) {
+            unsafe { libc::close(fd) };
+            return Err(e);
+        }
+
+        Ok(Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+        })
+    }
+
+    /// 通知一个等待者（读者有新数据，或写者有空槽位）
+    pub fn notify(&self) {
+        let byte = 1u8;
+        // EAGAIN 表示缓冲区已满，读者必然会被唤醒，忽略即可
+        unsafe {
+            libc::write(
+                self.fd.as_raw_fd(),
+                &byte as *const u8 as *const libc::c_void,
+                1,
+            );
+        }
+    }
+
+    /// 消费一个通知，没有积压时返回 `false`
+    fn consume_one(&self) -> bool {
+        let mut byte = 0u8;
+        let n = unsafe {
+            libc::read(
+                self.fd.as_raw_fd(),
+                &mut byte as *mut u8 as *mut libc::c_void,
+                1,
+            )
+        };
+        n == 1
+    }
+
+    /// 异步等待一个通知
+    ///
+    /// 每条通知只唤醒一个等待者；每次调用复制一个独立的文件描述符注册到 tokio 反应器，
+    /// 允许多个任务并发等待
+    pub async fn wait(&self) -> Result<()> {
+        let fd = unsafe { libc::dup(self.fd.as_raw_fd()) };
+        if fd == -1 {
+            return Err(anyhow::anyhow!("dup failed with errno: {}", shm_sync::errno()).into());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let async_fd = AsyncFd::with_interest(fd, Interest::READABLE)?;
+
+        loop {
+            let mut guard = async_fd.readable().await?;
+            if self.consume_one() {
+                return Ok(());
+            }
+            // 通知已被其他等待者取走，继续等待下一次可读
+            guard.clear_ready();
+        }
+    }
+}
+
+impl AsRawFd for PipeNotifier {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
@@ -1,10 +1,37 @@
-use crate::shared_slot::SlotState;
-use crate::{Message, SharedSlotPipe};
+use crate::access::{self, AccessPolicy};
+use crate::auth::{self, MessageAuthenticator};
+use crate::codec::CodecKind;
+use crate::encryption::{self, Encryption, PayloadCipher};
+use crate::heap_pipe::HeapSlotPipe;
+use crate::integrity::Integrity;
+use crate::memory::{self, MemoryAdvice, MemoryUsage};
+use crate::notify::PipeNotifier;
+use crate::shared_box::SharedMemoryMailbox;
+use crate::shared_slot::{
+    DEFAULT_MAX_DELIVERY_ATTEMPTS, DynSharedSlotPipe, FullPolicy, LATENCY_BUCKETS, PipeMode,
+    PipeSecurity, SlotHandle, SlotMapping, SlotState, TokioIPCError,
+};
+use crate::shm_registry::SharedMemoryRegistry;
+use crate::shm_sync::MutexAttr;
+use crate::snapshot::QueueSnapshot;
+use crate::uds_pipe::UdsPipe;
+use crate::wait::{self, WaitStrategy};
+use crate::{LargePayload, Message};
 
-use anyhow::Result;
-use std::ptr::NonNull;
+use crate::error::Result;
+use anyhow::Context;
+use serde::Serialize;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixListener;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
 use std::str::FromStr;
-use std::sync::atomic::AtomicU32;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 /// 动态管道trait，定义所有管道类型的通用接口
 pub trait DynamicPipe: Send + Sync {
@@ -17,18 +44,68 @@ pub trait DynamicPipe: Send + Sync {
     /// 获取消息
     fn fetch(&self) -> Result<usize>;
 
+    /// 异步获取消息，等待写者通知而不阻塞运行时线程
+    fn fetch_async(&self) -> Pin<Box<dyn Future<Output = Result<usize>> + Send + '_>>;
+
+    /// 异步获取空槽位，队列满时等待读者释放槽位而不阻塞运行时线程
+    fn hold_async(&self) -> Pin<Box<dyn Future<Output = Result<usize>> + Send + '_>>;
+
+    /// 异步发送消息，队列满时等待空槽位
+    fn send_async(
+        &self,
+        message: Message,
+    ) -> Pin<Box<dyn Future<Output = Result<u64>> + Send + '_>> {
+        Box::pin(async move {
+            let index = self.hold_async().await?;
+            self.send(index, message)
+        })
+    }
+
     /// 接收消息
     fn receive(&self, index: usize) -> Result<Message>;
 
-    /// 设置槽位状态
-    fn set_slot_state(&self, index: usize, state: SlotState) -> Result<()>;
+    /// 以指定 request_id 发送消息，用于请求/响应关联
+    fn send_tagged(&self, index: usize, request_id: u64, message: Message) -> Result<u64>;
+
+    /// 接收消息及其 request_id
+    fn receive_tagged(&self, index: usize) -> Result<(u64, Message)>;
+
+    /// 阻塞发送消息，队列满时休眠直到有空槽位或超时，超时返回 [`PipeTimeout`]
+    fn send_blocking(&self, message: Message, timeout: Duration) -> Result<u64>;
+
+    /// 阻塞接收消息，队列空时休眠直到有新数据或超时，超时返回 [`PipeTimeout`]
+    fn receive_blocking(&self, timeout: Duration) -> Result<Message>;
+
+    /// 非阻塞发送：获取空槽位并写入消息，队列满时返回 `Ok(None)`
+    ///
+    /// 写入失败（例如消息超出槽位大小）时槽位被放弃，不会遗留被占用的槽位
+    fn try_send(&self, message: Message) -> Result<Option<u64>> {
+        match self.hold() {
+            Ok(index) => self.send(index, message).map(Some),
+            Err(e) if is_queue_full(&e) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// 发送消息，队列满时等待空槽位直到超时，超时返回 [`PipeTimeout::Send`]
+    fn send_timeout(&self, message: Message, timeout: Duration) -> Result<u64> {
+        self.send_blocking(message, timeout)
+    }
 
     /// 获取槽位状态
     fn get_slot_state(&self, index: usize) -> Result<SlotState>;
 
+    /// 槽位是否为无锁模式下写入失败被放弃的槽位（状态为 READY 但不含消息）
+    fn is_slot_abandoned(&self, _index: usize) -> bool {
+        false
+    }
+
     /// 获取管道状态
     fn status(&self) -> PipeStatus;
 
+    /// 获取写入到读取的延迟统计
+    fn metrics(&self) -> PipeMetrics;
+
     /// 获取配置信息
     fn config(&self) -> PipeConfig;
 
@@ -37,6 +114,48 @@ pub trait DynamicPipe: Send + Sync {
 
     /// 获取槽位大小
     fn slot_size(&self) -> usize;
+
+    /// 获取共享内存名称
+    fn name(&self) -> &str;
+
+    /// 删除共享内存名称，已连接的进程不受影响
+    fn unlink(&self) -> Result<()>;
+
+    /// 当前连接到该管道的进程 PID 列表
+    fn attached_processes(&self) -> Vec<u32>;
+
+    /// 回收租约超时的槽位，返回回收数量
+    fn reclaim_stuck(&self, timeout: Duration) -> usize;
+
+    /// 是否处于背压（占用达到高水位且尚未降到低水位）
+    fn is_backpressured(&self) -> bool;
+
+    /// 接收消息但暂不释放槽位，处理完成后调用 `ack`，失败时调用 `nack`
+    fn receive_unacked(&self, index: usize) -> Result<(u64, Message)>;
+
+    /// 确认消息处理完成并释放槽位
+    fn ack(&self, index: usize) -> Result<()>;
+
+    /// 消息处理失败：重新投递，超过最大投递次数时转入死信，返回是否已重新投递
+    fn nack(&self, index: usize) -> Result<bool>;
+
+    /// 返回下一条待读取消息的副本及其 request_id，不移动读指针也不改变槽位状态，队列为空时返回 `None`
+    fn peek(&self) -> Result<Option<(u64, Message)>>;
+
+    /// 返回指定槽位中消息的副本，槽位中没有已写入的消息时返回 `None`
+    fn peek_at(&self, index: usize) -> Result<Option<(u64, Message)>>;
+
+    /// 是否有可读取的消息
+    fn has_ready(&self) -> bool {
+        self.status().ready_count > 0
+    }
+
+    /// 就绪通知 FIFO 的文件描述符，写者发送后变为可读，供 [`PipeSet`](crate::PipeSet) 同时等待多条管道
+    ///
+    /// 没有跨进程通知的管道返回 `None`。
+    fn notify_fd(&self) -> Option<RawFd> {
+        None
+    }
 }
 
 /// 管道类型枚举，支持预定义和自定义配置
@@ -77,6 +196,19 @@ impl PipeType {
     pub fn supported_types() -> Vec<&'static str> {
         vec!["small", "default", "large"]
     }
+
+    /// 解析 `custom(100x4096)` 或 `100x4096` 形式的自定义类型
+    fn parse_custom(s: &str) -> Option<Self> {
+        let inner = s
+            .strip_prefix("custom(")
+            .and_then(|rest| rest.strip_suffix(')'))
+            .unwrap_or(s);
+        let (capacity, slot_size) = inner.split_once('x')?;
+        Some(PipeType::Custom(
+            capacity.trim().parse().ok()?,
+            slot_size.trim().parse().ok()?,
+        ))
+    }
 }
 
 impl FromStr for PipeType {
@@ -87,11 +219,13 @@ impl FromStr for PipeType {
             "small" => Ok(PipeType::Small),
             "default" => Ok(PipeType::Default),
             "large" => Ok(PipeType::Large),
-            _ => Err(format!(
-                "不支持的管道类型: '{}'. 支持的类型: {:?}",
-                s,
-                PipeType::supported_types()
-            )),
+            other => Self::parse_custom(other).ok_or_else(|| {
+                format!(
+                    "不支持的管道类型: '{}'. 支持的类型: {:?} 或 custom(容量x槽位大小)",
+                    s,
+                    PipeType::supported_types()
+                )
+            }),
         }
     }
 }
@@ -109,7 +243,7 @@ impl std::fmt::Display for PipeType {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct PipeStatus {
     /// 队列槽位总数量
     pub capacity: usize,
@@ -131,6 +265,159 @@ pub struct PipeStatus {
     pub ready_count: usize,
     /// 已使用的槽位数量（非 EMPTY 状态的槽位）
     pub used_count: usize,
+    /// 并发模式
+    pub mode: PipeMode,
+    /// 编解码方式
+    pub codec: CodecKind,
+    /// 校验算法
+    pub integrity: Integrity,
+    /// 因租约超时被回收的槽位累计数量
+    pub reclaimed_count: u64,
+    /// 是否处于背压
+    pub backpressured: bool,
+    /// 接收时因过期被丢弃的消息累计数量
+    pub expired_count: u64,
+    /// 重新投递的消息累计数量
+    pub redelivered_count: u64,
+    /// 转入死信的消息累计数量
+    pub dead_lettered_count: u64,
+    /// 写入的消息累计数量
+    pub sent_count: u64,
+    /// 读取并释放的消息累计数量
+    pub received_count: u64,
+    /// 加锁时锁已被占用的累计次数
+    pub lock_contended_count: u64,
+    /// 覆盖模式下被丢弃的最早消息累计数量
+    pub dropped_count: u64,
+}
+
+impl PipeStatus {
+    /// 汇总另一条管道的状态：槽位计数与累计计数相加，任一管道背压即为背压
+    ///
+    /// 读写指针没有汇总意义，置为 0；并发模式等创建参数保留本条的。
+    pub fn merge(&mut self, other: &PipeStatus) {
+        self.write_pointer = 0;
+        self.read_pointer = 0;
+        self.capacity += other.capacity;
+        self.empty_count += other.empty_count;
+        self.writing_count += other.writing_count;
+        self.in_progress_count += other.in_progress_count;
+        self.reading_count += other.reading_count;
+        self.ready_count += other.ready_count;
+        self.used_count += other.used_count;
+        self.reclaimed_count += other.reclaimed_count;
+        self.backpressured |= other.backpressured;
+        self.expired_count += other.expired_count;
+        self.redelivered_count += other.redelivered_count;
+        self.dead_lettered_count += other.dead_lettered_count;
+        self.sent_count += other.sent_count;
+        self.received_count += other.received_count;
+        self.lock_contended_count += other.lock_contended_count;
+        self.dropped_count += other.dropped_count;
+    }
+}
+
+/// 单个槽位的状态，用于排查卡住的槽位
+#[derive(Debug, Clone)]
+pub struct SlotInfo {
+    pub index: usize,
+    /// 槽位状态，未知值为 `None`（共享内存被损坏）
+    pub state: Option<SlotState>,
+    pub request_id: u64,
+    /// 数据大小（字节），不含放弃标志
+    pub data_size: usize,
+    /// 无锁模式下写入失败被放弃的槽位：状态为 READY 但不含消息，消费者会跳过
+    pub abandoned: bool,
+    /// 已失败的投递次数
+    pub delivery_attempts: u32,
+    /// 被写者或读者持有的时长，未被持有时为 `None`
+    pub leased_for: Option<Duration>,
+}
+
+impl SlotInfo {
+    /// 槽位中是否有待读取的消息（包括零长度消息，不包括被放弃的槽位）
+    pub fn is_pending(&self) -> bool {
+        self.state == Some(SlotState::READY) && !self.abandoned
+    }
+}
+
+/// 消息从写入完成到被读取的延迟统计，由所有连接方共同累计
+///
+/// 延迟按 2 的幂次微秒分桶，分位数取所在桶的上界，误差不超过一倍。
+/// 重新投递的消息只在首次读取时计入。
+#[derive(Debug, Clone, Default)]
+pub struct PipeMetrics {
+    /// 计入统计的消息数量
+    pub count: u64,
+    /// 所有延迟之和
+    pub sum: Duration,
+    /// 平均延迟
+    pub mean: Duration,
+    /// 中位数延迟
+    pub p50: Duration,
+    /// 95 分位延迟
+    pub p95: Duration,
+    /// 99 分位延迟
+    pub p99: Duration,
+    /// 非累计的各桶计数：(桶上界, 落入该桶的消息数量)
+    pub buckets: Vec<(Duration, u64)>,
+    /// 最近相邻写入间隔的滑动平均，没有统计时为 0
+    pub arrival_gap: Duration,
+    /// [`WaitStrategy::Adaptive`] 按当前到达速率选用的轮询间隔，0 表示忙等
+    pub poll_interval: Duration,
+}
+
+impl PipeMetrics {
+    /// 由直方图各桶计数与延迟总和（纳秒）计算统计
+    pub fn from_histogram(counts: &[u64; LATENCY_BUCKETS], sum_nanos: u64) -> Self {
+        let buckets: Vec<(Duration, u64)> = counts
+            .iter()
+            .enumerate()
+            .map(|(i, &count)| (Duration::from_micros(2u64 << i), count))
+            .collect();
+        let count = counts.iter().sum();
+        let mut metrics = Self {
+            count,
+            sum: Duration::from_nanos(sum_nanos),
+            mean: Duration::from_nanos(sum_nanos.checked_div(count).unwrap_or(0)),
+            buckets,
+            ..Self::default()
+        };
+        metrics.p50 = metrics.percentile(0.50);
+        metrics.p95 = metrics.percentile(0.95);
+        metrics.p99 = metrics.percentile(0.99);
+        metrics
+    }
+
+    /// 估计分位数（`q` 取 0.0 ~ 1.0），没有数据时为 0
+    pub fn percentile(&self, q: f64) -> Duration {
+        let rank = ((self.count as f64 * q).ceil() as u64).max(1);
+        let mut seen = 0;
+        for &(upper, count) in &self.buckets {
+            seen += count;
+            if seen >= rank {
+                return upper;
+            }
+        }
+        Duration::ZERO
+    }
+}
+
+/// 读取到的消息已超过其过期时间，槽位已释放、消息被丢弃
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("消息 {request_id} 已过期 (expires_at={expires_at})")]
+pub struct MessageExpired {
+    pub request_id: u64,
+    pub expires_at: u64,
+}
+
+/// 阻塞收发在超时前没有等到空槽位 / 消息
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum PipeTimeout {
+    #[error("等待空槽位超时: {0:?}")]
+    Send(Duration),
+    #[error("等待消息超时: {0:?}")]
+    Receive(Duration),
 }
 
 /// 队列配置结构体
@@ -140,6 +427,28 @@ pub struct PipeConfig {
     pub capacity: usize,
     /// 每个槽位的大小（字节）
     pub slot_size: usize,
+    /// 并发模式，仅在创建管道时生效
+    pub mode: PipeMode,
+    /// 编解码方式，仅在创建管道时生效，连接方从共享内存头部读取
+    pub codec: CodecKind,
+    /// 校验算法，仅在创建管道时生效，连接方从共享内存头部读取
+    pub integrity: Integrity,
+    /// 背压高水位（非 EMPTY 槽位数量），0 表示关闭
+    pub high_watermark: usize,
+    /// 背压低水位，背压中占用降到该数量及以下时解除
+    pub low_watermark: usize,
+    /// 是否按到达顺序分配空槽位（票号排队），所有连接方共享
+    pub fair: bool,
+    /// 投递失败达到该次数后转入死信，0 表示不限，所有连接方共享
+    pub max_delivery_attempts: u32,
+    /// 队列满时写者的处理方式，所有连接方共享
+    pub full_policy: FullPolicy,
+    /// 读写互斥锁的创建属性（如优先级继承），仅在创建管道时生效，所有连接方共享
+    pub mutex_attr: MutexAttr,
+    /// 队列空或满时的等待方式，只影响本句柄
+    pub wait_strategy: WaitStrategy,
+    /// 本句柄映射的内存建议（锁定、预读、排空后释放），只影响本进程
+    pub memory: MemoryAdvice,
 }
 
 impl PipeConfig {
@@ -148,37 +457,92 @@ impl PipeConfig {
         Self {
             capacity,
             slot_size,
-        }
-    }
-
-    /// 默认配置：100个槽位，每个4KB
-    pub fn default() -> Self {
-        Self {
-            capacity: 100,
-            slot_size: 4096,
+            mode: PipeMode::Locked,
+            codec: CodecKind::Bincode,
+            integrity: Integrity::default(),
+            high_watermark: 0,
+            low_watermark: 0,
+            fair: false,
+            max_delivery_attempts: DEFAULT_MAX_DELIVERY_ATTEMPTS,
+            full_policy: FullPolicy::Block,
+            mutex_attr: MutexAttr::default(),
+            wait_strategy: wait::installed(),
+            memory: memory::installed(),
         }
     }
 
     /// 小型队列配置：10个槽位，每个1KB
     pub fn small() -> Self {
-        Self {
-            capacity: 10,
-            slot_size: 1024,
-        }
+        Self::new(10, 1024)
     }
 
     /// 大型队列配置：1000个槽位，每个8KB
     pub fn large() -> Self {
-        Self {
-            capacity: 1000,
-            slot_size: 8192,
-        }
+        Self::new(1000, 8192)
     }
 
-    /// 从字符串创建配置
-    pub fn from_str(s: &str) -> Result<Self, String> {
-        let pipe_type = PipeType::from_str(s)?;
-        Ok(pipe_type.config())
+    /// 设置并发模式
+    pub fn with_mode(mut self, mode: PipeMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// 设置编解码方式
+    pub fn with_codec(mut self, codec: CodecKind) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// 设置校验算法
+    pub fn with_integrity(mut self, integrity: Integrity) -> Self {
+        self.integrity = integrity;
+        self
+    }
+
+    /// 设置背压的高低水位
+    pub fn with_watermarks(mut self, high: usize, low: usize) -> Self {
+        self.high_watermark = high;
+        self.low_watermark = low;
+        self
+    }
+
+    /// 设置是否按到达顺序分配空槽位
+    pub fn with_fairness(mut self, fair: bool) -> Self {
+        self.fair = fair;
+        self
+    }
+
+    /// 设置最大投递失败次数
+    pub fn with_max_delivery_attempts(mut self, attempts: u32) -> Self {
+        self.max_delivery_attempts = attempts;
+        self
+    }
+
+    /// 设置队列满时写者的处理方式
+    pub fn with_full_policy(mut self, policy: FullPolicy) -> Self {
+        self.full_policy = policy;
+        self
+    }
+
+    /// 设置读写互斥锁的创建属性
+    ///
+    /// 低优先级的消费者持有 read_mutex 时会阻塞高优先级的一方，
+    /// 启用 [`MutexAttr::priority_inherit`] 后持有者临时继承等待者的优先级。
+    pub fn with_mutex_attr(mut self, mutex_attr: MutexAttr) -> Self {
+        self.mutex_attr = mutex_attr;
+        self
+    }
+
+    /// 设置队列空或满时的等待方式，默认为本进程安装的等待方式（见 [`crate::wait`]）
+    pub fn with_wait_strategy(mut self, wait_strategy: WaitStrategy) -> Self {
+        self.wait_strategy = wait_strategy;
+        self
+    }
+
+    /// 设置本句柄映射的内存建议，默认为本进程安装的建议（见 [`crate::memory`]）
+    pub fn with_memory_advice(mut self, memory: MemoryAdvice) -> Self {
+        self.memory = memory;
+        self
     }
 
     /// 验证配置是否有效
@@ -195,6 +559,9 @@ impl PipeConfig {
         if self.slot_size > 1024 * 1024 {
             return Err("槽大小过大，最大支持1MB".to_string());
         }
+        if self.high_watermark > self.capacity || self.low_watermark > self.high_watermark {
+            return Err("水位需满足 低水位 <= 高水位 <= 容量".to_string());
+        }
         Ok(())
     }
 
@@ -275,424 +642,3782 @@ impl PipeConfig {
     }
 }
 
-/// 跨进程Slot包装器，提供类似CrossProcessSlot的API
-/// 支持配置化的队列大小和槽位大小
-pub struct CrossProcessPipe<const CAPACITY: usize, const SLOT_SIZE: usize> {
-    pipe: NonNull<SharedSlotPipe<CAPACITY, SLOT_SIZE>>,
-    _name: String,
-    config: PipeConfig,
+impl Default for PipeConfig {
+    /// 默认配置：100个槽位，每个4KB
+    fn default() -> Self {
+        Self::new(100, 4096)
+    }
 }
 
-unsafe impl<const CAPACITY: usize, const SLOT_SIZE: usize> Send
-    for CrossProcessPipe<CAPACITY, SLOT_SIZE>
-{
+impl FromStr for PipeConfig {
+    type Err = String;
+
+    /// 从字符串创建配置
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let pipe_type = PipeType::from_str(s)?;
+        Ok(pipe_type.config())
+    }
 }
-unsafe impl<const CAPACITY: usize, const SLOT_SIZE: usize> Sync
-    for CrossProcessPipe<CAPACITY, SLOT_SIZE>
-{
+
+/// 跨进程Slot包装器，提供类似CrossProcessSlot的API
+/// 容量与槽位大小在运行时确定，连接时从共享内存头部读取
+///
+/// 通过 `create` 得到的实例是共享内存段的持有者，`Drop` 时自动 `shm_unlink`；
+/// 需要让段在创建进程退出后继续存在时调用 [`DynCrossProcessPipe::persist`]。
+pub struct DynCrossProcessPipe {
+    pipe: SlotMapping,
+    name: String,
+    config: PipeConfig,
+    owner: bool,
+    attach_index: Option<usize>,
+    notifier: Option<PipeNotifier>,
+    space_notifier: Option<PipeNotifier>,
+    /// 本句柄上异步等待空槽位的写者按到达顺序排队
+    space_queue: tokio::sync::Mutex<()>,
+    mailbox: Option<Arc<SharedMemoryMailbox>>,
+    dead_letter: Option<Arc<dyn DynamicPipe>>,
+    backpressure_callbacks: Mutex<Vec<BackpressureCallback>>,
+    backpressure_seen: AtomicBool,
+    cipher: Option<Arc<PayloadCipher>>,
+    handshake_server: Option<HandshakeServer>,
 }
 
-impl<const CAPACITY: usize, const SLOT_SIZE: usize> CrossProcessPipe<CAPACITY, SLOT_SIZE> {
-    /// 创建新的队列
-    pub fn create(name: &str) -> Result<Self> {
-        unsafe {
-            let pipe_ptr = SharedSlotPipe::<CAPACITY, SLOT_SIZE>::open(name, true)
-                .map_err(|e| anyhow::anyhow!("创建共享管道失败: {:?}", e))?;
+/// 背压状态变化回调，参数为新的背压状态
+type BackpressureCallback = Box<dyn Fn(bool) + Send + Sync>;
 
-            Ok(Self {
-                pipe: NonNull::new_unchecked(pipe_ptr),
-                _name: name.to_string(),
-                config: PipeConfig::new(CAPACITY, SLOT_SIZE),
-            })
-        }
+/// 共享内存中异步等待空槽位的写者登记，drop 时注销
+struct SpaceWaiter<'a>(&'a AtomicU64);
+
+impl<'a> SpaceWaiter<'a> {
+    fn register(waiters: &'a AtomicU64) -> Self {
+        waiters.fetch_add(1, Ordering::SeqCst);
+        Self(waiters)
     }
+}
 
-    /// 使用配置创建新的队列
-    pub fn create_with_config(name: &str, _config: PipeConfig) -> Result<Self> {
-        // 注意：配置参数在编译时确定，这里主要用于验证
-        if _config.capacity != CAPACITY || _config.slot_size != SLOT_SIZE {
-            return Err(anyhow::anyhow!(
-                "配置不匹配：期望 capacity={}, slot_size={}，实际 capacity={}, slot_size={}",
-                CAPACITY,
-                SLOT_SIZE,
-                _config.capacity,
-                _config.slot_size
-            ));
-        }
-        Self::create(name)
+impl Drop for SpaceWaiter<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
     }
+}
 
-    /// 连接到现有队列
-    pub fn connect(name: &str) -> Result<Self> {
-        unsafe {
-            let pipe_ptr = SharedSlotPipe::<CAPACITY, SLOT_SIZE>::open(name, false)
-                .map_err(|e| anyhow::anyhow!("连接到共享管道失败: {:?}", e))?;
+/// 被固定的读者槽位，见 [`DynCrossProcessPipe::fetch_pinned`]
+///
+/// 解引用为槽位数据（与 [`DynCrossProcessPipe::receive_with`] 看到的字节相同），直接指向共享内存；
+/// 加密管道中是解密后的副本。drop 时槽位才回到 EMPTY，持有期间其他进程不会改写该槽位。
+/// 持有时间超过 `reclaim_stuck` 的超时会被回收，应尽快处理完成。
+pub struct PinnedSlot<'a> {
+    pipe: &'a DynCrossProcessPipe,
+    index: usize,
+    request_id: u64,
+    plain: Option<Vec<u8>>,
+}
 
-            Ok(Self {
-                pipe: NonNull::new_unchecked(pipe_ptr),
-                _name: name.to_string(),
-                config: PipeConfig::new(CAPACITY, SLOT_SIZE),
-            })
-        }
+impl PinnedSlot<'_> {
+    /// 槽位下标
+    pub fn index(&self) -> usize {
+        self.index
     }
 
-    /// 获取 空slot
-    pub fn hold(&self) -> Result<usize> {
-        unsafe {
-            let pipe = self.pipe.as_ptr();
-            match (*pipe).hold() {
-                Some(index) => Ok(index),
-                None => Err(anyhow::anyhow!("队列已满，无法获取空槽位")),
-            }
-        }
-    }
-    /// 发送消息
-    /// 将数据写入slot
-    pub fn send(&self, index: usize, message: Message) -> Result<u64> {
-        unsafe {
-            let pipe = self.pipe.as_ptr();
-            match (*pipe).write(index, &message) {
-                Ok(request_id) => Ok(request_id),
-                Err(err) => Err(anyhow::anyhow!("写入消息失败: {:?}", err)),
-            }
-        }
+    /// 消息的 request_id
+    pub fn request_id(&self) -> u64 {
+        self.request_id
     }
+}
 
-    /// 接收消息
-    pub fn fetch(&self) -> Result<usize> {
-        unsafe {
-            let pipe = self.pipe.as_ptr();
-            match (*pipe).fetch() {
-                Some(index) => Ok(index),
-                None => Err(anyhow::anyhow!("队列为空，无法获取消息")),
-            }
-        }
-    }
+impl Deref for PinnedSlot<'_> {
+    type Target = [u8];
 
-    /// 接收消息
-    pub fn receive(&self, index: usize) -> Result<Message> {
-        unsafe {
-            let pipe = self.pipe.as_ptr();
-            match (*pipe).read::<Message>(index) {
-                Ok(Some((_, message))) => Ok(message),
-                Ok(None) => Err(anyhow::anyhow!("槽位为空，无法读取消息")),
-                Err(err) => Err(anyhow::anyhow!("读取消息失败: {:?}", err)),
-            }
+    fn deref(&self) -> &[u8] {
+        match &self.plain {
+            Some(plain) => plain,
+            None => self.pipe.pipe.held_data(self.index),
         }
     }
+}
 
-    /// 尝试接收消息（非阻塞，返回Option）
-    pub fn try_receive(&self, index: usize) -> Result<Option<Message>> {
-        unsafe {
-            let pipe = self.pipe.as_ptr();
-            match (*pipe).read::<Message>(index) {
-                Ok(Some((_, message))) => Ok(Some(message)),
-                Ok(None) => Ok(None),
-                Err(err) => Err(anyhow::anyhow!("尝试读取消息失败: {:?}", err)),
-            }
+impl Drop for PinnedSlot<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = self.pipe.ack(self.index) {
+            tracing::warn!(
+                "释放管道 {} 的槽位 {} 失败: {}",
+                self.pipe.name,
+                self.index,
+                e
+            );
         }
     }
+}
 
-    /// 获取队列状态
-    pub fn status(&self) -> PipeStatus {
-        unsafe {
-            let pipe = self.pipe.as_ptr();
-
-            // 获取写指针和读指针
-            let write_pointer = (*pipe).write_pointer;
-            let read_pointer = (*pipe).read_pointer;
-
-            // 统计各种状态的槽位数量
-            let mut empty_count = 0;
-            let mut writing_count = 0;
-            let mut in_progress_count = 0;
-            let mut reading_count = 0;
-            let mut ready_count = 0;
-
-            // 遍历所有槽位统计状态
-            for i in 0..CAPACITY {
-                match (*pipe).slots[i]
-                    .state
-                    .load(std::sync::atomic::Ordering::Acquire)
-                {
-                    x if x == SlotState::EMPTY as u32 => empty_count += 1,
-                    x if x == SlotState::WRITING as u32 => writing_count += 1,
-                    x if x == SlotState::INPROGRESS as u32 => in_progress_count += 1,
-                    x if x == SlotState::READING as u32 => reading_count += 1,
-                    x if x == SlotState::READY as u32 => ready_count += 1,
-                    _ => {} // 未知状态，忽略
-                }
-            }
+/// 异步等待通知的兜底超时，防止通知丢失（FIFO 缓冲区满）时永久等待
+const NOTIFY_FALLBACK: Duration = Duration::from_millis(500);
 
-            let used_count = CAPACITY - empty_count;
+/// 转发到死信管道时等待空槽位的时长
+pub(crate) const DEAD_LETTER_TIMEOUT: Duration = Duration::from_millis(100);
 
-            PipeStatus {
-                capacity: CAPACITY,
-                slot_size: SLOT_SIZE,
-                write_pointer,
-                read_pointer,
-                empty_count,
-                writing_count,
-                in_progress_count,
-                reading_count,
-                ready_count,
-                used_count,
-            }
+/// 创建或连接管道时使用的安全设置，默认不加密、不认证、不握手
+#[derive(Clone, Default)]
+pub struct SecurityOptions {
+    /// 负载加密器，见 [`crate::encryption`]
+    pub cipher: Option<Arc<PayloadCipher>>,
+    /// 消息认证器，见 [`crate::auth`]
+    pub authenticator: Option<&'static MessageAuthenticator>,
+    /// 访问控制策略，见 [`crate::access`]
+    pub access: Arc<AccessPolicy>,
+}
+
+impl SecurityOptions {
+    /// 本进程设置的负载加密器、消息认证器与访问控制策略
+    pub fn installed() -> Self {
+        Self {
+            cipher: encryption::installed(),
+            authenticator: auth::installed(),
+            access: access::policy(),
         }
     }
 
-    /// 获取队列配置
-    pub fn config(&self) -> PipeConfig {
-        self.config
+    /// 创建时写入头部的安全设置
+    fn header(&self) -> PipeSecurity {
+        PipeSecurity::new(
+            self.cipher.as_deref(),
+            self.authenticator,
+            self.access.handshake,
+        )
     }
+}
 
-    /// 获取队列容量
-    pub fn capacity(&self) -> usize {
-        CAPACITY
+/// 连接方等待创建者握手授权的时长
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// 创建者在握手套接字上处理连接请求的后台线程，Drop 时停止线程并删除套接字文件
+struct HandshakeServer {
+    path: PathBuf,
+    stop: Arc<AtomicBool>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl HandshakeServer {
+    /// 没有连接请求时检查停止标志的间隔
+    const POLL: Duration = Duration::from_millis(50);
+
+    /// 监听管道的握手套接字；监听失败时记录日志，连接方因无法握手而连接失败
+    fn spawn(name: &str, policy: Arc<AccessPolicy>) -> Self {
+        let path = access::socket_path(name);
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = match Self::listen(&path, &policy) {
+            Ok(listener) => {
+                let stop = Arc::clone(&stop);
+                let pipe_name = name.to_string();
+                std::thread::Builder::new()
+                    .name("mi7-handshake".to_string())
+                    .spawn(move || Self::serve(listener, &pipe_name, &policy, &stop))
+                    .inspect_err(|e| tracing::error!("启动管道 {} 的握手线程失败: {}", name, e))
+                    .ok()
+            }
+            Err(e) => {
+                tracing::error!("监听管道 {} 的握手套接字失败: {:#}", name, e);
+                None
+            }
+        };
+        Self { path, stop, thread }
     }
 
-    /// 获取槽位大小
-    pub fn slot_size(&self) -> usize {
-        SLOT_SIZE
+    /// 绑定握手套接字，按策略设置权限与属组：连接套接字需要写权限
+    fn listen(path: &Path, policy: &AccessPolicy) -> anyhow::Result<UnixListener> {
+        // 同名管道由本进程创建，之前遗留的套接字文件已无人监听
+        let _ = std::fs::remove_file(path);
+        let listener = UnixListener::bind(path)?;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(policy.mode))?;
+        if let Some(gid) = policy.group {
+            std::os::unix::fs::chown(path, None, Some(gid))?;
+        }
+        listener.set_nonblocking(true)?;
+        Ok(listener)
     }
 
-    /// 设置槽位状态（用于调度者）
-    pub fn set_slot_state(&self, index: usize, state: SlotState) -> Result<()> {
-        unsafe {
-            let queue = self.pipe.as_ptr();
-            (*queue)
-                .set_slot_state(index, state)
-                .map_err(|e| anyhow::anyhow!("{:?}", e))
+    fn serve(listener: UnixListener, name: &str, policy: &AccessPolicy, stop: &AtomicBool) {
+        while !stop.load(Ordering::Acquire) {
+            let mut stream = match listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    std::thread::sleep(Self::POLL);
+                    continue;
+                }
+                Err(e) => {
+                    tracing::warn!("管道 {} 接受握手连接失败: {}", name, e);
+                    std::thread::sleep(Self::POLL);
+                    continue;
+                }
+            };
+            // 连接方不读取结果时不阻塞握手线程
+            let _ = stream.set_nonblocking(false);
+            let _ = stream.set_write_timeout(Some(HANDSHAKE_TIMEOUT));
+            match access::serve_access(&mut stream, |peer| policy.authorize(peer)) {
+                Ok((peer, granted)) => tracing::info!(
+                    "管道 {} {}进程 {} (uid={}, gid={}) 的连接",
+                    name,
+                    if granted { "允许" } else { "拒绝" },
+                    peer.pid,
+                    peer.uid,
+                    peer.gid
+                ),
+                Err(e) => tracing::warn!("管道 {} 处理握手失败: {}", name, e),
+            }
         }
     }
+}
 
-    /// 获取槽位状态
-    pub fn get_slot_state(&self, index: usize) -> Result<SlotState> {
-        unsafe {
-            let queue = self.pipe.as_ptr();
-            (*queue)
-                .get_slot_state(index)
-                .map_err(|e| anyhow::anyhow!("{:?}", e))
+impl Drop for HandshakeServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
         }
+        let _ = std::fs::remove_file(&self.path);
     }
 }
 
-/// 为CrossProcessPipe实现DynamicPipe trait
-impl<const CAPACITY: usize, const SLOT_SIZE: usize> DynamicPipe
-    for CrossProcessPipe<CAPACITY, SLOT_SIZE>
-{
-    fn hold(&self) -> Result<usize> {
-        self.hold()
+/// 是否为队列已满（[`TokioIPCError::QueueFull`]）导致的失败
+pub(crate) fn is_queue_full(err: &crate::Error) -> bool {
+    matches!(
+        err.downcast_ref::<TokioIPCError>(),
+        Some(TokioIPCError::QueueFull)
+    )
+}
+
+/// 包装读取失败的错误，保留原错误以便调用方识别 `CorruptedData` 等
+fn read_error(err: crate::Error) -> crate::Error {
+    err.context("读取消息失败")
+}
+
+impl DynCrossProcessPipe {
+    /// 使用配置创建新的队列
+    ///
+    /// 容量、槽位大小、并发模式、编解码方式与校验算法写入共享内存头部，连接方据此保持一致；
+    /// 按 [`SecurityOptions::installed`] 设置安全选项：本进程设置了负载加密器时管道自动加密，
+    /// 设置了消息认证器时要求认证，访问控制策略要求握手时连接方需经本进程授权
+    pub fn create_with_config(name: &str, config: PipeConfig) -> Result<Self> {
+        Self::create_secured(name, config, SecurityOptions::installed())
     }
 
-    fn send(&self, index: usize, message: Message) -> Result<u64> {
-        self.send(index, message)
+    /// 使用指定的负载加密器创建新的队列，`None` 表示不加密
+    ///
+    /// 槽位与寄存箱 box 中只保存密文，每条消息占用的槽位空间增加 [`PayloadCipher::OVERHEAD`] 字节
+    pub fn create_with_cipher(
+        name: &str,
+        config: PipeConfig,
+        cipher: Option<Arc<PayloadCipher>>,
+    ) -> Result<Self> {
+        let security = SecurityOptions {
+            cipher,
+            ..SecurityOptions::installed()
+        };
+        Self::create_secured(name, config, security)
     }
 
-    fn fetch(&self) -> Result<usize> {
-        self.fetch()
+    /// 使用指定的安全设置创建新的队列
+    ///
+    /// 访问控制策略要求握手时在后台线程中处理连接方的授权请求，直到本实例 Drop。
+    pub fn create_secured(
+        name: &str,
+        config: PipeConfig,
+        security: SecurityOptions,
+    ) -> Result<Self> {
+        if security.cipher.is_some() && config.slot_size <= PayloadCipher::OVERHEAD {
+            return Err(anyhow::anyhow!(
+                "加密管道的槽位大小必须大于 {} 字节",
+                PayloadCipher::OVERHEAD
+            )
+            .into());
+        }
+        let pipe = unsafe {
+            DynSharedSlotPipe::create_secured(
+                name,
+                config.capacity,
+                config.slot_size,
+                config.mode,
+                config.codec,
+                config.integrity,
+                config.mutex_attr,
+                security.header(),
+            )
+            .context("创建共享管道失败")?
+            .with_authenticator(security.authenticator)
+            .with_wait_strategy(config.wait_strategy)
+        };
+        pipe.set_watermarks(config.high_watermark, config.low_watermark);
+        pipe.set_fair(config.fair);
+        pipe.set_full_policy(config.full_policy);
+        pipe.set_max_delivery_attempts(config.max_delivery_attempts);
+
+        SharedMemoryRegistry::register(name);
+        let pipe = Self {
+            pipe: unsafe { SlotMapping::new(pipe) },
+            name: name.to_string(),
+            config,
+            owner: true,
+            attach_index: Self::attach(&pipe, name),
+            notifier: Self::open_notifier(name, PipeNotifier::open),
+            space_notifier: Self::open_notifier(name, PipeNotifier::open_space),
+            space_queue: tokio::sync::Mutex::new(()),
+            mailbox: None,
+            dead_letter: None,
+            backpressure_callbacks: Mutex::new(Vec::new()),
+            backpressure_seen: AtomicBool::new(false),
+            cipher: security.cipher,
+            handshake_server: security
+                .access
+                .handshake
+                .then(|| HandshakeServer::spawn(name, security.access)),
+        };
+        pipe.advise(MemoryAdvice::default());
+        Ok(pipe)
     }
 
-    fn receive(&self, index: usize) -> Result<Message> {
-        self.receive(index)
+    /// 连接到现有队列，容量与槽位大小以共享内存头部记录的为准
+    ///
+    /// 管道已加密时使用本进程设置的负载加密器，未设置或密钥标识不符时连接失败；
+    /// 管道要求握手时先经创建者授权，被拒绝或 [`HANDSHAKE_TIMEOUT`] 内未获答复时连接失败
+    pub fn connect(name: &str) -> Result<Self> {
+        Self::connect_secured(name, SecurityOptions::installed())
     }
 
-    fn set_slot_state(&self, index: usize, state: SlotState) -> Result<()> {
-        self.set_slot_state(index, state)
+    /// 使用指定的负载加密器连接到现有队列
+    pub fn connect_with_cipher(name: &str, cipher: Option<Arc<PayloadCipher>>) -> Result<Self> {
+        let security = SecurityOptions {
+            cipher,
+            ..SecurityOptions::installed()
+        };
+        Self::connect_secured(name, security)
     }
 
-    fn get_slot_state(&self, index: usize) -> Result<SlotState> {
-        self.get_slot_state(index)
+    /// 使用指定的安全设置连接到现有队列
+    pub fn connect_secured(name: &str, security: SecurityOptions) -> Result<Self> {
+        let pipe =
+            unsafe { DynSharedSlotPipe::connect(name).context("连接到共享管道失败")? };
+        Self::attached(pipe, name, security, false)
     }
 
-    fn status(&self) -> PipeStatus {
-        self.status()
+    /// 连接到现有队列，头部记录的容量或槽位大小与期望不一致时返回 [`LayoutMismatch`]
+    ///
+    /// [`LayoutMismatch`]: crate::shared_slot::LayoutMismatch
+    pub fn connect_with_layout(name: &str, capacity: usize, slot_size: usize) -> Result<Self> {
+        let pipe = unsafe {
+            DynSharedSlotPipe::connect_with_layout(name, capacity, slot_size)
+                .context("连接到共享管道失败")?
+        };
+        Self::attached(pipe, name, SecurityOptions::installed(), false)
     }
 
-    fn config(&self) -> PipeConfig {
-        self.config()
+    /// 创建（或恢复）映射普通文件的持久化队列
+    ///
+    /// 文件已存在时校验布局，并将完整写入但尚未被消费完成的消息重新排队，
+    /// 守护进程重启不会丢失在途消息。`name` 仅用于日志与通知 FIFO；
+    /// 其他进程通过 [`DynCrossProcessPipe::connect_persistent`] 连接，Drop 时不会删除文件。
+    pub fn create_persistent(
+        name: &str,
+        path: impl AsRef<Path>,
+        config: PipeConfig,
+    ) -> Result<Self> {
+        config
+            .validate()
+            .map_err(|e| anyhow::anyhow!("配置无效: {}", e))?;
+
+        let path = path.as_ref();
+        let security = SecurityOptions::installed();
+        let (pipe, recovered) = unsafe {
+            DynSharedSlotPipe::open_file(
+                path,
+                config.capacity,
+                config.slot_size,
+                config.mode,
+                config.codec,
+                config.integrity,
+                config.mutex_attr,
+                security.header(),
+            )
+            .context("打开持久化管道失败")?
+        };
+        pipe.set_watermarks(config.high_watermark, config.low_watermark);
+        pipe.set_fair(config.fair);
+        pipe.set_full_policy(config.full_policy);
+        pipe.set_max_delivery_attempts(config.max_delivery_attempts);
+        if recovered > 0 {
+            tracing::info!(
+                "持久化管道 {} 已从 {} 恢复 {} 条消息",
+                name,
+                path.display(),
+                recovered
+            );
+        }
+        Ok(Self::attached(
+            pipe.with_wait_strategy(config.wait_strategy),
+            name,
+            security,
+            true,
+        )?
+        .with_memory_advice(config.memory))
     }
 
-    fn capacity(&self) -> usize {
-        self.capacity()
+    /// 连接到持久化队列，容量与槽位大小以文件头部记录的为准
+    pub fn connect_persistent(name: &str, path: impl AsRef<Path>) -> Result<Self> {
+        let pipe = unsafe {
+            DynSharedSlotPipe::connect_file(path.as_ref()).context("连接到持久化管道失败")?
+        };
+        Self::attached(pipe, name, SecurityOptions::installed(), false)
     }
 
-    fn slot_size(&self) -> usize {
-        self.slot_size()
+    /// 将队列内容同步写回持久化文件
+    pub fn flush(&self) -> Result<()> {
+        self.pipe.flush()
     }
-}
 
-/// 动态管道工厂，支持根据配置创建不同类型的管道
-pub struct PipeFactory;
+    /// 包装已映射的管道
+    ///
+    /// `creator` 为 `true` 时本进程是管道的创建者（持久化管道重新打开），头部要求握手时按
+    /// 访问控制策略处理连接方的授权请求；否则本进程是连接方，头部要求握手时先申请授权。
+    fn attached(
+        pipe: DynSharedSlotPipe,
+        name: &str,
+        security: SecurityOptions,
+        creator: bool,
+    ) -> Result<Self> {
+        // 校验失败返回时映射随所有者解除
+        let mut pipe = unsafe { SlotMapping::new(pipe) };
+        let cipher = Self::check_encryption(&pipe, name, security.cipher)?;
+        let authenticator = Self::check_authentication(&pipe, name, security.authenticator)?;
+        if !creator && pipe.requires_handshake() {
+            access::request_access(name, HANDSHAKE_TIMEOUT)
+                .with_context(|| format!("管道 {} 拒绝了本进程的连接", name))?;
+        }
+        *pipe = pipe.with_authenticator(authenticator);
+        let handshake_server = (creator && pipe.requires_handshake())
+            .then(|| HandshakeServer::spawn(name, security.access));
 
-impl PipeFactory {
-    /// 根据字符串类型创建管道
-    pub fn create(pipe_type_str: &str, name: &str) -> Result<Box<dyn DynamicPipe>> {
-        let pipe_type = PipeType::from_str(pipe_type_str)
-            .map_err(|e| anyhow::anyhow!("无效的管道类型: {}", e))?;
-        Self::create_pipe(pipe_type, name)
+        // 并发模式、编解码方式、校验算法与互斥锁属性以创建者写入头部的为准
+        let config = PipeConfig::new(pipe.capacity(), pipe.slot_size())
+            .with_mode(pipe.mode())
+            .with_codec(pipe.codec())
+            .with_integrity(pipe.integrity())
+            .with_mutex_attr(pipe.mutex_attr())
+            .with_fairness(pipe.is_fair())
+            .with_full_policy(pipe.full_policy())
+            .with_max_delivery_attempts(pipe.max_delivery_attempts())
+            .with_wait_strategy(pipe.wait_strategy());
+        let pipe = Self {
+            attach_index: Self::attach(&pipe, name),
+            pipe,
+            name: name.to_string(),
+            config,
+            owner: false,
+            notifier: Self::open_notifier(name, PipeNotifier::open),
+            space_notifier: Self::open_notifier(name, PipeNotifier::open_space),
+            space_queue: tokio::sync::Mutex::new(()),
+            mailbox: None,
+            dead_letter: None,
+            backpressure_callbacks: Mutex::new(Vec::new()),
+            backpressure_seen: AtomicBool::new(false),
+            cipher,
+            handshake_server,
+        };
+        pipe.advise(MemoryAdvice::default());
+        Ok(pipe)
     }
 
-    /// 根据字符串类型连接到现有管道
-    pub fn connect(pipe_type_str: &str, name: &str, create: bool) -> Result<Box<dyn DynamicPipe>> {
-        let pipe_type = PipeType::from_str(pipe_type_str)
-            .map_err(|e| anyhow::anyhow!("无效的管道类型: {}", e))?;
-        // 先尝试连接现有管道
-        match Self::connect_pipe(pipe_type, name) {
-            Ok(pipe) => Ok(pipe),
-            Err(_) => {
-                if create {
-                    // 连接失败则创建新管道
-                    Self::create_pipe(pipe_type, name)
-                } else {
-                    Err(anyhow::anyhow!("管道未找到"))
+    /// 按头部记录的加密算法与密钥标识确认本连接可以读写该管道
+    fn check_encryption(
+        pipe: &DynSharedSlotPipe,
+        name: &str,
+        cipher: Option<Arc<PayloadCipher>>,
+    ) -> Result<Option<Arc<PayloadCipher>>> {
+        match pipe.encryption() {
+            Some(Encryption::None) => {
+                if cipher.is_some() {
+                    tracing::warn!("管道 {} 未加密，本连接以明文读写", name);
                 }
+                Ok(None)
             }
+            Some(algorithm) => match cipher {
+                Some(cipher) if cipher.algorithm() == algorithm => {
+                    if cipher.key_id() != pipe.key_id() {
+                        return Err(anyhow::anyhow!(
+                            "管道 {} 的密钥标识 {:016x} 与本进程密钥 {:016x} 不一致",
+                            name,
+                            pipe.key_id(),
+                            cipher.key_id()
+                        )
+                        .into());
+                    }
+                    Ok(Some(cipher))
+                }
+                Some(cipher) => Err(anyhow::anyhow!(
+                    "管道 {} 使用 {} 加密，本进程密钥为 {}",
+                    name,
+                    algorithm,
+                    cipher.algorithm()
+                )
+                .into()),
+                None => {
+                    Err(
+                        anyhow::anyhow!("管道 {} 已加密 ({})，但本进程未配置密钥", name, algorithm)
+                            .into(),
+                    )
+                }
+            },
+            None => Err(anyhow::anyhow!(
+                "管道 {} 使用未知的加密算法 {}",
+                name,
+                pipe.header().encryption
+            )
+            .into()),
         }
     }
 
-    /// 根据管道类型创建管道
-    pub fn create_pipe(pipe_type: PipeType, name: &str) -> Result<Box<dyn DynamicPipe>> {
-        match pipe_type {
-            PipeType::Small => {
-                let pipe = CrossProcessPipe::<10, 1024>::create(name)?;
-                Ok(Box::new(pipe))
-            }
-            PipeType::Default => {
-                let pipe = CrossProcessPipe::<100, 4096>::create(name)?;
-                Ok(Box::new(pipe))
-            }
-            PipeType::Large => {
-                let pipe = CrossProcessPipe::<1000, 8192>::create(name)?;
-                Ok(Box::new(pipe))
+    /// 按头部记录的认证密钥标识确认本连接可以计算与校验消息认证码
+    fn check_authentication(
+        pipe: &DynSharedSlotPipe,
+        name: &str,
+        authenticator: Option<&'static MessageAuthenticator>,
+    ) -> Result<Option<&'static MessageAuthenticator>> {
+        match (pipe.auth_key_id(), authenticator) {
+            (0, _) => Ok(None),
+            (_, None) => {
+                Err(anyhow::anyhow!("管道 {} 要求消息认证，但本进程未配置认证密钥", name).into())
             }
-            PipeType::Custom(capacity, slot_size) => {
-                // 对于自定义配置，我们需要使用宏或者匹配常见的配置
-                Self::create_custom_pipe(capacity, slot_size, name)
+            (key_id, Some(authenticator)) if authenticator.key_id() != key_id => {
+                Err(anyhow::anyhow!(
+                    "管道 {} 的认证密钥标识 {:016x} 与本进程密钥 {:016x} 不一致",
+                    name,
+                    key_id,
+                    authenticator.key_id()
+                )
+                .into())
             }
+            (_, authenticator) => Ok(authenticator),
         }
     }
 
-    /// 根据配置创建管道
-    pub fn create_with_config(config: PipeConfig, name: &str) -> Result<Box<dyn DynamicPipe>> {
-        let pipe_type = PipeType::from_config(config);
+    /// 是否加密负载
+    pub fn is_encrypted(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    /// 编码消息写入槽位缓冲区，加密时在编码后原地加密
+    fn encode_payload(&self, message: &Message, buf: &mut [u8]) -> Result<usize> {
+        let codec = self.config.codec.codec();
+        match &self.cipher {
+            Some(cipher) => {
+                let room = buf.len().saturating_sub(PayloadCipher::OVERHEAD);
+                let len = codec.encode(message, &mut buf[..room])?;
+                cipher.seal_in_place(buf, len)
+            }
+            None => codec.encode(message, buf),
+        }
+    }
+
+    /// 从槽位数据解码消息，加密时先解密并校验认证标签
+    fn decode_payload(&self, buf: &[u8]) -> Result<Message> {
+        let codec = self.config.codec.codec();
+        match &self.cipher {
+            Some(cipher) => codec.decode(&cipher.open(buf)?),
+            None => codec.decode(buf),
+        }
+    }
+
+    /// 关联共享寄存箱，用于 [`DynCrossProcessPipe::send_large`] 传输超出槽位的大负载
+    pub fn with_mailbox(mut self, mailbox: Arc<SharedMemoryMailbox>) -> Self {
+        self.mailbox = Some(mailbox);
+        self
+    }
+
+    /// 关联的共享寄存箱
+    pub fn mailbox(&self) -> Option<&Arc<SharedMemoryMailbox>> {
+        self.mailbox.as_ref()
+    }
+
+    /// 关联死信管道，超过最大投递次数的消息经 [`DynCrossProcessPipe::nack`] 转发到该管道
+    pub fn with_dead_letter(mut self, dead_letter: Arc<dyn DynamicPipe>) -> Self {
+        self.dead_letter = Some(dead_letter);
+        self
+    }
+
+    /// 关联的死信管道
+    pub fn dead_letter(&self) -> Option<&Arc<dyn DynamicPipe>> {
+        self.dead_letter.as_ref()
+    }
+
+    /// 在头部 PID 表中登记当前连接
+    fn attach(pipe: &DynSharedSlotPipe, name: &str) -> Option<usize> {
+        let index = pipe.attach();
+        if index.is_none() {
+            tracing::warn!("管道 {} 的连接表已满，本连接不计入引用计数", name);
+        }
+        index
+    }
+
+    /// 打开管道对应的通知 FIFO，失败时异步接口退化为定时轮询
+    fn open_notifier(name: &str, open: fn(&str) -> Result<PipeNotifier>) -> Option<PipeNotifier> {
+        match open(name) {
+            Ok(notifier) => Some(notifier),
+            Err(e) => {
+                tracing::warn!("打开管道 {} 的通知 FIFO 失败: {}", name, e);
+                None
+            }
+        }
+    }
+
+    /// 唤醒异步等待的读者
+    pub(crate) fn notify(&self) {
+        if let Some(notifier) = &self.notifier {
+            notifier.notify();
+        }
+    }
+
+    /// 有写者异步等待空槽位时唤醒其中一个，没有等待者时不写 FIFO
+    fn notify_space(&self) {
+        if self.pipe.header().space_waiters.load(Ordering::SeqCst) == 0 {
+            return;
+        }
+        if let Some(notifier) = &self.space_notifier {
+            notifier.notify();
+        }
+    }
+
+    /// 槽位释放后刷新背压状态，并唤醒等待空槽位的异步写者；
+    /// 设置了排空后释放时，队列中没有待读取的消息则释放本进程映射的槽位页面
+    fn released(&self) {
+        if self.backpressure_seen.load(Ordering::Relaxed) {
+            self.is_backpressured();
+        }
+        if self.config.memory.releases_on_drain() && !self.has_ready() {
+            let (slots, len) = self.pipe.slot_region();
+            memory::release(slots, len);
+        }
+        self.notify_space();
+    }
+
+    /// 当前连接到该管道的进程 PID 列表
+    pub fn attached_processes(&self) -> Vec<u32> {
+        self.pipe.attached_processes()
+    }
+
+    /// 当前连接到该管道的句柄数量，为 1 且是自己时可以安全删除
+    pub fn attached_count(&self) -> usize {
+        self.pipe.attached_count()
+    }
+
+    /// 获取共享内存名称
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 是否为共享内存段的持有者（Drop 时负责删除）
+    pub fn is_owner(&self) -> bool {
+        self.owner
+    }
+
+    /// 放弃持有权，Drop 时不再删除共享内存段
+    pub fn persist(&mut self) {
+        if self.owner {
+            SharedMemoryRegistry::deregister(&self.name);
+            self.owner = false;
+        }
+    }
+
+    /// 本句柄队列空或满时的等待方式
+    pub fn wait_strategy(&self) -> WaitStrategy {
+        self.pipe.wait_strategy()
+    }
+
+    /// 改变本句柄队列空或满时的等待方式（例如连接后改为忙等），见 [`crate::wait`]
+    pub fn with_wait_strategy(mut self, wait_strategy: WaitStrategy) -> Self {
+        *self.pipe = self.pipe.with_wait_strategy(wait_strategy);
+        self.config.wait_strategy = wait_strategy;
+        self
+    }
+
+    /// 改变本句柄映射的内存建议（例如连接后锁定页面），见 [`crate::memory`]
+    pub fn with_memory_advice(mut self, advice: MemoryAdvice) -> Self {
+        let previous = self.config.memory;
+        self.config.memory = advice;
+        self.advise(previous);
+        self
+    }
+
+    /// 按本句柄的内存建议处理映射，`previous` 为此前生效的建议
+    fn advise(&self, previous: MemoryAdvice) {
+        memory::advise(
+            &self.name,
+            self.pipe.as_ptr() as *mut u8,
+            self.pipe.mapped_len(),
+            previous,
+            self.config.memory,
+        );
+    }
+
+    /// 共享内存段的映射大小与驻留内存大小
+    pub fn memory_usage(&self) -> MemoryUsage {
+        memory::usage(self.pipe.as_ptr() as *mut u8, self.pipe.mapped_len())
+    }
+
+    /// 删除共享内存名称
+    ///
+    /// 已连接的进程可继续使用当前映射，新的 `connect` 将失败
+    pub fn unlink(&self) -> Result<()> {
+        SharedMemoryRegistry::unlink(&self.name)
+    }
+
+    /// 删除共享内存名称并释放映射
+    pub fn destroy(mut self) -> Result<()> {
+        self.owner = false;
+        self.unlink()
+    }
+
+    /// 获取 空slot
+    pub fn hold(&self) -> Result<usize> {
+        let mut pipe = *self.pipe;
+        let held = unsafe { pipe.hold() };
+        self.is_backpressured();
+        Ok(held.ok_or_else(|| {
+            anyhow::Error::from(TokioIPCError::QueueFull).context("队列已满，无法获取空槽位")
+        })?)
+    }
+
+    /// 放弃已通过 `hold` 获取、尚未写入的槽位
+    pub(crate) fn abandon(&self, index: usize) {
+        let mut pipe = *self.pipe;
+        unsafe { pipe.abandon(index) };
+        self.notify_space();
+    }
+
+    /// 设置背压的高低水位（非 EMPTY 槽位数量），高水位为 0 表示关闭，所有连接方共享
+    pub fn set_watermarks(&self, high: usize, low: usize) -> Result<()> {
+        PipeConfig::new(self.capacity(), self.slot_size())
+            .with_watermarks(high, low)
+            .validate()
+            .map_err(|e| anyhow::anyhow!("水位无效: {}", e))?;
+        self.pipe.set_watermarks(high, low);
+        self.is_backpressured();
+        Ok(())
+    }
+
+    /// 是否处于背压：占用达到高水位后为真，降到低水位及以下才恢复
+    ///
+    /// 本进程观察到状态变化时调用 [`DynCrossProcessPipe::on_backpressure`] 注册的回调。
+    pub fn is_backpressured(&self) -> bool {
+        let backpressured = self.pipe.update_backpressure();
+        if self.backpressure_seen.swap(backpressured, Ordering::AcqRel) != backpressured {
+            if backpressured {
+                tracing::warn!("管道 {} 占用达到高水位，进入背压", self.name);
+            } else {
+                tracing::info!("管道 {} 占用降到低水位，解除背压", self.name);
+            }
+            for callback in self.backpressure_callbacks.lock().unwrap().iter() {
+                callback(backpressured);
+            }
+        }
+        backpressured
+    }
+
+    /// 注册背压状态变化回调，进入背压时以 `true` 调用，解除时以 `false` 调用
+    ///
+    /// 状态在本进程获取槽位、释放槽位或查询背压时刷新，回调在刷新的线程上同步执行。
+    pub fn on_backpressure<F>(&self, callback: F)
+    where
+        F: Fn(bool) + Send + Sync + 'static,
+    {
+        self.backpressure_callbacks
+            .lock()
+            .unwrap()
+            .push(Box::new(callback));
+    }
+
+    /// 发送消息
+    /// 将数据写入slot
+    pub fn send(&self, index: usize, message: Message) -> Result<u64> {
+        self.write_message(index, None, message)
+    }
+
+    /// 以指定 request_id 发送消息
+    ///
+    /// 响应方沿用请求的 request_id 写回响应管道，请求方据此找到等待者
+    pub fn send_tagged(&self, index: usize, request_id: u64, message: Message) -> Result<u64> {
+        self.write_message(index, Some(request_id), message)
+    }
+
+    fn write_message(
+        &self,
+        index: usize,
+        request_id: Option<u64>,
+        message: Message,
+    ) -> Result<u64> {
+        let mut pipe = *self.pipe;
+        match unsafe {
+            pipe.try_write_tagged(index, request_id, |buf| self.encode_payload(&message, buf))
+        } {
+            Ok(request_id) => {
+                self.notify();
+                Ok(request_id)
+            }
+            Err(err) => {
+                // 写入失败时槽位已被放弃
+                self.notify_space();
+                Err(err.context("写入消息失败"))
+            }
+        }
+    }
+
+    /// 接收消息
+    pub fn fetch(&self) -> Result<usize> {
+        let mut pipe = *self.pipe;
+        match unsafe { pipe.fetch() } {
+            Some(index) => Ok(index),
+            None => Err(anyhow::Error::from(TokioIPCError::QueueEmpty)
+                .context("队列为空，无法获取消息")
+                .into()),
+        }
+    }
+
+    /// 获取空槽位，返回带代数的句柄，配合 [`DynCrossProcessPipe::send_at`] 使用
+    pub fn hold_handle(&self) -> Result<SlotHandle> {
+        self.hold().map(|index| self.pipe.handle(index))
+    }
+
+    /// 获取可读槽位，返回带代数的句柄，配合 [`DynCrossProcessPipe::receive_at`] 使用
+    pub fn fetch_handle(&self) -> Result<SlotHandle> {
+        self.fetch().map(|index| self.pipe.handle(index))
+    }
+
+    /// 等待队列中出现可读消息（futex 跨进程休眠），超时返回 `false`
+    pub fn wait_for_ready(&self, timeout: Duration) -> bool {
+        self.pipe.wait_for_ready(Some(timeout))
+    }
+
+    /// 等待队列中出现空槽位（futex 跨进程休眠），超时返回 `false`
+    pub fn wait_for_empty(&self, timeout: Duration) -> bool {
+        self.pipe.wait_for_empty(Some(timeout))
+    }
+
+    /// 异步获取消息
+    ///
+    /// 队列空时在通知 FIFO 上等待（tokio `AsyncFd`），写者发送后立即唤醒，不占用运行时线程；
+    /// 轮询等待方式（见 [`crate::wait`]）下改为按策略让出或休眠后重试
+    pub async fn fetch_async(&self) -> Result<usize> {
+        let wait = self.wait_strategy();
+        let mut backoff = self.pipe.backoff();
+        loop {
+            let mut pipe = *self.pipe;
+            if let Some(index) = unsafe { pipe.fetch_timeout(Some(Duration::ZERO)) } {
+                return Ok(index);
+            }
+
+            match &self.notifier {
+                Some(notifier) if !wait.polls() => {
+                    // 超时只是兜底，醒来后重新检查队列
+                    let _ = tokio::time::timeout(NOTIFY_FALLBACK, notifier.wait()).await;
+                }
+                _ => backoff.snooze_async().await,
+            }
+        }
+    }
+
+    /// 异步获取空槽位
+    ///
+    /// 队列满时在空槽位通知 FIFO 上等待（tokio `AsyncFd`），读者释放槽位后立即唤醒，不占用运行时线程。
+    /// 同一句柄上的异步写者按到达顺序取得槽位；其他进程的等待者每释放一个槽位唤醒一个。
+    /// 取得槽位之前取消（drop）不会占用槽位。轮询等待方式下不等待通知，按策略让出或休眠后重试。
+    pub async fn hold_async(&self) -> Result<usize> {
+        // 前面的写者取得槽位之前，后来者不参与抢占
+        let _turn = self.space_queue.lock().await;
+        let waiter = SpaceWaiter::register(&self.pipe.header().space_waiters);
+        let wait = self.wait_strategy();
+        let mut backoff = self.pipe.backoff();
+        loop {
+            // 先登记再检查：读者释放槽位后要么看到登记并发出通知，要么这里能抢占到槽位
+            match self.hold() {
+                Ok(index) => {
+                    drop(waiter);
+                    // 还有空槽位时把通知传给下一个等待者
+                    if self.pipe.has_empty() {
+                        self.notify_space();
+                    }
+                    return Ok(index);
+                }
+                Err(e) if self.pipe.full_policy() == FullPolicy::Reject => return Err(e),
+                Err(_) => {}
+            }
+
+            match &self.space_notifier {
+                Some(notifier) if !wait.polls() => {
+                    // 超时只是兜底（例如旧版本的读者不发通知），醒来后重新检查队列
+                    let _ = tokio::time::timeout(NOTIFY_FALLBACK, notifier.wait()).await;
+                }
+                _ => backoff.snooze_async().await,
+            }
+        }
+    }
+
+    /// 异步发送消息，队列满时等待空槽位，见 [`DynCrossProcessPipe::hold_async`]
+    pub async fn send_async(&self, message: Message) -> Result<u64> {
+        let index = self.hold_async().await?;
+        self.send(index, message)
+    }
+
+    /// 向句柄指向的槽位写入消息，槽位已被回收或复用时返回 [`StaleHandle`](crate::shared_slot::StaleHandle)
+    pub fn send_at(&self, handle: SlotHandle, message: Message) -> Result<u64> {
+        self.pipe.check_handle(handle)?;
+        self.send(handle.index, message)
+    }
+
+    /// 读取句柄指向的槽位，槽位已被回收或复用时返回 [`StaleHandle`](crate::shared_slot::StaleHandle)
+    ///
+    /// 例如处理缓慢的消费者持有的槽位被回收后又装入了另一条消息，此时不会读到别人的消息。
+    pub fn receive_at(&self, handle: SlotHandle) -> Result<Message> {
+        self.pipe.check_handle(handle)?;
+        self.receive(handle.index)
+    }
+
+    /// 接收消息
+    pub fn receive(&self, index: usize) -> Result<Message> {
+        self.receive_tagged(index).map(|(_, message)| message)
+    }
+
+    /// 接收消息及其 request_id
+    ///
+    /// 消息已过期时槽位照常释放，返回 [`MessageExpired`] 错误并计入 `expired_count`
+    pub fn receive_tagged(&self, index: usize) -> Result<(u64, Message)> {
+        let mut pipe = *self.pipe;
+        let received = unsafe { pipe.try_read_with(index, |buf| self.decode_payload(buf)) }
+            .map_err(read_error);
+        self.released();
+
+        let (request_id, message) = received?;
+        if message.is_expired() {
+            self.pipe.record_expired();
+            tracing::debug!("管道 {} 丢弃已过期的消息 {}", self.name, request_id);
+            return Err(MessageExpired {
+                request_id,
+                expires_at: message.expires_at,
+            }
+            .into());
+        }
+        Ok((request_id, message))
+    }
+
+    /// 接收消息及其 request_id，但暂不释放槽位
+    ///
+    /// 处理成功后调用 [`DynCrossProcessPipe::ack`]，失败时调用 [`DynCrossProcessPipe::nack`]；
+    /// 消息已过期或无法解码时槽位直接释放。
+    pub fn receive_unacked(&self, index: usize) -> Result<(u64, Message)> {
+        let mut pipe = *self.pipe;
+        let (request_id, _, message) =
+            unsafe { pipe.peek_with(index, |buf| self.decode_payload(buf)) }.map_err(read_error)?;
+
+        if message.is_expired() {
+            self.ack(index)?;
+            self.pipe.record_expired();
+            tracing::debug!("管道 {} 丢弃已过期的消息 {}", self.name, request_id);
+            return Err(MessageExpired {
+                request_id,
+                expires_at: message.expires_at,
+            }
+            .into());
+        }
+        Ok((request_id, message))
+    }
+
+    /// 确认消息处理完成并释放槽位
+    pub fn ack(&self, index: usize) -> Result<()> {
+        let mut pipe = *self.pipe;
+        unsafe { pipe.ack(index) }.context("确认消息失败")?;
+        self.released();
+        Ok(())
+    }
+
+    /// 确认消息处理完成、推进已提交位置并释放槽位，返回消息的 request_id
+    ///
+    /// 配合 [`receive_unacked`](Self::receive_unacked) 实现至少一次消费：读者在提交前崩溃时，
+    /// 重启后调用 [`resume`](Self::resume) 让未提交的消息重新投递。
+    /// 已提交位置是提交过的最大 request_id，保存在共享内存头部，不随读者进程退出而丢失。
+    pub fn commit(&self, index: usize) -> Result<u64> {
+        let mut pipe = *self.pipe;
+        let request_id = unsafe { pipe.commit(index) }.context("提交消息失败")?;
+        self.released();
+        Ok(request_id)
+    }
+
+    /// 已提交的最大 request_id，尚未提交过时为 0
+    ///
+    /// 单个读者按顺序处理时，request_id 不超过该值的消息已处理过（例如提交后、释放槽位前崩溃
+    /// 而被重新投递），可以直接跳过。
+    pub fn committed_offset(&self) -> u64 {
+        self.pipe.committed_offset()
+    }
+
+    /// 读者重启后从已提交位置继续：重新投递被读取超过 `timeout` 仍未提交的消息，返回数量
+    ///
+    /// 超时应大于正常处理耗时，否则仍在处理的读者之后的提交会失败。
+    /// 无锁模式下没有空槽位可重新投递的消息释放后转入死信，与 [`DynCrossProcessPipe::nack`] 相同。
+    pub fn resume(&self, timeout: Duration) -> usize {
+        let mut pipe = *self.pipe;
+        let requeued = unsafe {
+            pipe.requeue_stuck_readers(timeout, |index| {
+                if let Err(e) = self.dead_letter_held(index) {
+                    tracing::error!("管道 {} 转移槽位 {} 的消息失败: {}", self.name, index, e);
+                }
+            })
+        };
+        if requeued > 0 {
+            tracing::info!("管道 {} 重新投递了 {} 条未提交的消息", self.name, requeued);
+            self.notify();
+        }
+        requeued
+    }
+
+    /// 消息处理失败，返回是否已重新投递
+    ///
+    /// 失败次数未达到 `max_delivery_attempts` 时消息回到 READY 等待再次读取；
+    /// 否则（或无锁模式下没有空槽位可重新投递时）释放槽位并转发到死信管道，
+    /// 没有关联死信管道时消息被丢弃。两种情况都计入管道状态。
+    pub fn nack(&self, index: usize) -> Result<bool> {
+        let mut pipe = *self.pipe;
+        if !pipe.exhausted(index)? {
+            match unsafe { pipe.requeue(index) } {
+                Ok(()) => {
+                    self.notify();
+                    return Ok(true);
+                }
+                // 消息仍在原槽位中，释放后转入死信
+                Err(e) => tracing::warn!("管道 {} 重新投递槽位 {} 失败: {}", self.name, index, e),
+            }
+        }
+        self.dead_letter_held(index)?;
+        Ok(false)
+    }
+
+    /// 取出读者持有的槽位中的消息，确认释放后转发到死信管道
+    fn dead_letter_held(&self, index: usize) -> Result<()> {
+        let mut pipe = *self.pipe;
+        let (request_id, attempts, message) =
+            unsafe { pipe.peek_with(index, |buf| self.decode_payload(buf)) }.map_err(read_error)?;
+        self.ack(index)?;
+
+        self.pipe.record_dead_lettered();
+        match &self.dead_letter {
+            Some(dead_letter) => {
+                if let Err(e) = dead_letter.send_blocking(message, DEAD_LETTER_TIMEOUT) {
+                    tracing::error!(
+                        "管道 {} 转发消息 {} 到死信管道 {} 失败: {}",
+                        self.name,
+                        request_id,
+                        dead_letter.name(),
+                        e
+                    );
+                }
+            }
+            None => tracing::warn!(
+                "管道 {} 的消息 {} 投递失败 {} 次，未关联死信管道，丢弃",
+                self.name,
+                request_id,
+                attempts + 1
+            ),
+        }
+        Ok(())
+    }
+
+    /// 槽位中消息已失败的投递次数
+    pub fn delivery_attempts(&self, index: usize) -> Result<u32> {
+        self.pipe.delivery_attempts(index)
+    }
+
+    /// 零拷贝发送：闭包直接填充槽位内存并返回写入的字节数
+    ///
+    /// 适合大负载，避免序列化到中间 Vec 再复制；槽位需已通过 `hold` 获取。
+    /// 数据以原始字节形式写入，接收方需使用 [`DynCrossProcessPipe::receive_with`] 读取。
+    /// 加密管道中闭包可用的空间减少 [`PayloadCipher::OVERHEAD`] 字节，数据在填充后原地加密。
+    pub fn send_with<F>(&self, index: usize, fill: F) -> Result<u64>
+    where
+        F: FnOnce(&mut [u8]) -> usize,
+    {
+        self.try_send_with(index, |buf| Ok(fill(buf)))
+    }
+
+    /// 与 [`DynCrossProcessPipe::send_with`] 相同，闭包返回错误时槽位被放弃
+    pub fn try_send_with<F>(&self, index: usize, fill: F) -> Result<u64>
+    where
+        F: FnOnce(&mut [u8]) -> Result<usize>,
+    {
+        let mut pipe = *self.pipe;
+        let written = match &self.cipher {
+            Some(cipher) => unsafe {
+                pipe.try_write_with(index, |buf| {
+                    let room = buf.len().saturating_sub(PayloadCipher::OVERHEAD);
+                    let len = fill(&mut buf[..room])?;
+                    cipher.seal_in_place(buf, len)
+                })
+            },
+            None => unsafe { pipe.try_write_with(index, fill) },
+        };
+        Ok(written
+            .inspect(|_| self.notify())
+            .inspect_err(|_| self.notify_space())
+            .context("写入消息失败")?)
+    }
+
+    /// 零拷贝接收：闭包直接读取槽位内存，返回值原样带出
+    ///
+    /// 闭包返回后槽位即被释放，切片不能逃逸出闭包。加密管道中闭包看到的是解密后的副本。
+    pub fn receive_with<F, R>(&self, index: usize, visit: F) -> Result<R>
+    where
+        F: FnOnce(&[u8]) -> R,
+    {
+        let mut pipe = *self.pipe;
+        let received = match &self.cipher {
+            Some(cipher) => unsafe {
+                pipe.try_read_with(index, |buf| cipher.open(buf).map(|plain| visit(&plain)))
+            },
+            None => unsafe { pipe.read_with(index, visit) },
+        };
+        self.released();
+        received.map(|(_, result)| result).map_err(read_error)
+    }
+
+    /// 获取消息并固定其槽位，队列空时等待
+    ///
+    /// 与 [`receive_with`](Self::receive_with) 相同地零拷贝读取槽位，但槽位在返回的
+    /// [`PinnedSlot`] drop 时才释放，可以直接在共享内存上解析大负载，不必先复制出来。
+    pub fn fetch_pinned(&self) -> Result<PinnedSlot<'_>> {
+        let index = self.fetch()?;
+        self.pin(index)
+    }
+
+    /// 固定已由 `fetch` 系列方法获取的槽位，校验失败时槽位被释放并返回错误
+    pub fn pin(&self, index: usize) -> Result<PinnedSlot<'_>> {
+        let mut pipe = *self.pipe;
+        let (request_id, _, plain) = unsafe {
+            pipe.peek_with(index, |buf| {
+                self.cipher
+                    .as_ref()
+                    .map(|cipher| cipher.open(buf))
+                    .transpose()
+            })
+        }
+        .map_err(read_error)?;
+        Ok(PinnedSlot {
+            pipe: self,
+            index,
+            request_id,
+            plain,
+        })
+    }
+
+    /// 尝试接收消息（非阻塞，返回Option），消息已过期时返回 `Ok(None)`
+    pub fn try_receive(&self, index: usize) -> Result<Option<Message>> {
+        match self.receive_tagged(index) {
+            Ok((_, message)) => Ok(Some(message)),
+            Err(e) if e.is::<MessageExpired>() => Ok(None),
+            Err(err) => Err(err.context("尝试读取消息失败")),
+        }
+    }
+
+    /// 接收第一条满足条件的消息及其 request_id，其余消息保持原状
+    ///
+    /// 从读指针开始检查 READY 槽位，供只处理特定消息类型（例如按 `flag`）的 worker 与其他
+    /// 消费者共用一条管道；没有满足条件的消息时返回 `Ok(None)`，已过期的消息被跳过。
+    /// 只支持加锁模式，无锁模式的槽位严格按顺序出队，返回错误。
+    pub fn receive_where<F>(&self, predicate: F) -> Result<Option<(u64, Message)>>
+    where
+        F: Fn(&Message) -> bool,
+    {
+        loop {
+            let mut pipe = *self.pipe;
+            let claimed = unsafe {
+                pipe.claim_ready_where(|data| {
+                    self.decode_payload(data)
+                        .is_ok_and(|message| predicate(&message))
+                })
+            }?;
+            let Some(index) = claimed else {
+                return Ok(None);
+            };
+
+            match self.receive_tagged(index) {
+                Err(e) if e.is::<MessageExpired>() => continue,
+                received => return received.map(Some),
+            }
+        }
+    }
+
+    /// 获取空槽位，队列满时在共享内存中的条件变量上休眠，超时返回 [`PipeTimeout::Send`]
+    ///
+    /// [`FullPolicy::Reject`] 时不等待，与 [`DynCrossProcessPipe::hold`] 相同；
+    /// [`FullPolicy::OverwriteOldest`] 时先丢弃最早的消息。
+    pub fn hold_timeout(&self, timeout: Duration) -> Result<usize> {
+        if self.pipe.full_policy() == FullPolicy::Reject {
+            return self.hold();
+        }
+        let mut pipe = *self.pipe;
+        unsafe { pipe.hold_timeout(Some(timeout)) }.ok_or_else(|| PipeTimeout::Send(timeout).into())
+    }
+
+    /// 获取 READY 槽位，队列空时在共享内存中的条件变量上休眠，超时返回 [`PipeTimeout::Receive`]
+    ///
+    /// 条件变量休眠是默认的 [`WaitStrategy::Blocking`]，其他等待方式下按策略轮询。
+    pub fn fetch_timeout(&self, timeout: Duration) -> Result<usize> {
+        let mut pipe = *self.pipe;
+        unsafe { pipe.fetch_timeout(Some(timeout)) }
+            .ok_or_else(|| PipeTimeout::Receive(timeout).into())
+    }
+
+    /// 阻塞发送消息
+    ///
+    /// 队列满时在共享内存中的条件变量上休眠，直到消费者释放槽位或超时
+    pub fn send_blocking(&self, message: Message, timeout: Duration) -> Result<u64> {
+        let index = self.hold_timeout(timeout)?;
+        self.send(index, message)
+    }
+
+    /// 非阻塞发送：获取空槽位并写入消息，队列满时返回 `Ok(None)`
+    ///
+    /// 写入失败（例如消息超出槽位大小）时槽位被放弃，不会遗留 WRITING 槽位
+    pub fn try_send(&self, message: Message) -> Result<Option<u64>> {
+        match self.hold() {
+            Ok(index) => self.send(index, message).map(Some),
+            Err(e) if is_queue_full(&e) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// 发送消息，队列满时等待空槽位直到超时，超时返回 [`PipeTimeout::Send`]
+    ///
+    /// 与 [`DynCrossProcessPipe::send_blocking`] 相同，与 [`DynCrossProcessPipe::try_send`] 对应
+    pub fn send_timeout(&self, message: Message, timeout: Duration) -> Result<u64> {
+        self.send_blocking(message, timeout)
+    }
+
+    /// 阻塞接收消息
+    ///
+    /// 队列空时在共享内存中的条件变量上休眠，直到生产者写入新数据或超时；
+    /// 已过期的消息被跳过
+    pub fn receive_blocking(&self, timeout: Duration) -> Result<Message> {
+        let deadline = std::time::Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+            let index = self
+                .fetch_timeout(remaining)
+                .map_err(|_| PipeTimeout::Receive(timeout))?;
+
+            match self.receive(index) {
+                Err(e) if e.is::<MessageExpired>() => continue,
+                received => return received,
+            }
+        }
+    }
+
+    /// 发送大负载：数据写入关联寄存箱的 box，管道中只传递 box 描述
+    ///
+    /// 寄存箱没有空 box 时等待释放，`timeout` 覆盖等待 box 与等待空槽位的总时长；
+    /// 消息未能写入管道时 box 被归还。加密管道中 box 保存的是密文。
+    pub fn send_large(&self, data: &[u8], timeout: Duration) -> Result<u64> {
+        let sealed;
+        let data = match &self.cipher {
+            Some(cipher) => {
+                sealed = cipher.seal(data)?;
+                sealed.as_slice()
+            }
+            None => data,
+        };
+        let mailbox = self
+            .mailbox
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("管道 {} 未关联寄存箱", self.name))?;
+        let size = mailbox
+            .smallest_size_for(data.len())
+            .ok_or_else(|| anyhow::anyhow!("数据过大，没有可容纳 {} 字节的 box", data.len()))?;
+
+        let deadline = std::time::Instant::now() + timeout;
+        let box_id = loop {
+            match mailbox.allocate(data.len()) {
+                Ok(box_id) => break box_id,
+                Err(e) => {
+                    let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+                    if remaining.is_zero() || !mailbox.wait_for_empty(size, Some(remaining)) {
+                        return Err(e.context("等待空 box 超时"));
+                    }
+                }
+            }
+        };
+
+        if let Err(e) = mailbox.write_data(box_id, data) {
+            let _ = mailbox.discard(box_id);
+            return Err(e);
+        }
+
+        let payload = LargePayload {
+            box_id,
+            size: data.len() as u64,
+            checksum: mailbox.integrity().checksum(data),
+        };
+        let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+        self.send_blocking(Message::large_payload(payload), remaining)
+            .inspect_err(|_| {
+                let _ = mailbox.discard(box_id);
+            })
+    }
+
+    /// 接收由 [`DynCrossProcessPipe::send_large`] 发送的数据，读取后释放 box
+    ///
+    /// 收到普通消息时直接返回其数据
+    pub fn receive_large(&self, timeout: Duration) -> Result<Vec<u8>> {
+        let message = self.receive_blocking(timeout)?;
+        let Some(payload) = message.as_large_payload() else {
+            return Ok(message.data);
+        };
+
+        let mailbox = self
+            .mailbox
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("管道 {} 未关联寄存箱", self.name))?;
+        let data = mailbox.take_data(payload.box_id)?;
+        if data.len() as u64 != payload.size || !mailbox.integrity().verify(&data, payload.checksum)
+        {
+            return Err(anyhow::anyhow!("box {} 的数据与消息描述不一致", payload.box_id).into());
+        }
+        match &self.cipher {
+            Some(cipher) => cipher.open(&data),
+            None => Ok(data),
+        }
+    }
+
+    /// 是否有可读取的消息
+    pub fn has_ready(&self) -> bool {
+        self.pipe.has_ready()
+    }
+
+    /// 就绪通知 FIFO 的文件描述符，未启用通知时返回 `None`
+    pub fn notify_fd(&self) -> Option<RawFd> {
+        self.notifier.as_ref().map(|notifier| notifier.as_raw_fd())
+    }
+
+    /// 获取队列状态
+    pub fn status(&self) -> PipeStatus {
+        let pipe = &self.pipe;
+
+        // 获取写指针和读指针（无锁模式下由生产/消费位置换算）
+        let (write_pointer, read_pointer) = pipe.pointers();
+
+        // 统计各种状态的槽位数量
+        let mut empty_count = 0;
+        let mut writing_count = 0;
+        let mut in_progress_count = 0;
+        let mut reading_count = 0;
+        let mut ready_count = 0;
+
+        // 遍历所有槽位统计状态
+        for i in 0..pipe.capacity() {
+            match pipe
+                .slot(i)
+                .state
+                .load(std::sync::atomic::Ordering::Acquire)
+            {
+                x if x == SlotState::EMPTY as u32 => empty_count += 1,
+                x if x == SlotState::WRITING as u32 => writing_count += 1,
+                x if x == SlotState::INPROGRESS as u32 => in_progress_count += 1,
+                x if x == SlotState::READING as u32 => reading_count += 1,
+                x if x == SlotState::READY as u32 => ready_count += 1,
+                _ => {} // 未知状态，忽略
+            }
+        }
+
+        let used_count = pipe.capacity() - empty_count;
+
+        PipeStatus {
+            capacity: pipe.capacity(),
+            slot_size: pipe.slot_size(),
+            write_pointer,
+            read_pointer,
+            empty_count,
+            writing_count,
+            in_progress_count,
+            reading_count,
+            ready_count,
+            used_count,
+            mode: pipe.mode(),
+            codec: pipe.codec(),
+            integrity: pipe.integrity(),
+            reclaimed_count: pipe.reclaimed_count(),
+            backpressured: pipe.header().backpressured.load(Ordering::Acquire),
+            expired_count: pipe.expired_count(),
+            redelivered_count: pipe.redelivered_count(),
+            dead_lettered_count: pipe.dead_lettered_count(),
+            sent_count: pipe.sent_count(),
+            received_count: pipe.received_count(),
+            lock_contended_count: pipe.lock_contended_count(),
+            dropped_count: pipe.dropped_count(),
+        }
+    }
+
+    /// 逐个列出槽位的状态，只读取元数据，不改变槽位
+    pub fn slots(&self) -> Vec<SlotInfo> {
+        let now = crate::shm_sync::monotonic_millis();
+        (0..self.capacity())
+            .map(|index| {
+                let slot = self.pipe.slot(index);
+                let leased_at = slot.leased_at.load(Ordering::Acquire);
+                SlotInfo {
+                    index,
+                    state: unsafe { self.pipe.get_slot_state(index) }.ok(),
+                    request_id: slot.request_id,
+                    data_size: slot.payload_size(),
+                    abandoned: slot.is_abandoned(),
+                    delivery_attempts: slot.delivery_attempts,
+                    leased_for: (leased_at != 0)
+                        .then(|| Duration::from_millis(now.saturating_sub(leased_at))),
+                }
+            })
+            .collect()
+    }
+
+    /// 返回下一条待读取消息的副本及其 request_id，不移动读指针也不改变槽位状态
+    ///
+    /// 从读指针开始查找第一个 READY 槽位，队列为空时返回 `None`。
+    /// 大负载引用与已过期的消息按原样返回。
+    pub fn peek(&self) -> Result<Option<(u64, Message)>> {
+        let capacity = self.capacity();
+        let start = self.pipe.pointers().1;
+        for index in (0..capacity).map(|i| (start + i) % capacity) {
+            if matches!(self.get_slot_state(index), Ok(SlotState::READY))
+                && let Some(found) = self.peek_at(index)?
+            {
+                return Ok(Some(found));
+            }
+        }
+        Ok(None)
+    }
+
+    /// 返回指定槽位中消息的副本，不改变槽位状态
+    ///
+    /// 包括 READY 槽位与读取方已取出但尚未确认的槽位；槽位为空、正在写入或复制期间被取走时
+    /// 返回 `None`，校验或认证失败时返回错误。
+    pub fn peek_at(&self, index: usize) -> Result<Option<(u64, Message)>> {
+        let Some((request_id, data)) = self.peek_bytes_at(index)? else {
+            return Ok(None);
+        };
+        Ok(Some((request_id, self.decode_payload(&data)?)))
+    }
+
+    /// 与 [`DynCrossProcessPipe::peek_at`] 相同，但返回未解码的槽位数据（加密管道中已解密）
+    pub fn peek_bytes_at(&self, index: usize) -> Result<Option<(u64, Vec<u8>)>> {
+        let mut pipe = *self.pipe;
+        let Some(entry) = (unsafe { pipe.copy_slot(index) })? else {
+            return Ok(None);
+        };
+        let data = match &self.cipher {
+            Some(cipher) => cipher.open(&entry.data)?,
+            None => entry.data,
+        };
+        Ok(Some((entry.request_id, data)))
+    }
+
+    /// 获取写入到读取的延迟统计
+    pub fn metrics(&self) -> PipeMetrics {
+        let (counts, sum_nanos) = self.pipe.latency_histogram();
+        let arrivals = self.pipe.arrival_stats();
+        PipeMetrics {
+            arrival_gap: arrivals.gap.unwrap_or_default(),
+            poll_interval: arrivals.poll_interval(),
+            ..PipeMetrics::from_histogram(&counts, sum_nanos)
+        }
+    }
+
+    /// 回收被抢占超过 `timeout` 仍未完成的槽位（持有进程崩溃或卡死），返回回收数量
+    pub fn reclaim_stuck(&self, timeout: Duration) -> usize {
+        let mut pipe = *self.pipe;
+        let reclaimed = unsafe { pipe.reclaim_stuck(timeout) };
+        if reclaimed > 0 {
+            tracing::warn!("管道 {} 回收了 {} 个租约超时的槽位", self.name, reclaimed);
+            // 回收的写者槽位可能以空槽发布，唤醒异步读者跳过它
+            self.notify();
+            self.notify_space();
+        }
+        reclaimed
+    }
+
+    /// 把尚未处理完成的消息保存到快照文件，返回保存的消息数量
+    ///
+    /// 不消费也不修改队列，可在运行中调用；为了得到一致的备份，应先停止生产方与消费方。
+    /// 读取方已取出但尚未确认的消息同样被保存。快照格式见 [`crate::snapshot`]。
+    pub fn snapshot(&self, path: impl AsRef<Path>) -> Result<usize> {
+        let mut pipe = *self.pipe;
+        let snapshot = unsafe { pipe.snapshot() };
+        snapshot.save(path)?;
+        Ok(snapshot.entries.len())
+    }
+
+    /// 把快照文件中的消息按原 request_id 顺序重新放入队列，返回恢复的消息数量
+    ///
+    /// 本队列的编解码方式、校验算法与密钥标识必须与快照一致，空槽位必须足以容纳全部消息；
+    /// 不满足时不写入任何消息。
+    pub fn restore(&self, path: impl AsRef<Path>) -> Result<usize> {
+        let snapshot = QueueSnapshot::load(path)?;
+        self.check_snapshot(&snapshot)?;
+        // 源管道已分配过的 request_id 不再复用
+        self.pipe
+            .header()
+            .seq
+            .fetch_max(snapshot.next_request_id, Ordering::Relaxed);
+
+        for entry in &snapshot.entries {
+            let index = self.hold()?;
+            let mut pipe = *self.pipe;
+            unsafe { pipe.restore_entry(index, entry) }
+                .with_context(|| format!("恢复消息 {} 失败", entry.request_id))?;
+            self.notify();
+        }
+        Ok(snapshot.entries.len())
+    }
+
+    /// 以 `capacity` 个槽位创建本管道的继任者 `name`，沿用其余配置与负载加密器，供扩容使用
+    pub(crate) fn create_successor(&self, name: &str, capacity: usize) -> Result<Self> {
+        let config = PipeConfig {
+            capacity,
+            ..self.config()
+        };
+        Self::create_with_cipher(name, config, self.cipher.clone())
+    }
+
+    /// 本管道的 request_id 接着 `previous` 已分配的继续，同一逻辑队列的各段之间不重复
+    ///
+    /// 为 `previous` 中仍在写入的槽位预留与其容量相同的编号。
+    pub(crate) fn continue_sequence(&self, previous: &DynCrossProcessPipe) {
+        let next = previous.pipe.header().seq.load(Ordering::Relaxed) + previous.capacity() as u64;
+        self.pipe.header().seq.fetch_max(next, Ordering::Relaxed);
+    }
+
+    /// 把 READY 消息移到 `target` 后调用 `switch`，返回移动的数量
+    ///
+    /// 见 [`DynSharedSlotPipe::migrate_ready`]。完成后唤醒本管道上等待的读者与写者，
+    /// 使它们发现写者已被切换。
+    pub(crate) fn migrate_to(
+        &self,
+        target: &DynCrossProcessPipe,
+        switch: impl FnOnce(),
+    ) -> Result<usize> {
+        let mut pipe = *self.pipe;
+        let mut target_pipe = *target.pipe;
+        let moved = unsafe { pipe.migrate_ready(&mut target_pipe, switch) }?;
+        target.notify();
+        self.notify();
+        self.notify_space();
+        Ok(moved)
+    }
+
+    /// 确认快照可以写回本队列
+    fn check_snapshot(&self, snapshot: &QueueSnapshot) -> Result<()> {
+        let header = self.pipe.header();
+        if (snapshot.codec, snapshot.integrity) != (header.codec, header.integrity) {
+            return Err(
+                anyhow::anyhow!("快照的编解码方式或校验算法与管道 {} 不一致", self.name).into(),
+            );
+        }
+        if (snapshot.encryption, snapshot.key_id, snapshot.auth_key_id)
+            != (header.encryption, header.key_id, header.auth_key_id)
+        {
+            return Err(
+                anyhow::anyhow!("快照的加密或认证密钥标识与管道 {} 不一致", self.name).into(),
+            );
+        }
+        if let Some(entry) = snapshot
+            .entries
+            .iter()
+            .find(|entry| entry.data.len() > self.slot_size())
+        {
+            return Err(anyhow::anyhow!(
+                "快照消息 {} 为 {} 字节，超过管道 {} 的槽位大小 {} 字节",
+                entry.request_id,
+                entry.data.len(),
+                self.name,
+                self.slot_size()
+            )
+            .into());
+        }
+        let free = self.capacity() - self.pipe.used_slots();
+        if snapshot.entries.len() > free {
+            return Err(anyhow::anyhow!(
+                "管道 {} 只有 {} 个空槽位，不足以恢复快照中的 {} 条消息",
+                self.name,
+                free,
+                snapshot.entries.len()
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// 获取队列配置，水位与最大投递次数以共享内存头部当前记录的为准
+    pub fn config(&self) -> PipeConfig {
+        let (high, low) = self.pipe.watermarks();
+        self.config
+            .with_watermarks(high, low)
+            .with_max_delivery_attempts(self.pipe.max_delivery_attempts())
+    }
+
+    /// 获取队列容量
+    pub fn capacity(&self) -> usize {
+        self.pipe.capacity()
+    }
+
+    /// 获取槽位大小
+    pub fn slot_size(&self) -> usize {
+        self.pipe.slot_size()
+    }
+
+    /// 获取槽位状态
+    pub fn get_slot_state(&self, index: usize) -> Result<SlotState> {
+        unsafe { self.pipe.get_slot_state(index) }
+    }
+
+    /// 槽位是否为无锁模式下写入失败被放弃的槽位，见 [`SlotInfo::abandoned`]
+    pub fn is_slot_abandoned(&self, index: usize) -> bool {
+        index < self.capacity() && self.pipe.slot(index).is_abandoned()
+    }
+}
+
+/// 为DynCrossProcessPipe实现DynamicPipe trait
+impl DynamicPipe for DynCrossProcessPipe {
+    fn hold(&self) -> Result<usize> {
+        self.hold()
+    }
+
+    fn send(&self, index: usize, message: Message) -> Result<u64> {
+        self.send(index, message)
+    }
+
+    fn fetch(&self) -> Result<usize> {
+        self.fetch()
+    }
+
+    fn fetch_async(&self) -> Pin<Box<dyn Future<Output = Result<usize>> + Send + '_>> {
+        Box::pin(self.fetch_async())
+    }
+
+    fn hold_async(&self) -> Pin<Box<dyn Future<Output = Result<usize>> + Send + '_>> {
+        Box::pin(self.hold_async())
+    }
+
+    fn send_async(
+        &self,
+        message: Message,
+    ) -> Pin<Box<dyn Future<Output = Result<u64>> + Send + '_>> {
+        Box::pin(self.send_async(message))
+    }
+
+    fn receive(&self, index: usize) -> Result<Message> {
+        self.receive(index)
+    }
+
+    fn send_tagged(&self, index: usize, request_id: u64, message: Message) -> Result<u64> {
+        self.send_tagged(index, request_id, message)
+    }
+
+    fn receive_tagged(&self, index: usize) -> Result<(u64, Message)> {
+        self.receive_tagged(index)
+    }
+
+    fn send_blocking(&self, message: Message, timeout: Duration) -> Result<u64> {
+        self.send_blocking(message, timeout)
+    }
+
+    fn receive_blocking(&self, timeout: Duration) -> Result<Message> {
+        self.receive_blocking(timeout)
+    }
+
+    fn try_send(&self, message: Message) -> Result<Option<u64>> {
+        self.try_send(message)
+    }
+
+    fn send_timeout(&self, message: Message, timeout: Duration) -> Result<u64> {
+        self.send_timeout(message, timeout)
+    }
+
+    fn get_slot_state(&self, index: usize) -> Result<SlotState> {
+        self.get_slot_state(index)
+    }
+
+    fn is_slot_abandoned(&self, index: usize) -> bool {
+        self.is_slot_abandoned(index)
+    }
+
+    fn status(&self) -> PipeStatus {
+        self.status()
+    }
+
+    fn metrics(&self) -> PipeMetrics {
+        self.metrics()
+    }
+
+    fn config(&self) -> PipeConfig {
+        self.config()
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity()
+    }
+
+    fn slot_size(&self) -> usize {
+        self.slot_size()
+    }
+
+    fn name(&self) -> &str {
+        self.name()
+    }
+
+    fn unlink(&self) -> Result<()> {
+        self.unlink()
+    }
+
+    fn attached_processes(&self) -> Vec<u32> {
+        self.attached_processes()
+    }
+
+    fn reclaim_stuck(&self, timeout: Duration) -> usize {
+        self.reclaim_stuck(timeout)
+    }
+
+    fn is_backpressured(&self) -> bool {
+        self.is_backpressured()
+    }
+
+    fn receive_unacked(&self, index: usize) -> Result<(u64, Message)> {
+        self.receive_unacked(index)
+    }
+
+    fn ack(&self, index: usize) -> Result<()> {
+        self.ack(index)
+    }
+
+    fn nack(&self, index: usize) -> Result<bool> {
+        self.nack(index)
+    }
+
+    fn peek(&self) -> Result<Option<(u64, Message)>> {
+        self.peek()
+    }
+
+    fn peek_at(&self, index: usize) -> Result<Option<(u64, Message)>> {
+        self.peek_at(index)
+    }
+
+    fn has_ready(&self) -> bool {
+        self.has_ready()
+    }
+
+    fn notify_fd(&self) -> Option<RawFd> {
+        self.notify_fd()
+    }
+}
+
+impl Drop for DynCrossProcessPipe {
+    fn drop(&mut self) {
+        if let Some(index) = self.attach_index {
+            self.pipe.detach(index);
+        }
+        // 停止接受握手后再删除共享内存名称
+        self.handshake_server.take();
+
+        if self.owner
+            && let Err(e) = self.unlink()
+        {
+            tracing::warn!("删除共享内存段 {} 失败: {}", self.name, e);
+        }
+    }
+}
+
+/// 编译期确定容量与槽位大小的跨进程管道
+///
+/// 内部即 [`DynCrossProcessPipe`]，所有方法通过 `Deref` 转发；
+/// 连接时校验共享内存头部记录的容量与槽位大小和类型参数一致。
+pub struct CrossProcessPipe<const CAPACITY: usize, const SLOT_SIZE: usize> {
+    inner: DynCrossProcessPipe,
+}
+
+impl<const CAPACITY: usize, const SLOT_SIZE: usize> CrossProcessPipe<CAPACITY, SLOT_SIZE> {
+    /// 创建新的队列
+    pub fn create(name: &str) -> Result<Self> {
+        Self::create_with_mode(name, PipeMode::Locked)
+    }
+
+    /// 使用指定并发模式创建新的队列
+    ///
+    /// [`PipeMode::LockFree`] 下多生产者/多消费者通过 CAS 抢占槽位，
+    /// 仅在需要休眠等待时才使用共享互斥锁。
+    pub fn create_with_mode(name: &str, mode: PipeMode) -> Result<Self> {
+        Self::create_with_config(name, PipeConfig::new(CAPACITY, SLOT_SIZE).with_mode(mode))
+    }
+
+    /// 使用配置创建新的队列
+    ///
+    /// 并发模式与编解码方式写入共享内存头部，连接方据此保持一致
+    pub fn create_with_config(name: &str, config: PipeConfig) -> Result<Self> {
+        // 注意：容量与槽位大小在编译时确定，这里主要用于验证
+        if config.capacity != CAPACITY || config.slot_size != SLOT_SIZE {
+            return Err(anyhow::anyhow!(
+                "配置不匹配：期望 capacity={}, slot_size={}，实际 capacity={}, slot_size={}",
+                CAPACITY,
+                SLOT_SIZE,
+                config.capacity,
+                config.slot_size
+            )
+            .into());
+        }
+
+        Ok(Self {
+            inner: DynCrossProcessPipe::create_with_config(name, config)?,
+        })
+    }
+
+    /// 连接到现有队列
+    pub fn connect(name: &str) -> Result<Self> {
+        Ok(Self {
+            inner: DynCrossProcessPipe::connect_with_layout(name, CAPACITY, SLOT_SIZE)?,
+        })
+    }
+
+    /// 创建（或恢复）映射普通文件的持久化队列，见 [`DynCrossProcessPipe::create_persistent`]
+    pub fn create_persistent(name: &str, path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self {
+            inner: DynCrossProcessPipe::create_persistent(
+                name,
+                path,
+                PipeConfig::new(CAPACITY, SLOT_SIZE),
+            )?,
+        })
+    }
+
+    /// 删除共享内存名称并释放映射
+    pub fn destroy(self) -> Result<()> {
+        self.inner.destroy()
+    }
+
+    /// 关联共享寄存箱，见 [`DynCrossProcessPipe::with_mailbox`]
+    pub fn with_mailbox(self, mailbox: Arc<SharedMemoryMailbox>) -> Self {
+        Self {
+            inner: self.inner.with_mailbox(mailbox),
+        }
+    }
+
+    /// 关联死信管道，见 [`DynCrossProcessPipe::with_dead_letter`]
+    pub fn with_dead_letter(self, dead_letter: Arc<dyn DynamicPipe>) -> Self {
+        Self {
+            inner: self.inner.with_dead_letter(dead_letter),
+        }
+    }
+
+    /// 转换为运行时尺寸的管道
+    pub fn into_dyn(self) -> DynCrossProcessPipe {
+        self.inner
+    }
+}
+
+impl<const CAPACITY: usize, const SLOT_SIZE: usize> Deref
+    for CrossProcessPipe<CAPACITY, SLOT_SIZE>
+{
+    type Target = DynCrossProcessPipe;
+
+    fn deref(&self) -> &DynCrossProcessPipe {
+        &self.inner
+    }
+}
+
+impl<const CAPACITY: usize, const SLOT_SIZE: usize> DerefMut
+    for CrossProcessPipe<CAPACITY, SLOT_SIZE>
+{
+    fn deref_mut(&mut self) -> &mut DynCrossProcessPipe {
+        &mut self.inner
+    }
+}
+
+/// 为CrossProcessPipe实现DynamicPipe trait
+impl<const CAPACITY: usize, const SLOT_SIZE: usize> DynamicPipe
+    for CrossProcessPipe<CAPACITY, SLOT_SIZE>
+{
+    fn hold(&self) -> Result<usize> {
+        self.inner.hold()
+    }
+
+    fn send(&self, index: usize, message: Message) -> Result<u64> {
+        self.inner.send(index, message)
+    }
+
+    fn fetch(&self) -> Result<usize> {
+        self.inner.fetch()
+    }
+
+    fn fetch_async(&self) -> Pin<Box<dyn Future<Output = Result<usize>> + Send + '_>> {
+        Box::pin(self.inner.fetch_async())
+    }
+
+    fn hold_async(&self) -> Pin<Box<dyn Future<Output = Result<usize>> + Send + '_>> {
+        Box::pin(self.inner.hold_async())
+    }
+
+    fn send_async(
+        &self,
+        message: Message,
+    ) -> Pin<Box<dyn Future<Output = Result<u64>> + Send + '_>> {
+        Box::pin(self.inner.send_async(message))
+    }
+
+    fn receive(&self, index: usize) -> Result<Message> {
+        self.inner.receive(index)
+    }
+
+    fn send_tagged(&self, index: usize, request_id: u64, message: Message) -> Result<u64> {
+        self.inner.send_tagged(index, request_id, message)
+    }
+
+    fn receive_tagged(&self, index: usize) -> Result<(u64, Message)> {
+        self.inner.receive_tagged(index)
+    }
+
+    fn send_blocking(&self, message: Message, timeout: Duration) -> Result<u64> {
+        self.inner.send_blocking(message, timeout)
+    }
+
+    fn receive_blocking(&self, timeout: Duration) -> Result<Message> {
+        self.inner.receive_blocking(timeout)
+    }
+
+    fn try_send(&self, message: Message) -> Result<Option<u64>> {
+        self.inner.try_send(message)
+    }
+
+    fn send_timeout(&self, message: Message, timeout: Duration) -> Result<u64> {
+        self.inner.send_timeout(message, timeout)
+    }
+
+    fn get_slot_state(&self, index: usize) -> Result<SlotState> {
+        self.inner.get_slot_state(index)
+    }
+
+    fn is_slot_abandoned(&self, index: usize) -> bool {
+        self.inner.is_slot_abandoned(index)
+    }
+
+    fn status(&self) -> PipeStatus {
+        self.inner.status()
+    }
+
+    fn metrics(&self) -> PipeMetrics {
+        self.inner.metrics()
+    }
+
+    fn config(&self) -> PipeConfig {
+        self.inner.config()
+    }
+
+    fn capacity(&self) -> usize {
+        CAPACITY
+    }
+
+    fn slot_size(&self) -> usize {
+        SLOT_SIZE
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn unlink(&self) -> Result<()> {
+        self.inner.unlink()
+    }
+
+    fn attached_processes(&self) -> Vec<u32> {
+        self.inner.attached_processes()
+    }
+
+    fn reclaim_stuck(&self, timeout: Duration) -> usize {
+        self.inner.reclaim_stuck(timeout)
+    }
+
+    fn is_backpressured(&self) -> bool {
+        self.inner.is_backpressured()
+    }
+    fn receive_unacked(&self, index: usize) -> Result<(u64, Message)> {
+        self.inner.receive_unacked(index)
+    }
+
+    fn ack(&self, index: usize) -> Result<()> {
+        self.inner.ack(index)
+    }
+
+    fn nack(&self, index: usize) -> Result<bool> {
+        self.inner.nack(index)
+    }
+
+    fn peek(&self) -> Result<Option<(u64, Message)>> {
+        self.inner.peek()
+    }
+
+    fn peek_at(&self, index: usize) -> Result<Option<(u64, Message)>> {
+        self.inner.peek_at(index)
+    }
+
+    fn has_ready(&self) -> bool {
+        self.inner.has_ready()
+    }
+
+    fn notify_fd(&self) -> Option<RawFd> {
+        self.inner.notify_fd()
+    }
+}
+
+/// 动态管道工厂，支持根据配置创建不同类型的管道
+pub struct PipeFactory;
+
+impl PipeFactory {
+    /// 根据字符串类型创建管道
+    ///
+    /// `memory` / `memory(容量x槽位大小)` 创建进程内的 [`HeapSlotPipe`]，供测试使用
+    pub fn create(pipe_type_str: &str, name: &str) -> Result<Box<dyn DynamicPipe>> {
+        if let Some(pipe_type) = Self::parse_prefixed("memory", pipe_type_str)? {
+            return Ok(Box::new(HeapSlotPipe::create(name, pipe_type.config())?));
+        }
+        if let Some(pipe_type) = Self::parse_prefixed("uds", pipe_type_str)? {
+            return Ok(Box::new(UdsPipe::create(name, pipe_type.config())?));
+        }
+        let pipe_type = PipeType::from_str(pipe_type_str)
+            .map_err(|e| anyhow::anyhow!("无效的管道类型: {}", e))?;
         Self::create_pipe(pipe_type, name)
     }
 
-    /// 连接到现有管道
-    pub fn connect_pipe(pipe_type: PipeType, name: &str) -> Result<Box<dyn DynamicPipe>> {
-        match pipe_type {
-            PipeType::Small => {
-                let pipe = CrossProcessPipe::<10, 1024>::connect(name)?;
-                Ok(Box::new(pipe))
+    /// 根据字符串类型连接到现有管道
+    pub fn connect(pipe_type_str: &str, name: &str, create: bool) -> Result<Box<dyn DynamicPipe>> {
+        if let Some(pipe_type) = Self::parse_prefixed("memory", pipe_type_str)? {
+            let config = pipe_type.config();
+            return match HeapSlotPipe::connect_with_layout(name, config.capacity, config.slot_size)
+            {
+                Ok(pipe) => Ok(Box::new(pipe)),
+                Err(_) if create => Ok(Box::new(HeapSlotPipe::create(name, config)?)),
+                Err(e) => Err(e),
+            };
+        }
+        if let Some(pipe_type) = Self::parse_prefixed("uds", pipe_type_str)? {
+            let config = pipe_type.config();
+            return match UdsPipe::connect_with_layout(name, config.capacity, config.slot_size) {
+                Ok(pipe) => Ok(Box::new(pipe)),
+                Err(_) if create => Ok(Box::new(UdsPipe::create(name, config)?)),
+                Err(e) => Err(e),
+            };
+        }
+        let pipe_type = PipeType::from_str(pipe_type_str)
+            .map_err(|e| anyhow::anyhow!("无效的管道类型: {}", e))?;
+        // 先尝试连接现有管道
+        match Self::connect_pipe(pipe_type, name) {
+            Ok(pipe) => Ok(pipe),
+            Err(_) => {
+                if create {
+                    // 连接失败则创建新管道
+                    Self::create_pipe(pipe_type, name)
+                } else {
+                    Err(anyhow::anyhow!("管道未找到").into())
+                }
+            }
+        }
+    }
+
+    /// 解析非共享内存的管道类型（进程内 `memory`、套接字 `uds`）：
+    /// 单独的前缀使用默认布局，`前缀(容量x槽位大小)` 指定布局
+    fn parse_prefixed(prefix: &str, pipe_type_str: &str) -> Result<Option<PipeType>> {
+        let lower = pipe_type_str.trim().to_lowercase();
+        let Some(rest) = lower.strip_prefix(prefix) else {
+            return Ok(None);
+        };
+        if rest.is_empty() {
+            return Ok(Some(PipeType::Default));
+        }
+        rest.strip_prefix('(')
+            .and_then(|rest| rest.strip_suffix(')'))
+            .and_then(PipeType::parse_custom)
+            .map(Some)
+            .ok_or_else(|| anyhow::anyhow!("无效的管道类型: {}", pipe_type_str).into())
+    }
+
+    /// 按共享内存头部记录的容量与槽位大小连接现有管道
+    pub fn open(name: &str) -> Result<Box<dyn DynamicPipe>> {
+        Ok(Box::new(DynCrossProcessPipe::connect(name)?))
+    }
+
+    /// 根据管道类型创建管道
+    pub fn create_pipe(pipe_type: PipeType, name: &str) -> Result<Box<dyn DynamicPipe>> {
+        Self::create_with_config(pipe_type.config(), name)
+    }
+
+    /// 根据管道类型和并发模式创建管道
+    pub fn create_pipe_with_mode(
+        pipe_type: PipeType,
+        name: &str,
+        mode: PipeMode,
+    ) -> Result<Box<dyn DynamicPipe>> {
+        Self::create_with_config(pipe_type.config().with_mode(mode), name)
+    }
+
+    /// 根据配置创建管道（容量、槽位大小、并发模式、编解码方式）
+    pub fn create_with_config(config: PipeConfig, name: &str) -> Result<Box<dyn DynamicPipe>> {
+        config
+            .validate()
+            .map_err(|e| anyhow::anyhow!("配置验证失败: {}", e))?;
+        Ok(Box::new(DynCrossProcessPipe::create_with_config(
+            name, config,
+        )?))
+    }
+
+    /// 连接到现有管道，头部记录的容量与槽位大小必须与管道类型一致
+    pub fn connect_pipe(pipe_type: PipeType, name: &str) -> Result<Box<dyn DynamicPipe>> {
+        let config = pipe_type.config();
+        Ok(Box::new(DynCrossProcessPipe::connect_with_layout(
+            name,
+            config.capacity,
+            config.slot_size,
+        )?))
+    }
+
+    /// 根据配置连接到现有管道
+    pub fn connect_with_config(config: PipeConfig, name: &str) -> Result<Box<dyn DynamicPipe>> {
+        let pipe_type = PipeType::from_config(config);
+        Self::connect_pipe(pipe_type, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    fn unique_name(tag: &str) -> String {
+        format!("mi7_test_{}_{}", tag, std::process::id())
+    }
+
+    #[test]
+    fn test_receive_blocking_wakes_on_send() {
+        let name = unique_name("blocking");
+        let pipe = Arc::new(CrossProcessPipe::<4, 256>::create(&name).unwrap());
+
+        let consumer = {
+            let pipe = Arc::clone(&pipe);
+            std::thread::spawn(move || pipe.receive_blocking(Duration::from_secs(5)))
+        };
+
+        std::thread::sleep(Duration::from_millis(50));
+        pipe.send_blocking(Message::init("hello".to_string()), Duration::from_secs(1))
+            .unwrap();
+
+        let message = consumer.join().unwrap().unwrap();
+        assert_eq!(message.data, b"hello");
+    }
+
+    #[test]
+    fn test_lock_free_mpmc() {
+        const PRODUCERS: u64 = 4;
+        const PER_PRODUCER: u64 = 200;
+
+        let name = unique_name("lockfree");
+        let pipe = Arc::new(
+            CrossProcessPipe::<8, 256>::create_with_mode(&name, PipeMode::LockFree).unwrap(),
+        );
+        assert_eq!(pipe.status().mode, PipeMode::LockFree);
+
+        let producers: Vec<_> = (0..PRODUCERS)
+            .map(|p| {
+                let pipe = Arc::clone(&pipe);
+                std::thread::spawn(move || {
+                    for i in 0..PER_PRODUCER {
+                        let value = p * PER_PRODUCER + i;
+                        pipe.send_blocking(
+                            Message::init(value.to_string()),
+                            Duration::from_secs(5),
+                        )
+                        .unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        let consumers: Vec<_> = (0..4)
+            .map(|_| {
+                let pipe = Arc::clone(&pipe);
+                std::thread::spawn(move || {
+                    let mut received = Vec::new();
+                    while let Ok(message) = pipe.receive_blocking(Duration::from_millis(200)) {
+                        let text = String::from_utf8(message.data).unwrap();
+                        received.push(text.parse::<u64>().unwrap());
+                    }
+                    received
+                })
+            })
+            .collect();
+
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        let mut all: Vec<u64> = consumers
+            .into_iter()
+            .flat_map(|c| c.join().unwrap())
+            .collect();
+        all.sort_unstable();
+
+        let expected: Vec<u64> = (0..PRODUCERS * PER_PRODUCER).collect();
+        assert_eq!(all, expected);
+    }
+
+    #[test]
+    fn test_lock_free_empty_payload() {
+        let name = unique_name("lockfree_empty");
+        let pipe = CrossProcessPipe::<4, 64>::create_with_mode(&name, PipeMode::LockFree).unwrap();
+        let timeout = Duration::from_secs(1);
+
+        // 写入失败被放弃的槽位夹在两条空消息之间，消费者跳过它，空消息照常送达
+        let index = pipe.hold().unwrap();
+        pipe.send_with(index, |_| 0).unwrap();
+        let index = pipe.hold().unwrap();
+        assert!(
+            pipe.try_send_with(index, |_| Err(anyhow::anyhow!("fill failed").into()))
+                .is_err()
+        );
+        pipe.send_blocking(Message::init(String::new()), timeout)
+            .unwrap();
+
+        let index = pipe.fetch().unwrap();
+        assert_eq!(pipe.receive_with(index, |buf| buf.len()).unwrap(), 0);
+        assert!(pipe.receive_blocking(timeout).unwrap().data.is_empty());
+        assert!(pipe.receive_blocking(Duration::from_millis(50)).is_err());
+        assert_eq!(pipe.status().empty_count, 4);
+    }
+
+    #[test]
+    fn test_owner_unlinks_on_drop() {
+        let name = unique_name("unlink");
+        let path = format!("/dev/shm/{}", name);
+
+        let pipe = CrossProcessPipe::<2, 128>::create(&name).unwrap();
+        let peer = CrossProcessPipe::<2, 128>::connect(&name).unwrap();
+        assert!(pipe.is_owner());
+        assert!(!peer.is_owner());
+        assert_eq!(pipe.attached_count(), 2);
+        assert_eq!(peer.attached_processes(), vec![std::process::id()]);
+        assert!(SharedMemoryRegistry::owned().contains(&name));
+
+        drop(peer);
+        assert_eq!(pipe.attached_count(), 1);
+        assert!(std::path::Path::new(&path).exists());
+
+        drop(pipe);
+        assert!(!std::path::Path::new(&path).exists());
+        assert!(!SharedMemoryRegistry::owned().contains(&name));
+        assert!(CrossProcessPipe::<2, 128>::connect(&name).is_err());
+
+        let mut pipe = CrossProcessPipe::<2, 128>::create(&name).unwrap();
+        pipe.persist();
+        drop(pipe);
+        assert!(std::path::Path::new(&path).exists());
+
+        // 无进程映射的遗留段会被清理
+        let removed = SharedMemoryRegistry::cleanup_stale(&name).unwrap();
+        assert_eq!(removed, vec![name.clone()]);
+        assert!(!std::path::Path::new(&path).exists());
+    }
+
+    #[test]
+    fn test_mappings_released_on_drop() {
+        use crate::encryption::PayloadKey;
+
+        let name = unique_name("mappings");
+        let path = format!("/dev/shm/{}", name);
+        let mapped = |path: &str| {
+            let deleted = format!("{} (deleted)", path);
+            std::fs::read_to_string("/proc/self/maps")
+                .unwrap()
+                .lines()
+                .filter(|line| line.ends_with(path) || line.ends_with(&deleted))
+                .count()
+        };
+
+        let cipher = Arc::new(PayloadCipher::new(&PayloadKey::from_bytes([5; 32])));
+        let config = PipeConfig::new(2, 128);
+        let pipe =
+            DynCrossProcessPipe::create_with_cipher(&name, config, Some(Arc::clone(&cipher)))
+                .unwrap();
+        assert_eq!(mapped(&path), 1);
+
+        // 反复重连：每次连接的映射在 Drop 时解除，校验失败的连接也不遗留映射
+        for _ in 0..16 {
+            let peer =
+                DynCrossProcessPipe::connect_with_cipher(&name, Some(Arc::clone(&cipher))).unwrap();
+            assert_eq!(mapped(&path), 2);
+            drop(peer);
+            assert!(DynCrossProcessPipe::connect_with_cipher(&name, None).is_err());
+        }
+        assert_eq!(mapped(&path), 1);
+
+        // 原始视图交给 SlotMapping 后同样在 Drop 时解除
+        let mapping = unsafe { SlotMapping::new(DynSharedSlotPipe::connect(&name).unwrap()) };
+        assert_eq!(mapped(&path), 2);
+        drop(mapping);
+        drop(pipe);
+        assert_eq!(mapped(&path), 0);
+
+        // 创建者可以让所有者在 Drop 时同时删除共享内存段
+        let view = unsafe {
+            DynSharedSlotPipe::create(
+                &name,
+                2,
+                128,
+                PipeMode::Locked,
+                CodecKind::Raw,
+                Integrity::default(),
+                MutexAttr::default(),
+            )
+            .unwrap()
+        };
+        let mapping = unsafe { SlotMapping::new(view) }.unlink_on_drop(&name);
+        assert_eq!(mapped(&path), 1);
+        drop(mapping);
+        assert_eq!(mapped(&path), 0);
+        assert!(!std::path::Path::new(&path).exists());
+    }
+
+    #[test]
+    fn test_zero_copy_send_receive() {
+        let name = unique_name("zerocopy");
+        let pipe = CrossProcessPipe::<2, 64>::create(&name).unwrap();
+        let payload: Vec<u8> = (0..64).collect();
+
+        let index = pipe.hold().unwrap();
+        pipe.send_with(index, |buf| {
+            buf[..payload.len()].copy_from_slice(&payload);
+            payload.len()
+        })
+        .unwrap();
+
+        let index = pipe.fetch().unwrap();
+        let sum = pipe
+            .receive_with(index, |buf| buf.iter().map(|&b| b as u32).sum::<u32>())
+            .unwrap();
+        assert_eq!(sum, (0..64).sum::<u32>());
+
+        // 超出槽位大小的写入被拒绝，槽位归还
+        let index = pipe.hold().unwrap();
+        assert!(pipe.send_with(index, |_| 65).is_err());
+        assert_eq!(pipe.status().empty_count, 2);
+    }
+
+    #[test]
+    fn test_pinned_slot_released_on_drop() {
+        use crate::encryption::PayloadKey;
+
+        let payload: Vec<u8> = (0..64).collect();
+        let cipher = Arc::new(PayloadCipher::new(&PayloadKey::from_bytes([6; 32])));
+        for cipher in [None, Some(cipher)] {
+            let name = unique_name("pinned");
+            let pipe =
+                DynCrossProcessPipe::create_with_cipher(&name, PipeConfig::new(2, 128), cipher)
+                    .unwrap();
+            let index = pipe.hold().unwrap();
+            let request_id = pipe
+                .try_send_with(index, |buf| {
+                    buf[..payload.len()].copy_from_slice(&payload);
+                    Ok(payload.len())
+                })
+                .unwrap();
+
+            // 持有期间槽位不被释放，drop 后回到 EMPTY
+            let pinned = pipe.fetch_pinned().unwrap();
+            assert_eq!(pinned.request_id(), request_id);
+            assert_eq!(&pinned[..], &payload[..]);
+            assert_eq!(pipe.status().empty_count, 1);
+            drop(pinned);
+            assert_eq!(pipe.status().empty_count, 2);
+        }
+    }
+
+    #[test]
+    fn test_codec_recorded_in_header() {
+        let name = unique_name("codec");
+        let config = PipeConfig::new(4, 256).with_codec(CodecKind::Json);
+        let pipe = CrossProcessPipe::<4, 256>::create_with_config(&name, config).unwrap();
+        let peer = CrossProcessPipe::<4, 256>::connect(&name).unwrap();
+        assert_eq!(peer.config().codec, CodecKind::Json);
+
+        pipe.send_blocking(Message::init("json".to_string()), Duration::from_secs(1))
+            .unwrap();
+        let message = peer.receive_blocking(Duration::from_secs(1)).unwrap();
+        assert_eq!(message.data, b"json");
+    }
+
+    #[test]
+    fn test_encrypted_pipe() {
+        use crate::encryption::PayloadKey;
+
+        let name = unique_name("encrypted");
+        let cipher = Arc::new(PayloadCipher::new(&PayloadKey::from_bytes([3; 32])));
+        let config = PipeConfig::new(4, 256);
+        let pipe =
+            DynCrossProcessPipe::create_with_cipher(&name, config, Some(Arc::clone(&cipher)))
+                .unwrap();
+        assert!(pipe.is_encrypted());
+
+        // 未配置密钥或密钥不符时拒绝连接
+        assert!(DynCrossProcessPipe::connect_with_cipher(&name, None).is_err());
+        let other = Arc::new(PayloadCipher::new(&PayloadKey::from_bytes([4; 32])));
+        assert!(DynCrossProcessPipe::connect_with_cipher(&name, Some(other)).is_err());
+        let peer = DynCrossProcessPipe::connect_with_cipher(&name, Some(cipher)).unwrap();
+
+        // 槽位中只有密文
+        pipe.send_blocking(
+            Message::init("card=4111".to_string()),
+            Duration::from_secs(1),
+        )
+        .unwrap();
+        let index = peer.fetch().unwrap();
+        let mut raw = *peer.pipe;
+        let (_, _, stored) = unsafe { raw.peek_with(index, |buf| Ok(buf.to_vec())) }.unwrap();
+        assert!(!stored.windows(9).any(|w| w == b"card=4111"));
+        assert_eq!(peer.receive(index).unwrap().data, b"card=4111");
+
+        // 绕过加密写入篡改后的密文，读取时报告 CorruptedData
+        let mut tampered = stored;
+        tampered[0] ^= 1;
+        let index = pipe.hold().unwrap();
+        unsafe {
+            raw.write_with(index, |buf| {
+                buf[..tampered.len()].copy_from_slice(&tampered);
+                tampered.len()
+            })
+        }
+        .unwrap();
+        let err = peer.receive_blocking(Duration::from_secs(1)).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<TokioIPCError>(),
+            Some(TokioIPCError::CorruptedData)
+        ));
+    }
+
+    #[test]
+    fn test_slots_report_zero_length_and_abandoned() {
+        let pipe = DynCrossProcessPipe::create_with_config(
+            &unique_name("slots_abandoned"),
+            PipeConfig::new(4, 256).with_mode(PipeMode::LockFree),
+        )
+        .unwrap();
+        let index = pipe.hold().unwrap();
+        pipe.send_with(index, |_| 0).unwrap();
+        let index = pipe.hold().unwrap();
+        assert!(
+            pipe.try_send_with(index, |_| Err(anyhow::anyhow!("fill failed").into()))
+                .is_err()
+        );
+
+        let slots = pipe.slots();
+        let empty = &slots[0];
+        assert_eq!((empty.data_size, empty.abandoned), (0, false));
+        assert!(empty.is_pending());
+        let abandoned = &slots[1];
+        assert_eq!(abandoned.state, Some(SlotState::READY));
+        assert_eq!((abandoned.data_size, abandoned.abandoned), (0, true));
+        assert!(!abandoned.is_pending());
+        assert_eq!(slots.iter().filter(|slot| slot.is_pending()).count(), 1);
+    }
+
+    #[test]
+    fn test_snapshot_and_restore() {
+        let name = unique_name("snapshot");
+        let path = std::env::temp_dir().join(format!("{}.snap", name));
+        let pipe = DynCrossProcessPipe::create_with_config(&name, PipeConfig::new(4, 256)).unwrap();
+        let mut ids = Vec::new();
+        for text in ["a", "b", "c"] {
+            ids.push(
+                pipe.send_blocking(Message::init(text.to_string()), Duration::from_secs(1))
+                    .unwrap(),
+            );
+        }
+        // 已取出但未确认的消息也在快照中
+        let index = pipe.fetch().unwrap();
+        let (first, _) = pipe.receive_unacked(index).unwrap();
+        assert_eq!(first, ids[0]);
+        assert_eq!(pipe.snapshot(&path).unwrap(), 3);
+        pipe.ack(index).unwrap();
+        drop(pipe);
+
+        // 维护后在新队列中恢复：顺序与 request_id 不变，新消息的 request_id 不会重复
+        let pipe = DynCrossProcessPipe::create_with_config(&name, PipeConfig::new(4, 256)).unwrap();
+        assert_eq!(pipe.restore(&path).unwrap(), 3);
+        for (id, text) in ids.iter().zip(["a", "b", "c"]) {
+            let index = pipe.fetch().unwrap();
+            let (request_id, message) = pipe.receive_tagged(index).unwrap();
+            assert_eq!(
+                (request_id, message.data.as_slice()),
+                (*id, text.as_bytes())
+            );
+        }
+        let next = pipe
+            .send_blocking(Message::init("d".to_string()), Duration::from_secs(1))
+            .unwrap();
+        assert!(next > ids[2]);
+
+        // 空槽位不足或编解码方式不一致时不写入任何消息
+        let full = DynCrossProcessPipe::create_with_config(
+            &unique_name("snapshot_small"),
+            PipeConfig::new(2, 256),
+        )
+        .unwrap();
+        assert!(full.restore(&path).is_err());
+        assert_eq!(full.pipe.used_slots(), 0);
+        let json = DynCrossProcessPipe::create_with_config(
+            &unique_name("snapshot_json"),
+            PipeConfig::new(4, 256).with_codec(CodecKind::Json),
+        )
+        .unwrap();
+        assert!(json.restore(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_stale_handles_are_rejected() {
+        use crate::shared_slot::StaleHandle;
+
+        for mode in [PipeMode::Locked, PipeMode::LockFree] {
+            let name = format!("mi7_test_stale_handle_{:?}_{}", mode, std::process::id());
+            let pipe = DynCrossProcessPipe::create_with_config(
+                &name,
+                PipeConfig::new(2, 256).with_mode(mode),
+            )
+            .unwrap();
+
+            // 写者的槽位被回收后旧句柄失效
+            let mut slow = None;
+            let stale = pipe.hold_handle().unwrap();
+            assert_eq!(pipe.reclaim_stuck(Duration::ZERO), 1);
+            let err = pipe
+                .send_at(stale, Message::init("late".to_string()))
+                .unwrap_err();
+            assert!(err.is::<StaleHandle>());
+
+            for data in ["first", "second"] {
+                if data == "second" {
+                    // 慢速消费者的槽位被回收，之后写入下一条消息
+                    assert_eq!(pipe.reclaim_stuck(Duration::ZERO), 1);
+                }
+                let held = pipe.hold_handle().unwrap();
+                pipe.send_at(held, Message::init(data.to_string())).unwrap();
+                if data == "first" {
+                    slow = Some(pipe.fetch_handle().unwrap());
+                }
             }
-            PipeType::Default => {
-                let pipe = CrossProcessPipe::<100, 4096>::connect(name)?;
-                Ok(Box::new(pipe))
+            let (slow, fresh) = (slow.unwrap(), pipe.fetch_handle().unwrap());
+            let err = pipe.receive_at(slow).unwrap_err();
+            assert!(err.is::<StaleHandle>());
+            assert_eq!(pipe.receive_at(fresh).unwrap().data, b"second");
+        }
+    }
+
+    #[test]
+    fn test_peek() {
+        let pipe =
+            DynCrossProcessPipe::create_with_config(&unique_name("peek"), PipeConfig::new(4, 256))
+                .unwrap();
+        assert!(pipe.peek().unwrap().is_none());
+        let ids: Vec<u64> = ["a", "b"]
+            .iter()
+            .map(|text| {
+                pipe.send_blocking(Message::init(text.to_string()), Duration::from_secs(1))
+                    .unwrap()
+            })
+            .collect();
+
+        // 读取副本不改变槽位状态，反复读取得到同一条消息
+        let before = pipe.status();
+        let (request_id, message) = pipe.peek().unwrap().unwrap();
+        assert_eq!((request_id, message.data.as_slice()), (ids[0], &b"a"[..]));
+        assert_eq!(pipe.peek().unwrap().unwrap().0, ids[0]);
+        let after = pipe.status();
+        assert_eq!(after.ready_count, before.ready_count);
+        assert_eq!(after.received_count, before.received_count);
+
+        // 随后的接收仍得到同一条消息，已取出但未确认的槽位可以按下标读取
+        let index = pipe.fetch().unwrap();
+        assert_eq!(pipe.peek_at(index).unwrap().unwrap().0, ids[0]);
+        let (received, _) = pipe.receive_tagged(index).unwrap();
+        assert_eq!(received, ids[0]);
+        assert!(pipe.peek_at(index).unwrap().is_none());
+        assert_eq!(pipe.peek().unwrap().unwrap().0, ids[1]);
+    }
+
+    #[test]
+    fn test_receive_where() {
+        let pipe = DynCrossProcessPipe::create_with_config(
+            &unique_name("receive_where"),
+            PipeConfig::new(4, 256),
+        )
+        .unwrap();
+        for (flag, text) in [(1, "a"), (2, "b"), (1, "c")] {
+            let mut message = Message::init(text.to_string());
+            message.flag = flag;
+            pipe.send_blocking(message, Duration::from_secs(1)).unwrap();
+        }
+
+        // 只取 flag 为 2 的消息，其余消息保持 READY 且顺序不变
+        let (_, message) = pipe.receive_where(|m| m.flag == 2).unwrap().unwrap();
+        assert_eq!(message.data, b"b");
+        assert!(pipe.receive_where(|m| m.flag == 2).unwrap().is_none());
+        assert_eq!(pipe.status().ready_count, 2);
+        for text in ["a", "c"] {
+            let message = pipe.receive_blocking(Duration::from_secs(1)).unwrap();
+            assert_eq!(message.data, text.as_bytes());
+        }
+
+        let lock_free = DynCrossProcessPipe::create_with_config(
+            &unique_name("receive_where_lock_free"),
+            PipeConfig::new(4, 256).with_mode(PipeMode::LockFree),
+        )
+        .unwrap();
+        assert!(lock_free.receive_where(|_| true).is_err());
+    }
+
+    #[test]
+    fn test_authenticated_pipe() {
+        let name = unique_name("authenticated");
+        let key: &'static MessageAuthenticator = Box::leak(Box::new(
+            MessageAuthenticator::new(b"0123456789abcdef").unwrap(),
+        ));
+        let security = SecurityOptions {
+            authenticator: Some(key),
+            ..SecurityOptions::default()
+        };
+        let pipe =
+            DynCrossProcessPipe::create_secured(&name, PipeConfig::new(4, 256), security).unwrap();
+
+        // 未配置密钥或密钥不符时拒绝连接
+        assert!(DynCrossProcessPipe::connect_secured(&name, SecurityOptions::default()).is_err());
+        let other: &'static MessageAuthenticator = Box::leak(Box::new(
+            MessageAuthenticator::new(b"0123456789abcdeF").unwrap(),
+        ));
+        let with = |authenticator| SecurityOptions {
+            authenticator: Some(authenticator),
+            ..SecurityOptions::default()
+        };
+        assert!(DynCrossProcessPipe::connect_secured(&name, with(other)).is_err());
+        let peer = DynCrossProcessPipe::connect_secured(&name, with(key)).unwrap();
+
+        pipe.send_blocking(Message::init("pay 10".to_string()), Duration::from_secs(1))
+            .unwrap();
+        let received = peer.receive_blocking(Duration::from_secs(1)).unwrap();
+        assert_eq!(received.data, b"pay 10");
+
+        // 不持有密钥的进程直接写入槽位伪造的消息，读取时报告 AuthenticationFailed
+        let forged = Message::init("pay 9999".to_string());
+        let mut raw = pipe.pipe.with_authenticator(None);
+        let index = pipe.hold().unwrap();
+        unsafe {
+            raw.write_with(index, |buf| {
+                CodecKind::Bincode.codec().encode(&forged, buf).unwrap()
+            })
+        }
+        .unwrap();
+        let err = peer.receive_blocking(Duration::from_secs(1)).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<TokioIPCError>(),
+            Some(TokioIPCError::AuthenticationFailed)
+        ));
+    }
+
+    #[test]
+    fn test_handshake_gates_connect() {
+        let name = unique_name("handshake");
+        let policy = Arc::new(AccessPolicy {
+            handshake: true,
+            ..AccessPolicy::default()
+        });
+        let security = SecurityOptions {
+            access: policy,
+            ..SecurityOptions::default()
+        };
+        let mut pipe =
+            DynCrossProcessPipe::create_secured(&name, PipeConfig::new(4, 256), security).unwrap();
+        assert!(pipe.pipe.requires_handshake());
+
+        // 同一用户的连接由创建者的握手线程授权
+        let peer = DynCrossProcessPipe::connect(&name).unwrap();
+        assert_eq!(pipe.attached_count(), 2);
+        drop(peer);
+
+        // 创建者拒绝时连接失败，且不登记连接
+        pipe.handshake_server.take();
+        let listener = UnixListener::bind(access::socket_path(&name)).unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            access::serve_access(&mut stream, |_| false).unwrap()
+        });
+        let err = DynCrossProcessPipe::connect(&name).err().unwrap();
+        assert!(format!("{:#}", err).contains("拒绝"));
+        let (peer, granted) = server.join().unwrap();
+        assert_eq!(peer, crate::access::PeerCredentials::current());
+        assert!(!granted);
+        let _ = std::fs::remove_file(access::socket_path(&name));
+        assert_eq!(pipe.attached_count(), 1);
+    }
+
+    #[test]
+    fn test_priority_inherit_mutex() {
+        let name = unique_name("prio_inherit");
+        let config = PipeConfig::new(2, 256).with_mutex_attr(MutexAttr::priority_inherit());
+        let pipe = CrossProcessPipe::<2, 256>::create_with_config(&name, config).unwrap();
+        let peer = CrossProcessPipe::<2, 256>::connect(&name).unwrap();
+        assert!(peer.config().mutex_attr.priority_inherit);
+
+        // 读者先在 read_mutex 配对的条件变量上等待，再由写者唤醒
+        let reader = std::thread::spawn(move || peer.receive_blocking(Duration::from_secs(2)));
+        std::thread::sleep(Duration::from_millis(50));
+        pipe.send_blocking(Message::init("pi".to_string()), Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(reader.join().unwrap().unwrap().data, b"pi");
+    }
+
+    #[tokio::test]
+    async fn test_fetch_async_wakes_on_send() {
+        let name = unique_name("async");
+        let pipe = Arc::new(CrossProcessPipe::<4, 256>::create(&name).unwrap());
+        let peer = CrossProcessPipe::<4, 256>::connect(&name).unwrap();
+
+        let waiter = {
+            let pipe = Arc::clone(&pipe);
+            tokio::spawn(async move {
+                let index = pipe.fetch_async().await.unwrap();
+                pipe.receive(index).unwrap()
+            })
+        };
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let start = std::time::Instant::now();
+        peer.send_blocking(Message::init("async".to_string()), Duration::from_secs(1))
+            .unwrap();
+
+        let message = waiter.await.unwrap();
+        assert_eq!(message.data, b"async");
+        // 由通知唤醒，而不是等到兜底超时
+        assert!(start.elapsed() < NOTIFY_FALLBACK);
+    }
+
+    #[tokio::test]
+    async fn test_send_async_waits_for_space_in_order() {
+        let name = unique_name("send_async");
+        let pipe = Arc::new(CrossProcessPipe::<1, 256>::create(&name).unwrap());
+        let peer = CrossProcessPipe::<1, 256>::connect(&name).unwrap();
+        let timeout = Duration::from_secs(1);
+        pipe.send_async(Message::init("first".to_string()))
+            .await
+            .unwrap();
+
+        // 队列已满：两个写者依次排队等待空槽位
+        let mut senders = Vec::new();
+        for data in ["second", "third"] {
+            let pipe = Arc::clone(&pipe);
+            senders.push(tokio::spawn(async move {
+                pipe.send_async(Message::init(data.to_string())).await
+            }));
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(pipe.pipe.header().space_waiters.load(Ordering::SeqCst), 1);
+
+        // 每释放一个槽位按到达顺序唤醒一个写者，由通知唤醒而不是等到兜底超时
+        for expected in ["first", "second", "third"] {
+            let start = std::time::Instant::now();
+            assert_eq!(
+                peer.receive_blocking(timeout).unwrap().data,
+                expected.as_bytes()
+            );
+            if expected != "third" {
+                while pipe.status().ready_count == 0 {
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                }
+                assert!(start.elapsed() < NOTIFY_FALLBACK);
             }
-            PipeType::Large => {
-                let pipe = CrossProcessPipe::<1000, 8192>::connect(name)?;
-                Ok(Box::new(pipe))
+        }
+        for sender in senders {
+            sender.await.unwrap().unwrap();
+        }
+        assert_eq!(pipe.pipe.header().space_waiters.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_wait_for_ready_futex() {
+        let name = unique_name("futex");
+        let pipe = Arc::new(CrossProcessPipe::<1, 256>::create(&name).unwrap());
+
+        assert!(!pipe.wait_for_ready(Duration::from_millis(20)));
+        assert!(pipe.wait_for_empty(Duration::from_millis(20)));
+
+        let waiter = {
+            let pipe = Arc::clone(&pipe);
+            std::thread::spawn(move || pipe.wait_for_ready(Duration::from_secs(5)))
+        };
+        std::thread::sleep(Duration::from_millis(50));
+        pipe.send_blocking(Message::init("futex".to_string()), Duration::from_secs(1))
+            .unwrap();
+        assert!(waiter.join().unwrap());
+        assert!(!pipe.wait_for_empty(Duration::from_millis(20)));
+    }
+
+    #[test]
+    fn test_try_send_and_send_timeout() {
+        let name = unique_name("try_send");
+        let shared =
+            DynCrossProcessPipe::create_with_config(&name, PipeConfig::new(2, 128)).unwrap();
+        let heap =
+            HeapSlotPipe::create(&unique_name("try_send_heap"), PipeConfig::new(2, 128)).unwrap();
+        let pipes: [&dyn DynamicPipe; 2] = [&shared, &heap];
+        let timeout = Duration::from_millis(10);
+
+        for pipe in pipes {
+            let first = pipe.try_send(Message::init("a".to_string())).unwrap();
+            assert!(first.is_some());
+            pipe.send_timeout(Message::init("b".to_string()), timeout)
+                .unwrap();
+
+            // 队列已满：不阻塞，返回 None；等待发送超时返回 PipeTimeout::Send
+            assert_eq!(pipe.try_send(Message::init("c".to_string())).unwrap(), None);
+            let err = pipe
+                .send_timeout(Message::init("c".to_string()), timeout)
+                .unwrap_err();
+            assert_eq!(
+                err.downcast_ref::<PipeTimeout>(),
+                Some(&PipeTimeout::Send(timeout))
+            );
+
+            // 写入失败时槽位被放弃
+            assert_eq!(pipe.receive_blocking(timeout).unwrap().data, b"a");
+            let large = Message::init("x".repeat(256));
+            assert!(pipe.try_send(large).is_err());
+            assert_eq!(pipe.status().empty_count, 1);
+            assert_eq!(pipe.receive_blocking(timeout).unwrap().data, b"b");
+        }
+    }
+
+    #[test]
+    fn test_full_policy() {
+        let timeout = Duration::from_millis(10);
+        let config = |policy| PipeConfig::new(2, 128).with_full_policy(policy);
+
+        // 覆盖模式：写者不等待，最早的消息被丢弃
+        let overwrite = DynCrossProcessPipe::create_with_config(
+            &unique_name("overwrite"),
+            config(FullPolicy::OverwriteOldest),
+        )
+        .unwrap();
+        let lock_free = DynCrossProcessPipe::create_with_config(
+            &unique_name("overwrite_lock_free"),
+            config(FullPolicy::OverwriteOldest).with_mode(PipeMode::LockFree),
+        )
+        .unwrap();
+        let heap = HeapSlotPipe::create(
+            &unique_name("overwrite_heap"),
+            config(FullPolicy::OverwriteOldest),
+        )
+        .unwrap();
+        let pipes: [&dyn DynamicPipe; 3] = [&overwrite, &lock_free, &heap];
+        for pipe in pipes {
+            for text in ["a", "b", "c", "d"] {
+                pipe.send_blocking(Message::init(text.to_string()), timeout)
+                    .unwrap();
             }
-            PipeType::Custom(capacity, slot_size) => {
-                Self::connect_custom_pipe(capacity, slot_size, name)
+            assert_eq!(pipe.status().dropped_count, 2);
+            assert_eq!(pipe.receive_blocking(timeout).unwrap().data, b"c");
+            assert_eq!(pipe.receive_blocking(timeout).unwrap().data, b"d");
+        }
+        let peer = DynCrossProcessPipe::connect(overwrite.name()).unwrap();
+        assert_eq!(peer.config().full_policy, FullPolicy::OverwriteOldest);
+
+        // 拒绝模式：队列满时立即返回队列已满，不等待超时
+        let reject = DynCrossProcessPipe::create_with_config(
+            &unique_name("reject"),
+            config(FullPolicy::Reject),
+        )
+        .unwrap();
+        let heap =
+            HeapSlotPipe::create(&unique_name("reject_heap"), config(FullPolicy::Reject)).unwrap();
+        let pipes: [&dyn DynamicPipe; 2] = [&reject, &heap];
+        for pipe in pipes {
+            for text in ["a", "b"] {
+                pipe.send_blocking(Message::init(text.to_string()), timeout)
+                    .unwrap();
             }
+            let start = std::time::Instant::now();
+            let err = pipe
+                .send_blocking(Message::init("c".to_string()), Duration::from_secs(5))
+                .unwrap_err();
+            assert!(is_queue_full(&err));
+            assert!(start.elapsed() < Duration::from_secs(1));
+            assert_eq!(pipe.status().dropped_count, 0);
         }
     }
 
-    /// 根据配置连接到现有管道
-    pub fn connect_with_config(config: PipeConfig, name: &str) -> Result<Box<dyn DynamicPipe>> {
-        let pipe_type = PipeType::from_config(config);
-        Self::connect_pipe(pipe_type, name)
+    #[test]
+    fn test_reclaim_stuck_slots() {
+        // 锁模式：写者抢占后崩溃，槽位回到 EMPTY
+        let name = unique_name("reclaim_locked");
+        let pipe = CrossProcessPipe::<1, 256>::create(&name).unwrap();
+        pipe.hold().unwrap();
+        assert!(pipe.hold().is_err());
+        assert_eq!(pipe.reclaim_stuck(Duration::from_secs(60)), 0);
+        assert_eq!(pipe.reclaim_stuck(Duration::ZERO), 1);
+        assert_eq!(pipe.status().reclaimed_count, 1);
+
+        // 读者获取后崩溃，槽位被释放
+        pipe.send_blocking(Message::init("lost".to_string()), Duration::from_secs(1))
+            .unwrap();
+        pipe.fetch().unwrap();
+        assert_eq!(pipe.reclaim_stuck(Duration::ZERO), 1);
+        assert_eq!(pipe.status().empty_count, 1);
+
+        // 无锁模式：写者占用的生产位置以空槽发布，后续消息正常收发
+        let name = unique_name("reclaim_lock_free");
+        let pipe = CrossProcessPipe::<2, 256>::create_with_mode(&name, PipeMode::LockFree).unwrap();
+        pipe.hold().unwrap();
+        assert_eq!(pipe.reclaim_stuck(Duration::ZERO), 1);
+        pipe.send_blocking(Message::init("next".to_string()), Duration::from_secs(1))
+            .unwrap();
+        let message = pipe.receive_blocking(Duration::from_secs(1)).unwrap();
+        assert_eq!(message.data, b"next");
     }
 
-    /// 创建自定义配置的管道（支持常见配置）
-    fn create_custom_pipe(
-        capacity: usize,
-        slot_size: usize,
-        name: &str,
-    ) -> Result<Box<dyn DynamicPipe>> {
-        match (capacity, slot_size) {
-            // 常见的自定义配置
-            (50, 2048) => {
-                let pipe = CrossProcessPipe::<50, 2048>::create(name)?;
-                Ok(Box::new(pipe))
+    #[test]
+    fn test_backpressure_watermarks() {
+        let name = unique_name("backpressure");
+        let pipe = DynCrossProcessPipe::create_with_config(
+            &name,
+            PipeConfig::new(4, 256).with_watermarks(3, 1),
+        )
+        .unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&events);
+        pipe.on_backpressure(move |state| recorded.lock().unwrap().push(state));
+
+        let consumer = DynCrossProcessPipe::connect(&name).unwrap();
+        assert_eq!(consumer.config().high_watermark, 3);
+        let timeout = Duration::from_secs(1);
+        for i in 0..3 {
+            assert!(!pipe.is_backpressured());
+            pipe.send_blocking(Message::init(format!("m{}", i)), timeout)
+                .unwrap();
+        }
+        assert!(pipe.is_backpressured() && consumer.is_backpressured());
+
+        // 降到低水位之前保持背压
+        consumer.receive_blocking(timeout).unwrap();
+        assert!(pipe.is_backpressured());
+        consumer.receive_blocking(timeout).unwrap();
+        assert!(!pipe.is_backpressured());
+        assert_eq!(*events.lock().unwrap(), vec![true, false]);
+        assert!(pipe.set_watermarks(5, 1).is_err());
+    }
+
+    #[test]
+    fn test_fair_hold_in_arrival_order() {
+        let name = unique_name("fair");
+        let pipe = Arc::new(
+            DynCrossProcessPipe::create_with_config(
+                &name,
+                PipeConfig::new(1, 256).with_fairness(true),
+            )
+            .unwrap(),
+        );
+        let timeout = Duration::from_secs(2);
+        pipe.send_blocking(Message::init("first".to_string()), timeout)
+            .unwrap();
+
+        // 依次排队的写者
+        let producers: Vec<_> = (0..3)
+            .map(|i| {
+                let producer = Arc::new(DynCrossProcessPipe::connect(&name).unwrap());
+                let handle = std::thread::spawn(move || {
+                    producer
+                        .send_blocking(Message::init(format!("p{}", i)), timeout)
+                        .unwrap();
+                });
+                std::thread::sleep(Duration::from_millis(50));
+                handle
+            })
+            .collect();
+
+        // 有写者排队时非阻塞 hold 不插队
+        pipe.receive_blocking(timeout).unwrap();
+        let mut received = Vec::new();
+        for _ in 0..3 {
+            assert!(pipe.hold().is_err());
+            let message = pipe.receive_blocking(timeout).unwrap();
+            received.push(String::from_utf8(message.data).unwrap());
+        }
+        for handle in producers {
+            handle.join().unwrap();
+        }
+        assert_eq!(received, vec!["p0", "p1", "p2"]);
+
+        // 超时放弃的票号不会阻塞后来者
+        pipe.send_blocking(Message::init("full".to_string()), timeout)
+            .unwrap();
+        assert!(
+            pipe.send_blocking(Message::init("late".to_string()), Duration::from_millis(20))
+                .is_err()
+        );
+        pipe.receive_blocking(timeout).unwrap();
+        pipe.send_blocking(Message::init("next".to_string()), timeout)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_expired_messages_skipped_on_receive() {
+        let name = unique_name("expiry");
+        let pipe = CrossProcessPipe::<4, 256>::create(&name).unwrap();
+        let timeout = Duration::from_secs(1);
+
+        pipe.send_blocking(
+            Message::init("stale".to_string()).with_ttl(Duration::from_millis(10)),
+            timeout,
+        )
+        .unwrap();
+        pipe.send_blocking(
+            Message::init("fresh".to_string()).with_ttl(Duration::from_secs(60)),
+            timeout,
+        )
+        .unwrap();
+        pipe.send_blocking(Message::init("forever".to_string()), timeout)
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+
+        assert_eq!(pipe.receive_blocking(timeout).unwrap().data, b"fresh");
+        assert_eq!(pipe.status().expired_count, 1);
+        assert_eq!(pipe.receive_blocking(timeout).unwrap().data, b"forever");
+
+        // 按索引读取时返回可识别的错误
+        pipe.send_blocking(
+            Message::init("late".to_string()).with_deadline(std::time::UNIX_EPOCH),
+            timeout,
+        )
+        .unwrap();
+        let index = pipe.fetch().unwrap();
+        let err = pipe.receive(index).unwrap_err();
+        assert!(err.is::<MessageExpired>());
+        assert_eq!(pipe.status().expired_count, 2);
+        assert_eq!(pipe.status().empty_count, 4);
+    }
+
+    #[test]
+    fn test_nack_redelivers_then_dead_letters() {
+        let timeout = Duration::from_secs(1);
+        let dead_letter = Arc::new(
+            DynCrossProcessPipe::create_with_config(&unique_name("dlq"), PipeConfig::new(4, 256))
+                .unwrap(),
+        );
+        let pipe = CrossProcessPipe::<4, 256>::create_with_config(
+            &unique_name("nack"),
+            PipeConfig::new(4, 256).with_max_delivery_attempts(2),
+        )
+        .unwrap()
+        .with_dead_letter(dead_letter.clone());
+
+        pipe.send_blocking(Message::init("poison".to_string()), timeout)
+            .unwrap();
+
+        // 第一次失败重新投递，第二次失败达到上限转入死信
+        for attempt in 0..2 {
+            let index = pipe.fetch().unwrap();
+            let (_, message) = pipe.receive_unacked(index).unwrap();
+            assert_eq!(message.data, b"poison");
+            assert_eq!(pipe.delivery_attempts(index).unwrap(), attempt);
+            assert_eq!(pipe.nack(index).unwrap(), attempt == 0);
+        }
+
+        let status = pipe.status();
+        assert_eq!(status.redelivered_count, 1);
+        assert_eq!(status.dead_lettered_count, 1);
+        assert_eq!(status.empty_count, 4);
+        assert_eq!(
+            dead_letter.receive_blocking(timeout).unwrap().data,
+            b"poison"
+        );
+
+        // 确认后槽位正常释放
+        pipe.send_blocking(Message::init("ok".to_string()), timeout)
+            .unwrap();
+        let index = pipe.fetch().unwrap();
+        pipe.receive_unacked(index).unwrap();
+        pipe.ack(index).unwrap();
+        assert_eq!(pipe.status().empty_count, 4);
+    }
+
+    #[test]
+    fn test_requeue_into_full_lock_free_pipe_dead_letters() {
+        let timeout = Duration::from_secs(1);
+        let dead_letter = Arc::new(
+            DynCrossProcessPipe::create_with_config(
+                &unique_name("full_dlq"),
+                PipeConfig::new(4, 256),
+            )
+            .unwrap(),
+        );
+        let name = unique_name("requeue_full");
+        let pipe = DynCrossProcessPipe::create_with_config(
+            &name,
+            PipeConfig::new(2, 256).with_mode(PipeMode::LockFree),
+        )
+        .unwrap()
+        .with_dead_letter(dead_letter.clone());
+
+        // 读者取走一条后管道仍是满的：新的生产位置正是读者持有的槽位
+        for text in ["first", "second"] {
+            pipe.send_blocking(Message::init(text.to_string()), timeout)
+                .unwrap();
+        }
+        let index = pipe.fetch().unwrap();
+        pipe.receive_unacked(index).unwrap();
+        assert!(pipe.hold().is_err());
+
+        // 无法重新投递的消息转入死信而不是丢失
+        assert!(!pipe.nack(index).unwrap());
+        assert_eq!(
+            dead_letter.receive_blocking(timeout).unwrap().data,
+            b"first"
+        );
+
+        // 读者崩溃后恢复时同样如此
+        pipe.send_blocking(Message::init("third".to_string()), timeout)
+            .unwrap();
+        let index = pipe.fetch().unwrap();
+        pipe.receive_unacked(index).unwrap();
+        let restarted = DynCrossProcessPipe::connect(&name)
+            .unwrap()
+            .with_dead_letter(dead_letter.clone());
+        assert_eq!(restarted.resume(Duration::ZERO), 0);
+        assert_eq!(
+            dead_letter.receive_blocking(timeout).unwrap().data,
+            b"second"
+        );
+
+        let status = pipe.status();
+        assert_eq!(status.redelivered_count, 0);
+        assert_eq!(status.dead_lettered_count, 2);
+        assert_eq!(pipe.receive_blocking(timeout).unwrap().data, b"third");
+        assert_eq!(pipe.status().empty_count, 2);
+    }
+
+    #[test]
+    fn test_commit_and_resume_after_reader_crash() {
+        let timeout = Duration::from_secs(1);
+        for mode in [PipeMode::Locked, PipeMode::LockFree] {
+            let name = unique_name(&format!("commit_{:?}", mode));
+            let pipe = DynCrossProcessPipe::create_with_config(
+                &name,
+                PipeConfig::new(4, 256).with_mode(mode),
+            )
+            .unwrap();
+            let first = pipe
+                .send_blocking(Message::init("one".to_string()), timeout)
+                .unwrap();
+            let second = pipe
+                .send_blocking(Message::init("two".to_string()), timeout)
+                .unwrap();
+            assert_eq!(pipe.committed_offset(), 0);
+
+            let index = pipe.fetch_timeout(timeout).unwrap();
+            pipe.receive_unacked(index).unwrap();
+            assert_eq!(pipe.commit(index).unwrap(), first);
+            assert_eq!(pipe.committed_offset(), first);
+
+            // 读者取出第二条消息后、提交前崩溃：重启的读者从已提交位置继续
+            let index = pipe.fetch_timeout(timeout).unwrap();
+            pipe.receive_unacked(index).unwrap();
+            let restarted = DynCrossProcessPipe::connect(&name).unwrap();
+            assert_eq!(restarted.resume(Duration::from_secs(60)), 0);
+            assert_eq!(restarted.resume(Duration::ZERO), 1);
+            assert_eq!(restarted.committed_offset(), first);
+
+            let index = restarted.fetch_timeout(timeout).unwrap();
+            let (request_id, message) = restarted.receive_unacked(index).unwrap();
+            assert_eq!((request_id, message.data.as_slice()), (second, &b"two"[..]));
+            assert_eq!(restarted.commit(index).unwrap(), second);
+            assert_eq!(pipe.committed_offset(), second);
+
+            let status = pipe.status();
+            assert_eq!(status.empty_count, 4);
+            assert_eq!(status.redelivered_count, 1);
+        }
+    }
+
+    #[test]
+    fn test_latency_metrics() {
+        let name = unique_name("latency");
+        let pipe = CrossProcessPipe::<4, 256>::create(&name).unwrap();
+        let timeout = Duration::from_secs(1);
+        assert_eq!(pipe.metrics().count, 0);
+        assert_eq!(pipe.metrics().p99, Duration::ZERO);
+
+        for i in 0..3 {
+            pipe.send_blocking(Message::init(format!("m{}", i)), timeout)
+                .unwrap();
+        }
+        std::thread::sleep(Duration::from_millis(5));
+        for _ in 0..3 {
+            pipe.receive_blocking(timeout).unwrap();
+        }
+
+        let metrics = pipe.metrics();
+        assert_eq!(metrics.count, 3);
+        assert!(metrics.mean >= Duration::from_millis(5));
+        // 分位数取桶上界，不小于真实延迟
+        assert!(metrics.p50 >= Duration::from_millis(5));
+        assert!(metrics.p50 <= metrics.p99);
+        assert_eq!(metrics.buckets.iter().map(|(_, n)| n).sum::<u64>(), 3);
+    }
+
+    #[test]
+    fn test_blocking_timeouts() {
+        let name = unique_name("timeout");
+        let pipe = CrossProcessPipe::<1, 256>::create(&name).unwrap();
+
+        assert!(pipe.receive_blocking(Duration::from_millis(20)).is_err());
+
+        pipe.send_blocking(Message::init("a".to_string()), Duration::from_millis(20))
+            .unwrap();
+        assert!(
+            pipe.send_blocking(Message::init("b".to_string()), Duration::from_millis(20))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_connect_rejects_layout_mismatch() {
+        use crate::shared_slot::{LayoutMismatch, read_layout};
+
+        let name = unique_name("layout");
+        let _pipe = CrossProcessPipe::<4, 256>::create(&name).unwrap();
+
+        let layout = read_layout(&name).unwrap();
+        assert_eq!((layout.capacity, layout.slot_size), (4, 256));
+
+        let err = CrossProcessPipe::<4, 512>::connect(&name).err().unwrap();
+        assert_eq!(
+            err.downcast_ref::<LayoutMismatch>(),
+            Some(&LayoutMismatch::SlotSize {
+                expected: 512,
+                found: 256
+            })
+        );
+        let err = CrossProcessPipe::<8, 256>::connect(&name).err().unwrap();
+        assert_eq!(
+            err.downcast_ref::<LayoutMismatch>(),
+            Some(&LayoutMismatch::Capacity {
+                expected: 8,
+                found: 4
+            })
+        );
+        assert!(CrossProcessPipe::<4, 256>::connect(&name).is_ok());
+
+        // 未初始化的共享内存段
+        let raw = unique_name("layout_raw");
+        let cname = std::ffi::CString::new(format!("/{}", raw)).unwrap();
+        unsafe {
+            let fd = libc::shm_open(cname.as_ptr(), libc::O_CREAT | libc::O_RDWR, 0o666);
+            assert!(fd >= 0);
+            libc::ftruncate(fd, 4096);
+            libc::close(fd);
+        }
+        let err = CrossProcessPipe::<4, 256>::connect(&raw).err().unwrap();
+        assert_eq!(
+            err.downcast_ref::<LayoutMismatch>(),
+            Some(&LayoutMismatch::Magic { found: 0 })
+        );
+        unsafe { libc::shm_unlink(cname.as_ptr()) };
+    }
+
+    #[test]
+    fn test_custom_pipe_any_size() {
+        let name = unique_name("custom");
+        let pipe_type: PipeType = "custom(3x100)".parse().unwrap();
+        assert_eq!(pipe_type, PipeType::Custom(3, 100));
+        assert_eq!(pipe_type.to_string().parse::<PipeType>(), Ok(pipe_type));
+
+        let pipe = PipeFactory::create_pipe(pipe_type, &name).unwrap();
+        assert_eq!((pipe.capacity(), pipe.slot_size()), (3, 100));
+
+        // 不知道尺寸的一方从头部读取
+        let peer = PipeFactory::open(&name).unwrap();
+        assert_eq!((peer.capacity(), peer.slot_size()), (3, 100));
+        assert!(PipeFactory::connect_pipe(PipeType::Custom(3, 100), &name).is_ok());
+        assert!(PipeFactory::connect_pipe(PipeType::Custom(3, 101), &name).is_err());
+
+        // 与编译期尺寸的管道互通
+        let typed = CrossProcessPipe::<3, 100>::connect(&name).unwrap();
+        pipe.send_blocking(Message::init("dyn".to_string()), Duration::from_secs(1))
+            .unwrap();
+        let message = typed.receive_blocking(Duration::from_secs(1)).unwrap();
+        assert_eq!(message.data, b"dyn");
+        assert_eq!(peer.status().empty_count, 3);
+    }
+
+    #[test]
+    fn test_send_large_through_mailbox() {
+        use crate::shared_box::{BoxConfig, BoxSize};
+
+        let name = unique_name("large");
+        let mut config = BoxConfig::new();
+        config.set_count(BoxSize::Size1M, 1);
+        let mailbox =
+            Arc::new(SharedMemoryMailbox::new_shared(&unique_name("large_box"), config).unwrap());
+
+        let pipe = CrossProcessPipe::<2, 128>::create(&name)
+            .unwrap()
+            .with_mailbox(Arc::clone(&mailbox));
+        let peer = CrossProcessPipe::<2, 128>::connect(&name)
+            .unwrap()
+            .with_mailbox(Arc::clone(&mailbox));
+
+        let data: Vec<u8> = (0..300 * 1024).map(|i| (i % 251) as u8).collect();
+        pipe.send_large(&data, Duration::from_secs(1)).unwrap();
+        assert_eq!(mailbox.get_stats().empty_count, 0);
+
+        // 唯一的 box 被占用，再次发送超时
+        assert!(pipe.send_large(&data, Duration::from_millis(20)).is_err());
+
+        assert_eq!(peer.receive_large(Duration::from_secs(1)).unwrap(), data);
+        assert_eq!(mailbox.get_stats().empty_count, 1);
+
+        // 超出最大 box 的数据直接拒绝
+        assert!(
+            pipe.send_large(&vec![0; 2 * 1024 * 1024], Duration::from_millis(20))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_persistent_pipe_recovers_after_restart() {
+        for mode in [PipeMode::Locked, PipeMode::LockFree] {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("queue.pipe");
+            let name = unique_name("persistent");
+            let config = PipeConfig::new(4, 128).with_mode(mode);
+
+            {
+                let pipe = DynCrossProcessPipe::create_persistent(&name, &path, config).unwrap();
+                for text in ["first", "second", "third"] {
+                    pipe.send_blocking(Message::init(text.to_string()), Duration::from_secs(1))
+                        .unwrap();
+                }
+                let message = pipe.receive_blocking(Duration::from_secs(1)).unwrap();
+                assert_eq!(message.data, b"first");
+
+                // 读取方取走槽位后崩溃，消息尚未处理完成
+                pipe.fetch().unwrap();
+            }
+
+            // 重启：未完成与未读取的消息按原顺序恢复，崩溃时正在处理的消息记一次投递
+            let pipe = DynCrossProcessPipe::create_persistent(&name, &path, config).unwrap();
+            assert_eq!(pipe.config().mode, mode);
+            assert_eq!(pipe.status().ready_count, 2);
+            let peer = DynCrossProcessPipe::connect_persistent(&name, &path).unwrap();
+            for (expected, attempts) in [("second", 1), ("third", 0)] {
+                let index = peer.fetch().unwrap();
+                assert_eq!(peer.delivery_attempts(index).unwrap(), attempts);
+                let message = peer.try_receive(index).unwrap().unwrap();
+                assert_eq!(message.data, expected.as_bytes());
+            }
+            pipe.send_blocking(Message::init("fourth".to_string()), Duration::from_secs(1))
+                .unwrap();
+            assert_eq!(
+                peer.receive_blocking(Duration::from_secs(1)).unwrap().data,
+                b"fourth"
+            );
+        }
+    }
+
+    /// 属性测试中被驱动的环形队列，锁模式
+    trait Ring {
+        /// 抢占空槽位并写入 `value`，队列满时返回 `false`
+        fn send(&mut self, value: u64) -> bool;
+        /// 阻塞接口，超时为 0
+        fn receive(&mut self) -> Option<u64>;
+        /// 非阻塞抢占 READY 槽位后读取
+        fn try_receive(&mut self) -> Option<u64>;
+        /// 写者抢占槽位后崩溃
+        fn crash_writer(&mut self) -> bool;
+        /// 读者取走槽位后崩溃，消息丢失
+        fn crash_reader(&mut self) -> bool;
+        fn reclaim(&mut self) -> usize;
+        /// 各状态槽位数量：EMPTY, WRITING, INPROGRESS, READING, READY
+        fn counts(&self) -> [usize; 5];
+    }
+
+    impl Ring for DynCrossProcessPipe {
+        fn send(&mut self, value: u64) -> bool {
+            let Ok(index) = self.hold() else {
+                return false;
+            };
+            DynCrossProcessPipe::send(self, index, Message::init(value.to_string())).unwrap();
+            true
+        }
+
+        fn receive(&mut self) -> Option<u64> {
+            let message = self.receive_blocking(Duration::ZERO).ok()?;
+            Some(String::from_utf8(message.data).unwrap().parse().unwrap())
+        }
+
+        fn try_receive(&mut self) -> Option<u64> {
+            let index = unsafe { self.pipe.fetch_timeout(Some(Duration::ZERO)) }?;
+            let message = DynCrossProcessPipe::try_receive(self, index).unwrap()?;
+            Some(String::from_utf8(message.data).unwrap().parse().unwrap())
+        }
+
+        fn crash_writer(&mut self) -> bool {
+            self.hold().is_ok()
+        }
+
+        fn crash_reader(&mut self) -> bool {
+            unsafe { self.pipe.fetch_timeout(Some(Duration::ZERO)) }.is_some()
+        }
+
+        fn reclaim(&mut self) -> usize {
+            self.reclaim_stuck(Duration::ZERO)
+        }
+
+        fn counts(&self) -> [usize; 5] {
+            let status = self.status();
+            [
+                status.empty_count,
+                status.writing_count,
+                status.in_progress_count,
+                status.reading_count,
+                status.ready_count,
+            ]
+        }
+    }
+
+    impl Ring for DynSharedSlotPipe {
+        fn send(&mut self, value: u64) -> bool {
+            let Some(index) = (unsafe { self.hold() }) else {
+                return false;
+            };
+            unsafe {
+                self.write_with(index, |buf| {
+                    buf[..8].copy_from_slice(&value.to_le_bytes());
+                    8
+                })
+                .unwrap();
             }
-            (200, 1024) => {
-                let pipe = CrossProcessPipe::<200, 1024>::create(name)?;
-                Ok(Box::new(pipe))
+            true
+        }
+
+        fn receive(&mut self) -> Option<u64> {
+            let index = unsafe { self.fetch_timeout(Some(Duration::ZERO)) }?;
+            unsafe {
+                let (_, value) = self
+                    .read_with(index, |buf| u64::from_le_bytes(buf.try_into().unwrap()))
+                    .unwrap();
+                Some(value)
             }
-            (500, 512) => {
-                let pipe = CrossProcessPipe::<500, 512>::create(name)?;
-                Ok(Box::new(pipe))
+        }
+
+        fn try_receive(&mut self) -> Option<u64> {
+            let index = unsafe { self.fetch_timeout(Some(Duration::ZERO)) }?;
+            unsafe {
+                let (_, value) = self
+                    .try_read_with(index, |buf| Ok(u64::from_le_bytes(buf.try_into().unwrap())))
+                    .unwrap();
+                Some(value)
             }
-            (20, 16384) => {
-                let pipe = CrossProcessPipe::<20, 16384>::create(name)?;
-                Ok(Box::new(pipe))
+        }
+
+        fn crash_writer(&mut self) -> bool {
+            unsafe { self.hold() }.is_some()
+        }
+
+        fn crash_reader(&mut self) -> bool {
+            unsafe { self.fetch_timeout(Some(Duration::ZERO)) }.is_some()
+        }
+
+        fn reclaim(&mut self) -> usize {
+            unsafe { self.reclaim_stuck(Duration::ZERO) }
+        }
+
+        fn counts(&self) -> [usize; 5] {
+            let mut counts = [0; 5];
+            for index in 0..self.capacity() {
+                counts[self.slot(index).state.load(Ordering::Acquire) as usize] += 1;
             }
-            // 如果是预定义配置，重定向到对应类型
-            (10, 1024) => {
-                let pipe = CrossProcessPipe::<10, 1024>::create(name)?;
-                Ok(Box::new(pipe))
+            [counts[0], counts[1], counts[2], counts[3], counts[4]]
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum RingOp {
+        Send,
+        Receive,
+        TryReceive,
+        CrashWriter,
+        CrashReader,
+        Reclaim,
+    }
+
+    fn ring_ops() -> impl proptest::strategy::Strategy<Value = Vec<RingOp>> {
+        use proptest::prelude::*;
+        let op = prop_oneof![
+            4 => Just(RingOp::Send),
+            2 => Just(RingOp::Receive),
+            2 => Just(RingOp::TryReceive),
+            1 => Just(RingOp::CrashWriter),
+            1 => Just(RingOp::CrashReader),
+            1 => Just(RingOp::Reclaim),
+        ];
+        proptest::collection::vec(op, 1..64)
+    }
+
+    fn pipe_modes() -> impl proptest::strategy::Strategy<Value = PipeMode> {
+        use proptest::prelude::*;
+        prop_oneof![Just(PipeMode::Locked), Just(PipeMode::LockFree)]
+    }
+
+    /// 对照模型执行操作序列，检查 FIFO、不丢不重与槽位状态守恒
+    ///
+    /// 回收让被跳过的槽位重新可用，之后写入的消息可能排到更早的消息前面，
+    /// 因此 FIFO 只在第一次回收之前检查。
+    fn check_ring_invariants(ring: &mut dyn Ring, capacity: usize, ops: &[RingOp]) {
+        let mut next = 0u64;
+        let mut queued: Vec<u64> = Vec::new(); // 已写入、未读取的消息（含被崩溃读者取走的）
+        let mut unknown_lost = 0; // 回收后被崩溃读者取走、无法确定是哪条的消息
+        let mut stuck = 0; // 崩溃的写者与读者持有的槽位
+        let mut reclaimed = false;
+        let mut received = HashSet::new();
+
+        for &op in ops {
+            let ready = queued.len() - unknown_lost;
+            match op {
+                RingOp::Send => {
+                    let full = ready + stuck == capacity;
+                    assert_eq!(ring.send(next), !full, "{:?}", op);
+                    if !full {
+                        queued.push(next);
+                        next += 1;
+                    }
+                }
+                RingOp::Receive | RingOp::TryReceive => {
+                    let value = match op {
+                        RingOp::Receive => ring.receive(),
+                        _ => ring.try_receive(),
+                    };
+                    assert_eq!(value.is_some(), ready > 0, "{:?}", op);
+                    if let Some(value) = value {
+                        if !reclaimed {
+                            assert_eq!(value, queued[0], "FIFO");
+                        }
+                        let position = queued.iter().position(|&v| v == value).unwrap();
+                        queued.remove(position);
+                        assert!(received.insert(value), "重复读取 {}", value);
+                    }
+                }
+                RingOp::CrashWriter => {
+                    let full = ready + stuck == capacity;
+                    assert_eq!(ring.crash_writer(), !full);
+                    if !full {
+                        stuck += 1;
+                    }
+                }
+                RingOp::CrashReader => {
+                    assert_eq!(ring.crash_reader(), ready > 0);
+                    if ready > 0 {
+                        stuck += 1;
+                        if reclaimed {
+                            unknown_lost += 1;
+                        } else {
+                            queued.remove(0);
+                        }
+                    }
+                }
+                RingOp::Reclaim => {
+                    assert_eq!(ring.reclaim(), stuck);
+                    reclaimed |= stuck > 0;
+                    stuck = 0;
+                }
             }
-            (100, 4096) => {
-                let pipe = CrossProcessPipe::<100, 4096>::create(name)?;
-                Ok(Box::new(pipe))
+
+            let [empty, writing, in_progress, reading, ready] = ring.counts();
+            assert_eq!(empty + writing + in_progress + reading + ready, capacity);
+            assert_eq!(in_progress, 0);
+            assert_eq!(writing + reading, stuck);
+            assert_eq!(ready, queued.len() - unknown_lost);
+        }
+
+        // 取完剩余消息：除被崩溃读者取走的之外全部送达，且只送达一次
+        while let Some(value) = ring.receive() {
+            assert!(received.insert(value), "重复读取 {}", value);
+            queued.retain(|&v| v != value);
+        }
+        assert_eq!(queued.len(), unknown_lost);
+    }
+
+    /// 无锁模式下槽位的模型状态
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum ModelSlot {
+        Empty,
+        Writing,
+        /// `None` 为写者崩溃后被回收、以放弃的空槽发布的槽位
+        Ready(Option<u64>),
+        Reading,
+    }
+
+    /// 无锁模式的对照模型：槽位按生产/消费位置严格轮转
+    ///
+    /// 与锁模式不同，崩溃写者持有的槽位挡住之后已发布的消息，回收后以空槽发布、由消费者跳过；
+    /// 崩溃读者持有的槽位在回收前挡住生产位置绕回。回收不改变消息顺序，FIFO 始终成立。
+    fn check_lock_free_ring_invariants(ring: &mut dyn Ring, capacity: usize, ops: &[RingOp]) {
+        let mut slots = vec![ModelSlot::Empty; capacity];
+        let (mut enqueue, mut dequeue) = (0, 0);
+        let mut next = 0u64;
+        let mut expected = 0u64; // 下一条应读到的消息，跳过被崩溃读者取走的
+        let mut lost = HashSet::new();
+
+        // 按消费位置抢占槽位，跳过放弃的空槽
+        let claim = |slots: &mut [ModelSlot], dequeue: &mut usize| loop {
+            let index = *dequeue % capacity;
+            match slots[index] {
+                ModelSlot::Ready(None) => {
+                    slots[index] = ModelSlot::Empty;
+                    *dequeue += 1;
+                }
+                ModelSlot::Ready(Some(value)) => {
+                    *dequeue += 1;
+                    return Some((index, value));
+                }
+                _ => return None,
             }
-            (1000, 8192) => {
-                let pipe = CrossProcessPipe::<1000, 8192>::create(name)?;
-                Ok(Box::new(pipe))
+        };
+
+        for &op in ops {
+            match op {
+                RingOp::Send | RingOp::CrashWriter => {
+                    let index = enqueue % capacity;
+                    let available = slots[index] == ModelSlot::Empty;
+                    let sent = match op {
+                        RingOp::Send => ring.send(next),
+                        _ => ring.crash_writer(),
+                    };
+                    assert_eq!(sent, available, "{:?}", op);
+                    if available {
+                        slots[index] = match op {
+                            RingOp::Send => ModelSlot::Ready(Some(next)),
+                            _ => ModelSlot::Writing,
+                        };
+                        if op == RingOp::Send {
+                            next += 1;
+                        }
+                        enqueue += 1;
+                    }
+                }
+                RingOp::Receive | RingOp::TryReceive => {
+                    let claimed = claim(&mut slots, &mut dequeue);
+                    let value = match op {
+                        RingOp::Receive => ring.receive(),
+                        _ => ring.try_receive(),
+                    };
+                    assert_eq!(value, claimed.map(|(_, value)| value), "{:?}", op);
+                    if let Some((index, value)) = claimed {
+                        slots[index] = ModelSlot::Empty;
+                        while lost.contains(&expected) {
+                            expected += 1;
+                        }
+                        assert_eq!(value, expected, "FIFO");
+                        expected += 1;
+                    }
+                }
+                RingOp::CrashReader => {
+                    let claimed = claim(&mut slots, &mut dequeue);
+                    assert_eq!(ring.crash_reader(), claimed.is_some());
+                    if let Some((index, value)) = claimed {
+                        slots[index] = ModelSlot::Reading;
+                        lost.insert(value);
+                    }
+                }
+                RingOp::Reclaim => {
+                    let mut stuck = 0;
+                    for slot in &mut slots {
+                        match slot {
+                            ModelSlot::Writing => *slot = ModelSlot::Ready(None),
+                            ModelSlot::Reading => *slot = ModelSlot::Empty,
+                            _ => continue,
+                        }
+                        stuck += 1;
+                    }
+                    assert_eq!(ring.reclaim(), stuck);
+                }
             }
-            _ => Err(anyhow::anyhow!(
-                "不支持的自定义配置: capacity={}, slot_size={}. 请使用预定义配置或添加支持的自定义配置",
-                capacity,
-                slot_size
-            )),
+
+            let count = |f: fn(&ModelSlot) -> bool| slots.iter().filter(|s| f(s)).count();
+            assert_eq!(
+                ring.counts(),
+                [
+                    count(|s| *s == ModelSlot::Empty),
+                    count(|s| *s == ModelSlot::Writing),
+                    0,
+                    count(|s| *s == ModelSlot::Reading),
+                    count(|s| matches!(s, ModelSlot::Ready(_))),
+                ],
+                "{:?}",
+                op
+            );
+        }
+
+        // 取完剩余消息：除被崩溃读者取走的之外全部按序送达
+        while let Some((index, value)) = claim(&mut slots, &mut dequeue) {
+            slots[index] = ModelSlot::Empty;
+            assert_eq!(ring.receive(), Some(value));
         }
+        assert_eq!(ring.receive(), None);
     }
 
-    /// 连接到自定义配置的管道
-    fn connect_custom_pipe(
-        capacity: usize,
-        slot_size: usize,
-        name: &str,
-    ) -> Result<Box<dyn DynamicPipe>> {
-        match (capacity, slot_size) {
-            // 常见的自定义配置
-            (50, 2048) => {
-                let pipe = CrossProcessPipe::<50, 2048>::connect(name)?;
-                Ok(Box::new(pipe))
-            }
-            (200, 1024) => {
-                let pipe = CrossProcessPipe::<200, 1024>::connect(name)?;
-                Ok(Box::new(pipe))
-            }
-            (500, 512) => {
-                let pipe = CrossProcessPipe::<500, 512>::connect(name)?;
-                Ok(Box::new(pipe))
-            }
-            (20, 16384) => {
-                let pipe = CrossProcessPipe::<20, 16384>::connect(name)?;
-                Ok(Box::new(pipe))
-            }
-            // 如果是预定义配置，重定向到对应类型
-            (10, 1024) => {
-                let pipe = CrossProcessPipe::<10, 1024>::connect(name)?;
-                Ok(Box::new(pipe))
-            }
-            (100, 4096) => {
-                let pipe = CrossProcessPipe::<100, 4096>::connect(name)?;
-                Ok(Box::new(pipe))
-            }
-            (1000, 8192) => {
-                let pipe = CrossProcessPipe::<1000, 8192>::connect(name)?;
-                Ok(Box::new(pipe))
-            }
-            _ => Err(anyhow::anyhow!(
-                "不支持的自定义配置: capacity={}, slot_size={}. 请使用预定义配置或添加支持的自定义配置",
-                capacity,
-                slot_size
-            )),
+    /// 按并发模式选择对照模型
+    fn check_invariants(ring: &mut dyn Ring, capacity: usize, mode: PipeMode, ops: &[RingOp]) {
+        match mode {
+            PipeMode::Locked => check_ring_invariants(ring, capacity, ops),
+            PipeMode::LockFree => check_lock_free_ring_invariants(ring, capacity, ops),
+        }
+    }
+
+    proptest::proptest! {
+        #![proptest_config(proptest::prelude::ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn prop_cross_process_pipe_invariants(mode in pipe_modes(), ops in ring_ops()) {
+            let name = unique_name("prop_pipe");
+            let config = PipeConfig::new(4, 128).with_mode(mode);
+            let mut pipe = CrossProcessPipe::<4, 128>::create_with_config(&name, config).unwrap();
+            check_invariants(&mut *pipe, 4, mode, &ops);
+        }
+
+        #[test]
+        fn prop_shared_slot_pipe_invariants(mode in pipe_modes(), ops in ring_ops()) {
+            let name = unique_name("prop_slot");
+            let mut pipe = unsafe {
+                DynSharedSlotPipe::create(
+                    &name,
+                    4,
+                    64,
+                    mode,
+                    CodecKind::Bincode,
+                    Integrity::None,
+                    MutexAttr::default(),
+                )
+            }
+            .unwrap();
+            check_invariants(&mut pipe, 4, mode, &ops);
+            unsafe { pipe.unmap() };
+            SharedMemoryRegistry::unlink(&name).unwrap();
         }
     }
 }
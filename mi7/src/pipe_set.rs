@@ -0,0 +1,222 @@
+//! 同时等待多条管道
+//!
+//! worker 往往既要处理请求管道，又要响应控制管道。[`PipeSet::wait_any`] 在一个线程里同时等待
+//! 多条管道，任意一条出现可读消息时返回它在切片中的位置，不需要为每条管道各开一个轮询任务：
+//!
+//! ```no_run
+//! # use mi7::pipe::{DynCrossProcessPipe, DynamicPipe};
+//! # use mi7::PipeSet;
+//! # use std::time::Duration;
+//! # fn example(requests: &DynCrossProcessPipe, control: &DynCrossProcessPipe) -> anyhow::Result<()> {
+//! // 控制管道排在前面：两条管道都有消息时优先处理控制消息
+//! let pipes: [&dyn DynamicPipe; 2] = [control, requests];
+//! loop {
+//!     let ready = PipeSet::wait_any(&pipes, Duration::from_secs(1))?;
+//!     // 其他消费者可能先取走消息，用零超时读取，落空时继续等待
+//!     if let Ok(message) = pipes[ready].receive_blocking(Duration::ZERO) {
+//!         // 处理消息
+//!     }
+//! }
+//! # }
+//! ```
+//!
+//! 等待基于各管道的就绪通知 FIFO（见 [`crate::notify`]）：用 `poll(2)`（异步版本用 tokio `AsyncFd`）
+//! 同时等待所有 FIFO，醒来后取走积压的通知并检查哪条管道有 READY 槽位。
+//! 取走通知会让同一管道上的 `fetch_async` 等待者改由兜底超时醒来，因此应由消费这些管道的进程使用。
+//! 没有通知 FIFO 的管道（进程内管道、UDS 连接方）按 [`POLL_INTERVAL`] 定时检查。
+
+use crate::error::Result;
+use crate::pipe::{DynamicPipe, PipeTimeout};
+use std::os::fd::{FromRawFd, OwnedFd, RawFd};
+use std::task::Poll;
+use std::time::{Duration, Instant};
+use tokio::io::Interest;
+use tokio::io::unix::AsyncFd;
+
+/// 有管道没有通知 FIFO 时检查队列的间隔
+pub const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// 所有管道都有通知 FIFO 时的兜底检查间隔，防止通知丢失（FIFO 缓冲区满、写者未发通知）时一直等待
+const NOTIFY_FALLBACK: Duration = Duration::from_millis(500);
+
+/// 多条管道的联合等待
+pub struct PipeSet;
+
+impl PipeSet {
+    /// 等待任意一条管道出现可读消息，返回其在 `pipes` 中的位置，超时返回 [`PipeTimeout::Receive`]
+    ///
+    /// 多条管道同时有消息时返回位置靠前的一条。返回后消息仍可能被其他消费者取走，
+    /// 调用方应以非阻塞方式读取（例如 `receive_blocking(Duration::ZERO)`）。
+    pub fn wait_any(pipes: &[&dyn DynamicPipe], timeout: Duration) -> Result<usize> {
+        if pipes.is_empty() {
+            return Err(anyhow::anyhow!("没有要等待的管道").into());
+        }
+        let deadline = Instant::now() + timeout;
+        let fds: Vec<RawFd> = pipes.iter().filter_map(|pipe| pipe.notify_fd()).collect();
+        let interval = Self::check_interval(pipes, &fds);
+        let mut poll_fds: Vec<libc::pollfd> = fds
+            .iter()
+            .map(|&fd| libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            })
+            .collect();
+
+        loop {
+            // 先取走通知再检查队列：检查之后发送的消息一定会让 FIFO 重新可读
+            fds.iter().for_each(|&fd| drain(fd));
+            if let Some(ready) = Self::first_ready(pipes) {
+                return Ok(ready);
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(PipeTimeout::Receive(timeout).into());
+            }
+            let wait = remaining.min(interval);
+            unsafe {
+                libc::poll(
+                    poll_fds.as_mut_ptr(),
+                    poll_fds.len() as libc::nfds_t,
+                    wait.as_millis().max(1) as libc::c_int,
+                );
+            }
+        }
+    }
+
+    /// [`PipeSet::wait_any`] 的异步版本，一直等待直到有管道可读，需要超时时配合 `tokio::time::timeout`
+    ///
+    /// 等待时不占用运行时线程。
+    pub async fn wait_any_async(pipes: &[&dyn DynamicPipe]) -> Result<usize> {
+        if pipes.is_empty() {
+            return Err(anyhow::anyhow!("没有要等待的管道").into());
+        }
+        let fds: Vec<RawFd> = pipes.iter().filter_map(|pipe| pipe.notify_fd()).collect();
+        let interval = Self::check_interval(pipes, &fds);
+        // 每次调用复制独立的文件描述符注册到 tokio 反应器，与其他等待者互不影响
+        let async_fds = fds
+            .iter()
+            .map(|&fd| {
+                let fd = unsafe { libc::dup(fd) };
+                if fd == -1 {
+                    return Err(anyhow::anyhow!(
+                        "dup failed with errno: {}",
+                        crate::shm_sync::errno()
+                    ));
+                }
+                let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+                Ok(AsyncFd::with_interest(fd, Interest::READABLE)?)
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        loop {
+            fds.iter().for_each(|&fd| drain(fd));
+            if let Some(ready) = Self::first_ready(pipes) {
+                return Ok(ready);
+            }
+
+            let readable = std::future::poll_fn(|cx| {
+                for fd in &async_fds {
+                    if let Poll::Ready(guard) = fd.poll_read_ready(cx) {
+                        if let Ok(mut guard) = guard {
+                            guard.clear_ready();
+                        }
+                        return Poll::Ready(());
+                    }
+                }
+                Poll::Pending
+            });
+            let _ = tokio::time::timeout(interval, readable).await;
+        }
+    }
+
+    /// 第一条有 READY 槽位的管道
+    fn first_ready(pipes: &[&dyn DynamicPipe]) -> Option<usize> {
+        pipes.iter().position(|pipe| pipe.has_ready())
+    }
+
+    /// 等待通知的最长时间：有管道没有通知 FIFO 时需要定时检查
+    fn check_interval(pipes: &[&dyn DynamicPipe], fds: &[RawFd]) -> Duration {
+        if fds.len() < pipes.len() {
+            POLL_INTERVAL
+        } else {
+            NOTIFY_FALLBACK
+        }
+    }
+}
+
+/// 取走 FIFO 中积压的全部通知（非阻塞）
+fn drain(fd: RawFd) {
+    let mut buf = [0u8; 256];
+    while unsafe { libc::read(fd, buf.as_mut_ptr().cast(), buf.len()) } > 0 {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Message;
+    use crate::heap_pipe::HeapSlotPipe;
+    use crate::pipe::{DynCrossProcessPipe, PipeConfig};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_wait_any_wakes_on_any_pipe() {
+        let requests_name = format!("mi7_test_pipe_set_requests_{}", std::process::id());
+        let control_name = format!("mi7_test_pipe_set_control_{}", std::process::id());
+        let requests =
+            DynCrossProcessPipe::create_with_config(&requests_name, PipeConfig::new(4, 128))
+                .unwrap();
+        let control = Arc::new(
+            DynCrossProcessPipe::create_with_config(&control_name, PipeConfig::new(4, 128))
+                .unwrap(),
+        );
+        let timeout = Duration::from_secs(1);
+
+        let pipes: [&dyn DynamicPipe; 2] = [&*control, &requests];
+        assert!(
+            PipeSet::wait_any(&pipes, Duration::from_millis(20))
+                .unwrap_err()
+                .is::<PipeTimeout>()
+        );
+
+        // 另一线程向控制管道发送，等待者由通知唤醒
+        let sender = {
+            let control = Arc::clone(&control);
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(20));
+                control
+                    .send_blocking(Message::init("stop".to_string()), timeout)
+                    .unwrap();
+            })
+        };
+        let start = Instant::now();
+        assert_eq!(PipeSet::wait_any(&pipes, timeout).unwrap(), 0);
+        assert!(start.elapsed() < NOTIFY_FALLBACK);
+        sender.join().unwrap();
+
+        // 两条管道都有消息时位置靠前的优先
+        requests
+            .send_blocking(Message::init("work".to_string()), timeout)
+            .unwrap();
+        assert_eq!(PipeSet::wait_any_async(&pipes).await.unwrap(), 0);
+        assert_eq!(
+            pipes[0].receive_blocking(Duration::ZERO).unwrap().data,
+            b"stop"
+        );
+        assert_eq!(PipeSet::wait_any_async(&pipes).await.unwrap(), 1);
+        assert_eq!(
+            pipes[1].receive_blocking(Duration::ZERO).unwrap().data,
+            b"work"
+        );
+
+        // 没有通知 FIFO 的进程内管道按间隔检查
+        let heap =
+            HeapSlotPipe::create(&format!("{}_heap", requests_name), PipeConfig::new(4, 128))
+                .unwrap();
+        heap.send_blocking(Message::init("heap".to_string()), timeout)
+            .unwrap();
+        let pipes: [&dyn DynamicPipe; 2] = [&requests, &heap];
+        assert_eq!(PipeSet::wait_any(&pipes, timeout).unwrap(), 1);
+    }
+}
@@ -0,0 +1,303 @@
+//! 以异步流的方式消费与写入管道
+//!
+//! [`PipeStream`] 为任意 [`DynamicPipe`] 实现 `futures::Stream<Item = Result<Message>>`，
+//! worker 可以用 `while let Some(message) = stream.next().await` 或 `buffer_unordered` 等组合子
+//! 处理消息，而不必手写 fetch / receive 循环：
+//!
+//! ```no_run
+//! # use futures::StreamExt;
+//! # use mi7::pipe::DynCrossProcessPipe;
+//! # use mi7::pipe_stream::PipeStream;
+//! # use std::sync::Arc;
+//! # async fn example() -> anyhow::Result<()> {
+//! let pipe = Arc::new(DynCrossProcessPipe::connect("task_queue")?);
+//! let mut stream = PipeStream::new(pipe);
+//! while let Some(message) = stream.next().await {
+//!     println!("收到 {} 字节", message?.data.len());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! 等待新消息时使用管道的 [`DynamicPipe::fetch_async`]：共享内存管道在写者的通知上挂起，
+//! 由通知唤醒，不占用运行时线程。流不会结束；读取失败的消息作为 `Err` 产出，之后继续读取，
+//! 已过期的消息被跳过。
+//!
+//! 在取得槽位之前丢弃流不会丢失消息：槽位在同一次轮询中取得并读取完成。
+//!
+//! [`PipeSink`] 实现 `futures::Sink<Message>`，管道满时异步等待空槽位；
+//! [`bridge_to_mpsc`] / [`bridge_from_mpsc`] 在后台任务中把管道与 tokio mpsc 通道相连。
+
+use crate::Message;
+use crate::error::Result;
+use crate::pipe::{DynamicPipe, MessageExpired, PipeTimeout};
+use futures_core::Stream;
+use futures_sink::Sink;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, ready};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+/// 正在进行的读取或写入
+type Pending<T> = Pin<Box<dyn Future<Output = Result<T>> + Send>>;
+
+/// 逐条产出管道消息的异步流
+pub struct PipeStream<P: ?Sized> {
+    pipe: Arc<P>,
+    receiving: Option<Pending<Message>>,
+}
+
+impl<P: DynamicPipe + ?Sized + 'static> PipeStream<P> {
+    pub fn new(pipe: Arc<P>) -> Self {
+        Self {
+            pipe,
+            receiving: None,
+        }
+    }
+
+    /// 被消费的管道
+    pub fn pipe(&self) -> &Arc<P> {
+        &self.pipe
+    }
+
+    /// 等待并读取下一条未过期的消息
+    fn receive_next(pipe: Arc<P>) -> Pending<Message> {
+        Box::pin(async move {
+            loop {
+                let index = pipe.fetch_async().await?;
+                match pipe.receive(index) {
+                    Err(e) if e.is::<MessageExpired>() => continue,
+                    received => return received,
+                }
+            }
+        })
+    }
+}
+
+impl<P: DynamicPipe + ?Sized + 'static> Stream for PipeStream<P> {
+    type Item = Result<Message>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        let receiving = this
+            .receiving
+            .get_or_insert_with(|| Self::receive_next(Arc::clone(&this.pipe)));
+        let received = ready!(receiving.as_mut().poll(cx));
+        this.receiving = None;
+        Poll::Ready(Some(received))
+    }
+}
+
+/// 把消息写入管道的 `Sink`
+///
+/// 同一时间只有一条消息在写入：`poll_ready` 等待上一条写入完成，写入失败的错误在下一次
+/// `poll_ready` / `poll_flush` 时返回。
+pub struct PipeSink<P: ?Sized> {
+    pipe: Arc<P>,
+    timeout: Option<Duration>,
+    sending: Option<Pending<u64>>,
+}
+
+impl<P: DynamicPipe + ?Sized + 'static> PipeSink<P> {
+    pub fn new(pipe: Arc<P>) -> Self {
+        Self {
+            pipe,
+            timeout: None,
+            sending: None,
+        }
+    }
+
+    /// 设置等待空槽位的超时，超时后写入失败并返回 [`PipeTimeout`]；默认一直等待
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// 被写入的管道
+    pub fn pipe(&self) -> &Arc<P> {
+        &self.pipe
+    }
+
+    /// 等待空槽位并写入消息，返回 request_id
+    fn send_next(pipe: Arc<P>, message: Message, timeout: Option<Duration>) -> Pending<u64> {
+        Box::pin(async move {
+            let index = match timeout {
+                Some(timeout) => tokio::time::timeout(timeout, pipe.hold_async())
+                    .await
+                    .map_err(|_| PipeTimeout::Send(timeout))??,
+                None => pipe.hold_async().await?,
+            };
+            pipe.send(index, message)
+        })
+    }
+
+    /// 等待正在进行的写入完成
+    fn poll_sent(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        if let Some(sending) = &mut self.sending {
+            let sent = ready!(sending.as_mut().poll(cx));
+            self.sending = None;
+            sent?;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<P: DynamicPipe + ?Sized + 'static> Sink<Message> for PipeSink<P> {
+    type Error = crate::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.poll_sent(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, message: Message) -> Result<()> {
+        let pipe = Arc::clone(&self.pipe);
+        self.sending = Some(Self::send_next(pipe, message, self.timeout));
+        Ok(())
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.poll_sent(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.poll_sent(cx)
+    }
+}
+
+/// 在后台任务中把管道中的消息转发到 tokio mpsc 通道，接收端关闭后任务结束
+///
+/// 先取得通道容量再读取管道，通道满时管道中的消息保持不动；读取失败的消息记录日志后跳过。
+pub fn bridge_to_mpsc<P>(pipe: Arc<P>, tx: mpsc::Sender<Message>) -> JoinHandle<()>
+where
+    P: DynamicPipe + ?Sized + 'static,
+{
+    tokio::spawn(async move {
+        let name = pipe.name().to_string();
+        let mut stream = PipeStream::new(pipe);
+        while let Ok(permit) = tx.reserve().await {
+            // 等待消息期间接收端关闭时直接结束，流在取得槽位之前可以安全丢弃
+            let received = tokio::select! {
+                received = std::future::poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)) => received,
+                _ = tx.closed() => break,
+            };
+            match received {
+                Some(Ok(message)) => permit.send(message),
+                Some(Err(e)) => warn!("从管道 {} 读取消息失败: {}", name, e),
+                None => break,
+            }
+        }
+    })
+}
+
+/// 在后台任务中把 tokio mpsc 通道中的消息写入管道，发送端全部关闭后任务结束
+///
+/// 管道满时等待空槽位，通道随之被填满，背压传递给发送方；写入失败的消息记录日志后丢弃。
+pub fn bridge_from_mpsc<P>(mut rx: mpsc::Receiver<Message>, pipe: Arc<P>) -> JoinHandle<()>
+where
+    P: DynamicPipe + ?Sized + 'static,
+{
+    tokio::spawn(async move {
+        while let Some(message) = rx.recv().await {
+            if let Err(e) = PipeSink::send_next(Arc::clone(&pipe), message, None).await {
+                warn!("写入管道 {} 失败: {}", pipe.name(), e);
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipe::{DynCrossProcessPipe, PipeConfig};
+    use futures::{SinkExt, StreamExt};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_pipe_stream() {
+        let name = format!("mi7_test_pipe_stream_{}", std::process::id());
+        let pipe = Arc::new(
+            DynCrossProcessPipe::create_with_config(&name, PipeConfig::new(8, 256)).unwrap(),
+        );
+        let producer = {
+            let pipe = Arc::clone(&pipe);
+            tokio::spawn(async move {
+                for i in 0..4 {
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                    pipe.send_blocking(Message::init(i.to_string()), Duration::from_secs(1))
+                        .unwrap();
+                }
+            })
+        };
+
+        // 等待中的流由写者唤醒，消息按发送顺序产出
+        let mut stream = PipeStream::new(Arc::clone(&pipe));
+        for i in 0..2 {
+            let message = stream.next().await.unwrap().unwrap();
+            assert_eq!(message.data, i.to_string().as_bytes());
+        }
+
+        // 组合子：并发处理剩余消息
+        let mut sizes: Vec<usize> = stream
+            .take(2)
+            .map(|message| async move { message.unwrap().data.len() })
+            .buffer_unordered(2)
+            .collect()
+            .await;
+        sizes.sort();
+        assert_eq!(sizes, [1, 1]);
+        producer.await.unwrap();
+        assert_eq!(pipe.status().received_count, 4);
+    }
+
+    #[tokio::test]
+    async fn test_pipe_sink_and_mpsc_bridges() {
+        let name = format!("mi7_test_pipe_sink_{}", std::process::id());
+        let pipe = Arc::new(
+            DynCrossProcessPipe::create_with_config(&name, PipeConfig::new(2, 256)).unwrap(),
+        );
+        let (in_tx, in_rx) = mpsc::channel(4);
+        let (out_tx, mut out_rx) = mpsc::channel(1);
+        let writer = bridge_from_mpsc(in_rx, Arc::clone(&pipe));
+        let reader = bridge_to_mpsc(Arc::clone(&pipe), out_tx);
+
+        let collector = tokio::spawn(async move {
+            let mut received = Vec::new();
+            for _ in 0..6 {
+                let message = tokio::time::timeout(Duration::from_secs(5), out_rx.recv())
+                    .await
+                    .unwrap()
+                    .unwrap();
+                received.push(String::from_utf8(message.data).unwrap());
+            }
+            (received, out_rx)
+        });
+
+        // 管道只有 2 个槽位：写入方等待读取方腾出空槽位
+        let mut sink = PipeSink::new(Arc::clone(&pipe)).with_timeout(Duration::from_secs(5));
+        for i in 0..3 {
+            in_tx
+                .send(Message::init(format!("mpsc{}", i)))
+                .await
+                .unwrap();
+            sink.send(Message::init(format!("sink{}", i)))
+                .await
+                .unwrap();
+        }
+        let (mut received, out_rx) = collector.await.unwrap();
+        received.sort();
+        assert_eq!(
+            received,
+            ["mpsc0", "mpsc1", "mpsc2", "sink0", "sink1", "sink2"]
+        );
+
+        // 两端关闭后桥接任务结束
+        drop(in_tx);
+        drop(out_rx);
+        writer.await.unwrap();
+        reader.await.unwrap();
+    }
+}
@@ -0,0 +1,213 @@
+//! 跨进程限流
+//!
+//! [`SharedRateLimiter`] 是放在命名共享内存段中的令牌桶，同一台机器上的多个 entry 进程连接同一个段，
+//! 合计不超过设定的速率。令牌桶按 GCRA（通用信元速率算法）实现：段中只保存"理论到达时间"（TAT），
+//! 每次取令牌用一次 CAS 推进它，不需要加锁，持有者崩溃也不会留下需要恢复的状态。
+//!
+//! 时间取自单调时钟（[`monotonic_nanos`]），同一台机器上的所有进程可比较。
+
+use crate::locks::Segment;
+use crate::shm_sync::monotonic_nanos;
+
+use crate::error::Result;
+use anyhow::anyhow;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// 默认的限流器共享内存名称
+pub const DEFAULT_LIMITER_NAME: &str = "mi7_rate_limit";
+
+/// 限流拒绝：令牌不足
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("请求过于频繁，{retry_after:?} 后重试")]
+pub struct RateLimited {
+    /// 令牌足够还需等待的时间；一次请求的令牌数超过桶容量时为 [`Duration::MAX`]
+    pub retry_after: Duration,
+}
+
+/// 限流统计，所有连接方的合计
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct RateLimiterStats {
+    /// 放行的令牌数
+    pub allowed: u64,
+    /// 被拒绝的请求数
+    pub limited: u64,
+}
+
+#[repr(C)]
+struct RateBlock {
+    /// 产生一个令牌的间隔（纳秒）
+    interval: AtomicU64,
+    /// 桶容量
+    burst: AtomicU64,
+    /// 理论到达时间：已放行的令牌按速率全部补回的单调时钟时刻，不晚于当前时刻时桶为满
+    tat: AtomicU64,
+    allowed: AtomicU64,
+    limited: AtomicU64,
+}
+
+/// 共享内存中的令牌桶，见模块文档
+///
+/// 创建者 Drop 时删除共享内存段，其他进程用 [`SharedRateLimiter::open`] 连接。
+pub struct SharedRateLimiter {
+    segment: Segment<RateBlock>,
+}
+
+impl SharedRateLimiter {
+    /// 创建（或重置）限流器：每秒产生 `rate` 个令牌，桶中最多积累 `burst` 个，初始为满
+    pub fn create(name: &str, rate: u32, burst: u32) -> Result<Self> {
+        if rate == 0 || burst == 0 {
+            return Err(anyhow!("限流速率与桶容量必须大于 0").into());
+        }
+        let segment = Segment::create(name, |block: &RateBlock| {
+            block.burst.store(burst as u64, Ordering::Relaxed);
+            block
+                .interval
+                .store((1_000_000_000 / rate as u64).max(1), Ordering::Relaxed);
+        })?;
+        Ok(Self { segment })
+    }
+
+    /// 连接已创建的限流器
+    pub fn open(name: &str) -> Result<Self> {
+        Ok(Self {
+            segment: Segment::open(name)?,
+        })
+    }
+
+    fn block(&self) -> &RateBlock {
+        self.segment.get()
+    }
+
+    /// 限流器名称
+    pub fn name(&self) -> &str {
+        self.segment.name()
+    }
+
+    /// 取一个令牌，令牌不足时返回 `false`
+    pub fn try_acquire(&self) -> bool {
+        self.check(1).is_ok()
+    }
+
+    /// 取 `permits` 个令牌，令牌不足时不取走任何令牌，返回还需等待的时间
+    pub fn check(&self, permits: u32) -> Result<(), RateLimited> {
+        let block = self.block();
+        let interval = block.interval.load(Ordering::Relaxed);
+        let tolerance = interval * block.burst.load(Ordering::Relaxed);
+        let increment = interval * permits as u64;
+        if increment > tolerance {
+            block.limited.fetch_add(1, Ordering::Relaxed);
+            return Err(RateLimited {
+                retry_after: Duration::MAX,
+            });
+        }
+
+        let now = monotonic_nanos();
+        let mut tat = block.tat.load(Ordering::Acquire);
+        loop {
+            let next = tat.max(now) + increment;
+            if next - now > tolerance {
+                block.limited.fetch_add(1, Ordering::Relaxed);
+                return Err(RateLimited {
+                    retry_after: Duration::from_nanos(next - now - tolerance),
+                });
+            }
+            match block
+                .tat
+                .compare_exchange_weak(tat, next, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => {
+                    block.allowed.fetch_add(permits as u64, Ordering::Relaxed);
+                    return Ok(());
+                }
+                Err(current) => tat = current,
+            }
+        }
+    }
+
+    /// 取 `permits` 个令牌，令牌不足时等待，最多等待 `timeout`
+    pub fn acquire_timeout(&self, permits: u32, timeout: Duration) -> Result<(), RateLimited> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let Err(limited) = self.check(permits) else {
+                return Ok(());
+            };
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if limited.retry_after > remaining {
+                return Err(limited);
+            }
+            std::thread::sleep(limited.retry_after);
+        }
+    }
+
+    /// 当前桶中的令牌数
+    pub fn available(&self) -> u32 {
+        let block = self.block();
+        let interval = block.interval.load(Ordering::Relaxed);
+        let burst = block.burst.load(Ordering::Relaxed);
+        let now = monotonic_nanos();
+        let used = block.tat.load(Ordering::Acquire).saturating_sub(now);
+        burst.saturating_sub(used.div_ceil(interval)) as u32
+    }
+
+    /// 每秒产生的令牌数
+    pub fn rate(&self) -> u32 {
+        (1_000_000_000 / self.block().interval.load(Ordering::Relaxed)) as u32
+    }
+
+    /// 桶容量
+    pub fn burst(&self) -> u32 {
+        self.block().burst.load(Ordering::Relaxed) as u32
+    }
+
+    /// 所有连接方合计的放行与拒绝数量
+    pub fn stats(&self) -> RateLimiterStats {
+        let block = self.block();
+        RateLimiterStats {
+            allowed: block.allowed.load(Ordering::Relaxed),
+            limited: block.limited.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter_shared_between_handles() {
+        let name = format!("mi7_test_rate_limit_{}", std::process::id());
+        assert!(SharedRateLimiter::open(&name).is_err(), "连接方不创建段");
+        let limiter = SharedRateLimiter::create(&name, 100, 5).unwrap();
+        let other = SharedRateLimiter::open(&name).unwrap();
+        assert_eq!((other.rate(), other.burst()), (100, 5));
+        assert_eq!(limiter.available(), 5);
+
+        // 两个句柄合计只能取走桶中的 5 个令牌
+        let taken = (0..10)
+            .filter(|i| {
+                if i % 2 == 0 {
+                    limiter.try_acquire()
+                } else {
+                    other.try_acquire()
+                }
+            })
+            .count();
+        assert_eq!(taken, 5);
+        let limited = other.check(1).unwrap_err();
+        assert!(limited.retry_after <= Duration::from_millis(10));
+        assert_eq!(
+            other.check(6).unwrap_err().retry_after,
+            Duration::MAX,
+            "超过桶容量的请求永远不能满足"
+        );
+
+        // 按速率恢复令牌，等待后可以取到
+        other
+            .acquire_timeout(2, Duration::from_millis(100))
+            .unwrap();
+        let stats = limiter.stats();
+        assert_eq!(stats.allowed, 7);
+        assert!(stats.limited >= 7);
+    }
+}
@@ -0,0 +1,450 @@
+//! 可在线扩容的管道
+//!
+//! 共享内存管道的容量在创建时确定。[`ResizablePipe`] 在一个小的控制段 `{name}.ctl` 中记录
+//! 当前的"代"：第 0 代是名为 `{name}` 的普通管道，第 g 代名为 `{name}.g{g}`。
+//! [`ResizablePipe::resize`] 依次：
+//!
+//! 1. 以新容量创建下一代管道，其余配置与负载加密器沿用当前一代
+//! 2. 持有当前一代的写锁，把 READY 消息按读取顺序移入新一代（保持 request_id）
+//! 3. 在控制段中切换到新一代，释放写锁并唤醒两代管道上的等待者
+//!
+//! 各句柄每次收发前检查代数，发现切换后连接新一代，旧一代留在本句柄中继续读取，
+//! 排空（没有任何已占用槽位）后释放；创建者释放旧一代时删除其共享内存。
+//! 写者抢占槽位后再次检查代数，期间发生了切换就放弃该槽位改写新一代，
+//! 因此旧一代排空后不会再有消息写入。
+//!
+//! 读者先读旧一代再读新一代。无锁模式的写者不经过写锁，切换前抢占、迁移后才发布的消息
+//! 留在旧一代，与已迁移的消息之间不保证顺序。
+
+use crate::Message;
+use crate::locks::Segment;
+use crate::pipe::{
+    DynCrossProcessPipe, DynamicPipe, PipeConfig, PipeStatus, PipeTimeout, is_queue_full,
+};
+use crate::pipe_set::PipeSet;
+use crate::shm_sync;
+
+use crate::error::Result;
+use anyhow::{Context, anyhow};
+use std::sync::atomic::{AtomicU32, Ordering, fence};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+/// 等待消息时检查是否已切换到新一代的最长间隔
+///
+/// 扩容会唤醒旧一代上的等待者，这里只是兜底。
+const REFRESH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 共享内存中的控制块，全零即为"第 0 代、无人扩容"的初始状态
+#[repr(C)]
+struct ControlBlock {
+    generation: AtomicU32, // 当前一代
+    resizer: AtomicU32,    // 正在扩容的进程 PID，0 表示空闲
+}
+
+/// 可在线扩容的管道
+pub struct ResizablePipe {
+    name: String,
+    control: Segment<ControlBlock>,
+    /// 本句柄当前使用的代数与管道
+    current: RwLock<(u32, Arc<DynCrossProcessPipe>)>,
+    /// 已被替换、尚未排空的旧代，按代数升序
+    draining: Mutex<Vec<Arc<DynCrossProcessPipe>>>,
+}
+
+impl ResizablePipe {
+    /// 创建 `capacity` 个槽位的管道
+    pub fn create(name: &str, capacity: usize, slot_size: usize) -> Result<Self> {
+        Self::create_with_config(name, PipeConfig::new(capacity, slot_size))
+    }
+
+    /// 以指定配置创建第 0 代管道与控制段，Drop 时删除全部共享内存
+    pub fn create_with_config(name: &str, config: PipeConfig) -> Result<Self> {
+        let pipe = DynCrossProcessPipe::create_with_config(name, config)?;
+        let control = Segment::create(&Self::control_name(name), |_| {})?;
+
+        Ok(Self {
+            name: name.to_string(),
+            control,
+            current: RwLock::new((0, Arc::new(pipe))),
+            draining: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// 连接已有的可扩容管道的当前一代
+    pub fn connect(name: &str) -> Result<Self> {
+        let control = Segment::<ControlBlock>::open(&Self::control_name(name))
+            .with_context(|| format!("可扩容管道 {} 不存在", name))?;
+        let generation = control.get().generation.load(Ordering::SeqCst);
+        let pipe = DynCrossProcessPipe::connect(&Self::segment_name(name, generation))?;
+
+        Ok(Self {
+            name: name.to_string(),
+            control,
+            current: RwLock::new((generation, Arc::new(pipe))),
+            draining: Mutex::new(Vec::new()),
+        })
+    }
+
+    fn control(&self) -> &ControlBlock {
+        self.control.get()
+    }
+
+    /// 控制段名称
+    pub fn control_name(name: &str) -> String {
+        format!("{}.ctl", name)
+    }
+
+    /// 第 `generation` 代管道的名称
+    pub fn segment_name(name: &str, generation: u32) -> String {
+        match generation {
+            0 => name.to_string(),
+            _ => format!("{}.g{}", name, generation),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 控制段记录的当前代数
+    pub fn generation(&self) -> u32 {
+        self.control().generation.load(Ordering::SeqCst)
+    }
+
+    /// 当前一代的管道
+    pub fn current(&self) -> Result<Arc<DynCrossProcessPipe>> {
+        Ok(self.refresh()?.1)
+    }
+
+    /// 当前一代的容量
+    pub fn capacity(&self) -> Result<usize> {
+        Ok(self.current()?.capacity())
+    }
+
+    /// 当前一代的状态；本句柄尚未排空的旧代不计入
+    pub fn status(&self) -> Result<PipeStatus> {
+        Ok(self.current()?.status())
+    }
+
+    /// 本句柄中尚未排空的旧代数量
+    pub fn draining_count(&self) -> usize {
+        self.draining.lock().unwrap().len()
+    }
+
+    /// 扩容到 `new_capacity` 个槽位，返回迁移的消息数量
+    ///
+    /// 任一连接方都可以调用，同一时间只允许一个进程扩容。新容量必须大于当前容量。
+    /// 其他句柄在下一次收发时切换到新一代。
+    pub fn resize(&self, new_capacity: usize) -> Result<usize> {
+        self.lock_resize()?;
+        let result = self.resize_locked(new_capacity);
+        self.control().resizer.store(0, Ordering::Release);
+        result
+    }
+
+    fn resize_locked(&self, new_capacity: usize) -> Result<usize> {
+        let (generation, pipe) = self.refresh()?;
+        if new_capacity <= pipe.capacity() {
+            return Err(anyhow!(
+                "新容量 {} 必须大于当前容量 {}",
+                new_capacity,
+                pipe.capacity()
+            )
+            .into());
+        }
+
+        let next = generation + 1;
+        let mut successor = pipe
+            .create_successor(&Self::segment_name(&self.name, next), new_capacity)
+            .with_context(|| format!("创建 {} 的第 {} 代管道失败", self.name, next))?;
+        // 新一代由创建者负责删除，其他进程扩容时不随本句柄删除
+        if !self.control.is_owner() {
+            successor.persist();
+        }
+
+        let control = self.control();
+        let moved = match pipe.migrate_to(&successor, || {
+            control.generation.store(next, Ordering::SeqCst)
+        }) {
+            Ok(moved) => moved,
+            Err(e) => {
+                successor.destroy()?;
+                return Err(e.context(format!("迁移 {} 的消息失败", self.name)));
+            }
+        };
+        self.install(next, Arc::new(successor));
+        tracing::info!(
+            "管道 {} 扩容到 {} 个槽位（第 {} 代），迁移 {} 条消息",
+            self.name,
+            new_capacity,
+            next,
+            moved
+        );
+        Ok(moved)
+    }
+
+    /// 取得扩容权，持有者已退出时接管
+    fn lock_resize(&self) -> Result<()> {
+        let pid = std::process::id();
+        let resizer = &self.control().resizer;
+        loop {
+            match resizer.compare_exchange(0, pid, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => return Ok(()),
+                Err(holder) if holder != pid && shm_sync::process_alive(holder) => {
+                    return Err(anyhow!("管道 {} 正在被进程 {} 扩容", self.name, holder).into());
+                }
+                Err(holder) => {
+                    if resizer
+                        .compare_exchange(holder, pid, Ordering::AcqRel, Ordering::Acquire)
+                        .is_ok()
+                    {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+
+    /// 切换到控制段记录的当前一代，返回其代数与管道；并释放已排空的旧代
+    fn refresh(&self) -> Result<(u32, Arc<DynCrossProcessPipe>)> {
+        let generation = self.generation();
+        let (local, pipe) = self.current.read().unwrap().clone();
+        let current = if local == generation {
+            (local, pipe)
+        } else {
+            // 跳过的中间代可能仍有消息；已被删除的说明已经排空
+            for skipped in local + 1..generation {
+                if let Ok(pipe) =
+                    DynCrossProcessPipe::connect(&Self::segment_name(&self.name, skipped))
+                {
+                    self.install(skipped, Arc::new(pipe));
+                }
+            }
+            let name = Self::segment_name(&self.name, generation);
+            let pipe = DynCrossProcessPipe::connect(&name)
+                .with_context(|| format!("连接 {} 的第 {} 代管道失败", self.name, generation))?;
+            (generation, self.install(generation, Arc::new(pipe)))
+        };
+        self.prune_drained();
+        Ok(current)
+    }
+
+    /// 把 `pipe` 设为第 `generation` 代，原来的一代转入排空列表；返回实际的当前一代
+    fn install(&self, generation: u32, pipe: Arc<DynCrossProcessPipe>) -> Arc<DynCrossProcessPipe> {
+        let mut current = self.current.write().unwrap();
+        if generation > current.0 {
+            let (_, old) = std::mem::replace(&mut *current, (generation, pipe));
+            self.draining.lock().unwrap().push(old);
+        } else if generation == current.0 && pipe.is_owner() {
+            // 扩容期间其他线程可能已先连接新一代，换成负责删除共享内存的句柄
+            current.1 = pipe;
+        }
+        Arc::clone(&current.1)
+    }
+
+    /// 释放已排空的旧代，创建者同时删除其共享内存
+    fn prune_drained(&self) {
+        let mut draining = self.draining.lock().unwrap();
+        if draining.is_empty() {
+            return;
+        }
+        // 写者先抢占槽位再检查代数：代数已切换后看到没有占用的槽位，就不会再有写入
+        fence(Ordering::SeqCst);
+        draining.retain(|pipe| {
+            if pipe.status().used_count > 0 {
+                return true;
+            }
+            if self.control.is_owner()
+                && !pipe.is_owner()
+                && let Err(e) = pipe.unlink()
+            {
+                tracing::warn!("删除已排空的管道 {} 失败: {}", pipe.name(), e);
+            }
+            tracing::debug!("管道 {} 已排空", pipe.name());
+            false
+        });
+    }
+
+    /// 抢占槽位后确认期间没有切换到新一代，否则放弃该槽位
+    fn still_current(&self, generation: u32, pipe: &DynCrossProcessPipe, index: usize) -> bool {
+        fence(Ordering::SeqCst);
+        if self.generation() == generation {
+            return true;
+        }
+        pipe.abandon(index);
+        false
+    }
+
+    /// 发送消息，当前一代已满时等待空槽位，超时返回 [`PipeTimeout::Send`]
+    ///
+    /// 等待期间发生扩容时改写新一代。
+    pub fn send(&self, message: Message, timeout: Duration) -> Result<u64> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let (generation, pipe) = self.refresh()?;
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let index = pipe
+                .hold_timeout(remaining)
+                .map_err(|_| PipeTimeout::Send(timeout))?;
+            if self.still_current(generation, &pipe, index) {
+                return pipe.send(index, message);
+            }
+        }
+    }
+
+    /// 非阻塞发送，当前一代已满时返回 `Ok(None)`
+    pub fn try_send(&self, message: Message) -> Result<Option<u64>> {
+        loop {
+            let (generation, pipe) = self.refresh()?;
+            let index = match pipe.hold() {
+                Ok(index) => index,
+                Err(e) if is_queue_full(&e) => return Ok(None),
+                Err(e) => return Err(e),
+            };
+            if self.still_current(generation, &pipe, index) {
+                return pipe.send(index, message).map(Some);
+            }
+        }
+    }
+
+    /// 读取一条消息，先读尚未排空的旧代；都为空时返回 `Ok(None)`
+    pub fn try_receive(&self) -> Result<Option<Message>> {
+        let (_, current) = self.refresh()?;
+        let draining = self.draining.lock().unwrap().clone();
+        for pipe in draining.iter().chain([&current]) {
+            match pipe.receive_blocking(Duration::ZERO) {
+                Ok(message) => return Ok(Some(message)),
+                Err(e) if e.is::<PipeTimeout>() => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(None)
+    }
+
+    /// 阻塞读取一条消息，超时返回 [`PipeTimeout::Receive`]
+    pub fn receive_blocking(&self, timeout: Duration) -> Result<Message> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(message) = self.try_receive()? {
+                return Ok(message);
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(PipeTimeout::Receive(timeout).into());
+            }
+            let pipes = self.pipes();
+            let pipes: Vec<&dyn DynamicPipe> = pipes
+                .iter()
+                .map(|pipe| &**pipe as &dyn DynamicPipe)
+                .collect();
+            match PipeSet::wait_any(&pipes, remaining.min(REFRESH_INTERVAL)) {
+                Err(e) if !e.is::<PipeTimeout>() => return Err(e),
+                _ => {}
+            }
+        }
+    }
+
+    /// 异步读取一条消息，等待时不占用运行时线程
+    pub async fn receive_async(&self) -> Result<Message> {
+        loop {
+            if let Some(message) = self.try_receive()? {
+                return Ok(message);
+            }
+            let pipes = self.pipes();
+            let pipes: Vec<&dyn DynamicPipe> = pipes
+                .iter()
+                .map(|pipe| &**pipe as &dyn DynamicPipe)
+                .collect();
+            if let Ok(waited) =
+                tokio::time::timeout(REFRESH_INTERVAL, PipeSet::wait_any_async(&pipes)).await
+            {
+                waited?;
+            }
+        }
+    }
+
+    /// 尚未排空的旧代与当前一代
+    fn pipes(&self) -> Vec<Arc<DynCrossProcessPipe>> {
+        let mut pipes = self.draining.lock().unwrap().clone();
+        pipes.push(Arc::clone(&self.current.read().unwrap().1));
+        pipes
+    }
+}
+
+impl Drop for ResizablePipe {
+    fn drop(&mut self) {
+        if !self.control.is_owner() {
+            return;
+        }
+        // 本进程创建的各代由管道句柄自己删除，其余的在这里删除；控制段由 Segment 删除
+        for pipe in self.pipes() {
+            if !pipe.is_owner()
+                && let Err(e) = pipe.unlink()
+            {
+                tracing::warn!("删除共享内存段 {} 失败: {}", pipe.name(), e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resize_migrates_and_drains() {
+        let name = format!("mi7_test_resizable_{}", std::process::id());
+        let timeout = Duration::from_secs(1);
+        let producer = Arc::new(ResizablePipe::create(&name, 2, 128).unwrap());
+        let consumer = ResizablePipe::connect(&name).unwrap();
+        for i in 0..2 {
+            producer
+                .send(Message::init(format!("m{}", i)), timeout)
+                .unwrap();
+        }
+        assert_eq!(
+            producer
+                .try_send(Message::init("full".to_string()))
+                .unwrap(),
+            None
+        );
+
+        // 队列满时等待的写者在扩容后改写新一代
+        let blocked = {
+            let producer = Arc::clone(&producer);
+            std::thread::spawn(move || {
+                producer
+                    .send(Message::init("m2".to_string()), timeout)
+                    .unwrap()
+            })
+        };
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(producer.resize(2).is_err());
+        assert_eq!(producer.resize(8).unwrap(), 2);
+        blocked.join().unwrap();
+        assert_eq!(producer.generation(), 1);
+        assert_eq!(producer.capacity().unwrap(), 8);
+
+        for i in 3..8 {
+            producer
+                .send(Message::init(format!("m{}", i)), timeout)
+                .unwrap();
+        }
+
+        // 连接在扩容之前的读者按顺序读到全部消息
+        for i in 0..8 {
+            let message = consumer.receive_blocking(timeout).unwrap();
+            assert_eq!(message.data, format!("m{}", i).as_bytes());
+        }
+        assert!(consumer.try_receive().unwrap().is_none());
+        assert_eq!(consumer.draining_count(), 0);
+
+        // 旧一代排空后由创建者删除，新连接直接进入新一代
+        producer.status().unwrap();
+        assert_eq!(producer.draining_count(), 0);
+        assert!(DynCrossProcessPipe::connect(&name).is_err());
+        let late = ResizablePipe::connect(&name).unwrap();
+        assert_eq!(late.capacity().unwrap(), 8);
+    }
+}
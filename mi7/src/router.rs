@@ -0,0 +1,284 @@
+//! 按 [`Message::flag`] 分派请求的路由
+//!
+//! worker 为每种消息标志注册处理函数，取代手写的解码与分派循环：
+//!
+//! ```no_run
+//! # use mi7::{Message, command, router::Router, rpc::RpcServer};
+//! # async fn example(server: RpcServer) {
+//! let router = Router::new()
+//!     .on(command::FLAG_HTTP, |request: Message| Ok(request))
+//!     .on(command::FLAG_MQTT, |request: Message| Ok(request));
+//! router.run(&server).await;
+//! # }
+//! ```
+//!
+//! 没有对应处理函数的标志返回 [`RouteError::Unrouted`]（可用 [`Router::fallback`] 兜底），
+//! 处理函数失败时返回 [`RouteError::Handler`]；[`Router::run`] 把错误转为错误回复，不中断循环。
+//! 每个标志的处理次数、失败次数与耗时见 [`Router::metrics`]。
+//!
+//! [`Router`] 同时实现了 [`InterfaceApi`]，可以直接交给 [`Interface::load`](crate::interface::Interface::load)。
+
+use crate::Message;
+use crate::error::Result;
+use crate::interface::InterfaceApi;
+use crate::rpc::RpcServer;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+/// 处理函数：由请求消息生成回复消息
+type Handler = Box<dyn Fn(Message) -> Result<Message> + Send + Sync>;
+
+/// 路由失败
+#[derive(Debug, thiserror::Error)]
+pub enum RouteError {
+    #[error("没有处理 flag={0} 的处理函数")]
+    Unrouted(u8),
+    #[error("flag={flag} 的处理函数失败: {source}")]
+    Handler {
+        flag: u8,
+        #[source]
+        source: crate::Error,
+    },
+}
+
+/// 单个处理函数的统计
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RouteMetrics {
+    /// 消息标志，`None` 表示兜底处理函数
+    pub flag: Option<u8>,
+    /// 处理的消息数量，包括失败的
+    pub handled: u64,
+    /// 处理函数返回错误的数量
+    pub failed: u64,
+    /// 处理函数的累计耗时
+    pub total: Duration,
+}
+
+impl RouteMetrics {
+    /// 平均每条消息的处理耗时
+    pub fn mean(&self) -> Duration {
+        if self.handled == 0 {
+            Duration::ZERO
+        } else {
+            self.total / self.handled as u32
+        }
+    }
+}
+
+/// 已注册的处理函数及其统计
+struct Route {
+    handler: Handler,
+    handled: AtomicU64,
+    failed: AtomicU64,
+    nanos: AtomicU64,
+}
+
+impl Route {
+    fn new(handler: Handler) -> Self {
+        Self {
+            handler,
+            handled: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+            nanos: AtomicU64::new(0),
+        }
+    }
+
+    fn call(&self, message: Message) -> Result<Message> {
+        let started = Instant::now();
+        let result = (self.handler)(message);
+        self.nanos
+            .fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        self.handled.fetch_add(1, Ordering::Relaxed);
+        if result.is_err() {
+            self.failed.fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+
+    fn metrics(&self, flag: Option<u8>) -> RouteMetrics {
+        RouteMetrics {
+            flag,
+            handled: self.handled.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            total: Duration::from_nanos(self.nanos.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// 按消息标志分派请求的路由
+#[derive(Default)]
+pub struct Router {
+    routes: HashMap<u8, Route>,
+    fallback: Option<Route>,
+    unrouted: AtomicU64,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 注册处理 `flag` 消息的处理函数，重复注册时后者生效
+    pub fn on<F>(mut self, flag: u8, handler: F) -> Self
+    where
+        F: Fn(Message) -> Result<Message> + Send + Sync + 'static,
+    {
+        self.routes.insert(flag, Route::new(Box::new(handler)));
+        self
+    }
+
+    /// 注册兜底处理函数，处理没有对应处理函数的消息
+    pub fn fallback<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(Message) -> Result<Message> + Send + Sync + 'static,
+    {
+        self.fallback = Some(Route::new(Box::new(handler)));
+        self
+    }
+
+    /// 把消息交给对应的处理函数
+    pub fn dispatch(&self, message: Message) -> Result<Message, RouteError> {
+        let flag = message.flag;
+        let route = match self.routes.get(&flag).or(self.fallback.as_ref()) {
+            Some(route) => route,
+            None => {
+                self.unrouted.fetch_add(1, Ordering::Relaxed);
+                return Err(RouteError::Unrouted(flag));
+            }
+        };
+        route
+            .call(message)
+            .map_err(|source| RouteError::Handler { flag, source })
+    }
+
+    /// 各处理函数的统计，按标志排列，兜底处理函数在最后
+    pub fn metrics(&self) -> Vec<RouteMetrics> {
+        let mut metrics: Vec<_> = self
+            .routes
+            .iter()
+            .map(|(flag, route)| route.metrics(Some(*flag)))
+            .collect();
+        metrics.sort_by_key(|metrics| metrics.flag);
+        metrics.extend(self.fallback.as_ref().map(|route| route.metrics(None)));
+        metrics
+    }
+
+    /// 没有对应处理函数而被拒绝的消息数量
+    pub fn unrouted_count(&self) -> u64 {
+        self.unrouted.load(Ordering::Relaxed)
+    }
+
+    /// 循环读取请求、分派并回复，收到停止控制消息时确认后返回
+    ///
+    /// 读取失败、处理失败与回复失败都只记录日志；处理失败时回复错误消息。
+    pub async fn run(&self, server: &RpcServer) {
+        loop {
+            let (message, responder) = match server.next().await {
+                Ok(request) => request,
+                Err(e) => {
+                    error!("读取请求失败: {}", e);
+                    continue;
+                }
+            };
+
+            if message.is_shutdown() {
+                info!("路由收到停止控制消息");
+                if let Err(e) = responder.reply(Message::shutdown()).await {
+                    error!("停止控制消息确认失败: {}", e);
+                }
+                return;
+            }
+
+            let request_id = responder.request_id();
+            let reply = self.handle(message).unwrap_or_else(|e| {
+                warn!("请求 {} 处理失败: {}", request_id, e);
+                error_reply(&e)
+            });
+            if let Err(e) = responder.reply(reply).await {
+                error!("请求 {} 回复失败: {}", request_id, e);
+            }
+        }
+    }
+}
+
+impl InterfaceApi for Router {
+    fn handle(&self, message: Message) -> Result<Message> {
+        self.dispatch(message).map_err(Into::into)
+    }
+}
+
+/// 处理失败时回复请求方的消息
+pub(crate) fn error_reply(error: &crate::Error) -> Message {
+    let error = serde_json::json!({
+        "success": false,
+        "error": error.to_string(),
+    });
+    Message::init(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipe::{DynamicPipe, PipeFactory};
+    use crate::rpc::RpcChannel;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_router_dispatches_by_flag() {
+        let request_pipe: Arc<Box<dyn DynamicPipe>> =
+            Arc::new(PipeFactory::create("memory(4x256)", "router_req").unwrap());
+        let response_pipe: Arc<Box<dyn DynamicPipe>> =
+            Arc::new(PipeFactory::create("memory(4x256)", "router_resp").unwrap());
+        let rpc = RpcChannel::new(Arc::clone(&request_pipe), Arc::clone(&response_pipe));
+        let dispatcher = rpc.start();
+
+        let router = Arc::new(
+            Router::new()
+                .on(1, |request: Message| {
+                    Ok(Message::new(1, format!("one:{}", request.flag)))
+                })
+                .on(2, |_| Err(anyhow::anyhow!("boom").into())),
+        );
+        let server = RpcServer::new(request_pipe, response_pipe);
+        let running = {
+            let router = Arc::clone(&router);
+            tokio::spawn(async move { router.run(&server).await })
+        };
+
+        let timeout = Duration::from_secs(5);
+        let reply = rpc
+            .call_timeout(Message::new(1, String::new()), timeout)
+            .await
+            .unwrap();
+        assert_eq!(reply.data, b"one:1");
+
+        // 处理失败与未注册的标志都得到错误回复，循环继续
+        for flag in [2, 3] {
+            let reply = rpc
+                .call_timeout(Message::new(flag, String::new()), timeout)
+                .await
+                .unwrap();
+            assert!(String::from_utf8_lossy(&reply.data).contains("\"success\":false"));
+        }
+
+        let reply = rpc
+            .call_timeout(Message::shutdown(), timeout)
+            .await
+            .unwrap();
+        assert!(reply.is_shutdown());
+        running.await.unwrap();
+        dispatcher.abort();
+
+        let metrics = router.metrics();
+        assert_eq!(
+            metrics
+                .iter()
+                .map(|m| (m.flag, m.handled, m.failed))
+                .collect::<Vec<_>>(),
+            [(Some(1), 1, 0), (Some(2), 1, 1)]
+        );
+        assert_eq!(router.unrouted_count(), 1);
+    }
+}
@@ -0,0 +1,597 @@
+//! 基于管道的请求/响应（RPC）关联
+//!
+//! [`RpcChannel`] 将请求管道与响应管道配对：请求以唯一的 request_id 写入请求管道，
+//! 响应方沿用同一 request_id 写回响应管道，后台分发任务按 ID 唤醒等待中的调用者。
+//! 除创建时指定的响应管道外，通道还可以挂接各 worker 的专属响应管道
+//! （见 [`RpcChannel::discover`]），每个响应管道由独立的分发任务读取。
+//!
+//! 响应方还可以通过 [`Pusher`] 主动推送消息（[`Message::PUSH`]），这类消息不对应任何请求，
+//! 由分发任务交给 [`RpcChannel::subscribe_pushes`] 的订阅者。
+
+use crate::Message;
+use crate::pipe::{DynamicPipe, MessageExpired, PipeFactory};
+use crate::worker_board::WorkerBoard;
+
+use crate::error::Result;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+type PendingMap = Arc<Mutex<HashMap<u64, oneshot::Sender<Message>>>>;
+
+type PushSink = Arc<Mutex<Option<mpsc::UnboundedSender<Message>>>>;
+
+/// 默认请求超时
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 请求/响应通道
+pub struct RpcChannel {
+    request_pipe: Arc<Box<dyn DynamicPipe>>,
+    response_pipe: Arc<Box<dyn DynamicPipe>>,
+    pending: PendingMap,
+    /// 推送消息的订阅者
+    pushes: PushSink,
+    next_id: AtomicU64,
+    timeout: Duration,
+    /// 已挂接的 worker 响应管道：名称 -> (创建者 PID, 分发任务)
+    attached: Mutex<HashMap<String, (u32, JoinHandle<()>)>>,
+}
+
+impl RpcChannel {
+    /// 由请求管道和响应管道创建通道
+    ///
+    /// request_id 高 32 位为当前进程 PID，避免多个请求方的 ID 冲突
+    pub fn new(
+        request_pipe: Arc<Box<dyn DynamicPipe>>,
+        response_pipe: Arc<Box<dyn DynamicPipe>>,
+    ) -> Self {
+        Self {
+            request_pipe,
+            response_pipe,
+            pending: Arc::new(Mutex::new(HashMap::new())),
+            pushes: Arc::new(Mutex::new(None)),
+            next_id: AtomicU64::new(((std::process::id() as u64) << 32) | 1),
+            timeout: DEFAULT_TIMEOUT,
+            attached: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 本进程的共享响应管道（不含挂接的 worker 专属响应管道）
+    pub fn response_pipe(&self) -> &Arc<Box<dyn DynamicPipe>> {
+        &self.response_pipe
+    }
+
+    /// 设置默认请求超时
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// 默认请求超时
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// 等待响应中的请求数量
+    pub fn pending_count(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+
+    /// 订阅响应方推送的消息，替换此前的订阅者；没有订阅者时推送消息被丢弃
+    pub fn subscribe_pushes(&self) -> mpsc::UnboundedReceiver<Message> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        *self.pushes.lock().unwrap() = Some(tx);
+        rx
+    }
+
+    /// 启动后台响应分发任务
+    ///
+    /// 从响应管道读取消息，按 request_id 交给对应的等待者；
+    /// 找不到等待者（已超时或取消）的响应直接丢弃
+    pub fn start(&self) -> JoinHandle<()> {
+        Self::spawn_dispatcher(
+            Arc::clone(&self.response_pipe),
+            Arc::clone(&self.pending),
+            Arc::clone(&self.pushes),
+        )
+    }
+
+    /// 挂接一个额外的响应管道（例如某个 worker 的专属响应管道），由独立的分发任务读取
+    ///
+    /// `owner_pid` 为创建该管道的进程；同名管道已挂接且创建者相同时不做任何事，
+    /// 创建者不同（worker 重启后重建了管道）时替换旧的挂接。返回是否新挂接。
+    pub fn attach(&self, name: &str, owner_pid: u32, pipe: Arc<Box<dyn DynamicPipe>>) -> bool {
+        let mut attached = self.attached.lock().unwrap();
+        if attached.get(name).is_some_and(|(pid, _)| *pid == owner_pid) {
+            return false;
+        }
+        let handle =
+            Self::spawn_dispatcher(pipe, Arc::clone(&self.pending), Arc::clone(&self.pushes));
+        if let Some((_, old)) = attached.insert(name.to_string(), (owner_pid, handle)) {
+            old.abort();
+        }
+        debug!("[RPC] 已挂接响应管道 {} (PID {})", name, owner_pid);
+        true
+    }
+
+    /// 按类型与名称连接并挂接响应管道，已挂接时不重复连接
+    pub fn attach_named(&self, pipe_type: &str, name: &str, owner_pid: u32) -> Result<bool> {
+        let current = self
+            .attached
+            .lock()
+            .unwrap()
+            .get(name)
+            .is_some_and(|(pid, _)| *pid == owner_pid);
+        if current {
+            return Ok(false);
+        }
+        let pipe = PipeFactory::connect(pipe_type, name, false)?;
+        Ok(self.attach(name, owner_pid, Arc::new(pipe)))
+    }
+
+    /// 取消挂接响应管道，返回此前是否已挂接
+    pub fn detach(&self, name: &str) -> bool {
+        match self.attached.lock().unwrap().remove(name) {
+            Some((_, handle)) => {
+                handle.abort();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 是否已挂接指定名称的响应管道
+    pub fn is_attached(&self, name: &str) -> bool {
+        self.attached.lock().unwrap().contains_key(name)
+    }
+
+    /// 已挂接的响应管道名称
+    pub fn response_pipes(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.attached.lock().unwrap().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// 按 worker 登记表挂接各 worker 的专属响应管道，并取消挂接已注销 worker 的管道
+    pub fn sync_response_pipes(&self, board: &WorkerBoard, pipe_type: &str) {
+        let workers = board.workers();
+        for worker in &workers {
+            if worker.response_pipe.is_empty() {
+                continue;
+            }
+            if let Err(e) = self.attach_named(pipe_type, &worker.response_pipe, worker.pid) {
+                warn!(
+                    "[RPC] 连接 worker {} 的响应管道 {} 失败: {}",
+                    worker.worker_id, worker.response_pipe, e
+                );
+            }
+        }
+        for name in self.response_pipes() {
+            if !workers.iter().any(|worker| worker.response_pipe == name) {
+                self.detach(&name);
+                debug!("[RPC] 已取消挂接响应管道 {}", name);
+            }
+        }
+    }
+
+    /// 在后台每隔 `interval` 按登记表同步一次 worker 的响应管道
+    pub fn discover(
+        self: &Arc<Self>,
+        board: Arc<WorkerBoard>,
+        pipe_type: &str,
+        interval: Duration,
+    ) -> JoinHandle<()> {
+        let channel = Arc::downgrade(self);
+        let pipe_type = pipe_type.to_string();
+        tokio::spawn(async move {
+            while let Some(channel) = channel.upgrade() {
+                channel.sync_response_pipes(&board, &pipe_type);
+                drop(channel);
+                tokio::time::sleep(interval).await;
+            }
+        })
+    }
+
+    fn spawn_dispatcher(
+        response_pipe: Arc<Box<dyn DynamicPipe>>,
+        pending: PendingMap,
+        pushes: PushSink,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                let index = match response_pipe.fetch_async().await {
+                    Ok(index) => index,
+                    Err(_) => continue,
+                };
+
+                match response_pipe.receive_tagged(index) {
+                    Ok((_, message)) if message.flag == Message::PUSH => {
+                        Self::forward_push(&pushes, message)
+                    }
+                    Ok((request_id, message)) => Self::dispatch(&pending, request_id, message),
+                    Err(e) => warn!("[RPC] 读取响应槽位 {} 失败: {}", index, e),
+                }
+            }
+        })
+    }
+
+    /// 将响应交给等待者
+    fn dispatch(pending: &PendingMap, request_id: u64, message: Message) {
+        let tx = pending.lock().unwrap().remove(&request_id);
+        match tx {
+            Some(tx) => {
+                if tx.send(message).is_err() {
+                    debug!("[RPC] 请求 {} 的等待者已退出，丢弃响应", request_id);
+                }
+            }
+            None => debug!(
+                "[RPC] 请求 {} 没有等待者（已超时或取消），丢弃响应",
+                request_id
+            ),
+        }
+    }
+
+    /// 将推送消息交给订阅者
+    fn forward_push(pushes: &PushSink, message: Message) {
+        let mut pushes = pushes.lock().unwrap();
+        let delivered = pushes.as_ref().is_some_and(|tx| tx.send(message).is_ok());
+        if !delivered {
+            *pushes = None;
+            debug!("[RPC] 没有推送订阅者，丢弃推送消息");
+        }
+    }
+
+    /// 发送请求并返回等待句柄，使用默认超时获取请求槽位
+    pub async fn request(&self, message: Message) -> Result<PendingReply> {
+        self.request_until(message, Instant::now() + self.timeout)
+            .await
+    }
+
+    /// 发送请求并等待响应，使用默认超时
+    pub async fn call(&self, message: Message) -> Result<Message> {
+        self.call_timeout(message, self.timeout).await
+    }
+
+    /// 发送请求并等待响应
+    ///
+    /// `timeout` 覆盖获取槽位与等待响应的总时长；返回的 future 被丢弃时请求随之取消
+    pub async fn call_timeout(&self, message: Message, timeout: Duration) -> Result<Message> {
+        let deadline = Instant::now() + timeout;
+        let reply = self.request_until(message, deadline).await?;
+        reply.wait_until(deadline).await
+    }
+
+    /// 经由指定的请求管道（例如某个 worker 的收件管道）发送请求，使用默认超时获取请求槽位
+    ///
+    /// 响应仍由本通道的响应管道接收。
+    pub async fn request_via(
+        &self,
+        request_pipe: &Arc<Box<dyn DynamicPipe>>,
+        message: Message,
+    ) -> Result<PendingReply> {
+        self.request_via_until(request_pipe, message, Instant::now() + self.timeout)
+            .await
+    }
+
+    /// 取消等待中的请求，返回请求是否仍在等待
+    ///
+    /// 已写入请求管道的消息不会撤回，之后到达的响应会被丢弃
+    pub fn cancel(&self, request_id: u64) -> bool {
+        self.pending.lock().unwrap().remove(&request_id).is_some()
+    }
+
+    async fn request_until(&self, message: Message, deadline: Instant) -> Result<PendingReply> {
+        self.request_via_until(&self.request_pipe, message, deadline)
+            .await
+    }
+
+    async fn request_via_until(
+        &self,
+        request_pipe: &Arc<Box<dyn DynamicPipe>>,
+        mut message: Message,
+        deadline: Instant,
+    ) -> Result<PendingReply> {
+        // 超过等待期限的请求即使被处理，响应也无人接收
+        if message.expires_at == 0 {
+            message = message.with_ttl(deadline.saturating_duration_since(Instant::now()));
+        }
+        let request_id = self.next_id.fetch_add(1, Ordering::Relaxed);
+
+        // 先登记等待者再发送，避免响应先于登记到达
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(request_id, tx);
+        let reply = PendingReply {
+            request_id,
+            rx,
+            pending: Arc::clone(&self.pending),
+        };
+
+        let index = tokio::time::timeout_at(deadline, request_pipe.hold_async())
+            .await
+            .map_err(|_| anyhow::anyhow!("请求 {} 等待空槽位超时", request_id))??;
+
+        request_pipe.send_tagged(index, request_id, message)?;
+
+        Ok(reply)
+    }
+}
+
+impl Drop for RpcChannel {
+    fn drop(&mut self) {
+        for (_, (_, handle)) in self.attached.lock().unwrap().drain() {
+            handle.abort();
+        }
+    }
+}
+
+/// 等待中的请求，被丢弃时自动取消
+pub struct PendingReply {
+    request_id: u64,
+    rx: oneshot::Receiver<Message>,
+    pending: PendingMap,
+}
+
+impl PendingReply {
+    /// 请求 ID
+    pub fn request_id(&self) -> u64 {
+        self.request_id
+    }
+
+    /// 等待响应，最多等待 `timeout`
+    pub async fn wait(self, timeout: Duration) -> Result<Message> {
+        self.wait_until(Instant::now() + timeout).await
+    }
+
+    async fn wait_until(mut self, deadline: Instant) -> Result<Message> {
+        match tokio::time::timeout_at(deadline, &mut self.rx).await {
+            Ok(Ok(message)) => Ok(message),
+            Ok(Err(_)) => Err(anyhow::anyhow!("请求 {} 已取消", self.request_id).into()),
+            Err(_) => Err(anyhow::anyhow!("请求 {} 等待响应超时", self.request_id).into()),
+        }
+    }
+}
+
+impl Drop for PendingReply {
+    fn drop(&mut self) {
+        self.pending.lock().unwrap().remove(&self.request_id);
+    }
+}
+
+/// 响应方（worker）一侧的通道：读取请求并附带可回复的 [`Responder`]
+pub struct RpcServer {
+    request_pipe: Arc<Box<dyn DynamicPipe>>,
+    response_pipe: Arc<Box<dyn DynamicPipe>>,
+}
+
+impl RpcServer {
+    /// 由请求管道和响应管道创建
+    pub fn new(
+        request_pipe: Arc<Box<dyn DynamicPipe>>,
+        response_pipe: Arc<Box<dyn DynamicPipe>>,
+    ) -> Self {
+        Self {
+            request_pipe,
+            response_pipe,
+        }
+    }
+
+    /// 读取已通过 `fetch` 获取的请求槽位，返回消息与对应的回复句柄
+    pub fn receive(&self, index: usize) -> Result<(Message, Responder)> {
+        let (request_id, message) = self.request_pipe.receive_tagged(index)?;
+        Ok((message, self.responder(request_id)))
+    }
+
+    /// 异步等待并读取下一个请求，跳过已过期（请求方已放弃等待）的请求
+    pub async fn next(&self) -> Result<(Message, Responder)> {
+        loop {
+            let index = self.request_pipe.fetch_async().await?;
+            match self.receive(index) {
+                Err(e) if e.is::<MessageExpired>() => continue,
+                received => return received,
+            }
+        }
+    }
+
+    /// 为指定 request_id 创建回复句柄
+    pub fn responder(&self, request_id: u64) -> Responder {
+        Responder {
+            request_id,
+            response_pipe: Arc::clone(&self.response_pipe),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// 创建主动推送消息的句柄
+    pub fn pusher(&self) -> Pusher {
+        Pusher {
+            response_pipe: Arc::clone(&self.response_pipe),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+/// 对单个请求的回复句柄
+///
+/// 回复以请求的 request_id 写入响应管道，请求方的 [`RpcChannel`] 据此唤醒等待者
+pub struct Responder {
+    request_id: u64,
+    response_pipe: Arc<Box<dyn DynamicPipe>>,
+    timeout: Duration,
+}
+
+impl Responder {
+    /// 请求 ID
+    pub fn request_id(&self) -> u64 {
+        self.request_id
+    }
+
+    /// 设置等待响应管道空槽位的超时
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// 回复请求，响应管道满时等待空槽位直到超时
+    pub async fn reply(self, message: Message) -> Result<()> {
+        let index = tokio::time::timeout(self.timeout, self.response_pipe.hold_async())
+            .await
+            .map_err(|_| anyhow::anyhow!("回复请求 {} 等待空槽位超时", self.request_id))??;
+
+        self.response_pipe
+            .send_tagged(index, self.request_id, message)?;
+        Ok(())
+    }
+}
+
+/// 主动推送句柄：不经请求，把消息推送给请求方的某个客户端连接
+///
+/// 推送消息写入响应管道，请求方的 [`RpcChannel`] 交给 [`RpcChannel::subscribe_pushes`] 的订阅者
+#[derive(Clone)]
+pub struct Pusher {
+    response_pipe: Arc<Box<dyn DynamicPipe>>,
+    timeout: Duration,
+}
+
+impl Pusher {
+    /// 设置等待响应管道空槽位的超时
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// 推送 `payload` 到连接 `connection_id`，响应管道满时阻塞等待空槽位直到超时
+    pub fn push(&self, connection_id: u64, payload: impl AsRef<[u8]>) -> Result<()> {
+        self.response_pipe
+            .send_blocking(Message::push(connection_id, payload), self.timeout)
+            .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CrossProcessPipe;
+
+    fn unique_name(tag: &str) -> String {
+        format!("mi7_test_rpc_{}_{}", tag, std::process::id())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_rpc_call_roundtrip_and_timeout() {
+        let req_name = unique_name("req");
+        let resp_name = unique_name("resp");
+        let request_pipe: Box<dyn DynamicPipe> =
+            Box::new(CrossProcessPipe::<4, 256>::create(&req_name).unwrap());
+        let response_pipe: Box<dyn DynamicPipe> =
+            Box::new(CrossProcessPipe::<4, 256>::create(&resp_name).unwrap());
+
+        let rpc = RpcChannel::new(Arc::new(request_pipe), Arc::new(response_pipe));
+        let dispatcher = rpc.start();
+
+        // 模拟 worker：回显请求内容
+        let server = RpcServer::new(
+            Arc::new(Box::new(
+                CrossProcessPipe::<4, 256>::connect(&req_name).unwrap(),
+            )),
+            Arc::new(Box::new(
+                CrossProcessPipe::<4, 256>::connect(&resp_name).unwrap(),
+            )),
+        );
+        let responder = tokio::spawn(async move {
+            let (message, responder) = server.next().await.unwrap();
+            responder.reply(message).await.unwrap();
+        });
+
+        let reply = rpc
+            .call_timeout(Message::init("ping".to_string()), Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(reply.data, b"ping");
+        responder.await.unwrap();
+
+        // 没有响应方时超时，等待者随之清理
+        let err = rpc
+            .call_timeout(Message::init("lost".to_string()), Duration::from_millis(50))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("超时"));
+        assert_eq!(rpc.pending_count(), 0);
+
+        // 显式取消
+        let pending = rpc
+            .request(Message::init("cancel".to_string()))
+            .await
+            .unwrap();
+        assert!(rpc.cancel(pending.request_id()));
+        assert!(pending.wait(Duration::from_secs(1)).await.is_err());
+
+        dispatcher.abort();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_replies_via_worker_response_pipe() {
+        let req_name = unique_name("shared_req");
+        let resp_name = unique_name("shared_resp");
+        let worker_resp = crate::worker_board::response_pipe_name(&unique_name("w1"));
+        let board = Arc::new(WorkerBoard::create(&unique_name("board")).unwrap());
+
+        let request_pipe: Arc<Box<dyn DynamicPipe>> =
+            Arc::new(PipeFactory::create("small", &req_name).unwrap());
+        let rpc = Arc::new(RpcChannel::new(
+            Arc::clone(&request_pipe),
+            Arc::new(PipeFactory::create("small", &resp_name).unwrap()),
+        ));
+
+        // worker 创建专属响应管道并登记，entry 据登记表挂接
+        let own_response: Arc<Box<dyn DynamicPipe>> =
+            Arc::new(PipeFactory::create("small", &worker_resp).unwrap());
+        let registration = board.register("w1", "", &worker_resp).unwrap();
+        rpc.sync_response_pipes(&board, "small");
+        assert_eq!(rpc.response_pipes(), std::slice::from_ref(&worker_resp));
+
+        let server = RpcServer::new(request_pipe, own_response);
+        let responder = tokio::spawn(async move {
+            let (message, responder) = server.next().await.unwrap();
+            responder.reply(message).await.unwrap();
+        });
+        let reply = rpc
+            .call_timeout(Message::init("ping".to_string()), Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(reply.data, b"ping");
+        responder.await.unwrap();
+
+        // worker 注销后取消挂接
+        drop(registration);
+        rpc.sync_response_pipes(&board, "small");
+        assert!(rpc.response_pipes().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_push_reaches_subscriber() {
+        let request_pipe: Arc<Box<dyn DynamicPipe>> =
+            Arc::new(PipeFactory::create("memory(4x256)", "rpc_push_req").unwrap());
+        let response_pipe: Arc<Box<dyn DynamicPipe>> =
+            Arc::new(PipeFactory::create("memory(4x256)", "rpc_push_resp").unwrap());
+        let rpc = RpcChannel::new(Arc::clone(&request_pipe), Arc::clone(&response_pipe));
+        let mut pushes = rpc.subscribe_pushes();
+        let dispatcher = rpc.start();
+
+        let server = RpcServer::new(request_pipe, response_pipe);
+        server.pusher().push(42, b"hello").unwrap();
+
+        let message = tokio::time::timeout(Duration::from_secs(5), pushes.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(message.as_push(), Some((42, &b"hello"[..])));
+        assert_eq!(rpc.pending_count(), 0);
+
+        dispatcher.abort();
+    }
+}
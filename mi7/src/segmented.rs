@@ -0,0 +1,516 @@
+//! 分段管道：容量超过单个共享内存段的队列
+//!
+//! 普通管道用一次 `ftruncate` + `mmap` 映射全部槽位，容量越大，单个映射越大，创建时就要
+//! 提交全部内存。[`SegmentedPipe`] 把队列拆成固定大小的数据段，按需创建、排空后删除：
+//!
+//! - 目录段 `{name}` 记录每段的配置、段数上限，以及仍在使用的段序号区间 `head..=tail`
+//! - 数据段 `{name}.seg{n}` 是普通管道；写者只写最新的段，写满时创建下一段，
+//!   段数达到上限时等待读者释放槽位
+//! - 读者从最早的段开始读，最早的段读空后被删除，内存随之归还
+//!
+//! 总容量为 `段容量 × 段数上限`，可以远超单个映射能容纳的大小（例如 1 MB 槽位、每段 1024 个、
+//! 最多 64 段即 64 GB），实际占用的内存只与积压的消息数量成正比。各段的 request_id 接续分配。
+//!
+//! 写者抢占槽位后确认该段仍是最新的段，否则放弃槽位改写最新的段；回收方只回收不是最新的段，
+//! 并在确认段中没有占用的槽位后才删除，两者之间以 SeqCst 屏障排序，因此不会有消息写入已删除的段。
+//! 同一时刻在两段中都有消息时，读者先读较早的段。
+
+use crate::Message;
+use crate::locks::Mapping;
+use crate::pipe::{
+    DynCrossProcessPipe, DynamicPipe, PipeConfig, PipeStatus, PipeTimeout, is_queue_full,
+};
+use crate::pipe_set::PipeSet;
+use crate::shared_slot::FullPolicy;
+use crate::shm_registry::SharedMemoryRegistry;
+use crate::shm_sync;
+
+use crate::error::Result;
+use anyhow::{Context, anyhow};
+use std::collections::BTreeMap;
+use std::mem::size_of;
+use std::ptr;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering, fence};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// 目录段初始化完成的标记
+const DIRECTORY_MAGIC: u64 = 0x4d49_3753_4547_0001;
+
+/// 等待空槽位或消息时重新检查段目录的最长间隔
+const REFRESH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 共享内存中的段目录
+#[repr(C)]
+struct Directory {
+    magic: AtomicU64,      // 初始化完成后写入 DIRECTORY_MAGIC
+    segment_capacity: u64, // 每段的槽位数量
+    max_segments: u32,     // 同时存在的段数上限
+    head: AtomicU32,       // 最早仍在使用的段序号
+    tail: AtomicU32,       // 写者写入的段序号
+    grower: AtomicU32,     // 正在创建新段的进程 PID，0 表示空闲
+}
+
+/// 分段管道
+pub struct SegmentedPipe {
+    name: String,
+    directory: Mapping,
+    owner: bool,
+    /// 本句柄已连接的段，按序号
+    segments: Mutex<BTreeMap<u32, Arc<DynCrossProcessPipe>>>,
+}
+
+impl SegmentedPipe {
+    /// 创建每段 `segment_capacity` 个槽位、最多 `max_segments` 段的管道
+    pub fn create(
+        name: &str,
+        segment_capacity: usize,
+        slot_size: usize,
+        max_segments: u32,
+    ) -> Result<Self> {
+        Self::create_with_config(
+            name,
+            PipeConfig::new(segment_capacity, slot_size),
+            max_segments,
+        )
+    }
+
+    /// 以 `config` 创建目录段与第一个数据段，`config.capacity` 为每段的槽位数量
+    ///
+    /// 队列满时总是等待读者释放槽位，`config.full_policy` 被忽略。Drop 时删除全部共享内存。
+    pub fn create_with_config(name: &str, config: PipeConfig, max_segments: u32) -> Result<Self> {
+        if max_segments == 0 {
+            return Err(anyhow!("段数上限必须大于 0").into());
+        }
+        let config = config.with_full_policy(FullPolicy::Block);
+        let mut first =
+            DynCrossProcessPipe::create_with_config(&Self::segment_name(name, 0), config)?;
+        // 各段由回收它的进程删除，不随创建它的句柄删除
+        first.persist();
+
+        let directory = Mapping::create(name, size_of::<Directory>())?;
+        let block = directory.as_ptr() as *mut Directory;
+        unsafe {
+            ptr::write(
+                block,
+                Directory {
+                    magic: AtomicU64::new(0),
+                    segment_capacity: config.capacity as u64,
+                    max_segments,
+                    head: AtomicU32::new(0),
+                    tail: AtomicU32::new(0),
+                    grower: AtomicU32::new(0),
+                },
+            );
+            (*block).magic.store(DIRECTORY_MAGIC, Ordering::Release);
+        }
+        SharedMemoryRegistry::register(name);
+
+        Ok(Self {
+            name: name.to_string(),
+            directory,
+            owner: true,
+            segments: Mutex::new(BTreeMap::from([(0, Arc::new(first))])),
+        })
+    }
+
+    /// 连接已有的分段管道，数据段在用到时才连接
+    pub fn connect(name: &str) -> Result<Self> {
+        let directory = Mapping::open(name, size_of::<Directory>())
+            .with_context(|| format!("分段管道 {} 不存在", name))?;
+        let pipe = Self {
+            name: name.to_string(),
+            directory,
+            owner: false,
+            segments: Mutex::new(BTreeMap::new()),
+        };
+        if pipe.directory().magic.load(Ordering::Acquire) != DIRECTORY_MAGIC {
+            return Err(anyhow!("{} 不是分段管道的目录段", name).into());
+        }
+        Ok(pipe)
+    }
+
+    fn directory(&self) -> &Directory {
+        unsafe { &*(self.directory.as_ptr() as *const Directory) }
+    }
+
+    /// 第 `sequence` 段的管道名称
+    pub fn segment_name(name: &str, sequence: u32) -> String {
+        format!("{}.seg{}", name, sequence)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 每段的槽位数量
+    pub fn segment_capacity(&self) -> usize {
+        self.directory().segment_capacity as usize
+    }
+
+    /// 同时存在的段数上限
+    pub fn max_segments(&self) -> u32 {
+        self.directory().max_segments
+    }
+
+    /// 所有段都创建后的总容量
+    pub fn max_capacity(&self) -> usize {
+        self.segment_capacity() * self.max_segments() as usize
+    }
+
+    /// 当前存在的段数
+    pub fn segment_count(&self) -> u32 {
+        let (head, tail) = self.bounds();
+        tail - head + 1
+    }
+
+    /// 仍在使用的段序号区间
+    fn bounds(&self) -> (u32, u32) {
+        let directory = self.directory();
+        let head = directory.head.load(Ordering::SeqCst);
+        (head, directory.tail.load(Ordering::SeqCst).max(head))
+    }
+
+    /// 第 `sequence` 段，本句柄尚未连接时连接；同时释放已被回收的段
+    fn segment(&self, sequence: u32) -> Result<Arc<DynCrossProcessPipe>> {
+        let head = self.directory().head.load(Ordering::SeqCst);
+        let mut segments = self.segments.lock().unwrap();
+        segments.retain(|&connected, _| connected >= head);
+        if let Some(pipe) = segments.get(&sequence) {
+            return Ok(Arc::clone(pipe));
+        }
+        let name = Self::segment_name(&self.name, sequence);
+        let pipe = Arc::new(
+            DynCrossProcessPipe::connect(&name)
+                .with_context(|| format!("连接数据段 {} 失败", name))?,
+        );
+        segments.insert(sequence, Arc::clone(&pipe));
+        Ok(pipe)
+    }
+
+    /// 汇总现有各段的状态，见 [`PipeStatus::merge`]；容量为已创建段的槽位总数
+    ///
+    /// 累计计数只包含现有的段，已删除的段不再计入。
+    pub fn status(&self) -> Result<PipeStatus> {
+        let (head, tail) = self.bounds();
+        let mut total: Option<PipeStatus> = None;
+        for sequence in head..=tail {
+            let Ok(pipe) = self.segment(sequence) else {
+                // 统计期间被回收
+                continue;
+            };
+            let status = pipe.status();
+            match &mut total {
+                Some(total) => total.merge(&status),
+                None => total = Some(status),
+            }
+        }
+        total.ok_or_else(|| anyhow!("分段管道 {} 没有可用的数据段", self.name).into())
+    }
+
+    /// 发送消息，段数达到上限且都已写满时等待，超时返回 [`PipeTimeout::Send`]
+    pub fn send(&self, message: Message, timeout: Duration) -> Result<u64> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let (tail, pipe, index) = match self.hold()? {
+                Held::Slot(tail, pipe, index) => (tail, pipe, index),
+                Held::Retry => continue,
+                Held::Full(tail, pipe) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return Err(PipeTimeout::Send(timeout).into());
+                    }
+                    // 在最新段上等待读者释放槽位，期间可能有其他写者创建了新段
+                    match pipe.hold_timeout(remaining.min(REFRESH_INTERVAL)) {
+                        Ok(index) => (tail, pipe, index),
+                        Err(e) if e.is::<PipeTimeout>() => continue,
+                        Err(e) => return Err(e),
+                    }
+                }
+            };
+            if self.still_tail(tail, &pipe, index) {
+                return pipe.send(index, message);
+            }
+        }
+    }
+
+    /// 非阻塞发送，段数达到上限且都已写满时返回 `Ok(None)`
+    pub fn try_send(&self, message: Message) -> Result<Option<u64>> {
+        loop {
+            match self.hold()? {
+                Held::Slot(tail, pipe, index) => {
+                    if self.still_tail(tail, &pipe, index) {
+                        return pipe.send(index, message).map(Some);
+                    }
+                }
+                Held::Retry => continue,
+                Held::Full(..) => return Ok(None),
+            }
+        }
+    }
+
+    /// 在最新的段上抢占空槽位，该段已满时创建下一段
+    fn hold(&self) -> Result<Held> {
+        let (_, tail) = self.bounds();
+        let pipe = match self.segment(tail) {
+            Ok(pipe) => pipe,
+            // 刚被回收（已有更新的段）
+            Err(_) if self.bounds().1 != tail => return Ok(Held::Retry),
+            Err(e) => return Err(e),
+        };
+        match pipe.hold() {
+            Ok(index) => return Ok(Held::Slot(tail, pipe, index)),
+            Err(e) if is_queue_full(&e) => {}
+            Err(e) => return Err(e),
+        }
+        if self.grow(tail, &pipe)? {
+            Ok(Held::Retry)
+        } else {
+            Ok(Held::Full(tail, pipe))
+        }
+    }
+
+    /// 抢占槽位后确认该段仍是最新的段，否则放弃该槽位
+    fn still_tail(&self, tail: u32, pipe: &DynCrossProcessPipe, index: usize) -> bool {
+        fence(Ordering::SeqCst);
+        if self.directory().tail.load(Ordering::SeqCst) == tail {
+            return true;
+        }
+        pipe.abandon(index);
+        false
+    }
+
+    /// 在第 `tail` 段之后创建新段；段数已达上限时返回 `false`
+    ///
+    /// 其他进程正在创建或已经创建了新段时直接返回 `true`，由调用方重新读取目录。
+    fn grow(&self, tail: u32, previous: &DynCrossProcessPipe) -> Result<bool> {
+        let pid = std::process::id();
+        let grower = &self.directory().grower;
+        if let Err(holder) = grower.compare_exchange(0, pid, Ordering::AcqRel, Ordering::Acquire) {
+            // 创建者已退出时接管，否则等它完成
+            if shm_sync::process_alive(holder)
+                || grower
+                    .compare_exchange(holder, pid, Ordering::AcqRel, Ordering::Acquire)
+                    .is_err()
+            {
+                std::thread::yield_now();
+                return Ok(true);
+            }
+        }
+        let result = self.grow_locked(tail, previous);
+        grower.store(0, Ordering::Release);
+        result
+    }
+
+    fn grow_locked(&self, tail: u32, previous: &DynCrossProcessPipe) -> Result<bool> {
+        let (head, current) = self.bounds();
+        if current != tail {
+            return Ok(true);
+        }
+        if tail - head + 1 >= self.max_segments() {
+            return Ok(false);
+        }
+
+        let next = tail + 1;
+        let name = Self::segment_name(&self.name, next);
+        let mut segment = previous
+            .create_successor(&name, self.segment_capacity())
+            .with_context(|| format!("创建数据段 {} 失败", name))?;
+        segment.persist();
+        segment.continue_sequence(previous);
+        self.segments
+            .lock()
+            .unwrap()
+            .insert(next, Arc::new(segment));
+        self.directory().tail.store(next, Ordering::SeqCst);
+        // 唤醒只等待旧段的读者，使它们把新段加入等待
+        previous.notify();
+        tracing::debug!("分段管道 {} 创建第 {} 段", self.name, next);
+        Ok(true)
+    }
+
+    /// 回收已读空的第 `head` 段并删除其共享内存；该段仍是最新的段或仍有占用的槽位时返回 `false`
+    fn retire(&self, head: u32, pipe: &DynCrossProcessPipe) -> bool {
+        let directory = self.directory();
+        if directory.tail.load(Ordering::SeqCst) <= head {
+            return false;
+        }
+        // 写者先抢占槽位再检查最新段：这里看不到占用的槽位，之后抢占的写者一定会放弃
+        fence(Ordering::SeqCst);
+        if pipe.status().used_count > 0
+            || directory
+                .head
+                .compare_exchange(head, head + 1, Ordering::SeqCst, Ordering::SeqCst)
+                .is_err()
+        {
+            return false;
+        }
+        if let Err(e) = pipe.unlink() {
+            tracing::warn!("删除已读空的数据段 {} 失败: {}", pipe.name(), e);
+        }
+        tracing::debug!("分段管道 {} 回收第 {} 段", self.name, head);
+        true
+    }
+
+    /// 读取一条消息，所有段都为空时返回 `Ok(None)`
+    ///
+    /// 从最早的段开始读，最早的段已读空时回收它。
+    pub fn try_receive(&self) -> Result<Option<Message>> {
+        let (head, tail) = self.bounds();
+        let mut oldest = true;
+        for sequence in head..=tail {
+            let pipe = match self.segment(sequence) {
+                Ok(pipe) => pipe,
+                // 被其他读者回收
+                Err(_) if self.directory().head.load(Ordering::SeqCst) > sequence => continue,
+                Err(e) => return Err(e),
+            };
+            match pipe.receive_blocking(Duration::ZERO) {
+                Ok(message) => return Ok(Some(message)),
+                Err(e) if e.is::<PipeTimeout>() => {}
+                Err(e) => return Err(e),
+            }
+            oldest = oldest && self.retire(sequence, &pipe);
+        }
+        Ok(None)
+    }
+
+    /// 阻塞读取一条消息，超时返回 [`PipeTimeout::Receive`]
+    pub fn receive_blocking(&self, timeout: Duration) -> Result<Message> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(message) = self.try_receive()? {
+                return Ok(message);
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(PipeTimeout::Receive(timeout).into());
+            }
+            let pipes = self.pipes();
+            let pipes: Vec<&dyn DynamicPipe> = pipes
+                .iter()
+                .map(|pipe| &**pipe as &dyn DynamicPipe)
+                .collect();
+            match PipeSet::wait_any(&pipes, remaining.min(REFRESH_INTERVAL)) {
+                Err(e) if !e.is::<PipeTimeout>() => return Err(e),
+                _ => {}
+            }
+        }
+    }
+
+    /// 异步读取一条消息，等待时不占用运行时线程
+    pub async fn receive_async(&self) -> Result<Message> {
+        loop {
+            if let Some(message) = self.try_receive()? {
+                return Ok(message);
+            }
+            let pipes = self.pipes();
+            let pipes: Vec<&dyn DynamicPipe> = pipes
+                .iter()
+                .map(|pipe| &**pipe as &dyn DynamicPipe)
+                .collect();
+            if let Ok(waited) =
+                tokio::time::timeout(REFRESH_INTERVAL, PipeSet::wait_any_async(&pipes)).await
+            {
+                waited?;
+            }
+        }
+    }
+
+    /// 现有的各段，按序号
+    fn pipes(&self) -> Vec<Arc<DynCrossProcessPipe>> {
+        let (head, tail) = self.bounds();
+        (head..=tail)
+            .filter_map(|sequence| self.segment(sequence).ok())
+            .collect()
+    }
+}
+
+/// [`SegmentedPipe::hold`] 的结果
+enum Held {
+    /// 在第 n 段抢占到槽位
+    Slot(u32, Arc<DynCrossProcessPipe>, usize),
+    /// 段目录已变化，重新读取
+    Retry,
+    /// 段数已达上限，最新的段已满
+    Full(u32, Arc<DynCrossProcessPipe>),
+}
+
+impl Drop for SegmentedPipe {
+    fn drop(&mut self) {
+        let (head, tail) = self.bounds();
+        if !self.owner {
+            return;
+        }
+        for sequence in head..=tail {
+            let name = Self::segment_name(&self.name, sequence);
+            if let Err(e) = SharedMemoryRegistry::unlink(&name) {
+                tracing::warn!("删除数据段 {} 失败: {}", name, e);
+            }
+        }
+        if let Err(e) = SharedMemoryRegistry::unlink(&self.name) {
+            tracing::warn!("删除分段管道目录 {} 失败: {}", self.name, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_segments_grow_on_demand_and_retire() {
+        let name = format!("mi7_test_segmented_{}", std::process::id());
+        let timeout = Duration::from_secs(1);
+        let producer = Arc::new(SegmentedPipe::create(&name, 4, 128, 3).unwrap());
+        assert_eq!(producer.max_capacity(), 12);
+        assert_eq!(producer.segment_count(), 1);
+
+        // 写满一段后按需创建下一段，request_id 跨段不重复
+        let ids: HashSet<u64> = (0..10)
+            .map(|i| {
+                producer
+                    .send(Message::init(format!("m{}", i)), timeout)
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(ids.len(), 10);
+        assert_eq!(producer.segment_count(), 3);
+        assert_eq!(producer.status().unwrap().capacity, 12);
+        for i in 10..12 {
+            producer
+                .try_send(Message::init(format!("m{}", i)))
+                .unwrap()
+                .unwrap();
+        }
+        assert_eq!(
+            producer
+                .try_send(Message::init("full".to_string()))
+                .unwrap(),
+            None
+        );
+
+        // 段数达到上限时写者等待读者释放槽位
+        let blocked = {
+            let producer = Arc::clone(&producer);
+            std::thread::spawn(move || {
+                producer
+                    .send(Message::init("m12".to_string()), timeout)
+                    .unwrap()
+            })
+        };
+
+        // 读者按顺序跨段读取，读空的段被回收
+        let consumer = SegmentedPipe::connect(&name).unwrap();
+        for i in 0..13 {
+            let message = consumer.receive_blocking(timeout).unwrap();
+            assert_eq!(message.data, format!("m{}", i).as_bytes());
+        }
+        blocked.join().unwrap();
+        assert!(consumer.try_receive().unwrap().is_none());
+        assert_eq!(consumer.segment_count(), 1);
+        assert!(DynCrossProcessPipe::connect(&SegmentedPipe::segment_name(&name, 0)).is_err());
+        assert_eq!(producer.status().unwrap().ready_count, 0);
+    }
+}
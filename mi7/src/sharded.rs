@@ -0,0 +1,277 @@
+//! 分片管道：把一条逻辑队列拆成多条共享内存管道
+//!
+//! 大量生产者争用同一条管道的写锁（以及同一组缓存行）时，[`ShardedPipe`] 把消息分散到
+//! N 条各自独立的管道（分片）上：
+//!
+//! - 携带亲和键（[`Message::affinity`]）的消息按哈希固定到一个分片，同一个键的消息保持顺序；
+//!   其余消息轮流写入各分片，当前分片已满时依次尝试下一个
+//! - 读取时先检查本句柄的"主分片"，没有消息再从其他分片窃取；所有分片都为空时用
+//!   [`PipeSet`] 同时等待全部分片的通知
+//!
+//! 分片是名为 `{name}.shard{i}` 的普通管道，可以单独用 mi7ctl 查看。
+//! [`ShardedPipe::connect`] 从 0 开始依次连接分片直到不存在，因此分片数量在创建后不能改变。
+//! 各分片的 request_id 相互独立，不同分片的消息可能有相同的 request_id。
+
+use crate::Message;
+use crate::pipe::{
+    DynCrossProcessPipe, DynamicPipe, PipeConfig, PipeStatus, PipeTimeout, is_queue_full,
+};
+use crate::pipe_set::PipeSet;
+
+use crate::error::Result;
+use anyhow::{Context, anyhow};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// 本进程内句柄编号，使同一进程的多个句柄从不同的主分片开始
+static NEXT_HANDLE: AtomicUsize = AtomicUsize::new(0);
+
+/// 分片管道
+pub struct ShardedPipe {
+    name: String,
+    shards: Vec<DynCrossProcessPipe>,
+    /// 读取时优先检查的分片
+    home: usize,
+    /// 轮流写入的下一个分片
+    next_send: AtomicUsize,
+}
+
+impl ShardedPipe {
+    /// 创建 `shards` 个分片，每个分片 `capacity` 个槽位
+    pub fn create(name: &str, shards: usize, capacity: usize, slot_size: usize) -> Result<Self> {
+        Self::create_with_config(name, shards, PipeConfig::new(capacity, slot_size))
+    }
+
+    /// 以相同配置创建 `shards` 个分片
+    pub fn create_with_config(name: &str, shards: usize, config: PipeConfig) -> Result<Self> {
+        if shards == 0 {
+            return Err(anyhow!("分片数量必须大于 0").into());
+        }
+        let shards = (0..shards)
+            .map(|index| {
+                let shard = Self::shard_name(name, index);
+                DynCrossProcessPipe::create_with_config(&shard, config)
+                    .with_context(|| format!("创建分片 {} 失败", shard))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self::from_shards(name, shards))
+    }
+
+    /// 连接已有的分片管道
+    pub fn connect(name: &str) -> Result<Self> {
+        let mut shards = Vec::new();
+        while let Ok(shard) = DynCrossProcessPipe::connect(&Self::shard_name(name, shards.len())) {
+            shards.push(shard);
+        }
+        if shards.is_empty() {
+            return Err(anyhow!("分片管道 {} 不存在", name).into());
+        }
+        Ok(Self::from_shards(name, shards))
+    }
+
+    fn from_shards(name: &str, shards: Vec<DynCrossProcessPipe>) -> Self {
+        let home = (std::process::id() as usize)
+            .wrapping_add(NEXT_HANDLE.fetch_add(1, Ordering::Relaxed))
+            % shards.len();
+        Self {
+            name: name.to_string(),
+            shards,
+            home,
+            next_send: AtomicUsize::new(home),
+        }
+    }
+
+    /// 第 `index` 个分片的管道名称
+    pub fn shard_name(name: &str, index: usize) -> String {
+        format!("{}.shard{}", name, index)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 分片数量
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// 第 `index` 个分片
+    pub fn shard(&self, index: usize) -> &DynCrossProcessPipe {
+        &self.shards[index]
+    }
+
+    /// 消息应写入的分片：携带亲和键时固定，否则为 `None`（轮流写入）
+    pub fn shard_for(&self, message: &Message) -> Option<usize> {
+        (message.affinity != 0).then(|| (message.affinity % self.shards.len() as u64) as usize)
+    }
+
+    /// 发送消息，返回写入的分片与 request_id
+    ///
+    /// 携带亲和键的消息只写入其分片，该分片满时等待；其余消息从下一个轮到的分片开始
+    /// 依次尝试，所有分片都满时在轮到的分片上等待，超时返回 [`PipeTimeout::Send`]。
+    pub fn send(&self, message: Message, timeout: Duration) -> Result<(usize, u64)> {
+        if let Some(shard) = self.shard_for(&message) {
+            let request_id = self.shards[shard].send_blocking(message, timeout)?;
+            return Ok((shard, request_id));
+        }
+
+        let start = self.next_send.fetch_add(1, Ordering::Relaxed) % self.shards.len();
+        for shard in self.ring(start) {
+            let pipe = &self.shards[shard];
+            match pipe.hold() {
+                Ok(index) => return Ok((shard, pipe.send(index, message)?)),
+                Err(e) if is_queue_full(&e) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        let request_id = self.shards[start].send_blocking(message, timeout)?;
+        Ok((start, request_id))
+    }
+
+    /// 从任意分片读取一条消息，所有分片都为空时返回 `Ok(None)`
+    ///
+    /// 先检查主分片，再依次从其他分片窃取；返回消息所在的分片。
+    pub fn try_receive(&self) -> Result<Option<(usize, Message)>> {
+        for shard in self.ring(self.home) {
+            match self.shards[shard].receive_blocking(Duration::ZERO) {
+                Ok(message) => return Ok(Some((shard, message))),
+                Err(e) if e.is::<PipeTimeout>() => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(None)
+    }
+
+    /// 阻塞读取一条消息，所有分片都为空时等待任一分片的通知，超时返回 [`PipeTimeout::Receive`]
+    pub fn receive_blocking(&self, timeout: Duration) -> Result<(usize, Message)> {
+        let deadline = Instant::now() + timeout;
+        let pipes = self.pipes();
+        loop {
+            if let Some(received) = self.try_receive()? {
+                return Ok(received);
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(PipeTimeout::Receive(timeout).into());
+            }
+            // 超时交给下一轮检查统一返回；其他消费者抢先取走时继续等待
+            match PipeSet::wait_any(&pipes, remaining) {
+                Err(e) if !e.is::<PipeTimeout>() => return Err(e),
+                _ => {}
+            }
+        }
+    }
+
+    /// 异步读取一条消息，等待时不占用运行时线程
+    pub async fn receive_async(&self) -> Result<(usize, Message)> {
+        let pipes = self.pipes();
+        loop {
+            if let Some(received) = self.try_receive()? {
+                return Ok(received);
+            }
+            PipeSet::wait_any_async(&pipes).await?;
+        }
+    }
+
+    /// 汇总所有分片的状态，见 [`PipeStatus::merge`]；读写指针为 0，并发模式等创建参数取第一个分片的
+    pub fn status(&self) -> PipeStatus {
+        let mut shards = self.shards.iter().map(|shard| shard.status());
+        let mut total = shards.next().expect("分片数量大于 0");
+        total.write_pointer = 0;
+        total.read_pointer = 0;
+        for status in shards {
+            total.merge(&status);
+        }
+        total
+    }
+
+    /// 各分片的状态
+    pub fn shard_statuses(&self) -> Vec<PipeStatus> {
+        self.shards.iter().map(|shard| shard.status()).collect()
+    }
+
+    /// 删除所有分片的共享内存
+    pub fn unlink(&self) -> Result<()> {
+        self.shards.iter().try_for_each(|shard| shard.unlink())
+    }
+
+    /// 从 `start` 开始环绕一圈的分片下标
+    fn ring(&self, start: usize) -> impl Iterator<Item = usize> + use<> {
+        let count = self.shards.len();
+        (0..count).map(move |offset| (start + offset) % count)
+    }
+
+    /// 以主分片开头的分片列表，供 [`PipeSet`] 等待
+    fn pipes(&self) -> Vec<&dyn DynamicPipe> {
+        self.ring(self.home)
+            .map(|shard| &self.shards[shard] as &dyn DynamicPipe)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_sharded_pipe_routes_and_steals() {
+        let name = format!("mi7_test_sharded_{}", std::process::id());
+        let timeout = Duration::from_secs(1);
+        let producer = Arc::new(ShardedPipe::create(&name, 4, 8, 256).unwrap());
+        assert_eq!(producer.shard_count(), 4);
+
+        // 没有亲和键的消息轮流写入各分片
+        let shards: HashSet<usize> = (0..4)
+            .map(|i| {
+                producer
+                    .send(Message::init(format!("m{}", i)), timeout)
+                    .unwrap()
+                    .0
+            })
+            .collect();
+        assert_eq!(shards.len(), 4);
+
+        // 相同亲和键的消息固定在同一分片
+        let keyed: HashSet<usize> = (0..3)
+            .map(|_| {
+                producer
+                    .send(
+                        Message::init("k".to_string()).with_affinity("user-1"),
+                        timeout,
+                    )
+                    .unwrap()
+                    .0
+            })
+            .collect();
+        assert_eq!(keyed.len(), 1);
+
+        let status = producer.status();
+        assert_eq!(status.capacity, 32);
+        assert_eq!(status.ready_count, 7);
+
+        // 连接方从所有分片窃取，直到全部读完
+        let consumer = ShardedPipe::connect(&name).unwrap();
+        assert_eq!(consumer.shard_count(), 4);
+        for _ in 0..7 {
+            consumer.receive_blocking(timeout).unwrap();
+        }
+        assert!(consumer.try_receive().unwrap().is_none());
+
+        // 所有分片为空时等待任一分片的通知
+        let sender = {
+            let producer = Arc::clone(&producer);
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(20));
+                producer
+                    .send(Message::init("late".to_string()), timeout)
+                    .unwrap();
+            })
+        };
+        let (_, message) = consumer.receive_blocking(timeout).unwrap();
+        assert_eq!(message.data, b"late");
+        sender.join().unwrap();
+        assert_eq!(consumer.status().received_count, 8);
+    }
+}
@@ -1,13 +1,27 @@
-use anyhow::{Result, anyhow};
+use anyhow::{Context, anyhow};
+use serde::Serialize;
 use libc::{
     MAP_FAILED, MAP_SHARED, O_CREAT, O_RDWR, PROT_READ, PROT_WRITE, close, ftruncate, mmap, munmap,
 };
 use std::collections::HashMap;
 use std::ffi::CString;
 use std::mem;
+use std::path::{Path, PathBuf};
 use std::ptr;
-use std::sync::atomic::{AtomicU8, AtomicU32, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU8, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::error::Result;
+use crate::futex;
+use crate::integrity::Integrity;
+use crate::lock_debug;
+use crate::locks::LockTimeout;
+use crate::memory::{self, MemoryAdvice, MemoryUsage};
+use crate::shm_registry::SharedMemoryRegistry;
+use crate::shm_sync;
+
+/// [`SharedMemoryMailbox::lock`] 等待全局锁的最长时间
+pub const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(5);
 
 /// Box 状态枚举
 #[repr(u8)]
@@ -32,7 +46,7 @@ impl From<u8> for BoxState {
 }
 
 /// Box 大小类型 (MB)
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
 pub enum BoxSize {
     Size1M = 1,
     Size2M = 2,
@@ -49,10 +63,35 @@ pub enum BoxSize {
     Size100M = 100,
 }
 
+/// 寄存箱使用的大页
+///
+/// 命名共享内存（`shm_open`）的映射不能使用 `MAP_HUGETLB`，跨进程共享的大页需要
+/// hugetlbfs 上的文件或 shmem 透明大页。大页不可用时都退回普通页，并输出警告。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum HugePages {
+    /// 普通页
+    #[default]
+    Off,
+    /// shmem 透明大页：对映射调用 `madvise(MADV_HUGEPAGE)`，
+    /// 需要 `/sys/kernel/mm/transparent_hugepage/shmem_enabled` 为 `advise` 或 `always`
+    Transparent,
+    /// 以 hugetlbfs 挂载点（如 `/dev/hugepages`）下的同名文件作为后备存储，
+    /// 大小向上取整到大页大小；挂载点不可用或预留的大页不足时退回普通共享内存
+    Hugetlbfs(PathBuf),
+}
+
 /// Box 配置结构体，用于指定每种大小的 box 数量
 #[derive(Debug, Clone)]
 pub struct BoxConfig {
     pub config: HashMap<BoxSize, usize>,
+    /// 校验算法，仅在创建寄存箱时生效，连接方从共享内存头部读取
+    pub integrity: Integrity,
+    /// 大页选项，连接方需使用相同的选项才能找到 hugetlbfs 上的寄存箱
+    pub hugepages: HugePages,
+    /// 把寄存箱内存绑定到该 NUMA 节点（`mbind`），仅在创建寄存箱时生效
+    pub numa_node: Option<u32>,
+    /// 本进程映射的内存建议（锁定、预读、box 归还后释放），见 [`crate::memory`]
+    pub memory: MemoryAdvice,
 }
 
 impl BoxConfig {
@@ -60,6 +99,10 @@ impl BoxConfig {
     pub fn new() -> Self {
         Self {
             config: HashMap::new(),
+            integrity: Integrity::default(),
+            hugepages: HugePages::Off,
+            numa_node: None,
+            memory: memory::installed(),
         }
     }
 
@@ -69,6 +112,30 @@ impl BoxConfig {
         self
     }
 
+    /// 设置校验算法
+    pub fn set_integrity(&mut self, integrity: Integrity) -> &mut Self {
+        self.integrity = integrity;
+        self
+    }
+
+    /// 设置大页选项
+    pub fn set_hugepages(&mut self, hugepages: HugePages) -> &mut Self {
+        self.hugepages = hugepages;
+        self
+    }
+
+    /// 设置绑定的 NUMA 节点
+    pub fn set_numa_node(&mut self, node: u32) -> &mut Self {
+        self.numa_node = Some(node);
+        self
+    }
+
+    /// 设置本进程映射的内存建议
+    pub fn set_memory_advice(&mut self, memory: MemoryAdvice) -> &mut Self {
+        self.memory = memory;
+        self
+    }
+
     /// 获取指定大小的 box 数量
     pub fn get_count(&self, size: BoxSize) -> usize {
         self.config.get(&size).copied().unwrap_or(0)
@@ -103,6 +170,13 @@ impl Default for BoxConfig {
 }
 
 impl BoxSize {
+    /// 由以 MB 为单位的大小还原，不是合法大小时返回 `None`
+    pub fn from_mb(mb: u32) -> Option<Self> {
+        Self::all_sizes()
+            .into_iter()
+            .find(|size| *size as u32 == mb)
+    }
+
     pub fn bytes(&self) -> usize {
         (*self as usize) * 1024 * 1024
     }
@@ -134,6 +208,8 @@ pub struct BoxMetadata {
     pub size: AtomicU32,        // Box 大小 (MB)
     pub data_length: AtomicU32, // 实际数据长度
     pub data_ptr: AtomicU32,    // 数据指针偏移量
+    pub owner_pid: AtomicU32,   // 正在写入/读取该 box 的进程 (0=无)
+    pub checksum: AtomicU64,    // 数据校验值，写满时按头部记录的算法计算
 }
 
 impl BoxMetadata {
@@ -144,6 +220,8 @@ impl BoxMetadata {
             size: AtomicU32::new(size as u32),
             data_length: AtomicU32::new(0),
             data_ptr: AtomicU32::new(data_offset),
+            owner_pid: AtomicU32::new(0),
+            checksum: AtomicU64::new(0),
         }
     }
 
@@ -155,6 +233,13 @@ impl BoxMetadata {
         self.state.store(state as u8, Ordering::Release);
     }
 
+    /// 以 CAS 将状态从 `from` 切换到 `to`，状态不符（被其他进程抢先）时返回 `false`
+    pub fn try_transition(&self, from: BoxState, to: BoxState) -> bool {
+        self.state
+            .compare_exchange(from as u8, to as u8, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+
     pub fn get_id(&self) -> u32 {
         self.id.load(Ordering::Relaxed)
     }
@@ -189,6 +274,15 @@ impl BoxMetadata {
     pub fn get_data_offset(&self) -> u32 {
         self.data_ptr.load(Ordering::Relaxed)
     }
+
+    /// 当前持有该 box 的进程，0 表示无人持有
+    pub fn get_owner_pid(&self) -> u32 {
+        self.owner_pid.load(Ordering::Acquire)
+    }
+
+    fn set_owner_pid(&self, pid: u32) {
+        self.owner_pid.store(pid, Ordering::Release);
+    }
 }
 
 /// 共享内存寄存箱头部信息
@@ -199,19 +293,25 @@ pub struct MailboxHeader {
     pub total_boxes: AtomicU32, // 总 box 数量
     pub lock: AtomicU32,        // 全局锁 (0=未锁定, 1=锁定)
     pub next_box_id: AtomicU32, // 下一个 box ID
+    pub ready_seq: AtomicU32,   // futex 字：每有 box 写满递增
+    pub empty_seq: AtomicU32,   // futex 字：每有 box 释放递增
+    pub integrity: AtomicU32,   // Integrity，创建时写入
 }
 
 impl MailboxHeader {
     const MAGIC: u32 = 0x4D41494C; // "MAIL"
-    const VERSION: u32 = 1;
+    const VERSION: u32 = 4;
 
-    pub fn new(total_boxes: u32) -> Self {
+    pub fn new(total_boxes: u32, integrity: Integrity) -> Self {
         Self {
             magic: AtomicU32::new(Self::MAGIC),
             version: AtomicU32::new(Self::VERSION),
             total_boxes: AtomicU32::new(total_boxes),
             lock: AtomicU32::new(0),
             next_box_id: AtomicU32::new(1),
+            ready_seq: AtomicU32::new(0),
+            empty_seq: AtomicU32::new(0),
+            integrity: AtomicU32::new(integrity as u32),
         }
     }
 
@@ -222,13 +322,19 @@ impl MailboxHeader {
 
     /// 尝试获取全局锁
     pub fn try_lock(&self) -> bool {
-        self.lock
+        let locked = self
+            .lock
             .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
-            .is_ok()
+            .is_ok();
+        if locked {
+            lock_debug::acquired(&self.lock);
+        }
+        locked
     }
 
     /// 释放全局锁
     pub fn unlock(&self) {
+        lock_debug::released(&self.lock);
         self.lock.store(0, Ordering::Release);
     }
 
@@ -250,6 +356,12 @@ impl Drop for SharedMemoryMailbox {
                 munmap(self.memory as *mut libc::c_void, self.size);
             }
         }
+
+        if self.owner
+            && let Err(e) = self.remove_backing()
+        {
+            tracing::warn!("删除共享内存段 {} 失败: {}", self.name, e);
+        }
     }
 }
 
@@ -265,7 +377,7 @@ impl<'a> Drop for MailboxLock<'a> {
 }
 
 /// 寄存箱统计信息
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Serialize)]
 pub struct MailboxStats {
     pub total_count: usize,
     pub empty_count: usize,
@@ -276,12 +388,20 @@ pub struct MailboxStats {
 }
 
 /// 支持进程间共享的内存寄存箱
+///
+/// 创建共享内存段的实例是持有者，`Drop` 时自动 `shm_unlink`；
+/// 需要让段在创建进程退出后继续存在时调用 [`SharedMemoryMailbox::persist`]。
 pub struct SharedMemoryMailbox {
     memory: *mut u8,
     size: usize,
+    name: String,
+    owner: bool,
+    file: Option<PathBuf>, // hugetlbfs 上的后备文件
+    memory_advice: MemoryAdvice,
     header: *mut MailboxHeader,
     boxes: Vec<*mut BoxMetadata>,
     box_index: HashMap<BoxSize, Vec<usize>>,
+    cursors: HashMap<BoxSize, AtomicUsize>,
 }
 
 unsafe impl Send for SharedMemoryMailbox {}
@@ -289,81 +409,179 @@ unsafe impl Sync for SharedMemoryMailbox {}
 
 impl SharedMemoryMailbox {
     /// 创建或打开共享内存寄存箱
+    ///
+    /// 新建时按 `config` 申请大页并绑定 NUMA 节点，均在初始化写入内存之前生效。
     pub fn new_shared(name: &str, config: BoxConfig) -> Result<Self> {
         let total_size = Self::calculate_memory_size(&config);
-
-        // 创建共享内存名称，确保以 '/' 开头
-        let shm_name = if name.starts_with('/') {
-            CString::new(name)
-        } else {
-            CString::new(format!("/{}", name))
+        let hugetlbfs = match &config.hugepages {
+            HugePages::Hugetlbfs(mount) => Some(mount.as_path()),
+            _ => None,
         };
 
-        let shm_name = shm_name.map_err(|_| anyhow!("Failed to create CString from name"))?;
-
-        // 尝试打开已存在的共享内存
-        let mut fd = unsafe { libc::shm_open(shm_name.as_ptr(), O_RDWR, 0o666) };
-        let is_new = if fd == -1 {
-            // 如果打开失败，创建新的共享内存
-            fd = unsafe { libc::shm_open(shm_name.as_ptr(), O_CREAT | O_RDWR, 0o666) };
-            if fd == -1 {
-                return Err(anyhow!("shm_open failed with errno: {}", unsafe {
-                    *libc::__errno_location()
-                }));
+        // 先连接已存在的寄存箱（hugetlbfs 上的优先），都不存在时再创建
+        let existing = match hugetlbfs.map(|mount| map_hugetlbfs(mount, name, total_size, false)) {
+            Some(Ok(Some(mapping))) => Some(mapping),
+            Some(Err(e)) => {
+                tracing::warn!("连接 hugetlbfs 上的寄存箱 {} 失败: {}", name, e);
+                map_shm(name, total_size, false)?
             }
-            true
-        } else {
-            false
+            _ => map_shm(name, total_size, false)?,
         };
-
-        // 如果是新创建的共享内存，设置大小
-        if is_new {
-            if unsafe { ftruncate(fd, total_size as i64) } == -1 {
-                unsafe { close(fd) };
-                return Err(anyhow!("ftruncate failed with errno: {}", unsafe {
-                    *libc::__errno_location()
-                }));
+        let mapping = match existing {
+            Some(mapping) => mapping,
+            None => {
+                let created = hugetlbfs.and_then(|mount| {
+                    map_hugetlbfs(mount, name, total_size, true)
+                        .inspect_err(|e| {
+                            tracing::warn!(
+                                "寄存箱 {} 无法使用 hugetlbfs 大页，退回普通共享内存: {}",
+                                name,
+                                e
+                            )
+                        })
+                        .ok()
+                        .flatten()
+                });
+                match created {
+                    Some(mapping) => mapping,
+                    None => map_shm(name, total_size, true)?
+                        .ok_or_else(|| anyhow!("Failed to create shared memory {}", name))?,
+                }
             }
-        }
-
-        // 创建内存映射
-        let memory = unsafe {
-            mmap(
-                ptr::null_mut(),
-                total_size,
-                PROT_READ | PROT_WRITE,
-                MAP_SHARED,
-                fd,
-                0,
-            )
         };
 
-        // 关闭文件描述符
-        unsafe { close(fd) };
-
-        if memory == MAP_FAILED {
-            return Err(anyhow!("mmap failed"));
+        if mapping.is_new {
+            if config.hugepages == HugePages::Transparent
+                && let Err(e) = advise_hugepages(mapping.memory, mapping.size)
+            {
+                tracing::warn!("寄存箱 {} 无法使用透明大页: {}", name, e);
+            }
+            if let Some(node) = config.numa_node
+                && let Err(e) = bind_numa_node(mapping.memory, mapping.size, node)
+            {
+                tracing::warn!("寄存箱 {} 无法绑定到 NUMA 节点 {}: {}", name, node, e);
+            }
         }
 
         let mut mailbox = Self {
-            memory: memory as *mut u8,
-            size: total_size,
-            header: memory as *mut MailboxHeader,
+            memory: mapping.memory,
+            size: mapping.size,
+            name: name.trim_start_matches('/').to_string(),
+            owner: mapping.is_new,
+            file: mapping.file,
+            memory_advice: config.memory,
+            header: mapping.memory as *mut MailboxHeader,
             boxes: Vec::new(),
             box_index: HashMap::new(),
+            cursors: HashMap::new(),
         };
 
         // 如果是新创建的共享内存，需要初始化
-        if is_new {
+        if mapping.is_new {
+            if mailbox.file.is_none() {
+                SharedMemoryRegistry::register(name);
+            }
             mailbox.initialize(&config)?;
         } else {
             // 如果是已存在的共享内存，重建索引
             mailbox.rebuild_index()?;
         }
+        mailbox.advise();
 
         Ok(mailbox)
     }
 
+    /// 按名称连接已存在的共享内存寄存箱，布局由头部记录的 box 数量决定
+    ///
+    /// 不存在或不是寄存箱时返回错误，不会创建新的共享内存段。
+    pub fn connect(name: &str) -> Result<Self> {
+        let mapping = map_shm(name, 0, false)?.ok_or_else(|| anyhow!("寄存箱 {} 不存在", name))?;
+        let mut mailbox = Self {
+            memory: mapping.memory,
+            size: mapping.size,
+            name: name.trim_start_matches('/').to_string(),
+            owner: false,
+            file: None,
+            memory_advice: memory::installed(),
+            header: mapping.memory as *mut MailboxHeader,
+            boxes: Vec::new(),
+            box_index: HashMap::new(),
+            cursors: HashMap::new(),
+        };
+        mailbox.rebuild_index()?;
+        mailbox.advise();
+        Ok(mailbox)
+    }
+
+    /// 按本进程的内存建议处理映射
+    fn advise(&self) {
+        memory::advise(
+            &self.name,
+            self.memory,
+            self.size,
+            MemoryAdvice::default(),
+            self.memory_advice,
+        );
+    }
+
+    /// 共享内存段的映射大小与驻留内存大小
+    pub fn memory_usage(&self) -> MemoryUsage {
+        memory::usage(self.memory, self.size)
+    }
+
+    /// box 归还为空后按内存建议释放其数据所在的页面
+    fn released(&self, metadata: &BoxMetadata) {
+        if self.memory_advice.releases_on_drain() {
+            let offset = metadata.get_data_offset() as usize;
+            memory::release(
+                unsafe { self.memory.add(offset) },
+                metadata.get_size().bytes(),
+            );
+        }
+    }
+
+    /// 由 hugetlbfs 上的文件承载时返回文件路径，普通共享内存返回 `None`
+    pub fn hugetlbfs_path(&self) -> Option<&Path> {
+        self.file.as_deref()
+    }
+
+    /// 删除共享内存名称或 hugetlbfs 文件
+    fn remove_backing(&self) -> Result<()> {
+        match &self.file {
+            Some(path) => match std::fs::remove_file(path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    Err(anyhow!("删除 {} 失败: {}", path.display(), e).into())
+                }
+                _ => Ok(()),
+            },
+            None => SharedMemoryRegistry::unlink(&self.name),
+        }
+    }
+
+    /// 是否为共享内存段的持有者（Drop 时负责删除）
+    pub fn is_owner(&self) -> bool {
+        self.owner
+    }
+
+    /// 放弃持有权，Drop 时不再删除共享内存段
+    pub fn persist(&mut self) {
+        if self.owner {
+            SharedMemoryRegistry::deregister(&self.name);
+            self.owner = false;
+        }
+    }
+
+    /// 删除共享内存名称，已连接的进程可继续使用当前映射
+    pub fn unlink(&self) -> Result<()> {
+        self.remove_backing()
+    }
+
+    /// 删除共享内存名称并释放映射
+    pub fn destroy(mut self) -> Result<()> {
+        self.owner = false;
+        self.remove_backing()
+    }
+
     /// 计算所需的内存大小
     fn calculate_memory_size(config: &BoxConfig) -> usize {
         let header_size = mem::size_of::<MailboxHeader>();
@@ -390,7 +608,10 @@ impl SharedMemoryMailbox {
 
         // 初始化头部
         unsafe {
-            std::ptr::write(self.header, MailboxHeader::new(total_boxes as u32));
+            std::ptr::write(
+                self.header,
+                MailboxHeader::new(total_boxes as u32, config.integrity),
+            );
         }
 
         // 计算各部分的偏移量
@@ -430,17 +651,34 @@ impl SharedMemoryMailbox {
 
             self.box_index.insert(size, size_indices);
         }
+        self.reset_cursors();
 
         Ok(())
     }
 
     /// 重建索引（用于打开已存在的共享内存）
+    ///
+    /// 共享内存由其他进程写入，可能已损坏：头部、box 数量与各 box 的数据区都要落在映射范围内。
     fn rebuild_index(&mut self) -> Result<()> {
-        let header = unsafe { &*self.header };
-        let total_boxes = header.get_total_boxes() as usize;
-
         let header_size = mem::size_of::<MailboxHeader>();
+        if self.size < header_size || !self.header().is_valid() {
+            return Err(anyhow!("Mailbox {} has an invalid header", self.name).into());
+        }
+        let total_boxes = self.header().get_total_boxes() as usize;
+
         let metadata_start = header_size;
+        let data_start = total_boxes
+            .checked_mul(mem::size_of::<BoxMetadata>())
+            .and_then(|size| size.checked_add(metadata_start))
+            .filter(|end| *end <= self.size)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Mailbox {} box count {} exceeds mapped size {}",
+                    self.name,
+                    total_boxes,
+                    self.size
+                )
+            })?;
 
         self.boxes.clear();
         self.box_index.clear();
@@ -451,35 +689,65 @@ impl SharedMemoryMailbox {
             let metadata_ptr = unsafe { self.memory.add(metadata_offset) as *mut BoxMetadata };
 
             let metadata = unsafe { &*metadata_ptr };
-            let size = metadata.get_size();
+            let size = BoxSize::from_mb(metadata.size.load(Ordering::Relaxed))
+                .ok_or_else(|| anyhow!("Box {} has an invalid size", metadata.get_id()))?;
+            let offset = metadata.get_data_offset() as usize;
+            if offset < data_start || offset + size.bytes() > self.size {
+                return Err(anyhow!(
+                    "Box {} data region {}+{} is out of bounds",
+                    metadata.get_id(),
+                    offset,
+                    size.bytes()
+                )
+                .into());
+            }
 
             self.boxes.push(metadata_ptr);
 
             // 更新索引
-            self.box_index.entry(size).or_insert_with(Vec::new).push(i);
+            self.box_index.entry(size).or_default().push(i);
         }
+        self.reset_cursors();
 
         Ok(())
     }
 
-    /// 获取全局锁
-    pub fn lock(&self) -> Result<MailboxLock> {
+    fn reset_cursors(&mut self) {
+        self.cursors = self
+            .box_index
+            .keys()
+            .map(|&size| (size, AtomicUsize::new(0)))
+            .collect();
+    }
+
+    /// 获取全局锁，最多等待 [`DEFAULT_LOCK_TIMEOUT`]
+    ///
+    /// box 的分配与读写通过各自状态的 CAS 完成，不需要持有全局锁；
+    /// 全局锁仅用于需要整体一致视图的操作。
+    pub fn lock(&self) -> Result<MailboxLock<'_>> {
+        Ok(self.lock_timeout(DEFAULT_LOCK_TIMEOUT)?)
+    }
+
+    /// 获取全局锁，最多等待 `timeout`
+    pub fn lock_timeout(&self, timeout: Duration) -> Result<MailboxLock<'_>, LockTimeout> {
         let header = unsafe { &*self.header };
+        let deadline = Instant::now() + timeout;
 
-        // 改进的等待策略：先自旋，然后休眠
-        let mut attempts = 0;
+        // 先让出时间片，然后短暂休眠
+        let mut attempts = 0u32;
         while !header.try_lock() {
-            attempts += 1;
-            if attempts > 100000 {
-                return Err(anyhow!("Failed to acquire lock after 100000 attempts"));
+            if attempts == 0 {
+                lock_debug::waiting(&header.lock);
             }
-
+            if Instant::now() >= deadline {
+                lock_debug::abandoned(&header.lock);
+                return Err(LockTimeout { timeout });
+            }
+            attempts = attempts.saturating_add(1);
             if attempts < 1000 {
-                // 前1000次尝试使用 yield
                 std::thread::yield_now();
             } else {
-                // 之后使用短暂休眠
-                std::thread::sleep(std::time::Duration::from_micros(1));
+                std::thread::sleep(Duration::from_micros(1));
             }
         }
 
@@ -487,21 +755,66 @@ impl SharedMemoryMailbox {
     }
 
     /// 获取指定大小的空 box
+    ///
+    /// 逐个 box 以 CAS 将 Empty 切换为 Writing，不使用全局锁；
+    /// 每次从上次分配位置之后开始扫描，减少并发分配者争抢同一个 box。
     pub fn get_empty_box(&self, size: BoxSize) -> Result<u32> {
         let indices = self
             .box_index
             .get(&size)
             .ok_or_else(|| anyhow!("Invalid box size: {:?}", size))?;
 
-        for &index in indices {
-            let metadata = unsafe { &*self.boxes[index] };
-            if metadata.get_state() == BoxState::Empty {
-                metadata.set_state(BoxState::Writing);
+        let start = self.cursors[&size].load(Ordering::Relaxed);
+        for offset in 0..indices.len() {
+            let position = (start + offset) % indices.len();
+            let metadata = unsafe { &*self.boxes[indices[position]] };
+            if metadata.try_transition(BoxState::Empty, BoxState::Writing) {
+                metadata.set_owner_pid(std::process::id());
+                self.cursors[&size].store(position + 1, Ordering::Relaxed);
                 return Ok(metadata.get_id());
             }
         }
 
-        Err(anyhow!("No empty box available for size: {:?}", size))
+        Err(anyhow!("No empty box available for size: {:?}", size).into())
+    }
+
+    /// 能容纳 `len` 字节的最小已配置 box 大小
+    pub fn smallest_size_for(&self, len: usize) -> Option<BoxSize> {
+        self.box_index
+            .keys()
+            .copied()
+            .filter(|size| size.bytes() >= len)
+            .min_by_key(|size| size.bytes())
+    }
+
+    /// 为 `len` 字节的数据分配 box：从能容纳的最小大小开始依次尝试
+    pub fn allocate(&self, len: usize) -> Result<u32> {
+        let mut sizes: Vec<BoxSize> = self
+            .box_index
+            .keys()
+            .copied()
+            .filter(|size| size.bytes() >= len)
+            .collect();
+        if sizes.is_empty() {
+            return Err(anyhow!("No box size can hold {} bytes", len).into());
+        }
+        sizes.sort_by_key(|size| size.bytes());
+
+        sizes
+            .into_iter()
+            .find_map(|size| self.get_empty_box(size).ok())
+            .ok_or_else(|| anyhow!("No empty box available for {} bytes", len).into())
+    }
+
+    /// 放弃 box（任意状态）并归还为空，用于写入后消息未能送达等异常路径
+    pub fn discard(&self, box_id: u32) -> Result<()> {
+        let metadata = self.find_box_by_id(box_id)?;
+        metadata.set_data_length(0);
+        metadata.set_owner_pid(0);
+        metadata.set_state(BoxState::Empty);
+        self.released(metadata);
+        futex::bump_and_wake(&self.header().empty_seq);
+        Ok(())
     }
 
     /// 写入数据到指定 box
@@ -509,7 +822,7 @@ impl SharedMemoryMailbox {
         let metadata = self.find_box_by_id(box_id)?;
 
         if metadata.get_state() != BoxState::Writing {
-            return Err(anyhow!("Box {} is not in writing state", box_id));
+            return Err(anyhow!("Box {} is not in writing state", box_id).into());
         }
 
         let size = metadata.get_size();
@@ -518,31 +831,97 @@ impl SharedMemoryMailbox {
                 "Data size {} exceeds box capacity {}",
                 data.len(),
                 size.bytes()
-            ));
+            )
+            .into());
         }
 
         let data_offset = metadata.get_data_offset() as usize;
-        let data_ptr = unsafe { self.memory.add(data_offset) as *mut u8 };
+        let data_ptr = unsafe { self.memory.add(data_offset) };
 
         unsafe {
             std::ptr::copy_nonoverlapping(data.as_ptr(), data_ptr, data.len());
         }
 
         metadata.set_data_length(data.len() as u32);
-        metadata.set_state(BoxState::Full);
+        metadata
+            .checksum
+            .store(self.integrity().checksum(data), Ordering::Release);
+        metadata.set_owner_pid(0);
+        if !metadata.try_transition(BoxState::Writing, BoxState::Full) {
+            return Err(anyhow!("Box {} is not in writing state", box_id).into());
+        }
+        futex::bump_and_wake(&self.header().ready_seq);
 
         Ok(())
     }
 
+    /// 读取指定 box 的数据并释放 box
+    ///
+    /// 依次执行 `start_reading`、`read_data`、`finish_reading`；读取失败时 box 同样被释放
+    pub fn take_data(&self, box_id: u32) -> Result<Vec<u8>> {
+        self.start_reading(box_id)?;
+        let data = self.read_data(box_id);
+        self.finish_reading(box_id)?;
+        data
+    }
+
+    /// 以流式方式写入处于 Writing 状态的 box
+    ///
+    /// 数据直接拷贝进共享内存，不需要完整的中间缓冲；调用 [`BoxWriter::finish`]
+    /// 后 box 变为 Full，未调用 `finish` 就被丢弃时 box 归还为空。
+    pub fn writer(&self, box_id: u32) -> Result<BoxWriter<'_>> {
+        let metadata = self.find_box_by_id(box_id)?;
+        if metadata.get_state() != BoxState::Writing {
+            return Err(anyhow!("Box {} is not in writing state", box_id).into());
+        }
+
+        Ok(BoxWriter {
+            mailbox: self,
+            metadata,
+            position: 0,
+            finished: false,
+        })
+    }
+
+    /// 以流式方式读取写满的 box
+    ///
+    /// 创建时将 box 切换为 Reading 并校验数据，[`BoxReader`] 被丢弃时释放 box。
+    pub fn reader(&self, box_id: u32) -> Result<BoxReader<'_>> {
+        self.start_reading(box_id)?;
+        let metadata = self.find_box_by_id(box_id)?;
+
+        let data_length = match self.stored_length(metadata) {
+            Ok(length) => length,
+            Err(e) => {
+                self.finish_reading(box_id)?;
+                return Err(e);
+            }
+        };
+        let data = unsafe { std::slice::from_raw_parts(self.data_ptr(metadata), data_length) };
+        if !self
+            .integrity()
+            .verify(data, metadata.checksum.load(Ordering::Acquire))
+        {
+            self.finish_reading(box_id)?;
+            return Err(anyhow!("Box {} checksum mismatch", box_id).into());
+        }
+
+        Ok(BoxReader {
+            mailbox: self,
+            metadata,
+            position: 0,
+        })
+    }
+
     /// 开始读取指定 box
     pub fn start_reading(&self, box_id: u32) -> Result<()> {
         let metadata = self.find_box_by_id(box_id)?;
 
-        if metadata.get_state() != BoxState::Full {
-            return Err(anyhow!("Box {} is not full", box_id));
+        // 多个读者竞争同一个 box 时只有一个能成功
+        if !metadata.try_transition(BoxState::Full, BoxState::Reading) {
+            return Err(anyhow!("Box {} is not full", box_id).into());
         }
-
-        metadata.set_state(BoxState::Reading);
+        metadata.set_owner_pid(std::process::id());
         Ok(())
     }
 
@@ -551,18 +930,24 @@ impl SharedMemoryMailbox {
         let metadata = self.find_box_by_id(box_id)?;
 
         if metadata.get_state() != BoxState::Reading {
-            return Err(anyhow!("Box {} is not in reading state", box_id));
+            return Err(anyhow!("Box {} is not in reading state", box_id).into());
         }
 
-        let data_length = metadata.get_data_length() as usize;
-        let data_offset = metadata.get_data_offset() as usize;
-        let data_ptr = unsafe { self.memory.add(data_offset) };
+        let data_length = self.stored_length(metadata)?;
+        let data_ptr = self.data_ptr(metadata);
 
         let mut data = vec![0u8; data_length];
         unsafe {
             std::ptr::copy_nonoverlapping(data_ptr, data.as_mut_ptr(), data_length);
         }
 
+        if !self
+            .integrity()
+            .verify(&data, metadata.checksum.load(Ordering::Acquire))
+        {
+            return Err(anyhow!("Box {} checksum mismatch", box_id).into());
+        }
+
         Ok(data)
     }
 
@@ -571,14 +956,113 @@ impl SharedMemoryMailbox {
         let metadata = self.find_box_by_id(box_id)?;
 
         if metadata.get_state() != BoxState::Reading {
-            return Err(anyhow!("Box {} is not in reading state", box_id));
+            return Err(anyhow!("Box {} is not in reading state", box_id).into());
         }
 
         metadata.set_data_length(0);
-        metadata.set_state(BoxState::Empty);
+        metadata.set_owner_pid(0);
+        if !metadata.try_transition(BoxState::Reading, BoxState::Empty) {
+            return Err(anyhow!("Box {} is not in reading state", box_id).into());
+        }
+        self.released(metadata);
+        futex::bump_and_wake(&self.header().empty_seq);
         Ok(())
     }
 
+    /// 回收已退出进程遗留的 box，返回回收数量
+    ///
+    /// 进程在写入或读取途中崩溃时，box 会永久停留在 Writing / Reading；
+    /// 这里检查持有进程是否仍存活（`kill(pid, 0)`），已退出的 box 直接归还为空。
+    /// Full 状态的 box 不属于任何进程，仍等待读者取走，不做处理。
+    pub fn reclaim_orphans(&self) -> usize {
+        let mut reclaimed = 0;
+
+        for &metadata_ptr in &self.boxes {
+            let metadata = unsafe { &*metadata_ptr };
+            let state = metadata.get_state();
+            if !matches!(state, BoxState::Writing | BoxState::Reading) {
+                continue;
+            }
+
+            let pid = metadata.get_owner_pid();
+            if pid == 0 || shm_sync::process_alive(pid) {
+                continue;
+            }
+
+            // 持有者可能恰好在此期间完成了操作，CAS 失败时跳过
+            metadata.set_owner_pid(0);
+            if metadata.try_transition(state, BoxState::Empty) {
+                metadata.set_data_length(0);
+                reclaimed += 1;
+                tracing::warn!(
+                    "回收进程 {} 遗留的 box {} ({:?})",
+                    pid,
+                    metadata.get_id(),
+                    state
+                );
+            }
+        }
+
+        if reclaimed > 0 {
+            futex::bump_and_wake(&self.header().empty_seq);
+        }
+        reclaimed
+    }
+
+    /// 等待出现写满的 box（futex 跨进程休眠），超时返回 `false`
+    pub fn wait_for_ready(&self, timeout: Option<Duration>) -> bool {
+        futex::wait_until(&self.header().ready_seq, timeout, || {
+            !self.get_full_boxes().is_empty()
+        })
+    }
+
+    /// 等待指定大小出现空 box（futex 跨进程休眠），超时返回 `false`
+    pub fn wait_for_empty(&self, size: BoxSize, timeout: Option<Duration>) -> bool {
+        let Some(indices) = self.box_index.get(&size) else {
+            return false;
+        };
+        futex::wait_until(&self.header().empty_seq, timeout, || {
+            indices
+                .iter()
+                .any(|&index| unsafe { &*self.boxes[index] }.get_state() == BoxState::Empty)
+        })
+    }
+
+    /// 校验算法，以创建者写入头部的为准
+    pub fn integrity(&self) -> Integrity {
+        Integrity::from_u32(self.header().integrity.load(Ordering::Relaxed))
+    }
+
+    /// 共享内存名称
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 记录的数据长度，超过 box 容量或映射范围（被损坏的元数据）时返回错误
+    fn stored_length(&self, metadata: &BoxMetadata) -> Result<usize> {
+        let length = metadata.get_data_length() as usize;
+        let capacity = metadata.get_size().bytes();
+        let offset = metadata.get_data_offset() as usize;
+        if length > capacity || offset.saturating_add(length) > self.size {
+            return Err(anyhow!(
+                "Box {} data length {} exceeds capacity {}",
+                metadata.get_id(),
+                length,
+                capacity
+            )
+            .into());
+        }
+        Ok(length)
+    }
+
+    fn data_ptr(&self, metadata: &BoxMetadata) -> *mut u8 {
+        unsafe { self.memory.add(metadata.get_data_offset() as usize) }
+    }
+
+    fn header(&self) -> &MailboxHeader {
+        unsafe { &*self.header }
+    }
+
     /// 根据 ID 查找 box
     fn find_box_by_id(&self, box_id: u32) -> Result<&BoxMetadata> {
         for &metadata_ptr in &self.boxes {
@@ -587,7 +1071,7 @@ impl SharedMemoryMailbox {
                 return Ok(metadata);
             }
         }
-        Err(anyhow!("Box with ID {} not found", box_id))
+        Err(anyhow!("Box with ID {} not found", box_id).into())
     }
 
     /// 获取所有满的 box ID
@@ -635,3 +1119,563 @@ impl SharedMemoryMailbox {
         stats
     }
 }
+
+/// box 的流式写入器，见 [`SharedMemoryMailbox::writer`]
+pub struct BoxWriter<'a> {
+    mailbox: &'a SharedMemoryMailbox,
+    metadata: &'a BoxMetadata,
+    position: usize,
+    finished: bool,
+}
+
+impl BoxWriter<'_> {
+    /// box ID
+    pub fn box_id(&self) -> u32 {
+        self.metadata.get_id()
+    }
+
+    /// 已写入的字节数
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// 剩余可写入的字节数
+    pub fn remaining(&self) -> usize {
+        self.metadata.get_size().bytes() - self.position
+    }
+
+    /// 完成写入：记录数据长度并将 box 切换为 Full，返回写入的字节数
+    pub fn finish(mut self) -> Result<usize> {
+        let box_id = self.box_id();
+        self.finished = true;
+
+        let data = unsafe {
+            std::slice::from_raw_parts(self.mailbox.data_ptr(self.metadata), self.position)
+        };
+        self.metadata.set_data_length(self.position as u32);
+        self.metadata
+            .checksum
+            .store(self.mailbox.integrity().checksum(data), Ordering::Release);
+        self.metadata.set_owner_pid(0);
+        if !self
+            .metadata
+            .try_transition(BoxState::Writing, BoxState::Full)
+        {
+            return Err(anyhow!("Box {} is not in writing state", box_id).into());
+        }
+        futex::bump_and_wake(&self.mailbox.header().ready_seq);
+
+        Ok(self.position)
+    }
+}
+
+impl std::io::Write for BoxWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // box 写满后返回 0，`write_all` 据此报告 WriteZero
+        let len = buf.len().min(self.remaining());
+        unsafe {
+            let dst = self.mailbox.data_ptr(self.metadata).add(self.position);
+            std::ptr::copy_nonoverlapping(buf.as_ptr(), dst, len);
+        }
+        self.position += len;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for BoxWriter<'_> {
+    fn drop(&mut self) {
+        if !self.finished
+            && let Err(e) = self.mailbox.discard(self.box_id())
+        {
+            tracing::warn!("归还未完成写入的 box {} 失败: {}", self.box_id(), e);
+        }
+    }
+}
+
+/// box 的流式读取器，见 [`SharedMemoryMailbox::reader`]
+pub struct BoxReader<'a> {
+    mailbox: &'a SharedMemoryMailbox,
+    metadata: &'a BoxMetadata,
+    position: usize,
+}
+
+impl BoxReader<'_> {
+    /// box ID
+    pub fn box_id(&self) -> u32 {
+        self.metadata.get_id()
+    }
+
+    /// box 中数据的总长度
+    pub fn len(&self) -> usize {
+        (self.metadata.get_data_length() as usize).min(self.metadata.get_size().bytes())
+    }
+
+    /// box 中是否没有数据
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 已读取的字节数
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// 尚未读取的字节数
+    pub fn remaining(&self) -> usize {
+        self.len() - self.position
+    }
+}
+
+impl std::io::Read for BoxReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = buf.len().min(self.remaining());
+        unsafe {
+            let src = self.mailbox.data_ptr(self.metadata).add(self.position);
+            std::ptr::copy_nonoverlapping(src, buf.as_mut_ptr(), len);
+        }
+        self.position += len;
+        Ok(len)
+    }
+}
+
+impl Drop for BoxReader<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = self.mailbox.finish_reading(self.box_id()) {
+            tracing::warn!("释放 box {} 失败: {}", self.box_id(), e);
+        }
+    }
+}
+
+/// 寄存箱的内存映射
+struct Mapping {
+    memory: *mut u8,
+    size: usize,
+    is_new: bool,
+    file: Option<PathBuf>,
+}
+
+/// 映射命名共享内存，`create` 为 `false` 且共享内存不存在时返回 `None`
+fn map_shm(name: &str, size: usize, create: bool) -> Result<Option<Mapping>> {
+    // 创建共享内存名称，确保以 '/' 开头
+    let shm_name = CString::new(format!("/{}", name.trim_start_matches('/')))
+        .map_err(|_| anyhow!("Failed to create CString from name"))?;
+
+    let flags = if create { O_CREAT | O_RDWR } else { O_RDWR };
+    let fd = unsafe { libc::shm_open(shm_name.as_ptr(), flags, crate::access::mode()) };
+    if fd == -1 {
+        if !create {
+            return Ok(None);
+        }
+        return Err(anyhow!("shm_open failed with errno: {}", shm_sync::errno()).into());
+    }
+    if create && let Err(e) = crate::access::restrict(fd) {
+        unsafe { libc::close(fd) };
+        return Err(e);
+    }
+
+    // 如果是新创建的共享内存，设置大小
+    if create && unsafe { ftruncate(fd, size as libc::off_t) } == -1 {
+        unsafe { close(fd) };
+        return Err(anyhow!("ftruncate failed with errno: {}", shm_sync::errno()).into());
+    }
+
+    // 连接已有的寄存箱时按共享内存的实际大小映射，布局由头部记录的 box 数量决定
+    let size = if create {
+        size
+    } else {
+        let mut stat: libc::stat = unsafe { mem::zeroed() };
+        if unsafe { libc::fstat(fd, &mut stat) } == -1 {
+            unsafe { close(fd) };
+            return Err(anyhow!("fstat failed with errno: {}", shm_sync::errno()).into());
+        }
+        stat.st_size as usize
+    };
+
+    let memory = map_fd(fd, size);
+    unsafe { close(fd) };
+    Ok(Some(Mapping {
+        memory: memory?,
+        size,
+        is_new: create,
+        file: None,
+    }))
+}
+
+fn map_fd(fd: libc::c_int, size: usize) -> Result<*mut u8> {
+    let memory = unsafe {
+        mmap(
+            ptr::null_mut(),
+            size,
+            PROT_READ | PROT_WRITE,
+            MAP_SHARED,
+            fd,
+            0,
+        )
+    };
+    if memory == MAP_FAILED {
+        return Err(anyhow!("mmap failed with errno: {}", shm_sync::errno()).into());
+    }
+    Ok(memory as *mut u8)
+}
+
+/// hugetlbfs 文件系统的 `f_type`
+#[cfg(target_os = "linux")]
+const HUGETLBFS_MAGIC: libc::c_long = 0x9584_58f6;
+
+/// 映射 hugetlbfs 挂载点下的同名文件，大小向上取整到大页大小
+///
+/// `create` 为 `false` 且文件不存在时返回 `None`。
+#[cfg(target_os = "linux")]
+fn map_hugetlbfs(mount: &Path, name: &str, size: usize, create: bool) -> Result<Option<Mapping>> {
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::os::unix::io::AsRawFd;
+
+    let cmount = CString::new(mount.as_os_str().as_encoded_bytes())
+        .map_err(|_| anyhow!("Failed to create CString from path"))?;
+    let mut stat: libc::statfs = unsafe { mem::zeroed() };
+    if unsafe { libc::statfs(cmount.as_ptr(), &mut stat) } == -1 {
+        return Err(anyhow!(
+            "statfs {} failed with errno: {}",
+            mount.display(),
+            shm_sync::errno()
+        )
+        .into());
+    }
+    if stat.f_type as libc::c_long != HUGETLBFS_MAGIC {
+        return Err(anyhow!("{} 不是 hugetlbfs 挂载点", mount.display()).into());
+    }
+    let page = stat.f_bsize as usize;
+    let size = size.div_ceil(page) * page;
+
+    let path = mount.join(name.trim_start_matches('/'));
+    let file = match std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(create)
+        .mode(crate::access::mode())
+        .open(&path)
+    {
+        Ok(file) => file,
+        Err(e) if !create && e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(anyhow!("打开 {} 失败: {}", path.display(), e).into()),
+    };
+    if create && let Err(e) = crate::access::restrict(file.as_raw_fd()) {
+        let _ = std::fs::remove_file(&path);
+        return Err(e);
+    }
+
+    // 预留的大页不足时 mmap 失败，新建的文件随之删除
+    let memory = if create {
+        file.set_len(size as u64)
+            .with_context(|| format!("设置 {} 的大小失败", path.display()))
+            .map_err(Into::into)
+            .and_then(|_| map_fd(file.as_raw_fd(), size))
+    } else {
+        file.metadata()
+            .with_context(|| format!("读取 {} 的大小失败", path.display()))
+            .map_err(Into::into)
+            .and_then(|metadata| map_fd(file.as_raw_fd(), metadata.len() as usize))
+    };
+    let memory = match memory {
+        Ok(memory) => memory,
+        Err(e) => {
+            if create {
+                let _ = std::fs::remove_file(&path);
+            }
+            return Err(e);
+        }
+    };
+    Ok(Some(Mapping {
+        memory,
+        size,
+        is_new: create,
+        file: Some(path),
+    }))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn map_hugetlbfs(
+    _mount: &Path,
+    _name: &str,
+    _size: usize,
+    _create: bool,
+) -> Result<Option<Mapping>> {
+    Err(anyhow!("hugetlbfs 仅 Linux 支持"))
+}
+
+/// 请求 shmem 透明大页，内核未启用时返回错误
+#[cfg(target_os = "linux")]
+fn advise_hugepages(memory: *mut u8, size: usize) -> Result<()> {
+    let enabled = std::fs::read_to_string("/sys/kernel/mm/transparent_hugepage/shmem_enabled")
+        .context("内核不支持透明大页")?;
+    // 当前取值位于方括号中，如 "always within_size [advise] never deny force"
+    let current = enabled
+        .split_whitespace()
+        .find(|value| value.starts_with('['))
+        .map(|value| value.trim_matches(['[', ']']))
+        .unwrap_or("never");
+    if matches!(current, "never" | "deny") {
+        return Err(anyhow!("shmem 透明大页未启用 (shmem_enabled={})", current).into());
+    }
+    if unsafe { libc::madvise(memory as *mut libc::c_void, size, libc::MADV_HUGEPAGE) } == -1 {
+        return Err(anyhow!("madvise failed with errno: {}", shm_sync::errno()).into());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn advise_hugepages(_memory: *mut u8, _size: usize) -> Result<()> {
+    Err(anyhow!("透明大页仅 Linux 支持"))
+}
+
+/// 把尚未访问的内存绑定到 NUMA 节点，之后写入时才在该节点上分配物理页
+#[cfg(target_os = "linux")]
+fn bind_numa_node(memory: *mut u8, size: usize, node: u32) -> Result<()> {
+    const MPOL_BIND: libc::c_long = 2;
+
+    if !Path::new(&format!("/sys/devices/system/node/node{}", node)).exists() {
+        return Err(anyhow!("NUMA 节点 {} 不存在", node).into());
+    }
+    let mut mask = vec![0u64; node as usize / 64 + 1];
+    mask[node as usize / 64] |= 1 << (node % 64);
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_mbind,
+            memory,
+            size,
+            MPOL_BIND,
+            mask.as_ptr(),
+            mask.len() * 64 + 1,
+            0,
+        )
+    };
+    if result == -1 {
+        return Err(anyhow!("mbind failed with errno: {}", shm_sync::errno()).into());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn bind_numa_node(_memory: *mut u8, _size: usize, _node: u32) -> Result<()> {
+    Err(anyhow!("NUMA 绑定仅 Linux 支持"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_concurrent_allocation_without_global_lock() {
+        let name = format!("mi7_test_mailbox_alloc_{}", std::process::id());
+        let mut config = BoxConfig::new();
+        config
+            .set_count(BoxSize::Size1M, 8)
+            .set_count(BoxSize::Size2M, 4);
+        let mailbox = Arc::new(SharedMemoryMailbox::new_shared(&name, config).unwrap());
+
+        // 持有全局锁时分配不受影响
+        let _guard = mailbox.lock().unwrap();
+        let timeout = Duration::from_millis(10);
+        assert_eq!(
+            mailbox.lock_timeout(timeout).err(),
+            Some(LockTimeout { timeout })
+        );
+
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let mailbox = Arc::clone(&mailbox);
+                let size = if i % 2 == 0 {
+                    BoxSize::Size1M
+                } else {
+                    BoxSize::Size2M
+                };
+                std::thread::spawn(move || {
+                    let mut ids = Vec::new();
+                    while let Ok(id) = mailbox.get_empty_box(size) {
+                        ids.push(id);
+                    }
+                    ids
+                })
+            })
+            .collect();
+
+        let ids: Vec<u32> = handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect();
+        let unique: HashSet<u32> = ids.iter().copied().collect();
+        assert_eq!(ids.len(), 12);
+        assert_eq!(unique.len(), 12);
+        assert_eq!(mailbox.get_stats().writing_count, 12);
+    }
+
+    #[test]
+    fn test_reclaim_orphans_from_dead_process() {
+        let name = format!("mi7_test_mailbox_orphans_{}", std::process::id());
+        let mut config = BoxConfig::new();
+        config.set_count(BoxSize::Size1M, 3);
+        let mailbox = SharedMemoryMailbox::new_shared(&name, config).unwrap();
+
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let dead_pid = child.id();
+        child.wait().unwrap();
+
+        // 已退出进程持有的写入中 box
+        let writing = mailbox.get_empty_box(BoxSize::Size1M).unwrap();
+        assert_eq!(
+            mailbox.find_box_by_id(writing).unwrap().get_owner_pid(),
+            std::process::id()
+        );
+        mailbox
+            .find_box_by_id(writing)
+            .unwrap()
+            .set_owner_pid(dead_pid);
+
+        // 已退出进程持有的读取中 box
+        let reading = mailbox.get_empty_box(BoxSize::Size1M).unwrap();
+        mailbox.write_data(reading, b"orphan").unwrap();
+        mailbox.start_reading(reading).unwrap();
+        mailbox
+            .find_box_by_id(reading)
+            .unwrap()
+            .set_owner_pid(dead_pid);
+
+        // 当前进程仍持有的 box 不会被回收
+        let alive = mailbox.get_empty_box(BoxSize::Size1M).unwrap();
+
+        assert_eq!(mailbox.reclaim_orphans(), 2);
+        let stats = mailbox.get_stats();
+        assert_eq!(stats.empty_count, 2);
+        assert_eq!(stats.writing_count, 1);
+        assert_eq!(
+            mailbox.find_box_by_id(alive).unwrap().get_state(),
+            BoxState::Writing
+        );
+        assert_eq!(mailbox.reclaim_orphans(), 0);
+    }
+
+    #[test]
+    fn test_streaming_write_and_read() {
+        use std::io::{Read, Write};
+
+        let name = format!("mi7_test_mailbox_stream_{}", std::process::id());
+        let mut config = BoxConfig::new();
+        config.set_count(BoxSize::Size1M, 2);
+        let mailbox = SharedMemoryMailbox::new_shared(&name, config).unwrap();
+
+        let chunk: Vec<u8> = (0..=255u8).collect();
+        let box_id = mailbox.get_empty_box(BoxSize::Size1M).unwrap();
+        let mut writer = mailbox.writer(box_id).unwrap();
+        for _ in 0..1024 {
+            writer.write_all(&chunk).unwrap();
+        }
+        assert_eq!(writer.position(), 256 * 1024);
+        assert_eq!(writer.finish().unwrap(), 256 * 1024);
+
+        let mut reader = mailbox.reader(box_id).unwrap();
+        assert_eq!(reader.len(), 256 * 1024);
+        let mut head = [0u8; 300];
+        reader.read_exact(&mut head).unwrap();
+        assert_eq!(&head[..256], &chunk[..]);
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest.len(), 256 * 1024 - 300);
+        drop(reader);
+        assert_eq!(mailbox.get_stats().empty_count, 2);
+
+        // 超出容量时 write_all 报错，未完成的写入在丢弃时归还
+        let box_id = mailbox.get_empty_box(BoxSize::Size1M).unwrap();
+        let mut writer = mailbox.writer(box_id).unwrap();
+        let err = writer
+            .write_all(&vec![0u8; BoxSize::Size1M.bytes() + 1])
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::WriteZero);
+        drop(writer);
+        assert_eq!(mailbox.get_stats().empty_count, 2);
+    }
+
+    #[test]
+    fn test_read_detects_corrupted_box() {
+        let name = format!("mi7_test_mailbox_integrity_{}", std::process::id());
+        let mut config = BoxConfig::new();
+        config
+            .set_count(BoxSize::Size1M, 1)
+            .set_integrity(Integrity::XxHash64);
+        let mailbox = SharedMemoryMailbox::new_shared(&name, config).unwrap();
+
+        let box_id = mailbox.get_empty_box(BoxSize::Size1M).unwrap();
+        mailbox.write_data(box_id, b"abcdef").unwrap();
+
+        // 模拟数据被覆盖：交换前两个字节
+        let metadata = mailbox.find_box_by_id(box_id).unwrap();
+        unsafe {
+            std::ptr::swap(
+                mailbox.data_ptr(metadata),
+                mailbox.data_ptr(metadata).add(1),
+            )
+        };
+
+        let err = mailbox.take_data(box_id).unwrap_err();
+        assert!(err.to_string().contains("checksum mismatch"));
+        assert_eq!(mailbox.get_stats().empty_count, 1);
+    }
+
+    #[test]
+    fn test_connect_rejects_corrupted_metadata() {
+        let name = format!("mi7_test_mailbox_corrupted_{}", std::process::id());
+        let mut config = BoxConfig::new();
+        config.set_count(BoxSize::Size1M, 2);
+        let mailbox = SharedMemoryMailbox::new_shared(&name, config.clone()).unwrap();
+
+        // box 数量超出映射范围
+        mailbox
+            .header()
+            .total_boxes
+            .store(u32::MAX, Ordering::Relaxed);
+        assert!(SharedMemoryMailbox::new_shared(&name, config.clone()).is_err());
+        mailbox.header().total_boxes.store(2, Ordering::Relaxed);
+
+        // 数据区偏移指向映射之外
+        let metadata = unsafe { &*mailbox.boxes[1] };
+        let offset = metadata.get_data_offset();
+        metadata.data_ptr.store(u32::MAX - 1, Ordering::Relaxed);
+        assert!(SharedMemoryMailbox::new_shared(&name, config.clone()).is_err());
+        metadata.data_ptr.store(offset, Ordering::Relaxed);
+
+        // 记录的数据长度超过容量
+        let box_id = mailbox.get_empty_box(BoxSize::Size1M).unwrap();
+        mailbox.write_data(box_id, b"abc").unwrap();
+        let peer = SharedMemoryMailbox::new_shared(&name, config).unwrap();
+        let metadata = peer.find_box_by_id(box_id).unwrap();
+        metadata.set_data_length(u32::MAX);
+        assert!(peer.take_data(box_id).is_err());
+        assert_eq!(mailbox.get_stats().empty_count, 2);
+    }
+
+    #[test]
+    fn test_hugepage_options_fall_back() {
+        let name = format!("mi7_test_mailbox_hugepages_{}", std::process::id());
+        // 普通目录不是 hugetlbfs 挂载点，退回普通共享内存
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = BoxConfig::new();
+        config
+            .set_count(BoxSize::Size1M, 1)
+            .set_hugepages(HugePages::Hugetlbfs(dir.path().to_path_buf()))
+            .set_numa_node(0);
+        let mailbox = SharedMemoryMailbox::new_shared(&name, config.clone()).unwrap();
+        assert!(mailbox.is_owner());
+        assert_eq!(mailbox.hugetlbfs_path(), None);
+
+        let box_id = mailbox.get_empty_box(BoxSize::Size1M).unwrap();
+        mailbox.write_data(box_id, b"huge").unwrap();
+        let peer = SharedMemoryMailbox::new_shared(&name, config).unwrap();
+        assert!(!peer.is_owner());
+        assert_eq!(peer.take_data(box_id).unwrap(), b"huge");
+    }
+}
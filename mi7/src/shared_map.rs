@@ -0,0 +1,513 @@
+//! 共享内存哈希表：跨进程的小型键值存储
+//!
+//! [`SharedHashMap`] 把固定大小的键值对存放在命名共享内存段中的开放寻址表里（线性探测），
+//! 适合会话令牌这类需要被多个进程查询、更新的小数据。创建者指定容量（向上取整为 2 的幂），
+//! 其他进程按名称连接，键值类型的大小与创建者不一致时拒绝连接。
+//!
+//! 每个桶带一把自旋锁保护其内容，读写只在检查的桶上短暂加锁；插入还持有键所在起始桶的
+//! 链锁，同一个键的插入因此串行执行，不会在探测链上留下两份。锁字记录持有者 PID，持有者
+//! 进程退出后等待方接管该锁（此时桶内容可能只写了一半）。
+//!
+//! 删除留下墓碑，之后的插入复用墓碑；表中没有空桶或墓碑时插入返回 [`MapFull`]。
+//! 键按字节做哈希，`K` 与 `V` 应为没有填充字节、任意位模式都有效的纯数据（整数、数组及其组合）。
+
+use crate::locks::Mapping;
+use crate::shm_registry::SharedMemoryRegistry;
+use crate::shm_sync;
+
+use crate::error::Result;
+use anyhow::anyhow;
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::mem::{MaybeUninit, align_of, size_of};
+use std::ptr;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use xxhash_rust::xxh64::xxh64;
+
+/// 共享内存段已初始化的标记
+const MAP_MAGIC: u32 = 0x4d49_374d; // "MI7M"
+
+/// 桶状态
+const EMPTY: u32 = 0;
+const OCCUPIED: u32 = 1;
+const TOMBSTONE: u32 = 2;
+
+/// 自旋多少次检查一次锁持有者是否存活
+const OWNER_CHECK_SPINS: u32 = 1024;
+
+/// 表中没有可用的桶
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("共享哈希表 {name} 已满（容量 {capacity}）")]
+pub struct MapFull {
+    pub name: String,
+    pub capacity: usize,
+}
+
+/// 哈希表的使用统计，所有连接方的合计
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MapStats {
+    pub capacity: usize,
+    pub len: usize,
+    /// 已删除、尚未被复用的桶
+    pub tombstones: usize,
+    /// 命中的查询次数
+    pub hits: u64,
+    /// 未命中的查询次数
+    pub misses: u64,
+    /// 新增键的次数
+    pub inserts: u64,
+    /// 覆盖已有键的次数
+    pub updates: u64,
+    pub removes: u64,
+}
+
+impl MapStats {
+    /// 已占用的桶（包括墓碑）占容量的比例
+    pub fn load_factor(&self) -> f64 {
+        if self.capacity == 0 {
+            0.0
+        } else {
+            (self.len + self.tombstones) as f64 / self.capacity as f64
+        }
+    }
+}
+
+#[repr(C)]
+struct MapHeader {
+    magic: AtomicU32,
+    key_size: u32,
+    value_size: u32,
+    bucket_size: u32,
+    capacity: u64,
+    len: AtomicU64,
+    tombstones: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    inserts: AtomicU64,
+    updates: AtomicU64,
+    removes: AtomicU64,
+}
+
+#[repr(C)]
+struct Bucket<K, V> {
+    chain: AtomicU32, // 起始桶为本桶的键的插入锁，持有者 PID，0 表示空闲
+    lock: AtomicU32,  // 桶内容锁，持有者 PID，0 表示空闲
+    state: AtomicU32,
+    _reserved: u32,
+    key: UnsafeCell<MaybeUninit<K>>,
+    value: UnsafeCell<MaybeUninit<V>>,
+}
+
+impl<K: Copy, V: Copy> Bucket<K, V> {
+    /// 调用者需持有 `lock` 且桶状态为 OCCUPIED
+    unsafe fn key(&self) -> K {
+        unsafe { ptr::read_volatile(self.key.get()).assume_init() }
+    }
+
+    /// 调用者需持有 `lock` 且桶状态为 OCCUPIED
+    unsafe fn value(&self) -> V {
+        unsafe { ptr::read_volatile(self.value.get()).assume_init() }
+    }
+
+    /// 调用者需持有 `lock`
+    unsafe fn write(&self, key: K, value: V) {
+        unsafe {
+            ptr::write_volatile(self.key.get(), MaybeUninit::new(key));
+            ptr::write_volatile(self.value.get(), MaybeUninit::new(value));
+        }
+    }
+}
+
+/// 持有自旋锁，Drop 时释放
+struct SpinGuard<'a> {
+    word: &'a AtomicU32,
+}
+
+impl<'a> SpinGuard<'a> {
+    fn lock(word: &'a AtomicU32) -> Self {
+        let pid = std::process::id();
+        let mut spins = 0u32;
+        loop {
+            let holder = word.load(Ordering::Relaxed);
+            if holder == 0 {
+                if word
+                    .compare_exchange_weak(0, pid, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    return Self { word };
+                }
+            } else if spins % OWNER_CHECK_SPINS == OWNER_CHECK_SPINS - 1
+                && !shm_sync::process_alive(holder)
+                && word
+                    .compare_exchange(holder, pid, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                tracing::warn!("接管已退出进程 {} 持有的哈希表桶锁", holder);
+                return Self { word };
+            }
+            spins = spins.wrapping_add(1);
+            if spins < 64 {
+                std::hint::spin_loop();
+            } else {
+                std::thread::yield_now();
+            }
+        }
+    }
+}
+
+impl Drop for SpinGuard<'_> {
+    fn drop(&mut self) {
+        self.word.store(0, Ordering::Release);
+    }
+}
+
+/// 命名共享内存段中的固定大小键值哈希表，创建者 Drop 时删除该段
+pub struct SharedHashMap<K, V> {
+    mapping: Mapping,
+    name: String,
+    owner: bool,
+    _marker: PhantomData<(K, V)>,
+}
+
+unsafe impl<K: Copy + Send, V: Copy + Send> Send for SharedHashMap<K, V> {}
+unsafe impl<K: Copy + Send, V: Copy + Send> Sync for SharedHashMap<K, V> {}
+
+impl<K: Copy + Eq, V: Copy> SharedHashMap<K, V> {
+    /// 创建（或清空）哈希表，容量向上取整为 2 的幂
+    pub fn create(name: &str, capacity: usize) -> Result<Self> {
+        if capacity == 0 {
+            return Err(anyhow!("共享哈希表容量不能为 0").into());
+        }
+        let capacity = capacity.next_power_of_two();
+        let size = Self::buckets_offset() + capacity * size_of::<Bucket<K, V>>();
+        let mapping = Mapping::create(name, size)?;
+        unsafe { ptr::write_bytes(mapping.as_ptr(), 0, size) };
+        let header = unsafe { &mut *(mapping.as_ptr() as *mut MapHeader) };
+        let map = Self {
+            mapping,
+            name: name.to_string(),
+            owner: true,
+            _marker: PhantomData,
+        };
+        SharedMemoryRegistry::register(name);
+
+        header.key_size = size_of::<K>() as u32;
+        header.value_size = size_of::<V>() as u32;
+        header.bucket_size = size_of::<Bucket<K, V>>() as u32;
+        header.capacity = capacity as u64;
+        header.magic.store(MAP_MAGIC, Ordering::Release);
+        Ok(map)
+    }
+
+    /// 连接已创建的哈希表，`K` 与 `V` 的大小必须与创建者一致
+    pub fn open(name: &str) -> Result<Self> {
+        // 先只映射头部读取容量，再按容量映射整个表
+        let capacity = {
+            let mapping = Mapping::open(name, size_of::<MapHeader>())?;
+            let header = unsafe { &*(mapping.as_ptr() as *const MapHeader) };
+            Self::check(name, header)?;
+            header.capacity as usize
+        };
+
+        let size = Self::buckets_offset() + capacity * size_of::<Bucket<K, V>>();
+        Ok(Self {
+            mapping: Mapping::open(name, size)?,
+            name: name.to_string(),
+            owner: false,
+            _marker: PhantomData,
+        })
+    }
+
+    /// 检查段已发布且键值大小一致
+    fn check(name: &str, header: &MapHeader) -> Result<()> {
+        if header.magic.load(Ordering::Acquire) != MAP_MAGIC {
+            return Err(anyhow!("共享哈希表 {} 尚未初始化", name).into());
+        }
+        if header.key_size as usize != size_of::<K>()
+            || header.value_size as usize != size_of::<V>()
+            || header.bucket_size as usize != size_of::<Bucket<K, V>>()
+        {
+            return Err(anyhow!(
+                "共享哈希表 {} 的键值大小为 {}/{} 字节，与 {}/{} 不一致",
+                name,
+                header.key_size,
+                header.value_size,
+                size_of::<K>(),
+                size_of::<V>()
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// 第一个桶相对段起始的偏移
+    fn buckets_offset() -> usize {
+        size_of::<MapHeader>().next_multiple_of(align_of::<Bucket<K, V>>())
+    }
+
+    fn header(&self) -> &MapHeader {
+        unsafe { &*(self.mapping.as_ptr() as *const MapHeader) }
+    }
+
+    fn bucket(&self, index: usize) -> &Bucket<K, V> {
+        unsafe {
+            let offset = Self::buckets_offset() + index * size_of::<Bucket<K, V>>();
+            &*(self.mapping.as_ptr().add(offset) as *const Bucket<K, V>)
+        }
+    }
+
+    /// 键的起始桶
+    fn home(&self, key: &K) -> usize {
+        let bytes =
+            unsafe { std::slice::from_raw_parts(key as *const K as *const u8, size_of::<K>()) };
+        xxh64(bytes, 0) as usize & (self.capacity() - 1)
+    }
+
+    /// 从 `home` 开始的探测顺序，覆盖整张表
+    fn probe(&self, home: usize) -> impl Iterator<Item = usize> + use<K, V> {
+        let mask = self.capacity() - 1;
+        (0..self.capacity()).map(move |i| (home + i) & mask)
+    }
+
+    /// 共享内存段名称
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 桶的数量
+    pub fn capacity(&self) -> usize {
+        self.header().capacity as usize
+    }
+
+    /// 键值对的数量
+    pub fn len(&self) -> usize {
+        self.header().len.load(Ordering::Relaxed) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 查找键所在的桶，在持有该桶内容锁时调用 `on_match`
+    fn find<R>(&self, key: &K, on_match: impl FnOnce(&Bucket<K, V>) -> R) -> Option<R> {
+        for index in self.probe(self.home(key)) {
+            let bucket = self.bucket(index);
+            let _guard = SpinGuard::lock(&bucket.lock);
+            match bucket.state.load(Ordering::Relaxed) {
+                EMPTY => return None,
+                OCCUPIED if unsafe { bucket.key() } == *key => return Some(on_match(bucket)),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// 读取键对应的值
+    pub fn get(&self, key: &K) -> Option<V> {
+        let value = self.find(key, |bucket| unsafe { bucket.value() });
+        let counter = match value {
+            Some(_) => &self.header().hits,
+            None => &self.header().misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        value
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// 插入或覆盖键值对，返回旧值；没有可用的桶时返回 [`MapFull`]
+    pub fn insert(&self, key: K, value: V) -> Result<Option<V>> {
+        let home = self.home(&key);
+        let _chain = SpinGuard::lock(&self.bucket(home).chain);
+        loop {
+            let mut vacant = None;
+            for index in self.probe(home) {
+                let bucket = self.bucket(index);
+                let _guard = SpinGuard::lock(&bucket.lock);
+                match bucket.state.load(Ordering::Relaxed) {
+                    OCCUPIED if unsafe { bucket.key() } == key => {
+                        let old = unsafe { bucket.value() };
+                        unsafe { bucket.write(key, value) };
+                        self.header().updates.fetch_add(1, Ordering::Relaxed);
+                        return Ok(Some(old));
+                    }
+                    OCCUPIED => {}
+                    TOMBSTONE => vacant = vacant.or(Some(index)),
+                    _ => {
+                        vacant = vacant.or(Some(index));
+                        break;
+                    }
+                }
+            }
+
+            let Some(index) = vacant else {
+                return Err(MapFull {
+                    name: self.name.clone(),
+                    capacity: self.capacity(),
+                }
+                .into());
+            };
+            // 检查与占用之间该桶可能已被其他键占用，此时重新探测
+            let bucket = self.bucket(index);
+            let _guard = SpinGuard::lock(&bucket.lock);
+            let state = bucket.state.load(Ordering::Relaxed);
+            if state == OCCUPIED {
+                continue;
+            }
+            unsafe { bucket.write(key, value) };
+            bucket.state.store(OCCUPIED, Ordering::Relaxed);
+            if state == TOMBSTONE {
+                self.header().tombstones.fetch_sub(1, Ordering::Relaxed);
+            }
+            self.header().len.fetch_add(1, Ordering::Relaxed);
+            self.header().inserts.fetch_add(1, Ordering::Relaxed);
+            return Ok(None);
+        }
+    }
+
+    /// 删除键值对，返回被删除的值
+    pub fn remove(&self, key: &K) -> Option<V> {
+        let removed = self.find(key, |bucket| {
+            bucket.state.store(TOMBSTONE, Ordering::Relaxed);
+            unsafe { bucket.value() }
+        });
+        if removed.is_some() {
+            let header = self.header();
+            header.len.fetch_sub(1, Ordering::Relaxed);
+            header.tombstones.fetch_add(1, Ordering::Relaxed);
+            header.removes.fetch_add(1, Ordering::Relaxed);
+        }
+        removed
+    }
+
+    /// 删除全部键值对
+    ///
+    /// 先按顺序取得所有链锁，清空期间的插入等待清空完成。
+    pub fn clear(&self) {
+        let _chains: Vec<_> = (0..self.capacity())
+            .map(|index| SpinGuard::lock(&self.bucket(index).chain))
+            .collect();
+        for index in 0..self.capacity() {
+            let bucket = self.bucket(index);
+            let _guard = SpinGuard::lock(&bucket.lock);
+            bucket.state.store(EMPTY, Ordering::Relaxed);
+        }
+        self.header().len.store(0, Ordering::Relaxed);
+        self.header().tombstones.store(0, Ordering::Relaxed);
+    }
+
+    /// 按桶顺序遍历键值对
+    ///
+    /// 每个桶在读取时单独加锁，遍历期间其他进程的修改可能被看到，也可能看不到。
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            map: self,
+            index: 0,
+        }
+    }
+
+    /// 所有连接方累计的统计
+    pub fn stats(&self) -> MapStats {
+        let header = self.header();
+        MapStats {
+            capacity: self.capacity(),
+            len: self.len(),
+            tombstones: header.tombstones.load(Ordering::Relaxed) as usize,
+            hits: header.hits.load(Ordering::Relaxed),
+            misses: header.misses.load(Ordering::Relaxed),
+            inserts: header.inserts.load(Ordering::Relaxed),
+            updates: header.updates.load(Ordering::Relaxed),
+            removes: header.removes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl<K, V> Drop for SharedHashMap<K, V> {
+    fn drop(&mut self) {
+        if self.owner
+            && let Err(e) = SharedMemoryRegistry::unlink(&self.name)
+        {
+            tracing::warn!("删除共享哈希表 {} 失败: {}", self.name, e);
+        }
+    }
+}
+
+/// [`SharedHashMap::iter`] 返回的迭代器
+pub struct Iter<'a, K, V> {
+    map: &'a SharedHashMap<K, V>,
+    index: usize,
+}
+
+impl<K: Copy + Eq, V: Copy> Iterator for Iter<'_, K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        while self.index < self.map.capacity() {
+            let bucket = self.map.bucket(self.index);
+            self.index += 1;
+            let _guard = SpinGuard::lock(&bucket.lock);
+            if bucket.state.load(Ordering::Relaxed) == OCCUPIED {
+                return Some(unsafe { (bucket.key(), bucket.value()) });
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_hash_map() {
+        let name = format!("mi7_test_shared_map_{}", std::process::id());
+        let map = SharedHashMap::<u64, [u8; 16]>::create(&name, 12).unwrap();
+        assert_eq!(map.capacity(), 16);
+        assert!(SharedHashMap::<u64, [u8; 8]>::open(&name).is_err());
+        let other = SharedHashMap::<u64, [u8; 16]>::open(&name).unwrap();
+
+        assert_eq!(map.insert(1, [1; 16]).unwrap(), None);
+        assert_eq!(other.get(&1), Some([1; 16]));
+        assert_eq!(other.insert(1, [2; 16]).unwrap(), Some([1; 16]));
+        assert_eq!(map.remove(&1), Some([2; 16]));
+        assert_eq!(other.get(&1), None);
+
+        // 多个线程并发写入相同与不同的键，每个键只出现一次
+        std::thread::scope(|scope| {
+            for t in 0..4u8 {
+                let map = SharedHashMap::<u64, [u8; 16]>::open(&name).unwrap();
+                scope.spawn(move || {
+                    for key in 0..12u64 {
+                        map.insert(key, [t; 16]).unwrap();
+                        if key % 3 == t as u64 % 3 {
+                            map.remove(&key);
+                            map.insert(key, [t; 16]).unwrap();
+                        }
+                    }
+                });
+            }
+        });
+        let mut keys: Vec<_> = other.iter().map(|(key, _)| key).collect();
+        keys.sort();
+        assert_eq!(keys, (0..12).collect::<Vec<_>>());
+        assert_eq!(map.len(), 12);
+
+        // 墓碑被复用；没有可用的桶时插入失败
+        for key in 12..16 {
+            map.insert(key, [0; 16]).unwrap();
+        }
+        let err = map.insert(16, [0; 16]).unwrap_err();
+        assert!(err.is::<MapFull>());
+        assert!(map.remove(&3).is_some());
+        map.insert(16, [0; 16]).unwrap();
+        assert_eq!(map.stats().tombstones, 0);
+
+        map.clear();
+        assert!(other.is_empty());
+        assert_eq!(other.iter().count(), 0);
+        let stats = other.stats();
+        assert!(stats.hits > 0 && stats.removes > 0);
+    }
+}
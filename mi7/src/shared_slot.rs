@@ -1,13 +1,24 @@
 use libc::{
-    EOWNERDEAD, MAP_FAILED, MAP_SHARED, O_CREAT, O_RDWR, PROT_READ, PROT_WRITE,
-    PTHREAD_MUTEX_ROBUST, PTHREAD_PROCESS_SHARED, close, ftruncate, mmap, pthread_mutex_consistent,
-    pthread_mutex_init, pthread_mutex_lock, pthread_mutex_t, pthread_mutex_unlock,
-    pthread_mutexattr_init, pthread_mutexattr_setpshared, pthread_mutexattr_setrobust,
-    pthread_mutexattr_t,
+    MAP_FAILED, MAP_SHARED, O_CREAT, O_RDWR, PROT_READ, PROT_WRITE, close, ftruncate, mmap,
+    timespec,
 };
 
-use anyhow::Result;
+use crate::auth::MessageAuthenticator;
+use crate::codec::{self, CodecKind};
+use crate::encryption::{Encryption, PayloadCipher};
+use crate::error::Result;
+use crate::futex;
+use crate::integrity::Integrity;
+use crate::shm_sync::{self, CachePadded, MutexAttr, ShmCondvar, ShmMutex};
+use crate::snapshot::{QueueSnapshot, SnapshotEntry};
+use crate::wait::{ArrivalStats, Backoff, WaitStrategy};
+use serde::Serialize;
+use std::ops::{Deref, DerefMut};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::ptr::NonNull;
 use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::time::Duration;
 use std::{ffi::CString, mem, ptr};
 
 /// Tokio IPC 错误类型
@@ -17,9 +28,15 @@ pub enum TokioIPCError {
     MmapFailed,
     MutexLockFailed,
     QueueFull,
+    /// 队列中没有可读取的消息
+    QueueEmpty,
     SerializationFailed,
     ChecksumMismatch,
     SlotNotReady,
+    /// 认证标签校验失败：数据被篡改，或读取方的密钥与写入方不同
+    CorruptedData,
+    /// 消息认证码校验失败：消息不是由持有认证密钥的进程写入，或写入后被篡改
+    AuthenticationFailed,
 }
 
 impl std::fmt::Display for TokioIPCError {
@@ -31,17 +48,40 @@ impl std::fmt::Display for TokioIPCError {
             TokioIPCError::MmapFailed => write!(f, "Memory mapping failed"),
             TokioIPCError::MutexLockFailed => write!(f, "Mutex lock failed"),
             TokioIPCError::QueueFull => write!(f, "Queue is full"),
+            TokioIPCError::QueueEmpty => write!(f, "Queue is empty"),
             TokioIPCError::SerializationFailed => write!(f, "Serialization failed"),
             TokioIPCError::ChecksumMismatch => write!(f, "Checksum mismatch"),
             TokioIPCError::SlotNotReady => write!(f, "Slot is not ready"),
+            TokioIPCError::CorruptedData => {
+                write!(f, "Corrupted data: authentication failed")
+            }
+            TokioIPCError::AuthenticationFailed => write!(f, "Message authentication failed"),
         }
     }
 }
 
 impl std::error::Error for TokioIPCError {}
 
+/// 槽位状态
+///
+/// 状态只由管道自身切换，调用者只能通过 hold / fetch / write / read 等接口推动：
+///
+/// ```text
+///            hold                 write 开始 (CAS)            write 完成
+/// EMPTY ───────────▶ WRITING ─────────────────▶ INPROGRESS ─────────────▶ READY
+///   ▲                                                                       │
+///   │ read 完成 / ack          read 开始 (CAS)                  fetch       │
+///   └──────────────── INPROGRESS ◀───────────────── READING ◀───────────────┘
+/// ```
+///
+/// - 抢占（EMPTY → WRITING、READY → READING）在加锁模式下由读写锁保护，在无锁模式下由
+///   推进 enqueue_pos / dequeue_pos 的 CAS 保证唯一；抢占前先记录租约，再以 Release 写入状态。
+/// - 开始读写时以 CAS 把抢占时的状态切换为 INPROGRESS 并续约，槽位已被 `reclaim_stuck`
+///   回收或从未被调用者抢占时 CAS 失败，读写返回错误而不会触碰槽位数据。
+/// - 写者先写数据与槽位元数据，再以 Release 写入 READY（无锁模式再以 Release 推进序列号）；
+///   读者以 Acquire 读取状态 / 序列号后才读数据，因此一定看到完整的消息。
 #[repr(u32)]
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
 pub enum SlotState {
     EMPTY = 0,
     WRITING = 1,
@@ -50,379 +90,3763 @@ pub enum SlotState {
     READY = 4,
 }
 
+/// 管道并发模式，在创建时选定并记录在共享内存头部
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+pub enum PipeMode {
+    /// 读写各使用一把进程间互斥锁保护指针与槽位扫描
+    Locked = 0,
+    /// 无锁 MPMC 模式：每个槽位带序列号（Vyukov 有界队列），生产者/消费者通过 CAS 推进位置
+    LockFree = 1,
+}
+
+impl PipeMode {
+    pub(crate) fn from_u32(value: u32) -> Self {
+        match value {
+            1 => PipeMode::LockFree,
+            _ => PipeMode::Locked,
+        }
+    }
+}
+
+/// 队列满时写者的处理方式，记录在共享内存头部，所有连接方共享
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum FullPolicy {
+    /// 等待读者释放槽位直到超时
+    #[default]
+    Block = 0,
+    /// 不等待，立即返回队列已满
+    Reject = 1,
+    /// 丢弃最早的 READY 消息腾出槽位（遥测等宁可丢旧数据也不阻塞写者的场景），
+    /// 所有槽位都被读写者持有时仍然等待
+    OverwriteOldest = 2,
+}
+
+impl FullPolicy {
+    pub(crate) fn from_u32(value: u32) -> Self {
+        match value {
+            1 => FullPolicy::Reject,
+            2 => FullPolicy::OverwriteOldest,
+            _ => FullPolicy::Block,
+        }
+    }
+}
+
+/// 管道共享内存的标识，初始化完成后最后写入
+pub const PIPE_MAGIC: u64 = u64::from_le_bytes(*b"MI7PIPE\0");
+
+/// 管道共享内存的布局版本，结构体字段变化时递增
+pub const PIPE_LAYOUT_VERSION: u32 = 22;
+
+/// 与当前布局互相兼容的最低布局版本
+///
+/// 只在 [`PipeHeader::reserved`] 中增加字段的变更递增 [`PIPE_LAYOUT_VERSION`] 但保持本值不变，
+/// 该范围内的新旧程序可以连接同一管道，旧布局缺少的字段由 [`LAYOUT_MIGRATIONS`] 补齐；
+/// 移动字段或改变槽位布局的变更必须把本值提升到新版本。
+pub const PIPE_COMPAT_VERSION: u32 = 22;
+
+/// 头部预留区域的大小（`u64` 个数）
+pub const HEADER_RESERVED_WORDS: usize = 16;
+
+/// 写入间隔滑动平均的平滑系数：每个新间隔占 1/8 的权重
+const ARRIVAL_SMOOTHING: u64 = 8;
+
+/// 位于共享内存最前面的布局描述，连接方据此校验编译期参数是否一致
+///
+/// `magic`、`version`、`capacity` 与 `slot_size` 的偏移在所有布局版本中保持不变。
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LayoutHeader {
+    pub magic: u64,
+    pub version: u32,
+    pub capacity: u32,
+    pub slot_size: u64,
+    /// 创建者的 [`PIPE_COMPAT_VERSION`]，布局版本不低于该值的程序可以连接
+    pub compat_version: u32,
+    pub reserved: u32,
+}
+
+/// 只修改头部的布局迁移，连接时把 `from` 版本创建的管道原地升级
+///
+/// 迁移只能初始化 [`PipeHeader::reserved`] 中新增的字段，必须是幂等的：多个进程可能同时连接
+/// 并执行同一迁移。
+pub struct LayoutMigration {
+    pub from: u32,
+    pub migrate: fn(&PipeHeader),
+}
+
+/// 已知的头部迁移，按 `from` 升序排列
+pub const LAYOUT_MIGRATIONS: &[LayoutMigration] = &[];
+
+/// 协商连接方与共享内存的布局版本，需要迁移时返回共享内存的布局版本
+fn negotiate(layout: &LayoutHeader) -> Result<Option<u32>, LayoutMismatch> {
+    let found = layout.version;
+    if found == PIPE_LAYOUT_VERSION {
+        Ok(None)
+    } else if found > PIPE_LAYOUT_VERSION {
+        // 更新的程序创建：只要其声明兼容当前版本即可连接，新增字段对当前程序不可见
+        if layout.compat_version <= PIPE_LAYOUT_VERSION {
+            Ok(None)
+        } else {
+            Err(LayoutMismatch::Newer {
+                found,
+                compat: layout.compat_version,
+                supported: PIPE_LAYOUT_VERSION,
+            })
+        }
+    } else if found >= PIPE_COMPAT_VERSION {
+        Ok(Some(found))
+    } else {
+        Err(LayoutMismatch::Older {
+            found,
+            compat: PIPE_COMPAT_VERSION,
+        })
+    }
+}
+
+/// 依次执行 `found` 版本之后的迁移
+fn apply_migrations(header: &PipeHeader, found: u32, migrations: &[LayoutMigration]) {
+    for migration in migrations.iter().filter(|m| m.from >= found) {
+        (migration.migrate)(header);
+    }
+}
+
+/// 带代数的槽位句柄，由 [`DynSharedSlotPipe::handle`] 在抢占槽位后取得
+///
+/// 槽位被回收并再次抢占后代数改变，持有旧句柄的一方不会误读、误写已复用的槽位。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SlotHandle {
+    pub index: usize,
+    pub generation: u64,
+}
+
+/// 句柄指向的槽位已被回收或复用
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("槽位 {index} 的句柄已失效（句柄代数 {generation}，当前代数 {current}）")]
+pub struct StaleHandle {
+    pub index: usize,
+    pub generation: u64,
+    pub current: u64,
+}
+
+/// 序列化后的数据超过槽位大小
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("数据 {size} 字节超过槽位大小 {capacity} 字节")]
+pub struct PayloadTooLarge {
+    pub size: usize,
+    pub capacity: usize,
+}
+
+/// 连接的共享内存与期望布局不一致
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum LayoutMismatch {
+    #[error("共享内存未初始化或不是管道 (magic={found:#x})")]
+    Magic { found: u64 },
+    #[error(
+        "管道由更新的程序创建（布局版本 {found}，要求不低于 {compat}），当前程序的布局版本为 {supported}，请升级当前程序"
+    )]
+    Newer {
+        found: u32,
+        compat: u32,
+        supported: u32,
+    },
+    #[error(
+        "管道由旧版本程序创建（布局版本 {found}），当前程序只兼容 {compat} 及以上，请停止旧进程后重新创建管道"
+    )]
+    Older { found: u32, compat: u32 },
+    #[error("容量不匹配：期望 {expected}，实际 {found}")]
+    Capacity { expected: usize, found: usize },
+    #[error("槽位大小不匹配：期望 {expected}，实际 {found}")]
+    SlotSize { expected: usize, found: usize },
+    #[error("共享内存大小不匹配：期望 {expected} 字节，实际 {found} 字节")]
+    Size { expected: usize, found: usize },
+}
+
+/// 读取共享内存段的布局描述（不映射整个段）
+fn read_layout_fd(fd: libc::c_int) -> Result<(LayoutHeader, usize)> {
+    let mut stat: libc::stat = unsafe { mem::zeroed() };
+    if unsafe { libc::fstat(fd, &mut stat) } == -1 {
+        return Err(anyhow::anyhow!("fstat failed with errno: {}", shm_sync::errno()).into());
+    }
+    let file_size = stat.st_size as usize;
+
+    let mut header = mem::MaybeUninit::<LayoutHeader>::zeroed();
+    let len = mem::size_of::<LayoutHeader>();
+    if file_size < len {
+        return Err(LayoutMismatch::Magic { found: 0 }.into());
+    }
+    let n = unsafe { libc::pread(fd, header.as_mut_ptr() as *mut libc::c_void, len, 0) };
+    if n != len as isize {
+        return Err(anyhow::anyhow!("pread failed with errno: {}", shm_sync::errno()).into());
+    }
+    Ok((unsafe { header.assume_init() }, file_size))
+}
+
+/// 加锁并统计锁竞争：锁已被占用时计入 `contended` 后再阻塞等待
+///
+/// `deadline`（单调时钟）为 `None` 时无限等待，超时返回 `false`
+///
+/// # Safety
+/// 同 [`ShmMutex::lock`]。
+unsafe fn lock_counted(
+    mutex: &mut ShmMutex,
+    contended: &AtomicU64,
+    deadline: Option<&timespec>,
+) -> bool {
+    if unsafe { mutex.try_lock() } {
+        return true;
+    }
+    contended.fetch_add(1, Ordering::Relaxed);
+    match deadline {
+        Some(deadline) => unsafe { mutex.lock_timeout(shm_sync::time_until(deadline)) },
+        None => unsafe { mutex.lock() },
+    }
+}
+
+/// `shm_open` 失败的错误，保留 `io::Error` 以便按 `NotFound` / `PermissionDenied` 区分
+fn shm_open_error() -> anyhow::Error {
+    let errno = shm_sync::errno();
+    anyhow::Error::from(std::io::Error::from_raw_os_error(errno))
+        .context(format!("shm_open failed with errno: {}", errno))
+}
+
+/// 读取指定管道共享内存的布局描述，用于在连接前确定容量与槽位大小
+pub fn read_layout(name: &str) -> Result<LayoutHeader> {
+    let cname = CString::new(format!("/{}", name.trim_start_matches('/')))
+        .map_err(|_| anyhow::anyhow!("Failed to create CString from name"))?;
+    let fd = unsafe { libc::shm_open(cname.as_ptr(), libc::O_RDONLY, 0) };
+    if fd == -1 {
+        return Err(shm_open_error().into());
+    }
+    let result = read_layout_fd(fd);
+    unsafe { close(fd) };
+
+    let (header, _) = result?;
+    if header.magic != PIPE_MAGIC {
+        return Err(LayoutMismatch::Magic {
+            found: header.magic,
+        }
+        .into());
+    }
+    Ok(header)
+}
+
+/// 头部 PID 表的容量，即同时连接同一管道的句柄上限
+pub const MAX_ATTACHED: usize = 64;
+
+/// 公平模式下的票据窗口，同时排队等待槽位的写者不能超过该数量
+pub const FAIR_WINDOW: usize = 256;
+
+/// 延迟直方图的桶数：第 i 个桶统计 [2^i, 2^(i+1)) 微秒的延迟，第 0 个桶包含 1 微秒以下
+pub const LATENCY_BUCKETS: usize = 32;
+
+/// 默认的最大投递失败次数
+pub const DEFAULT_MAX_DELIVERY_ATTEMPTS: u32 = 3;
+
+/// 无锁模式下重新投递等待空槽位的时长
+const REQUEUE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// 排队者检查叫号持有者是否存活的间隔
+const FAIR_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 槽位租约由写者持有（WRITING / 写入前的 INPROGRESS）
+const LEASE_WRITER: u32 = 1;
+/// 槽位租约由读者持有（READING / 读取前的 INPROGRESS）
+const LEASE_READER: u32 = 2;
+/// 租约字中持有方角色所在的低位，其余位为持有进程号
+const LEASE_ROLE_MASK: u32 = 0b11;
+
+/// 当前进程以 `role` 持有槽位时的租约字
+fn lease_owner(role: u32) -> u32 {
+    role | std::process::id() << 2
+}
+
+/// 无锁模式下写入失败被放弃的槽位在 `data_size` 中置位的标志
+///
+/// 槽位大小远小于 2^31，置位后的长度必然超过槽位大小，恢复与快照会跳过这样的槽位。
+const ABANDONED_SLOT: u32 = 1 << 31;
+
+/// 槽位元数据，位于每个槽位数据区之前
+///
+/// 元数据恰好占满一个缓存行，槽位按缓存行对齐：抢占槽位时的状态 CAS 不会与相邻槽位
+/// 或本槽位数据区的读写落在同一缓存行。
+#[repr(C, align(64))]
+pub struct SlotHeader {
+    pub state: AtomicU32,       // 简化的原子状态
+    pub lease_role: AtomicU32,  // 租约持有方：低 2 位为写者 / 读者，其余位为进程号
+    pub sequence: AtomicU64,    // 无锁模式下的槽位序列号；加锁模式下为槽位代数，每次被抢占时递增
+    pub leased_at: AtomicU64,   // 被抢占的时间（单调时钟毫秒），0 表示未被持有
+    pub request_id: u64,        // 请求ID
+    pub checksum: u64,          // 数据校验和
+    pub enqueued_at: u64,       // 写入完成的时间（单调时钟纳秒）
+    pub data_size: u32,         // 实际数据大小
+    pub delivery_attempts: u32, // 已失败（nack）的投递次数
+    pub mac: u64,               // 消息认证码（HMAC-SHA256 前 64 位），未启用认证时为 0
+}
+
+impl SlotHeader {
+    /// 以当前进程记录槽位被抢占的时间与持有方
+    fn lease(&self, role: u32) {
+        self.lease_role.store(lease_owner(role), Ordering::Relaxed);
+        self.leased_at
+            .store(shm_sync::monotonic_millis().max(1), Ordering::Release);
+    }
+
+    /// 加锁模式：递增槽位代数后记录租约，此前发出的句柄随之失效
+    fn lease_next(&self, role: u32) {
+        self.sequence.fetch_add(1, Ordering::Relaxed);
+        self.lease(role);
+    }
+
+    /// 租约持有方的角色，没有租约时为 0
+    fn lease_role(&self) -> u32 {
+        self.lease_role.load(Ordering::Relaxed) & LEASE_ROLE_MASK
+    }
+
+    /// 是否为无锁模式下写入失败被放弃的槽位
+    pub fn is_abandoned(&self) -> bool {
+        self.data_size & ABANDONED_SLOT != 0
+    }
+
+    /// 去掉放弃标志后的数据大小
+    pub fn payload_size(&self) -> usize {
+        (self.data_size & !ABANDONED_SLOT) as usize
+    }
+
+    /// 槽位回到 READY / EMPTY 时清除租约
+    fn clear_lease(&self) {
+        self.leased_at.store(0, Ordering::Release);
+        self.lease_role.store(0, Ordering::Relaxed);
+    }
+}
+
+const _: () = assert!(mem::size_of::<SlotHeader>() == shm_sync::CACHE_LINE);
+
 #[repr(C)]
 pub struct Slot<const SLOT_SIZE: usize> {
-    pub state: AtomicU32, // 简化的原子状态
-    pub request_id: u64,  // 请求ID
-    pub data_size: u32,   // 实际数据大小
-    pub checksum: u64,    // 数据校验和
+    pub header: SlotHeader,
     pub data: [u8; SLOT_SIZE],
 }
 
+impl<const SLOT_SIZE: usize> Deref for Slot<SLOT_SIZE> {
+    type Target = SlotHeader;
+
+    fn deref(&self) -> &SlotHeader {
+        &self.header
+    }
+}
+
+impl<const SLOT_SIZE: usize> DerefMut for Slot<SLOT_SIZE> {
+    fn deref_mut(&mut self) -> &mut SlotHeader {
+        &mut self.header
+    }
+}
+
+/// 创建时写入头部的安全设置
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PipeSecurity {
+    /// 负载加密算法
+    pub encryption: Encryption,
+    /// 负载密钥标识，0 表示未加密
+    pub key_id: u64,
+    /// 连接前是否需要经创建者握手授权，见 [`crate::access`]
+    pub handshake: bool,
+    /// 消息认证密钥标识，0 表示不认证
+    pub auth_key_id: u64,
+}
+
+impl PipeSecurity {
+    pub fn new(
+        cipher: Option<&PayloadCipher>,
+        authenticator: Option<&MessageAuthenticator>,
+        handshake: bool,
+    ) -> Self {
+        let (encryption, key_id) =
+            cipher.map_or((Encryption::None, 0), |c| (c.algorithm(), c.key_id()));
+        Self {
+            encryption,
+            key_id,
+            handshake,
+            auth_key_id: authenticator.map_or(0, |a| a.key_id()),
+        }
+    }
+
+    /// 是否需要连接方理解当前布局（旧程序会把密文当作明文读取，或跳过握手与认证）
+    fn requires_current_layout(&self) -> bool {
+        self.encryption != Encryption::None || self.handshake || self.auth_key_id != 0
+    }
+}
+
+/// 管道头部：槽位数组之前的全部字段，大小与容量、槽位大小无关
+///
+/// 写者与读者各自频繁修改的字段（互斥锁、生产/消费位置、futex 字、计数）各占一个缓存行，
+/// 避免跨核心的伪共享。
+#[repr(C)]
+pub struct PipeHeader {
+    pub layout: LayoutHeader,                        // 布局描述，必须位于最前面
+    pub write_mutex: CachePadded<ShmMutex>,          // 保护写操作
+    pub read_mutex: CachePadded<ShmMutex>,           // 保护读操作
+    pub ready_cond: ShmCondvar,                      // 有 READY 槽位时唤醒读者（配合 read_mutex）
+    pub empty_cond: ShmCondvar,                      // 有 EMPTY 槽位时唤醒写者（配合 write_mutex）
+    pub write_pointer: usize,                        // 可写的索引
+    pub read_pointer: usize,                         // 可读的索引
+    pub mode: u32,                                   // PipeMode，创建时写入
+    pub codec: u32,                                  // CodecKind，创建时写入
+    pub integrity: u32,                              // Integrity，创建时写入
+    pub mutex_attr: u32,                             // 读写互斥锁的 MutexAttr，创建时写入
+    pub enqueue_pos: CachePadded<AtomicU64>,         // 无锁模式的生产位置
+    pub dequeue_pos: CachePadded<AtomicU64>,         // 无锁模式的消费位置
+    pub ready_waiters: AtomicU32,                    // 在 ready_cond 上等待的读者数量
+    pub empty_waiters: AtomicU32,                    // 在 empty_cond 上等待的写者数量
+    pub attached_count: AtomicU32,                   // 当前连接的句柄数量
+    pub attached_pids: [AtomicU32; MAX_ATTACHED],    // 连接者 PID 表（0 表示空位）
+    pub seq: CachePadded<AtomicU64>,                 // request_id 生成器
+    pub begin: AtomicBool,                           // "有数据"信号（原子变量，线程安全）
+    pub shared_value: CachePadded<AtomicU32>,        // futex 字：每发布一个 READY 槽位递增
+    pub empty_value: CachePadded<AtomicU32>,         // futex 字：每释放一个槽位递增
+    pub reclaimed_count: AtomicU64,                  // 因租约超时被回收的槽位累计数量
+    pub high_watermark: AtomicU32,                   // 背压高水位（非 EMPTY 槽位数，0 表示关闭）
+    pub low_watermark: AtomicU32,                    // 背压低水位，占用降到该值时解除
+    pub backpressured: AtomicBool,                   // 当前是否处于背压
+    pub fair: AtomicU32,                             // 是否按到达顺序分配空槽位
+    pub next_ticket: AtomicU32,                      // 公平模式：下一张票号
+    pub now_serving: AtomicU32,                      // 公平模式：当前叫号（futex 字）
+    pub serving_pid: AtomicU32,                      // 公平模式：当前叫号持有者的 PID
+    pub cancelled_tickets: [AtomicU64; FAIR_WINDOW], // 公平模式：超时放弃的票号 + 1
+    pub expired_count: AtomicU64,                    // 接收时因过期被丢弃的消息累计数量
+    pub max_delivery_attempts: AtomicU32,            // 投递失败达到该次数后转入死信（0 表示不限）
+    pub redelivered_count: AtomicU64,                // 重新投递的消息累计数量
+    pub dead_lettered_count: AtomicU64,              // 转入死信的消息累计数量
+    pub sent_count: CachePadded<AtomicU64>,          // 写入的消息累计数量
+    pub received_count: CachePadded<AtomicU64>,      // 读取并释放的消息累计数量
+    pub latency_buckets: [AtomicU64; LATENCY_BUCKETS], // 写入到读取的延迟直方图
+    pub latency_sum_nanos: AtomicU64,                // 直方图中所有延迟之和（纳秒）
+    pub lock_contended_count: AtomicU64,             // 加锁时锁已被占用的累计次数
+    pub key_id: u64,                                 // 负载密钥标识（0 表示未加密），创建时写入
+    pub encryption: u32,                             // Encryption，创建时写入
+    pub access_control: u32,                         // 连接前是否需要握手授权，创建时写入
+    pub auth_key_id: u64,                            // 消息认证密钥标识（0 表示不认证），创建时写入
+    pub space_waiters: AtomicU64,                    // 在空槽位 FIFO 上异步等待的写者数量
+    pub committed_offset: AtomicU64,                 // 读者已提交的最大 request_id
+    pub full_policy: AtomicU32,                      // FullPolicy，所有连接方共享
+    pub full_policy_reserved: u32,                   // 对齐到 8 字节，保持为 0
+    pub dropped_count: AtomicU64,                    // 覆盖模式下被丢弃的最早消息累计数量
+    pub last_arrival_nanos: AtomicU64,               // 最近一次写入消息的单调时间（纳秒）
+    pub arrival_gap_nanos: AtomicU64,                // 相邻写入间隔的滑动平均（纳秒），0 表示未统计
+    pub reserved: [AtomicU64; HEADER_RESERVED_WORDS - 9], // 预留给只修改头部的布局变更，创建时为 0
+}
+
+/// 编译期确定容量与槽位大小的管道布局
+///
+/// 内存布局与 [`DynSharedSlotPipe`] 完全一致，两者可以连接同一个共享内存段；
+/// 所有操作都委托给 [`SharedSlotPipe::as_dyn`] 返回的运行时视图。
 #[repr(C)]
 pub struct SharedSlotPipe<const N: usize, const SLOT_SIZE: usize> {
-    pub write_mutex: pthread_mutex_t, // 保护写操作
-    pub read_mutex: pthread_mutex_t,  // 保护读操作
-    pub write_pointer: usize,         // 可写的索引
-    pub read_pointer: usize,          // 可读的索引
+    pub header: PipeHeader,
     pub slots: [Slot<SLOT_SIZE>; N],
-    pub seq: AtomicU64,          // request_id 生成器
-    pub begin: AtomicBool,       // "有数据"信号（原子变量，线程安全）
-    pub shared_value: AtomicU32, // AsyncFutex 使用
 }
 
-unsafe impl<const N: usize, const SLOT_SIZE: usize> Send for SharedSlotPipe<N, SLOT_SIZE> {}
+unsafe impl<const N: usize, const SLOT_SIZE: usize> Send for SharedSlotPipe<N, SLOT_SIZE> {}
+
+unsafe impl<const N: usize, const SLOT_SIZE: usize> Sync for SharedSlotPipe<N, SLOT_SIZE> {}
+
+impl<const N: usize, const SLOT_SIZE: usize> Deref for SharedSlotPipe<N, SLOT_SIZE> {
+    type Target = PipeHeader;
+
+    fn deref(&self) -> &PipeHeader {
+        &self.header
+    }
+}
+
+impl<const N: usize, const SLOT_SIZE: usize> DerefMut for SharedSlotPipe<N, SLOT_SIZE> {
+    fn deref_mut(&mut self) -> &mut PipeHeader {
+        &mut self.header
+    }
+}
+
+impl<const N: usize, const SLOT_SIZE: usize> SharedSlotPipe<N, SLOT_SIZE> {
+    /// 打开或创建共享内存
+    ///
+    /// # Safety
+    /// 返回的指针指向共享内存映射，调用者需保证在使用期间不解除映射。
+    pub unsafe fn open(name: &str, create: bool) -> Result<*mut Self> {
+        unsafe {
+            Self::open_with(
+                name,
+                create,
+                PipeMode::Locked,
+                CodecKind::Bincode,
+                Integrity::default(),
+            )
+        }
+    }
+
+    /// 打开或创建共享内存，创建时使用指定的并发模式、编解码方式与校验算法
+    ///
+    /// 连接已有管道时忽略 `mode`、`codec` 与 `integrity`，以创建者写入头部的为准；
+    /// 头部记录的容量或槽位大小与 `N`、`SLOT_SIZE` 不一致时返回 [`LayoutMismatch`]。
+    ///
+    /// # Safety
+    /// 同 [`SharedSlotPipe::open`]。
+    pub unsafe fn open_with(
+        name: &str,
+        create: bool,
+        mode: PipeMode,
+        codec: CodecKind,
+        integrity: Integrity,
+    ) -> Result<*mut Self> {
+        let pipe = unsafe {
+            if create {
+                DynSharedSlotPipe::create(
+                    name,
+                    N,
+                    SLOT_SIZE,
+                    mode,
+                    codec,
+                    integrity,
+                    MutexAttr::default(),
+                )?
+            } else {
+                DynSharedSlotPipe::connect_with_layout(name, N, SLOT_SIZE)?
+            }
+        };
+        Ok(pipe.as_ptr() as *mut Self)
+    }
+
+    /// 以运行时视图访问本管道
+    pub fn as_dyn(&mut self) -> DynSharedSlotPipe {
+        unsafe { DynSharedSlotPipe::from_raw(NonNull::from(self).cast(), N, SLOT_SIZE) }
+    }
+
+    /// 获取管道的并发模式
+    pub fn mode(&self) -> PipeMode {
+        PipeMode::from_u32(self.header.mode)
+    }
+
+    /// 获取管道的编解码方式
+    pub fn codec(&self) -> CodecKind {
+        CodecKind::from_u32(self.header.codec)
+    }
+
+    /// 获取管道的校验算法
+    pub fn integrity(&self) -> Integrity {
+        Integrity::from_u32(self.header.integrity)
+    }
+
+    /// 基于 futex 等待出现 READY 槽位，不抢占槽位
+    pub fn wait_for_ready(&mut self, timeout: Option<Duration>) -> bool {
+        self.as_dyn().wait_for_ready(timeout)
+    }
+
+    /// 基于 futex 等待出现空槽位，不抢占槽位
+    pub fn wait_for_empty(&mut self, timeout: Option<Duration>) -> bool {
+        self.as_dyn().wait_for_empty(timeout)
+    }
+
+    /// 非阻塞抢占slot，如果队列满立即返回错误
+    ///
+    /// # Safety
+    /// `self` 必须指向由 [`SharedSlotPipe::open`] 映射并初始化过的共享内存。
+    pub unsafe fn hold(&mut self) -> Option<usize> {
+        unsafe { self.as_dyn().hold() }
+    }
+
+    /// 阻塞抢占slot，队列满时在条件变量上休眠直到有槽位被释放
+    ///
+    /// # Safety
+    /// `self` 必须指向由 [`SharedSlotPipe::open`] 映射并初始化过的共享内存。
+    pub unsafe fn hold_timeout(&mut self, timeout: Option<Duration>) -> Option<usize> {
+        unsafe { self.as_dyn().hold_timeout(timeout) }
+    }
+
+    /// 向指定索引的槽位写入数据
+    ///
+    /// # Safety
+    /// `self` 必须指向由 [`SharedSlotPipe::open`] 映射并初始化过的共享内存。
+    pub unsafe fn write<T: bincode::Encode>(&mut self, index: usize, data: &T) -> Result<u64> {
+        unsafe { self.as_dyn().write(index, data) }
+    }
+
+    /// 由调用者直接填充槽位内存（零拷贝写入）
+    ///
+    /// # Safety
+    /// `self` 必须指向由 [`SharedSlotPipe::open`] 映射并初始化过的共享内存。
+    pub unsafe fn write_with<F>(&mut self, index: usize, fill: F) -> Result<u64>
+    where
+        F: FnOnce(&mut [u8]) -> usize,
+    {
+        unsafe { self.as_dyn().write_with(index, fill) }
+    }
+
+    /// 由可能失败的闭包填充槽位并发布为 READY
+    ///
+    /// # Safety
+    /// `self` 必须指向由 [`SharedSlotPipe::open`] 映射并初始化过的共享内存。
+    pub unsafe fn try_write_with<F>(&mut self, index: usize, fill: F) -> Result<u64>
+    where
+        F: FnOnce(&mut [u8]) -> Result<usize>,
+    {
+        unsafe { self.as_dyn().try_write_with(index, fill) }
+    }
+
+    /// 与 [`SharedSlotPipe::try_write_with`] 相同，但由调用者指定槽位的 request_id
+    ///
+    /// # Safety
+    /// `self` 必须指向由 [`SharedSlotPipe::open`] 映射并初始化过的共享内存。
+    pub unsafe fn try_write_tagged<F>(
+        &mut self,
+        index: usize,
+        request_id: Option<u64>,
+        fill: F,
+    ) -> Result<u64>
+    where
+        F: FnOnce(&mut [u8]) -> Result<usize>,
+    {
+        unsafe { self.as_dyn().try_write_tagged(index, request_id, fill) }
+    }
+
+    /// 获取READY的 slot, 返回index
+    ///
+    /// # Safety
+    /// `self` 必须指向由 [`SharedSlotPipe::open`] 映射并初始化过的共享内存。
+    pub unsafe fn fetch(&mut self) -> Option<usize> {
+        unsafe { self.as_dyn().fetch() }
+    }
+
+    /// 获取READY的 slot，最多等待 `timeout`
+    ///
+    /// # Safety
+    /// `self` 必须指向由 [`SharedSlotPipe::open`] 映射并初始化过的共享内存。
+    pub unsafe fn fetch_timeout(&mut self, timeout: Option<Duration>) -> Option<usize> {
+        unsafe { self.as_dyn().fetch_timeout(timeout) }
+    }
+
+    ///  获取 slot 的 data
+    /// 并释放 slot 为 EMPTY
+    ///
+    /// # Safety
+    /// `self` 必须指向由 [`SharedSlotPipe::open`] 映射并初始化过的共享内存。
+    pub unsafe fn read<T: bincode::Decode<()>>(
+        &mut self,
+        index: usize,
+    ) -> Result<Option<(u64, T)>> {
+        unsafe { self.as_dyn().read(index) }
+    }
+
+    /// 直接访问槽位内存读取数据（零拷贝读取）
+    ///
+    /// # Safety
+    /// `self` 必须指向由 [`SharedSlotPipe::open`] 映射并初始化过的共享内存。
+    pub unsafe fn read_with<F, R>(&mut self, index: usize, visit: F) -> Result<(u64, R)>
+    where
+        F: FnOnce(&[u8]) -> R,
+    {
+        unsafe { self.as_dyn().read_with(index, visit) }
+    }
+
+    /// 校验槽位数据、交给可能失败的闭包处理并释放槽位
+    ///
+    /// # Safety
+    /// `self` 必须指向由 [`SharedSlotPipe::open`] 映射并初始化过的共享内存。
+    pub unsafe fn try_read_with<F, R>(&mut self, index: usize, visit: F) -> Result<(u64, R)>
+    where
+        F: FnOnce(&[u8]) -> Result<R>,
+    {
+        unsafe { self.as_dyn().try_read_with(index, visit) }
+    }
+
+    /// 回收租约超过 `timeout` 仍未完成的槽位，返回回收数量
+    ///
+    /// # Safety
+    /// `self` 必须指向由 [`SharedSlotPipe::open`] 映射并初始化过的共享内存。
+    pub unsafe fn reclaim_stuck(&mut self, timeout: Duration) -> usize {
+        unsafe { self.as_dyn().reclaim_stuck(timeout) }
+    }
+
+    /// 因租约超时被回收的槽位累计数量
+    pub fn reclaimed_count(&self) -> u64 {
+        self.header.reclaimed_count.load(Ordering::Relaxed)
+    }
+
+    /// 查找第一个 EMPTY 状态的槽位索引
+    ///
+    /// # Safety
+    /// `self` 必须指向由 [`SharedSlotPipe::open`] 映射并初始化过的共享内存。
+    pub unsafe fn next_empty(&mut self, current_index: usize) -> Option<usize> {
+        unsafe { self.as_dyn().next_empty(current_index) }
+    }
+
+    /// 查找第一个 READY 状态的槽位索引
+    ///
+    /// # Safety
+    /// `self` 必须指向由 [`SharedSlotPipe::open`] 映射并初始化过的共享内存。
+    pub unsafe fn next_ready(&mut self, current_index: usize) -> Option<usize> {
+        unsafe { self.as_dyn().next_ready(current_index) }
+    }
+
+    /// 查找第一个指定状态的槽位索引
+    ///
+    /// # Safety
+    /// `self` 必须指向由 [`SharedSlotPipe::open`] 映射并初始化过的共享内存。
+    pub unsafe fn next_slot_by_state(
+        &mut self,
+        current_index: usize,
+        target_state: SlotState,
+    ) -> Option<usize> {
+        unsafe {
+            self.as_dyn()
+                .next_slot_by_state(current_index, target_state)
+        }
+    }
+
+    /// 获取队列容量
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// 登记当前进程的一个连接，返回 PID 表中的位置
+    pub fn attach(&mut self) -> Option<usize> {
+        self.as_dyn().attach()
+    }
+
+    /// 注销由 [`SharedSlotPipe::attach`] 登记的连接
+    pub fn detach(&mut self, index: usize) {
+        self.as_dyn().detach(index)
+    }
+
+    /// 清理已退出（崩溃）进程留下的连接记录，返回清理数量
+    pub fn prune_attached(&mut self) -> usize {
+        self.as_dyn().prune_attached()
+    }
+
+    /// 当前连接的进程 PID 列表（去重，已清理崩溃的连接者）
+    pub fn attached_processes(&mut self) -> Vec<u32> {
+        self.as_dyn().attached_processes()
+    }
+
+    /// 当前连接的句柄数量（已清理崩溃的连接者）
+    pub fn attached_count(&mut self) -> usize {
+        self.as_dyn().attached_count()
+    }
+
+    /// 获取指定索引槽位的状态
+    ///
+    /// # Safety
+    /// `self` 必须指向由 [`SharedSlotPipe::open`] 映射并初始化过的共享内存。
+    pub unsafe fn get_slot_state(&mut self, index: usize) -> Result<SlotState> {
+        unsafe { self.as_dyn().get_slot_state(index) }
+    }
+}
+
+/// 运行时确定容量与槽位大小的管道视图
+///
+/// 容量与槽位大小在连接时从共享内存头部读取，槽位地址按偏移计算，
+/// 因此任意 `(capacity, slot_size)` 组合都不需要单独实例化泛型。
+/// 视图本身不持有映射，由创建者负责在不再使用时调用 [`DynSharedSlotPipe::unmap`]，
+/// 或交给 [`SlotMapping`] 在 Drop 时解除。
+#[derive(Debug, Clone, Copy)]
+pub struct DynSharedSlotPipe {
+    header: NonNull<PipeHeader>,
+    capacity: usize,
+    slot_size: usize,
+    stride: usize,
+    authenticator: Option<&'static MessageAuthenticator>,
+    wait: WaitStrategy,
+}
+
+unsafe impl Send for DynSharedSlotPipe {}
+
+unsafe impl Sync for DynSharedSlotPipe {}
+
+impl DynSharedSlotPipe {
+    /// 单个槽位（元数据 + 数据区）占用的字节数
+    pub fn slot_stride(slot_size: usize) -> usize {
+        let align = mem::align_of::<SlotHeader>();
+        (mem::size_of::<SlotHeader>() + slot_size).div_ceil(align) * align
+    }
+
+    /// 指定容量与槽位大小的管道占用的共享内存字节数
+    pub fn mapped_size(capacity: usize, slot_size: usize) -> usize {
+        mem::size_of::<PipeHeader>() + capacity * Self::slot_stride(slot_size)
+    }
+
+    /// 由已映射的共享内存构造视图
+    ///
+    /// # Safety
+    /// `header` 必须指向至少 [`DynSharedSlotPipe::mapped_size`] 字节、按该布局初始化的映射。
+    pub unsafe fn from_raw(header: NonNull<PipeHeader>, capacity: usize, slot_size: usize) -> Self {
+        Self {
+            header,
+            capacity,
+            slot_size,
+            stride: Self::slot_stride(slot_size),
+            authenticator: None,
+            wait: crate::wait::installed(),
+        }
+    }
+
+    /// 使用 `authenticator` 为写入的消息计算认证码、校验读取的消息
+    ///
+    /// 头部要求认证（[`DynSharedSlotPipe::auth_key_id`] 非 0）时，没有认证器的视图读取任何消息
+    /// 都返回 [`TokioIPCError::AuthenticationFailed`]。
+    pub fn with_authenticator(
+        mut self,
+        authenticator: Option<&'static MessageAuthenticator>,
+    ) -> Self {
+        self.authenticator = authenticator;
+        self
+    }
+
+    /// 队列空或满时按 `wait` 等待，默认为本进程安装的等待方式，见 [`crate::wait`]
+    pub fn with_wait_strategy(mut self, wait: WaitStrategy) -> Self {
+        self.wait = wait;
+        self
+    }
+
+    /// 本视图的等待方式
+    pub fn wait_strategy(&self) -> WaitStrategy {
+        self.wait
+    }
+
+    /// 创建（或重置）共享内存并初始化管道，读写互斥锁按 `mutex_attr` 创建
+    ///
+    /// # Safety
+    /// 返回的视图指向共享内存映射，调用者需保证在使用期间不解除映射。
+    pub unsafe fn create(
+        name: &str,
+        capacity: usize,
+        slot_size: usize,
+        mode: PipeMode,
+        codec: CodecKind,
+        integrity: Integrity,
+        mutex_attr: MutexAttr,
+    ) -> Result<Self> {
+        unsafe {
+            Self::create_secured(
+                name,
+                capacity,
+                slot_size,
+                mode,
+                codec,
+                integrity,
+                mutex_attr,
+                PipeSecurity::default(),
+            )
+        }
+    }
+
+    /// 与 [`DynSharedSlotPipe::create`] 相同，并在头部记录加密算法、密钥标识与是否需要握手
+    ///
+    /// 管道本身不加解密，由 [`crate::pipe::DynCrossProcessPipe`] 在读写时处理；
+    /// 加密或需要握手的管道要求连接方的布局版本不低于当前版本。
+    ///
+    /// # Safety
+    /// 同 [`DynSharedSlotPipe::create`]。
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn create_secured(
+        name: &str,
+        capacity: usize,
+        slot_size: usize,
+        mode: PipeMode,
+        codec: CodecKind,
+        integrity: Integrity,
+        mutex_attr: MutexAttr,
+        security: PipeSecurity,
+    ) -> Result<Self> {
+        if capacity == 0 || slot_size == 0 {
+            return Err(anyhow::anyhow!(
+                "容量与槽位大小不能为0: capacity={}, slot_size={}",
+                capacity,
+                slot_size
+            )
+            .into());
+        }
+        if capacity > u32::MAX as usize {
+            return Err(anyhow::anyhow!("容量过大: {}", capacity).into());
+        }
+
+        let size = Self::mapped_size(capacity, slot_size);
+        let fd = Self::shm_open(name, O_CREAT | O_RDWR)?;
+        if let Err(e) = crate::access::restrict(fd) {
+            unsafe { close(fd) };
+            return Err(e);
+        }
+        if unsafe { ftruncate(fd, size as libc::off_t) } == -1 {
+            unsafe { close(fd) };
+            return Err(
+                anyhow::anyhow!("ftruncate failed with errno: {}", shm_sync::errno()).into(),
+            );
+        }
+
+        let header = unsafe { Self::map(fd, size)? };
+        // 初始化失败时映射随所有者解除
+        let mut pipe = unsafe { SlotMapping::new(Self::from_raw(header, capacity, slot_size)) };
+        unsafe { pipe.init(mode, codec, integrity, mutex_attr, security)? };
+        Ok(pipe.into_raw())
+    }
+
+    /// 连接到已有管道，容量与槽位大小从共享内存头部读取
+    ///
+    /// # Safety
+    /// 同 [`DynSharedSlotPipe::create`]。
+    pub unsafe fn connect(name: &str) -> Result<Self> {
+        unsafe { Self::open_existing(name, None) }
+    }
+
+    /// 连接到已有管道，并要求头部记录的容量与槽位大小和期望一致
+    ///
+    /// # Safety
+    /// 同 [`DynSharedSlotPipe::create`]。
+    pub unsafe fn connect_with_layout(
+        name: &str,
+        capacity: usize,
+        slot_size: usize,
+    ) -> Result<Self> {
+        unsafe { Self::open_existing(name, Some((capacity, slot_size))) }
+    }
+
+    unsafe fn open_existing(name: &str, expected: Option<(usize, usize)>) -> Result<Self> {
+        let fd = Self::shm_open(name, O_RDWR)?;
+
+        // 先校验布局再映射，避免把不同参数创建的段按错误的偏移访问
+        let (layout, migrate_from) = match Self::validate_fd(fd, expected) {
+            Ok(validated) => validated,
+            Err(e) => {
+                unsafe { close(fd) };
+                return Err(e);
+            }
+        };
+
+        let capacity = layout.capacity as usize;
+        let slot_size = layout.slot_size as usize;
+        let header = unsafe { Self::map(fd, Self::mapped_size(capacity, slot_size))? };
+        let mut pipe = unsafe { Self::from_raw(header, capacity, slot_size) };
+        pipe.migrate(migrate_from);
+        Ok(pipe)
+    }
+
+    /// 打开（或创建）映射普通文件的持久化管道，返回管道与恢复的消息数量
+    ///
+    /// 文件不存在或为空时按参数初始化；已存在时校验布局并执行 [`DynSharedSlotPipe::recover`]，
+    /// 此时以文件头部记录的模式、编解码方式、校验算法与互斥锁属性为准。
+    ///
+    /// 新建时 `security` 的含义同 [`DynSharedSlotPipe::create_secured`]；已存在的文件保留
+    /// 头部记录的安全设置。
+    ///
+    /// # Safety
+    /// 同 [`DynSharedSlotPipe::create`]；恢复会重新初始化锁，调用时不能有其他进程正在使用该文件。
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn open_file(
+        path: &Path,
+        capacity: usize,
+        slot_size: usize,
+        mode: PipeMode,
+        codec: CodecKind,
+        integrity: Integrity,
+        mutex_attr: MutexAttr,
+        security: PipeSecurity,
+    ) -> Result<(Self, usize)> {
+        let fd = Self::open_path(path, O_CREAT | O_RDWR)?;
+        if let Err(e) = crate::access::restrict(fd) {
+            unsafe { close(fd) };
+            return Err(e);
+        }
+
+        // 空文件或从未完成初始化的文件按新管道处理
+        let existing = matches!(read_layout_fd(fd), Ok((layout, _)) if layout.magic != 0);
+        if existing {
+            let migrate_from = match Self::validate_fd(fd, Some((capacity, slot_size))) {
+                Ok((_, migrate_from)) => migrate_from,
+                Err(e) => {
+                    unsafe { close(fd) };
+                    return Err(
+                        e.context(format!("持久化文件 {} 与管道参数不一致", path.display()))
+                    );
+                }
+            };
+            let header = unsafe { Self::map(fd, Self::mapped_size(capacity, slot_size))? };
+            let mut pipe = unsafe { SlotMapping::new(Self::from_raw(header, capacity, slot_size)) };
+            pipe.migrate(migrate_from);
+            let recovered = unsafe { pipe.recover()? };
+            return Ok((pipe.into_raw(), recovered));
+        }
+
+        let size = Self::mapped_size(capacity, slot_size);
+        if unsafe { ftruncate(fd, size as libc::off_t) } == -1 {
+            unsafe { close(fd) };
+            return Err(
+                anyhow::anyhow!("ftruncate failed with errno: {}", shm_sync::errno()).into(),
+            );
+        }
+        let header = unsafe { Self::map(fd, size)? };
+        let mut pipe = unsafe { SlotMapping::new(Self::from_raw(header, capacity, slot_size)) };
+        unsafe { pipe.init(mode, codec, integrity, mutex_attr, security)? };
+        Ok((pipe.into_raw(), 0))
+    }
+
+    /// 连接到由 [`DynSharedSlotPipe::open_file`] 创建的持久化管道
+    ///
+    /// # Safety
+    /// 同 [`DynSharedSlotPipe::create`]。
+    pub unsafe fn connect_file(path: &Path) -> Result<Self> {
+        let fd = Self::open_path(path, O_RDWR)?;
+        let (layout, migrate_from) = match Self::validate_fd(fd, None) {
+            Ok(validated) => validated,
+            Err(e) => {
+                unsafe { close(fd) };
+                return Err(e);
+            }
+        };
+
+        let capacity = layout.capacity as usize;
+        let slot_size = layout.slot_size as usize;
+        let header = unsafe { Self::map(fd, Self::mapped_size(capacity, slot_size))? };
+        let mut pipe = unsafe { Self::from_raw(header, capacity, slot_size) };
+        pipe.migrate(migrate_from);
+        Ok(pipe)
+    }
+
+    /// 崩溃或重启后的恢复：重新初始化头部，把完整写入但未被消费完成的消息重新排队
+    ///
+    /// 校验通过的消息槽位（READY，以及读取方尚未完成的槽位，见 [`holds_message`](Self::holds_message)）
+    /// 按 request_id 顺序重新放入队列，其余槽位归还为空。消息保留已记录的投递次数，
+    /// 崩溃时仍由读取方持有的消息算作一次投递，反复使读者崩溃的消息最终也会超过最大投递次数。
+    /// 返回恢复的消息数量。
+    ///
+    /// # Safety
+    /// 调用时不能有其他进程正在使用该管道。
+    pub unsafe fn recover(&mut self) -> Result<usize> {
+        let mode = self.mode();
+        let codec = self.codec();
+        let integrity = self.integrity();
+        let mutex_attr = self.mutex_attr();
+        let security = PipeSecurity {
+            encryption: self.encryption().unwrap_or_default(),
+            key_id: self.key_id(),
+            handshake: self.requires_handshake(),
+            auth_key_id: self.auth_key_id(),
+        };
+        let seq = self.header().seq.load(Ordering::Relaxed);
+        let (high_watermark, low_watermark) = self.watermarks();
+        let fair = self.is_fair();
+        let full_policy = self.full_policy();
+        let max_delivery_attempts = self.max_delivery_attempts();
+
+        let mut messages = Vec::new();
+        for index in 0..self.capacity {
+            let slot = self.slot(index);
+            let data_size = slot.data_size as usize;
+            if !self.holds_message(index) || data_size > self.slot_size {
+                continue;
+            }
+
+            let (request_id, checksum, mac) = (slot.request_id, slot.checksum, slot.mac);
+            let delivered = slot.state.load(Ordering::Acquire) != SlotState::READY as u32;
+            let attempts = slot.delivery_attempts + delivered as u32;
+            let data = self.data_mut(index)[..data_size].to_vec();
+            if !integrity.verify(&data, checksum) {
+                tracing::warn!("恢复管道时丢弃校验失败的槽位 {}", index);
+                continue;
+            }
+            messages.push((request_id, (checksum, mac, attempts), data));
+        }
+        messages.sort_by_key(|(request_id, _, _)| *request_id);
+
+        unsafe { self.init(mode, codec, integrity, mutex_attr, security)? };
+
+        let next_seq = messages
+            .iter()
+            .map(|(request_id, _, _)| request_id + 1)
+            .fold(seq, u64::max);
+        self.header().seq.store(next_seq, Ordering::Relaxed);
+        self.set_watermarks(high_watermark, low_watermark);
+        self.set_fair(fair);
+        self.set_full_policy(full_policy);
+        self.set_max_delivery_attempts(max_delivery_attempts);
+
+        let count = messages.len();
+        for (index, (request_id, (checksum, mac, attempts), data)) in
+            messages.into_iter().enumerate()
+        {
+            self.data_mut(index)[..data.len()].copy_from_slice(&data);
+            let lock_free = self.is_lock_free();
+            let slot = self.slot_mut(index);
+            slot.data_size = data.len() as u32;
+            slot.checksum = checksum;
+            slot.mac = mac;
+            slot.request_id = request_id;
+            slot.delivery_attempts = attempts;
+            slot.state.store(SlotState::READY as u32, Ordering::Release);
+            if lock_free {
+                Self::publish_lock_free(slot);
+            }
+        }
+
+        let capacity = self.capacity;
+        let header = self.header_mut();
+        header.write_pointer = count % capacity;
+        header.read_pointer = 0;
+        header.enqueue_pos.store(count as u64, Ordering::Relaxed);
+        header.begin.store(count > 0, Ordering::SeqCst);
+        Ok(count)
+    }
+
+    /// 将映射内容同步写回底层文件（持久化管道）
+    pub fn flush(&self) -> Result<()> {
+        if unsafe {
+            libc::msync(
+                self.as_ptr() as *mut libc::c_void,
+                self.mapped_len(),
+                libc::MS_SYNC,
+            )
+        } == -1
+        {
+            return Err(anyhow::anyhow!("msync failed with errno: {}", shm_sync::errno()).into());
+        }
+        Ok(())
+    }
+
+    /// 复制所有已写入但尚未处理完成的槽位，不改变槽位状态
+    ///
+    /// 包括 READY 槽位，以及读取方已取出但尚未确认的槽位；写入方正在填充的槽位不在其中。
+    /// 复制期间被其他进程取走或改写的槽位、校验失败的槽位被跳过。
+    ///
+    /// # Safety
+    /// 视图必须指向已映射并初始化过的共享内存。
+    pub unsafe fn snapshot(&mut self) -> QueueSnapshot {
+        let mut entries = Vec::new();
+        for index in 0..self.capacity {
+            match unsafe { self.copy_slot(index) } {
+                Ok(Some(entry)) => entries.push(entry),
+                Ok(None) => {}
+                Err(e) => tracing::warn!("快照跳过槽位 {}: {}", index, e),
+            }
+        }
+        entries.sort_by_key(|entry| entry.request_id);
+
+        let header = self.header();
+        QueueSnapshot {
+            codec: header.codec,
+            integrity: header.integrity,
+            encryption: header.encryption,
+            key_id: header.key_id,
+            auth_key_id: header.auth_key_id,
+            capacity: self.capacity as u64,
+            slot_size: self.slot_size as u64,
+            next_request_id: header.seq.load(Ordering::Relaxed),
+            entries,
+        }
+    }
+
+    /// 槽位中是否有已写入的消息：READY、READING，或由读取方持有
+    ///
+    /// 写入方持有的槽位在发布前没有数据；无锁模式下被放弃的槽位以 READY 发布，
+    /// 由 `data_size` 中的 [`ABANDONED_SLOT`] 标志区分。
+    fn holds_message(&self, index: usize) -> bool {
+        let slot = self.slot(index);
+        let state = slot.state.load(Ordering::Acquire);
+        state == SlotState::READY as u32
+            || state == SlotState::READING as u32
+            || (state == SlotState::INPROGRESS as u32 && slot.lease_role() == LEASE_READER)
+    }
+
+    /// 复制一个已写入但尚未处理完成的槽位，不改变槽位状态
+    ///
+    /// 槽位为空、正在被写入，或复制期间被其他进程取走、改写时返回 `None`；
+    /// 数据校验和或认证码不符时返回 [`TokioIPCError::ChecksumMismatch`] 或
+    /// [`TokioIPCError::AuthenticationFailed`]。
+    ///
+    /// # Safety
+    /// 视图必须指向已映射并初始化过的共享内存。
+    pub unsafe fn copy_slot(&mut self, index: usize) -> Result<Option<SnapshotEntry>> {
+        if index >= self.capacity {
+            return Err(anyhow::anyhow!("Slot index out of bounds").into());
+        }
+
+        let slot = self.slot(index);
+        let state = slot.state.load(Ordering::Acquire);
+        let data_size = slot.data_size as usize;
+        if !self.holds_message(index) || data_size > self.slot_size {
+            return Ok(None);
+        }
+
+        let entry = SnapshotEntry {
+            request_id: slot.request_id,
+            delivery_attempts: slot.delivery_attempts,
+            checksum: slot.checksum,
+            mac: slot.mac,
+            data: self.data_mut(index)[..data_size].to_vec(),
+        };
+        let slot = self.slot(index);
+        if slot.state.load(Ordering::Acquire) != state || slot.request_id != entry.request_id {
+            return Ok(None);
+        }
+        if !self.integrity().verify(&entry.data, entry.checksum) {
+            return Err(TokioIPCError::ChecksumMismatch.into());
+        }
+        if !self.mac_verifier()(entry.request_id, &entry.data, entry.mac) {
+            return Err(TokioIPCError::AuthenticationFailed.into());
+        }
+        Ok(Some(entry))
+    }
+
+    /// 加锁模式：抢占第一个数据满足 `select` 的 READY 槽位，不移动读指针，其余槽位保持原状
+    ///
+    /// 在 read_mutex 保护下从读指针开始检查，`select` 收到槽位数据的副本；校验失败的槽位
+    /// 留给普通读取处理。无锁模式的槽位按环形顺序出队，不支持按条件抢占，返回错误。
+    ///
+    /// # Safety
+    /// 视图必须指向已映射并初始化过的共享内存。
+    pub unsafe fn claim_ready_where<F>(&mut self, mut select: F) -> Result<Option<usize>>
+    where
+        F: FnMut(&[u8]) -> bool,
+    {
+        if self.is_lock_free() {
+            return Err(anyhow::anyhow!("无锁模式不支持按条件读取").into());
+        }
+        if !unsafe { self.lock_read(None) } {
+            return Ok(None);
+        }
+
+        let capacity = self.capacity;
+        let start_index = self.header().read_pointer;
+        let mut claimed = None;
+        for slot_index in (0..capacity).map(|i| (start_index + i) % capacity) {
+            if self.slot(slot_index).state.load(Ordering::Acquire) != SlotState::READY as u32 {
+                continue;
+            }
+            let Ok(Some(entry)) = (unsafe { self.copy_slot(slot_index) }) else {
+                continue;
+            };
+            if select(&entry.data) {
+                let slot = self.slot(slot_index);
+                slot.lease_next(LEASE_READER);
+                slot.state
+                    .store(SlotState::READING as u32, Ordering::Release);
+                claimed = Some(slot_index);
+                break;
+            }
+        }
+
+        unsafe {
+            self.header_mut().read_mutex.unlock();
+        }
+        Ok(claimed)
+    }
+
+    /// 把快照中的一条消息写入已抢占的槽位，沿用原 request_id 与投递次数
+    ///
+    /// 写入前按本管道的校验算法与认证密钥校验数据，失败时槽位被放弃。
+    /// request_id 生成器推进到该消息之后，新消息不会与之重复。
+    ///
+    /// # Safety
+    /// 视图必须指向已映射并初始化过的共享内存。
+    pub unsafe fn restore_entry(&mut self, index: usize, entry: &SnapshotEntry) -> Result<u64> {
+        let integrity = self.integrity();
+        let authentic = self.mac_verifier();
+        let request_id = unsafe {
+            self.write_slot(
+                index,
+                Some(entry.request_id),
+                entry.delivery_attempts,
+                |buf| {
+                    if !integrity.verify(&entry.data, entry.checksum) {
+                        return Err(TokioIPCError::ChecksumMismatch.into());
+                    }
+                    if !authentic(entry.request_id, &entry.data, entry.mac) {
+                        return Err(TokioIPCError::AuthenticationFailed.into());
+                    }
+                    let room = buf.len();
+                    buf.get_mut(..entry.data.len())
+                        .ok_or_else(|| {
+                            anyhow::anyhow!(
+                                "快照消息 {} 字节超过槽位大小 {} 字节",
+                                entry.data.len(),
+                                room
+                            )
+                        })?
+                        .copy_from_slice(&entry.data);
+                    Ok(entry.data.len())
+                },
+            )?
+        };
+        self.header()
+            .seq
+            .fetch_max(request_id + 1, Ordering::Relaxed);
+        self.header().sent_count.fetch_add(1, Ordering::Relaxed);
+        Ok(request_id)
+    }
+
+    /// 把 READY 消息按读取顺序移到 `target`，随后调用 `switch`，返回移动的数量
+    ///
+    /// 队列扩容（见 [`crate::resizable`]）使用。加锁模式下整个过程持有 write_mutex，写者无法
+    /// 抢占新槽位，`switch` 在释放锁之前把写者切换到 `target`；无锁模式的写者不经过 write_mutex，
+    /// 切换之前抢占的槽位仍发布在本管道，由读者排空。最多移动 `capacity` 条，
+    /// 消息保持原 request_id、投递次数与（加密管道中的）密文，校验失败的消息被丢弃。
+    /// 消息写入 `target` 失败时放回本管道并停止迁移，返回错误，不调用 `switch`。
+    ///
+    /// `target` 必须是还没有写者的空管道，容量不小于本管道。
+    ///
+    /// # Safety
+    /// 两个视图都必须指向已映射并初始化过的共享内存。
+    pub(crate) unsafe fn migrate_ready(
+        &mut self,
+        target: &mut DynSharedSlotPipe,
+        switch: impl FnOnce(),
+    ) -> Result<usize> {
+        if target.capacity < self.capacity || target.slot_size < self.slot_size {
+            return Err(anyhow::anyhow!("目标管道的容量或槽位大小小于源管道").into());
+        }
+        let locked = !self.is_lock_free();
+        if locked && !unsafe { self.lock_write(None) } {
+            return Err(TokioIPCError::MutexLockFailed.into());
+        }
+        // 源管道已分配过的 request_id 不再复用
+        target
+            .header()
+            .seq
+            .fetch_max(self.header().seq.load(Ordering::Relaxed), Ordering::Relaxed);
+
+        let mut moved = 0;
+        let mut result: Result<()> = Ok(());
+        for _ in 0..self.capacity {
+            // 先占好目标槽位，保证取出的消息一定有处可放
+            let Some(target_index) = (unsafe { target.claim_empty_now() }) else {
+                result = Err(anyhow::anyhow!("目标管道没有空槽位").into());
+                break;
+            };
+            let Some(index) = (unsafe { self.claim_ready_now() }) else {
+                unsafe { target.abandon(target_index) };
+                break;
+            };
+            if !self.begin(index, LEASE_READER) {
+                unsafe { target.abandon(target_index) };
+                continue;
+            }
+            match unsafe { self.copy_slot(index) } {
+                Ok(Some(entry)) => match unsafe { target.restore_entry(target_index, &entry) } {
+                    Ok(_) => moved += 1,
+                    // 目标槽位已被放弃；消息留在本管道，由调用者决定如何处理
+                    Err(e) => {
+                        let e = e.context(format!("迁移消息 {} 失败", entry.request_id));
+                        result = match unsafe { self.put_back(index) } {
+                            Ok(()) => Err(e),
+                            Err(put_back) => Err(e.context(format!(
+                                "消息仍由槽位 {} 持有，放回失败: {}",
+                                index, put_back
+                            ))),
+                        };
+                        break;
+                    }
+                },
+                // 无锁模式下放弃写入的空槽
+                Ok(None) => unsafe { target.abandon(target_index) },
+                Err(e) => {
+                    tracing::warn!("迁移时丢弃槽位 {}: {}", index, e);
+                    unsafe { target.abandon(target_index) };
+                }
+            }
+            // 持有 write_mutex 时不能唤醒写者（notify_empty 需要同一把锁），释放锁后统一唤醒
+            self.clear_message(index);
+            self.vacate(index);
+        }
+
+        if result.is_ok() {
+            switch();
+        }
+        if locked {
+            unsafe { self.header_mut().write_mutex.unlock() };
+        }
+        unsafe { self.notify_empty() };
+        if result.is_err() {
+            unsafe { self.notify_ready() };
+        }
+        result.map(|_| moved)
+    }
+
+    fn open_path(path: &Path, flags: libc::c_int) -> Result<libc::c_int> {
+        let cpath = CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| anyhow::anyhow!("Failed to create CString from path"))?;
+
+        let fd = unsafe {
+            libc::open(
+                cpath.as_ptr(),
+                flags | libc::O_CLOEXEC,
+                crate::access::mode() as libc::c_uint,
+            )
+        };
+        if fd == -1 {
+            return Err(anyhow::anyhow!(
+                "open {} failed with errno: {}",
+                path.display(),
+                shm_sync::errno()
+            )
+            .into());
+        }
+        Ok(fd)
+    }
+
+    fn shm_open(name: &str, flags: libc::c_int) -> Result<libc::c_int> {
+        let cname = CString::new(format!("/{}", name.trim_start_matches('/')))
+            .map_err(|_| anyhow::anyhow!("Failed to create CString from name"))?;
+
+        let fd = unsafe { libc::shm_open(cname.as_ptr(), flags, crate::access::mode()) };
+        if fd == -1 {
+            return Err(shm_open_error().into());
+        }
+        Ok(fd)
+    }
+
+    /// 映射 `size` 字节并关闭 fd
+    unsafe fn map(fd: libc::c_int, size: usize) -> Result<NonNull<PipeHeader>> {
+        let addr = unsafe {
+            mmap(
+                ptr::null_mut(),
+                size,
+                PROT_READ | PROT_WRITE,
+                MAP_SHARED,
+                fd,
+                0,
+            )
+        };
+
+        unsafe {
+            close(fd);
+        }
+        if addr == MAP_FAILED {
+            return Err(anyhow::anyhow!("mmap failed").into());
+        }
+
+        Ok(unsafe { NonNull::new_unchecked(addr as *mut PipeHeader) })
+    }
+
+    /// 校验共享内存段的布局，`expected` 给出时要求容量与槽位大小一致
+    ///
+    /// 返回布局描述与需要迁移时共享内存的布局版本（见 [`DynSharedSlotPipe::migrate`]）。
+    fn validate_fd(
+        fd: libc::c_int,
+        expected: Option<(usize, usize)>,
+    ) -> Result<(LayoutHeader, Option<u32>)> {
+        let (header, file_size) = read_layout_fd(fd)?;
+        let capacity = header.capacity as usize;
+        let slot_size = header.slot_size as usize;
+
+        let mut migrate_from = None;
+        let mismatch = if header.magic != PIPE_MAGIC {
+            Some(LayoutMismatch::Magic {
+                found: header.magic,
+            })
+        } else if let Err(mismatch) = negotiate(&header).map(|from| migrate_from = from) {
+            Some(mismatch)
+        } else if let Some((expected, _)) = expected
+            && capacity != expected
+        {
+            Some(LayoutMismatch::Capacity {
+                expected,
+                found: capacity,
+            })
+        } else if let Some((_, expected)) = expected
+            && slot_size != expected
+        {
+            Some(LayoutMismatch::SlotSize {
+                expected,
+                found: slot_size,
+            })
+        } else if file_size < Self::mapped_size(capacity, slot_size) {
+            Some(LayoutMismatch::Size {
+                expected: Self::mapped_size(capacity, slot_size),
+                found: file_size,
+            })
+        } else {
+            None
+        };
+
+        match mismatch {
+            Some(mismatch) => Err(mismatch.into()),
+            None => Ok((header, migrate_from)),
+        }
+    }
+
+    /// 把旧布局版本创建的管道升级到当前版本：执行迁移后更新头部的布局版本
+    fn migrate(&mut self, from: Option<u32>) {
+        let Some(from) = from else {
+            return;
+        };
+        apply_migrations(self.header(), from, LAYOUT_MIGRATIONS);
+        std::sync::atomic::fence(Ordering::Release);
+        self.header_mut().layout.version = PIPE_LAYOUT_VERSION;
+        tracing::info!("管道布局已从版本 {} 迁移到 {}", from, PIPE_LAYOUT_VERSION);
+    }
+
+    unsafe fn init(
+        &mut self,
+        mode: PipeMode,
+        codec: CodecKind,
+        integrity: Integrity,
+        mutex_attr: MutexAttr,
+        security: PipeSecurity,
+    ) -> Result<()> {
+        let capacity = self.capacity;
+        let slot_size = self.slot_size;
+        let header = self.header_mut();
+
+        // 先清除 magic，初始化期间连接方会校验失败而不是读到半初始化的数据
+        header.layout = LayoutHeader {
+            magic: 0,
+            version: PIPE_LAYOUT_VERSION,
+            capacity: capacity as u32,
+            slot_size: slot_size as u64,
+            // 旧程序不认识加密与握手字段，会把密文当作明文读取或跳过握手
+            compat_version: if security.requires_current_layout() {
+                PIPE_LAYOUT_VERSION
+            } else {
+                PIPE_COMPAT_VERSION
+            },
+            reserved: 0,
+        };
+        std::sync::atomic::fence(Ordering::Release);
+
+        unsafe {
+            header
+                .write_mutex
+                .init_with(mutex_attr)
+                .map_err(|_| anyhow::anyhow!("Failed to initialize write mutex"))?;
+            header
+                .read_mutex
+                .init_with(mutex_attr)
+                .map_err(|_| anyhow::anyhow!("Failed to initialize read mutex"))?;
+            header
+                .ready_cond
+                .init()
+                .map_err(|_| anyhow::anyhow!("Failed to initialize ready condition"))?;
+            header
+                .empty_cond
+                .init()
+                .map_err(|_| anyhow::anyhow!("Failed to initialize empty condition"))?;
+        }
+
+        header.write_pointer = 0;
+        header.read_pointer = 0;
+        header.mode = mode as u32;
+        header.codec = codec as u32;
+        header.integrity = integrity as u32;
+        header.mutex_attr = mutex_attr.to_bits();
+        header.encryption = security.encryption as u32;
+        header.key_id = security.key_id;
+        header.access_control = security.handshake as u32;
+        header.auth_key_id = security.auth_key_id;
+        header.enqueue_pos = CachePadded::new(AtomicU64::new(0));
+        header.dequeue_pos = CachePadded::new(AtomicU64::new(0));
+        header.ready_waiters = AtomicU32::new(0);
+        header.empty_waiters = AtomicU32::new(0);
+        header.attached_count = AtomicU32::new(0);
+        for pid in header.attached_pids.iter_mut() {
+            *pid = AtomicU32::new(0);
+        }
+        header.seq = CachePadded::new(AtomicU64::new(1));
+        header.begin = AtomicBool::new(false);
+        header.shared_value = CachePadded::new(AtomicU32::new(0));
+        header.empty_value = CachePadded::new(AtomicU32::new(0));
+        header.reclaimed_count = AtomicU64::new(0);
+        header.high_watermark = AtomicU32::new(0);
+        header.low_watermark = AtomicU32::new(0);
+        header.backpressured = AtomicBool::new(false);
+        header.fair = AtomicU32::new(0);
+        header.expired_count = AtomicU64::new(0);
+        header.max_delivery_attempts = AtomicU32::new(DEFAULT_MAX_DELIVERY_ATTEMPTS);
+        header.redelivered_count = AtomicU64::new(0);
+        header.dead_lettered_count = AtomicU64::new(0);
+        header.sent_count = CachePadded::new(AtomicU64::new(0));
+        header.received_count = CachePadded::new(AtomicU64::new(0));
+        header.lock_contended_count = AtomicU64::new(0);
+        for bucket in header.latency_buckets.iter_mut() {
+            *bucket = AtomicU64::new(0);
+        }
+        header.latency_sum_nanos = AtomicU64::new(0);
+        header.next_ticket = AtomicU32::new(0);
+        header.now_serving = AtomicU32::new(0);
+        header.serving_pid = AtomicU32::new(0);
+        for ticket in header.cancelled_tickets.iter_mut() {
+            *ticket = AtomicU64::new(0);
+        }
+        header.space_waiters = AtomicU64::new(0);
+        header.committed_offset = AtomicU64::new(0);
+        header.full_policy = AtomicU32::new(FullPolicy::Block as u32);
+        header.full_policy_reserved = 0;
+        header.dropped_count = AtomicU64::new(0);
+        header.last_arrival_nanos = AtomicU64::new(0);
+        header.arrival_gap_nanos = AtomicU64::new(0);
+        for word in header.reserved.iter_mut() {
+            *word = AtomicU64::new(0);
+        }
+
+        for i in 0..capacity {
+            let slot = self.slot_mut(i);
+            slot.state = AtomicU32::new(SlotState::EMPTY as u32);
+            slot.sequence = AtomicU64::new(i as u64);
+            slot.leased_at = AtomicU64::new(0);
+            slot.lease_role = AtomicU32::new(0);
+            slot.request_id = 0;
+            slot.data_size = 0;
+            slot.checksum = 0;
+            slot.mac = 0;
+            slot.delivery_attempts = 0;
+            self.data_mut(i).fill(0);
+        }
+
+        // 全部初始化完成后才写入 magic
+        std::sync::atomic::fence(Ordering::Release);
+        unsafe { ptr::write_volatile(&mut self.header_mut().layout.magic, PIPE_MAGIC) };
+
+        Ok(())
+    }
+
+    /// 共享内存起始地址
+    pub fn as_ptr(&self) -> *mut PipeHeader {
+        self.header.as_ptr()
+    }
+
+    /// 映射的字节数
+    pub fn mapped_len(&self) -> usize {
+        Self::mapped_size(self.capacity, self.slot_size)
+    }
+
+    /// 槽位区域（头部之后）的起始地址与字节数
+    pub(crate) fn slot_region(&self) -> (*mut u8, usize) {
+        let offset = mem::size_of::<PipeHeader>();
+        (
+            unsafe { (self.as_ptr() as *mut u8).add(offset) },
+            self.capacity * self.stride,
+        )
+    }
+
+    /// 解除映射
+    ///
+    /// # Safety
+    /// 调用后该视图及其所有副本都不能再使用。
+    pub unsafe fn unmap(self) {
+        unsafe {
+            libc::munmap(self.as_ptr() as *mut libc::c_void, self.mapped_len());
+        }
+    }
+
+    /// 管道头部
+    pub fn header(&self) -> &PipeHeader {
+        unsafe { self.header.as_ref() }
+    }
+
+    fn header_mut(&mut self) -> &mut PipeHeader {
+        unsafe { self.header.as_mut() }
+    }
+
+    fn slot_ptr(&self, index: usize) -> *mut u8 {
+        debug_assert!(index < self.capacity);
+        unsafe {
+            (self.header.as_ptr() as *mut u8)
+                .add(mem::size_of::<PipeHeader>() + index * self.stride)
+        }
+    }
+
+    /// 指定索引槽位的元数据，调用者需保证 `index < capacity`
+    pub fn slot(&self, index: usize) -> &SlotHeader {
+        unsafe { &*(self.slot_ptr(index) as *const SlotHeader) }
+    }
+
+    fn slot_mut(&mut self, index: usize) -> &mut SlotHeader {
+        unsafe { &mut *(self.slot_ptr(index) as *mut SlotHeader) }
+    }
+
+    /// 指定索引槽位的数据区
+    fn data_mut(&mut self, index: usize) -> &mut [u8] {
+        unsafe {
+            std::slice::from_raw_parts_mut(
+                self.slot_ptr(index).add(mem::size_of::<SlotHeader>()),
+                self.slot_size,
+            )
+        }
+    }
+
+    /// 槽位中已写入的数据，调用者需保证 `index < capacity` 且槽位由自己以读者身份持有
+    pub(crate) fn held_data(&self, index: usize) -> &[u8] {
+        let len = (self.slot(index).data_size as usize).min(self.slot_size);
+        unsafe {
+            std::slice::from_raw_parts(self.slot_ptr(index).add(mem::size_of::<SlotHeader>()), len)
+        }
+    }
+
+    /// 获取 write_mutex，并统计锁竞争；超过 `deadline` 返回 `false`
+    unsafe fn lock_write(&mut self, deadline: Option<&timespec>) -> bool {
+        let header = self.header_mut();
+        unsafe {
+            lock_counted(
+                &mut header.write_mutex,
+                &header.lock_contended_count,
+                deadline,
+            )
+        }
+    }
+
+    /// 获取 read_mutex，并统计锁竞争；超过 `deadline` 返回 `false`
+    unsafe fn lock_read(&mut self, deadline: Option<&timespec>) -> bool {
+        let header = self.header_mut();
+        unsafe {
+            lock_counted(
+                &mut header.read_mutex,
+                &header.lock_contended_count,
+                deadline,
+            )
+        }
+    }
+
+    /// 在 write_mutex 保护下抢占一个 EMPTY 槽位
+    fn claim_empty(&mut self) -> Option<usize> {
+        let capacity = self.capacity;
+        let start_index = self.header().write_pointer;
+
+        for i in 0..capacity {
+            let slot_index = (start_index + i) % capacity;
+            let slot = self.slot(slot_index);
+
+            // 简单的状态检查，无需复杂的原子操作
+            if slot.state.load(Ordering::Acquire) == SlotState::EMPTY as u32 {
+                // 先记录租约再改状态：持有者在两步之间被杀时槽位仍能被回收
+                slot.lease_next(LEASE_WRITER);
+                slot.state
+                    .store(SlotState::WRITING as u32, Ordering::Release);
+                self.header_mut().write_pointer = (slot_index + 1) % capacity;
+                return Some(slot_index);
+            }
+        }
+
+        None
+    }
+
+    /// 在 read_mutex 保护下抢占一个 READY 槽位
+    fn claim_ready(&mut self) -> Option<usize> {
+        let capacity = self.capacity;
+        let start_index = self.header().read_pointer;
+
+        for i in 0..capacity {
+            let slot_index = (start_index + i) % capacity;
+            let slot = self.slot(slot_index);
+
+            // 将槽位状态设置为 READING
+            if slot.state.load(Ordering::Acquire) == SlotState::READY as u32 {
+                slot.lease_next(LEASE_READER);
+                slot.state
+                    .store(SlotState::READING as u32, Ordering::Release);
+                self.header_mut().read_pointer = (slot_index + 1) % capacity;
+                return Some(slot_index);
+            }
+        }
+
+        None
+    }
+
+    /// 无锁模式：通过 CAS 推进 enqueue_pos 抢占槽位
+    fn claim_empty_lock_free(&self) -> Option<usize> {
+        let capacity = self.capacity as u64;
+        let header = self.header();
+        let mut pos = header.enqueue_pos.load(Ordering::Relaxed);
+        loop {
+            let slot = self.slot((pos % capacity) as usize);
+            let seq = slot.sequence.load(Ordering::Acquire);
+            let diff = seq as i64 - pos as i64;
+
+            if diff == 0 {
+                match header.enqueue_pos.compare_exchange_weak(
+                    pos,
+                    pos + 1,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        slot.lease(LEASE_WRITER);
+                        slot.state
+                            .store(SlotState::WRITING as u32, Ordering::Release);
+                        return Some((pos % capacity) as usize);
+                    }
+                    Err(current) => pos = current,
+                }
+            } else if diff < 0 {
+                // 槽位尚未被消费，队列已满
+                return None;
+            } else {
+                pos = header.enqueue_pos.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// 无锁模式：通过 CAS 推进 dequeue_pos 抢占已发布的槽位
+    fn claim_ready_lock_free(&self) -> Option<usize> {
+        let capacity = self.capacity as u64;
+        let header = self.header();
+        let mut pos = header.dequeue_pos.load(Ordering::Relaxed);
+        loop {
+            let index = (pos % capacity) as usize;
+            let slot = self.slot(index);
+            let seq = slot.sequence.load(Ordering::Acquire);
+            let diff = seq as i64 - (pos + 1) as i64;
+
+            if diff == 0 {
+                match header.dequeue_pos.compare_exchange_weak(
+                    pos,
+                    pos + 1,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        if slot.is_abandoned() {
+                            // 写入失败被放弃的槽位，直接归还并继续
+                            self.release_lock_free(slot);
+                            pos = header.dequeue_pos.load(Ordering::Relaxed);
+                            continue;
+                        }
+                        slot.lease(LEASE_READER);
+                        slot.state
+                            .store(SlotState::READING as u32, Ordering::Release);
+                        return Some(index);
+                    }
+                    Err(current) => pos = current,
+                }
+            } else if diff < 0 {
+                // 生产者尚未发布该位置，队列为空
+                return None;
+            } else {
+                pos = header.dequeue_pos.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// 无锁模式：发布已写入的槽位（序列号 pos -> pos + 1）
+    fn publish_lock_free(slot: &SlotHeader) {
+        slot.clear_lease();
+        let seq = slot.sequence.load(Ordering::Relaxed);
+        slot.sequence.store(seq + 1, Ordering::Release);
+    }
+
+    /// 无锁模式：归还已消费的槽位（序列号 pos + 1 -> pos + N）
+    fn release_lock_free(&self, slot: &SlotHeader) {
+        slot.clear_lease();
+        slot.state.store(SlotState::EMPTY as u32, Ordering::Release);
+        let seq = slot.sequence.load(Ordering::Relaxed);
+        slot.sequence
+            .store(seq - 1 + self.capacity as u64, Ordering::Release);
+    }
+
+    /// 无锁模式：按序列号判断槽位的位置是否已被推进但尚未发布 / 归还，返回持有方
+    ///
+    /// 抢占者在 CAS 推进位置之后、记录租约之前被杀时，槽位没有租约，只能据此识别。
+    fn lock_free_holder(&self, index: usize) -> Option<u32> {
+        let capacity = self.capacity as u64;
+        let header = self.header();
+        let seq = self.slot(index).sequence.load(Ordering::Acquire);
+        if seq % capacity == index as u64 {
+            // 等待写入：序列号即生产位置
+            (header.enqueue_pos.load(Ordering::Acquire) > seq).then_some(LEASE_WRITER)
+        } else {
+            // 等待读取：序列号即消费位置 + 1
+            (header.dequeue_pos.load(Ordering::Acquire) >= seq).then_some(LEASE_READER)
+        }
+    }
+
+    /// 获取管道的并发模式
+    pub fn mode(&self) -> PipeMode {
+        PipeMode::from_u32(self.header().mode)
+    }
+
+    /// 获取管道的编解码方式
+    pub fn codec(&self) -> CodecKind {
+        CodecKind::from_u32(self.header().codec)
+    }
+
+    /// 获取管道的校验算法
+    pub fn integrity(&self) -> Integrity {
+        Integrity::from_u32(self.header().integrity)
+    }
+
+    /// 获取读写互斥锁的创建属性
+    pub fn mutex_attr(&self) -> MutexAttr {
+        MutexAttr::from_bits(self.header().mutex_attr)
+    }
+
+    /// 获取负载加密算法，头部记录了未知算法时返回 `None`
+    pub fn encryption(&self) -> Option<Encryption> {
+        Encryption::from_u32(self.header().encryption)
+    }
+
+    /// 获取负载密钥标识，0 表示未加密
+    pub fn key_id(&self) -> u64 {
+        self.header().key_id
+    }
+
+    /// 获取消息认证密钥标识，0 表示不认证
+    pub fn auth_key_id(&self) -> u64 {
+        self.header().auth_key_id
+    }
+
+    /// 校验消息认证码的函数：头部不要求认证时总是通过
+    fn mac_verifier(&self) -> impl Fn(u64, &[u8], u64) -> bool + use<> {
+        let required = self.auth_key_id() != 0;
+        let authenticator = self.authenticator;
+        move |request_id, data, mac| {
+            !required
+                || authenticator
+                    .is_some_and(|authenticator| authenticator.verify(request_id, data, mac))
+        }
+    }
+
+    /// 连接前是否需要经创建者握手授权
+    pub fn requires_handshake(&self) -> bool {
+        self.header().access_control != 0
+    }
+
+    fn is_lock_free(&self) -> bool {
+        self.mode() == PipeMode::LockFree
+    }
+
+    /// 按当前模式抢占 EMPTY 槽位（锁模式下调用者需持有 write_mutex）
+    fn claim_empty_by_mode(&mut self) -> Option<usize> {
+        if self.is_lock_free() {
+            self.claim_empty_lock_free()
+        } else {
+            self.claim_empty()
+        }
+    }
+
+    /// 按当前模式抢占 READY 槽位（锁模式下调用者需持有 read_mutex）
+    fn claim_ready_by_mode(&mut self) -> Option<usize> {
+        if self.is_lock_free() {
+            self.claim_ready_lock_free()
+        } else {
+            self.claim_ready()
+        }
+    }
+
+    /// 是否存在可读取的槽位
+    pub(crate) fn has_ready(&self) -> bool {
+        if self.is_lock_free() {
+            let pos = self.header().dequeue_pos.load(Ordering::Acquire);
+            let slot = self.slot((pos % self.capacity as u64) as usize);
+            slot.sequence.load(Ordering::Acquire) == pos + 1
+        } else {
+            (0..self.capacity)
+                .any(|i| self.slot(i).state.load(Ordering::Acquire) == SlotState::READY as u32)
+        }
+    }
+
+    /// 是否存在可写入的空槽位
+    pub(crate) fn has_empty(&self) -> bool {
+        if self.is_lock_free() {
+            let pos = self.header().enqueue_pos.load(Ordering::Acquire);
+            let slot = self.slot((pos % self.capacity as u64) as usize);
+            slot.sequence.load(Ordering::Acquire) == pos
+        } else {
+            (0..self.capacity)
+                .any(|i| self.slot(i).state.load(Ordering::Acquire) == SlotState::EMPTY as u32)
+        }
+    }
+
+    /// 基于 futex 等待出现 READY 槽位，不抢占槽位
+    ///
+    /// 返回 `true` 时调用 `fetch`/`hold` 仍可能因竞争失败，需要重试；超时返回 `false`
+    pub fn wait_for_ready(&self, timeout: Option<Duration>) -> bool {
+        futex::wait_until(&self.header().shared_value, timeout, || self.has_ready())
+    }
+
+    /// 基于 futex 等待出现空槽位，不抢占槽位
+    pub fn wait_for_empty(&self, timeout: Option<Duration>) -> bool {
+        futex::wait_until(&self.header().empty_value, timeout, || self.has_empty())
+    }
+
+    /// 通知等待数据的读者
+    unsafe fn notify_ready(&mut self) {
+        let header = self.header_mut();
+        futex::bump_and_wake(&header.shared_value);
+
+        // 没有等待者时不触碰互斥锁，避免无锁模式退化为串行
+        if header.ready_waiters.load(Ordering::SeqCst) == 0 {
+            return;
+        }
+        // 先取得 read_mutex 再通知，保证读者不会在“检查”与“等待”之间错过信号
+        unsafe {
+            if lock_counted(&mut header.read_mutex, &header.lock_contended_count, None) {
+                header.ready_cond.signal();
+                header.read_mutex.unlock();
+            }
+        }
+    }
+
+    /// 通知等待空槽位的写者
+    unsafe fn notify_empty(&mut self) {
+        let header = self.header_mut();
+        futex::bump_and_wake(&header.empty_value);
+
+        if header.empty_waiters.load(Ordering::SeqCst) == 0 {
+            return;
+        }
+        unsafe {
+            if lock_counted(&mut header.write_mutex, &header.lock_contended_count, None) {
+                header.empty_cond.signal();
+                header.write_mutex.unlock();
+            }
+        }
+    }
+
+    /// 非阻塞抢占slot，如果队列满立即返回错误
+    ///
+    /// # Safety
+    /// 视图必须指向已映射并初始化过的共享内存。
+    pub unsafe fn hold(&mut self) -> Option<usize> {
+        if !self.is_fair() {
+            return unsafe { self.hold_now() };
+        }
+
+        // 有写者排队时不插队
+        let ticket = self.try_take_turn()?;
+        let index = unsafe { self.hold_now() };
+        self.advance_turn(ticket.wrapping_add(1));
+        index
+    }
+
+    /// 非阻塞抢占空槽位；覆盖模式下队列满时丢弃最早的消息后重试
+    unsafe fn hold_now(&mut self) -> Option<usize> {
+        for _ in 0..self.capacity {
+            if let Some(index) = unsafe { self.claim_empty_now() } {
+                return Some(index);
+            }
+            if self.full_policy() != FullPolicy::OverwriteOldest || !unsafe { self.drop_oldest() } {
+                return None;
+            }
+        }
+        None
+    }
+
+    unsafe fn claim_empty_now(&mut self) -> Option<usize> {
+        if self.is_lock_free() {
+            return self.claim_empty_lock_free();
+        }
+
+        if !unsafe { self.lock_write(None) } {
+            return None;
+        }
+
+        let index = self.claim_empty();
+
+        unsafe {
+            self.header_mut().write_mutex.unlock();
+        }
+
+        index
+    }
+
+    /// 非阻塞取出最早的 READY 槽位
+    unsafe fn claim_ready_now(&mut self) -> Option<usize> {
+        if self.is_lock_free() {
+            return self.claim_ready_lock_free();
+        }
+
+        if !unsafe { self.lock_read(None) } {
+            return None;
+        }
+
+        let index = self.claim_ready();
+
+        unsafe {
+            self.header_mut().read_mutex.unlock();
+        }
+
+        index
+    }
+
+    /// 按读取顺序取出最早的 READY 槽位并丢弃，没有可丢弃的槽位时返回 `false`
+    unsafe fn drop_oldest(&mut self) -> bool {
+        let Some(index) = (unsafe { self.claim_ready_now() }) else {
+            return false;
+        };
+        if !self.begin(index, LEASE_READER) {
+            return false;
+        }
+        self.header().dropped_count.fetch_add(1, Ordering::Relaxed);
+        unsafe { self.discard(index) };
+        true
+    }
+
+    /// 阻塞抢占slot，队列满时在条件变量上休眠直到有槽位被释放
+    ///
+    /// `timeout` 为 `None` 时无限等待，超时返回 `None`
+    ///
+    /// 等待方式不是 [`WaitStrategy::Blocking`] 时改为轮询，见 [`DynSharedSlotPipe::with_wait_strategy`]。
+    ///
+    /// # Safety
+    /// 视图必须指向已映射并初始化过的共享内存。
+    pub unsafe fn hold_timeout(&mut self, timeout: Option<Duration>) -> Option<usize> {
+        if !self.is_fair() {
+            return unsafe { self.hold_wait(timeout) };
+        }
+
+        let deadline = timeout.map(|t| std::time::Instant::now() + t);
+        let ticket = self.header().next_ticket.fetch_add(1, Ordering::AcqRel);
+        if !self.wait_turn(ticket, deadline) {
+            return None;
+        }
+        let remaining = deadline.map(|d| d.saturating_duration_since(std::time::Instant::now()));
+        let index = unsafe { self.hold_wait(remaining) };
+        self.advance_turn(ticket.wrapping_add(1));
+        index
+    }
+
+    /// 没有写者排队时直接取得叫号，返回票号
+    fn try_take_turn(&self) -> Option<u32> {
+        let header = self.header();
+        let serving = header.now_serving.load(Ordering::Acquire);
+        header
+            .next_ticket
+            .compare_exchange(
+                serving,
+                serving.wrapping_add(1),
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            )
+            .ok()?;
+        header
+            .serving_pid
+            .store(std::process::id(), Ordering::Release);
+        Some(serving)
+    }
+
+    /// 等待叫到 `ticket`，超时则放弃该票号并返回 `false`
+    ///
+    /// 叫号持有者所在进程退出时跳过它，避免队伍永久停滞。
+    fn wait_turn(&self, ticket: u32, deadline: Option<std::time::Instant>) -> bool {
+        let header = self.header();
+        loop {
+            let serving = header.now_serving.load(Ordering::Acquire);
+            if serving == ticket {
+                header
+                    .serving_pid
+                    .store(std::process::id(), Ordering::Release);
+                return true;
+            }
+
+            let remaining =
+                deadline.map(|d| d.saturating_duration_since(std::time::Instant::now()));
+            if remaining.is_some_and(|r| r.is_zero()) {
+                self.cancel_ticket(ticket);
+                return false;
+            }
+
+            let holder = header.serving_pid.load(Ordering::Acquire);
+            if holder != 0
+                && !shm_sync::process_alive(holder)
+                && header
+                    .serving_pid
+                    .compare_exchange(holder, 0, Ordering::AcqRel, Ordering::Relaxed)
+                    .is_ok()
+            {
+                tracing::warn!("叫号持有者 {} 已退出，跳过票号 {}", holder, serving);
+                self.advance_turn(serving.wrapping_add(1));
+                continue;
+            }
+
+            let wait = remaining.map_or(FAIR_CHECK_INTERVAL, |r| r.min(FAIR_CHECK_INTERVAL));
+            futex::futex_wait(&header.now_serving, serving, Some(wait));
+        }
+    }
+
+    /// 放弃尚未叫到的票号；恰好轮到自己时把叫号让给下一位
+    fn cancel_ticket(&self, ticket: u32) {
+        let mark = &self.header().cancelled_tickets[ticket as usize % FAIR_WINDOW];
+        mark.store(ticket as u64 + 1, Ordering::Release);
+        if self.header().now_serving.load(Ordering::Acquire) == ticket
+            && mark
+                .compare_exchange(ticket as u64 + 1, 0, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+        {
+            self.advance_turn(ticket.wrapping_add(1));
+        }
+    }
+
+    /// 叫号推进到 `next`，跳过已放弃的票号并唤醒排队者
+    fn advance_turn(&self, mut next: u32) {
+        let header = self.header();
+        header.serving_pid.store(0, Ordering::Release);
+        loop {
+            header.now_serving.store(next, Ordering::Release);
+            let mark = &header.cancelled_tickets[next as usize % FAIR_WINDOW];
+            if mark
+                .compare_exchange(next as u64 + 1, 0, Ordering::AcqRel, Ordering::Relaxed)
+                .is_err()
+            {
+                break;
+            }
+            next = next.wrapping_add(1);
+        }
+        futex::futex_wake(&header.now_serving, u32::MAX);
+    }
+
+    unsafe fn hold_wait(&mut self, timeout: Option<Duration>) -> Option<usize> {
+        // 覆盖模式先丢弃最早的消息，没有可丢弃的（槽位都被持有）时才等待
+        if self.full_policy() == FullPolicy::OverwriteOldest
+            && let Some(index) = unsafe { self.hold_now() }
+        {
+            return Some(index);
+        }
+
+        if self.wait.polls() {
+            return unsafe { self.poll_claim(timeout, Self::hold_now) };
+        }
+
+        let deadline = timeout.map(shm_sync::deadline_after);
+
+        // 无锁模式先走快速路径，只有需要休眠时才使用互斥锁
+        if self.is_lock_free()
+            && let Some(index) = self.claim_empty_lock_free()
+        {
+            return Some(index);
+        }
+
+        // 等待互斥锁同样受超时约束
+        if !unsafe { self.lock_write(deadline.as_ref()) } {
+            return None;
+        }
+
+        self.header().empty_waiters.fetch_add(1, Ordering::SeqCst);
+        let index = loop {
+            if let Some(index) = self.claim_empty_by_mode() {
+                break Some(index);
+            }
+
+            if deadline.as_ref().is_some_and(shm_sync::is_expired) {
+                break None;
+            }
+
+            let header = self.header_mut();
+            unsafe {
+                header
+                    .empty_cond
+                    .wait(&mut header.write_mutex, deadline.as_ref());
+            }
+        };
+        self.header().empty_waiters.fetch_sub(1, Ordering::SeqCst);
+
+        unsafe {
+            self.header_mut().write_mutex.unlock();
+        }
+
+        index
+    }
+
+    /// 向指定索引的槽位写入数据
+    ///
+    /// # Safety
+    /// 视图必须指向已映射并初始化过的共享内存。
+    pub unsafe fn write<T: bincode::Encode>(&mut self, index: usize, data: &T) -> Result<u64> {
+        // 直接序列化到槽位内存，不经过中间 Vec
+        unsafe {
+            self.try_write_with(index, |buf| {
+                bincode::encode_into_slice(data, buf, bincode::config::standard()).map_err(|e| {
+                    match e {
+                        bincode::error::EncodeError::UnexpectedEnd => PayloadTooLarge {
+                            size: codec::bincode_len(data),
+                            capacity: buf.len(),
+                        }
+                        .into(),
+                        _ => anyhow::anyhow!("Serialization failed").into(),
+                    }
+                })
+            })
+        }
+    }
+
+    /// 由调用者直接填充槽位内存（零拷贝写入）
+    ///
+    /// 闭包收到整个槽位缓冲区，返回实际写入的字节数，可以为 0；超过
+    /// 槽位大小视为失败，槽位会被放弃。
+    ///
+    /// # Safety
+    /// 视图必须指向已映射并初始化过的共享内存。
+    pub unsafe fn write_with<F>(&mut self, index: usize, fill: F) -> Result<u64>
+    where
+        F: FnOnce(&mut [u8]) -> usize,
+    {
+        unsafe { self.try_write_with(index, |buf| Ok(fill(buf))) }
+    }
+
+    /// 由可能失败的闭包填充槽位并发布为 READY
+    ///
+    /// 闭包失败时槽位被放弃，错误原样返回。
+    ///
+    /// # Safety
+    /// 视图必须指向已映射并初始化过的共享内存。
+    pub unsafe fn try_write_with<F>(&mut self, index: usize, fill: F) -> Result<u64>
+    where
+        F: FnOnce(&mut [u8]) -> Result<usize>,
+    {
+        unsafe { self.try_write_tagged(index, None, fill) }
+    }
+
+    /// 与 [`DynSharedSlotPipe::try_write_with`] 相同，但由调用者指定槽位的 request_id
+    ///
+    /// `request_id` 为 `None` 时由管道自增生成；RPC 响应借此沿用请求方的 ID。
+    ///
+    /// # Safety
+    /// 视图必须指向已映射并初始化过的共享内存。
+    pub unsafe fn try_write_tagged<F>(
+        &mut self,
+        index: usize,
+        request_id: Option<u64>,
+        fill: F,
+    ) -> Result<u64>
+    where
+        F: FnOnce(&mut [u8]) -> Result<usize>,
+    {
+        let written = unsafe { self.write_slot(index, request_id, 0, fill)? };
+        self.header().sent_count.fetch_add(1, Ordering::Relaxed);
+        self.record_arrival();
+        Ok(written)
+    }
+
+    /// 更新写入间隔的滑动平均，供 [`WaitStrategy::Adaptive`] 估计消息到达速率
+    ///
+    /// 并发写者之间的更新可能相互覆盖，平均值只是估计。
+    fn record_arrival(&self) {
+        let header = self.header();
+        let now = shm_sync::monotonic_nanos();
+        let last = header.last_arrival_nanos.swap(now, Ordering::Relaxed);
+        if last == 0 || now <= last {
+            return;
+        }
+        let gap = now - last;
+        let average = header.arrival_gap_nanos.load(Ordering::Relaxed);
+        let average = if average == 0 {
+            gap
+        } else {
+            average - average / ARRIVAL_SMOOTHING + gap / ARRIVAL_SMOOTHING
+        };
+        header.arrival_gap_nanos.store(average, Ordering::Relaxed);
+    }
+
+    /// 按本视图的等待方式开始一次轮询等待
+    pub(crate) fn backoff(&self) -> Backoff {
+        match self.wait {
+            WaitStrategy::Adaptive => Backoff::adaptive(self.arrival_stats()),
+            wait => Backoff::new(wait),
+        }
+    }
+
+    /// 最近写入间隔的滑动平均与距最近一次写入的时间，还没有写入过两条消息时间隔为 `None`
+    pub fn arrival_stats(&self) -> ArrivalStats {
+        let header = self.header();
+        let last = header.last_arrival_nanos.load(Ordering::Relaxed);
+        let gap = header.arrival_gap_nanos.load(Ordering::Relaxed);
+        ArrivalStats {
+            gap: (gap > 0).then(|| Duration::from_nanos(gap)),
+            idle: Duration::from_nanos(shm_sync::monotonic_nanos().saturating_sub(last)),
+        }
+    }
+
+    unsafe fn write_slot<F>(
+        &mut self,
+        index: usize,
+        request_id: Option<u64>,
+        delivery_attempts: u32,
+        fill: F,
+    ) -> Result<u64>
+    where
+        F: FnOnce(&mut [u8]) -> Result<usize>,
+    {
+        if index >= self.capacity {
+            return Err(anyhow::anyhow!("Slot index out of bounds").into());
+        }
+
+        // 验证槽位由调用者持有并开始写入
+        if !self.begin(index, LEASE_WRITER) {
+            return Err(anyhow::Error::from(TokioIPCError::SlotNotReady)
+                .context("Slot not ready for writing")
+                .into());
+        }
+
+        let len = match fill(self.data_mut(index)) {
+            Ok(len) if len > self.slot_size => {
+                unsafe { self.abandon(index) };
+                return Err(PayloadTooLarge {
+                    size: len,
+                    capacity: self.slot_size,
+                }
+                .into());
+            }
+            Ok(len) => len,
+            Err(e) => {
+                unsafe { self.abandon(index) };
+                return Err(e);
+            }
+        };
+        let lock_free = self.is_lock_free();
+
+        // 计算校验和
+        let integrity = self.integrity();
+        let checksum = integrity.checksum(&self.data_mut(index)[..len]);
+        let request_id =
+            request_id.unwrap_or_else(|| self.header().seq.fetch_add(1, Ordering::Relaxed));
+        let mac = self.authenticator.map_or(0, |authenticator| {
+            authenticator.tag(request_id, &self.data_mut(index)[..len])
+        });
+
+        // 更新槽位元数据
+        let slot = self.slot_mut(index);
+        slot.data_size = len as u32;
+        slot.checksum = checksum;
+        slot.mac = mac;
+        slot.request_id = request_id;
+        slot.delivery_attempts = delivery_attempts;
+        slot.enqueued_at = shm_sync::monotonic_nanos();
+
+        // 标记为就绪
+        slot.clear_lease();
+        slot.state.store(SlotState::READY as u32, Ordering::Release);
+        if lock_free {
+            Self::publish_lock_free(slot);
+        }
+
+        // 设置"有数据"标志（原子操作，立即对其他进程可见）
+        self.header().begin.store(true, Ordering::SeqCst);
+
+        // 唤醒等待数据的读者
+        unsafe {
+            self.notify_ready();
+        }
+
+        Ok(request_id)
+    }
+
+    /// 放弃一个已抢占但写入失败的槽位
+    ///
+    /// 锁模式下槽位直接回到 EMPTY；无锁模式下槽位必须按序发布，
+    /// 因此在 `data_size` 中置位 [`ABANDONED_SLOT`] 后发布，消费者会跳过它。
+    pub(crate) unsafe fn abandon(&mut self, index: usize) {
+        let lock_free = self.is_lock_free();
+        let slot = self.slot_mut(index);
+        slot.data_size = if lock_free { ABANDONED_SLOT } else { 0 };
+        slot.checksum = 0;
+        slot.mac = 0;
+        if lock_free {
+            slot.state.store(SlotState::READY as u32, Ordering::Release);
+            Self::publish_lock_free(slot);
+            unsafe { self.notify_ready() };
+        } else {
+            slot.clear_lease();
+            slot.state.store(SlotState::EMPTY as u32, Ordering::Release);
+            unsafe { self.notify_empty() };
+        }
+    }
+
+    /// 获取READY的 slot, 返回index
+    ///
+    /// 没有数据时在条件变量上休眠，直到写者写入新数据
+    ///
+    /// # Safety
+    /// 视图必须指向已映射并初始化过的共享内存。
+    pub unsafe fn fetch(&mut self) -> Option<usize> {
+        unsafe { self.fetch_timeout(None) }
+    }
+
+    /// 获取READY的 slot，最多等待 `timeout`
+    ///
+    /// `timeout` 为 `None` 时无限等待，超时返回 `None`
+    ///
+    /// 等待方式不是 [`WaitStrategy::Blocking`] 时改为轮询，见 [`DynSharedSlotPipe::with_wait_strategy`]。
+    ///
+    /// # Safety
+    /// 视图必须指向已映射并初始化过的共享内存。
+    pub unsafe fn fetch_timeout(&mut self, timeout: Option<Duration>) -> Option<usize> {
+        if self.wait.polls() {
+            return unsafe { self.poll_claim(timeout, Self::claim_ready_now) };
+        }
+
+        let deadline = timeout.map(shm_sync::deadline_after);
+
+        // 无锁模式先走快速路径，只有需要休眠时才使用互斥锁
+        if self.is_lock_free()
+            && let Some(index) = self.claim_ready_lock_free()
+        {
+            return Some(index);
+        }
+
+        if !unsafe { self.lock_read(deadline.as_ref()) } {
+            return None;
+        }
+
+        self.header().ready_waiters.fetch_add(1, Ordering::SeqCst);
+        let index = loop {
+            if let Some(index) = self.claim_ready_by_mode() {
+                break Some(index);
+            }
+
+            // 数据取完，设置"无数据"标志
+            self.header().begin.store(false, Ordering::SeqCst);
+
+            if deadline.as_ref().is_some_and(shm_sync::is_expired) {
+                break None;
+            }
+
+            let header = self.header_mut();
+            unsafe {
+                header
+                    .ready_cond
+                    .wait(&mut header.read_mutex, deadline.as_ref());
+            }
+        };
+        self.header().ready_waiters.fetch_sub(1, Ordering::SeqCst);
+
+        unsafe {
+            self.header_mut().read_mutex.unlock();
+        }
+
+        index
+    }
+
+    /// 轮询等待方式：反复尝试 `claim`，两次尝试之间按 [`Backoff`] 忙等或休眠，超时返回 `None`
+    unsafe fn poll_claim(
+        &mut self,
+        timeout: Option<Duration>,
+        claim: unsafe fn(&mut Self) -> Option<usize>,
+    ) -> Option<usize> {
+        let deadline = timeout.map(|t| std::time::Instant::now() + t);
+        let mut backoff = self.backoff();
+        loop {
+            if let Some(index) = unsafe { claim(self) } {
+                return Some(index);
+            }
+            let remaining =
+                deadline.map(|d| d.saturating_duration_since(std::time::Instant::now()));
+            if remaining.is_some_and(|r| r.is_zero()) {
+                return None;
+            }
+            backoff.snooze(remaining);
+        }
+    }
+
+    ///  获取 slot 的 data
+    /// 并释放 slot 为 EMPTY
+    ///
+    /// # Safety
+    /// 视图必须指向已映射并初始化过的共享内存。
+    pub unsafe fn read<T: bincode::Decode<()>>(
+        &mut self,
+        index: usize,
+    ) -> Result<Option<(u64, T)>> {
+        let (request_id, data) = unsafe {
+            self.try_read_with(index, |buf| {
+                bincode::decode_from_slice::<T, _>(buf, bincode::config::standard())
+                    .map(|(data, _)| data)
+                    .map_err(|_| anyhow::anyhow!("Deserialization failed").into())
+            })?
+        };
+        Ok(Some((request_id, data)))
+    }
+
+    /// 直接访问槽位内存读取数据（零拷贝读取）
+    ///
+    /// 闭包收到校验通过的有效数据切片，返回值原样带出；闭包返回后槽位即被释放。
+    ///
+    /// # Safety
+    /// 视图必须指向已映射并初始化过的共享内存。
+    pub unsafe fn read_with<F, R>(&mut self, index: usize, visit: F) -> Result<(u64, R)>
+    where
+        F: FnOnce(&[u8]) -> R,
+    {
+        unsafe { self.try_read_with(index, |buf| Ok(visit(buf))) }
+    }
+
+    /// 校验槽位数据、交给可能失败的闭包处理并释放槽位
+    ///
+    /// # Safety
+    /// 视图必须指向已映射并初始化过的共享内存。
+    pub unsafe fn try_read_with<F, R>(&mut self, index: usize, visit: F) -> Result<(u64, R)>
+    where
+        F: FnOnce(&[u8]) -> Result<R>,
+    {
+        if index >= self.capacity {
+            return Err(anyhow::anyhow!("Slot index out of bounds").into());
+        }
 
-unsafe impl<const N: usize, const SLOT_SIZE: usize> Sync for SharedSlotPipe<N, SLOT_SIZE> {}
+        // 验证槽位由调用者持有并开始读取
+        if !self.begin(index, LEASE_READER) {
+            return Err(anyhow::Error::from(TokioIPCError::SlotNotReady)
+                .context("Slot not ready for reading")
+                .into());
+        }
+        let slot = self.slot(index);
 
-impl<const N: usize, const SLOT_SIZE: usize> SharedSlotPipe<N, SLOT_SIZE> {
-    /// 打开或创建共享内存
-    pub unsafe fn open(name: &str, create: bool) -> Result<*mut Self> {
-        let cname = if name.starts_with('/') {
-            CString::new(name)
+        let request_id = slot.request_id;
+        let data_size = (slot.data_size as usize).min(self.slot_size);
+        let checksum = slot.checksum;
+        let mac = slot.mac;
+        let first_delivery = slot.delivery_attempts == 0;
+        let enqueued_at = slot.enqueued_at;
+        let integrity = self.integrity();
+        let authentic = self.mac_verifier();
+
+        // 验证校验和与认证码
+        let data_slice = &self.data_mut(index)[..data_size];
+        let result = if !integrity.verify(data_slice, checksum) {
+            Err(TokioIPCError::ChecksumMismatch.into())
+        } else if !authentic(request_id, data_slice, mac) {
+            Err(TokioIPCError::AuthenticationFailed.into())
         } else {
-            CString::new(format!("/{}", name))
+            visit(data_slice)
         };
 
-        let cname = match cname {
-            Ok(name) => name,
-            Err(_) => {
-                return Err(anyhow::anyhow!("Failed to create CString from name"));
-            }
-        };
+        // 重置slot
+        let slot = self.slot_mut(index);
+        slot.data_size = 0;
+        slot.checksum = 0;
+        slot.mac = 0;
+        slot.request_id = 0;
+        slot.delivery_attempts = 0;
 
-        let flags = if create { O_CREAT | O_RDWR } else { O_RDWR };
-        let fd = unsafe { libc::shm_open(cname.as_ptr(), flags, 0o666) };
-        if fd == -1 {
-            return Err(anyhow::anyhow!("shm_open failed with errno: {}", unsafe {
-                *libc::__errno_location()
-            }));
+        unsafe {
+            self.release(index);
         }
-
-        if create {
-            if unsafe { ftruncate(fd, mem::size_of::<Self>() as i64) } == -1 {
-                unsafe { close(fd) };
-                return Err(anyhow::anyhow!("ftruncate failed with errno: {}", unsafe {
-                    *libc::__errno_location()
-                }));
+        if result.is_ok() {
+            self.header().received_count.fetch_add(1, Ordering::Relaxed);
+            if first_delivery {
+                self.record_latency(enqueued_at);
             }
         }
+        result.map(|data| (request_id, data))
+    }
 
-        let size = mem::size_of::<Self>();
+    /// 校验槽位数据并交给闭包处理，但不释放槽位，返回 request_id 与已失败的投递次数
+    ///
+    /// 处理成功后调用 [`DynSharedSlotPipe::ack`] 释放，失败时调用
+    /// [`DynSharedSlotPipe::requeue`] 重新投递。校验失败或闭包返回错误时槽位直接释放。
+    ///
+    /// # Safety
+    /// 视图必须指向已映射并初始化过的共享内存。
+    pub unsafe fn peek_with<F, R>(&mut self, index: usize, visit: F) -> Result<(u64, u32, R)>
+    where
+        F: FnOnce(&[u8]) -> Result<R>,
+    {
+        if index >= self.capacity {
+            return Err(anyhow::anyhow!("Slot index out of bounds").into());
+        }
 
-        let addr = unsafe {
-            mmap(
-                ptr::null_mut(),
-                size,
-                PROT_READ | PROT_WRITE,
-                MAP_SHARED,
-                fd,
-                0,
-            )
+        if !self.begin(index, LEASE_READER) {
+            return Err(anyhow::Error::from(TokioIPCError::SlotNotReady)
+                .context("Slot not ready for reading")
+                .into());
+        }
+        let slot = self.slot(index);
+
+        let (request_id, attempts) = (slot.request_id, slot.delivery_attempts);
+        let data_size = (slot.data_size as usize).min(self.slot_size);
+        let checksum = slot.checksum;
+        let mac = slot.mac;
+        let enqueued_at = slot.enqueued_at;
+        let integrity = self.integrity();
+        let authentic = self.mac_verifier();
+        let data_slice = &self.data_mut(index)[..data_size];
+        let result = if !integrity.verify(data_slice, checksum) {
+            Err(TokioIPCError::ChecksumMismatch.into())
+        } else if !authentic(request_id, data_slice, mac) {
+            Err(TokioIPCError::AuthenticationFailed.into())
+        } else {
+            visit(data_slice)
         };
+        if result.is_err() {
+            unsafe { self.ack(index)? };
+        } else if attempts == 0 {
+            // 重新投递的消息不重复计入延迟
+            self.record_latency(enqueued_at);
+        }
+        result.map(|data| (request_id, attempts, data))
+    }
 
-        unsafe {
-            close(fd);
+    /// 确认处理完成，释放由 [`DynSharedSlotPipe::peek_with`] 读取的槽位
+    ///
+    /// # Safety
+    /// 视图必须指向已映射并初始化过的共享内存。
+    pub unsafe fn ack(&mut self, index: usize) -> Result<()> {
+        if index >= self.capacity {
+            return Err(anyhow::anyhow!("Slot index out of bounds").into());
         }
-        if addr == MAP_FAILED {
-            return Err(anyhow::anyhow!("mmap failed"));
+        if !self.begin(index, LEASE_READER) {
+            return Err(anyhow::Error::from(TokioIPCError::SlotNotReady)
+                .context("Slot not held for reading")
+                .into());
         }
+        unsafe { self.complete(index) };
+        Ok(())
+    }
 
-        let shared_pipe = addr as *mut Self;
-
-        if create {
-            unsafe {
-                (*shared_pipe).init()?;
-            }
+    /// 确认处理完成，把消息的 request_id 记入已提交位置后释放槽位，返回该 request_id
+    ///
+    /// 已提交位置保存在共享内存头部，只增不减，读者重启后仍可读取。
+    ///
+    /// # Safety
+    /// 视图必须指向已映射并初始化过的共享内存。
+    pub unsafe fn commit(&mut self, index: usize) -> Result<u64> {
+        if index >= self.capacity {
+            return Err(anyhow::anyhow!("Slot index out of bounds").into());
+        }
+        if !self.begin(index, LEASE_READER) {
+            return Err(anyhow::Error::from(TokioIPCError::SlotNotReady)
+                .context("Slot not held for reading")
+                .into());
         }
+        let request_id = self.slot(index).request_id;
+        self.header()
+            .committed_offset
+            .fetch_max(request_id, Ordering::AcqRel);
+        unsafe { self.complete(index) };
+        Ok(request_id)
+    }
 
-        Ok(shared_pipe)
+    /// 已提交的最大 request_id，尚未提交过时为 0
+    pub fn committed_offset(&self) -> u64 {
+        self.header().committed_offset.load(Ordering::Acquire)
     }
 
-    unsafe fn init(&mut self) -> Result<()> {
-        let mut attr: pthread_mutexattr_t = unsafe { mem::zeroed() };
-        unsafe {
-            pthread_mutexattr_init(&mut attr);
-            pthread_mutexattr_setpshared(&mut attr, PTHREAD_PROCESS_SHARED);
-            pthread_mutexattr_setrobust(&mut attr, PTHREAD_MUTEX_ROBUST);
+    /// 清空读者已占用（INPROGRESS）的槽位并释放，计入读取数量
+    unsafe fn complete(&mut self, index: usize) {
+        unsafe { self.discard(index) };
+        self.header().received_count.fetch_add(1, Ordering::Relaxed);
+    }
 
-            if pthread_mutex_init(&mut self.write_mutex, &attr) != 0 {
-                return Err(anyhow::anyhow!("Failed to initialize write mutex"));
-            }
+    /// 清空读者已占用（INPROGRESS）的槽位并释放
+    unsafe fn discard(&mut self, index: usize) {
+        self.clear_message(index);
+        unsafe { self.release(index) };
+    }
 
-            if pthread_mutex_init(&mut self.read_mutex, &attr) != 0 {
-                return Err(anyhow::anyhow!("Failed to initialize read mutex"));
-            }
-        }
+    /// 清空槽位中的消息元数据
+    fn clear_message(&mut self, index: usize) {
+        let slot = self.slot_mut(index);
+        slot.data_size = 0;
+        slot.checksum = 0;
+        slot.mac = 0;
+        slot.request_id = 0;
+        slot.delivery_attempts = 0;
+    }
 
-        self.write_pointer = 0;
-        self.read_pointer = 0;
-        self.seq = AtomicU64::new(1);
-        self.begin = AtomicBool::new(false);
-        self.shared_value = AtomicU32::new(0);
+    /// 处理失败，失败次数加一后重新投递槽位中的消息
+    ///
+    /// 锁模式下槽位原地回到 READY；无锁模式下槽位必须按序发布，先抢占新的空槽位、
+    /// 把消息复制过去后才释放原槽位。没有空槽位时返回 [`TokioIPCError::QueueFull`]，原槽位仍由读者持有，
+    /// 调用者可以稍后重试或把消息转入死信。是否超过最大投递次数
+    /// 由调用者通过 [`DynSharedSlotPipe::exhausted`] 判断。
+    ///
+    /// # Safety
+    /// 视图必须指向已映射并初始化过的共享内存。
+    pub unsafe fn requeue(&mut self, index: usize) -> Result<()> {
+        if index >= self.capacity {
+            return Err(anyhow::anyhow!("Slot index out of bounds").into());
+        }
+        if !self.begin(index, LEASE_READER) {
+            return Err(anyhow::Error::from(TokioIPCError::SlotNotReady)
+                .context("Slot not held for reading")
+                .into());
+        }
 
-        for slot in self.slots.iter_mut() {
-            slot.state = AtomicU32::new(SlotState::EMPTY as u32);
-            slot.request_id = 0;
-            slot.data_size = 0;
-            slot.checksum = 0;
-            slot.data = [0; SLOT_SIZE];
+        if !self.is_lock_free() {
+            unsafe { self.requeue_in_place(index) };
+            return Ok(());
         }
 
+        let attempts = self.slot(index).delivery_attempts + 1;
+        unsafe { self.republish(index, attempts)? };
+        self.header()
+            .redelivered_count
+            .fetch_add(1, Ordering::Relaxed);
         Ok(())
     }
 
-    /// 非阻塞抢占slot，如果队列满立即返回错误
-    pub unsafe fn hold(&mut self) -> Option<usize> {
-        let result = unsafe { pthread_mutex_lock(&mut self.write_mutex) };
-        if result == EOWNERDEAD {
-            unsafe {
-                pthread_mutex_consistent(&mut self.write_mutex);
-            }
-        } else if result != 0 {
-            return None;
+    /// 无锁模式：把读者持有的槽位中的消息以 `attempts` 次投递重新发布到新槽位
+    ///
+    /// 先占到新槽位再释放原槽位，任何一步失败消息都还留在原槽位中。
+    unsafe fn republish(&mut self, index: usize, attempts: u32) -> Result<()> {
+        let target =
+            unsafe { self.hold_timeout(Some(REQUEUE_TIMEOUT)) }.ok_or(TokioIPCError::QueueFull)?;
+        let slot = self.slot(index);
+        let request_id = slot.request_id;
+        let data_size = (slot.data_size as usize).min(self.slot_size);
+        let data = self.data_mut(index)[..data_size].to_vec();
+        unsafe {
+            self.write_slot(target, Some(request_id), attempts, |buf| {
+                buf[..data.len()].copy_from_slice(&data);
+                Ok(data.len())
+            })?;
+            self.ack(index)
         }
+    }
 
-        let mut index = None;
-        let start_index = self.write_pointer;
-
-        for i in 0..N {
-            let slot_index = (start_index + i) % N;
-            let slot = &self.slots[slot_index];
-
-            // 简单的状态检查，无需复杂的原子操作
-            if slot.state.load(Ordering::Acquire) == SlotState::EMPTY as u32 {
-                slot.state
-                    .store(SlotState::WRITING as u32, Ordering::Release);
-                self.write_pointer = (slot_index + 1) % N;
-                index = Some(slot_index);
-                break;
-            }
+    /// 把读者持有的槽位中的消息原样放回队列，投递次数不变
+    ///
+    /// 锁模式下槽位原地回到 READY；无锁模式下重新发布，没有空槽位时返回
+    /// [`TokioIPCError::QueueFull`]，原槽位仍由读者持有。
+    unsafe fn put_back(&mut self, index: usize) -> Result<()> {
+        if self.is_lock_free() {
+            let attempts = self.slot(index).delivery_attempts;
+            return unsafe { self.republish(index, attempts) };
         }
+        let slot = self.slot(index);
+        slot.clear_lease();
+        slot.state.store(SlotState::READY as u32, Ordering::Release);
+        self.header().begin.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// 锁模式：槽位原地回到 READY，失败次数加一
+    unsafe fn requeue_in_place(&mut self, index: usize) {
+        let slot = self.slot_mut(index);
+        slot.delivery_attempts += 1;
+        slot.clear_lease();
+        slot.state.store(SlotState::READY as u32, Ordering::Release);
+        self.header()
+            .redelivered_count
+            .fetch_add(1, Ordering::Relaxed);
+        self.header().begin.store(true, Ordering::SeqCst);
+        unsafe { self.notify_ready() };
+    }
 
+    /// 将已读取的槽位归还为 EMPTY 并唤醒等待空槽位的写者
+    unsafe fn release(&mut self, index: usize) {
+        self.vacate(index);
         unsafe {
-            pthread_mutex_unlock(&mut self.write_mutex);
+            self.notify_empty();
         }
+    }
 
-        index
+    /// 将槽位归还为 EMPTY，不唤醒等待者
+    fn vacate(&self, index: usize) {
+        let slot = self.slot(index);
+        if self.is_lock_free() {
+            self.release_lock_free(slot);
+        } else {
+            slot.clear_lease();
+            slot.state.store(SlotState::EMPTY as u32, Ordering::Release);
+        }
     }
 
-    /// 向指定索引的槽位写入数据
-    pub unsafe fn write<T: bincode::Encode>(&mut self, index: usize, data: &T) -> Result<u64> {
-        if index >= N {
-            return Err(anyhow::anyhow!("Slot index out of bounds"));
+    /// 回收租约超过 `timeout` 仍未完成的槽位（持有者崩溃或卡死），返回回收数量
+    ///
+    /// 写者持有的槽位在锁模式下回到 EMPTY，无锁模式下以空槽发布（消费者会跳过）；
+    /// 读者持有的槽位直接释放，数据被丢弃。超时应远大于正常处理耗时，
+    /// 否则仍在工作的持有者之后的写入/读取会因状态不符而失败。
+    ///
+    /// 无锁模式下已被抢占却没有租约的槽位（抢占者在记录租约前被杀）在本次调用时补记租约，
+    /// 超时后的调用才会回收；`Duration::ZERO` 时立即回收。
+    ///
+    /// # Safety
+    /// 视图必须指向已映射并初始化过的共享内存。
+    pub unsafe fn reclaim_stuck(&mut self, timeout: Duration) -> usize {
+        let now = shm_sync::monotonic_millis();
+        let timeout_ms = timeout.as_millis() as u64;
+        let lock_free = self.is_lock_free();
+        let mut reclaimed = 0;
+
+        for index in 0..self.capacity {
+            let slot = self.slot(index);
+            if lock_free
+                && slot.leased_at.load(Ordering::Acquire) == 0
+                && let Some(role) = self.lock_free_holder(index)
+            {
+                // 持有者恰好记录了租约时 CAS 失败，以持有者的租约为准；补记的租约不属于任何进程
+                if slot
+                    .leased_at
+                    .compare_exchange(0, now.max(1), Ordering::AcqRel, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    slot.lease_role.store(role, Ordering::Relaxed);
+                }
+            }
+            let leased_at = slot.leased_at.load(Ordering::Acquire);
+            if leased_at == 0 || now.saturating_sub(leased_at) < timeout_ms {
+                continue;
+            }
+            // 抢占回收权；持有者恰好完成或其他回收者已处理时 CAS 失败
+            if slot
+                .leased_at
+                .compare_exchange(leased_at, 0, Ordering::AcqRel, Ordering::Relaxed)
+                .is_err()
+            {
+                continue;
+            }
+            let role = slot.lease_role.swap(0, Ordering::AcqRel) & LEASE_ROLE_MASK;
+            if unsafe { self.reclaim_slot(index, role) } {
+                reclaimed += 1;
+            }
         }
 
-        let slot = &mut self.slots[index];
+        if reclaimed > 0 {
+            self.header()
+                .reclaimed_count
+                .fetch_add(reclaimed as u64, Ordering::Relaxed);
+        }
+        reclaimed
+    }
 
-        // 验证槽位状态
-        if slot.state.load(Ordering::Acquire) != SlotState::INPROGRESS as u32 {
-            return Err(anyhow::anyhow!("Slot not ready for writing"));
+    /// 重新投递读者持有超过 `timeout` 仍未确认的消息（读者在确认前崩溃），返回重新投递的数量
+    ///
+    /// 与 [`DynSharedSlotPipe::reclaim_stuck`] 丢弃读者槽位不同，消息回到队列等待再次读取，
+    /// 失败次数加一。写者持有的槽位不处理。超时的租约由调用者接管，原持有者之后无法再确认该槽位。
+    ///
+    /// 无锁模式下没有空槽位时消息无法重新投递，而原槽位挡住了之后的生产位置，等待也不会
+    /// 腾出空槽位：此时以槽位索引调用 `unplaced`，槽位仍由读者持有，调用者取出消息后
+    /// 确认释放（例如转入死信）。
+    ///
+    /// # Safety
+    /// 视图必须指向已映射并初始化过的共享内存。
+    pub unsafe fn requeue_stuck_readers(
+        &mut self,
+        timeout: Duration,
+        mut unplaced: impl FnMut(usize),
+    ) -> usize {
+        let now = shm_sync::monotonic_millis();
+        let timeout_ms = timeout.as_millis() as u64;
+        let lock_free = self.is_lock_free();
+        let mut requeued = 0;
+
+        for index in 0..self.capacity {
+            let slot = self.slot(index);
+            let owner = slot.lease_role.load(Ordering::Relaxed);
+            let role = if lock_free {
+                self.lock_free_holder(index)
+            } else {
+                Some(owner & LEASE_ROLE_MASK)
+            };
+            let leased_at = slot.leased_at.load(Ordering::Acquire);
+            if role != Some(LEASE_READER)
+                || leased_at == 0
+                || now.saturating_sub(leased_at) < timeout_ms
+            {
+                continue;
+            }
+            // 接管租约：原持有者之后的确认因持有方不符而失败。持有者恰好续约或已确认时 CAS 失败，跳过
+            if slot
+                .leased_at
+                .compare_exchange(leased_at, now.max(1), Ordering::AcqRel, Ordering::Relaxed)
+                .is_err()
+                || slot
+                    .lease_role
+                    .compare_exchange(
+                        owner,
+                        lease_owner(LEASE_READER),
+                        Ordering::AcqRel,
+                        Ordering::Relaxed,
+                    )
+                    .is_err()
+            {
+                continue;
+            }
+            match unsafe { self.requeue(index) } {
+                Ok(()) => requeued += 1,
+                Err(e) if matches!(e.downcast_ref(), Some(TokioIPCError::QueueFull)) => {
+                    unplaced(index)
+                }
+                Err(e) => tracing::debug!("槽位 {} 未重新投递: {}", index, e),
+            }
         }
+        requeued
+    }
 
-        // 序列化数据
-        let serialized = bincode::encode_to_vec(data, bincode::config::standard())
-            .map_err(|_| anyhow::anyhow!("Serialization failed"))?;
+    /// 回收单个槽位，槽位实际未被持有（租约已过时）时只清除租约并返回 false
+    unsafe fn reclaim_slot(&mut self, index: usize, role: u32) -> bool {
+        let lock_free = self.is_lock_free();
+        // 无锁模式以序列号为准：补记的租约可能在持有者完成后才写入
+        let role = if lock_free {
+            match self.lock_free_holder(index) {
+                Some(holder) => holder,
+                None => {
+                    self.slot(index).clear_lease();
+                    return false;
+                }
+            }
+        } else {
+            role
+        };
+        let slot = self.slot_mut(index);
 
-        if serialized.len() > slot.data.len() {
-            return Err(anyhow::anyhow!("Serialized data too large for slot"));
+        // 锁模式下持有者在记录租约之后、修改状态之前被杀：槽位仍是 EMPTY / READY，只需清除租约
+        let state = slot.state.load(Ordering::Acquire);
+        if !lock_free && (state == SlotState::EMPTY as u32 || state == SlotState::READY as u32) {
+            slot.clear_lease();
+            return false;
+        }
+        // 以放弃的槽位发布时需要置位标志，消费者才会跳过它
+        slot.data_size = if lock_free && role == LEASE_WRITER {
+            ABANDONED_SLOT
+        } else {
+            0
+        };
+        slot.checksum = 0;
+        slot.mac = 0;
+        slot.request_id = 0;
+        slot.delivery_attempts = 0;
+
+        if lock_free && role == LEASE_WRITER {
+            // 已占用生产位置，必须按序发布
+            slot.state.store(SlotState::READY as u32, Ordering::Release);
+            Self::publish_lock_free(slot);
+            unsafe { self.notify_ready() };
+        } else {
+            unsafe { self.release(index) };
         }
+        true
+    }
 
-        // 计算校验和
-        let checksum = Self::calculate_checksum(&serialized);
+    /// 因租约超时被回收的槽位累计数量
+    pub fn reclaimed_count(&self) -> u64 {
+        self.header().reclaimed_count.load(Ordering::Relaxed)
+    }
 
-        // 更新槽位数据
-        slot.data[..serialized.len()].copy_from_slice(&serialized);
-        slot.data_size = serialized.len() as u32;
-        slot.checksum = checksum;
-        slot.request_id = self.seq.fetch_add(1, Ordering::Relaxed);
+    /// 接收时因过期被丢弃的消息累计数量
+    pub fn expired_count(&self) -> u64 {
+        self.header().expired_count.load(Ordering::Relaxed)
+    }
 
-        // 标记为就绪
-        slot.state.store(SlotState::READY as u32, Ordering::Release);
+    /// 投递失败达到该次数后转入死信，0 表示不限
+    pub fn max_delivery_attempts(&self) -> u32 {
+        self.header().max_delivery_attempts.load(Ordering::Relaxed)
+    }
 
-        // 设置"有数据"标志（原子操作，立即对其他进程可见）
-        self.begin.store(true, Ordering::SeqCst);
+    /// 设置最大投递失败次数，所有连接方共享
+    pub fn set_max_delivery_attempts(&self, attempts: u32) {
+        self.header()
+            .max_delivery_attempts
+            .store(attempts, Ordering::Relaxed);
+    }
 
-        Ok(slot.request_id)
+    /// 重新投递的消息累计数量
+    pub fn redelivered_count(&self) -> u64 {
+        self.header().redelivered_count.load(Ordering::Relaxed)
     }
 
-    /// 获取READY的 slot, 返回index
-    pub unsafe fn fetch(&mut self) -> Option<usize> {
-        let mut index = None;
+    /// 转入死信的消息累计数量
+    pub fn dead_lettered_count(&self) -> u64 {
+        self.header().dead_lettered_count.load(Ordering::Relaxed)
+    }
 
-        loop {
-            // 检查是否有数据（原子操作，非阻塞）
-            if self.begin.load(Ordering::SeqCst) {
-                let result = unsafe { pthread_mutex_lock(&mut self.read_mutex) };
-                if result == EOWNERDEAD {
-                    // 内联恢复逻辑
-                    unsafe {
-                        pthread_mutex_consistent(&mut self.read_mutex);
-                    }
-                } else if result != 0 {
-                    return None;
-                }
+    /// 将一次写入到读取的延迟计入直方图
+    fn record_latency(&self, enqueued_at: u64) {
+        let nanos = shm_sync::monotonic_nanos().saturating_sub(enqueued_at);
+        let micros = nanos / 1000;
+        let bucket = ((u64::BITS - micros.leading_zeros()) as usize)
+            .saturating_sub(1)
+            .min(LATENCY_BUCKETS - 1);
+        let header = self.header();
+        header.latency_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        header.latency_sum_nanos.fetch_add(nanos, Ordering::Relaxed);
+    }
 
-                let start_index = self.read_pointer;
-                for i in 0..N {
-                    let slot_index = (start_index + i) % N;
-                    let slot = &mut self.slots[slot_index];
+    /// 延迟直方图各桶的计数与所有延迟之和（纳秒），桶的划分见 [`LATENCY_BUCKETS`]
+    pub fn latency_histogram(&self) -> ([u64; LATENCY_BUCKETS], u64) {
+        let header = self.header();
+        let buckets = std::array::from_fn(|i| header.latency_buckets[i].load(Ordering::Relaxed));
+        (buckets, header.latency_sum_nanos.load(Ordering::Relaxed))
+    }
 
-                    // 将槽位状态设置为 READING
-                    if slot.state.load(Ordering::Acquire) == SlotState::READY as u32 {
-                        slot.state
-                            .store(SlotState::READING as u32, Ordering::Release);
-                        self.read_pointer = (slot_index + 1) % N;
-                        index = Some(slot_index);
-                        break;
-                    }
-                }
+    /// 写入的消息累计数量
+    pub fn sent_count(&self) -> u64 {
+        self.header().sent_count.load(Ordering::Relaxed)
+    }
 
-                unsafe {
-                    pthread_mutex_unlock(&mut self.read_mutex);
-                }
+    /// 读取并释放的消息累计数量
+    pub fn received_count(&self) -> u64 {
+        self.header().received_count.load(Ordering::Relaxed)
+    }
 
-                if index.is_none() {
-                    // 数据取完，设置"无数据"标志
-                    self.begin.store(false, Ordering::SeqCst);
-                } else {
-                    break; // 跳出 loop ，返回 index
-                }
-            } else {
-                // 短暂休眠，避免忙等
-                std::thread::sleep(std::time::Duration::from_millis(1000));
-            }
-        }
+    /// 加锁时锁已被占用（需要等待）的累计次数
+    pub fn lock_contended_count(&self) -> u64 {
+        self.header().lock_contended_count.load(Ordering::Relaxed)
+    }
 
-        index
+    /// 记录一条转入死信的消息
+    pub fn record_dead_lettered(&self) {
+        self.header()
+            .dead_lettered_count
+            .fetch_add(1, Ordering::Relaxed);
     }
 
-    ///  获取 slot 的 data
-    /// 并释放 slot 为 EMPTY
-    pub unsafe fn read<T: bincode::Decode<()>>(
-        &mut self,
-        index: usize,
-    ) -> Result<Option<(u64, T)>> {
-        if index >= N {
-            return Err(anyhow::anyhow!("Slot index out of bounds"));
+    /// 槽位中消息已失败的投递次数
+    pub fn delivery_attempts(&self, index: usize) -> Result<u32> {
+        if index >= self.capacity {
+            return Err(anyhow::anyhow!("Slot index out of bounds").into());
         }
+        Ok(self.slot(index).delivery_attempts)
+    }
+
+    /// 再失败一次是否达到最大投递次数
+    pub fn exhausted(&self, index: usize) -> Result<bool> {
+        let max = self.max_delivery_attempts();
+        Ok(max != 0 && self.delivery_attempts(index)? + 1 >= max)
+    }
 
-        let slot = &mut self.slots[index];
+    /// 记录一条因过期被丢弃的消息
+    pub fn record_expired(&self) {
+        self.header().expired_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 背压的高低水位（非 EMPTY 槽位数量），高水位为 0 表示关闭
+    pub fn watermarks(&self) -> (usize, usize) {
+        let header = self.header();
+        (
+            header.high_watermark.load(Ordering::Relaxed) as usize,
+            header.low_watermark.load(Ordering::Relaxed) as usize,
+        )
+    }
 
-        // 验证槽位状态
-        if slot.state.load(Ordering::Acquire) != SlotState::INPROGRESS as u32 {
-            return Err(anyhow::anyhow!("Slot not ready for reading"));
+    /// 设置背压的高低水位，所有连接方共享
+    pub fn set_watermarks(&self, high: usize, low: usize) {
+        let header = self.header();
+        header.low_watermark.store(low as u32, Ordering::Relaxed);
+        header.high_watermark.store(high as u32, Ordering::Relaxed);
+        if high == 0 {
+            header.backpressured.store(false, Ordering::Release);
         }
+    }
 
-        let mut result_data = None;
-        let request_id = slot.request_id;
+    /// 是否按到达顺序分配空槽位
+    pub fn is_fair(&self) -> bool {
+        self.header().fair.load(Ordering::Relaxed) != 0
+    }
 
-        // 验证校验和
-        let data_slice = &slot.data[..slot.data_size as usize];
-        let expected_checksum = Self::calculate_checksum(data_slice);
+    /// 开启或关闭公平模式，所有连接方共享
+    ///
+    /// 公平模式下 [`DynSharedSlotPipe::hold_timeout`] 按取票顺序分配空槽位；
+    /// 非阻塞的 [`DynSharedSlotPipe::hold`] 只在没有写者排队时才尝试，不会插队。
+    pub fn set_fair(&self, fair: bool) {
+        self.header().fair.store(fair as u32, Ordering::Relaxed);
+    }
 
-        if slot.checksum != expected_checksum {
-            // 验证校验和失败
-            // 清空slot
-            slot.state.store(SlotState::EMPTY as u32, Ordering::Release);
-            unsafe { pthread_mutex_unlock(&mut self.read_mutex) };
-            return Err(anyhow::anyhow!("Checksum mismatch"));
-        }
-
-        // 反序列化数据
-        match bincode::decode_from_slice::<T, _>(data_slice, bincode::config::standard()) {
-            Ok((data, _)) => {
-                result_data = Some((request_id, data));
-
-                // 重置slot
-                slot.data_size = 0;
-                slot.checksum = 0;
-                slot.request_id = 0;
-                slot.data = [0; SLOT_SIZE];
-                slot.state.store(SlotState::EMPTY as u32, Ordering::Release);
-            }
-            Err(_) => {
-                slot.state.store(SlotState::EMPTY as u32, Ordering::Release);
-                unsafe {
-                    pthread_mutex_unlock(&mut self.read_mutex);
-                }
-                return Err(anyhow::anyhow!("Deserialization failed"));
-            }
+    /// 队列满时写者的处理方式
+    pub fn full_policy(&self) -> FullPolicy {
+        FullPolicy::from_u32(self.header().full_policy.load(Ordering::Relaxed))
+    }
+
+    /// 设置队列满时写者的处理方式，所有连接方共享
+    pub fn set_full_policy(&self, policy: FullPolicy) {
+        self.header()
+            .full_policy
+            .store(policy as u32, Ordering::Relaxed);
+    }
+
+    /// 覆盖模式下被丢弃的最早消息累计数量
+    pub fn dropped_count(&self) -> u64 {
+        self.header().dropped_count.load(Ordering::Relaxed)
+    }
+
+    /// 非 EMPTY 状态的槽位数量
+    pub fn used_slots(&self) -> usize {
+        (0..self.capacity)
+            .filter(|&i| self.slot(i).state.load(Ordering::Acquire) != SlotState::EMPTY as u32)
+            .count()
+    }
+
+    /// 按当前占用刷新背压状态并返回
+    ///
+    /// 占用达到高水位时进入背压，降到低水位及以下才解除，避免在水位附近来回切换。
+    pub fn update_backpressure(&self) -> bool {
+        let header = self.header();
+        let (high, low) = self.watermarks();
+        if high == 0 {
+            return false;
         }
 
-        unsafe {
-            pthread_mutex_unlock(&mut self.read_mutex);
+        let used = self.used_slots();
+        let backpressured = header.backpressured.load(Ordering::Acquire);
+        if !backpressured && used >= high {
+            header.backpressured.store(true, Ordering::Release);
+            true
+        } else if backpressured && used <= low {
+            header.backpressured.store(false, Ordering::Release);
+            false
+        } else {
+            backpressured
+        }
+    }
+
+    /// 写指针与读指针（无锁模式下由生产/消费位置换算）
+    pub fn pointers(&self) -> (usize, usize) {
+        let header = self.header();
+        match self.mode() {
+            PipeMode::Locked => (header.write_pointer, header.read_pointer),
+            PipeMode::LockFree => {
+                let capacity = self.capacity as u64;
+                (
+                    (header.enqueue_pos.load(Ordering::Relaxed) % capacity) as usize,
+                    (header.dequeue_pos.load(Ordering::Relaxed) % capacity) as usize,
+                )
+            }
         }
-        Ok(result_data)
     }
 
     /// 查找第一个 EMPTY 状态的槽位索引
+    ///
+    /// # Safety
+    /// 视图必须指向已映射并初始化过的共享内存。
     pub unsafe fn next_empty(&self, current_index: usize) -> Option<usize> {
         unsafe { self.next_slot_by_state(current_index, SlotState::EMPTY) }
     }
 
+    /// 查找第一个 READY 状态的槽位索引
+    ///
+    /// # Safety
+    /// 视图必须指向已映射并初始化过的共享内存。
     pub unsafe fn next_ready(&self, current_index: usize) -> Option<usize> {
         unsafe { self.next_slot_by_state(current_index, SlotState::READY) }
     }
 
     /// 查找第一个指定状态的槽位索引
+    ///
+    /// # Safety
+    /// 视图必须指向已映射并初始化过的共享内存。
     pub unsafe fn next_slot_by_state(
         &self,
         current_index: usize,
         target_state: SlotState,
     ) -> Option<usize> {
-        let mut index = (current_index + 1) % N; // 从下一个位置开始
+        let capacity = self.capacity;
+        let mut index = (current_index + 1) % capacity; // 从下一个位置开始
         // 最多遍历 n 次（覆盖整个数组）
         while index != current_index {
-            if self.slots[index].state.load(Ordering::Acquire) == target_state as u32 {
+            if self.slot(index).state.load(Ordering::Acquire) == target_state as u32 {
                 return Some(index);
             }
-            index = (index + 1) % N; // 循环移动到下一个位置
+            index = (index + 1) % capacity; // 循环移动到下一个位置
         }
         None
     }
 
-    /// 从指定索引的槽位读取数据（用于新架构）
-
     /// 获取队列容量
     pub fn capacity(&self) -> usize {
-        N
+        self.capacity
+    }
+
+    /// 获取槽位大小
+    pub fn slot_size(&self) -> usize {
+        self.slot_size
+    }
+
+    /// 登记当前进程的一个连接，返回 PID 表中的位置
+    ///
+    /// PID 表已满时先清理已退出的进程再重试，仍然没有空位返回 `None`
+    pub fn attach(&self) -> Option<usize> {
+        let pid = std::process::id();
+        let header = self.header();
+        for _ in 0..2 {
+            for (i, entry) in header.attached_pids.iter().enumerate() {
+                if entry
+                    .compare_exchange(0, pid, Ordering::AcqRel, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    header.attached_count.fetch_add(1, Ordering::AcqRel);
+                    return Some(i);
+                }
+            }
+            self.prune_attached();
+        }
+        None
+    }
+
+    /// 注销由 [`DynSharedSlotPipe::attach`] 登记的连接
+    pub fn detach(&self, index: usize) {
+        let pid = std::process::id();
+        let header = self.header();
+        if let Some(entry) = header.attached_pids.get(index)
+            && entry
+                .compare_exchange(pid, 0, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+        {
+            header.attached_count.fetch_sub(1, Ordering::AcqRel);
+        }
+    }
+
+    /// 清理已退出（崩溃）进程留下的连接记录，返回清理数量
+    pub fn prune_attached(&self) -> usize {
+        let header = self.header();
+        let mut pruned = 0;
+        for entry in header.attached_pids.iter() {
+            let pid = entry.load(Ordering::Acquire);
+            if pid != 0
+                && !shm_sync::process_alive(pid)
+                && entry
+                    .compare_exchange(pid, 0, Ordering::AcqRel, Ordering::Relaxed)
+                    .is_ok()
+            {
+                header.attached_count.fetch_sub(1, Ordering::AcqRel);
+                pruned += 1;
+            }
+        }
+        pruned
+    }
+
+    /// 当前连接的进程 PID 列表（去重，已清理崩溃的连接者）
+    pub fn attached_processes(&self) -> Vec<u32> {
+        self.prune_attached();
+        let mut pids: Vec<u32> = self
+            .header()
+            .attached_pids
+            .iter()
+            .map(|entry| entry.load(Ordering::Acquire))
+            .filter(|&pid| pid != 0)
+            .collect();
+        pids.sort_unstable();
+        pids.dedup();
+        pids
+    }
+
+    /// 当前连接的句柄数量（已清理崩溃的连接者）
+    pub fn attached_count(&self) -> usize {
+        self.prune_attached();
+        self.header().attached_count.load(Ordering::Acquire) as usize
+    }
+
+    /// 刚抢占的槽位的句柄
+    ///
+    /// 加锁模式的代数在每次抢占时递增；无锁模式的代数即槽位序列号，槽位被持有期间不变，
+    /// 发布或归还时改变。
+    pub fn handle(&self, index: usize) -> SlotHandle {
+        let generation = if index < self.capacity {
+            self.slot(index).sequence.load(Ordering::Acquire)
+        } else {
+            0
+        };
+        SlotHandle { index, generation }
     }
 
-    /// 设置指定索引槽位的状态
-    pub unsafe fn set_slot_state(&mut self, index: usize, state: SlotState) -> Result<()> {
-        if index >= N {
-            return Err(anyhow::anyhow!("Slot index out of bounds"));
+    /// 检查句柄仍指向同一次抢占：槽位代数未变且仍被持有（WRITING / READING / INPROGRESS）
+    pub fn check_handle(&self, handle: SlotHandle) -> Result<(), StaleHandle> {
+        let (current, held) = if handle.index < self.capacity {
+            let slot = self.slot(handle.index);
+            let state = slot.state.load(Ordering::Acquire);
+            let held = [
+                SlotState::WRITING,
+                SlotState::READING,
+                SlotState::INPROGRESS,
+            ]
+            .iter()
+            .any(|&s| s as u32 == state);
+            (slot.sequence.load(Ordering::Acquire), held)
+        } else {
+            (0, false)
+        };
+        if !held || current != handle.generation {
+            return Err(StaleHandle {
+                index: handle.index,
+                generation: handle.generation,
+                current,
+            });
         }
-        self.slots[index]
-            .state
-            .store(state as u32, Ordering::Release);
         Ok(())
     }
 
+    /// 持有者开始读写：以 CAS 把抢占时的状态（写者 WRITING / 读者 READING）切换为 INPROGRESS 并续约
+    ///
+    /// 租约必须由当前进程以 `role` 持有。本进程同一方已切换过的 INPROGRESS 槽位（例如
+    /// [`DynSharedSlotPipe::peek_with`] 之后的 ack）直接通过；其他进程持有的槽位，或状态说明
+    /// 槽位不归调用者持有（从未抢占、已被回收或接管）时返回 `false`。
+    fn begin(&self, index: usize, role: u32) -> bool {
+        let slot = self.slot(index);
+        if slot.lease_role.load(Ordering::Relaxed) != lease_owner(role) {
+            return false;
+        }
+        let held = if role == LEASE_WRITER {
+            SlotState::WRITING
+        } else {
+            SlotState::READING
+        };
+        match slot.state.compare_exchange(
+            held as u32,
+            SlotState::INPROGRESS as u32,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => {
+                // 续约；槽位同时被回收时租约已清零，不再写回
+                let leased_at = slot.leased_at.load(Ordering::Acquire);
+                if leased_at != 0 {
+                    let _ = slot.leased_at.compare_exchange(
+                        leased_at,
+                        shm_sync::monotonic_millis().max(1),
+                        Ordering::AcqRel,
+                        Ordering::Relaxed,
+                    );
+                }
+                true
+            }
+            Err(state) => {
+                state == SlotState::INPROGRESS as u32
+                    && slot.lease_role.load(Ordering::Relaxed) == lease_owner(role)
+            }
+        }
+    }
+
     /// 获取指定索引槽位的状态
+    ///
+    /// # Safety
+    /// 视图必须指向已映射并初始化过的共享内存。
     pub unsafe fn get_slot_state(&self, index: usize) -> Result<SlotState> {
-        if index >= N {
-            return Err(anyhow::anyhow!("Slot index out of bounds"));
+        if index >= self.capacity {
+            return Err(anyhow::anyhow!("Slot index out of bounds").into());
         }
-        let state_value = self.slots[index].state.load(Ordering::Acquire);
+        let state_value = self.slot(index).state.load(Ordering::Acquire);
         match state_value {
             x if x == SlotState::EMPTY as u32 => Ok(SlotState::EMPTY),
             x if x == SlotState::WRITING as u32 => Ok(SlotState::WRITING),
             x if x == SlotState::INPROGRESS as u32 => Ok(SlotState::INPROGRESS),
             x if x == SlotState::READING as u32 => Ok(SlotState::READING),
             x if x == SlotState::READY as u32 => Ok(SlotState::READY),
-            _ => Err(anyhow::anyhow!("Unknown slot state: {}", state_value)), // 未知状态
+            _ => Err(anyhow::anyhow!("Unknown slot state: {}", state_value).into()), // 未知状态
+        }
+    }
+}
+
+/// 持有管道映射的所有者，Drop 时解除映射
+///
+/// [`DynSharedSlotPipe`] 视图可以复制，本身不解除映射；把映射交给 `SlotMapping` 后，
+/// 进程反复连接、断开时不会累积映射。创建者可以通过 [`SlotMapping::unlink_on_drop`]
+/// 让 Drop 同时删除共享内存段。从本所有者复制出的视图不能在其 Drop 之后使用。
+pub struct SlotMapping {
+    view: DynSharedSlotPipe,
+    unlink: Option<CString>,
+}
+
+impl SlotMapping {
+    /// 接管视图指向的映射
+    ///
+    /// # Safety
+    /// 映射不能再由其他所有者或 [`DynSharedSlotPipe::unmap`] 解除。
+    pub unsafe fn new(view: DynSharedSlotPipe) -> Self {
+        Self { view, unlink: None }
+    }
+
+    /// Drop 时同时删除名为 `name` 的共享内存段（仅创建者使用）
+    pub fn unlink_on_drop(mut self, name: &str) -> Self {
+        self.unlink = CString::new(format!("/{}", name.trim_start_matches('/'))).ok();
+        self
+    }
+
+    /// 放弃所有权，返回视图；之后由调用者负责解除映射
+    pub fn into_raw(self) -> DynSharedSlotPipe {
+        let view = self.view;
+        mem::forget(self);
+        view
+    }
+}
+
+impl Deref for SlotMapping {
+    type Target = DynSharedSlotPipe;
+
+    fn deref(&self) -> &DynSharedSlotPipe {
+        &self.view
+    }
+}
+
+impl DerefMut for SlotMapping {
+    fn deref_mut(&mut self) -> &mut DynSharedSlotPipe {
+        &mut self.view
+    }
+}
+
+impl Drop for SlotMapping {
+    fn drop(&mut self) {
+        unsafe { self.view.unmap() };
+        if let Some(name) = &self.unlink {
+            unsafe { libc::shm_unlink(name.as_ptr()) };
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dyn_layout_matches_typed_pipe() {
+        assert_eq!(
+            DynSharedSlotPipe::slot_stride(256),
+            mem::size_of::<Slot<256>>()
+        );
+        assert_eq!(
+            DynSharedSlotPipe::slot_stride(13),
+            mem::size_of::<Slot<13>>()
+        );
+        assert_eq!(
+            DynSharedSlotPipe::mapped_size(4, 256),
+            mem::size_of::<SharedSlotPipe<4, 256>>()
+        );
+        assert_eq!(
+            DynSharedSlotPipe::mapped_size(3, 13),
+            mem::size_of::<SharedSlotPipe<3, 13>>()
+        );
+    }
+
+    #[test]
+    fn test_dyn_pipe_roundtrip_with_typed_peer() {
+        let name = format!("mi7_test_dyn_slot_{}", std::process::id());
+        unsafe {
+            let mut pipe = DynSharedSlotPipe::create(
+                &name,
+                3,
+                13,
+                PipeMode::Locked,
+                CodecKind::Bincode,
+                Integrity::default(),
+                MutexAttr::default(),
+            )
+            .unwrap();
+
+            // 连接方不需要知道容量与槽位大小
+            let mut peer = DynSharedSlotPipe::connect(&name).unwrap();
+            assert_eq!((peer.capacity(), peer.slot_size()), (3, 13));
+
+            // 编译期类型与运行时视图共享同一布局
+            let typed = SharedSlotPipe::<3, 13>::open(&name, false).unwrap();
+
+            let index = pipe.hold().unwrap();
+            pipe.write_with(index, |buf| {
+                buf[..5].copy_from_slice(b"hello");
+                5
+            })
+            .unwrap();
+
+            let index = (*typed).fetch_timeout(Some(Duration::ZERO)).unwrap();
+            let (_, data) = peer.read_with(index, |buf| buf.to_vec()).unwrap();
+            assert_eq!(data, b"hello");
+
+            libc::munmap(
+                typed as *mut libc::c_void,
+                mem::size_of::<SharedSlotPipe<3, 13>>(),
+            );
+            peer.unmap();
+            pipe.unmap();
+            let cname = CString::new(format!("/{}", name)).unwrap();
+            libc::shm_unlink(cname.as_ptr());
+        }
+    }
+
+    #[test]
+    fn test_layout_version_negotiation() {
+        let name = format!("mi7_test_slot_layout_version_{}", std::process::id());
+        unsafe {
+            let mut pipe = DynSharedSlotPipe::create(
+                &name,
+                2,
+                16,
+                PipeMode::Locked,
+                CodecKind::Raw,
+                Integrity::default(),
+                MutexAttr::default(),
+            )
+            .unwrap();
+            let connect_error = |name: &str| {
+                DynSharedSlotPipe::connect(name)
+                    .err()
+                    .unwrap()
+                    .downcast::<LayoutMismatch>()
+                    .unwrap()
+            };
+
+            // 更新的程序创建且声明兼容当前版本：可以连接
+            pipe.header_mut().layout.version = PIPE_LAYOUT_VERSION + 1;
+            DynSharedSlotPipe::connect(&name).unwrap().unmap();
+
+            // 更新的程序创建且不再兼容
+            pipe.header_mut().layout.compat_version = PIPE_LAYOUT_VERSION + 1;
+            assert_eq!(
+                connect_error(&name),
+                LayoutMismatch::Newer {
+                    found: PIPE_LAYOUT_VERSION + 1,
+                    compat: PIPE_LAYOUT_VERSION + 1,
+                    supported: PIPE_LAYOUT_VERSION,
+                }
+            );
+
+            // 低于兼容范围的旧布局
+            pipe.header_mut().layout.version = PIPE_COMPAT_VERSION - 1;
+            assert_eq!(
+                connect_error(&name),
+                LayoutMismatch::Older {
+                    found: PIPE_COMPAT_VERSION - 1,
+                    compat: PIPE_COMPAT_VERSION,
+                }
+            );
+
+            // 兼容范围内的旧布局只执行之后版本的迁移
+            let migrations = [
+                LayoutMigration {
+                    from: PIPE_LAYOUT_VERSION - 1,
+                    migrate: |header| header.reserved[0].store(1, Ordering::Relaxed),
+                },
+                LayoutMigration {
+                    from: PIPE_LAYOUT_VERSION,
+                    migrate: |header| header.reserved[1].store(2, Ordering::Relaxed),
+                },
+            ];
+            apply_migrations(pipe.header(), PIPE_LAYOUT_VERSION, &migrations);
+            assert_eq!(pipe.header().reserved[0].load(Ordering::Relaxed), 0);
+            assert_eq!(pipe.header().reserved[1].load(Ordering::Relaxed), 2);
+
+            pipe.unmap();
+            let cname = CString::new(format!("/{}", name)).unwrap();
+            libc::shm_unlink(cname.as_ptr());
+        }
+    }
+
+    #[test]
+    fn test_read_detects_corrupted_slot() {
+        let name = format!("mi7_test_slot_integrity_{}", std::process::id());
+        unsafe {
+            let mut pipe = DynSharedSlotPipe::create(
+                &name,
+                2,
+                16,
+                PipeMode::Locked,
+                CodecKind::Raw,
+                Integrity::XxHash64,
+                MutexAttr::default(),
+            )
+            .unwrap();
+            assert_eq!(
+                DynSharedSlotPipe::connect(&name).unwrap().integrity(),
+                Integrity::XxHash64
+            );
+
+            let index = pipe.hold().unwrap();
+            pipe.write_with(index, |buf| {
+                buf[..4].copy_from_slice(b"abcd");
+                4
+            })
+            .unwrap();
+
+            // 模拟数据被覆盖：交换前两个字节
+            pipe.data_mut(index).swap(0, 1);
+
+            let index = pipe.fetch_timeout(Some(Duration::ZERO)).unwrap();
+            let err = pipe.read_with(index, |buf| buf.to_vec()).unwrap_err();
+            assert!(err.to_string().contains("Checksum mismatch"));
+
+            pipe.unmap();
+            let cname = CString::new(format!("/{}", name)).unwrap();
+            libc::shm_unlink(cname.as_ptr());
+        }
+    }
+
+    #[test]
+    fn test_failed_migration_keeps_messages_in_source() {
+        for mode in [PipeMode::Locked, PipeMode::LockFree] {
+            let name = format!("mi7_test_slot_migrate_{:?}_{}", mode, std::process::id());
+            let target_name = format!("{}_next", name);
+            unsafe {
+                let mut pipe = DynSharedSlotPipe::create(
+                    &name,
+                    4,
+                    16,
+                    mode,
+                    CodecKind::Raw,
+                    Integrity::XxHash64,
+                    MutexAttr::default(),
+                )
+                .unwrap();
+                // 校验算法不同，目标管道拒绝写入迁移过来的消息
+                let mut target = DynSharedSlotPipe::create(
+                    &target_name,
+                    4,
+                    16,
+                    mode,
+                    CodecKind::Raw,
+                    Integrity::Crc32,
+                    MutexAttr::default(),
+                )
+                .unwrap();
+                for message in [b"m0", b"m1"] {
+                    let index = pipe.hold().unwrap();
+                    pipe.write_with(index, |buf| {
+                        buf[..2].copy_from_slice(message);
+                        2
+                    })
+                    .unwrap();
+                }
+
+                let mut switched = false;
+                assert!(pipe.migrate_ready(&mut target, || switched = true).is_err());
+                assert!(!switched);
+                assert!(target.fetch_timeout(Some(Duration::ZERO)).is_none());
 
-    /// 计算校验和
-    fn calculate_checksum(data: &[u8]) -> u64 {
-        use std::collections::hash_map::DefaultHasher;
-        use std::hash::{Hash, Hasher};
+                let mut received = Vec::new();
+                while let Some(index) = pipe.fetch_timeout(Some(Duration::ZERO)) {
+                    received.push(pipe.read_with(index, |buf| buf.to_vec()).unwrap().1);
+                }
+                received.sort();
+                assert_eq!(received, vec![b"m0".to_vec(), b"m1".to_vec()]);
+
+                pipe.unmap();
+                target.unmap();
+                for name in [&name, &target_name] {
+                    let cname = CString::new(format!("/{}", name)).unwrap();
+                    libc::shm_unlink(cname.as_ptr());
+                }
+            }
+        }
+    }
 
-        let mut hasher = DefaultHasher::new();
-        data.hash(&mut hasher);
-        hasher.finish()
+    #[test]
+    fn test_slot_transitions_require_holder() {
+        for mode in [PipeMode::Locked, PipeMode::LockFree] {
+            let name = format!("mi7_test_slot_holder_{:?}_{}", mode, std::process::id());
+            unsafe {
+                let mut pipe = DynSharedSlotPipe::create(
+                    &name,
+                    2,
+                    16,
+                    mode,
+                    CodecKind::Raw,
+                    Integrity::XxHash64,
+                    MutexAttr::default(),
+                )
+                .unwrap();
+                let fill = |buf: &mut [u8]| {
+                    buf[..2].copy_from_slice(b"ok");
+                    2
+                };
+
+                // 未抢占的槽位不能写入；写入由 hold 得到的 WRITING 槽位直接开始
+                assert!(pipe.write_with(0, fill).is_err());
+                let index = pipe.hold().unwrap();
+                pipe.write_with(index, fill).unwrap();
+
+                // READY 槽位必须先 fetch 才能读取，读者持有的槽位不能被写入
+                assert!(pipe.read_with(index, |buf| buf.to_vec()).is_err());
+                let index = pipe.fetch_timeout(Some(Duration::ZERO)).unwrap();
+                assert!(pipe.write_with(index, fill).is_err());
+                let (_, data) = pipe.read_with(index, |buf| buf.to_vec()).unwrap();
+                assert_eq!(data, b"ok");
+
+                // 被回收的槽位不能再由原持有者写入
+                let index = pipe.hold().unwrap();
+                assert_eq!(pipe.reclaim_stuck(Duration::ZERO), 1);
+                assert!(pipe.write_with(index, fill).is_err());
+
+                // 其他进程以同一角色持有的槽位不能被本进程确认，包括已开始处理的槽位
+                let index = pipe.hold().unwrap();
+                pipe.write_with(index, fill).unwrap();
+                let index = pipe.fetch_timeout(Some(Duration::ZERO)).unwrap();
+                pipe.peek_with(index, |buf| Ok(buf.to_vec())).unwrap();
+                let ours = lease_owner(LEASE_READER);
+                let other = LEASE_READER | (std::process::id() + 1) << 2;
+                pipe.slot(index).lease_role.store(other, Ordering::Relaxed);
+                assert!(pipe.ack(index).is_err());
+                pipe.slot(index).lease_role.store(ours, Ordering::Relaxed);
+                pipe.ack(index).unwrap();
+
+                pipe.unmap();
+                let cname = CString::new(format!("/{}", name)).unwrap();
+                libc::shm_unlink(cname.as_ptr());
+            }
+        }
     }
 }
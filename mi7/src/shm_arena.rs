@@ -0,0 +1,576 @@
+//! 共享内存分配区：在一个命名段内分配变长数据块
+//!
+//! 寄存箱的 box 大小固定（1M、2M……），10 KB 的负载也要占用一个 1M 的 box。
+//! [`ShmArena`] 在单个共享内存段上实现伙伴分配器：数据区按 2 的幂划分为块，最小块
+//! [`MIN_BLOCK_SIZE`] 字节，分配时取能容纳数据的最小块，空闲块按阶挂在双向空闲链表上，
+//! 释放时与空闲的伙伴块逐级合并。
+//!
+//! 分配得到的 [`ArenaHandle`] 由偏移、长度与代数组成，可以放进消息发给其他进程。块被释放
+//! 后再次分配时代数递增，持有旧句柄的读写与释放返回 [`ArenaError::StaleHandle`]。
+//! 分配与释放由段内的 robust 互斥锁串行化；数据读写不加锁，由句柄的持有者之间自行约定
+//! （通常是写完后把句柄交给读者，读者读完后释放）。
+
+use crate::locks::Mapping;
+use crate::shm_registry::SharedMemoryRegistry;
+use crate::shm_sync::ShmMutex;
+
+use crate::error::Result;
+use anyhow::{Context, anyhow};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::{mem, ptr};
+
+/// 最小块的字节数
+pub const MIN_BLOCK_SIZE: usize = 256;
+
+/// 阶的数量上限，最大块为 `MIN_BLOCK_SIZE << (MAX_ORDERS - 1)`
+const MAX_ORDERS: usize = 32;
+
+/// 分配区共享内存的标识
+const ARENA_MAGIC: u64 = u64::from_le_bytes(*b"MI7ARNA\0");
+
+/// 分配区共享内存的布局版本
+const ARENA_LAYOUT_VERSION: u32 = 1;
+
+/// 数据区相对段起始的对齐
+const DATA_ALIGN: usize = 4096;
+
+/// 空闲链表的结束标记
+const NIL: u32 = u32::MAX;
+
+/// 块状态：不是块的起始（属于更大的块）、空闲、已分配
+const INTERIOR: u8 = 0;
+const FREE: u8 = 1;
+const ALLOCATED: u8 = 2;
+
+/// 分配或访问失败
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum ArenaError {
+    #[error("{len} 字节超出分配区的最大块 {max} 字节")]
+    TooLarge { len: usize, max: usize },
+    #[error("分配区没有足够大的空闲块容纳 {len} 字节")]
+    Exhausted { len: usize },
+    #[error("句柄 (offset={offset}, generation={generation}) 已失效")]
+    StaleHandle { offset: u64, generation: u32 },
+}
+
+/// 已分配的数据块
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, bincode::Encode, bincode::Decode)]
+pub struct ArenaHandle {
+    /// 块在数据区内的偏移
+    pub offset: u64,
+    /// 分配时请求的字节数
+    pub len: u64,
+    /// 块的代数，块每次被分配时递增
+    pub generation: u32,
+}
+
+/// 分配区的使用统计，所有连接方的合计
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ArenaStats {
+    /// 数据区大小
+    pub capacity: usize,
+    /// 已分配块的总大小
+    pub allocated: usize,
+    /// 已分配块中调用者请求的字节数
+    pub requested: usize,
+    /// 当前已分配的块数量
+    pub live: u64,
+    /// 最大的空闲块（无锁读取的近似值）
+    pub largest_free: usize,
+    pub allocs: u64,
+    pub frees: u64,
+    /// 没有足够大的空闲块而失败的分配次数
+    pub failures: u64,
+}
+
+impl ArenaStats {
+    /// 已分配块中未被使用的比例（伙伴分配的内部碎片）
+    pub fn internal_fragmentation(&self) -> f64 {
+        if self.allocated == 0 {
+            0.0
+        } else {
+            1.0 - self.requested as f64 / self.allocated as f64
+        }
+    }
+}
+
+/// 分配区头部，其后紧跟每个最小块一项的 [`BlockMeta`]，数据区从 `data_offset` 开始
+#[repr(C)]
+struct ArenaHeader {
+    magic: u64,
+    version: u32,
+    max_order: u32, // 整个数据区为一个该阶的块
+    data_offset: u64,
+    mutex: ShmMutex,                     // 保护空闲链表与块元数据
+    free_heads: [AtomicU32; MAX_ORDERS], // 各阶空闲链表的第一个块，持锁修改
+    allocated: AtomicU64,
+    requested: AtomicU64,
+    live: AtomicU64,
+    allocs: AtomicU64,
+    frees: AtomicU64,
+    failures: AtomicU64,
+}
+
+impl ArenaHeader {
+    /// `order` 阶空闲链表的第一个块
+    fn free_head(&self, order: usize) -> u32 {
+        self.free_heads[order].load(Ordering::Relaxed)
+    }
+}
+
+/// 最小块的元数据，只有块的起始项有效
+#[repr(C)]
+struct BlockMeta {
+    state: u8,
+    order: u8,
+    _reserved: u16,
+    generation: u32,
+}
+
+/// 空闲块数据开头的链表指针（最小块序号）
+#[repr(C)]
+struct FreeLink {
+    prev: u32,
+    next: u32,
+}
+
+/// 命名共享内存段上的伙伴分配器，创建者 Drop 时删除该段
+pub struct ShmArena {
+    mapping: Mapping,
+    blocks: usize,
+    name: String,
+    owner: bool,
+}
+
+unsafe impl Send for ShmArena {}
+unsafe impl Sync for ShmArena {}
+
+impl ShmArena {
+    /// 创建（或重置）分配区，数据区大小向上取整为 `MIN_BLOCK_SIZE` 的 2 的幂倍
+    pub fn create(name: &str, capacity: usize) -> Result<Self> {
+        let max = MIN_BLOCK_SIZE << (MAX_ORDERS - 1);
+        if capacity == 0 || capacity > max {
+            return Err(anyhow!("无效的分配区大小: {}（最大 {}）", capacity, max).into());
+        }
+        let blocks = capacity.div_ceil(MIN_BLOCK_SIZE).next_power_of_two();
+
+        let mut arena = Self {
+            mapping: Mapping::create(name, Self::mapped_size(blocks))?,
+            blocks,
+            name: name.trim_start_matches('/').to_string(),
+            owner: true,
+        };
+        unsafe { arena.init()? };
+        SharedMemoryRegistry::register(name);
+        Ok(arena)
+    }
+
+    /// 连接到已有分配区，布局参数从头部读取
+    pub fn connect(name: &str) -> Result<Self> {
+        let (magic, version, max_order) = {
+            let mapping = Mapping::open(name, mem::size_of::<ArenaHeader>())
+                .with_context(|| format!("共享内存 {} 不是分配区", name))?;
+            let h = unsafe { &*(mapping.as_ptr() as *const ArenaHeader) };
+            (
+                unsafe { ptr::read_volatile(&h.magic) },
+                h.version,
+                h.max_order,
+            )
+        };
+        if magic != ARENA_MAGIC || version != ARENA_LAYOUT_VERSION {
+            return Err(anyhow!("共享内存 {} 不是分配区或布局版本不匹配", name).into());
+        }
+
+        let blocks = 1usize << max_order;
+        Ok(Self {
+            mapping: Mapping::open(name, Self::mapped_size(blocks))
+                .with_context(|| format!("分配区 {} 的共享内存大小不一致", name))?,
+            blocks,
+            name: name.trim_start_matches('/').to_string(),
+            owner: false,
+        })
+    }
+
+    /// 共享内存名称
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 数据区大小
+    pub fn capacity(&self) -> usize {
+        self.blocks * MIN_BLOCK_SIZE
+    }
+
+    /// 分配能容纳 `len` 字节的块，内容未初始化；单次最多分配整个数据区
+    pub fn alloc(&self, len: usize) -> Result<ArenaHandle> {
+        if len > self.capacity() {
+            return Err(ArenaError::TooLarge {
+                len,
+                max: self.capacity(),
+            }
+            .into());
+        }
+        let order = Self::order_for(len);
+
+        let _guard = self.lock()?;
+        let header = self.header();
+        let Some(mut found) =
+            (order..=header.max_order as usize).find(|&o| header.free_head(o) != NIL)
+        else {
+            header.failures.fetch_add(1, Ordering::Relaxed);
+            return Err(ArenaError::Exhausted { len }.into());
+        };
+
+        let index = header.free_head(found);
+        let meta = unsafe {
+            self.unlink_free(index, found);
+            // 逐级拆分，后一半作为空闲块挂到低一阶的链表上
+            while found > order {
+                found -= 1;
+                let buddy = index + (1 << found);
+                self.push_free(buddy, found);
+            }
+            &mut *self.meta_ptr(index)
+        };
+        meta.state = ALLOCATED;
+        meta.order = order as u8;
+        meta.generation = meta.generation.wrapping_add(1);
+        header
+            .allocated
+            .fetch_add((MIN_BLOCK_SIZE << order) as u64, Ordering::Relaxed);
+        header.requested.fetch_add(len as u64, Ordering::Relaxed);
+        header.live.fetch_add(1, Ordering::Relaxed);
+        header.allocs.fetch_add(1, Ordering::Relaxed);
+        Ok(ArenaHandle {
+            offset: (index as usize * MIN_BLOCK_SIZE) as u64,
+            len: len as u64,
+            generation: meta.generation,
+        })
+    }
+
+    /// 释放块，与空闲的伙伴块合并
+    pub fn free(&self, handle: ArenaHandle) -> Result<()> {
+        let _guard = self.lock()?;
+        let (mut index, mut order) = self.validate(&handle)?;
+        let header = self.header();
+        header
+            .allocated
+            .fetch_sub((MIN_BLOCK_SIZE << order) as u64, Ordering::Relaxed);
+        header.requested.fetch_sub(handle.len, Ordering::Relaxed);
+        header.live.fetch_sub(1, Ordering::Relaxed);
+        header.frees.fetch_add(1, Ordering::Relaxed);
+
+        unsafe {
+            (*self.meta_ptr(index)).state = INTERIOR;
+            while order < header.max_order as usize {
+                let buddy = index ^ (1 << order);
+                let meta = &*self.meta_ptr(buddy);
+                if meta.state != FREE || meta.order as usize != order {
+                    break;
+                }
+                self.unlink_free(buddy, order);
+                (*self.meta_ptr(buddy)).state = INTERIOR;
+                index = index.min(buddy);
+                order += 1;
+            }
+            self.push_free(index, order);
+        }
+        Ok(())
+    }
+
+    /// 分配块并写入数据
+    pub fn store(&self, data: &[u8]) -> Result<ArenaHandle> {
+        let handle = self.alloc(data.len())?;
+        self.write(&handle, data)?;
+        Ok(handle)
+    }
+
+    /// 从块的开头写入数据，超出分配长度时返回错误
+    pub fn write(&self, handle: &ArenaHandle, data: &[u8]) -> Result<()> {
+        if data.len() as u64 > handle.len {
+            return Err(anyhow!("写入 {} 字节超出块的分配长度 {}", data.len(), handle.len).into());
+        }
+        self.check(handle)?;
+        unsafe {
+            ptr::copy_nonoverlapping(data.as_ptr(), self.data_ptr(handle.offset), data.len());
+        }
+        Ok(())
+    }
+
+    /// 读取块中分配长度的数据
+    ///
+    /// 复制前后各检查一次句柄，复制期间块被释放并重新分配时返回 [`ArenaError::StaleHandle`]。
+    pub fn read(&self, handle: &ArenaHandle) -> Result<Vec<u8>> {
+        self.check(handle)?;
+        let mut data = vec![0u8; handle.len as usize];
+        unsafe {
+            ptr::copy_nonoverlapping(self.data_ptr(handle.offset), data.as_mut_ptr(), data.len());
+        }
+        self.check(handle)?;
+        Ok(data)
+    }
+
+    /// 读取数据并释放块
+    pub fn take(&self, handle: ArenaHandle) -> Result<Vec<u8>> {
+        let data = self.read(&handle)?;
+        self.free(handle)?;
+        Ok(data)
+    }
+
+    /// 句柄是否仍指向已分配的块
+    pub fn is_live(&self, handle: &ArenaHandle) -> bool {
+        self.lock()
+            .is_ok_and(|_guard| self.validate(handle).is_ok())
+    }
+
+    /// 所有连接方累计的统计
+    pub fn stats(&self) -> ArenaStats {
+        let header = self.header();
+        let largest_free = (0..=header.max_order as usize)
+            .rev()
+            .find(|&o| header.free_head(o) != NIL)
+            .map_or(0, |o| MIN_BLOCK_SIZE << o);
+        ArenaStats {
+            capacity: self.capacity(),
+            allocated: header.allocated.load(Ordering::Relaxed) as usize,
+            requested: header.requested.load(Ordering::Relaxed) as usize,
+            live: header.live.load(Ordering::Relaxed),
+            largest_free,
+            allocs: header.allocs.load(Ordering::Relaxed),
+            frees: header.frees.load(Ordering::Relaxed),
+            failures: header.failures.load(Ordering::Relaxed),
+        }
+    }
+
+    /// 能容纳 `len` 字节的最小块的阶
+    fn order_for(len: usize) -> usize {
+        len.div_ceil(MIN_BLOCK_SIZE)
+            .max(1)
+            .next_power_of_two()
+            .trailing_zeros() as usize
+    }
+
+    /// 加锁后检查句柄
+    fn check(&self, handle: &ArenaHandle) -> Result<()> {
+        let _guard = self.lock()?;
+        self.validate(handle)?;
+        Ok(())
+    }
+
+    /// 检查句柄指向已分配且代数一致的块，返回块序号与阶；调用者需持有锁
+    fn validate(&self, handle: &ArenaHandle) -> Result<(u32, usize), ArenaError> {
+        let stale = ArenaError::StaleHandle {
+            offset: handle.offset,
+            generation: handle.generation,
+        };
+        let offset = handle.offset as usize;
+        if !offset.is_multiple_of(MIN_BLOCK_SIZE) || offset / MIN_BLOCK_SIZE >= self.blocks {
+            return Err(stale);
+        }
+        let index = (offset / MIN_BLOCK_SIZE) as u32;
+        let meta = unsafe { &*self.meta_ptr(index) };
+        let order = meta.order as usize;
+        if meta.state != ALLOCATED
+            || meta.generation != handle.generation
+            || handle.len as usize > MIN_BLOCK_SIZE << order
+        {
+            return Err(stale);
+        }
+        Ok((index, order))
+    }
+
+    /// 把块挂到 `order` 阶空闲链表的开头
+    ///
+    /// # Safety
+    /// 调用者需持有分配区的锁，且调用期间不持有该块元数据或链表中任何块链表指针的引用。
+    unsafe fn push_free(&self, index: u32, order: usize) {
+        let header = self.header();
+        let head = header.free_head(order);
+        unsafe {
+            *self.link_ptr(index) = FreeLink {
+                prev: NIL,
+                next: head,
+            };
+            if head != NIL {
+                (*self.link_ptr(head)).prev = index;
+            }
+        }
+        header.free_heads[order].store(index, Ordering::Relaxed);
+        let meta = unsafe { &mut *self.meta_ptr(index) };
+        meta.state = FREE;
+        meta.order = order as u8;
+    }
+
+    /// 把块从 `order` 阶空闲链表中摘下
+    ///
+    /// # Safety
+    /// 同 [`ShmArena::push_free`]。
+    unsafe fn unlink_free(&self, index: u32, order: usize) {
+        let FreeLink { prev, next } = unsafe { ptr::read(self.link_ptr(index)) };
+        if prev == NIL {
+            self.header().free_heads[order].store(next, Ordering::Relaxed);
+        } else {
+            unsafe { (*self.link_ptr(prev)).next = next };
+        }
+        if next != NIL {
+            unsafe { (*self.link_ptr(next)).prev = prev };
+        }
+    }
+
+    /// 初始化头部、块元数据与空闲链表，完成后写入魔数发布
+    unsafe fn init(&mut self) -> Result<()> {
+        let blocks = self.blocks;
+        let header = unsafe { &mut *self.header_ptr() };
+
+        header.magic = 0;
+        std::sync::atomic::fence(Ordering::Release);
+        unsafe { header.mutex.init()? };
+        header.version = ARENA_LAYOUT_VERSION;
+        header.max_order = blocks.trailing_zeros();
+        header.data_offset = Self::data_offset(blocks) as u64;
+        for head in &header.free_heads {
+            head.store(NIL, Ordering::Relaxed);
+        }
+        for counter in [
+            &header.allocated,
+            &header.requested,
+            &header.live,
+            &header.allocs,
+            &header.frees,
+            &header.failures,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+        for index in 0..blocks as u32 {
+            unsafe {
+                self.meta_ptr(index).write(BlockMeta {
+                    state: INTERIOR,
+                    order: 0,
+                    _reserved: 0,
+                    generation: 0,
+                })
+            };
+        }
+        unsafe { self.push_free(0, blocks.trailing_zeros() as usize) };
+
+        std::sync::atomic::fence(Ordering::Release);
+        unsafe { ptr::write_volatile(&mut (*self.header_ptr()).magic, ARENA_MAGIC) };
+        Ok(())
+    }
+
+    fn lock(&self) -> Result<ArenaGuard<'_>> {
+        let mutex = &self.header().mutex;
+        if !unsafe { mutex.lock() } {
+            return Err(anyhow!("分配区 {} 加锁失败", self.name).into());
+        }
+        Ok(ArenaGuard { mutex })
+    }
+
+    fn header_ptr(&self) -> *mut ArenaHeader {
+        self.mapping.as_ptr() as *mut ArenaHeader
+    }
+
+    fn header(&self) -> &ArenaHeader {
+        unsafe { &*self.header_ptr() }
+    }
+
+    /// 块的元数据，只能在持有分配区的锁时解引用
+    fn meta_ptr(&self, index: u32) -> *mut BlockMeta {
+        unsafe {
+            let base = (self.header_ptr() as *mut u8).add(mem::size_of::<ArenaHeader>());
+            (base as *mut BlockMeta).add(index as usize)
+        }
+    }
+
+    /// 空闲块的链表指针，只能在持有分配区的锁时解引用
+    fn link_ptr(&self, index: u32) -> *mut FreeLink {
+        self.data_ptr(index as u64 * MIN_BLOCK_SIZE as u64) as *mut FreeLink
+    }
+
+    fn data_ptr(&self, offset: u64) -> *mut u8 {
+        unsafe {
+            (self.header_ptr() as *mut u8).add(Self::data_offset(self.blocks) + offset as usize)
+        }
+    }
+
+    fn data_offset(blocks: usize) -> usize {
+        (mem::size_of::<ArenaHeader>() + blocks * mem::size_of::<BlockMeta>())
+            .next_multiple_of(DATA_ALIGN)
+    }
+
+    fn mapped_size(blocks: usize) -> usize {
+        Self::data_offset(blocks) + blocks * MIN_BLOCK_SIZE
+    }
+}
+
+impl Drop for ShmArena {
+    fn drop(&mut self) {
+        if self.owner
+            && let Err(e) = SharedMemoryRegistry::unlink(&self.name)
+        {
+            tracing::warn!("删除共享内存段 {} 失败: {}", self.name, e);
+        }
+    }
+}
+
+struct ArenaGuard<'a> {
+    mutex: &'a ShmMutex,
+}
+
+impl Drop for ArenaGuard<'_> {
+    fn drop(&mut self) {
+        unsafe { self.mutex.unlock() };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arena_alloc_free_and_merge() {
+        let name = format!("mi7_test_shm_arena_{}", std::process::id());
+        let arena = ShmArena::create(&name, 64 * 1024).unwrap();
+        let other = ShmArena::connect(&name).unwrap();
+        assert_eq!(other.capacity(), 64 * 1024);
+
+        // 10 KB 只占用 16 KB 的块
+        let payload = vec![7u8; 10 * 1024];
+        let big = arena.store(&payload).unwrap();
+        assert_eq!(other.read(&big).unwrap(), payload);
+        let small: Vec<_> = (0..4u8).map(|i| other.store(&[i; 100]).unwrap()).collect();
+        let stats = arena.stats();
+        assert_eq!(stats.allocated, 16 * 1024 + 4 * MIN_BLOCK_SIZE);
+        assert_eq!(stats.live, 5);
+        assert_eq!(stats.largest_free, 32 * 1024);
+
+        let err = arena.alloc(48 * 1024).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(ArenaError::Exhausted { .. })
+        ));
+        assert!(arena.alloc(128 * 1024).is_err());
+
+        // 释放后旧句柄失效，块被重新分配时代数变化
+        assert_eq!(arena.take(small[0]).unwrap(), [0u8; 100]);
+        let err = arena.free(small[0]).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(ArenaError::StaleHandle { .. })
+        ));
+        let reused = arena.alloc(100).unwrap();
+        assert_eq!(reused.offset, small[0].offset);
+        assert_ne!(reused.generation, small[0].generation);
+        assert!(other.read(&small[0]).is_err());
+
+        // 全部释放后伙伴块合并回整个数据区
+        for handle in small.into_iter().skip(1).chain([reused, big]) {
+            other.free(handle).unwrap();
+        }
+        let stats = arena.stats();
+        assert_eq!((stats.allocated, stats.live), (0, 0));
+        assert_eq!(stats.largest_free, 64 * 1024);
+        assert_eq!(stats.failures, 1);
+        assert!(arena.alloc(64 * 1024).is_ok());
+    }
+}
@@ -0,0 +1,184 @@
+//! 共享内存段的生命周期管理
+//!
+//! 由本进程 `create` 出来的共享内存段会登记到进程内的注册表，持有者 `Drop`
+//! 或显式 `unlink`/`destroy` 时从 `/dev/shm` 中移除；守护进程启动时可以调用
+//! [`SharedMemoryRegistry::cleanup_stale`] 清理上次异常退出遗留的段。
+
+use crate::error::Result;
+use std::collections::HashSet;
+use std::ffi::CString;
+use std::sync::{Mutex, OnceLock};
+use tracing::{debug, warn};
+
+use crate::notify::PipeNotifier;
+use crate::shm_sync;
+
+static OWNED: OnceLock<Mutex<HashSet<String>>> = OnceLock::new();
+
+/// 共享内存段注册表
+pub struct SharedMemoryRegistry;
+
+impl SharedMemoryRegistry {
+    fn owned_set() -> &'static Mutex<HashSet<String>> {
+        OWNED.get_or_init(|| Mutex::new(HashSet::new()))
+    }
+
+    /// 规范化共享内存名称（去掉开头的 '/'）
+    fn normalize(name: &str) -> &str {
+        name.trim_start_matches('/')
+    }
+
+    /// 登记一个由本进程创建并负责清理的共享内存段
+    pub fn register(name: &str) {
+        let name = Self::normalize(name).to_string();
+        debug!("登记共享内存段: {}", name);
+        Self::owned_set().lock().unwrap().insert(name);
+    }
+
+    /// 取消登记，返回该段此前是否已登记
+    pub fn deregister(name: &str) -> bool {
+        Self::owned_set()
+            .lock()
+            .unwrap()
+            .remove(Self::normalize(name))
+    }
+
+    /// 本进程当前持有的共享内存段
+    pub fn owned() -> Vec<String> {
+        let mut names: Vec<String> = Self::owned_set().lock().unwrap().iter().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// 删除共享内存段的名称
+    ///
+    /// 已映射该段的进程不受影响，映射全部解除后内核回收内存。名称不存在时视为成功。
+    pub fn unlink(name: &str) -> Result<()> {
+        let name = Self::normalize(name);
+        Self::deregister(name);
+
+        let cname = CString::new(format!("/{}", name))
+            .map_err(|_| anyhow::anyhow!("Failed to create CString from name"))?;
+        if unsafe { libc::shm_unlink(cname.as_ptr()) } == -1 {
+            let errno = shm_sync::errno();
+            if errno != libc::ENOENT {
+                return Err(
+                    anyhow::anyhow!("shm_unlink {} failed with errno: {}", name, errno).into(),
+                );
+            }
+        }
+        // 同时删除管道的通知 FIFO（不存在时忽略）
+        let _ = std::fs::remove_file(PipeNotifier::path_for(name));
+        let _ = std::fs::remove_file(PipeNotifier::space_path_for(name));
+        debug!("已删除共享内存段: {}", name);
+        Ok(())
+    }
+
+    /// 删除本进程登记的所有共享内存段，返回成功删除的数量
+    pub fn unlink_all_owned() -> usize {
+        Self::owned()
+            .into_iter()
+            .filter(|name| match Self::unlink(name) {
+                Ok(()) => true,
+                Err(e) => {
+                    warn!("删除共享内存段失败: {}", e);
+                    false
+                }
+            })
+            .count()
+    }
+
+    /// 清理以 `prefix` 开头、且没有任何进程映射的共享内存段
+    ///
+    /// 通过扫描 `/dev/shm` 与 `/proc/*/maps` 判断段是否仍在使用，返回被删除的名称。
+    /// 非 Linux 平台无法枚举共享内存段，直接返回空列表。
+    pub fn cleanup_stale(prefix: &str) -> Result<Vec<String>> {
+        #[cfg(target_os = "linux")]
+        {
+            let prefix = Self::normalize(prefix);
+            let in_use = Self::mapped_segments();
+            let mut removed = Vec::new();
+
+            for entry in std::fs::read_dir("/dev/shm")? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if !name.starts_with(prefix) || in_use.contains(&name) {
+                    continue;
+                }
+
+                match Self::unlink(&name) {
+                    Ok(()) => removed.push(name),
+                    Err(e) => warn!("清理遗留共享内存段失败: {}", e),
+                }
+            }
+
+            Ok(removed)
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = prefix;
+            Ok(Vec::new())
+        }
+    }
+
+    /// 列出以 `prefix` 开头的共享内存段，按名称排序，并给出是否仍被进程映射
+    ///
+    /// 非 Linux 平台无法枚举共享内存段，直接返回空列表。
+    pub fn list(prefix: &str) -> Result<Vec<(String, bool)>> {
+        #[cfg(target_os = "linux")]
+        {
+            let prefix = Self::normalize(prefix);
+            let in_use = Self::mapped_segments();
+            let mut segments = Vec::new();
+            for entry in std::fs::read_dir("/dev/shm")? {
+                let name = entry?.file_name().to_string_lossy().into_owned();
+                if name.starts_with(prefix) {
+                    let mapped = in_use.contains(&name);
+                    segments.push((name, mapped));
+                }
+            }
+            segments.sort();
+            Ok(segments)
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = prefix;
+            Ok(Vec::new())
+        }
+    }
+
+    /// 收集所有进程当前映射的 /dev/shm 段名称
+    #[cfg(target_os = "linux")]
+    fn mapped_segments() -> HashSet<String> {
+        let mut segments = HashSet::new();
+        let Ok(procs) = std::fs::read_dir("/proc") else {
+            return segments;
+        };
+
+        for entry in procs.flatten() {
+            let file_name = entry.file_name();
+            if !file_name
+                .to_string_lossy()
+                .bytes()
+                .all(|b| b.is_ascii_digit())
+            {
+                continue;
+            }
+
+            // 进程可能已退出或无权限读取，忽略即可
+            let Ok(maps) = std::fs::read_to_string(entry.path().join("maps")) else {
+                continue;
+            };
+            for line in maps.lines() {
+                if let Some(pos) = line.find("/dev/shm/") {
+                    let name = line[pos + "/dev/shm/".len()..].trim_end_matches(" (deleted)");
+                    segments.insert(name.to_string());
+                }
+            }
+        }
+
+        segments
+    }
+}
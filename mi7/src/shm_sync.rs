@@ -0,0 +1,506 @@
+//! 共享内存中的进程间同步原语
+//!
+//! - Linux：robust + process-shared 的 pthread 互斥锁与使用单调时钟的条件变量，
+//!   持有者进程崩溃后通过 `EOWNERDEAD` 恢复锁的一致性。
+//! - macOS 等其他平台：没有 `PTHREAD_MUTEX_ROBUST`，互斥锁退化为记录持有者 PID 的自旋锁，
+//!   竞争时用 `kill(pid, 0)` 探测持有者是否存活，持有者已退出则直接接管；
+//!   条件变量退化为序列号 + 短睡眠轮询。
+
+use crate::lock_debug;
+
+use crate::error::Result;
+use libc::{CLOCK_MONOTONIC, timespec};
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::time::Duration;
+
+#[cfg(not(target_os = "linux"))]
+pub use fallback::{ShmCondvar, ShmMutex};
+#[cfg(target_os = "linux")]
+pub use linux::{ShmCondvar, ShmMutex};
+
+/// 缓存行大小
+pub const CACHE_LINE: usize = 64;
+
+/// 独占一个缓存行的字段
+///
+/// 生产者与消费者各自频繁写入的字段放在同一缓存行时，双方所在的核心会反复使对方的
+/// 缓存行失效（伪共享）。包装后字段按缓存行对齐，并填充到缓存行大小。
+#[repr(C, align(64))]
+#[derive(Debug, Default)]
+pub struct CachePadded<T>(pub T);
+
+impl<T> CachePadded<T> {
+    pub const fn new(value: T) -> Self {
+        Self(value)
+    }
+}
+
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for CachePadded<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+/// 共享内存互斥锁的创建属性，连接方无需关心，属性随锁一起保存在共享内存中
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MutexAttr {
+    /// 优先级继承（`PTHREAD_PRIO_INHERIT`）：低优先级的持有者阻塞高优先级的等待者时，
+    /// 临时提升到等待者的优先级，避免优先级反转。仅 Linux 支持，其他平台忽略
+    pub priority_inherit: bool,
+}
+
+impl MutexAttr {
+    /// 启用优先级继承的属性
+    pub fn priority_inherit() -> Self {
+        Self {
+            priority_inherit: true,
+        }
+    }
+
+    /// 写入共享内存头部的表示
+    pub fn to_bits(self) -> u32 {
+        self.priority_inherit as u32
+    }
+
+    /// 从共享内存头部记录的表示还原
+    pub fn from_bits(bits: u32) -> Self {
+        Self {
+            priority_inherit: bits & 1 != 0,
+        }
+    }
+}
+
+/// 读取当前线程的 errno（跨平台，替代 `__errno_location`）
+pub fn errno() -> i32 {
+    std::io::Error::last_os_error().raw_os_error().unwrap_or(0)
+}
+
+/// 进程是否仍然存活（`kill(pid, 0)` 探测，无权限时视为存活）
+pub fn process_alive(pid: u32) -> bool {
+    let result = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    result == 0 || errno() != libc::ESRCH
+}
+
+/// 计算单调时钟上的绝对超时时间
+pub fn deadline_after(timeout: Duration) -> timespec {
+    let mut now: timespec = unsafe { mem::zeroed() };
+    unsafe {
+        libc::clock_gettime(CLOCK_MONOTONIC, &mut now);
+    }
+
+    let total_nanos = now.tv_nsec as u64 + timeout.subsec_nanos() as u64;
+    timespec {
+        tv_sec: now
+            .tv_sec
+            .saturating_add(timeout.as_secs() as libc::time_t)
+            .saturating_add((total_nanos / 1_000_000_000) as libc::time_t),
+        tv_nsec: (total_nanos % 1_000_000_000) as libc::c_long,
+    }
+}
+
+/// 单调时钟上的 `deadline` 距现在的剩余时间，已过期时为 0
+pub fn time_until(deadline: &timespec) -> Duration {
+    let now = deadline_after(Duration::ZERO);
+    let deadline = Duration::new(deadline.tv_sec.max(0) as u64, deadline.tv_nsec as u32);
+    deadline.saturating_sub(Duration::new(now.tv_sec.max(0) as u64, now.tv_nsec as u32))
+}
+
+/// 计算实时时钟上的绝对超时时间（pthread 的 `timedlock` 系列接口使用实时时钟）
+pub fn realtime_after(timeout: Duration) -> timespec {
+    let mut now: timespec = unsafe { mem::zeroed() };
+    unsafe {
+        libc::clock_gettime(libc::CLOCK_REALTIME, &mut now);
+    }
+
+    let total_nanos = now.tv_nsec as u64 + timeout.subsec_nanos() as u64;
+    timespec {
+        tv_sec: now
+            .tv_sec
+            .saturating_add(timeout.as_secs() as libc::time_t)
+            .saturating_add((total_nanos / 1_000_000_000) as libc::time_t),
+        tv_nsec: (total_nanos % 1_000_000_000) as libc::c_long,
+    }
+}
+
+/// 单调时钟上的当前时间（纳秒），同一台机器上的所有进程可比较
+pub fn monotonic_nanos() -> u64 {
+    let now = deadline_after(Duration::ZERO);
+    now.tv_sec as u64 * 1_000_000_000 + now.tv_nsec as u64
+}
+
+/// 单调时钟上的当前时间（毫秒），同一台机器上的所有进程可比较
+pub fn monotonic_millis() -> u64 {
+    let now = deadline_after(Duration::ZERO);
+    now.tv_sec as u64 * 1000 + now.tv_nsec as u64 / 1_000_000
+}
+
+/// 单调时钟上的当前时间是否已超过 `deadline`
+pub fn is_expired(deadline: &timespec) -> bool {
+    let now = deadline_after(Duration::ZERO);
+    (now.tv_sec, now.tv_nsec) >= (deadline.tv_sec, deadline.tv_nsec)
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::*;
+    use crate::futex;
+    use libc::{
+        EOWNERDEAD, PTHREAD_MUTEX_ROBUST, PTHREAD_PRIO_INHERIT, PTHREAD_PROCESS_SHARED,
+        pthread_mutex_consistent, pthread_mutex_init, pthread_mutex_lock, pthread_mutex_t,
+        pthread_mutex_timedlock, pthread_mutex_trylock, pthread_mutex_unlock,
+        pthread_mutexattr_init, pthread_mutexattr_setprotocol, pthread_mutexattr_setpshared,
+        pthread_mutexattr_setrobust, pthread_mutexattr_t,
+    };
+    use std::cell::UnsafeCell;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// 进程间 robust 互斥锁
+    ///
+    /// 加锁与解锁只需要共享引用：锁本身就是供多个线程与进程同时访问的。
+    #[repr(C)]
+    pub struct ShmMutex {
+        raw: UnsafeCell<pthread_mutex_t>,
+    }
+
+    unsafe impl Send for ShmMutex {}
+    unsafe impl Sync for ShmMutex {}
+
+    impl ShmMutex {
+        /// 就地初始化
+        ///
+        /// # Safety
+        /// `self` 必须位于共享内存中，且只能由创建者在其他进程连接前初始化一次。
+        pub unsafe fn init(&mut self) -> Result<()> {
+            unsafe { self.init_with(MutexAttr::default()) }
+        }
+
+        /// 按指定属性就地初始化
+        ///
+        /// # Safety
+        /// 同 [`ShmMutex::init`]。
+        pub unsafe fn init_with(&mut self, options: MutexAttr) -> Result<()> {
+            let mut attr: pthread_mutexattr_t = unsafe { mem::zeroed() };
+            unsafe {
+                pthread_mutexattr_init(&mut attr);
+                pthread_mutexattr_setpshared(&mut attr, PTHREAD_PROCESS_SHARED);
+                pthread_mutexattr_setrobust(&mut attr, PTHREAD_MUTEX_ROBUST);
+                if options.priority_inherit
+                    && pthread_mutexattr_setprotocol(&mut attr, PTHREAD_PRIO_INHERIT) != 0
+                {
+                    return Err(
+                        anyhow::anyhow!("Priority inheritance mutexes are not supported").into(),
+                    );
+                }
+
+                if pthread_mutex_init(self.raw.get_mut(), &attr) != 0 {
+                    return Err(anyhow::anyhow!("Failed to initialize mutex").into());
+                }
+            }
+            Ok(())
+        }
+
+        /// 加锁，持有者进程崩溃时恢复锁的一致性
+        ///
+        /// # Safety
+        /// `self` 必须已通过 [`ShmMutex::init`] 初始化。
+        pub unsafe fn lock(&self) -> bool {
+            lock_debug::waiting(self);
+            let result = unsafe { pthread_mutex_lock(self.raw.get()) };
+            if result == EOWNERDEAD {
+                unsafe {
+                    pthread_mutex_consistent(self.raw.get());
+                }
+            } else if result != 0 {
+                lock_debug::abandoned(self);
+                return false;
+            }
+            lock_debug::acquired(self);
+            true
+        }
+
+        /// 加锁，最多等待 `timeout`，超时返回 `false`
+        ///
+        /// # Safety
+        /// 同 [`ShmMutex::lock`]。
+        pub unsafe fn lock_timeout(&self, timeout: Duration) -> bool {
+            let abstime = realtime_after(timeout);
+            lock_debug::waiting(self);
+            let locked = match unsafe { pthread_mutex_timedlock(self.raw.get(), &abstime) } {
+                0 => true,
+                EOWNERDEAD => {
+                    unsafe { pthread_mutex_consistent(self.raw.get()) };
+                    true
+                }
+                _ => false,
+            };
+            if locked {
+                lock_debug::acquired(self);
+            } else {
+                lock_debug::abandoned(self);
+            }
+            locked
+        }
+
+        /// 尝试加锁，锁已被占用时立即返回 `false`
+        ///
+        /// # Safety
+        /// 同 [`ShmMutex::lock`]。
+        pub unsafe fn try_lock(&self) -> bool {
+            let locked = match unsafe { pthread_mutex_trylock(self.raw.get()) } {
+                0 => true,
+                EOWNERDEAD => {
+                    unsafe { pthread_mutex_consistent(self.raw.get()) };
+                    true
+                }
+                _ => false,
+            };
+            if locked {
+                lock_debug::acquired(self);
+            }
+            locked
+        }
+
+        /// 解锁
+        ///
+        /// # Safety
+        /// 调用者必须持有该锁。
+        pub unsafe fn unlock(&self) {
+            lock_debug::released(self);
+            unsafe {
+                pthread_mutex_unlock(self.raw.get());
+            }
+        }
+    }
+
+    /// 基于 futex 序列号的进程间条件变量（单调时钟）
+    ///
+    /// 不使用 `pthread_cond_t`：glibc 的进程间条件变量不是 robust 的，等待者在
+    /// `pthread_cond_wait` 中被 SIGKILL 后，之后的 `pthread_cond_signal` 可能永久阻塞。
+    /// 序列号上的 futex 不记录等待者，崩溃的进程不会留下任何状态。
+    #[repr(C)]
+    pub struct ShmCondvar {
+        seq: AtomicU32,
+    }
+
+    impl ShmCondvar {
+        /// 就地初始化
+        ///
+        /// # Safety
+        /// 同 [`ShmMutex::init`]。
+        pub unsafe fn init(&mut self) -> Result<()> {
+            self.seq = AtomicU32::new(0);
+            Ok(())
+        }
+
+        /// 等待通知，`deadline` 为 `None` 时无限等待
+        ///
+        /// 返回 `false` 表示已超时；可能被虚假唤醒，调用者需重新检查条件
+        ///
+        /// # Safety
+        /// 调用者必须持有 `mutex`，且 `mutex` 是与该条件变量配对的锁。
+        pub unsafe fn wait(&mut self, mutex: &mut ShmMutex, deadline: Option<&timespec>) -> bool {
+            // 持有锁时读取序列号，解锁之后的通知会让 futex 等待立即返回
+            let seen = self.seq.load(Ordering::Acquire);
+            unsafe { mutex.unlock() };
+
+            let notified = match deadline {
+                Some(abstime) => {
+                    let remaining = time_until(abstime);
+                    !remaining.is_zero() && futex::futex_wait(&self.seq, seen, Some(remaining))
+                }
+                None => futex::futex_wait(&self.seq, seen, None),
+            };
+
+            unsafe { mutex.lock() };
+            notified || self.seq.load(Ordering::Acquire) != seen
+        }
+
+        /// 唤醒一个等待者
+        ///
+        /// # Safety
+        /// `self` 必须已通过 [`ShmCondvar::init`] 初始化。
+        pub unsafe fn signal(&mut self) {
+            self.seq.fetch_add(1, Ordering::Release);
+            futex::futex_wake(&self.seq, 1);
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod fallback {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// 轮询等待的最长睡眠间隔
+    const MAX_BACKOFF: Duration = Duration::from_millis(1);
+
+    /// 记录持有者 PID 的进程间自旋锁
+    #[repr(C)]
+    pub struct ShmMutex {
+        owner: AtomicU32,
+    }
+
+    impl ShmMutex {
+        /// 就地初始化
+        ///
+        /// # Safety
+        /// `self` 必须位于共享内存中，且只能由创建者在其他进程连接前初始化一次。
+        pub unsafe fn init(&mut self) -> Result<()> {
+            self.owner = AtomicU32::new(0);
+            Ok(())
+        }
+
+        /// 按指定属性就地初始化，自旋锁不支持优先级继承，属性被忽略
+        ///
+        /// # Safety
+        /// 同 [`ShmMutex::init`]。
+        pub unsafe fn init_with(&mut self, _options: MutexAttr) -> Result<()> {
+            unsafe { self.init() }
+        }
+
+        /// 加锁，持有者进程已退出时接管锁
+        ///
+        /// # Safety
+        /// `self` 必须已通过 [`ShmMutex::init`] 初始化。
+        pub unsafe fn lock(&self) -> bool {
+            self.acquire(None)
+        }
+
+        /// 加锁，最多等待 `timeout`，超时返回 `false`
+        ///
+        /// # Safety
+        /// 同 [`ShmMutex::lock`]。
+        pub unsafe fn lock_timeout(&self, timeout: Duration) -> bool {
+            self.acquire(Some(deadline_after(timeout)))
+        }
+
+        fn acquire(&self, deadline: Option<timespec>) -> bool {
+            lock_debug::waiting(self);
+            let locked = self.spin(deadline);
+            if locked {
+                lock_debug::acquired(self);
+            } else {
+                lock_debug::abandoned(self);
+            }
+            locked
+        }
+
+        fn spin(&self, deadline: Option<timespec>) -> bool {
+            let pid = std::process::id();
+            let mut spins = 0u32;
+            loop {
+                match self
+                    .owner
+                    .compare_exchange(0, pid, Ordering::Acquire, Ordering::Relaxed)
+                {
+                    Ok(_) => return true,
+                    Err(owner) if owner != pid && !process_alive(owner) => {
+                        // 持有者进程已不存在，接管锁
+                        if self
+                            .owner
+                            .compare_exchange(owner, pid, Ordering::Acquire, Ordering::Relaxed)
+                            .is_ok()
+                        {
+                            return true;
+                        }
+                    }
+                    Err(_) => {}
+                }
+                if deadline.as_ref().is_some_and(is_expired) {
+                    return false;
+                }
+
+                spins = spins.saturating_add(1);
+                if spins < 64 {
+                    std::hint::spin_loop();
+                } else {
+                    std::thread::sleep(MAX_BACKOFF.min(Duration::from_micros(spins as u64)));
+                }
+            }
+        }
+
+        /// 尝试加锁，锁已被占用时立即返回 `false`（不检查持有者是否存活）
+        ///
+        /// # Safety
+        /// 同 [`ShmMutex::lock`]。
+        pub unsafe fn try_lock(&self) -> bool {
+            let locked = self
+                .owner
+                .compare_exchange(0, std::process::id(), Ordering::Acquire, Ordering::Relaxed)
+                .is_ok();
+            if locked {
+                lock_debug::acquired(self);
+            }
+            locked
+        }
+
+        /// 解锁
+        ///
+        /// # Safety
+        /// 调用者必须持有该锁。
+        pub unsafe fn unlock(&self) {
+            lock_debug::released(self);
+            self.owner.store(0, Ordering::Release);
+        }
+    }
+
+    /// 基于序列号轮询的进程间条件变量
+    #[repr(C)]
+    pub struct ShmCondvar {
+        seq: AtomicU32,
+    }
+
+    impl ShmCondvar {
+        /// 就地初始化
+        ///
+        /// # Safety
+        /// 同 [`ShmMutex::init`]。
+        pub unsafe fn init(&mut self) -> Result<()> {
+            self.seq = AtomicU32::new(0);
+            Ok(())
+        }
+
+        /// 等待通知，`deadline` 为 `None` 时无限等待
+        ///
+        /// 返回 `false` 表示已超时
+        ///
+        /// # Safety
+        /// 调用者必须持有 `mutex`，且 `mutex` 是与该条件变量配对的锁。
+        pub unsafe fn wait(&mut self, mutex: &mut ShmMutex, deadline: Option<&timespec>) -> bool {
+            let start = self.seq.load(Ordering::Acquire);
+            unsafe { mutex.unlock() };
+
+            let mut backoff = Duration::from_micros(10);
+            let notified = loop {
+                if self.seq.load(Ordering::Acquire) != start {
+                    break true;
+                }
+                if deadline.is_some_and(is_expired) {
+                    break false;
+                }
+                std::thread::sleep(backoff);
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            };
+
+            unsafe { mutex.lock() };
+            notified
+        }
+
+        /// 唤醒等待者
+        ///
+        /// # Safety
+        /// `self` 必须已通过 [`ShmCondvar::init`] 初始化。
+        pub unsafe fn signal(&mut self) {
+            self.seq.fetch_add(1, Ordering::Release);
+        }
+    }
+}
@@ -0,0 +1,232 @@
+//! 跨进程优雅停止协调
+//!
+//! 守护进程创建一个共享内存控制块并在收到停止信号时置位；entry 与 worker
+//! 连接控制块并登记自己的 PID，观察到停止请求后停止接收新任务、处理完在途任务，
+//! 再在控制块中确认。守护进程等待所有存活参与者确认（或超时）后退出。
+
+use crate::futex;
+use crate::locks::Segment;
+use crate::shm_sync;
+
+use crate::error::Result;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::Duration;
+
+/// 控制块可登记的参与进程数量
+pub const MAX_PARTICIPANTS: usize = 64;
+
+/// 异步等待停止请求 / 确认时的轮询间隔
+const SHUTDOWN_POLL: Duration = Duration::from_millis(50);
+
+/// 共享内存中的控制块，全零即为"运行中、无参与者"的初始状态
+#[repr(C)]
+struct ControlBlock {
+    shutdown: AtomicU32,                         // futex 字：0 运行中，1 已请求停止
+    requested_at: AtomicU64,                     // 请求停止的时间（Unix 毫秒）
+    participants: [AtomicU32; MAX_PARTICIPANTS], // 参与进程 PID，0 表示空闲
+    acked: [AtomicU32; MAX_PARTICIPANTS],        // 1 表示对应参与者已确认停止
+}
+
+/// 优雅停止协调器
+pub struct ShutdownCoordinator {
+    segment: Segment<ControlBlock>,
+    index: Option<usize>,
+}
+
+impl ShutdownCoordinator {
+    /// 创建（或重置）控制块，由守护进程持有，Drop 时删除
+    pub fn create(name: &str) -> Result<Self> {
+        Ok(Self {
+            segment: Segment::create(name, |_| {})?,
+            index: None,
+        })
+    }
+
+    /// 连接守护进程创建的控制块并登记为参与者
+    pub fn open(name: &str) -> Result<Self> {
+        let mut coordinator = Self {
+            segment: Segment::open(name)?,
+            index: None,
+        };
+        coordinator.index = coordinator.register();
+        if coordinator.index.is_none() {
+            tracing::warn!("控制块 {} 的参与者表已满，本进程不参与停止确认", name);
+        }
+        Ok(coordinator)
+    }
+
+    fn block(&self) -> &ControlBlock {
+        self.segment.get()
+    }
+
+    /// 在参与者表中登记当前进程，必要时先清理已退出进程的记录
+    fn register(&self) -> Option<usize> {
+        let pid = std::process::id();
+        let block = self.block();
+        for _ in 0..2 {
+            for (i, entry) in block.participants.iter().enumerate() {
+                if entry
+                    .compare_exchange(0, pid, Ordering::AcqRel, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    block.acked[i].store(0, Ordering::Release);
+                    return Some(i);
+                }
+            }
+            self.prune();
+        }
+        None
+    }
+
+    /// 清理已退出进程的参与记录
+    fn prune(&self) {
+        let block = self.block();
+        for (entry, acked) in block.participants.iter().zip(block.acked.iter()) {
+            let pid = entry.load(Ordering::Acquire);
+            if pid != 0
+                && !shm_sync::process_alive(pid)
+                && entry
+                    .compare_exchange(pid, 0, Ordering::AcqRel, Ordering::Relaxed)
+                    .is_ok()
+            {
+                acked.store(0, Ordering::Release);
+            }
+        }
+    }
+
+    /// 控制块名称
+    pub fn name(&self) -> &str {
+        self.segment.name()
+    }
+
+    /// 是否为控制块的持有者（Drop 时负责删除）
+    pub fn is_owner(&self) -> bool {
+        self.segment.is_owner()
+    }
+
+    /// 请求所有参与者停止
+    pub fn trigger(&self) {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let block = self.block();
+        block.requested_at.store(now, Ordering::Release);
+        block.shutdown.store(1, Ordering::Release);
+        futex::futex_wake(&block.shutdown, u32::MAX);
+    }
+
+    /// 是否已请求停止
+    pub fn is_shutdown_requested(&self) -> bool {
+        self.block().shutdown.load(Ordering::Acquire) != 0
+    }
+
+    /// 请求停止的时间（Unix 毫秒），尚未请求时为 0
+    pub fn requested_at(&self) -> u64 {
+        self.block().requested_at.load(Ordering::Acquire)
+    }
+
+    /// 阻塞等待停止请求，超时返回 `false`
+    pub fn wait(&self, timeout: Option<Duration>) -> bool {
+        let block = self.block();
+        futex::wait_until(&block.shutdown, timeout, || {
+            block.shutdown.load(Ordering::Acquire) != 0
+        })
+    }
+
+    /// 异步等待停止请求
+    pub async fn wait_for_shutdown(&self) {
+        while !self.is_shutdown_requested() {
+            tokio::time::sleep(SHUTDOWN_POLL).await;
+        }
+    }
+
+    /// 确认当前进程已完成停止前的清理
+    pub fn acknowledge(&self) {
+        if let Some(index) = self.index {
+            self.block().acked[index].store(1, Ordering::Release);
+        }
+    }
+
+    /// 尚未确认停止的存活参与者 PID（不含当前进程）
+    pub fn pending_acks(&self) -> Vec<u32> {
+        self.prune();
+        let own_pid = std::process::id();
+        let block = self.block();
+        block
+            .participants
+            .iter()
+            .zip(block.acked.iter())
+            .filter_map(|(entry, acked)| {
+                let pid = entry.load(Ordering::Acquire);
+                (pid != 0 && pid != own_pid && acked.load(Ordering::Acquire) == 0).then_some(pid)
+            })
+            .collect()
+    }
+
+    /// 等待所有参与者确认停止，返回超时后仍未确认的 PID
+    pub async fn wait_for_acks(&self, timeout: Duration) -> Vec<u32> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let pending = self.pending_acks();
+            if pending.is_empty() || tokio::time::Instant::now() >= deadline {
+                return pending;
+            }
+            tokio::time::sleep(SHUTDOWN_POLL).await;
+        }
+    }
+}
+
+impl Drop for ShutdownCoordinator {
+    fn drop(&mut self) {
+        // 已确认的参与者保留记录，守护进程据此判断；未确认的视为离开
+        if let Some(index) = self.index {
+            let block = self.block();
+            if block.acked[index].load(Ordering::Acquire) == 0 {
+                let _ = block.participants[index].compare_exchange(
+                    std::process::id(),
+                    0,
+                    Ordering::AcqRel,
+                    Ordering::Relaxed,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_shutdown_trigger_and_ack() {
+        let name = format!("mi7_test_shutdown_{}", std::process::id());
+        assert!(
+            ShutdownCoordinator::open(&name).is_err(),
+            "连接方不创建控制块"
+        );
+        let daemon = ShutdownCoordinator::create(&name).unwrap();
+        let worker = ShutdownCoordinator::open(&name).unwrap();
+
+        assert!(!worker.is_shutdown_requested());
+        assert!(!worker.wait(Some(Duration::from_millis(10))));
+
+        // 同一进程内参与者 PID 与守护进程相同，pending_acks 会排除自身，
+        // 这里直接检查确认标志
+        daemon.trigger();
+        assert!(worker.wait(Some(Duration::from_secs(1))));
+        worker.wait_for_shutdown().await;
+        assert!(daemon.requested_at() > 0);
+
+        let index = worker.index.unwrap();
+        assert_eq!(daemon.block().acked[index].load(Ordering::Acquire), 0);
+        worker.acknowledge();
+        assert_eq!(daemon.block().acked[index].load(Ordering::Acquire), 1);
+        assert!(
+            daemon
+                .wait_for_acks(Duration::from_millis(10))
+                .await
+                .is_empty()
+        );
+    }
+}
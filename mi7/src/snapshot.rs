@@ -0,0 +1,273 @@
+//! 队列快照：把管道中尚未处理完成的消息保存到文件，维护后再写回
+//!
+//! [`DynCrossProcessPipe::snapshot`](crate::pipe::DynCrossProcessPipe::snapshot) 在不消费
+//! 消息的前提下复制所有已写入的槽位（READY，以及读取方已取出但尚未确认的槽位），连同
+//! 管道头部的编解码、校验与密钥标识一起写入快照文件；
+//! [`DynCrossProcessPipe::restore`](crate::pipe::DynCrossProcessPipe::restore) 校验快照后
+//! 以原 request_id 与投递次数把消息重新放入管道。
+//!
+//! 槽位中保存的是编码（以及加密）后的原始字节，快照不解码也不解密，恢复时只能写回
+//! 编解码方式、校验算法与密钥标识都一致的管道。读取方已取出的消息同样被保存，
+//! 恢复后可能被再次投递（至少一次）。
+//!
+//! 文件布局：头部、按 request_id 排列的记录，最后是此前全部内容的 xxHash64；
+//! 先写临时文件并 `fsync`，再原子地重命名为目标路径。
+
+use crate::error::Result;
+use anyhow::{Context, anyhow};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// 快照文件标识
+const SNAPSHOT_MAGIC: u64 = u64::from_le_bytes(*b"MI7SNAP\0");
+
+/// 快照文件布局版本
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// 快照中的一条消息：槽位元数据与原始数据
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotEntry {
+    pub request_id: u64,
+    /// 已失败的投递次数，恢复后沿用
+    pub delivery_attempts: u32,
+    /// 数据校验和，按管道的校验算法计算
+    pub checksum: u64,
+    /// 消息认证码，未启用认证时为 0
+    pub mac: u64,
+    pub data: Vec<u8>,
+}
+
+/// 队列快照
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueueSnapshot {
+    /// 编解码方式（`CodecKind`）
+    pub codec: u32,
+    /// 校验算法（`Integrity`）
+    pub integrity: u32,
+    /// 负载加密算法（`Encryption`）
+    pub encryption: u32,
+    /// 负载密钥标识，0 表示未加密
+    pub key_id: u64,
+    /// 消息认证密钥标识，0 表示不认证
+    pub auth_key_id: u64,
+    /// 源管道的容量
+    pub capacity: u64,
+    /// 源管道的槽位大小
+    pub slot_size: u64,
+    /// 源管道的 request_id 生成器，恢复后新消息的 request_id 不小于该值
+    pub next_request_id: u64,
+    /// 按 request_id 排列的消息
+    pub entries: Vec<SnapshotEntry>,
+}
+
+impl QueueSnapshot {
+    /// 写入快照文件，先写同目录下的临时文件，完整落盘后再重命名
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let temp = Path::new(&temp);
+
+        let file =
+            File::create(temp).with_context(|| format!("创建快照文件 {} 失败", temp.display()))?;
+        let mut writer = HashingWriter::new(BufWriter::new(file));
+        self.write_body(&mut writer)?;
+        let digest = writer.hasher.digest();
+        let mut writer = writer.inner;
+        writer.write_all(&digest.to_le_bytes())?;
+        let file = writer.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        std::fs::rename(temp, path)
+            .with_context(|| format!("重命名快照文件 {} 失败", path.display()))?;
+        Ok(())
+    }
+
+    /// 读取并校验快照文件
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file =
+            File::open(path).with_context(|| format!("打开快照文件 {} 失败", path.display()))?;
+        let mut reader = HashingReader::new(BufReader::new(file));
+        let snapshot = Self::read_body(&mut reader)
+            .with_context(|| format!("快照文件 {} 格式无效", path.display()))?;
+        let digest = reader.hasher.digest();
+        let mut trailer = [0u8; 8];
+        reader
+            .inner
+            .read_exact(&mut trailer)
+            .with_context(|| format!("快照文件 {} 不完整", path.display()))?;
+        if u64::from_le_bytes(trailer) != digest {
+            return Err(anyhow!("快照文件 {} 校验失败", path.display()).into());
+        }
+        Ok(snapshot)
+    }
+
+    fn write_body(&self, w: &mut impl Write) -> Result<()> {
+        w.write_all(&SNAPSHOT_MAGIC.to_le_bytes())?;
+        w.write_all(&SNAPSHOT_VERSION.to_le_bytes())?;
+        w.write_all(&self.codec.to_le_bytes())?;
+        w.write_all(&self.integrity.to_le_bytes())?;
+        w.write_all(&self.encryption.to_le_bytes())?;
+        w.write_all(&self.key_id.to_le_bytes())?;
+        w.write_all(&self.auth_key_id.to_le_bytes())?;
+        w.write_all(&self.capacity.to_le_bytes())?;
+        w.write_all(&self.slot_size.to_le_bytes())?;
+        w.write_all(&self.next_request_id.to_le_bytes())?;
+        w.write_all(&(self.entries.len() as u64).to_le_bytes())?;
+        for entry in &self.entries {
+            w.write_all(&entry.request_id.to_le_bytes())?;
+            w.write_all(&entry.delivery_attempts.to_le_bytes())?;
+            w.write_all(&(entry.data.len() as u32).to_le_bytes())?;
+            w.write_all(&entry.checksum.to_le_bytes())?;
+            w.write_all(&entry.mac.to_le_bytes())?;
+            w.write_all(&entry.data)?;
+        }
+        Ok(())
+    }
+
+    fn read_body(r: &mut impl Read) -> Result<Self> {
+        if read_u64(r)? != SNAPSHOT_MAGIC {
+            return Err(anyhow!("不是队列快照文件").into());
+        }
+        let version = read_u32(r)?;
+        if version != SNAPSHOT_VERSION {
+            return Err(
+                anyhow!("不支持的快照版本 {}（支持 {}）", version, SNAPSHOT_VERSION).into(),
+            );
+        }
+        let mut snapshot = Self {
+            codec: read_u32(r)?,
+            integrity: read_u32(r)?,
+            encryption: read_u32(r)?,
+            key_id: read_u64(r)?,
+            auth_key_id: read_u64(r)?,
+            capacity: read_u64(r)?,
+            slot_size: read_u64(r)?,
+            next_request_id: read_u64(r)?,
+            entries: Vec::new(),
+        };
+        let count = read_u64(r)?;
+        if count > snapshot.capacity {
+            return Err(anyhow!("记录数 {} 超过源管道容量 {}", count, snapshot.capacity).into());
+        }
+        for _ in 0..count {
+            let request_id = read_u64(r)?;
+            let delivery_attempts = read_u32(r)?;
+            let len = read_u32(r)? as u64;
+            if len > snapshot.slot_size {
+                return Err(
+                    anyhow!("记录长度 {} 超过源管道槽位大小 {}", len, snapshot.slot_size).into(),
+                );
+            }
+            let checksum = read_u64(r)?;
+            let mac = read_u64(r)?;
+            let mut data = vec![0u8; len as usize];
+            r.read_exact(&mut data)?;
+            snapshot.entries.push(SnapshotEntry {
+                request_id,
+                delivery_attempts,
+                checksum,
+                mac,
+                data,
+            });
+        }
+        Ok(snapshot)
+    }
+}
+
+fn read_u32(r: &mut impl Read) -> Result<u32> {
+    let mut buf = [0u8; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(r: &mut impl Read) -> Result<u64> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+/// 写入时同时计算 xxHash64
+struct HashingWriter<W> {
+    inner: W,
+    hasher: xxhash_rust::xxh64::Xxh64,
+}
+
+impl<W: Write> HashingWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: xxhash_rust::xxh64::Xxh64::new(0),
+        }
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// 读取时同时计算 xxHash64
+struct HashingReader<R> {
+    inner: R,
+    hasher: xxhash_rust::xxh64::Xxh64,
+}
+
+impl<R: Read> HashingReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: xxhash_rust::xxh64::Xxh64::new(0),
+        }
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!("mi7_snapshot_{}.bin", std::process::id()));
+        let snapshot = QueueSnapshot {
+            codec: 1,
+            capacity: 4,
+            slot_size: 64,
+            next_request_id: 9,
+            entries: vec![SnapshotEntry {
+                request_id: 7,
+                delivery_attempts: 1,
+                checksum: 42,
+                mac: 0,
+                data: b"payload".to_vec(),
+            }],
+            ..QueueSnapshot::default()
+        };
+        snapshot.save(&path).unwrap();
+        assert_eq!(QueueSnapshot::load(&path).unwrap(), snapshot);
+
+        // 内容被修改时整体校验失败
+        let mut raw = std::fs::read(&path).unwrap();
+        let last = raw.len() - 9;
+        raw[last] ^= 1;
+        std::fs::write(&path, &raw).unwrap();
+        assert!(QueueSnapshot::load(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
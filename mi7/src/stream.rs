@@ -0,0 +1,593 @@
+//! 消费组：多个 worker 池各自独立消费同一消息流
+//!
+//! [`StreamPipe`] 是一个按序号追加的环形消息流。每个消费组在共享内存头部有独立的
+//! 读游标，组内多个消费者竞争同一游标（每条消息在组内只被处理一次），不同组之间
+//! 互不影响。消息只有在所有组都消费之后才会被覆盖；没有任何组时消息保留，
+//! 之后加入的组从最早保留的消息开始消费。
+
+use crate::Message;
+use crate::codec::CodecKind;
+use crate::futex;
+use crate::locks::Mapping;
+use crate::shm_registry::SharedMemoryRegistry;
+use crate::shm_sync::{self, ShmMutex};
+
+use crate::error::Result;
+use anyhow::{Context, anyhow};
+use std::cell::UnsafeCell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::Duration;
+use std::{mem, ptr, slice};
+
+/// 最多支持的消费组数量
+pub const MAX_GROUPS: usize = 16;
+
+/// 消费组名称的最大字节数
+pub const GROUP_NAME_LEN: usize = 32;
+
+/// 消息流共享内存的标识
+const STREAM_MAGIC: u64 = u64::from_le_bytes(*b"MI7STRM\0");
+
+/// 消息流共享内存的布局版本
+const STREAM_LAYOUT_VERSION: u32 = 2;
+
+/// 消费组读游标
+#[repr(C)]
+struct GroupCursor {
+    active: AtomicU32,                      // 是否已登记
+    pid: AtomicU32,                         // 所属进程，0 表示持久组
+    cursor: AtomicU64,                      // 下一条待消费消息的序号
+    name: UnsafeCell<[u8; GROUP_NAME_LEN]>, // 组名（UTF-8，不足部分补 0），持锁在登记前写入
+}
+
+/// 消息流头部
+#[repr(C)]
+struct StreamHeader {
+    magic: u64,
+    version: u32,
+    capacity: u32,
+    slot_size: u64,
+    mutex: ShmMutex,                   // 保护追加、游标推进与组登记
+    head: AtomicU64,                   // 下一条写入消息的序号
+    tail: AtomicU64,                   // 最早保留消息的序号
+    published: AtomicU32,              // futex 字：每追加一条消息递增
+    consumed: AtomicU32,               // futex 字：每释放一个槽位递增
+    groups: [GroupCursor; MAX_GROUPS], // 消费组读游标
+}
+
+/// 槽位头部，其后紧跟 `slot_size` 字节数据
+#[repr(C)]
+struct EntryHeader {
+    seq: u64,
+    len: u32,
+    _reserved: u32,
+}
+
+/// 多消费组消息流
+pub struct StreamPipe {
+    mapping: Mapping,
+    capacity: usize,
+    slot_size: usize,
+    name: String,
+    owner: bool,
+}
+
+unsafe impl Send for StreamPipe {}
+unsafe impl Sync for StreamPipe {}
+
+impl StreamPipe {
+    /// 创建（或重置）消息流
+    pub fn create(name: &str, capacity: usize, slot_size: usize) -> Result<Self> {
+        if capacity == 0 || slot_size == 0 || capacity > u32::MAX as usize {
+            return Err(anyhow!(
+                "无效的消息流参数: capacity={}, slot_size={}",
+                capacity,
+                slot_size
+            )
+            .into());
+        }
+
+        let mut pipe = Self {
+            mapping: Mapping::create(name, Self::mapped_size(capacity, slot_size))?,
+            capacity,
+            slot_size,
+            name: name.trim_start_matches('/').to_string(),
+            owner: true,
+        };
+        unsafe { pipe.init()? };
+        SharedMemoryRegistry::register(name);
+        Ok(pipe)
+    }
+
+    /// 连接到已有消息流，容量与槽位大小从头部读取
+    pub fn connect(name: &str) -> Result<Self> {
+        let (magic, version, capacity, slot_size) = {
+            let mapping = Mapping::open(name, mem::size_of::<StreamHeader>())
+                .with_context(|| format!("共享内存 {} 不是消息流", name))?;
+            let h = unsafe { &*(mapping.as_ptr() as *const StreamHeader) };
+            (
+                unsafe { ptr::read_volatile(&h.magic) },
+                h.version,
+                h.capacity as usize,
+                h.slot_size as usize,
+            )
+        };
+        if magic != STREAM_MAGIC || version != STREAM_LAYOUT_VERSION {
+            return Err(anyhow!("共享内存 {} 不是消息流或布局版本不匹配", name).into());
+        }
+
+        Ok(Self {
+            mapping: Mapping::open(name, Self::mapped_size(capacity, slot_size))
+                .with_context(|| format!("消息流 {} 的共享内存大小不一致", name))?,
+            capacity,
+            slot_size,
+            name: name.trim_start_matches('/').to_string(),
+            owner: false,
+        })
+    }
+
+    /// 共享内存名称
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 容量（槽位数量）
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// 槽位大小
+    pub fn slot_size(&self) -> usize {
+        self.slot_size
+    }
+
+    /// 当前保留（尚未被所有组消费）的消息数量
+    pub fn len(&self) -> usize {
+        let header = self.header();
+        (header.head.load(Ordering::Acquire) - header.tail.load(Ordering::Acquire)) as usize
+    }
+
+    /// 是否没有保留的消息
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 追加消息，所有槽位都未被消费完时返回 `Ok(None)`；成功时返回消息序号
+    pub fn try_publish(&self, message: &Message) -> Result<Option<u64>> {
+        let codec = CodecKind::Bincode.codec();
+        let _guard = self.lock()?;
+        let header = self.header();
+
+        let seq = header.head.load(Ordering::Relaxed);
+        if seq - header.tail.load(Ordering::Relaxed) >= self.capacity as u64 {
+            return Ok(None);
+        }
+
+        let index = (seq % self.capacity as u64) as usize;
+        let data = unsafe { slice::from_raw_parts_mut(self.data_ptr(index), self.slot_size) };
+        let len = codec.encode(message, data)?;
+        unsafe {
+            self.entry_ptr(index).write(EntryHeader {
+                seq,
+                len: len as u32,
+                _reserved: 0,
+            })
+        };
+        header.head.store(seq + 1, Ordering::Release);
+        futex::bump_and_wake(&header.published);
+        Ok(Some(seq))
+    }
+
+    /// 追加消息，消息流已满时等待最慢的组消费，超时返回错误
+    pub fn publish(&self, message: &Message, timeout: Duration) -> Result<u64> {
+        let deadline = std::time::Instant::now() + timeout;
+        loop {
+            if let Some(seq) = self.try_publish(message)? {
+                return Ok(seq);
+            }
+            let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+            let header = self.header();
+            let has_space = futex::wait_until(&header.consumed, Some(remaining), || {
+                header.head.load(Ordering::Acquire) - header.tail.load(Ordering::Acquire)
+                    < self.capacity as u64
+            });
+            if !has_space {
+                return Err(anyhow!("消息流 {} 已满，等待消费超时", self.name).into());
+            }
+        }
+    }
+
+    /// 加入（或创建）消费组
+    ///
+    /// 新建的组从最早保留的消息开始消费；同名组已存在时共享其读游标。
+    pub fn subscribe(self: &Arc<Self>, group: &str) -> Result<Subscription> {
+        self.subscribe_with(group, false, 0)
+    }
+
+    /// 加入（或创建）消费组
+    ///
+    /// `from_head` 为真时新建的组只接收此后追加的消息；`pid` 非 0 时该组随进程
+    /// 退出失效，可由 [`StreamPipe::reclaim_dead_groups`] 回收。
+    pub(crate) fn subscribe_with(
+        self: &Arc<Self>,
+        group: &str,
+        from_head: bool,
+        pid: u32,
+    ) -> Result<Subscription> {
+        let name = Self::encode_group_name(group)?;
+        let _guard = self.lock()?;
+        let header = self.header();
+
+        if let Some(index) = self.find_group(&name) {
+            return Ok(Subscription {
+                pipe: Arc::clone(self),
+                index,
+                group: group.to_string(),
+            });
+        }
+
+        let index = (0..MAX_GROUPS)
+            .find(|&i| header.groups[i].active.load(Ordering::Acquire) == 0)
+            .ok_or_else(|| anyhow!("消费组数量已达上限 {}", MAX_GROUPS))?;
+        let cursor = &header.groups[index];
+        let start = if from_head {
+            &header.head
+        } else {
+            &header.tail
+        };
+        unsafe { *cursor.name.get() = name };
+        cursor.pid.store(pid, Ordering::Relaxed);
+        cursor
+            .cursor
+            .store(start.load(Ordering::Relaxed), Ordering::Relaxed);
+        cursor.active.store(1, Ordering::Release);
+        // 从最新位置开始的组可能使之前无人消费的消息得以释放
+        self.advance_tail();
+
+        Ok(Subscription {
+            pipe: Arc::clone(self),
+            index,
+            group: group.to_string(),
+        })
+    }
+
+    /// 删除消费组，返回该组此前是否存在
+    ///
+    /// 删除后该组未消费的消息不再阻止覆盖。
+    pub fn remove_group(&self, group: &str) -> Result<bool> {
+        let name = Self::encode_group_name(group)?;
+        let _guard = self.lock()?;
+        let Some(index) = self.find_group(&name) else {
+            return Ok(false);
+        };
+        self.header().groups[index]
+            .active
+            .store(0, Ordering::Release);
+        self.advance_tail();
+        Ok(true)
+    }
+
+    /// 删除所属进程已退出的消费组，返回删除的数量
+    pub fn reclaim_dead_groups(&self) -> Result<usize> {
+        let _guard = self.lock()?;
+        let mut reclaimed = 0;
+        for group in self.header().groups.iter() {
+            let pid = group.pid.load(Ordering::Relaxed);
+            if group.active.load(Ordering::Acquire) != 0
+                && pid != 0
+                && !shm_sync::process_alive(pid)
+            {
+                group.active.store(0, Ordering::Release);
+                reclaimed += 1;
+            }
+        }
+        if reclaimed > 0 {
+            self.advance_tail();
+        }
+        Ok(reclaimed)
+    }
+
+    /// 释放槽位时递增的 futex 字
+    pub(crate) fn consumed_word(&self) -> &AtomicU32 {
+        &self.header().consumed
+    }
+
+    /// 没有任何消费组时丢弃保留的消息
+    pub(crate) fn discard_unsubscribed(&self) -> Result<()> {
+        let _guard = self.lock()?;
+        let header = self.header();
+        if header
+            .groups
+            .iter()
+            .all(|group| group.active.load(Ordering::Acquire) == 0)
+        {
+            header
+                .tail
+                .store(header.head.load(Ordering::Relaxed), Ordering::Release);
+            futex::bump_and_wake(&header.consumed);
+        }
+        Ok(())
+    }
+
+    /// 所有消费组及其积压的消息数量
+    pub fn groups(&self) -> Vec<(String, u64)> {
+        let header = self.header();
+        let head = header.head.load(Ordering::Acquire);
+        header
+            .groups
+            .iter()
+            .filter(|group| group.active.load(Ordering::Acquire) != 0)
+            .map(|group| {
+                let name = unsafe { &*group.name.get() };
+                let len = name.iter().position(|&b| b == 0).unwrap_or(GROUP_NAME_LEN);
+                (
+                    String::from_utf8_lossy(&name[..len]).into_owned(),
+                    head - group.cursor.load(Ordering::Acquire),
+                )
+            })
+            .collect()
+    }
+
+    /// 为指定组领取下一条消息，没有新消息时返回 `Ok(None)`
+    fn try_consume(&self, index: usize) -> Result<Option<(u64, Message)>> {
+        let codec = CodecKind::Bincode.codec();
+        let _guard = self.lock()?;
+        let header = self.header();
+        let group = &header.groups[index];
+        if group.active.load(Ordering::Acquire) == 0 {
+            return Err(anyhow!("消费组已被删除").into());
+        }
+
+        let seq = group.cursor.load(Ordering::Relaxed);
+        if seq >= header.head.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+
+        // 持锁解码：槽位在所有组的游标越过之前不会被覆盖
+        let slot = (seq % self.capacity as u64) as usize;
+        let len = (unsafe { (*self.entry_ptr(slot)).len } as usize).min(self.slot_size);
+        let result = codec.decode(unsafe { slice::from_raw_parts(self.data_ptr(slot), len) });
+        group.cursor.store(seq + 1, Ordering::Release);
+        self.advance_tail();
+
+        result.map(|message| Some((seq, message)))
+    }
+
+    /// 将 tail 推进到所有组中最小的游标，释放槽位时唤醒等待的写者
+    fn advance_tail(&self) {
+        let header = self.header();
+        let head = header.head.load(Ordering::Relaxed);
+        let tail = header.tail.load(Ordering::Relaxed);
+
+        let min_cursor = header
+            .groups
+            .iter()
+            .filter(|group| group.active.load(Ordering::Acquire) != 0)
+            .map(|group| group.cursor.load(Ordering::Relaxed))
+            .min();
+        // 没有任何组时保留全部消息
+        let new_tail = min_cursor.unwrap_or(tail).clamp(tail, head);
+        if new_tail != tail {
+            header.tail.store(new_tail, Ordering::Release);
+            futex::bump_and_wake(&header.consumed);
+        }
+    }
+
+    fn find_group(&self, name: &[u8; GROUP_NAME_LEN]) -> Option<usize> {
+        self.header().groups.iter().position(|group| {
+            group.active.load(Ordering::Acquire) != 0 && unsafe { &*group.name.get() } == name
+        })
+    }
+
+    fn encode_group_name(group: &str) -> Result<[u8; GROUP_NAME_LEN]> {
+        if group.is_empty() || group.len() > GROUP_NAME_LEN {
+            return Err(anyhow!(
+                "消费组名称长度必须在 1 到 {} 字节之间: '{}'",
+                GROUP_NAME_LEN,
+                group
+            )
+            .into());
+        }
+        let mut name = [0u8; GROUP_NAME_LEN];
+        name[..group.len()].copy_from_slice(group.as_bytes());
+        Ok(name)
+    }
+
+    unsafe fn init(&mut self) -> Result<()> {
+        let capacity = self.capacity;
+        let slot_size = self.slot_size;
+        let header = unsafe { &mut *self.header_ptr() };
+
+        header.magic = 0;
+        std::sync::atomic::fence(Ordering::Release);
+        unsafe { header.mutex.init()? };
+        header.version = STREAM_LAYOUT_VERSION;
+        header.capacity = capacity as u32;
+        header.slot_size = slot_size as u64;
+        header.head = AtomicU64::new(0);
+        header.tail = AtomicU64::new(0);
+        header.published = AtomicU32::new(0);
+        header.consumed = AtomicU32::new(0);
+        for group in header.groups.iter_mut() {
+            group.active = AtomicU32::new(0);
+            group.pid = AtomicU32::new(0);
+            group.cursor = AtomicU64::new(0);
+            *group.name.get_mut() = [0; GROUP_NAME_LEN];
+        }
+
+        std::sync::atomic::fence(Ordering::Release);
+        unsafe { ptr::write_volatile(&mut (*self.header_ptr()).magic, STREAM_MAGIC) };
+        Ok(())
+    }
+
+    fn lock(&self) -> Result<StreamGuard<'_>> {
+        let mutex = &self.header().mutex;
+        if !unsafe { mutex.lock() } {
+            return Err(anyhow!("消息流 {} 加锁失败", self.name).into());
+        }
+        Ok(StreamGuard { mutex })
+    }
+
+    fn header_ptr(&self) -> *mut StreamHeader {
+        self.mapping.as_ptr() as *mut StreamHeader
+    }
+
+    fn header(&self) -> &StreamHeader {
+        unsafe { &*self.header_ptr() }
+    }
+
+    fn stride(slot_size: usize) -> usize {
+        (mem::size_of::<EntryHeader>() + slot_size).div_ceil(8) * 8
+    }
+
+    fn mapped_size(capacity: usize, slot_size: usize) -> usize {
+        mem::size_of::<StreamHeader>() + capacity * Self::stride(slot_size)
+    }
+
+    fn slot_ptr(&self, index: usize) -> *mut u8 {
+        unsafe {
+            self.mapping
+                .as_ptr()
+                .add(mem::size_of::<StreamHeader>() + index * Self::stride(self.slot_size))
+        }
+    }
+
+    /// 槽位头部，只能在持有消息流的锁时解引用
+    fn entry_ptr(&self, index: usize) -> *mut EntryHeader {
+        self.slot_ptr(index) as *mut EntryHeader
+    }
+
+    /// 槽位数据区（`slot_size` 字节），只能在持有消息流的锁时访问
+    fn data_ptr(&self, index: usize) -> *mut u8 {
+        unsafe { self.slot_ptr(index).add(mem::size_of::<EntryHeader>()) }
+    }
+}
+
+impl Drop for StreamPipe {
+    fn drop(&mut self) {
+        if self.owner
+            && let Err(e) = SharedMemoryRegistry::unlink(&self.name)
+        {
+            tracing::warn!("删除共享内存段 {} 失败: {}", self.name, e);
+        }
+    }
+}
+
+struct StreamGuard<'a> {
+    mutex: &'a ShmMutex,
+}
+
+impl Drop for StreamGuard<'_> {
+    fn drop(&mut self) {
+        unsafe { self.mutex.unlock() };
+    }
+}
+
+/// 消费组成员，见 [`StreamPipe::subscribe`]
+pub struct Subscription {
+    pipe: Arc<StreamPipe>,
+    index: usize,
+    group: String,
+}
+
+impl Subscription {
+    /// 消费组名称
+    pub fn group(&self) -> &str {
+        &self.group
+    }
+
+    /// 所属消息流
+    pub fn pipe(&self) -> &Arc<StreamPipe> {
+        &self.pipe
+    }
+
+    /// 本组积压的消息数量
+    pub fn lag(&self) -> u64 {
+        let header = self.pipe.header();
+        header.head.load(Ordering::Acquire)
+            - header.groups[self.index].cursor.load(Ordering::Acquire)
+    }
+
+    /// 领取本组的下一条消息及其序号，没有新消息时返回 `Ok(None)`
+    pub fn try_receive(&self) -> Result<Option<(u64, Message)>> {
+        self.pipe.try_consume(self.index)
+    }
+
+    /// 阻塞领取本组的下一条消息，超时返回错误
+    pub fn receive_blocking(&self, timeout: Duration) -> Result<(u64, Message)> {
+        let deadline = std::time::Instant::now() + timeout;
+        loop {
+            if let Some(received) = self.try_receive()? {
+                return Ok(received);
+            }
+            let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+            if !futex::wait_until(&self.pipe.header().published, Some(remaining), || {
+                self.lag() > 0
+            }) {
+                return Err(anyhow!("消费组 {} 等待消息超时", self.group).into());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_groups_consume_independently() {
+        let name = format!("mi7_test_stream_{}", std::process::id());
+        let stream = Arc::new(StreamPipe::create(&name, 4, 128).unwrap());
+        let timeout = Duration::from_millis(200);
+
+        let billing = stream.subscribe("billing").unwrap();
+        let audit_a = stream.subscribe("audit").unwrap();
+        let audit_b = Arc::new(StreamPipe::connect(&name).unwrap())
+            .subscribe("audit")
+            .unwrap();
+
+        for i in 0..4 {
+            stream
+                .publish(&Message::init(format!("m{}", i)), timeout)
+                .unwrap();
+        }
+        // 有组尚未消费时不能覆盖
+        assert!(
+            stream
+                .try_publish(&Message::init("full".to_string()))
+                .unwrap()
+                .is_none()
+        );
+
+        // billing 读到全部消息
+        for i in 0..4 {
+            let (seq, message) = billing.receive_blocking(timeout).unwrap();
+            assert_eq!((seq, message.data), (i, format!("m{}", i).into_bytes()));
+        }
+        assert!(billing.try_receive().unwrap().is_none());
+        assert!(
+            stream
+                .try_publish(&Message::init("full".to_string()))
+                .unwrap()
+                .is_none()
+        );
+
+        // audit 组内两个成员分摊消息
+        let mut seqs = Vec::new();
+        for member in [&audit_a, &audit_b, &audit_a, &audit_b] {
+            seqs.push(member.receive_blocking(timeout).unwrap().0);
+        }
+        assert_eq!(seqs, vec![0, 1, 2, 3]);
+        assert!(stream.is_empty());
+
+        // 删除落后的组后不再阻塞写入
+        stream
+            .publish(&Message::init("m4".to_string()), timeout)
+            .unwrap();
+        assert_eq!(billing.lag(), 1);
+        assert!(stream.remove_group("audit").unwrap());
+        assert!(audit_a.try_receive().is_err());
+        assert_eq!(stream.groups(), vec![("billing".to_string(), 1)]);
+    }
+}
@@ -0,0 +1,524 @@
+//! 主题路由：一个共享内存段内承载多个逻辑通道
+//!
+//! 每个逻辑通道单独创建一个共享内存段会浪费内存与文件描述符。[`TopicPipe`] 在
+//! 单个段内划分出固定数量的子队列，头部的主题表按主题名称的哈希（开放寻址）
+//! 映射到子队列。主题在首次发送或订阅时登记，每条消息在主题内只被一个消费者取走。
+
+use crate::Message;
+use crate::codec::CodecKind;
+use crate::futex;
+use crate::integrity::Integrity;
+use crate::locks::Mapping;
+use crate::shm_registry::SharedMemoryRegistry;
+use crate::shm_sync::ShmMutex;
+
+use crate::error::Result;
+use anyhow::{Context, anyhow};
+use std::cell::UnsafeCell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use std::{mem, ptr, slice};
+
+/// 主题名称的最大字节数
+pub const TOPIC_NAME_LEN: usize = 32;
+
+/// 主题管道共享内存的标识
+const TOPIC_MAGIC: u64 = u64::from_le_bytes(*b"MI7TOPC\0");
+
+/// 主题管道共享内存的布局版本
+const TOPIC_LAYOUT_VERSION: u32 = 1;
+
+/// 主题表项，其子队列的槽位位于段尾部的第 `index` 个分区
+#[repr(C)]
+struct TopicEntry {
+    hash: AtomicU64,                        // 主题名称的 xxHash64
+    active: AtomicU32,                      // 是否已登记
+    _reserved: u32,                         //
+    head: AtomicU64,                        // 下一条写入消息的序号
+    tail: AtomicU64,                        // 下一条读取消息的序号
+    name: UnsafeCell<[u8; TOPIC_NAME_LEN]>, // 主题名称（UTF-8，不足部分补 0），持锁在登记前写入
+}
+
+/// 主题管道头部，其后紧跟 `max_topics` 个 [`TopicEntry`] 与全部槽位
+#[repr(C)]
+struct TopicHeader {
+    magic: u64,
+    version: u32,
+    max_topics: u32,
+    capacity: u32, // 每个主题的槽位数量
+    _reserved: u32,
+    slot_size: u64,
+    mutex: ShmMutex,      // 保护主题表与所有子队列
+    published: AtomicU32, // futex 字：每写入一条消息递增
+    consumed: AtomicU32,  // futex 字：每取走一条消息递增
+}
+
+/// 槽位头部，其后紧跟 `slot_size` 字节数据
+#[repr(C)]
+struct EntryHeader {
+    seq: u64,
+    len: u32,
+    _reserved: u32,
+}
+
+/// 单段多主题管道
+pub struct TopicPipe {
+    mapping: Mapping,
+    max_topics: usize,
+    capacity: usize,
+    slot_size: usize,
+    name: String,
+    owner: bool,
+}
+
+unsafe impl Send for TopicPipe {}
+unsafe impl Sync for TopicPipe {}
+
+impl TopicPipe {
+    /// 创建（或重置）主题管道，最多 `max_topics` 个主题，每个主题 `capacity` 个槽位
+    pub fn create(
+        name: &str,
+        max_topics: usize,
+        capacity: usize,
+        slot_size: usize,
+    ) -> Result<Self> {
+        if max_topics == 0
+            || capacity == 0
+            || slot_size == 0
+            || max_topics > u32::MAX as usize
+            || capacity > u32::MAX as usize
+        {
+            return Err(anyhow!(
+                "无效的主题管道参数: max_topics={}, capacity={}, slot_size={}",
+                max_topics,
+                capacity,
+                slot_size
+            )
+            .into());
+        }
+
+        let mut pipe = Self {
+            mapping: Mapping::create(name, Self::mapped_size(max_topics, capacity, slot_size))?,
+            max_topics,
+            capacity,
+            slot_size,
+            name: name.trim_start_matches('/').to_string(),
+            owner: true,
+        };
+        unsafe { pipe.init()? };
+        SharedMemoryRegistry::register(name);
+        Ok(pipe)
+    }
+
+    /// 连接到已有主题管道，布局参数从头部读取
+    pub fn connect(name: &str) -> Result<Self> {
+        let (magic, version, max_topics, capacity, slot_size) = {
+            let mapping = Mapping::open(name, mem::size_of::<TopicHeader>())
+                .with_context(|| format!("共享内存 {} 不是主题管道", name))?;
+            let h = unsafe { &*(mapping.as_ptr() as *const TopicHeader) };
+            (
+                unsafe { ptr::read_volatile(&h.magic) },
+                h.version,
+                h.max_topics as usize,
+                h.capacity as usize,
+                h.slot_size as usize,
+            )
+        };
+        if magic != TOPIC_MAGIC || version != TOPIC_LAYOUT_VERSION {
+            return Err(anyhow!("共享内存 {} 不是主题管道或布局版本不匹配", name).into());
+        }
+
+        Ok(Self {
+            mapping: Mapping::open(name, Self::mapped_size(max_topics, capacity, slot_size))
+                .with_context(|| format!("主题管道 {} 的共享内存大小不一致", name))?,
+            max_topics,
+            capacity,
+            slot_size,
+            name: name.trim_start_matches('/').to_string(),
+            owner: false,
+        })
+    }
+
+    /// 共享内存名称
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 最多可登记的主题数量
+    pub fn max_topics(&self) -> usize {
+        self.max_topics
+    }
+
+    /// 每个主题的槽位数量
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// 槽位大小
+    pub fn slot_size(&self) -> usize {
+        self.slot_size
+    }
+
+    /// 向主题写入消息，主题子队列已满时返回 `Ok(None)`；成功时返回消息序号
+    pub fn try_send_to(&self, topic: &str, message: &Message) -> Result<Option<u64>> {
+        let codec = CodecKind::Bincode.codec();
+        let _guard = self.lock()?;
+        let index = self.register_topic(topic)?;
+        let entry = self.topic(index);
+
+        let seq = entry.head.load(Ordering::Relaxed);
+        if seq - entry.tail.load(Ordering::Relaxed) >= self.capacity as u64 {
+            return Ok(None);
+        }
+
+        let slot = self.slot_index(index, seq);
+        let data = unsafe { slice::from_raw_parts_mut(self.data_ptr(slot), self.slot_size) };
+        let len = codec.encode(message, data)?;
+        unsafe {
+            self.slot_header_ptr(slot).write(EntryHeader {
+                seq,
+                len: len as u32,
+                _reserved: 0,
+            })
+        };
+        entry.head.store(seq + 1, Ordering::Release);
+        futex::bump_and_wake(&self.header().published);
+        Ok(Some(seq))
+    }
+
+    /// 向主题写入消息，子队列已满时等待消费，超时返回错误
+    pub fn send_to(&self, topic: &str, message: &Message, timeout: Duration) -> Result<u64> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(seq) = self.try_send_to(topic, message)? {
+                return Ok(seq);
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let has_space = futex::wait_until(&self.header().consumed, Some(remaining), || {
+                self.find_topic(topic)
+                    .is_some_and(|index| self.pending(index) < self.capacity as u64)
+            });
+            if !has_space {
+                return Err(anyhow!("主题 {} 已满，等待消费超时", topic).into());
+            }
+        }
+    }
+
+    /// 订阅主题，主题尚未登记时登记
+    pub fn subscribe(self: &Arc<Self>, topic: &str) -> Result<TopicSubscriber> {
+        let index = {
+            let _guard = self.lock()?;
+            self.register_topic(topic)?
+        };
+        Ok(TopicSubscriber {
+            pipe: Arc::clone(self),
+            index,
+            topic: topic.to_string(),
+        })
+    }
+
+    /// 已登记的主题及其待消费的消息数量
+    pub fn topics(&self) -> Vec<(String, u64)> {
+        (0..self.max_topics)
+            .filter(|&index| self.topic(index).active.load(Ordering::Acquire) != 0)
+            .map(|index| {
+                let name = unsafe { &*self.topic(index).name.get() };
+                let len = name.iter().position(|&b| b == 0).unwrap_or(TOPIC_NAME_LEN);
+                (
+                    String::from_utf8_lossy(&name[..len]).into_owned(),
+                    self.pending(index),
+                )
+            })
+            .collect()
+    }
+
+    /// 取走主题子队列中的下一条消息，没有消息时返回 `Ok(None)`
+    fn try_consume(&self, index: usize) -> Result<Option<(u64, Message)>> {
+        let codec = CodecKind::Bincode.codec();
+        let _guard = self.lock()?;
+        let entry = self.topic(index);
+
+        let seq = entry.tail.load(Ordering::Relaxed);
+        if seq >= entry.head.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+
+        let slot = self.slot_index(index, seq);
+        let len = (unsafe { (*self.slot_header_ptr(slot)).len } as usize).min(self.slot_size);
+        let result = codec.decode(unsafe { slice::from_raw_parts(self.data_ptr(slot), len) });
+        entry.tail.store(seq + 1, Ordering::Release);
+        futex::bump_and_wake(&self.header().consumed);
+
+        result.map(|message| Some((seq, message)))
+    }
+
+    fn pending(&self, index: usize) -> u64 {
+        let entry = self.topic(index);
+        entry.head.load(Ordering::Acquire) - entry.tail.load(Ordering::Acquire)
+    }
+
+    /// 查找主题所在的表项，不加锁
+    fn find_topic(&self, topic: &str) -> Option<usize> {
+        let name = Self::encode_topic_name(topic).ok()?;
+        let hash = Self::topic_hash(topic);
+        self.probe(hash)
+            .take_while(|&index| self.topic(index).active.load(Ordering::Acquire) != 0)
+            .find(|&index| {
+                let entry = self.topic(index);
+                entry.hash.load(Ordering::Relaxed) == hash && unsafe { *entry.name.get() } == name
+            })
+    }
+
+    /// 查找或登记主题，调用者需持有锁
+    fn register_topic(&self, topic: &str) -> Result<usize> {
+        let name = Self::encode_topic_name(topic)?;
+        let hash = Self::topic_hash(topic);
+
+        for index in self.probe(hash) {
+            let entry = self.topic(index);
+            if entry.active.load(Ordering::Acquire) == 0 {
+                entry.hash.store(hash, Ordering::Relaxed);
+                unsafe { *entry.name.get() = name };
+                entry.head.store(0, Ordering::Relaxed);
+                entry.tail.store(0, Ordering::Relaxed);
+                entry.active.store(1, Ordering::Release);
+                return Ok(index);
+            }
+            if entry.hash.load(Ordering::Relaxed) == hash && unsafe { *entry.name.get() } == name {
+                return Ok(index);
+            }
+        }
+        Err(anyhow!("主题数量已达上限 {}", self.max_topics).into())
+    }
+
+    /// 从哈希对应的位置开始线性探测
+    fn probe(&self, hash: u64) -> impl Iterator<Item = usize> + use<> {
+        let max_topics = self.max_topics;
+        let start = (hash % max_topics as u64) as usize;
+        (0..max_topics).map(move |i| (start + i) % max_topics)
+    }
+
+    fn topic_hash(topic: &str) -> u64 {
+        Integrity::XxHash64.checksum(topic.as_bytes())
+    }
+
+    fn encode_topic_name(topic: &str) -> Result<[u8; TOPIC_NAME_LEN]> {
+        if topic.is_empty() || topic.len() > TOPIC_NAME_LEN {
+            return Err(anyhow!(
+                "主题名称长度必须在 1 到 {} 字节之间: '{}'",
+                TOPIC_NAME_LEN,
+                topic
+            )
+            .into());
+        }
+        let mut name = [0u8; TOPIC_NAME_LEN];
+        name[..topic.len()].copy_from_slice(topic.as_bytes());
+        Ok(name)
+    }
+
+    unsafe fn init(&mut self) -> Result<()> {
+        let (max_topics, capacity, slot_size) = (self.max_topics, self.capacity, self.slot_size);
+        let header = unsafe { &mut *self.header_ptr() };
+
+        header.magic = 0;
+        std::sync::atomic::fence(Ordering::Release);
+        unsafe { header.mutex.init()? };
+        header.version = TOPIC_LAYOUT_VERSION;
+        header.max_topics = max_topics as u32;
+        header.capacity = capacity as u32;
+        header.slot_size = slot_size as u64;
+        header.published = AtomicU32::new(0);
+        header.consumed = AtomicU32::new(0);
+        for index in 0..max_topics {
+            let entry = unsafe { &mut *self.topic_ptr(index) };
+            entry.active = AtomicU32::new(0);
+            entry.hash = AtomicU64::new(0);
+            *entry.name.get_mut() = [0; TOPIC_NAME_LEN];
+        }
+
+        std::sync::atomic::fence(Ordering::Release);
+        unsafe { ptr::write_volatile(&mut (*self.header_ptr()).magic, TOPIC_MAGIC) };
+        Ok(())
+    }
+
+    fn lock(&self) -> Result<TopicGuard<'_>> {
+        let mutex = &self.header().mutex;
+        if !unsafe { mutex.lock() } {
+            return Err(anyhow!("主题管道 {} 加锁失败", self.name).into());
+        }
+        Ok(TopicGuard { mutex })
+    }
+
+    fn header_ptr(&self) -> *mut TopicHeader {
+        self.mapping.as_ptr() as *mut TopicHeader
+    }
+
+    fn header(&self) -> &TopicHeader {
+        unsafe { &*self.header_ptr() }
+    }
+
+    fn topic_ptr(&self, index: usize) -> *mut TopicEntry {
+        unsafe {
+            self.mapping
+                .as_ptr()
+                .add(mem::size_of::<TopicHeader>() + index * mem::size_of::<TopicEntry>())
+                as *mut TopicEntry
+        }
+    }
+
+    fn topic(&self, index: usize) -> &TopicEntry {
+        unsafe { &*self.topic_ptr(index) }
+    }
+
+    /// 主题子队列中序号 `seq` 所在的全局槽位编号
+    fn slot_index(&self, topic: usize, seq: u64) -> usize {
+        topic * self.capacity + (seq % self.capacity as u64) as usize
+    }
+
+    fn stride(slot_size: usize) -> usize {
+        (mem::size_of::<EntryHeader>() + slot_size).div_ceil(8) * 8
+    }
+
+    fn mapped_size(max_topics: usize, capacity: usize, slot_size: usize) -> usize {
+        mem::size_of::<TopicHeader>()
+            + max_topics * mem::size_of::<TopicEntry>()
+            + max_topics * capacity * Self::stride(slot_size)
+    }
+
+    fn slot_ptr(&self, slot: usize) -> *mut u8 {
+        unsafe {
+            self.mapping.as_ptr().add(
+                mem::size_of::<TopicHeader>()
+                    + self.max_topics * mem::size_of::<TopicEntry>()
+                    + slot * Self::stride(self.slot_size),
+            )
+        }
+    }
+
+    /// 槽位头部，只能在持有主题管道的锁时解引用
+    fn slot_header_ptr(&self, slot: usize) -> *mut EntryHeader {
+        self.slot_ptr(slot) as *mut EntryHeader
+    }
+
+    /// 槽位数据区（`slot_size` 字节），只能在持有主题管道的锁时访问
+    fn data_ptr(&self, slot: usize) -> *mut u8 {
+        unsafe { self.slot_ptr(slot).add(mem::size_of::<EntryHeader>()) }
+    }
+}
+
+impl Drop for TopicPipe {
+    fn drop(&mut self) {
+        if self.owner
+            && let Err(e) = SharedMemoryRegistry::unlink(&self.name)
+        {
+            tracing::warn!("删除共享内存段 {} 失败: {}", self.name, e);
+        }
+    }
+}
+
+struct TopicGuard<'a> {
+    mutex: &'a ShmMutex,
+}
+
+impl Drop for TopicGuard<'_> {
+    fn drop(&mut self) {
+        unsafe { self.mutex.unlock() };
+    }
+}
+
+/// 主题消费者，见 [`TopicPipe::subscribe`]
+pub struct TopicSubscriber {
+    pipe: Arc<TopicPipe>,
+    index: usize,
+    topic: String,
+}
+
+impl TopicSubscriber {
+    /// 主题名称
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    /// 主题中待消费的消息数量
+    pub fn pending(&self) -> u64 {
+        self.pipe.pending(self.index)
+    }
+
+    /// 取走主题中的下一条消息及其序号，没有消息时返回 `Ok(None)`
+    pub fn try_receive(&self) -> Result<Option<(u64, Message)>> {
+        self.pipe.try_consume(self.index)
+    }
+
+    /// 阻塞取走主题中的下一条消息，超时返回错误
+    pub fn receive_blocking(&self, timeout: Duration) -> Result<(u64, Message)> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(received) = self.try_receive()? {
+                return Ok(received);
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if !futex::wait_until(&self.pipe.header().published, Some(remaining), || {
+                self.pending() > 0
+            }) {
+                return Err(anyhow!("主题 {} 等待消息超时", self.topic).into());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_topics_are_routed_independently() {
+        let name = format!("mi7_test_topic_{}", std::process::id());
+        let pipe = Arc::new(TopicPipe::create(&name, 4, 2, 128).unwrap());
+        let timeout = Duration::from_millis(200);
+
+        let orders = pipe.subscribe("orders").unwrap();
+        let remote = Arc::new(TopicPipe::connect(&name).unwrap());
+        let logs = remote.subscribe("logs").unwrap();
+
+        pipe.send_to("orders", &Message::init("o1".to_string()), timeout)
+            .unwrap();
+        remote
+            .send_to("logs", &Message::init("l1".to_string()), timeout)
+            .unwrap();
+        pipe.send_to("orders", &Message::init("o2".to_string()), timeout)
+            .unwrap();
+
+        // orders 已满不影响其他主题
+        assert!(
+            pipe.try_send_to("orders", &Message::init("o3".to_string()))
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            pipe.try_send_to("metrics", &Message::init("m1".to_string()))
+                .unwrap()
+                .is_some()
+        );
+
+        assert_eq!(logs.receive_blocking(timeout).unwrap().1.data, b"l1");
+        assert!(logs.try_receive().unwrap().is_none());
+        assert_eq!(orders.receive_blocking(timeout).unwrap().1.data, b"o1");
+        assert_eq!(orders.receive_blocking(timeout).unwrap().1.data, b"o2");
+
+        let mut topics = remote.topics();
+        topics.sort();
+        assert_eq!(
+            topics,
+            vec![
+                ("logs".to_string(), 0),
+                ("metrics".to_string(), 1),
+                ("orders".to_string(), 0),
+            ]
+        );
+
+        // 主题表已满
+        pipe.subscribe("events").unwrap();
+        assert!(pipe.subscribe("overflow").is_err());
+    }
+}
@@ -0,0 +1,156 @@
+//! 跨进程的链路追踪上下文传播
+//!
+//! 生产方通过 [`producer_span`] 为消息分配 span，并把 [`TraceContext`] 写入消息头；
+//! 消费方通过 [`consumer_span`] 创建 `parent_span_id` 指向生产方 span 的新 span。
+//! 两端的 span 带有相同的 `trace_id` 字段，在追踪后端中按该字段即可串起一个请求
+//! 从 entry 经过管道到 worker 的完整路径。上下文可与 W3C `traceparent` 头互相转换，
+//! 便于延续上游 HTTP 调用方的链路。
+
+use crate::Message;
+use crate::shm_sync;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::Span;
+use xxhash_rust::xxh64::xxh64;
+
+/// 本进程内的 ID 生成计数器
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// 随消息传播的追踪上下文
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    bincode::Encode,
+    bincode::Decode,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct TraceContext {
+    /// 整条链路共享的 ID，非 0
+    pub trace_id: u128,
+    /// 写入消息的 span 的 ID，非 0
+    pub span_id: u64,
+}
+
+impl TraceContext {
+    /// 开始一条新的链路
+    pub fn new_root() -> Self {
+        Self {
+            trace_id: ((next_id() as u128) << 64) | next_id() as u128,
+            span_id: next_id(),
+        }
+    }
+
+    /// 同一链路下的新 span
+    pub fn child(&self) -> Self {
+        Self {
+            trace_id: self.trace_id,
+            span_id: next_id(),
+        }
+    }
+
+    /// 十六进制的 trace_id，与 `traceparent` 中的写法一致
+    pub fn trace_id_hex(&self) -> String {
+        format!("{:032x}", self.trace_id)
+    }
+
+    /// 十六进制的 span_id
+    pub fn span_id_hex(&self) -> String {
+        format!("{:016x}", self.span_id)
+    }
+
+    /// 转换为 W3C `traceparent` 头
+    pub fn to_traceparent(&self) -> String {
+        format!("00-{}-{}-01", self.trace_id_hex(), self.span_id_hex())
+    }
+
+    /// 解析 W3C `traceparent` 头，格式无效或 ID 全为 0 时返回 `None`
+    pub fn from_traceparent(header: &str) -> Option<Self> {
+        let mut parts = header.trim().split('-');
+        let (version, trace_id, span_id) = (parts.next()?, parts.next()?, parts.next()?);
+        parts.next()?;
+        if version.len() != 2 || trace_id.len() != 32 || span_id.len() != 16 {
+            return None;
+        }
+        let context = Self {
+            trace_id: u128::from_str_radix(trace_id, 16).ok()?,
+            span_id: u64::from_str_radix(span_id, 16).ok()?,
+        };
+        (context.trace_id != 0 && context.span_id != 0).then_some(context)
+    }
+}
+
+/// 生成非 0 的随机 ID
+fn next_id() -> u64 {
+    let mut seed = [0u8; 24];
+    seed[..8].copy_from_slice(&shm_sync::monotonic_nanos().to_le_bytes());
+    seed[8..16].copy_from_slice(&NEXT_ID.fetch_add(1, Ordering::Relaxed).to_le_bytes());
+    seed[16..].copy_from_slice(&(std::process::id() as u64).to_le_bytes());
+    xxh64(&seed, 0).max(1)
+}
+
+/// 生产方：为即将发送的消息创建 span，并把新 span 的上下文写入消息
+///
+/// 消息已携带上下文（例如来自上游的 `traceparent`）时作为其子 span，否则开始新链路。
+pub fn producer_span(message: &mut Message) -> Span {
+    let parent = message.trace;
+    let context = parent.map_or_else(TraceContext::new_root, |parent| parent.child());
+    message.trace = Some(context);
+    tracing::info_span!(
+        "mi7.produce",
+        trace_id = %context.trace_id_hex(),
+        span_id = %context.span_id_hex(),
+        parent_span_id = parent.map(|parent| parent.span_id_hex()),
+    )
+}
+
+/// 消费方：为收到的消息创建以生产方 span 为父的 span，消息未携带上下文时返回禁用的 span
+pub fn consumer_span(message: &Message) -> Span {
+    let Some(parent) = message.trace else {
+        return Span::none();
+    };
+    let context = parent.child();
+    tracing::info_span!(
+        "mi7.consume",
+        trace_id = %context.trace_id_hex(),
+        span_id = %context.span_id_hex(),
+        parent_span_id = %parent.span_id_hex(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::CodecKind;
+
+    #[test]
+    fn test_trace_context_propagates_through_message() {
+        let upstream = TraceContext::from_traceparent(
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        )
+        .unwrap();
+        assert_eq!(
+            upstream.to_traceparent(),
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+        );
+        assert!(TraceContext::from_traceparent("00-0-0-01").is_none());
+
+        let mut message = Message::init("traced".to_string()).with_trace(upstream);
+        let _span = producer_span(&mut message);
+        let sent = message.trace.unwrap();
+        assert_eq!(sent.trace_id, upstream.trace_id);
+        assert_ne!(sent.span_id, upstream.span_id);
+
+        // 上下文随消息编码传到消费方
+        for kind in [CodecKind::Bincode, CodecKind::Raw] {
+            let codec = kind.codec();
+            let mut buf = [0u8; 256];
+            let len = codec.encode(&message, &mut buf).unwrap();
+            assert_eq!(codec.decode(&buf[..len]).unwrap().trace, Some(sent));
+        }
+        assert!(Message::init("plain".to_string()).trace.is_none());
+    }
+}
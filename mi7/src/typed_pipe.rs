@@ -0,0 +1,192 @@
+//! 直接收发任意类型的管道
+//!
+//! [`TypedPipe<T>`] 把 `T` 用 bincode 编码后直接写入槽位，读取时解码回 `T`，
+//! 应用不必再手动把数据包装成 [`Message`](crate::Message) 的字节：
+//!
+//! ```no_run
+//! # use mi7::pipe::PipeConfig;
+//! # use mi7::typed_pipe::TypedPipe;
+//! # use std::time::Duration;
+//! #[derive(bincode::Encode, bincode::Decode)]
+//! struct Order {
+//!     id: u64,
+//!     item: String,
+//! }
+//!
+//! # fn example() -> anyhow::Result<()> {
+//! let producer = TypedPipe::<Order>::create("orders", PipeConfig::small())?;
+//! producer.send(&Order { id: 1, item: "book".into() }, Duration::from_secs(1))?;
+//!
+//! let consumer = TypedPipe::<Order>::connect("orders")?;
+//! let order = consumer.receive(Duration::from_secs(1))?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! 每个槽位的数据以类型哈希（`std::any::type_name::<T>()` 的 xxHash64，小端 8 字节）开头，
+//! 之后是 bincode 编码的值。槽位头部没有空余字段，因此哈希随数据一起写入：
+//! 连接时检查队列中已有的消息，读取时检查每条消息，类型不一致时返回 [`TypeMismatch`]。
+//! 类型名相同但字段不同的两个版本无法区分，升级类型时应同时更换类型名或管道名。
+//!
+//! 类型化管道的槽位不是 [`Message`](crate::Message) 编码，不能与 [`DynamicPipe`](crate::pipe::DynamicPipe)
+//! 的收发接口混用。
+
+use crate::error::Result;
+use crate::pipe::{DynCrossProcessPipe, PipeConfig};
+use std::marker::PhantomData;
+use std::time::Duration;
+use xxhash_rust::xxh64::xxh64;
+
+/// 类型哈希的长度
+const TYPE_HASH_LEN: usize = 8;
+
+/// 槽位中的消息类型与管道的类型参数不一致
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("管道 {pipe} 的消息类型不是 {expected} (类型哈希 {found:#018x})")]
+pub struct TypeMismatch {
+    pub pipe: String,
+    pub expected: &'static str,
+    pub found: u64,
+}
+
+/// 收发 `T` 类型值的共享内存管道
+pub struct TypedPipe<T> {
+    inner: DynCrossProcessPipe,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> TypedPipe<T>
+where
+    T: bincode::Encode + bincode::Decode<()>,
+{
+    /// 创建类型化管道
+    pub fn create(name: &str, config: PipeConfig) -> Result<Self> {
+        Ok(Self::wrap(DynCrossProcessPipe::create_with_config(
+            name, config,
+        )?))
+    }
+
+    /// 连接已存在的类型化管道，队列中已有其他类型的消息时返回 [`TypeMismatch`]
+    pub fn connect(name: &str) -> Result<Self> {
+        Self::from_pipe(DynCrossProcessPipe::connect(name)?)
+    }
+
+    /// 包装已打开的管道（例如加密管道），队列中已有其他类型的消息时返回 [`TypeMismatch`]
+    pub fn from_pipe(pipe: DynCrossProcessPipe) -> Result<Self> {
+        let typed = Self::wrap(pipe);
+        for index in 0..typed.inner.capacity() {
+            if let Some((_, data)) = typed.inner.peek_bytes_at(index)? {
+                typed.check(&data)?;
+            }
+        }
+        Ok(typed)
+    }
+
+    fn wrap(inner: DynCrossProcessPipe) -> Self {
+        Self {
+            inner,
+            _marker: PhantomData,
+        }
+    }
+
+    /// `T` 的类型哈希
+    pub fn type_hash() -> u64 {
+        xxh64(std::any::type_name::<T>().as_bytes(), 0)
+    }
+
+    /// 底层管道，用于查看状态、指标等
+    pub fn pipe(&self) -> &DynCrossProcessPipe {
+        &self.inner
+    }
+
+    /// 把值直接编码进空槽位，返回 request_id
+    ///
+    /// 队列满时等待空槽位直到超时；编码后超出槽位大小时槽位被放弃并返回错误。
+    pub fn send(&self, value: &T, timeout: Duration) -> Result<u64> {
+        let index = self.inner.hold_timeout(timeout)?;
+        self.inner.try_send_with(index, |buf| {
+            if buf.len() < TYPE_HASH_LEN {
+                return Err(anyhow::anyhow!("槽位大小不足以容纳类型哈希").into());
+            }
+            let (hash, body) = buf.split_at_mut(TYPE_HASH_LEN);
+            hash.copy_from_slice(&Self::type_hash().to_le_bytes());
+            let len = bincode::encode_into_slice(value, body, bincode::config::standard())?;
+            Ok(TYPE_HASH_LEN + len)
+        })
+    }
+
+    /// 等待并读取下一个值，队列空时等待直到超时
+    ///
+    /// 类型哈希不一致的消息被取出后丢弃，返回 [`TypeMismatch`]。
+    pub fn receive(&self, timeout: Duration) -> Result<T> {
+        let index = self.inner.fetch_timeout(timeout)?;
+        self.inner.receive_with(index, |data| self.decode(data))?
+    }
+
+    /// 检查槽位数据的类型哈希
+    fn check(&self, data: &[u8]) -> Result<()> {
+        let found = data
+            .first_chunk::<TYPE_HASH_LEN>()
+            .map_or(0, |hash| u64::from_le_bytes(*hash));
+        if found != Self::type_hash() {
+            return Err(TypeMismatch {
+                pipe: self.inner.name().to_string(),
+                expected: std::any::type_name::<T>(),
+                found,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// 检查类型哈希后解码槽位数据
+    fn decode(&self, data: &[u8]) -> Result<T> {
+        self.check(data)?;
+        let (value, _) =
+            bincode::decode_from_slice(&data[TYPE_HASH_LEN..], bincode::config::standard())?;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, bincode::Encode, bincode::Decode)]
+    struct Order {
+        id: u64,
+        item: String,
+    }
+
+    #[test]
+    fn test_typed_pipe() {
+        let name = format!("mi7_test_typed_pipe_{}", std::process::id());
+        let timeout = Duration::from_secs(1);
+        let producer = TypedPipe::<Order>::create(&name, PipeConfig::new(4, 128)).unwrap();
+        let order = Order {
+            id: 7,
+            item: "book".to_string(),
+        };
+        producer.send(&order, timeout).unwrap();
+
+        // 队列中已有 Order 消息：以其他类型连接失败
+        let err = TypedPipe::<String>::connect(&name).err().unwrap();
+        assert!(err.is::<TypeMismatch>());
+        let consumer = TypedPipe::<Order>::connect(&name).unwrap();
+        assert_eq!(consumer.receive(timeout).unwrap(), order);
+
+        // 队列为空时可以连接，读取时发现类型不一致
+        let wrong = TypedPipe::<u32>::connect(&name).unwrap();
+        producer.send(&order, timeout).unwrap();
+        let err = wrong.receive(timeout).unwrap_err();
+        assert!(err.is::<TypeMismatch>());
+
+        // 超出槽位大小的值不写入
+        let large = Order {
+            id: 8,
+            item: "x".repeat(256),
+        };
+        assert!(producer.send(&large, timeout).is_err());
+        assert!(consumer.receive(Duration::from_millis(10)).is_err());
+    }
+}
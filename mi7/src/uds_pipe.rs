@@ -0,0 +1,920 @@
+//! UNIX 域套接字管道：无法共享 IPC 命名空间时替代共享内存
+//!
+//! 创建方进程持有一条 [`HeapSlotPipe`] 作为队列本体，并在套接字上提供服务；
+//! 连接方的每次 [`DynamicPipe`] 调用都转换为一帧请求（`u32` 小端长度 + bincode），
+//! 由创建方在队列上执行后应答。槽位下标、状态机、ack/nack 与过期语义和共享内存管道一致，
+//! entry / worker 代码无需修改。
+//!
+//! [`PipeFactory`] 以 `uds` 或 `uds(容量x槽位大小)` 类型创建与连接。管道名称包含 `/`
+//! 时直接作为套接字路径（容器间通过共享卷访问），否则为临时目录下的 `<名称>.sock`。
+//!
+//! 连接方崩溃时持有的槽位与共享内存管道一样，由 [`DynamicPipe::reclaim_stuck`] 回收。
+//!
+//! [`PipeFactory`]: crate::pipe::PipeFactory
+
+use crate::Message;
+use crate::codec::{BincodeCodec, CodecKind};
+use crate::heap_pipe::HeapSlotPipe;
+use crate::integrity::Integrity;
+use crate::pipe::{
+    DynamicPipe, MessageExpired, PipeConfig, PipeMetrics, PipeStatus, PipeTimeout, is_queue_full,
+};
+use crate::shared_slot::{LATENCY_BUCKETS, PipeMode, SlotState, TokioIPCError};
+
+use crate::error::Result;
+use anyhow::{Context, anyhow};
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::{self, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// 单帧的最大长度，与 bincode 解码上限一致
+const MAX_FRAME: usize = BincodeCodec::DECODE_LIMIT;
+
+/// 连接方异步等待消息或空槽位时的轮询间隔
+const FETCH_POLL: Duration = Duration::from_millis(10);
+
+/// 连接方发往创建方的请求，一个请求对应 [`DynamicPipe`] 的一次调用
+#[derive(Debug, bincode::Encode, bincode::Decode)]
+enum Request {
+    Layout,
+    Hold,
+    Send {
+        index: u64,
+        request_id: Option<u64>,
+        message: Message,
+    },
+    Fetch,
+    Receive {
+        index: u64,
+        unacked: bool,
+    },
+    SendBlocking {
+        message: Message,
+        timeout_ms: u64,
+    },
+    ReceiveBlocking {
+        timeout_ms: u64,
+    },
+    GetSlotState {
+        index: u64,
+    },
+    Status,
+    Metrics,
+    AttachedProcesses,
+    ReclaimStuck {
+        timeout_ms: u64,
+    },
+    IsBackpressured,
+    Ack {
+        index: u64,
+    },
+    Nack {
+        index: u64,
+    },
+    /// 不消费地读取消息，未指定槽位时读取下一条待读取消息
+    Peek {
+        index: Option<u64>,
+    },
+}
+
+/// 创建方的应答
+#[derive(Debug, bincode::Encode, bincode::Decode)]
+enum Reply {
+    Unit,
+    /// 槽位下标、request_id、数量或槽位状态
+    Value(u64),
+    Flag(bool),
+    /// 非阻塞获取的结果，队列为空时为 None
+    Fetched(Option<u64>),
+    Received {
+        request_id: u64,
+        message: Message,
+    },
+    Layout {
+        capacity: u64,
+        slot_size: u64,
+    },
+    Status(WireStatus),
+    Metrics {
+        buckets: Vec<u64>,
+        sum_nanos: u64,
+    },
+    Pids(Vec<u32>),
+    /// 不消费读取的结果，没有消息时为 None
+    Peeked(Option<(u64, Message)>),
+    Error(WireError),
+}
+
+/// 跨进程传递的错误，保留调用方需要区分的类型
+#[derive(Debug, bincode::Encode, bincode::Decode)]
+enum WireError {
+    SendTimeout {
+        timeout_ms: u64,
+    },
+    ReceiveTimeout {
+        timeout_ms: u64,
+    },
+    Expired {
+        request_id: u64,
+        expires_at: u64,
+    },
+    Other(String),
+    /// 队列已满，附带原始错误描述
+    QueueFull(String),
+}
+
+impl From<&crate::Error> for WireError {
+    fn from(e: &crate::Error) -> Self {
+        match (
+            e.downcast_ref::<PipeTimeout>(),
+            e.downcast_ref::<MessageExpired>(),
+        ) {
+            (Some(PipeTimeout::Send(timeout)), _) => WireError::SendTimeout {
+                timeout_ms: timeout.as_millis() as u64,
+            },
+            (Some(PipeTimeout::Receive(timeout)), _) => WireError::ReceiveTimeout {
+                timeout_ms: timeout.as_millis() as u64,
+            },
+            (None, Some(expired)) => WireError::Expired {
+                request_id: expired.request_id,
+                expires_at: expired.expires_at,
+            },
+            (None, None) if is_queue_full(e) => WireError::QueueFull(format!("{:#}", e)),
+            (None, None) => WireError::Other(format!("{:#}", e)),
+        }
+    }
+}
+
+impl From<WireError> for crate::Error {
+    fn from(e: WireError) -> Self {
+        match e {
+            WireError::SendTimeout { timeout_ms } => {
+                PipeTimeout::Send(Duration::from_millis(timeout_ms)).into()
+            }
+            WireError::ReceiveTimeout { timeout_ms } => {
+                PipeTimeout::Receive(Duration::from_millis(timeout_ms)).into()
+            }
+            WireError::Expired {
+                request_id,
+                expires_at,
+            } => MessageExpired {
+                request_id,
+                expires_at,
+            }
+            .into(),
+            WireError::Other(message) => anyhow!(message).into(),
+            WireError::QueueFull(message) => {
+                crate::Error::from(TokioIPCError::QueueFull).context(message)
+            }
+        }
+    }
+}
+
+/// [`PipeStatus`] 的传输形式
+#[derive(Debug, bincode::Encode, bincode::Decode)]
+struct WireStatus {
+    capacity: u64,
+    slot_size: u64,
+    write_pointer: u64,
+    read_pointer: u64,
+    empty_count: u64,
+    writing_count: u64,
+    in_progress_count: u64,
+    reading_count: u64,
+    ready_count: u64,
+    used_count: u64,
+    mode: u32,
+    codec: u32,
+    integrity: u32,
+    reclaimed_count: u64,
+    backpressured: bool,
+    expired_count: u64,
+    redelivered_count: u64,
+    dead_lettered_count: u64,
+    sent_count: u64,
+    received_count: u64,
+    lock_contended_count: u64,
+    dropped_count: u64,
+}
+
+impl From<PipeStatus> for WireStatus {
+    fn from(status: PipeStatus) -> Self {
+        Self {
+            capacity: status.capacity as u64,
+            slot_size: status.slot_size as u64,
+            write_pointer: status.write_pointer as u64,
+            read_pointer: status.read_pointer as u64,
+            empty_count: status.empty_count as u64,
+            writing_count: status.writing_count as u64,
+            in_progress_count: status.in_progress_count as u64,
+            reading_count: status.reading_count as u64,
+            ready_count: status.ready_count as u64,
+            used_count: status.used_count as u64,
+            mode: status.mode as u32,
+            codec: status.codec as u32,
+            integrity: status.integrity as u32,
+            reclaimed_count: status.reclaimed_count,
+            backpressured: status.backpressured,
+            expired_count: status.expired_count,
+            redelivered_count: status.redelivered_count,
+            dead_lettered_count: status.dead_lettered_count,
+            sent_count: status.sent_count,
+            received_count: status.received_count,
+            lock_contended_count: status.lock_contended_count,
+            dropped_count: status.dropped_count,
+        }
+    }
+}
+
+impl From<WireStatus> for PipeStatus {
+    fn from(status: WireStatus) -> Self {
+        Self {
+            capacity: status.capacity as usize,
+            slot_size: status.slot_size as usize,
+            write_pointer: status.write_pointer as usize,
+            read_pointer: status.read_pointer as usize,
+            empty_count: status.empty_count as usize,
+            writing_count: status.writing_count as usize,
+            in_progress_count: status.in_progress_count as usize,
+            reading_count: status.reading_count as usize,
+            ready_count: status.ready_count as usize,
+            used_count: status.used_count as usize,
+            mode: PipeMode::from_u32(status.mode),
+            codec: CodecKind::from_u32(status.codec),
+            integrity: Integrity::from_u32(status.integrity),
+            reclaimed_count: status.reclaimed_count,
+            backpressured: status.backpressured,
+            expired_count: status.expired_count,
+            redelivered_count: status.redelivered_count,
+            dead_lettered_count: status.dead_lettered_count,
+            sent_count: status.sent_count,
+            received_count: status.received_count,
+            lock_contended_count: status.lock_contended_count,
+            dropped_count: status.dropped_count,
+        }
+    }
+}
+
+fn slot_state_from(value: u32) -> Result<SlotState> {
+    Ok(match value {
+        0 => SlotState::EMPTY,
+        1 => SlotState::WRITING,
+        2 => SlotState::INPROGRESS,
+        3 => SlotState::READING,
+        4 => SlotState::READY,
+        other => return Err(anyhow!("无效的槽位状态: {}", other).into()),
+    })
+}
+
+fn write_frame<T: bincode::Encode>(stream: &mut UnixStream, value: &T) -> Result<()> {
+    let payload = bincode::encode_to_vec(value, bincode::config::standard())?;
+    let mut frame = Vec::with_capacity(4 + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&payload);
+    stream.write_all(&frame)?;
+    Ok(())
+}
+
+/// 读取一帧，对端关闭连接时返回 None
+fn read_frame<T: bincode::Decode<()>>(stream: &mut UnixStream) -> Result<Option<T>> {
+    let mut len = [0u8; 4];
+    match stream.read_exact(&mut len) {
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        result => result?,
+    }
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_FRAME {
+        return Err(anyhow!("帧长度 {} 超过上限 {}", len, MAX_FRAME).into());
+    }
+    let mut payload = vec![0u8; len];
+    stream.read_exact(&mut payload)?;
+    let (value, _) = bincode::decode_from_slice(&payload, bincode::config::standard())?;
+    Ok(Some(value))
+}
+
+/// 管道名称对应的套接字路径
+fn socket_path(name: &str) -> PathBuf {
+    if name.contains('/') {
+        PathBuf::from(name)
+    } else {
+        std::env::temp_dir().join(format!("{}.sock", name))
+    }
+}
+
+/// 对端进程号，无法获取时为 0
+#[cfg(target_os = "linux")]
+fn peer_pid(stream: &UnixStream) -> u32 {
+    use std::os::fd::AsRawFd;
+
+    let mut cred: libc::ucred = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&mut cred as *mut libc::ucred).cast(),
+            &mut len,
+        )
+    };
+    if ret == 0 { cred.pid as u32 } else { 0 }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn peer_pid(_stream: &UnixStream) -> u32 {
+    0
+}
+
+/// 创建方的服务状态：已建立的连接与停止标志
+struct Server {
+    path: PathBuf,
+    stopping: AtomicBool,
+    next_connection: AtomicU64,
+    connections: Mutex<HashMap<u64, (u32, UnixStream)>>,
+}
+
+impl Server {
+    fn attached_processes(&self) -> Vec<u32> {
+        let mut pids = vec![std::process::id()];
+        for (pid, _) in self.connections.lock().unwrap().values() {
+            if *pid != 0 && !pids.contains(pid) {
+                pids.push(*pid);
+            }
+        }
+        pids
+    }
+
+    /// 接受连接，每个连接由独立线程处理
+    fn accept_loop(self: Arc<Self>, listener: UnixListener, pipe: Arc<HeapSlotPipe>) {
+        for stream in listener.incoming() {
+            if self.stopping.load(Ordering::Acquire) {
+                break;
+            }
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    tracing::warn!("UDS 管道 {} 接受连接失败: {}", self.path.display(), e);
+                    continue;
+                }
+            };
+            let id = self.next_connection.fetch_add(1, Ordering::Relaxed);
+            if let Ok(clone) = stream.try_clone() {
+                self.connections
+                    .lock()
+                    .unwrap()
+                    .insert(id, (peer_pid(&stream), clone));
+            }
+            let server = self.clone();
+            let pipe = pipe.clone();
+            std::thread::spawn(move || {
+                if let Err(e) = server.serve(stream, &pipe) {
+                    tracing::debug!("UDS 管道 {} 连接断开: {}", server.path.display(), e);
+                }
+                server.connections.lock().unwrap().remove(&id);
+            });
+        }
+    }
+
+    fn serve(&self, mut stream: UnixStream, pipe: &HeapSlotPipe) -> Result<()> {
+        while let Some(request) = read_frame::<Request>(&mut stream)? {
+            let reply = self.execute(pipe, request);
+            write_frame(&mut stream, &reply)?;
+        }
+        Ok(())
+    }
+
+    /// 在队列上执行一次请求
+    fn execute(&self, pipe: &HeapSlotPipe, request: Request) -> Reply {
+        let result = match request {
+            Request::Layout => Ok(Reply::Layout {
+                capacity: pipe.capacity() as u64,
+                slot_size: pipe.slot_size() as u64,
+            }),
+            Request::Hold => pipe.hold().map(|index| Reply::Value(index as u64)),
+            Request::Send {
+                index,
+                request_id,
+                message,
+            } => match request_id {
+                Some(request_id) => pipe.send_tagged(index as usize, request_id, message),
+                None => pipe.send(index as usize, message),
+            }
+            .map(Reply::Value),
+            Request::Fetch => Ok(Reply::Fetched(pipe.fetch().ok().map(|index| index as u64))),
+            Request::Receive { index, unacked } => if unacked {
+                pipe.receive_unacked(index as usize)
+            } else {
+                pipe.receive_tagged(index as usize)
+            }
+            .map(|(request_id, message)| Reply::Received {
+                request_id,
+                message,
+            }),
+            Request::SendBlocking {
+                message,
+                timeout_ms,
+            } => pipe
+                .send_blocking(message, Duration::from_millis(timeout_ms))
+                .map(Reply::Value),
+            Request::ReceiveBlocking { timeout_ms } => pipe
+                .receive_blocking(Duration::from_millis(timeout_ms))
+                .map(|message| Reply::Received {
+                    request_id: 0,
+                    message,
+                }),
+            Request::GetSlotState { index } => pipe
+                .get_slot_state(index as usize)
+                .map(|state| Reply::Value(state as u64)),
+            Request::Status => Ok(Reply::Status(pipe.status().into())),
+            Request::Metrics => {
+                let metrics = pipe.metrics();
+                Ok(Reply::Metrics {
+                    buckets: metrics.buckets.iter().map(|&(_, count)| count).collect(),
+                    sum_nanos: metrics.sum.as_nanos() as u64,
+                })
+            }
+            Request::AttachedProcesses => Ok(Reply::Pids(self.attached_processes())),
+            Request::ReclaimStuck { timeout_ms } => Ok(Reply::Value(
+                pipe.reclaim_stuck(Duration::from_millis(timeout_ms)) as u64,
+            )),
+            Request::IsBackpressured => Ok(Reply::Flag(pipe.is_backpressured())),
+            Request::Ack { index } => pipe.ack(index as usize).map(|_| Reply::Unit),
+            Request::Nack { index } => pipe.nack(index as usize).map(Reply::Flag),
+            Request::Peek { index } => match index {
+                Some(index) => pipe.peek_at(index as usize),
+                None => pipe.peek(),
+            }
+            .map(Reply::Peeked),
+        };
+        result.unwrap_or_else(|e| Reply::Error(WireError::from(&e)))
+    }
+}
+
+/// 管道的一端：创建方持有队列，连接方经套接字转发调用
+enum Endpoint {
+    Server {
+        pipe: Arc<HeapSlotPipe>,
+        server: Arc<Server>,
+        acceptor: Option<JoinHandle<()>>,
+    },
+    Client {
+        /// 空闲连接；并发调用（如多个阻塞接收）各自占用一个连接
+        idle: Mutex<Vec<UnixStream>>,
+    },
+}
+
+/// 基于 UNIX 域套接字的管道，实现 [`DynamicPipe`]
+pub struct UdsPipe {
+    name: String,
+    path: PathBuf,
+    config: PipeConfig,
+    endpoint: Endpoint,
+}
+
+impl UdsPipe {
+    /// 创建管道并在套接字上提供服务，句柄释放时停止服务并删除套接字文件
+    ///
+    /// 套接字文件已存在但无人监听（上次崩溃遗留）时被替换。
+    pub fn create(name: &str, config: PipeConfig) -> Result<Self> {
+        let path = socket_path(name);
+        if path.exists() {
+            if UnixStream::connect(&path).is_ok() {
+                return Err(anyhow!("UDS 管道 {} 已在服务中", path.display()).into());
+            }
+            std::fs::remove_file(&path)
+                .with_context(|| format!("删除遗留的套接字 {} 失败", path.display()))?;
+        }
+        let pipe = Arc::new(HeapSlotPipe::create(name, config)?);
+        let listener = UnixListener::bind(&path)
+            .with_context(|| format!("绑定套接字 {} 失败", path.display()))?;
+
+        let server = Arc::new(Server {
+            path: path.clone(),
+            stopping: AtomicBool::new(false),
+            next_connection: AtomicU64::new(0),
+            connections: Mutex::new(HashMap::new()),
+        });
+        let acceptor = {
+            let server = server.clone();
+            let pipe = pipe.clone();
+            std::thread::Builder::new()
+                .name(format!("mi7-uds-{}", name))
+                .spawn(move || server.accept_loop(listener, pipe))?
+        };
+        Ok(Self {
+            name: name.to_string(),
+            path,
+            config: pipe.config(),
+            endpoint: Endpoint::Server {
+                pipe,
+                server,
+                acceptor: Some(acceptor),
+            },
+        })
+    }
+
+    /// 连接到其他进程创建的管道，容量与槽位大小以创建方为准
+    pub fn connect(name: &str) -> Result<Self> {
+        let path = socket_path(name);
+        let mut pipe = Self {
+            name: name.to_string(),
+            path,
+            config: PipeConfig::default(),
+            endpoint: Endpoint::Client {
+                idle: Mutex::new(Vec::new()),
+            },
+        };
+        match pipe.call(Request::Layout)? {
+            Reply::Layout {
+                capacity,
+                slot_size,
+            } => pipe.config = PipeConfig::new(capacity as usize, slot_size as usize),
+            other => return Err(unexpected(other).into()),
+        }
+        Ok(pipe)
+    }
+
+    /// 连接到管道，容量或槽位大小与期望不一致时返回错误
+    pub fn connect_with_layout(name: &str, capacity: usize, slot_size: usize) -> Result<Self> {
+        let pipe = Self::connect(name)?;
+        if pipe.capacity() != capacity || pipe.slot_size() != slot_size {
+            return Err(anyhow!(
+                "UDS 管道 {} 的布局为 {}x{}，期望 {}x{}",
+                name,
+                pipe.capacity(),
+                pipe.slot_size(),
+                capacity,
+                slot_size
+            )
+            .into());
+        }
+        Ok(pipe)
+    }
+
+    /// 套接字路径
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 执行一次调用：创建方直接在队列上执行，连接方经套接字转发
+    fn call(&self, request: Request) -> Result<Reply> {
+        let reply = match &self.endpoint {
+            Endpoint::Server { pipe, server, .. } => server.execute(pipe, request),
+            Endpoint::Client { idle } => {
+                let idle_stream = idle.lock().unwrap().pop();
+                let mut stream = match idle_stream {
+                    Some(stream) => stream,
+                    None => UnixStream::connect(&self.path)
+                        .with_context(|| format!("连接 UDS 管道 {} 失败", self.path.display()))?,
+                };
+                write_frame(&mut stream, &request)?;
+                let reply = read_frame::<Reply>(&mut stream)?
+                    .ok_or_else(|| anyhow!("UDS 管道 {} 连接已关闭", self.path.display()))?;
+                idle.lock().unwrap().push(stream);
+                reply
+            }
+        };
+        match reply {
+            Reply::Error(e) => Err(e.into()),
+            reply => Ok(reply),
+        }
+    }
+
+    fn call_value(&self, request: Request) -> Result<u64> {
+        match self.call(request)? {
+            Reply::Value(value) => Ok(value),
+            other => Err(unexpected(other).into()),
+        }
+    }
+
+    fn call_flag(&self, request: Request) -> Result<bool> {
+        match self.call(request)? {
+            Reply::Flag(flag) => Ok(flag),
+            other => Err(unexpected(other).into()),
+        }
+    }
+
+    fn call_unit(&self, request: Request) -> Result<()> {
+        match self.call(request)? {
+            Reply::Unit => Ok(()),
+            other => Err(unexpected(other).into()),
+        }
+    }
+
+    fn call_received(&self, request: Request) -> Result<(u64, Message)> {
+        match self.call(request)? {
+            Reply::Received {
+                request_id,
+                message,
+            } => Ok((request_id, message)),
+            other => Err(unexpected(other).into()),
+        }
+    }
+
+    fn call_peeked(&self, request: Request) -> Result<Option<(u64, Message)>> {
+        match self.call(request)? {
+            Reply::Peeked(peeked) => Ok(peeked),
+            other => Err(unexpected(other).into()),
+        }
+    }
+
+    fn try_fetch(&self) -> Result<Option<usize>> {
+        match self.call(Request::Fetch)? {
+            Reply::Fetched(index) => Ok(index.map(|index| index as usize)),
+            other => Err(unexpected(other).into()),
+        }
+    }
+}
+
+fn unexpected(reply: Reply) -> anyhow::Error {
+    anyhow!("UDS 管道应答不符合请求: {:?}", reply)
+}
+
+impl Drop for UdsPipe {
+    fn drop(&mut self) {
+        if let Endpoint::Server {
+            server, acceptor, ..
+        } = &mut self.endpoint
+        {
+            server.stopping.store(true, Ordering::Release);
+            // 唤醒阻塞在 accept 上的线程
+            let _ = UnixStream::connect(&server.path);
+            if let Some(acceptor) = acceptor.take() {
+                let _ = acceptor.join();
+            }
+            for (_, stream) in server.connections.lock().unwrap().values() {
+                let _ = stream.shutdown(std::net::Shutdown::Both);
+            }
+            let _ = std::fs::remove_file(&server.path);
+        }
+    }
+}
+
+impl DynamicPipe for UdsPipe {
+    fn hold(&self) -> Result<usize> {
+        self.call_value(Request::Hold).map(|index| index as usize)
+    }
+
+    fn send(&self, index: usize, message: Message) -> Result<u64> {
+        self.call_value(Request::Send {
+            index: index as u64,
+            request_id: None,
+            message,
+        })
+    }
+
+    fn fetch(&self) -> Result<usize> {
+        Ok(self.try_fetch()?.ok_or_else(|| {
+            anyhow::Error::from(TokioIPCError::QueueEmpty).context("队列为空，无法获取消息")
+        })?)
+    }
+
+    fn fetch_async(&self) -> Pin<Box<dyn Future<Output = Result<usize>> + Send + '_>> {
+        match &self.endpoint {
+            Endpoint::Server { pipe, .. } => pipe.fetch_async(),
+            Endpoint::Client { .. } => Box::pin(async move {
+                loop {
+                    if let Some(index) = self.try_fetch()? {
+                        return Ok(index);
+                    }
+                    tokio::time::sleep(FETCH_POLL).await;
+                }
+            }),
+        }
+    }
+
+    fn hold_async(&self) -> Pin<Box<dyn Future<Output = Result<usize>> + Send + '_>> {
+        match &self.endpoint {
+            Endpoint::Server { pipe, .. } => pipe.hold_async(),
+            // 连接方收不到创建方进程内的通知，队列满时定时重试
+            Endpoint::Client { .. } => Box::pin(async move {
+                loop {
+                    match self.hold() {
+                        Err(e) if is_queue_full(&e) => tokio::time::sleep(FETCH_POLL).await,
+                        held => return held,
+                    }
+                }
+            }),
+        }
+    }
+
+    fn receive(&self, index: usize) -> Result<Message> {
+        self.receive_tagged(index).map(|(_, message)| message)
+    }
+
+    fn send_tagged(&self, index: usize, request_id: u64, message: Message) -> Result<u64> {
+        self.call_value(Request::Send {
+            index: index as u64,
+            request_id: Some(request_id),
+            message,
+        })
+    }
+
+    fn receive_tagged(&self, index: usize) -> Result<(u64, Message)> {
+        self.call_received(Request::Receive {
+            index: index as u64,
+            unacked: false,
+        })
+    }
+
+    fn send_blocking(&self, message: Message, timeout: Duration) -> Result<u64> {
+        self.call_value(Request::SendBlocking {
+            message,
+            timeout_ms: timeout.as_millis() as u64,
+        })
+    }
+
+    fn receive_blocking(&self, timeout: Duration) -> Result<Message> {
+        self.call_received(Request::ReceiveBlocking {
+            timeout_ms: timeout.as_millis() as u64,
+        })
+        .map(|(_, message)| message)
+    }
+
+    fn get_slot_state(&self, index: usize) -> Result<SlotState> {
+        let state = self.call_value(Request::GetSlotState {
+            index: index as u64,
+        })?;
+        slot_state_from(state as u32)
+    }
+
+    fn status(&self) -> PipeStatus {
+        match self.call(Request::Status) {
+            Ok(Reply::Status(status)) => status.into(),
+            result => {
+                tracing::warn!("获取 UDS 管道 {} 状态失败: {:?}", self.name, result);
+                PipeStatus {
+                    capacity: self.config.capacity,
+                    slot_size: self.config.slot_size,
+                    write_pointer: 0,
+                    read_pointer: 0,
+                    empty_count: 0,
+                    writing_count: 0,
+                    in_progress_count: 0,
+                    reading_count: 0,
+                    ready_count: 0,
+                    used_count: 0,
+                    mode: self.config.mode,
+                    codec: self.config.codec,
+                    integrity: self.config.integrity,
+                    reclaimed_count: 0,
+                    backpressured: false,
+                    expired_count: 0,
+                    redelivered_count: 0,
+                    dead_lettered_count: 0,
+                    sent_count: 0,
+                    received_count: 0,
+                    lock_contended_count: 0,
+                    dropped_count: 0,
+                }
+            }
+        }
+    }
+
+    fn metrics(&self) -> PipeMetrics {
+        match self.call(Request::Metrics) {
+            Ok(Reply::Metrics { buckets, sum_nanos }) => {
+                let mut counts = [0u64; LATENCY_BUCKETS];
+                for (count, value) in counts.iter_mut().zip(buckets) {
+                    *count = value;
+                }
+                PipeMetrics::from_histogram(&counts, sum_nanos)
+            }
+            _ => PipeMetrics::default(),
+        }
+    }
+
+    fn config(&self) -> PipeConfig {
+        self.config
+    }
+
+    fn capacity(&self) -> usize {
+        self.config.capacity
+    }
+
+    fn slot_size(&self) -> usize {
+        self.config.slot_size
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 创建方删除套接字文件，之后无法再建立新连接；连接方什么也不做
+    fn unlink(&self) -> Result<()> {
+        if let Endpoint::Server { pipe, .. } = &self.endpoint {
+            pipe.unlink()?;
+            match std::fs::remove_file(&self.path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn attached_processes(&self) -> Vec<u32> {
+        match self.call(Request::AttachedProcesses) {
+            Ok(Reply::Pids(pids)) => pids,
+            _ => Vec::new(),
+        }
+    }
+
+    fn reclaim_stuck(&self, timeout: Duration) -> usize {
+        self.call_value(Request::ReclaimStuck {
+            timeout_ms: timeout.as_millis() as u64,
+        })
+        .unwrap_or(0) as usize
+    }
+
+    fn is_backpressured(&self) -> bool {
+        self.call_flag(Request::IsBackpressured).unwrap_or(false)
+    }
+
+    fn receive_unacked(&self, index: usize) -> Result<(u64, Message)> {
+        self.call_received(Request::Receive {
+            index: index as u64,
+            unacked: true,
+        })
+    }
+
+    fn ack(&self, index: usize) -> Result<()> {
+        self.call_unit(Request::Ack {
+            index: index as u64,
+        })
+    }
+
+    fn nack(&self, index: usize) -> Result<bool> {
+        self.call_flag(Request::Nack {
+            index: index as u64,
+        })
+    }
+
+    fn peek(&self) -> Result<Option<(u64, Message)>> {
+        self.call_peeked(Request::Peek { index: None })
+    }
+
+    fn peek_at(&self, index: usize) -> Result<Option<(u64, Message)>> {
+        self.call_peeked(Request::Peek {
+            index: Some(index as u64),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uds_pipe_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let name = dir.path().join("uds_pipe.sock");
+        let name = name.to_str().unwrap();
+        let server = UdsPipe::create(name, PipeConfig::new(4, 256)).unwrap();
+        let client = UdsPipe::connect_with_layout(name, 4, 256).unwrap();
+
+        // 连接方写入，创建方按槽位状态机读取
+        let index = client.hold().unwrap();
+        let request_id = client
+            .send_tagged(index, 42, Message::init("hello".to_string()))
+            .unwrap();
+        assert_eq!(request_id, 42);
+        assert_eq!(client.status().ready_count, 1);
+
+        let index = server.fetch().unwrap();
+        let (request_id, message) = server.receive_tagged(index).unwrap();
+        assert_eq!(request_id, 42);
+        assert_eq!(message.data, b"hello");
+
+        // 超时错误类型跨进程保留
+        let err = client
+            .receive_blocking(Duration::from_millis(10))
+            .unwrap_err();
+        assert!(err.is::<PipeTimeout>());
+        assert!(client.attached_processes().contains(&std::process::id()));
+
+        // 队列已满同样跨进程保留，非阻塞发送返回 None
+        for _ in 0..4 {
+            assert!(
+                client
+                    .try_send(Message::init("fill".to_string()))
+                    .unwrap()
+                    .is_some()
+            );
+        }
+        assert_eq!(
+            client.try_send(Message::init("full".to_string())).unwrap(),
+            None
+        );
+
+        drop(server);
+        assert!(!Path::new(name).exists());
+        assert!(client.hold().is_err());
+    }
+}
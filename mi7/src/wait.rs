@@ -0,0 +1,380 @@
+//! 队列空或满时的等待方式
+//!
+//! 读者等消息、写者等空槽位时默认在共享内存中的条件变量 / futex 上休眠（[`WaitStrategy::Blocking`]），
+//! 不占用 CPU，但唤醒要经过一次系统调用和调度。对延迟敏感、独占 CPU 核心的部署可以改为轮询：
+//!
+//! | 策略 | 等待时 | 适用场景 |
+//! |------|--------|----------|
+//! | `Blocking` | 条件变量 / futex 休眠 | 默认，CPU 与延迟兼顾 |
+//! | `SpinHot` | 一直忙等 | 独占核心、微秒级延迟 |
+//! | `SpinThenYield` | 先忙等，再每轮让出 CPU | 核心数充裕、消息密集 |
+//! | `SleepBackoff { min, max }` | 休眠时间从 `min` 倍增到 `max` | 不希望进入内核等待队列 |
+//! | `Adaptive` | 按最近的消息到达速率自动选择 | 流量时有时无 |
+//!
+//! `Adaptive` 根据共享内存头部记录的写入间隔滑动平均（[`ArrivalStats`]）选择初始轮询间隔：
+//! 消息密集到达时忙等，稀疏时按平均间隔的四分之一轮询，空闲时从短间隔开始指数退避到 1 毫秒。
+//! 当前选用的间隔见 [`PipeMetrics::poll_interval`](crate::pipe::PipeMetrics::poll_interval)。
+//!
+//! 等待方式是每个进程、每个句柄自己的选择，不记录在共享内存头部：同一条管道上的写者可以忙等，
+//! 读者仍然休眠。[`PipeConfig::with_wait_strategy`](crate::pipe::PipeConfig::with_wait_strategy)
+//! 为单条管道指定；进程启动时调用 [`init_from_config`] 从 `[wait]` 配置段设置本进程的默认值。
+//!
+//! 轮询策略下写者发布消息仍会唤醒休眠的读者，两种等待方式的进程可以混用。
+
+use crate::config;
+use crate::error::Result;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// [`WaitStrategy::SpinThenYield`] 改为让出 CPU 之前的忙等次数
+const SPIN_LIMIT: u32 = 100;
+
+/// [`WaitStrategy::Blocking`] 下没有可等待的通知时（例如进程内管道的异步等待）的轮询间隔
+pub const BLOCKING_POLL: Duration = Duration::from_millis(10);
+
+/// [`WaitStrategy::Adaptive`] 的最短与最长休眠时间
+pub const ADAPTIVE_MIN: Duration = Duration::from_micros(10);
+pub const ADAPTIVE_MAX: Duration = Duration::from_millis(1);
+
+/// [`WaitStrategy::Adaptive`] 轮询间隔短于该值时改为忙等
+const ADAPTIVE_SPIN_BELOW: Duration = Duration::from_micros(20);
+
+/// [`WaitStrategy::Adaptive`] 忙等该次数仍未等到时改为退避休眠
+const ADAPTIVE_SPINS: u32 = 4096;
+
+/// 距最近一次写入超过平均间隔的该倍数时视为空闲
+const IDLE_FACTOR: u32 = 8;
+
+/// 队列空或满时的等待方式
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum WaitStrategy {
+    /// 一直忙等，不让出 CPU
+    SpinHot,
+    /// 先忙等一百次，之后每轮让出 CPU
+    SpinThenYield,
+    /// 轮询之间休眠，休眠时间从 `min` 倍增到 `max`
+    SleepBackoff { min: Duration, max: Duration },
+    /// 在条件变量 / futex 上休眠，直到被唤醒
+    #[default]
+    Blocking,
+    /// 按最近的消息到达速率在忙等与退避休眠之间自动切换
+    Adaptive,
+}
+
+impl WaitStrategy {
+    /// 默认参数的 [`WaitStrategy::SleepBackoff`]：10 微秒倍增到 1 毫秒
+    pub const SLEEP_BACKOFF: Self = WaitStrategy::SleepBackoff {
+        min: Duration::from_micros(10),
+        max: Duration::from_millis(1),
+    };
+
+    /// 是否以轮询代替休眠
+    pub fn polls(self) -> bool {
+        self != WaitStrategy::Blocking
+    }
+}
+
+impl FromStr for WaitStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('-', "_").as_str() {
+            "spin_hot" | "spin" => Ok(WaitStrategy::SpinHot),
+            "spin_then_yield" | "yield" => Ok(WaitStrategy::SpinThenYield),
+            "sleep_backoff" | "sleep" => Ok(WaitStrategy::SLEEP_BACKOFF),
+            "blocking" => Ok(WaitStrategy::Blocking),
+            "adaptive" => Ok(WaitStrategy::Adaptive),
+            _ => Err(format!(
+                "不支持的等待方式: '{}'. 支持: blocking, spin_hot, spin_then_yield, sleep_backoff, adaptive",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for WaitStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WaitStrategy::SpinHot => write!(f, "spin_hot"),
+            WaitStrategy::SpinThenYield => write!(f, "spin_then_yield"),
+            WaitStrategy::SleepBackoff { min, max } => {
+                write!(f, "sleep_backoff({:?}..{:?})", min, max)
+            }
+            WaitStrategy::Blocking => write!(f, "blocking"),
+            WaitStrategy::Adaptive => write!(f, "adaptive"),
+        }
+    }
+}
+
+/// 管道最近的消息到达情况，见 [`WaitStrategy::Adaptive`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ArrivalStats {
+    /// 相邻写入间隔的滑动平均，还没有写入过两条消息时为 `None`
+    pub gap: Option<Duration>,
+    /// 距最近一次写入的时间
+    pub idle: Duration,
+}
+
+impl ArrivalStats {
+    /// 自适应等待的初始轮询间隔，0 表示忙等
+    ///
+    /// 消息持续到达时取平均间隔的四分之一，短于 20 微秒时忙等；距最近一次写入超过平均间隔的
+    /// 8 倍视为空闲，从平均间隔开始退避。没有统计时取最长间隔。
+    pub fn poll_interval(&self) -> Duration {
+        let Some(gap) = self.gap else {
+            return ADAPTIVE_MAX;
+        };
+        if self.idle > gap.saturating_mul(IDLE_FACTOR) {
+            return gap.clamp(ADAPTIVE_MIN, ADAPTIVE_MAX);
+        }
+        let interval = gap / 4;
+        if interval < ADAPTIVE_SPIN_BELOW {
+            Duration::ZERO
+        } else {
+            interval.min(ADAPTIVE_MAX)
+        }
+    }
+}
+
+/// 轮询等待的退避状态：每次条件不满足时调用一次 [`Backoff::snooze`]
+pub struct Backoff {
+    strategy: WaitStrategy,
+    spins: u32,
+    sleep: Duration,
+}
+
+impl Backoff {
+    pub fn new(strategy: WaitStrategy) -> Self {
+        let sleep = match strategy {
+            WaitStrategy::SleepBackoff { min, .. } => min,
+            _ => BLOCKING_POLL,
+        };
+        Self {
+            strategy,
+            spins: 0,
+            sleep,
+        }
+    }
+
+    /// [`WaitStrategy::Adaptive`] 的退避状态，初始间隔由 `stats` 决定
+    pub fn adaptive(stats: ArrivalStats) -> Self {
+        Self {
+            strategy: WaitStrategy::Adaptive,
+            spins: 0,
+            sleep: stats.poll_interval(),
+        }
+    }
+
+    /// 按策略等待一轮，休眠不超过 `remaining`
+    ///
+    /// [`WaitStrategy::Blocking`] 时休眠 [`BLOCKING_POLL`]，仅用于没有通知可等待的场合。
+    pub fn snooze(&mut self, remaining: Option<Duration>) {
+        let strategy = self.strategy;
+        match strategy {
+            WaitStrategy::SpinHot => std::hint::spin_loop(),
+            WaitStrategy::SpinThenYield if self.spins < SPIN_LIMIT => {
+                self.spins += 1;
+                std::hint::spin_loop();
+            }
+            WaitStrategy::SpinThenYield => std::thread::yield_now(),
+            WaitStrategy::Adaptive if self.adaptive_spin() => std::hint::spin_loop(),
+            WaitStrategy::SleepBackoff { .. } | WaitStrategy::Blocking | WaitStrategy::Adaptive => {
+                std::thread::sleep(self.next_sleep(remaining));
+            }
+        }
+    }
+
+    /// [`Backoff::snooze`] 的异步版本，忙等策略改为让出运行时线程
+    pub async fn snooze_async(&mut self) {
+        let strategy = self.strategy;
+        match strategy {
+            WaitStrategy::SpinHot | WaitStrategy::SpinThenYield => tokio::task::yield_now().await,
+            WaitStrategy::Adaptive if self.adaptive_spin() => tokio::task::yield_now().await,
+            WaitStrategy::SleepBackoff { .. } | WaitStrategy::Blocking | WaitStrategy::Adaptive => {
+                tokio::time::sleep(self.next_sleep(None)).await
+            }
+        }
+    }
+
+    /// 自适应等待本轮是否忙等：忙等次数用完后转入最短间隔的退避休眠
+    fn adaptive_spin(&mut self) -> bool {
+        if !self.sleep.is_zero() {
+            return false;
+        }
+        if self.spins < ADAPTIVE_SPINS {
+            self.spins += 1;
+            return true;
+        }
+        self.sleep = ADAPTIVE_MIN;
+        false
+    }
+
+    /// 本轮的休眠时间，之后按策略倍增
+    fn next_sleep(&mut self, remaining: Option<Duration>) -> Duration {
+        let sleep = remaining.map_or(self.sleep, |r| r.min(self.sleep));
+        match self.strategy {
+            WaitStrategy::SleepBackoff { min, max } => {
+                self.sleep = (self.sleep * 2).clamp(min, max.max(min));
+            }
+            WaitStrategy::Adaptive => {
+                self.sleep = (self.sleep * 2).clamp(ADAPTIVE_MIN, ADAPTIVE_MAX);
+            }
+            _ => {}
+        }
+        sleep
+    }
+}
+
+/// 本进程的默认等待方式，由 [`install`] 或 [`init_from_config`] 设置
+static INSTALLED: OnceLock<WaitStrategy> = OnceLock::new();
+
+/// 设置本进程的默认等待方式，此后新建的 [`PipeConfig`](crate::pipe::PipeConfig) 与连接的管道使用它；
+/// 已设置过时返回 `false`
+pub fn install(strategy: WaitStrategy) -> bool {
+    INSTALLED.set(strategy).is_ok()
+}
+
+/// 本进程的默认等待方式，未设置时为 [`WaitStrategy::Blocking`]
+pub fn installed() -> WaitStrategy {
+    INSTALLED.get().copied().unwrap_or_default()
+}
+
+/// 按 `[wait]` 配置设置本进程的默认等待方式
+///
+/// 未配置该段时保持 [`WaitStrategy::Blocking`]，返回 `false`。`sleep_backoff` 的休眠区间由
+/// `min_sleep_micros` 与 `max_sleep_micros` 指定。
+pub fn init_from_config() -> Result<bool> {
+    let config = config::get_config();
+    if config.get_keys("wait").is_none() {
+        return Ok(false);
+    }
+    let mut strategy: WaitStrategy = config::string_or("wait", "strategy", "blocking")
+        .parse()
+        .map_err(|e: String| anyhow::anyhow!(e))?;
+    if let WaitStrategy::SleepBackoff { min, max } = &mut strategy {
+        let micros = |key: &str, default: &Duration| {
+            let value = config::int_or("wait", key, default.as_micros() as i64);
+            Duration::from_micros(value.max(1) as u64)
+        };
+        *min = micros("min_sleep_micros", min);
+        *max = micros("max_sleep_micros", max).max(*min);
+    }
+    tracing::info!("队列等待方式: {}", strategy);
+    Ok(install(strategy))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Message;
+    use crate::pipe::{DynCrossProcessPipe, PipeConfig, PipeTimeout};
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    #[tokio::test]
+    async fn test_wait_strategies() {
+        assert_eq!(
+            "spin-then-yield".parse::<WaitStrategy>(),
+            Ok(WaitStrategy::SpinThenYield)
+        );
+        assert!("busy".parse::<WaitStrategy>().is_err());
+
+        let mut backoff = Backoff::new(WaitStrategy::SleepBackoff {
+            min: Duration::from_micros(10),
+            max: Duration::from_micros(30),
+        });
+        let sleeps: Vec<_> = (0..3).map(|_| backoff.next_sleep(None)).collect();
+        assert_eq!(sleeps, [10, 20, 30].map(Duration::from_micros));
+
+        let strategies = [
+            WaitStrategy::SpinHot,
+            WaitStrategy::SpinThenYield,
+            WaitStrategy::SLEEP_BACKOFF,
+            WaitStrategy::Blocking,
+        ];
+        let timeout = Duration::from_secs(1);
+        for (i, strategy) in strategies.into_iter().enumerate() {
+            let name = format!("mi7_test_wait_{}_{}", i, std::process::id());
+            let config = PipeConfig::new(1, 128).with_wait_strategy(strategy);
+            let pipe = Arc::new(DynCrossProcessPipe::create_with_config(&name, config).unwrap());
+            assert_eq!(pipe.wait_strategy(), strategy);
+            assert_eq!(pipe.config().wait_strategy, strategy);
+
+            // 另一线程稍后发送，读者按策略等到消息
+            let sender = {
+                let pipe = Arc::clone(&pipe);
+                std::thread::spawn(move || {
+                    std::thread::sleep(Duration::from_millis(20));
+                    pipe.send_blocking(Message::init("ping".to_string()), timeout)
+                        .unwrap();
+                })
+            };
+            assert_eq!(pipe.receive_blocking(timeout).unwrap().data, b"ping");
+            sender.join().unwrap();
+
+            // 队列满时写者按策略等待直到超时
+            pipe.hold().unwrap();
+            let start = Instant::now();
+            let err = pipe.hold_timeout(Duration::from_millis(20)).unwrap_err();
+            assert!(err.is::<PipeTimeout>(), "{}: {}", strategy, err);
+            assert!(start.elapsed() >= Duration::from_millis(20));
+        }
+
+        // 连接后改为忙等，异步读取不依赖通知
+        let name = format!("mi7_test_wait_async_{}", std::process::id());
+        let producer =
+            DynCrossProcessPipe::create_with_config(&name, PipeConfig::new(2, 128)).unwrap();
+        let consumer = DynCrossProcessPipe::connect(&name)
+            .unwrap()
+            .with_wait_strategy(WaitStrategy::SpinHot);
+        let fetch = consumer.fetch_async();
+        producer
+            .send_blocking(Message::init("async".to_string()), timeout)
+            .unwrap();
+        let index = tokio::time::timeout(timeout, fetch).await.unwrap().unwrap();
+        assert_eq!(consumer.receive(index).unwrap().data, b"async");
+    }
+
+    #[test]
+    fn test_adaptive_interval_follows_arrivals() {
+        let us = Duration::from_micros;
+        let flowing = |gap, idle| ArrivalStats {
+            gap: Some(us(gap)),
+            idle: us(idle),
+        };
+        // 没有统计时取最长间隔；密集到达时忙等；稀疏时取平均间隔的四分之一；空闲时从平均间隔退避
+        assert_eq!(ArrivalStats::default().poll_interval(), ADAPTIVE_MAX);
+        assert_eq!(flowing(40, 10).poll_interval(), Duration::ZERO);
+        assert_eq!(flowing(400, 100).poll_interval(), us(100));
+        assert_eq!(flowing(400, 10_000).poll_interval(), us(400));
+        assert_eq!(flowing(1, 10_000).poll_interval(), ADAPTIVE_MIN);
+
+        let mut backoff = Backoff::adaptive(flowing(400, 10_000));
+        let sleeps: Vec<_> = (0..3).map(|_| backoff.next_sleep(None)).collect();
+        assert_eq!(sleeps, [us(400), us(800), ADAPTIVE_MAX]);
+
+        // 写者记录到达间隔，指标给出自适应轮询间隔
+        let name = format!("mi7_test_wait_adaptive_{}", std::process::id());
+        let config = PipeConfig::new(8, 128).with_wait_strategy(WaitStrategy::Adaptive);
+        let pipe = DynCrossProcessPipe::create_with_config(&name, config).unwrap();
+        assert_eq!(pipe.metrics().poll_interval, ADAPTIVE_MAX);
+        let timeout = Duration::from_secs(1);
+        for i in 0..4 {
+            pipe.send_blocking(Message::init(i.to_string()), timeout)
+                .unwrap();
+        }
+        let metrics = pipe.metrics();
+        assert!(metrics.arrival_gap > Duration::ZERO);
+        assert!(metrics.poll_interval <= ADAPTIVE_MAX);
+        for _ in 0..4 {
+            pipe.receive_blocking(timeout).unwrap();
+        }
+        assert!(
+            pipe.receive_blocking(Duration::from_millis(5))
+                .unwrap_err()
+                .is::<PipeTimeout>()
+        );
+    }
+}
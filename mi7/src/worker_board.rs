@@ -0,0 +1,409 @@
+//! worker 登记表：共享内存中的 worker 心跳与负载区
+//!
+//! 每个 worker 启动时在登记表中占用一项，写入 PID、worker ID 与专属收件管道名称，
+//! 并定期更新心跳。entry 的调度者读取登记表，把请求派发给在途任务最少的存活
+//! worker：派发时递增该项的在途计数，worker 处理完成后递减并累计处理数量与耗时。
+//! 每个 worker 还登记自己的响应管道（[`response_pipe_name`]），entry 据此发现并读取
+//! 各 worker 的回复。登记表由守护进程创建，entry 与 worker 只连接已存在的登记表。
+
+use crate::locks::Segment;
+use crate::shm_sync;
+
+use crate::error::Result;
+use anyhow::anyhow;
+use std::cell::UnsafeCell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::Duration;
+
+/// 登记表可容纳的 worker 数量
+pub const MAX_WORKERS: usize = 64;
+
+/// worker ID 的最大字节数
+pub const WORKER_ID_LEN: usize = 32;
+
+/// 收件管道与响应管道名称的最大字节数
+pub const INBOX_NAME_LEN: usize = 64;
+
+/// worker 专属响应管道的名称前缀
+pub const RESPONSE_PIPE_PREFIX: &str = "resp_pipe_";
+
+/// 默认的登记表名称
+pub const DEFAULT_BOARD_NAME: &str = "mi7_workers";
+
+/// 超过该时长未更新心跳的 worker 视为失联
+pub const DEFAULT_HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 登记表中的一项，`registered_at` 非 0 表示已发布
+#[repr(C)]
+struct WorkerEntry {
+    pid: AtomicU32,        // 占用该项的进程，0 表示空闲
+    in_flight: AtomicU32,  // 已派发、尚未完成的任务数量
+    generation: AtomicU32, // 每次登记递增，区分同一项的先后占用者
+    _reserved: u32,
+    registered_at: AtomicU64, // 登记时间（Unix 毫秒），0 表示尚未发布
+    last_beat: AtomicU64,     // 最近一次心跳（单调时钟毫秒）
+    processed: AtomicU64,     // 累计完成的任务数量
+    busy_nanos: AtomicU64,    // 累计处理耗时
+    worker_id: UnsafeCell<[u8; WORKER_ID_LEN]>,
+    inbox: UnsafeCell<[u8; INBOX_NAME_LEN]>,
+    response_pipe: UnsafeCell<[u8; INBOX_NAME_LEN]>,
+}
+
+#[repr(C)]
+struct BoardBlock {
+    workers: [WorkerEntry; MAX_WORKERS],
+}
+
+/// 登记表中一个 worker 的快照
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkerInfo {
+    /// 在登记表中的位置
+    pub index: usize,
+    pub pid: u32,
+    pub worker_id: String,
+    /// 专属收件管道名称，为空表示只从共享请求管道取任务
+    pub inbox: String,
+    /// 专属响应管道名称，为空表示回复写入 entry 的共享响应管道
+    pub response_pipe: String,
+    pub in_flight: u32,
+    pub processed: u64,
+    /// 累计处理耗时
+    pub busy: Duration,
+    /// 自登记以来的时长
+    pub uptime: Duration,
+    /// 距上次心跳的时长
+    pub since_beat: Duration,
+    /// 进程存活且心跳未超时
+    pub alive: bool,
+}
+
+impl WorkerInfo {
+    /// 自登记以来平均每秒完成的任务数量
+    pub fn throughput(&self) -> f64 {
+        let secs = self.uptime.as_secs_f64();
+        if secs > 0.0 {
+            self.processed as f64 / secs
+        } else {
+            0.0
+        }
+    }
+}
+
+/// worker 登记表
+pub struct WorkerBoard {
+    segment: Segment<BoardBlock>,
+    heartbeat_timeout: Duration,
+}
+
+unsafe impl Send for WorkerBoard {}
+unsafe impl Sync for WorkerBoard {}
+
+impl WorkerBoard {
+    /// 创建（或清空）登记表，由守护进程持有，Drop 时删除
+    pub fn create(name: &str) -> Result<Self> {
+        Ok(Self {
+            segment: Segment::create(name, |_| {})?,
+            heartbeat_timeout: DEFAULT_HEARTBEAT_TIMEOUT,
+        })
+    }
+
+    /// 连接守护进程创建的登记表
+    pub fn open(name: &str) -> Result<Self> {
+        Ok(Self {
+            segment: Segment::open(name)?,
+            heartbeat_timeout: DEFAULT_HEARTBEAT_TIMEOUT,
+        })
+    }
+
+    /// 设置判定失联的心跳超时
+    pub fn with_heartbeat_timeout(mut self, timeout: Duration) -> Self {
+        self.heartbeat_timeout = timeout;
+        self
+    }
+
+    fn block(&self) -> &BoardBlock {
+        self.segment.get()
+    }
+
+    /// 登记表名称
+    pub fn name(&self) -> &str {
+        self.segment.name()
+    }
+
+    /// 登记当前进程中的 worker，`inbox` 与 `response_pipe` 为其专属收件管道与响应管道名称（均可为空）
+    ///
+    /// 同一 worker ID 的旧记录（例如崩溃前留下的）会被替换。
+    pub fn register(
+        self: &Arc<Self>,
+        worker_id: &str,
+        inbox: &str,
+        response_pipe: &str,
+    ) -> Result<WorkerRegistration> {
+        let id = encode::<WORKER_ID_LEN>(worker_id, "worker ID")?;
+        let inbox_name = encode::<INBOX_NAME_LEN>(inbox, "收件管道名称")?;
+        let response_name = encode::<INBOX_NAME_LEN>(response_pipe, "响应管道名称")?;
+        self.prune();
+        for info in self.workers() {
+            if info.worker_id == worker_id {
+                self.release_entry(info.index, info.pid);
+            }
+        }
+
+        let pid = std::process::id();
+        let (index, entry) = self
+            .block()
+            .workers
+            .iter()
+            .enumerate()
+            .find(|(_, entry)| {
+                entry
+                    .pid
+                    .compare_exchange(0, pid, Ordering::AcqRel, Ordering::Relaxed)
+                    .is_ok()
+            })
+            .ok_or_else(|| anyhow!("worker 登记表 {} 已满", self.name()))?;
+
+        unsafe {
+            *entry.worker_id.get() = id;
+            *entry.inbox.get() = inbox_name;
+            *entry.response_pipe.get() = response_name;
+        }
+        let generation = entry
+            .generation
+            .fetch_add(1, Ordering::AcqRel)
+            .wrapping_add(1);
+        entry.in_flight.store(0, Ordering::Relaxed);
+        entry.processed.store(0, Ordering::Relaxed);
+        entry.busy_nanos.store(0, Ordering::Relaxed);
+        entry
+            .last_beat
+            .store(shm_sync::monotonic_millis(), Ordering::Relaxed);
+        entry.registered_at.store(unix_millis(), Ordering::Release);
+
+        Ok(WorkerRegistration {
+            board: Arc::clone(self),
+            index,
+            generation,
+        })
+    }
+
+    /// 所有已登记的 worker
+    pub fn workers(&self) -> Vec<WorkerInfo> {
+        let now = shm_sync::monotonic_millis();
+        let unix_now = unix_millis();
+        self.block()
+            .workers
+            .iter()
+            .enumerate()
+            .filter_map(|(index, entry)| {
+                let registered_at = entry.registered_at.load(Ordering::Acquire);
+                let pid = entry.pid.load(Ordering::Acquire);
+                if registered_at == 0 || pid == 0 {
+                    return None;
+                }
+                let since_beat = Duration::from_millis(
+                    now.saturating_sub(entry.last_beat.load(Ordering::Acquire)),
+                );
+                Some(WorkerInfo {
+                    index,
+                    pid,
+                    worker_id: decode(unsafe { &*entry.worker_id.get() }),
+                    inbox: decode(unsafe { &*entry.inbox.get() }),
+                    response_pipe: decode(unsafe { &*entry.response_pipe.get() }),
+                    in_flight: entry.in_flight.load(Ordering::Acquire),
+                    processed: entry.processed.load(Ordering::Acquire),
+                    busy: Duration::from_nanos(entry.busy_nanos.load(Ordering::Acquire)),
+                    uptime: Duration::from_millis(unix_now.saturating_sub(registered_at)),
+                    since_beat,
+                    alive: since_beat <= self.heartbeat_timeout && shm_sync::process_alive(pid),
+                })
+            })
+            .collect()
+    }
+
+    /// 在途任务最少的存活 worker，在途数量相同时取累计处理较少者
+    pub fn least_loaded(&self) -> Option<WorkerInfo> {
+        self.workers()
+            .into_iter()
+            .filter(|worker| worker.alive)
+            .min_by_key(|worker| (worker.in_flight, worker.processed))
+    }
+
+    /// 记录一个任务已派发给 `worker`，该项已换成其他进程时返回 `false`
+    pub fn assign(&self, worker: &WorkerInfo) -> bool {
+        let entry = &self.block().workers[worker.index];
+        if entry.pid.load(Ordering::Acquire) != worker.pid {
+            return false;
+        }
+        entry.in_flight.fetch_add(1, Ordering::AcqRel);
+        true
+    }
+
+    /// 撤销 [`assign`](Self::assign) 记录的派发（任务未能写入收件管道）
+    pub fn unassign(&self, worker: &WorkerInfo) {
+        let entry = &self.block().workers[worker.index];
+        if entry.pid.load(Ordering::Acquire) == worker.pid {
+            decrement(&entry.in_flight);
+        }
+    }
+
+    /// 删除所属进程已退出的记录，返回删除的数量
+    pub fn prune(&self) -> usize {
+        let mut removed = 0;
+        for (index, entry) in self.block().workers.iter().enumerate() {
+            let pid = entry.pid.load(Ordering::Acquire);
+            if pid != 0 && !shm_sync::process_alive(pid) && self.release_entry(index, pid) {
+                removed += 1;
+            }
+        }
+        removed
+    }
+
+    /// 删除 `pid` 的全部记录（例如心跳区判定其已失联），返回删除的数量
+    pub fn evict(&self, pid: u32) -> usize {
+        self.workers()
+            .into_iter()
+            .filter(|worker| worker.pid == pid && self.release_entry(worker.index, pid))
+            .count()
+    }
+
+    fn release_entry(&self, index: usize, pid: u32) -> bool {
+        let entry = &self.block().workers[index];
+        entry.registered_at.store(0, Ordering::Release);
+        entry
+            .pid
+            .compare_exchange(pid, 0, Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok()
+    }
+}
+
+/// worker 在登记表中的记录，Drop 时注销
+pub struct WorkerRegistration {
+    board: Arc<WorkerBoard>,
+    index: usize,
+    generation: u32,
+}
+
+impl WorkerRegistration {
+    fn entry(&self) -> &WorkerEntry {
+        &self.board.block().workers[self.index]
+    }
+
+    /// 更新心跳
+    pub fn beat(&self) {
+        self.entry()
+            .last_beat
+            .store(shm_sync::monotonic_millis(), Ordering::Release);
+    }
+
+    /// 开始处理一个未经派发（取自共享请求管道）的任务
+    pub fn start(&self) {
+        self.entry().in_flight.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// 完成一个任务：在途数量减一，累计处理数量与耗时
+    pub fn complete(&self, elapsed: Duration) {
+        let entry = self.entry();
+        decrement(&entry.in_flight);
+        entry.processed.fetch_add(1, Ordering::AcqRel);
+        entry
+            .busy_nanos
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::AcqRel);
+    }
+
+    /// 放弃一个任务（读取失败或已过期），只减少在途数量
+    pub fn abandon(&self) {
+        decrement(&self.entry().in_flight);
+    }
+}
+
+impl Drop for WorkerRegistration {
+    fn drop(&mut self) {
+        // 该项已被同名 worker 的新登记替换时不再注销
+        if self.entry().generation.load(Ordering::Acquire) == self.generation {
+            self.board.release_entry(self.index, std::process::id());
+        }
+    }
+}
+
+/// worker 专属响应管道的名称：`resp_pipe_{worker_id}`
+pub fn response_pipe_name(worker_id: &str) -> String {
+    format!("{}{}", RESPONSE_PIPE_PREFIX, worker_id)
+}
+
+/// 计数减一，不低于 0
+fn decrement(counter: &AtomicU32) {
+    let _ = counter.fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| n.checked_sub(1));
+}
+
+fn unix_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+        .max(1)
+}
+
+fn encode<const N: usize>(value: &str, what: &str) -> Result<[u8; N]> {
+    if value.len() > N {
+        return Err(anyhow!("{} 过长（最多 {} 字节）: {}", what, N, value).into());
+    }
+    let mut bytes = [0u8; N];
+    bytes[..value.len()].copy_from_slice(value.as_bytes());
+    Ok(bytes)
+}
+
+fn decode(bytes: &[u8]) -> String {
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..len]).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_least_loaded_worker() {
+        let name = format!("mi7_test_workers_{}", std::process::id());
+        assert!(WorkerBoard::open(&name).is_err(), "连接方不创建登记表");
+        let board = Arc::new(WorkerBoard::create(&name).unwrap());
+        let peer = Arc::new(WorkerBoard::open(&name).unwrap());
+
+        let a = peer.register("a", "inbox_a", "").unwrap();
+        let b = peer.register("b", "inbox_b", "").unwrap();
+        assert_eq!(board.workers().len(), 2);
+
+        // 派发给 a 后，b 成为负载最低者
+        let first = board.least_loaded().unwrap();
+        assert!(board.assign(&first));
+        let second = board.least_loaded().unwrap();
+        assert_ne!(first.worker_id, second.worker_id);
+        assert_eq!(first.inbox, format!("inbox_{}", first.worker_id));
+
+        let (done, _) = if first.worker_id == "a" {
+            (&a, &b)
+        } else {
+            (&b, &a)
+        };
+        done.complete(Duration::from_millis(5));
+        let info = board
+            .workers()
+            .into_iter()
+            .find(|w| w.worker_id == first.worker_id)
+            .unwrap();
+        assert_eq!((info.in_flight, info.processed), (0, 1));
+        assert_eq!(info.busy, Duration::from_millis(5));
+
+        // 同名 worker 重新登记时替换旧记录；注销后不再列出
+        let a2 = peer.register("a", "inbox_a2", "").unwrap();
+        drop(a);
+        let inboxes: Vec<String> = board.workers().into_iter().map(|w| w.inbox).collect();
+        assert_eq!(inboxes.len(), 2);
+        assert!(inboxes.contains(&"inbox_a2".to_string()));
+        drop(a2);
+        drop(b);
+        assert!(board.workers().is_empty());
+        assert!(board.least_loaded().is_none());
+    }
+}
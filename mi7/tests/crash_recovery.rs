@@ -0,0 +1,150 @@
+//! 进程崩溃恢复测试
+//!
+//! fork 出的子进程抢占槽位并持有管道的读写锁后被 SIGKILL，父进程必须能够：
+//!
+//! - 通过 `EOWNERDEAD` + `pthread_mutex_consistent` 取回被遗弃的锁，收发不被永久阻塞
+//! - 用 `reclaim_stuck` 回收子进程遗留的 WRITING / READING 槽位
+//! - 之后正常收发，所有槽位回到 EMPTY
+//!
+//! 这些行为是 crate 对进程崩溃的保证，修改锁或槽位状态机时必须保持本测试通过。
+//!
+//! 多线程进程中 fork 的子进程只保留调用线程，其他线程持有的锁（包括分配器的锁）永远不会释放，
+//! 因此本文件不使用 libtest（`harness = false`），由 `main` 在单线程中依次执行各个用例。
+
+use mi7::Message;
+use mi7::pipe::{DynCrossProcessPipe, PipeConfig};
+use mi7::shared_slot::DynSharedSlotPipe;
+use mi7::shm_sync::ShmMutex;
+use std::time::Duration;
+
+/// 父进程收发的超时：锁没有恢复时收发超时失败，而不是让测试挂起
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// 父进程等待子进程通知的超时
+const CHILD_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 要让子进程持有的锁
+#[derive(Clone, Copy)]
+enum Held {
+    Write,
+    Read,
+}
+
+fn unique_name(tag: &str) -> String {
+    format!("mi7_test_crash_{}_{}", tag, std::process::id())
+}
+
+/// fork 子进程执行 `claim` 抢占槽位，随后持有 `held` 锁并通知父进程，父进程将其 SIGKILL
+///
+/// 子进程不返回到测试框架：`claim` 失败或 panic 时以非零状态退出，父进程收不到通知即失败。
+fn crash_while_holding(name: &str, held: Held, claim: fn(&mut DynSharedSlotPipe) -> bool) {
+    let mut fds = [0; 2];
+    assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+    let (read_fd, write_fd) = (fds[0], fds[1]);
+
+    let pid = unsafe { libc::fork() };
+    assert!(pid >= 0, "fork 失败");
+    if pid == 0 {
+        let claimed = std::panic::catch_unwind(|| unsafe {
+            let mut pipe = DynSharedSlotPipe::connect(name).unwrap();
+            if !claim(&mut pipe) {
+                return false;
+            }
+            let header = &mut *pipe.as_ptr();
+            let mutex: &mut ShmMutex = match held {
+                Held::Write => &mut header.write_mutex,
+                Held::Read => &mut header.read_mutex,
+            };
+            mutex.lock()
+        });
+        unsafe {
+            if claimed.unwrap_or(false) {
+                libc::write(write_fd, [1u8].as_ptr().cast(), 1);
+                loop {
+                    libc::pause();
+                }
+            }
+            libc::_exit(1);
+        }
+    }
+
+    unsafe {
+        libc::close(write_fd);
+        // 子进程卡住时不能无限阻塞在 read 上
+        let mut poll_fd = libc::pollfd {
+            fd: read_fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let mut ready = 0u8;
+        let n = if libc::poll(&mut poll_fd, 1, CHILD_TIMEOUT.as_millis() as libc::c_int) > 0 {
+            libc::read(read_fd, (&mut ready as *mut u8).cast(), 1)
+        } else {
+            0
+        };
+        libc::close(read_fd);
+        libc::kill(pid, libc::SIGKILL);
+        let mut status = 0;
+        libc::waitpid(pid, &mut status, 0);
+        assert_eq!(n, 1, "子进程未能抢占槽位并持有锁");
+        assert!(libc::WIFSIGNALED(status));
+    }
+}
+
+fn writer_killed_holding_write_mutex() {
+    let name = unique_name("writer");
+    let pipe = DynCrossProcessPipe::create_with_config(&name, PipeConfig::new(4, 128)).unwrap();
+
+    crash_while_holding(&name, Held::Write, |pipe| unsafe { pipe.hold().is_some() });
+    assert_eq!(pipe.status().writing_count, 1);
+
+    // 被遗弃的写锁可以取回，遗留的 WRITING 槽位被回收
+    assert_eq!(pipe.reclaim_stuck(Duration::ZERO), 1);
+    pipe.send_blocking(Message::init("after crash".to_string()), TIMEOUT)
+        .unwrap();
+    let message = pipe.receive_blocking(TIMEOUT).unwrap();
+    assert_eq!(message.data, b"after crash");
+
+    let status = pipe.status();
+    assert_eq!(status.empty_count, 4);
+    assert_eq!(status.reclaimed_count, 1);
+}
+
+fn reader_killed_holding_read_mutex() {
+    let name = unique_name("reader");
+    let pipe = DynCrossProcessPipe::create_with_config(&name, PipeConfig::new(4, 128)).unwrap();
+    pipe.send_blocking(Message::init("lost".to_string()), TIMEOUT)
+        .unwrap();
+
+    crash_while_holding(&name, Held::Read, |pipe| unsafe {
+        pipe.fetch_timeout(Some(Duration::ZERO)).is_some()
+    });
+    assert_eq!(pipe.status().reading_count, 1);
+
+    // 被遗弃的读锁可以取回，遗留的 READING 槽位被回收后继续收发
+    assert_eq!(pipe.reclaim_stuck(Duration::ZERO), 1);
+    pipe.send_blocking(Message::init("next".to_string()), TIMEOUT)
+        .unwrap();
+    let message = pipe.receive_blocking(TIMEOUT).unwrap();
+    assert_eq!(message.data, b"next");
+    assert_eq!(pipe.status().empty_count, 4);
+}
+
+fn main() {
+    let cases: [(&str, fn()); 2] = [
+        (
+            "writer_killed_holding_write_mutex",
+            writer_killed_holding_write_mutex,
+        ),
+        (
+            "reader_killed_holding_read_mutex",
+            reader_killed_holding_read_mutex,
+        ),
+    ];
+    println!("\nrunning {} tests", cases.len());
+    for (name, case) in cases {
+        case();
+        println!("test {} ... ok", name);
+    }
+    println!("\ntest result: ok. {} passed\n", cases.len());
+}
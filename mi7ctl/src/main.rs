@@ -0,0 +1,353 @@
+//! mi7ctl：按名称检查与清理运行中的共享内存队列和寄存箱
+//!
+//! ```bash
+//! mi7ctl list [前缀]            # 列出 /dev/shm 中的共享内存段
+//! mi7ctl status <名称>          # 队列 / 寄存箱的状态与被持有的槽位
+//! mi7ctl peek <名称> [n]        # 显示最早的 n 条待处理消息及其内容，不消费
+//! mi7ctl drain <名称>           # 取出并丢弃所有待处理消息
+//! mi7ctl reclaim <名称> [毫秒]  # 回收被持有超过该时长的槽位（寄存箱：已退出进程持有的 box）
+//! mi7ctl unlink <名称>          # 删除共享内存段的名称
+//! ```
+//!
+//! 连接受保护的管道时使用与其他进程相同的 `[access]`、`[encryption]` 与 `[authentication]`
+//! 配置，`--set section.key=value` 可覆盖配置项。
+
+use anyhow::{Result, anyhow};
+use mi7::pipe::{DynCrossProcessPipe, PipeTimeout};
+use mi7::shared_box::SharedMemoryMailbox;
+use mi7::shared_slot::{SlotState, read_layout};
+use mi7::{MemoryUsage, SharedMemoryRegistry, config};
+use std::time::Duration;
+
+/// `reclaim` 未指定超时时的默认值
+const DEFAULT_RECLAIM_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// `peek` 未指定数量时的默认值
+const DEFAULT_PEEK_COUNT: usize = 10;
+
+/// `peek` 显示的消息数据最多字节数
+const PREVIEW_BYTES: usize = 64;
+
+const USAGE: &str = "用法: mi7ctl <list [前缀] | status <名称> | peek <名称> [n] | drain <名称> | reclaim <名称> [毫秒] | unlink <名称>> [--set section.key=value]...";
+
+/// 按名称打开的共享内存段
+enum Segment {
+    Pipe(DynCrossProcessPipe),
+    Mailbox(SharedMemoryMailbox),
+}
+
+impl Segment {
+    /// 按头部识别管道或寄存箱并连接
+    fn open(name: &str) -> Result<Self> {
+        if read_layout(name).is_ok() {
+            return Ok(Segment::Pipe(DynCrossProcessPipe::connect(name)?));
+        }
+        SharedMemoryMailbox::connect(name)
+            .map(Segment::Mailbox)
+            .map_err(|e| anyhow!("{} 不是管道也不是寄存箱: {}", name, e))
+    }
+}
+
+fn main() -> Result<()> {
+    // 去掉由配置系统处理的 --set 覆盖项
+    let mut args = Vec::new();
+    let mut raw = std::env::args().skip(1);
+    while let Some(arg) = raw.next() {
+        match arg.as_str() {
+            "--set" => {
+                raw.next();
+            }
+            other if other.starts_with("--set=") => {}
+            _ => args.push(arg),
+        }
+    }
+
+    // 没有配置文件时只能连接未加密、不认证的管道
+    match config::init_config() {
+        Ok(()) => {
+            mi7::access::init_from_config()?;
+            mi7::encryption::init_from_config()?;
+            mi7::auth::init_from_config()?;
+        }
+        Err(e) => eprintln!("加载配置失败，按默认设置连接: {}", e),
+    }
+
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["list"] => list(""),
+        ["list", prefix] => list(prefix),
+        ["status", name] => status(name),
+        ["peek", name] => peek(name, DEFAULT_PEEK_COUNT),
+        ["peek", name, count] => peek(name, count.parse()?),
+        ["drain", name] => drain(name),
+        ["reclaim", name] => reclaim(name, DEFAULT_RECLAIM_TIMEOUT),
+        ["reclaim", name, millis] => reclaim(name, Duration::from_millis(millis.parse()?)),
+        ["unlink", name] => unlink(name),
+        _ => Err(anyhow!(USAGE)),
+    }
+}
+
+fn list(prefix: &str) -> Result<()> {
+    println!("{:<40} {:<8} {:<8} 详情", "名称", "类型", "映射中");
+    for (name, mapped) in SharedMemoryRegistry::list(prefix)? {
+        let (kind, detail) = match read_layout(&name) {
+            Ok(layout) => (
+                "pipe",
+                format!(
+                    "容量 {}，槽位 {} 字节，布局版本 {}",
+                    layout.capacity, layout.slot_size, layout.version
+                ),
+            ),
+            Err(_) => match SharedMemoryMailbox::connect(&name) {
+                Ok(mailbox) => {
+                    let stats = mailbox.get_stats();
+                    (
+                        "mailbox",
+                        format!(
+                            "{} 个 box，{} 个待读取",
+                            stats.total_count, stats.full_count
+                        ),
+                    )
+                }
+                Err(_) => ("other", String::new()),
+            },
+        };
+        let mapped = if mapped { "是" } else { "否" };
+        println!("{:<40} {:<8} {:<8} {}", name, kind, mapped, detail);
+    }
+    Ok(())
+}
+
+fn status(name: &str) -> Result<()> {
+    match Segment::open(name)? {
+        Segment::Pipe(pipe) => {
+            let status = pipe.status();
+            let metrics = pipe.metrics();
+            println!("管道 {}", name);
+            println!(
+                "  容量 {}，槽位 {} 字节，模式 {:?}，编解码 {}，校验 {}，加密 {}",
+                status.capacity,
+                status.slot_size,
+                status.mode,
+                status.codec,
+                status.integrity,
+                if pipe.is_encrypted() { "是" } else { "否" }
+            );
+            println!(
+                "  READY {}  INPROGRESS {}  READING {}  WRITING {}  EMPTY {}{}",
+                status.ready_count,
+                status.in_progress_count,
+                status.reading_count,
+                status.writing_count,
+                status.empty_count,
+                if status.backpressured {
+                    "  (背压中)"
+                } else {
+                    ""
+                }
+            );
+            println!(
+                "  写入 {}，读取 {}，回收 {}，过期 {}，重新投递 {}，死信 {}，覆盖丢弃 {}，锁竞争 {}",
+                status.sent_count,
+                status.received_count,
+                status.reclaimed_count,
+                status.expired_count,
+                status.redelivered_count,
+                status.dead_lettered_count,
+                status.dropped_count,
+                status.lock_contended_count
+            );
+            println!(
+                "  延迟 p50 {:?}，p99 {:?}（{} 条）",
+                metrics.p50, metrics.p99, metrics.count
+            );
+            println!(
+                "  到达间隔 {:?}，自适应轮询间隔 {:?}",
+                metrics.arrival_gap, metrics.poll_interval
+            );
+            // 本工具自身也登记为连接方
+            let pids: Vec<u32> = pipe
+                .attached_processes()
+                .into_iter()
+                .filter(|pid| *pid != std::process::id())
+                .collect();
+            println!("  连接进程: {:?}", pids);
+            print_memory(pipe.memory_usage());
+
+            let held: Vec<_> = pipe
+                .slots()
+                .into_iter()
+                .filter(|slot| slot.leased_for.is_some())
+                .collect();
+            if !held.is_empty() {
+                println!("  被持有的槽位:");
+                for slot in held {
+                    println!(
+                        "    #{:<5} {:<10} request_id={} 大小 {} 投递失败 {} 已持有 {:?}",
+                        slot.index,
+                        if slot.abandoned {
+                            "ABANDONED"
+                        } else {
+                            state_name(slot.state)
+                        },
+                        slot.request_id,
+                        slot.data_size,
+                        slot.delivery_attempts,
+                        slot.leased_for.unwrap_or_default()
+                    );
+                }
+            }
+        }
+        Segment::Mailbox(mailbox) => {
+            let stats = mailbox.get_stats();
+            println!("寄存箱 {}", name);
+            println!(
+                "  box {}：空 {}，写入中 {}，待读取 {}，读取中 {}",
+                stats.total_count,
+                stats.empty_count,
+                stats.writing_count,
+                stats.full_count,
+                stats.reading_count
+            );
+            let mut sizes: Vec<_> = stats.size_counts.into_iter().collect();
+            sizes.sort_by_key(|(size, _)| size.bytes());
+            for (size, count) in sizes {
+                println!("  {:?}: {} 个", size, count);
+            }
+            print_memory(mailbox.memory_usage());
+        }
+    }
+    Ok(())
+}
+
+/// 共享内存段的映射与驻留大小
+fn print_memory(usage: MemoryUsage) {
+    println!(
+        "  内存: 映射 {} KB，驻留 {} KB",
+        usage.mapped / 1024,
+        usage.resident / 1024
+    );
+}
+
+fn peek(name: &str, count: usize) -> Result<()> {
+    match Segment::open(name)? {
+        Segment::Pipe(pipe) => {
+            let mut ready: Vec<_> = pipe
+                .slots()
+                .into_iter()
+                .filter(|slot| slot.is_pending())
+                .collect();
+            ready.sort_by_key(|slot| slot.request_id);
+            println!("管道 {}：{} 条待处理消息", name, ready.len());
+            for slot in ready.into_iter().take(count) {
+                println!(
+                    "  #{:<5} request_id={} 大小 {} 投递失败 {}",
+                    slot.index, slot.request_id, slot.data_size, slot.delivery_attempts
+                );
+                match pipe.peek_at(slot.index) {
+                    Ok(Some((_, message))) => println!(
+                        "         flag={} 数据 {} 字节: {}",
+                        message.flag,
+                        message.data.len(),
+                        preview(&message.data)
+                    ),
+                    // 列出后已被消费
+                    Ok(None) => println!("         已被取走"),
+                    Err(e) => println!("         读取失败: {}", e),
+                }
+            }
+        }
+        Segment::Mailbox(mailbox) => {
+            let full = mailbox.get_full_boxes();
+            println!("寄存箱 {}：{} 个待读取的 box", name, full.len());
+            for box_id in full.into_iter().take(count) {
+                println!("  box {}", box_id);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn drain(name: &str) -> Result<()> {
+    let (drained, failed) = match Segment::open(name)? {
+        Segment::Pipe(pipe) => {
+            let (mut drained, mut failed) = (0, 0);
+            loop {
+                match pipe.receive_blocking(Duration::ZERO) {
+                    Ok(_) => drained += 1,
+                    Err(e) if e.is::<PipeTimeout>() => break,
+                    // 校验失败的消息同样被释放
+                    Err(_) => failed += 1,
+                }
+            }
+            (drained, failed)
+        }
+        Segment::Mailbox(mailbox) => {
+            let (mut drained, mut failed) = (0, 0);
+            for box_id in mailbox.get_full_boxes() {
+                match mailbox.take_data(box_id) {
+                    Ok(_) => drained += 1,
+                    Err(_) => failed += 1,
+                }
+            }
+            (drained, failed)
+        }
+    };
+    println!(
+        "{}：丢弃 {} 条，其中 {} 条校验失败",
+        name,
+        drained + failed,
+        failed
+    );
+    Ok(())
+}
+
+fn reclaim(name: &str, timeout: Duration) -> Result<()> {
+    let reclaimed = match Segment::open(name)? {
+        Segment::Pipe(pipe) => pipe.reclaim_stuck(timeout),
+        Segment::Mailbox(mailbox) => mailbox.reclaim_orphans(),
+    };
+    println!("{}：回收 {} 个槽位", name, reclaimed);
+    Ok(())
+}
+
+fn unlink(name: &str) -> Result<()> {
+    let mapped = SharedMemoryRegistry::list(name)?
+        .into_iter()
+        .any(|(segment, mapped)| segment == name.trim_start_matches('/') && mapped);
+    SharedMemoryRegistry::unlink(name)?;
+    if mapped {
+        println!(
+            "已删除 {}，仍在映射的进程可继续使用，全部解除映射后内存才会释放",
+            name
+        );
+    } else {
+        println!("已删除 {}", name);
+    }
+    Ok(())
+}
+
+/// 消息数据的可读预览，过长时截断
+fn preview(data: &[u8]) -> String {
+    let text = String::from_utf8_lossy(&data[..data.len().min(PREVIEW_BYTES)]);
+    let text: String = text
+        .chars()
+        .map(|c| if c.is_control() { '.' } else { c })
+        .collect();
+    if data.len() > PREVIEW_BYTES {
+        format!("{}...", text)
+    } else {
+        text
+    }
+}
+
+fn state_name(state: Option<SlotState>) -> &'static str {
+    match state {
+        Some(SlotState::EMPTY) => "EMPTY",
+        Some(SlotState::WRITING) => "WRITING",
+        Some(SlotState::INPROGRESS) => "INPROGRESS",
+        Some(SlotState::READING) => "READING",
+        Some(SlotState::READY) => "READY",
+        None => "UNKNOWN",
+    }
+}
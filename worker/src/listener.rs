@@ -1,10 +1,7 @@
-use anyhow::Result;
 use async_channel::Sender;
 use mi7::pipe::DynamicPipe;
-use mi7::shared_slot::SlotState;
 use std::sync::Arc;
-use tokio::time::{Duration, sleep};
-use tracing::{debug, error, info};
+use tracing::info;
 
 pub struct Listener {
     worker_id: String,
@@ -53,12 +50,14 @@ impl Listener {
                 Ok(Ok(())) => {
                     // 发送成功
                     // info!("Listener 发送任务 {} ", slot_index);
+                    processed_count += 1;
                     // 主动让出 CPU 时间，让消费者有机会处理消息
                     tokio::task::yield_now().await;
                 }
                 Ok(Err(e)) => {
-                    // 通道发送错误
+                    // 通道已关闭，退出
                     eprintln!("Failed to send slot index: {:?}", e);
+                    break;
                 }
                 Err(_) => {
                     // 超时
@@ -1,17 +1,20 @@
+// listener / operator / router 为拆分式消费流程保留，当前由 Interface 统一处理
+#[allow(dead_code)]
 mod listener;
+#[allow(dead_code)]
 mod operator;
 mod router;
 
 use anyhow::Result;
-use async_channel::bounded;
 use mi7::config;
+use mi7::cluster::{self, ProcessRole};
+use mi7::ShutdownCoordinator;
 use mi7::interface::Interface;
-use mi7::pipe::PipeFactory;
 use std::env;
 use std::process;
-use std::sync::{Arc, Mutex};
-use std::thread::sleep;
-use tracing::{error, info};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -19,30 +22,99 @@ async fn main() -> Result<()> {
     // 初始化配置系统
     config::init_config()?;
 
-    // 获取worker ID（从命令行参数或进程ID）
-    let worker_id = env::args()
-        .nth(1)
+    // 获取worker ID（从命令行参数或进程ID），忽略 --set 配置覆盖项
+    let worker_id = config::positional_args(env::args().skip(1))
+        .into_iter()
+        .next()
         .unwrap_or_else(|| process::id().to_string());
 
     // 使用新的通用配置读取方式获取配置信息
-    let interface_name = config::string("worker", "interface_name");
-    let interface_type = config::string("worker", "interface_type");
     let log_prefix = config::string("worker", "log_prefix");
     let _log_level = config::string("worker", "log_level");
 
     // 初始化安全的多进程日志系统 - 使用配置中的日志前缀
-    mi7::logging::init_safe_multiprocess_default_logging(&log_prefix)?;
+    // 启用日志汇聚时写入守护进程的日志环
+    if config::bool_or("logging", "aggregate", false) {
+        mi7::logging::init_aggregated_logging(
+            mi7::logging::LogConfig::new(log_prefix.as_str()),
+            &config::string_or("logging", "ring_name", mi7::log_ring::DEFAULT_RING_NAME),
+        )?;
+    } else {
+        mi7::logging::init_safe_multiprocess_default_logging(&log_prefix)?;
+    }
 
-    let interface = match Interface::new(version) {
+    // 按 [access] 设置共享内存的创建权限与连接握手策略
+    mi7::access::init_from_config()?;
+    // 按 [encryption] 加载负载密钥，此后创建或连接的共享内存管道加密读写
+    mi7::encryption::init_from_config()?;
+    // 按 [authentication] 加载消息认证密钥，此后创建或连接的共享内存管道校验每条消息
+    mi7::auth::init_from_config()?;
+    // 按 [wait] 设置队列空或满时的等待方式（休眠或轮询）
+    mi7::wait::init_from_config()?;
+    // 按 [memory] 锁定或预读共享内存段的页面
+    mi7::memory::init_from_config()?;
+
+    let mut interface = match Interface::new(version) {
         Ok(interface) => interface,
         Err(e) => {
             println!("失败：{}", e);
-            return Err(e);
+            return Err(e.into());
         },
     };
-    interface.load(3);
+    // 登记到 worker 登记表并创建专属收件管道与响应管道，失败时只使用共享管道
+    if let Err(e) = interface.register(&worker_id) {
+        warn!("Worker {} 登记失败，仅使用共享管道: {:?}", worker_id, e);
+    }
+    let router = Arc::new(router::build(worker_id.clone()));
+    interface.load(3, Arc::new(Box::new(Arc::clone(&router))))?;
+
+    // 加入进程心跳区，守护进程据此判断存活
+    let heartbeat_handle = cluster::from_config(false)
+        .and_then(|view| cluster::start_heartbeat(&Arc::new(view), ProcessRole::Worker, &worker_id))
+        .inspect_err(|e| warn!("Worker {} 加入进程心跳区失败: {:?}", worker_id, e))
+        .ok();
+
+    // 连接停止协调控制块，守护进程据此通知停止
+    let coordinator = ShutdownCoordinator::open(&config::string_or(
+        "shutdown",
+        "control_name",
+        "mi7_control",
+    ))?;
+
+    interface.start().await?;
+
+    // 等待停止信号：本地 Ctrl+C、守护进程的停止请求或停止控制消息
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => info!("Worker {} 收到 Ctrl+C", worker_id),
+        _ = coordinator.wait_for_shutdown() => info!("Worker {} 收到守护进程的停止请求", worker_id),
+        _ = interface.stopped() => info!("Worker {} 收到停止控制消息", worker_id),
+    }
 
-    interface.start().await;
+    // 停止获取新任务，处理完在途任务后确认
+    let timeout = Duration::from_secs(config::int_or("shutdown", "timeout_seconds", 10).max(1) as u64);
+    if interface.drain(timeout).await {
+        info!("Worker {} 在途任务已处理完成", worker_id);
+    } else {
+        warn!(
+            "Worker {} 等待在途任务超时，仍有 {} 个任务未完成",
+            worker_id,
+            interface.in_flight()
+        );
+    }
+    for metrics in router.metrics() {
+        info!(
+            "Worker {} flag={:?} 处理 {} 条，失败 {} 条，平均耗时 {:?}",
+            worker_id,
+            metrics.flag,
+            metrics.handled,
+            metrics.failed,
+            metrics.mean()
+        );
+    }
+    if let Some(handle) = heartbeat_handle {
+        handle.abort();
+    }
+    coordinator.acknowledge();
 
     info!("Worker {} 主进程退出", worker_id);
 
@@ -3,7 +3,7 @@
  */
 use async_channel::Receiver;
 use mi7::pipe::DynamicPipe;
-use mi7::shared_slot::SlotState;
+use mi7::tracing_ipc;
 use std::sync::Arc;
 use tracing::{error, info};
 
@@ -29,11 +29,6 @@ impl Operator {
                         std::time::SystemTime::now()
                     );
 
-                    if let Err(_) = self.pipe.set_slot_state(slot_index, SlotState::INPROGRESS) {
-                        error!("Listener {} 设置槽位状态失败", slot_index);
-                        continue;
-                    }
-
                     // // 接收消息
                     let message = match self.pipe.receive(slot_index) {
                         Ok(msg) => msg,
@@ -43,6 +38,7 @@ impl Operator {
                             continue;
                         }
                     };
+                    let _span = tracing_ipc::consumer_span(&message).entered();
                     info!(
                         "Listener {} 收到任务 flag={}: {}",
                         slot_index,
@@ -1,43 +1,84 @@
-use crate::listener::Listener;
-use anyhow::Result;
-use mi7::pipe::DynamicPipe;
-use mi7::shared_slot::SlotState;
+use mi7::Result;
+use mi7::command::{FLAG_HTTP, FLAG_MQTT, FLAG_RAW, FLAG_WEBSOCKET};
+use mi7::{Command, Message, RawTransport, Response, Router};
 use std::sync::Arc;
-use tokio::time::{Duration, sleep};
-use tracing::{debug, error, info};
+use tracing::{info, warn};
 
-pub struct Router {
-    worker_id: String,
-    pipe: Arc<Box<dyn DynamicPipe>>,
+/// 创建请求路由，按命令类型的消息标志分派 entry 转发的请求
+///
+/// 旧版 entry 发送的命令不带类型标志（flag 为 0），由兜底处理函数按命令内容处理。
+pub fn build(worker_id: String) -> Router {
+    let worker_id: Arc<str> = worker_id.into();
+    let handler = || {
+        let worker_id = Arc::clone(&worker_id);
+        move |message: Message| respond(&worker_id, message)
+    };
+    Router::new()
+        .on(FLAG_HTTP, handler())
+        .on(FLAG_WEBSOCKET, handler())
+        .on(FLAG_RAW, handler())
+        .on(FLAG_MQTT, handler())
+        .fallback(handler())
 }
 
-impl Router {
-    pub fn new(worker_id: String, pipe: Arc<Box<dyn DynamicPipe>>) -> Router {
-        Self { worker_id, pipe }
-    }
+/// 解码命令并生成响应
+fn respond(worker_id: &str, message: Message) -> Result<Message> {
+    info!(
+        "Worker {} 处理任务 flag={}, 数据大小: {} bytes",
+        worker_id,
+        message.flag,
+        message.data.len()
+    );
 
-    pub async fn run(&self) -> Result<()> {
-        // 设置槽位状态为处理中
-        // if let Err(_) = self.pipe.set_slot_state(slot_index, SlotState::INPROGRESS) {
-        //     error!("Listener {} 设置槽位状态失败", self.worker_id);
-        //     continue;
-        // }
-        //
-        // // 接收消息
-        // let message = match self.pipe.receive(slot_index) {
-        //     Ok(msg) => msg,
-        //     Err(_) => {
-        //         error!("Listener {} 读取消息失败", self.worker_id);
-        //
-        //         continue;
-        //     }
-        // };
-        // info!(
-        //         "Listener {} 收到任务 flag={}: {}",
-        //         self.worker_id,
-        //         message.flag,
-        //         String::from_utf8_lossy(&message.data)
-        //     );
-        Ok(())
-    }
+    // 无法解码的命令（如 entry 使用更新的协议版本）明确回复错误，而不是按错误布局处理
+    let response = match Command::decode(&message.data) {
+        Ok(command) => Response::Ok {
+            id: command.id(),
+            body: route(worker_id, &command).to_string().into_bytes(),
+        },
+        Err(e) => {
+            warn!("Worker {} 无法解码命令: {}", worker_id, e);
+            Response::Error {
+                id: 0,
+                code: 400,
+                message: e.to_string(),
+            }
+        }
+    };
+
+    let mut reply = Message::new(message.flag, String::new());
+    reply.data = response.encode()?;
+    Ok(reply)
+}
+
+/// 处理一条命令，返回 JSON 格式的业务数据
+fn route(worker_id: &str, command: &Command) -> serde_json::Value {
+    let (kind, detail) = match command {
+        Command::HttpRequest { path, method, .. } => (
+            "http",
+            serde_json::json!({ "method": method, "path": path }),
+        ),
+        Command::WsMessage { connection, .. } => {
+            ("websocket", serde_json::json!({ "connection": connection }))
+        }
+        Command::RawRequest {
+            transport, payload, ..
+        } => (
+            match transport {
+                RawTransport::Tcp => "tcp",
+                RawTransport::Udp => "udp",
+            },
+            serde_json::json!({ "size": payload.len() }),
+        ),
+        Command::MqttPublish { topic, .. } => ("mqtt", serde_json::json!({ "topic": topic })),
+    };
+    serde_json::json!({
+        "success": true,
+        "message": "请求已由 worker 处理完成",
+        "worker_id": worker_id,
+        "task_id": command.id(),
+        "kind": kind,
+        "request": detail,
+        "processed_at": chrono::Utc::now().to_rfc3339()
+    })
 }